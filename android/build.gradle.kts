@@ -3,6 +3,16 @@ plugins {
     id("org.jetbrains.kotlin.android")
 }
 
+// Also reported by the `diagnostics` command.
+val billingLibraryVersion = "7.0.0"
+val amazonAppstoreSdkVersion = "3.0.5"
+
+// Store the plugin sells through: `play` by default, or `amazon` with
+// `tauri.iap.store=amazon` in the app's gradle.properties, together with the
+// `amazon-appstore` cargo feature. Only that flavor is built, so apps don't
+// need a `missingDimensionStrategy` for it.
+val iapStore = providers.gradleProperty("tauri.iap.store").getOrElse("play")
+
 kotlin {
     jvmToolchain(17)
 }
@@ -23,6 +33,22 @@ android {
 
         testInstrumentationRunner = "androidx.test.runner.AndroidJUnitRunner"
         consumerProguardFiles("consumer-rules.pro")
+        buildConfigField("String", "BILLING_LIBRARY_VERSION", "\"$billingLibraryVersion\"")
+    }
+
+    buildFeatures {
+        buildConfig = true
+    }
+
+    flavorDimensions += "store"
+    productFlavors {
+        create("play") {
+            dimension = "store"
+        }
+        create("amazon") {
+            dimension = "store"
+            buildConfigField("String", "AMAZON_APPSTORE_SDK_VERSION", "\"$amazonAppstoreSdkVersion\"")
+        }
     }
 
     buildTypes {
@@ -46,11 +72,18 @@ android {
     }
 }
 
+androidComponents {
+    beforeVariants { variant ->
+        variant.enable = variant.flavorName == iapStore
+    }
+}
+
 dependencies {
     implementation("androidx.core:core-ktx:1.12.0")
     implementation("androidx.appcompat:appcompat:1.6.1")
     implementation("com.google.android.material:material:1.11.0")
-    implementation("com.android.billingclient:billing-ktx:6.1.0")
+    implementation("com.android.billingclient:billing-ktx:$billingLibraryVersion")
+    "amazonImplementation"("com.amazon.device:amazon-appstore-sdk:$amazonAppstoreSdkVersion")
     implementation("org.jetbrains.kotlinx:kotlinx-coroutines-android:1.7.3")
     implementation("org.jetbrains.kotlinx:kotlinx-coroutines-core:1.7.3")
     implementation("com.squareup.moshi:moshi-kotlin:1.15.0")
@@ -0,0 +1,265 @@
+package com.plugin.iap
+
+import android.app.Activity
+import android.content.ActivityNotFoundException
+import android.content.Intent
+import android.content.pm.ApplicationInfo
+import android.content.pm.PackageManager
+import android.net.Uri
+import android.os.Build
+import android.util.Log
+import com.amazon.device.iap.PurchasingListener
+import com.amazon.device.iap.PurchasingService
+import com.amazon.device.iap.model.FulfillmentResult
+import com.amazon.device.iap.model.Product
+import com.amazon.device.iap.model.ProductDataResponse
+import com.amazon.device.iap.model.ProductType
+import com.amazon.device.iap.model.PurchaseResponse
+import com.amazon.device.iap.model.PurchaseUpdatesResponse
+import com.amazon.device.iap.model.Receipt
+import com.amazon.device.iap.model.RequestId
+import com.amazon.device.iap.model.UserData
+import com.amazon.device.iap.model.UserDataResponse
+import kotlinx.coroutines.CompletableDeferred
+import org.json.JSONObject
+
+private const val TAG = "AmazonIap"
+
+/** Packages of the Amazon Appstore and of the App Tester, which sells in sandbox mode. */
+private val APPSTORE_PACKAGES = listOf("com.amazon.venezia", "com.amazon.sdktestclient")
+
+/**
+ * Maps a failed Appstore `RequestStatus` to the `IapErrorCode` of the Rust
+ * core, keeping the status in the details.
+ *
+ * @param status The `RequestStatus` of the response
+ * @param failed Code of the plain `FAILED` status, which depends on the request
+ * @param message What failed
+ */
+internal fun requestStatusError(status: Enum<*>, failed: String, message: String): IapException {
+    val code = when (status.name) {
+        "ALREADY_PURCHASED" -> "itemAlreadyOwned"
+        "INVALID_SKU" -> "developerError"
+        "NOT_SUPPORTED" -> "featureNotSupported"
+        else -> failed
+    }
+    return IapException(code, "$message: ${status.name}", JSONObject().put("requestStatus", status.name))
+}
+
+/**
+ * Core implementation of In-App Purchase functionality using the Amazon
+ * Appstore SDK, built into the `amazon` flavor in place of [Iap].
+ *
+ * The SDK answers every request through a single [PurchasingListener], on the
+ * main thread, matched to the request by its [RequestId]. Requests must be
+ * sent from the main thread too, see [request].
+ *
+ * @property activity The Android Activity context required for purchases
+ */
+class AmazonIap(private val activity: Activity) : PurchasingListener {
+    private val pending = mutableMapOf<RequestId, CompletableDeferred<Any>>()
+    private var registered = false
+    private var purchaseUpdateListener: ((List<Receipt>) -> Unit)? = null
+
+    /** The Amazon user and marketplace, known after [initialize]. */
+    var userData: UserData? = null
+        private set
+
+    /**
+     * Registers the listener with the SDK and reads the current user, which
+     * fails while the user isn't signed in to the Appstore.
+     */
+    suspend fun initialize(): Boolean {
+        if (!registered) {
+            PurchasingService.registerListener(activity.applicationContext, this)
+            registered = true
+            Log.d(TAG, "Registered, sandbox mode: ${PurchasingService.IS_SANDBOX_MODE}")
+        }
+        val response: UserDataResponse = request { PurchasingService.getUserData() }
+        if (response.requestStatus != UserDataResponse.RequestStatus.SUCCESSFUL) {
+            throw requestStatusError(
+                response.requestStatus,
+                "billingClientInitError",
+                "Failed to get the Appstore user"
+            )
+        }
+        userData = response.userData
+        return true
+    }
+
+    /**
+     * Forgets the user. The SDK has no connection to end, so responses to
+     * requests still running are dropped.
+     */
+    fun endConnection() {
+        userData = null
+        pending.clear()
+    }
+
+    /** Whether the Appstore or the App Tester is installed. */
+    fun isAppstoreInstalled(): Boolean = APPSTORE_PACKAGES.any { packageName ->
+        try {
+            activity.packageManager.getPackageInfo(packageName, 0)
+            true
+        } catch (e: PackageManager.NameNotFoundException) {
+            false
+        }
+    }
+
+    /**
+     * Queries the products by SKU.
+     *
+     * @return The products found and the SKUs that aren't available
+     */
+    suspend fun queryProducts(skus: List<String>): Pair<List<Product>, Set<String>> {
+        val response: ProductDataResponse = request { PurchasingService.getProductData(skus.toSet()) }
+        if (response.requestStatus != ProductDataResponse.RequestStatus.SUCCESSFUL) {
+            throw requestStatusError(response.requestStatus, "productQueryError", "Failed to query products")
+        }
+        return response.productData.values.toList() to response.unavailableSkus
+    }
+
+    /**
+     * Shows the Appstore purchase dialog. Bought receipts are reported to
+     * the purchase update listener, like Google Play reports purchases.
+     *
+     * @return Whether the purchase went through, `false` while it awaits
+     *   approval, e.g. by a parent, and is reported by a later restore
+     * @throws IapException With `userCancelled` for `FAILED`, which the SDK
+     *   reports both when the user closes the dialog and when the payment
+     *   fails, after the dialog explained why
+     */
+    suspend fun purchase(sku: String): Boolean {
+        val response: PurchaseResponse = request { PurchasingService.purchase(sku) }
+        response.userData?.let { userData = it }
+        return when {
+            response.requestStatus == PurchaseResponse.RequestStatus.SUCCESSFUL -> {
+                purchaseUpdateListener?.invoke(listOf(response.receipt))
+                true
+            }
+            // SDK 3.0.3+ reports purchases awaiting approval as `PENDING`.
+            response.requestStatus.name == "PENDING" -> false
+            else -> throw requestStatusError(response.requestStatus, "userCancelled", "Purchase of $sku failed")
+        }
+    }
+
+    /**
+     * Tells the Appstore the purchase was delivered. Until then, it is
+     * reported again by [purchaseUpdates] and consumables can't be bought
+     * again. The SDK sends no response.
+     */
+    fun notifyFulfillment(receiptId: String) {
+        PurchasingService.notifyFulfillment(receiptId, FulfillmentResult.FULFILLED)
+    }
+
+    /**
+     * Every receipt of the user, following the pages of `getPurchaseUpdates`
+     * from the start, including canceled ones.
+     */
+    suspend fun purchaseUpdates(): List<Receipt> {
+        val receipts = mutableListOf<Receipt>()
+        var reset = true
+        do {
+            val response: PurchaseUpdatesResponse = request { PurchasingService.getPurchaseUpdates(reset) }
+            if (response.requestStatus != PurchaseUpdatesResponse.RequestStatus.SUCCESSFUL) {
+                throw requestStatusError(response.requestStatus, "restoreError", "Failed to get the purchases")
+            }
+            response.userData?.let { userData = it }
+            receipts += response.receipts
+            reset = false
+        } while (response.hasMore())
+        return receipts
+    }
+
+    /**
+     * The receipts of the entitlements and active subscriptions the user owns,
+     * without consumables.
+     */
+    suspend fun currentEntitlements(): List<Receipt> =
+        purchaseUpdates().filter { !it.isCanceled && it.productType != ProductType.CONSUMABLE }
+
+    fun setPurchaseUpdateListener(listener: (List<Receipt>) -> Unit) {
+        purchaseUpdateListener = listener
+    }
+
+    override fun onUserDataResponse(response: UserDataResponse) = respond(response.requestId, response)
+
+    override fun onProductDataResponse(response: ProductDataResponse) = respond(response.requestId, response)
+
+    override fun onPurchaseResponse(response: PurchaseResponse) = respond(response.requestId, response)
+
+    override fun onPurchaseUpdatesResponse(response: PurchaseUpdatesResponse) =
+        respond(response.requestId, response)
+
+    /**
+     * Sends a request and suspends until the listener receives its response.
+     * Both run on the main thread, so the response can't arrive before it is
+     * registered here.
+     */
+    private suspend fun <T> request(send: () -> RequestId): T {
+        val response = CompletableDeferred<Any>()
+        pending[send()] = response
+        @Suppress("UNCHECKED_CAST")
+        return response.await() as T
+    }
+
+    private fun respond(requestId: RequestId, response: Any) {
+        pending.remove(requestId)?.complete(response)
+            ?: Log.w(TAG, "Dropping the response to request $requestId")
+    }
+
+    /**
+     * Gets the package name of the installer of the app, which the Rust core
+     * maps to an `InstallSource`.
+     */
+    fun installerPackageName(): String? {
+        val packageManager = activity.packageManager
+        return if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.R) {
+            packageManager.getInstallSourceInfo(activity.packageName).installingPackageName
+        } else {
+            @Suppress("DEPRECATION")
+            packageManager.getInstallerPackageName(activity.packageName)
+        }
+    }
+
+    /**
+     * Whether this is a debuggable build, e.g. run from Android Studio.
+     */
+    val isDebuggable: Boolean
+        get() = activity.applicationInfo.flags and ApplicationInfo.FLAG_DEBUGGABLE != 0
+
+    /**
+     * Opens the Amazon page where the user manages their memberships and
+     * subscriptions, which the Appstore app has no deep link to.
+     *
+     * @throws IapException With `featureNotSupported` if no app can open the page
+     */
+    fun openManageSubscriptions() {
+        val uri = "https://www.amazon.com/yourmembershipsandsubscriptions"
+        try {
+            activity.startActivity(Intent(Intent.ACTION_VIEW, Uri.parse(uri)))
+        } catch (e: ActivityNotFoundException) {
+            throw IapException("featureNotSupported", "No app can open $uri")
+        }
+    }
+
+    /**
+     * Opens the Appstore page of an app in the Appstore app, or in the browser
+     * if the Appstore isn't installed.
+     *
+     * @param packageName Package name of the app, this app if null
+     * @throws IapException With `featureNotSupported` if no app can open the page
+     */
+    fun openStoreProductPage(packageName: String?) {
+        val id = packageName ?: activity.packageName
+        for (uri in listOf("amzn://apps/android?p=$id", "https://www.amazon.com/gp/mas/dl/android?p=$id")) {
+            try {
+                activity.startActivity(Intent(Intent.ACTION_VIEW, Uri.parse(uri)))
+                return
+            } catch (e: ActivityNotFoundException) {
+                Log.d(TAG, "No activity opens $uri")
+            }
+        }
+        throw IapException("featureNotSupported", "No app can open the Appstore page of $id")
+    }
+}
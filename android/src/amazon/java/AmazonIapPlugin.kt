@@ -0,0 +1,409 @@
+package com.plugin.iap
+
+import android.app.Activity
+import android.util.Log
+import app.tauri.annotation.CommandHandler
+import app.tauri.annotation.TauriPlugin
+import app.tauri.plugin.JSObject
+import app.tauri.plugin.Plugin
+import com.amazon.device.iap.model.Product
+import com.amazon.device.iap.model.ProductType
+import com.amazon.device.iap.model.Receipt
+import kotlinx.coroutines.CoroutineScope
+import kotlinx.coroutines.Dispatchers
+import kotlinx.coroutines.launch
+import org.json.JSONArray
+import org.json.JSONObject
+import java.text.SimpleDateFormat
+import java.util.Currency
+import java.util.Date
+import java.util.Locale
+import java.util.TimeZone
+
+private const val TAG = "AmazonIapPlugin"
+
+/** ISO 8601 durations of the Appstore subscription periods. */
+private val SUBSCRIPTION_PERIODS = mapOf(
+    "Weekly" to "P1W",
+    "BiWeekly" to "P2W",
+    "Monthly" to "P1M",
+    "BiMonthly" to "P2M",
+    "Quarterly" to "P3M",
+    "SemiAnnually" to "P6M",
+    "Annually" to "P1Y"
+)
+
+/**
+ * Reports unexpected exceptions as `internalError` so every rejection follows
+ * the `{ code, message, details }` convention.
+ */
+private fun Throwable.toIapException(): IapException =
+    this as? IapException ?: IapException("internalError", message ?: javaClass.simpleName)
+
+/**
+ * Tauri plugin for handling In-App Purchases through the Amazon Appstore,
+ * registered by the Rust core with the `amazon-appstore` feature.
+ *
+ * It answers the commands of [IapPlugin] in the same shapes, with purchases
+ * carrying Amazon receipts as `verificationData` with the `amazon` source.
+ */
+@TauriPlugin
+class AmazonIapPlugin(activity: Activity): Plugin(activity) {
+    private val implementation = AmazonIap(activity)
+    private val scope = CoroutineScope(Dispatchers.Main)
+    // Keeps receipt IDs out of the `purchaseUpdate` channel event.
+    private var redactVerificationData = false
+
+    @CommandHandler
+    fun initialize(args: JSObject, callback: (Result<Boolean>) -> Unit) {
+        scope.launch {
+            try {
+                redactVerificationData = args.optBoolean("redactVerificationData", false)
+                val result = implementation.initialize()
+                Log.d(TAG, "Initialization result: $result")
+                callback(Result.success(result))
+            } catch (e: Exception) {
+                Log.e(TAG, "Initialization failed", e)
+                callback(Result.failure(e.toIapException()))
+            }
+        }
+    }
+
+    // Builds for the Appstore can only buy on devices that have it.
+    @CommandHandler
+    fun isAvailable(callback: (Result<Boolean>) -> Unit) {
+        callback(Result.success(implementation.isAppstoreInstalled()))
+    }
+
+    @CommandHandler
+    fun endConnection(callback: (Result<Boolean>) -> Unit) {
+        implementation.endConnection()
+        callback(Result.success(true))
+    }
+
+    @CommandHandler
+    fun queryProductDetails(args: JSObject, callback: (Result<JSObject>) -> Unit) {
+        scope.launch {
+            try {
+                val productIds = args.getJSONArray("productIds")?.let {
+                    List(it.length()) { i -> it.getString(i) }
+                } ?: emptyList()
+
+                val (products, unavailable) = implementation.queryProducts(productIds)
+                Log.d(TAG, "Query result: ${products.size} products found")
+                val response = JSObject().apply {
+                    put("success", true)
+                    put("productDetails", JSONArray().apply {
+                        products.forEach { put(convertProduct(it)) }
+                    })
+                    put("notFoundIds", JSONArray().apply {
+                        unavailable.forEach { put(it) }
+                    })
+                }
+                callback(Result.success(response))
+            } catch (e: Exception) {
+                callback(Result.failure(e.toIapException()))
+            }
+        }
+    }
+
+    @CommandHandler
+    fun buyNonConsumable(args: JSObject, callback: (Result<Boolean>) -> Unit) {
+        buy(args, callback)
+    }
+
+    @CommandHandler
+    fun buyConsumable(args: JSObject, callback: (Result<Boolean>) -> Unit) {
+        buy(args, callback)
+    }
+
+    // The SKU decides whether a purchase is consumable on the Appstore.
+    private fun buy(args: JSObject, callback: (Result<Boolean>) -> Unit) {
+        scope.launch {
+            try {
+                val param = args.optJSONObject("purchaseParam") ?: args
+                val sku = param.getJSONObject("productDetails").getString("id")
+                val result = implementation.purchase(sku)
+                Log.d(TAG, "Purchase result: $result")
+                callback(Result.success(result))
+            } catch (e: Exception) {
+                callback(Result.failure(e.toIapException()))
+            }
+        }
+    }
+
+    /**
+     * Fulfills the receipt, the purchase ID of Appstore purchases. This
+     * completes consumables and non-consumables alike.
+     */
+    @CommandHandler
+    fun completePurchase(args: JSObject, callback: (Result<Boolean>) -> Unit) {
+        try {
+            implementation.notifyFulfillment(args.getString("purchaseId"))
+            callback(Result.success(true))
+        } catch (e: Exception) {
+            callback(Result.failure(e.toIapException()))
+        }
+    }
+
+    /**
+     * Returns the receipts of `getPurchaseUpdates` as `PurchaseDetails`. The
+     * Appstore has no restore flow, so this is the same query as
+     * [ownedPurchases].
+     */
+    @CommandHandler
+    fun restorePurchases(args: JSObject?, callback: (Result<JSObject>) -> Unit) {
+        ownedPurchases(callback)
+    }
+
+    @CommandHandler
+    fun ownedPurchases(callback: (Result<JSObject>) -> Unit) {
+        scope.launch {
+            try {
+                val receipts = implementation.purchaseUpdates().filter { !it.isCanceled }
+                callback(Result.success(purchasesResponse(receipts, "restored")))
+            } catch (e: Exception) {
+                callback(Result.failure(e.toIapException()))
+            }
+        }
+    }
+
+    @CommandHandler
+    fun currentEntitlements(callback: (Result<JSObject>) -> Unit) {
+        scope.launch {
+            try {
+                callback(Result.success(purchasesResponse(implementation.currentEntitlements(), "restored")))
+            } catch (e: Exception) {
+                callback(Result.failure(e.toIapException()))
+            }
+        }
+    }
+
+    /**
+     * Returns the `SubscriptionStatus` of a subscription the user owns. The
+     * Appstore only tells active receipts from canceled ones, so any receipt
+     * not canceled is reported as active.
+     */
+    @CommandHandler
+    fun subscriptionStatus(args: JSObject, callback: (Result<JSObject>) -> Unit) {
+        scope.launch {
+            try {
+                val productId = args.getString("productId")
+                val owned = implementation.currentEntitlements()
+                    .any { it.productType == ProductType.SUBSCRIPTION && it.sku == productId }
+                if (!owned) throw IapException("itemNotOwned", "Item not owned: $productId")
+                callback(Result.success(JSObject().apply {
+                    put("productId", productId)
+                    put("isActive", true)
+                    put("state", "active")
+                }))
+            } catch (e: Exception) {
+                callback(Result.failure(e.toIapException()))
+            }
+        }
+    }
+
+    // Subscription changes happen in the Appstore, not through the SDK.
+    @CommandHandler
+    fun buyUpgrade(args: JSObject, callback: (Result<JSObject>) -> Unit) {
+        callback(Result.failure(
+            IapException("featureNotSupported", "Subscriptions are changed in the Amazon Appstore")
+        ))
+    }
+
+    // The marketplace of the Appstore user, e.g. `US`.
+    @CommandHandler
+    fun countryCode(callback: (Result<String>) -> Unit) {
+        val marketplace = implementation.userData?.marketplace
+        if (marketplace != null) {
+            callback(Result.success(marketplace))
+        } else {
+            callback(Result.failure(IapException("notInitialized", "The Appstore user isn't known yet")))
+        }
+    }
+
+    @CommandHandler
+    fun isFeatureSupported(args: JSObject, callback: (Result<JSObject>) -> Unit) {
+        try {
+            val supported = when (val feature = args.getString("feature")) {
+                "subscriptions" -> true
+                // Subscription changes happen in the Appstore, not through the SDK.
+                "subscriptionsUpdate", "inAppMessaging", "externalOffer", "priceChangeConfirmation" -> false
+                else -> throw IapException("featureNotSupported", "Unknown store feature: $feature")
+            }
+            callback(Result.success(JSObject().apply { put("supported", supported) }))
+        } catch (e: Exception) {
+            callback(Result.failure(e.toIapException()))
+        }
+    }
+
+    @CommandHandler
+    fun diagnostics(callback: (Result<JSObject>) -> Unit) {
+        callback(Result.success(JSObject().apply {
+            put("billingLibraryVersion", BuildConfig.AMAZON_APPSTORE_SDK_VERSION)
+        }))
+    }
+
+    // The Rust core maps the installer to an `InstallSource`.
+    @CommandHandler
+    fun installSource(callback: (Result<JSObject>) -> Unit) {
+        try {
+            callback(Result.success(JSObject().apply {
+                put("installerPackageName", implementation.installerPackageName())
+                put("debuggable", implementation.isDebuggable)
+            }))
+        } catch (e: Exception) {
+            callback(Result.failure(e.toIapException()))
+        }
+    }
+
+    @CommandHandler
+    fun openManageSubscriptions(callback: (Result<Boolean>) -> Unit) {
+        try {
+            implementation.openManageSubscriptions()
+            callback(Result.success(true))
+        } catch (e: Exception) {
+            callback(Result.failure(e.toIapException()))
+        }
+    }
+
+    @CommandHandler
+    fun openStoreProductPage(args: JSObject, callback: (Result<Boolean>) -> Unit) {
+        try {
+            val productId = if (args.isNull("productId")) null else args.getString("productId")
+            implementation.openStoreProductPage(productId)
+            callback(Result.success(true))
+        } catch (e: Exception) {
+            callback(Result.failure(e.toIapException()))
+        }
+    }
+
+    @CommandHandler
+    fun isAlternativeBillingAvailable(callback: (Result<Boolean>) -> Unit) {
+        callback(Result.failure(alternativeBillingUnsupported()))
+    }
+
+    @CommandHandler
+    fun showAlternativeBillingDialog(callback: (Result<Boolean>) -> Unit) {
+        callback(Result.failure(alternativeBillingUnsupported()))
+    }
+
+    @CommandHandler
+    fun createAlternativeBillingToken(callback: (Result<JSObject>) -> Unit) {
+        callback(Result.failure(alternativeBillingUnsupported()))
+    }
+
+    private fun alternativeBillingUnsupported() =
+        IapException("featureNotSupported", "Alternative billing only is a Google Play program")
+
+    /**
+     * Converts an Appstore product to a JSON representation. The SDK only has
+     * the formatted price, so the currency is the one of the user's
+     * marketplace and the raw price is parsed from it.
+     */
+    private fun convertProduct(product: Product): JSONObject {
+        val currency = implementation.userData?.marketplace?.let {
+            runCatching { Currency.getInstance(Locale("", it)) }.getOrNull()
+        }
+        return JSONObject().apply {
+            put("id", product.sku)
+            put("title", product.title)
+            put("description", product.description)
+            put("price", product.price ?: "")
+            put("rawPrice", rawPrice(product.price ?: ""))
+            put("currencyCode", currency?.currencyCode ?: "")
+            put("currencySymbol", currency?.symbol ?: "")
+            SUBSCRIPTION_PERIODS[product.subscriptionPeriod]?.let { put("subscriptionPeriod", it) }
+            SUBSCRIPTION_PERIODS[product.freeTrialPeriod]?.let { put("freeTrialPeriod", it) }
+        }
+    }
+
+    /**
+     * Parses a formatted price, e.g. `$1,299.99` or `1.299,99 €`, where the
+     * last separator followed by two digits is the decimal one.
+     */
+    private fun rawPrice(price: String): Double {
+        val digits = price.filter { it.isDigit() || it == '.' || it == ',' }
+        val decimal = digits.lastIndexOfAny(charArrayOf('.', ','))
+        val number = if (decimal >= 0 && digits.length - decimal - 1 == 2) {
+            digits.substring(0, decimal).filter { it.isDigit() } + "." + digits.substring(decimal + 1)
+        } else {
+            digits.filter { it.isDigit() }
+        }
+        return number.toDoubleOrNull() ?: 0.0
+    }
+
+    /**
+     * The `{ purchases }` response of the receipts, with canceled ones
+     * reported as such.
+     */
+    private fun purchasesResponse(receipts: List<Receipt>, status: String): JSObject = JSObject().apply {
+        put("purchases", JSONArray().apply {
+            receipts.forEach { put(convertReceipt(it, status, redact = false)) }
+        })
+    }
+
+    /**
+     * Converts a receipt to `PurchaseDetails`, where the receipt ID is the
+     * purchase ID. Receipts stay unfulfilled until `completePurchase`, which
+     * may be called again for fulfilled ones.
+     */
+    private fun convertReceipt(receipt: Receipt, status: String, redact: Boolean): JSONObject =
+        JSONObject().apply {
+            put("purchaseId", receipt.receiptId)
+            put("productId", receipt.sku)
+            put("transactionDateMs", receipt.purchaseDate.time)
+            put("status", if (receipt.isCanceled) "canceled" else status)
+            put("pendingCompletePurchase", !receipt.isCanceled)
+            if (receipt.productType == ProductType.SUBSCRIPTION) {
+                put("subscriptionState", if (receipt.isCanceled) "expired" else "active")
+                receipt.cancelDate?.let { put("expirationDate", isoDate(it.time)) }
+            }
+            if (redact) {
+                put("verificationAvailable", receipt.receiptId.isNotEmpty())
+            } else {
+                put("verificationData", verificationData(receipt))
+            }
+        }
+
+    /**
+     * The receipt for verification with the Receipt Verification Service,
+     * which looks it up by receipt and user ID.
+     */
+    private fun verificationData(receipt: Receipt): JSONObject = JSONObject().apply {
+        val local = receipt.toJSON().apply {
+            implementation.userData?.let {
+                put("userId", it.userId)
+                put("marketplace", it.marketplace)
+            }
+        }
+        put("localVerificationData", local.toString())
+        put("serverVerificationData", receipt.receiptId)
+        put("source", "amazon")
+    }
+
+    /**
+     * Formats epoch milliseconds as an ISO datetime string in UTC.
+     */
+    private fun isoDate(millis: Long): String =
+        SimpleDateFormat("yyyy-MM-dd'T'HH:mm:ss'Z'", Locale.US)
+            .apply { timeZone = TimeZone.getTimeZone("UTC") }
+            .format(Date(millis))
+
+    init {
+        IapBridge.reportLocaleChanges(activity)
+        implementation.setPurchaseUpdateListener { receipts ->
+            Log.d(TAG, "Purchase update received: ${receipts.size} purchases")
+            // The Rust core records and emits them to the webview.
+            IapBridge.reportPurchases(JSONArray().apply {
+                receipts.forEach { put(convertReceipt(it, "purchased", redact = false)) }
+            })
+            val event = JSObject().apply {
+                put("purchases", JSONArray().apply {
+                    receipts.forEach { put(convertReceipt(it, "purchased", redactVerificationData)) }
+                })
+            }
+            notifyListeners("purchaseUpdate", event)
+        }
+    }
+}
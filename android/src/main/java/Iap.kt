@@ -1,13 +1,21 @@
 package com.plugin.iap
 
 import android.app.Activity
+import android.content.ActivityNotFoundException
+import android.content.Intent
+import android.content.pm.ApplicationInfo
+import android.content.pm.PackageManager
+import android.net.Uri
+import android.os.Build
 import android.util.Log
 import com.android.billingclient.api.*
 import kotlinx.coroutines.*
 import kotlin.coroutines.resume
+import kotlin.coroutines.resumeWithException
 import kotlin.coroutines.suspendCoroutine
 
 private const val TAG = "Iap"
+private const val PLAY_STORE_PACKAGE = "com.android.vending"
 
 /**
  * Core implementation of In-App Purchase functionality using Google Play Billing Library.
@@ -31,32 +39,87 @@ class Iap(private val activity: Activity) {
         billingClient = testBillingClient
     }
     private var purchaseUpdateListener: ((List<Purchase>) -> Unit)? = null
+    private var purchaseErrorListener: ((IapException) -> Unit)? = null
+    private var connectionStateListener: ((String, Int) -> Unit)? = null
     private val purchaseCache = mutableMapOf<String, Purchase>()
-    
+
     /**
-     * Initializes the billing client when the class is instantiated.
-     * This ensures the billing client is ready for use when needed.
+     * Pending purchase settings from the plugin configuration, applied when
+     * the billing client is set up by [initialize].
      */
-    init {
-        setupBillingClient()
-    }
+    var pendingPurchases = PendingPurchasesConfig()
+        private set
+
+    /**
+     * Whether the billing client is set up for Alternative Billing Only,
+     * from the plugin configuration.
+     */
+    private var alternativeBillingOnly = false
+
+    /**
+     * How the billing service is reconnected after it disconnects, from the
+     * plugin configuration.
+     */
+    private var connectionRetry = ConnectionRetryPolicy()
+    private val reconnectScope = CoroutineScope(Dispatchers.Main)
+    private var reconnectJob: Job? = null
+
+    /**
+     * Set by [endConnection], after which disconnects aren't reconnected and
+     * purchase updates aren't reported, until [initialize] is called again.
+     */
+    @Volatile
+    private var closed = false
+
+    /**
+     * Set when connecting reported BILLING_UNAVAILABLE, i.e. the device has
+     * no Google Play to buy from, until a later connection succeeds.
+     */
+    @Volatile
+    private var storeUnavailable = false
+
+    /**
+     * The subscription [changeSubscription] switches to and the call waiting
+     * for its purchase update, resumed by [handlePurchaseUpdate].
+     */
+    private var subscriptionChange: Pair<String, CancellableContinuation<Purchase?>>? = null
 
     /**
      * Sets up the BillingClient with required configurations.
-     * Configures the purchase update listener and enables pending purchases.
+     * Configures the purchase update listener and enables pending purchases,
+     * which Google Play requires for one-time products. A client ended by
+     * [endConnection] can't connect again, so it is replaced.
      */
     private fun setupBillingClient() {
-        if (!::billingClient.isInitialized) {
+        if (!::billingClient.isInitialized || closed) {
+            val pendingParams = PendingPurchasesParams.newBuilder()
+                .enableOneTimeProducts()
+                .apply { if (pendingPurchases.prepaidPlans) enablePrepaidPlans() }
+                .build()
             billingClient = BillingClient.newBuilder(activity)
                 .setListener { billingResult, purchases ->
                     handlePurchaseUpdate(billingResult, purchases)
                 }
-                .enablePendingPurchases()
+                .enablePendingPurchases(pendingParams)
+                .apply { if (alternativeBillingOnly) enableAlternativeBillingOnly() }
                 .build()
         }
     }
 
     private fun handlePurchaseUpdate(billingResult: BillingResult, purchases: List<Purchase>?) {
+        if (closed) return
+        var failureReported = false
+        subscriptionChange?.let { (productId, continuation) ->
+            val purchase = purchases?.firstOrNull { productId in it.products }
+            if (billingResult.responseCode != BillingClient.BillingResponseCode.OK) {
+                subscriptionChange = null
+                failureReported = true
+                continuation.resumeWithException(billingResult.toIapException())
+            } else if (purchase != null) {
+                subscriptionChange = null
+                continuation.resume(purchase.takeIf { it.purchaseState == Purchase.PurchaseState.PURCHASED })
+            }
+        }
         if (billingResult.responseCode == BillingClient.BillingResponseCode.OK && purchases != null) {
             Log.d(TAG, "Purchase update received: ${purchases.size} purchases")
             for (purchase in purchases) {
@@ -65,30 +128,117 @@ class Iap(private val activity: Activity) {
             purchaseUpdateListener?.invoke(purchases)
         } else {
             Log.e(TAG, "Purchase update error: ${billingResult.debugMessage}")
+            // A failed `buy_upgrade` rejects the command instead.
+            if (!failureReported) {
+                purchaseErrorListener?.invoke(billingResult.toIapException())
+            }
         }
     }
 
     /**
      * Initializes the connection to Google Play Billing service.
      *
+     * The Rust core retries failed connections, this only reconnects after
+     * an established connection drops. The billing client is shared, so an
+     * open connection is kept rather than started a second time.
+     *
+     * @param config Pending purchase settings for the billing client
+     * @param alternativeBilling Whether to enable Alternative Billing Only
+     * @param retry How to reconnect when the billing service disconnects
      * @return Boolean indicating whether the initialization was successful
-     * @throws BillingException if the service connection fails
+     * @throws IapException if the service connection fails
      */
-    suspend fun initialize(): Boolean = suspendCoroutine { continuation ->
+    suspend fun initialize(
+        config: PendingPurchasesConfig,
+        alternativeBilling: Boolean = false,
+        retry: ConnectionRetryPolicy = ConnectionRetryPolicy()
+    ): Boolean {
+        if (!closed && ::billingClient.isInitialized && billingClient.isReady) {
+            return true
+        }
+        pendingPurchases = config
+        alternativeBillingOnly = alternativeBilling
+        connectionRetry = retry
+        setupBillingClient()
+        closed = false
+        val billingResult = connect()
+        storeUnavailable =
+            billingResult.responseCode == BillingClient.BillingResponseCode.BILLING_UNAVAILABLE
+        if (billingResult.responseCode != BillingClient.BillingResponseCode.OK) {
+            throw billingResult.toIapException()
+        }
+        return true
+    }
+
+    /**
+     * Whether purchases can be made: the Play Store is installed and the
+     * billing service didn't report itself unavailable when connecting.
+     */
+    fun isAvailable(): Boolean {
+        if (storeUnavailable) return false
+        return try {
+            activity.packageManager.getPackageInfo(PLAY_STORE_PACKAGE, 0)
+            true
+        } catch (e: PackageManager.NameNotFoundException) {
+            false
+        }
+    }
+
+    private suspend fun connect(): BillingResult = suspendCoroutine { continuation ->
         billingClient.startConnection(object : BillingClientStateListener {
             override fun onBillingSetupFinished(billingResult: BillingResult) {
-                val success = billingResult.responseCode == BillingClient.BillingResponseCode.OK
                 Log.d(TAG, "Billing setup finished: ${billingResult.debugMessage}")
-                continuation.resume(success)
+                continuation.resume(billingResult)
             }
 
             override fun onBillingServiceDisconnected() {
+                if (closed) return
                 Log.w(TAG, "Billing service disconnected, attempting to reconnect")
-                setupBillingClient()
+                connectionStateListener?.invoke("reconnecting", 0)
+                reconnect()
             }
         })
     }
 
+    /**
+     * Reconnects with the delays of [connectionRetry] until connected, out
+     * of attempts or [endConnection] is called, reporting each attempt. Only
+     * one reconnection runs at a time.
+     */
+    private fun reconnect() {
+        if (closed || reconnectJob?.isActive == true) return
+        reconnectJob = reconnectScope.launch {
+            val responseCode = connectionRetry.reconnect(
+                connect = {
+                    connect().responseCode.also {
+                        if (it == BillingClient.BillingResponseCode.BILLING_UNAVAILABLE) {
+                            storeUnavailable = true
+                        }
+                    }
+                },
+                report = { state, attempt -> connectionStateListener?.invoke(state, attempt) }
+            )
+            if (responseCode == BillingClient.BillingResponseCode.OK) {
+                Log.d(TAG, "Billing service reconnected")
+            } else {
+                Log.e(TAG, "Giving up reconnecting to the billing service")
+            }
+        }
+    }
+
+    /**
+     * Stops reconnecting and closes the billing client, until [initialize]
+     * sets up a new one.
+     */
+    fun endConnection() {
+        closed = true
+        reconnectJob?.cancel()
+        reconnectJob = null
+        if (::billingClient.isInitialized) {
+            billingClient.endConnection()
+        }
+    }
+
     /**
      * Queries product details from Google Play for the specified product IDs.
      *
@@ -130,10 +280,22 @@ class Iap(private val activity: Activity) {
      * Initiates a purchase flow for the specified product.
      *
      * @param productDetails The product details for the item to purchase
-     * @return PurchaseResult containing the purchase status and any error information
-     * @throws BillingException if the purchase flow fails to launch
+     * @return PurchaseResult once the purchase flow launched
+     * @throws IapException if the purchase flow fails to launch
+     * @throws IapException with code `invalidConfig` for prepaid plans unless they are enabled
      */
-    suspend fun purchase(productDetails: ProductDetails): PurchaseResult = suspendCoroutine { continuation ->
+    suspend fun purchase(productDetails: ProductDetails): PurchaseResult {
+        if (!pendingPurchases.prepaidPlans && isPrepaidPlan(productDetails)) {
+            throw IapException(
+                "invalidConfig",
+                "${productDetails.productId} is a prepaid plan, which needs pendingPurchases.prepaidPlans " +
+                    "enabled in the iap plugin configuration"
+            )
+        }
+        return launchBillingFlow(productDetails)
+    }
+
+    private suspend fun launchBillingFlow(productDetails: ProductDetails): PurchaseResult = suspendCoroutine { continuation ->
         val flowParams = BillingFlowParams.newBuilder()
             .setProductDetailsParamsList(
                 listOf(
@@ -144,14 +306,82 @@ class Iap(private val activity: Activity) {
             )
             .build()
 
-        val responseCode = billingClient.launchBillingFlow(activity, flowParams).responseCode
-        if (responseCode != BillingClient.BillingResponseCode.OK) {
-            continuation.resume(
-                PurchaseResult(
-                    success = false,
-                    errorMessage = "Failed to launch billing flow: $responseCode"
+        // The outcome is reported through the purchase update and error
+        // listeners once the user leaves the flow. A flow that didn't launch
+        // fails the command, as `false` means the purchase awaits approval.
+        val billingResult = billingClient.launchBillingFlow(activity, flowParams)
+        if (billingResult.responseCode != BillingClient.BillingResponseCode.OK) {
+            continuation.resumeWithException(billingResult.toIapException())
+        } else {
+            continuation.resume(PurchaseResult(success = true))
+        }
+    }
+
+    /**
+     * Replaces a subscription the user has by another one, launching the
+     * billing flow with the base plan of [productId] and waiting for Google
+     * Play to report the new purchase.
+     *
+     * @param productId The subscription to switch to
+     * @param oldPurchaseToken Purchase token of the subscription it replaces
+     * @param replacementMode Google's `ReplacementMode` constant
+     * @return The new purchase, or null while its payment is pending
+     * @throws IapException if the subscription or its base plan isn't found, or the flow fails
+     */
+    suspend fun changeSubscription(productId: String, oldPurchaseToken: String, replacementMode: Int): Purchase? {
+        val productDetails = querySubscriptionDetails(productId)
+        // The base plan is offered to everyone, unlike the offers on top of it.
+        val offerToken = productDetails.subscriptionOfferDetails
+            ?.firstOrNull { it.offerId == null }
+            ?.offerToken
+            ?: throw IapException("developerError", "$productId has no active base plan")
+        val flowParams = BillingFlowParams.newBuilder()
+            .setProductDetailsParamsList(
+                listOf(
+                    BillingFlowParams.ProductDetailsParams.newBuilder()
+                        .setProductDetails(productDetails)
+                        .setOfferToken(offerToken)
+                        .build()
+                )
+            )
+            .setSubscriptionUpdateParams(
+                BillingFlowParams.SubscriptionUpdateParams.newBuilder()
+                    .setOldPurchaseToken(oldPurchaseToken)
+                    .setSubscriptionReplacementMode(replacementMode)
+                    .build()
+            )
+            .build()
+
+        return suspendCancellableCoroutine { continuation ->
+            subscriptionChange = productId to continuation
+            val billingResult = billingClient.launchBillingFlow(activity, flowParams)
+            if (billingResult.responseCode != BillingClient.BillingResponseCode.OK) {
+                subscriptionChange = null
+                continuation.resumeWithException(billingResult.toIapException())
+            }
+        }
+    }
+
+    private suspend fun querySubscriptionDetails(productId: String): ProductDetails = suspendCoroutine { continuation ->
+        val params = QueryProductDetailsParams.newBuilder()
+            .setProductList(
+                listOf(
+                    QueryProductDetailsParams.Product.newBuilder()
+                        .setProductId(productId)
+                        .setProductType(BillingClient.ProductType.SUBS)
+                        .build()
                 )
             )
+            .build()
+        billingClient.queryProductDetailsAsync(params) { billingResult, productDetailsList ->
+            val details = productDetailsList.firstOrNull()
+            when {
+                billingResult.responseCode != BillingClient.BillingResponseCode.OK ->
+                    continuation.resumeWithException(billingResult.toIapException())
+                details == null ->
+                    continuation.resumeWithException(IapException("productQueryError", "Subscription not found: $productId"))
+                else -> continuation.resume(details)
+            }
         }
     }
 
@@ -234,6 +464,181 @@ class Iap(private val activity: Activity) {
         }
     }
 
+    /**
+     * Queries the owned in-app products and active subscriptions, without
+     * any UI. Pending purchases aren't owned yet and are left out.
+     *
+     * @return The purchases granting an entitlement
+     * @throws IapException if a query fails
+     */
+    suspend fun currentEntitlements(): List<Purchase> =
+        queryPurchases(BillingClient.ProductType.INAPP)
+            .filter { it.purchaseState == Purchase.PurchaseState.PURCHASED } + activeSubscriptions()
+
+    /**
+     * Queries the subscriptions granting access. Google Play leaves out the
+     * ones on account hold, paused or expired, while those in the grace
+     * period look like any other.
+     *
+     * @return The purchased subscriptions
+     * @throws IapException if the query fails
+     */
+    suspend fun activeSubscriptions(): List<Purchase> =
+        queryPurchases(BillingClient.ProductType.SUBS)
+            .filter { it.purchaseState == Purchase.PurchaseState.PURCHASED }
+
+    private suspend fun queryPurchases(productType: String): List<Purchase> = suspendCoroutine { continuation ->
+        billingClient.queryPurchasesAsync(
+            QueryPurchasesParams.newBuilder()
+                .setProductType(productType)
+                .build()
+        ) { billingResult, purchaseList ->
+            if (billingResult.responseCode == BillingClient.BillingResponseCode.OK) {
+                purchaseList.forEach { purchase ->
+                    purchaseCache[purchase.orderId] = purchase
+                }
+                continuation.resume(purchaseList)
+            } else {
+                continuation.resumeWithException(billingResult.toIapException())
+            }
+        }
+    }
+
+    /**
+     * Checks if the user can buy through Alternative Billing Only.
+     *
+     * @return Boolean indicating whether alternative billing is available
+     * @throws IapException if the check fails for another reason than unavailability
+     */
+    suspend fun isAlternativeBillingAvailable(): Boolean = suspendCoroutine { continuation ->
+        billingClient.isAlternativeBillingOnlyAvailableAsync { billingResult ->
+            when (billingResult.responseCode) {
+                BillingClient.BillingResponseCode.OK -> continuation.resume(true)
+                BillingClient.BillingResponseCode.BILLING_UNAVAILABLE,
+                BillingClient.BillingResponseCode.FEATURE_NOT_SUPPORTED -> continuation.resume(false)
+                else -> continuation.resumeWithException(billingResult.toIapException())
+            }
+        }
+    }
+
+    /**
+     * Checks if the Play Store on the device supports a billing feature.
+     *
+     * @param featureType One of the `BillingClient.FeatureType` constants
+     * @return Boolean indicating whether the feature is supported
+     * @throws IapException if the check fails for another reason than missing support
+     */
+    fun isFeatureSupported(featureType: String): Boolean {
+        val billingResult = billingClient.isFeatureSupported(featureType)
+        return when (billingResult.responseCode) {
+            BillingClient.BillingResponseCode.OK -> true
+            BillingClient.BillingResponseCode.FEATURE_NOT_SUPPORTED -> false
+            else -> throw billingResult.toIapException()
+        }
+    }
+
+    /**
+     * Package name of the app that installed this one, e.g. `com.android.vending`
+     * for Google Play, or null if none is recorded, e.g. after `adb install`.
+     */
+    fun installerPackageName(): String? {
+        val packageManager = activity.packageManager
+        return if (Build.VERSION.SDK_INT >= Build.VERSION_CODES.R) {
+            packageManager.getInstallSourceInfo(activity.packageName).installingPackageName
+        } else {
+            @Suppress("DEPRECATION")
+            packageManager.getInstallerPackageName(activity.packageName)
+        }
+    }
+
+    /**
+     * Whether this is a debuggable build, e.g. run from Android Studio.
+     */
+    val isDebuggable: Boolean
+        get() = activity.applicationInfo.flags and ApplicationInfo.FLAG_DEBUGGABLE != 0
+
+    /**
+     * Opens the Google Play page where the user manages their subscriptions
+     * to this app, in the Play Store app or else in the browser.
+     *
+     * @throws IapException With `featureNotSupported` if no app can open the page
+     */
+    fun openManageSubscriptions() {
+        val uri = "https://play.google.com/store/account/subscriptions?package=${activity.packageName}"
+        try {
+            activity.startActivity(Intent(Intent.ACTION_VIEW, Uri.parse(uri)))
+        } catch (e: ActivityNotFoundException) {
+            throw IapException("featureNotSupported", "No app can open $uri")
+        }
+    }
+
+    /**
+     * Opens the Play Store page of an app in the Play Store app, or in the
+     * browser if the Play Store isn't installed.
+     *
+     * @param packageName Package name of the app, this app if null
+     * @throws IapException With `featureNotSupported` if no app can open the page
+     */
+    fun openStoreProductPage(packageName: String?) {
+        val id = packageName ?: activity.packageName
+        for (uri in listOf("market://details?id=$id", "https://play.google.com/store/apps/details?id=$id")) {
+            try {
+                activity.startActivity(Intent(Intent.ACTION_VIEW, Uri.parse(uri)))
+                return
+            } catch (e: ActivityNotFoundException) {
+                Log.d(TAG, "No activity opens $uri")
+            }
+        }
+        throw IapException("featureNotSupported", "No app can open the Play Store page of $id")
+    }
+
+    /**
+     * Shows the information dialog Google Play requires before the first
+     * alternative billing purchase. Play doesn't show it again once the user
+     * acknowledged it.
+     *
+     * @return Boolean indicating whether the user acknowledged the dialog
+     * @throws IapException if the dialog can't be shown
+     */
+    suspend fun showAlternativeBillingDialog(): Boolean = suspendCoroutine { continuation ->
+        billingClient.showAlternativeBillingOnlyInformationDialog(activity) { billingResult ->
+            when (billingResult.responseCode) {
+                BillingClient.BillingResponseCode.OK -> continuation.resume(true)
+                BillingClient.BillingResponseCode.USER_CANCELED -> continuation.resume(false)
+                else -> continuation.resumeWithException(billingResult.toIapException())
+            }
+        }
+    }
+
+    /**
+     * Creates the token reporting an alternative billing purchase to Google Play.
+     *
+     * @return The external transaction token
+     * @throws IapException if the token can't be created
+     */
+    suspend fun createAlternativeBillingToken(): String = suspendCoroutine { continuation ->
+        billingClient.createAlternativeBillingOnlyReportingDetailsAsync { billingResult, details ->
+            if (billingResult.responseCode == BillingClient.BillingResponseCode.OK && details != null) {
+                continuation.resume(details.externalTransactionToken)
+            } else {
+                continuation.resumeWithException(billingResult.toIapException())
+            }
+        }
+    }
+
+    /**
+     * Whether every offer of the subscription is a prepaid plan, whose
+     * pricing phases don't renew.
+     */
+    private fun isPrepaidPlan(productDetails: ProductDetails): Boolean {
+        val offers = productDetails.subscriptionOfferDetails ?: return false
+        return offers.isNotEmpty() && offers.all { offer ->
+            offer.pricingPhases.pricingPhaseList.all {
+                it.recurrenceMode == ProductDetails.RecurrenceMode.NON_RECURRING
+            }
+        }
+    }
+
     /**
      * Sets a listener for purchase updates.
      * This listener will be called whenever a purchase state changes.
@@ -243,8 +648,149 @@ class Iap(private val activity: Activity) {
     fun setPurchaseUpdateListener(listener: (List<Purchase>) -> Unit) {
         purchaseUpdateListener = listener
     }
+
+    /**
+     * Sets a listener for the failed purchase updates, e.g. a purchase the
+     * user cancelled after the billing flow launched.
+     *
+     * @param listener Callback function that receives the mapped failure
+     */
+    fun setPurchaseErrorListener(listener: (IapException) -> Unit) {
+        purchaseErrorListener = listener
+    }
+
+    /**
+     * Sets a listener for the connection states after the billing service
+     * disconnects: `reconnecting`, again before each attempt, then `ready`
+     * once reconnected or `uninitialized` when giving up.
+     *
+     * @param listener Callback function that receives the camelCase state and
+     * the reconnection attempt, or 0 outside of attempts
+     */
+    fun setConnectionStateListener(listener: (String, Int) -> Unit) {
+        connectionStateListener = listener
+    }
+}
+
+/**
+ * Maps a failed billing result to the matching `IapErrorCode`.
+ */
+internal fun BillingResult.toIapException(): IapException {
+    val code = when (responseCode) {
+        BillingClient.BillingResponseCode.USER_CANCELED -> "userCancelled"
+        BillingClient.BillingResponseCode.BILLING_UNAVAILABLE -> "storeUnavailable"
+        BillingClient.BillingResponseCode.FEATURE_NOT_SUPPORTED -> "featureNotSupported"
+        BillingClient.BillingResponseCode.SERVICE_DISCONNECTED -> "serviceDisconnected"
+        BillingClient.BillingResponseCode.SERVICE_UNAVAILABLE,
+        BillingClient.BillingResponseCode.NETWORK_ERROR -> "networkError"
+        BillingClient.BillingResponseCode.DEVELOPER_ERROR -> "developerError"
+        else -> "internalError"
+    }
+    return IapException(
+        code,
+        debugMessage.ifEmpty { "Billing response code $responseCode" },
+        nativeCode = responseCode
+    )
+}
+
+/**
+ * Connection retry settings from the `connectionRetry` plugin configuration,
+ * mirroring `ConnectionRetryPolicy` of the Rust core.
+ *
+ * @property maxAttempts Number of reconnection attempts, or null to retry until connected
+ * @property baseDelayMs Delay before the first attempt
+ * @property maxDelayMs Upper bound for any single delay
+ * @property jitter Whether delays are randomized
+ */
+data class ConnectionRetryPolicy(
+    val maxAttempts: Int? = 3,
+    val baseDelayMs: Long = 1000,
+    val maxDelayMs: Long = 30_000,
+    val jitter: Boolean = true
+) {
+    /**
+     * Delay in milliseconds before attempt [attempt] (starting at 1), computed
+     * like the Rust core.
+     */
+    fun backoff(attempt: Int): Long {
+        val factor = 1L shl (attempt - 1).coerceIn(0, 30)
+        val delay = minOf(baseDelayMs.saturatingTimes(factor), maxDelayMs)
+        if (!jitter) return delay
+        return delay / 2 + (delay / 2 * Math.random()).toLong()
+    }
+
+    /**
+     * Calls [connect] with the delays of [backoff] until it returns `OK` or
+     * the attempts run out, reporting `reconnecting` before each attempt,
+     * then `ready` or `uninitialized`. Stops early once the billing service
+     * is unavailable, as Google Play went missing, which retrying won't change.
+     *
+     * @param connect Connects the billing client, returning the response code
+     * @param report Receives the camelCase state and the attempt, or 0
+     * @param sleep Waits the given milliseconds between attempts
+     * @return The response code of the last attempt
+     */
+    internal suspend fun reconnect(
+        connect: suspend () -> Int,
+        report: (String, Int) -> Unit,
+        sleep: suspend (Long) -> Unit = { delay(it) }
+    ): Int {
+        var responseCode = BillingClient.BillingResponseCode.SERVICE_DISCONNECTED
+        var attempt = 1
+        while (maxAttempts?.let { attempt <= it } != false) {
+            sleep(backoff(attempt))
+            report("reconnecting", attempt)
+            responseCode = connect()
+            if (responseCode == BillingClient.BillingResponseCode.OK) {
+                report("ready", 0)
+                return responseCode
+            }
+            if (responseCode == BillingClient.BillingResponseCode.BILLING_UNAVAILABLE) break
+            attempt++
+        }
+        report("uninitialized", 0)
+        return responseCode
+    }
+
+    private fun Long.saturatingTimes(factor: Long): Long =
+        if (this > Long.MAX_VALUE / factor) Long.MAX_VALUE else this * factor
 }
 
+/**
+ * Google's `ReplacementMode` constants by the camelCase names of the Rust
+ * `ReplacementMode`, which checks the names before they get here.
+ */
+internal val REPLACEMENT_MODES = mapOf(
+    "withTimeProration" to BillingFlowParams.SubscriptionUpdateParams.ReplacementMode.WITH_TIME_PRORATION,
+    "chargeProratedPrice" to BillingFlowParams.SubscriptionUpdateParams.ReplacementMode.CHARGE_PRORATED_PRICE,
+    "withoutProration" to BillingFlowParams.SubscriptionUpdateParams.ReplacementMode.WITHOUT_PRORATION,
+    "chargeFullPrice" to BillingFlowParams.SubscriptionUpdateParams.ReplacementMode.CHARGE_FULL_PRICE,
+    "deferred" to BillingFlowParams.SubscriptionUpdateParams.ReplacementMode.DEFERRED,
+)
+
+/**
+ * The Google constant of a replacement mode name.
+ *
+ * @throws IapException With `developerError` for unknown names
+ */
+internal fun replacementMode(name: String): Int =
+    REPLACEMENT_MODES[name]
+        ?: throw IapException(
+            "developerError",
+            "Unknown replacement mode `$name`, expected one of: ${REPLACEMENT_MODES.keys.joinToString()}"
+        )
+
+/**
+ * Pending purchase settings from the `pendingPurchases` plugin configuration.
+ *
+ * @property enabled Whether purchases awaiting payment are reported before they are paid
+ * @property prepaidPlans Whether pending purchases of prepaid subscription plans are allowed
+ */
+data class PendingPurchasesConfig(
+    val enabled: Boolean = true,
+    val prepaidPlans: Boolean = false
+)
+
 /**
  * Represents the result of a product details query.
  *
@@ -0,0 +1,81 @@
+package com.plugin.iap
+
+import android.app.Activity
+import android.content.ComponentCallbacks
+import android.content.res.Configuration
+import android.util.Log
+import androidx.core.os.ConfigurationCompat
+import org.json.JSONArray
+
+private const val TAG = "IapBridge"
+
+/**
+ * Callbacks into the Rust core, shared by the Google Play and the Amazon
+ * Appstore plugins. The Rust library exports them as
+ * `Java_com_plugin_iap_IapBridge_*`.
+ *
+ * The `report*` wrappers drop the report with a warning while the Rust
+ * library isn't loaded, e.g. in unit tests running on the JVM.
+ */
+object IapBridge {
+    /** Reports purchases, a JSON array of `PurchaseDetails`, to the Rust core. */
+    @JvmStatic
+    external fun onPurchaseUpdate(purchasesJson: String)
+
+    /** Reports a connection state change to the Rust core. */
+    @JvmStatic
+    external fun onConnectionStateChanged(state: String, attempt: Int)
+
+    /** Reports a locale change, which localizes product details, to the Rust core. */
+    @JvmStatic
+    external fun onLocaleChanged()
+
+    /** Reports a failure outside of any command, the JSON body of an [IapException]. */
+    @JvmStatic
+    external fun handleError(errorJson: String)
+
+    /**
+     * Reports the purchases the store delivered, with their verification
+     * data: the Rust core verifies and records them before emitting them,
+     * redacted with `redactVerificationData`.
+     */
+    fun reportPurchases(purchases: JSONArray) {
+        if (purchases.length() == 0) return
+        report("${purchases.length()} purchases") { onPurchaseUpdate(purchases.toString()) }
+    }
+
+    fun reportConnectionState(state: String, attempt: Int) {
+        report("connection state $state") { onConnectionStateChanged(state, attempt) }
+    }
+
+    fun reportError(error: IapException) {
+        report("error ${error.code}") { handleError(error.message ?: "") }
+    }
+
+    /**
+     * Reports the locale changes of [activity]. Activities declaring `locale`
+     * in `configChanges`, like Tauri's, aren't recreated when the user
+     * switches languages.
+     */
+    fun reportLocaleChanges(activity: Activity) {
+        var locales = ConfigurationCompat.getLocales(activity.resources.configuration).toLanguageTags()
+        activity.registerComponentCallbacks(object : ComponentCallbacks {
+            override fun onConfigurationChanged(newConfig: Configuration) {
+                val changed = ConfigurationCompat.getLocales(newConfig).toLanguageTags()
+                if (changed == locales) return
+                locales = changed
+                report("locale change") { onLocaleChanged() }
+            }
+
+            override fun onLowMemory() {}
+        })
+    }
+
+    private inline fun report(what: String, call: () -> Unit) {
+        try {
+            call()
+        } catch (e: UnsatisfiedLinkError) {
+            Log.w(TAG, "Rust core not loaded, dropping $what")
+        }
+    }
+}
@@ -6,15 +6,45 @@ import app.tauri.annotation.CommandHandler
 import app.tauri.annotation.TauriPlugin
 import app.tauri.plugin.JSObject
 import app.tauri.plugin.Plugin
+import com.android.billingclient.api.BillingClient
 import com.android.billingclient.api.ProductDetails
+import com.android.billingclient.api.Purchase
 import kotlinx.coroutines.CoroutineScope
 import kotlinx.coroutines.Dispatchers
 import kotlinx.coroutines.launch
 import org.json.JSONArray
 import org.json.JSONObject
+import java.util.Currency
 
 private const val TAG = "IapPlugin"
 
+/**
+ * Failure reported to the Rust core.
+ *
+ * The exception message is the JSON body `{ code, message, details, nativeCode }`
+ * that the Rust side parses into the matching error variant, where `code` is one
+ * of the camelCase `IapErrorCode` strings and `nativeCode` the raw
+ * `BillingResponseCode`, if any.
+ */
+class IapException(
+    val code: String,
+    val reason: String,
+    val details: JSONObject? = null,
+    val nativeCode: Int? = null
+) : Exception(JSONObject().apply {
+    put("code", code)
+    put("message", reason)
+    details?.let { put("details", it) }
+    nativeCode?.let { put("nativeCode", it) }
+}.toString())
+
+/**
+ * Reports unexpected exceptions as `internalError` so every rejection follows
+ * the `{ code, message, details }` convention.
+ */
+private fun Throwable.toIapException(): IapException =
+    this as? IapException ?: IapException("internalError", message ?: javaClass.simpleName)
+
 /**
  * Tauri plugin for handling In-App Purchases through Google Play Billing.
  *
@@ -25,23 +55,48 @@ class IapPlugin(activity: Activity): Plugin(activity) {
     private val implementation = Iap(activity)
     private val scope = CoroutineScope(Dispatchers.Main)
     private val productDetailsCache = mutableMapOf<String, ProductDetails>()
+    // Keeps purchase tokens out of the `purchaseUpdate` channel event.
+    private var redactVerificationData = false
 
     @CommandHandler
     fun initialize(args: JSObject, callback: (Result<Boolean>) -> Unit) {
         scope.launch {
             try {
-                val result = implementation.initialize()
+                val pendingPurchases = args.optJSONObject("pendingPurchases")
+                val config = PendingPurchasesConfig(
+                    enabled = pendingPurchases?.optBoolean("enabled", true) ?: true,
+                    prepaidPlans = pendingPurchases?.optBoolean("prepaidPlans", false) ?: false
+                )
+                val alternativeBillingOnly = args.optBoolean("alternativeBillingOnly", false)
+                redactVerificationData = args.optBoolean("redactVerificationData", false)
+                val retry = args.optJSONObject("connectionRetry")?.let {
+                    ConnectionRetryPolicy(
+                        maxAttempts = if (it.isNull("maxAttempts")) null else it.getInt("maxAttempts"),
+                        baseDelayMs = it.optLong("baseDelayMs", 1000),
+                        maxDelayMs = it.optLong("maxDelayMs", 30_000),
+                        jitter = it.optBoolean("jitter", true)
+                    )
+                } ?: ConnectionRetryPolicy()
+                val result = implementation.initialize(config, alternativeBillingOnly, retry)
                 Log.d(TAG, "Initialization result: $result")
                 callback(Result.success(result))
             } catch (e: Exception) {
                 Log.e(TAG, "Initialization failed", e)
-                callback(Result.failure(e))
+                callback(Result.failure(e.toIapException()))
             }
         }
     }
 
     @CommandHandler
     fun isAvailable(callback: (Result<Boolean>) -> Unit) {
+        callback(Result.success(implementation.isAvailable()))
+    }
+
+    // Answers right away, as the Rust core calls this while the plugin is
+    // dropped.
+    @CommandHandler
+    fun endConnection(callback: (Result<Boolean>) -> Unit) {
+        implementation.endConnection()
         callback(Result.success(true))
     }
 
@@ -75,7 +130,7 @@ class IapPlugin(activity: Activity): Plugin(activity) {
                 }
                 callback(Result.success(response))
             } catch (e: Exception) {
-                callback(Result.failure(e))
+                callback(Result.failure(e.toIapException()))
             }
         }
     }
@@ -90,7 +145,7 @@ class IapPlugin(activity: Activity): Plugin(activity) {
                 Log.d(TAG, "Non-consumable purchase result: ${result.success}")
                 callback(Result.success(result.success))
             } catch (e: Exception) {
-                callback(Result.failure(e))
+                callback(Result.failure(e.toIapException()))
             }
         }
     }
@@ -105,7 +160,7 @@ class IapPlugin(activity: Activity): Plugin(activity) {
                 Log.d(TAG, "Consumable purchase result: ${result.success}")
                 callback(Result.success(result.success))
             } catch (e: Exception) {
-                callback(Result.failure(e))
+                callback(Result.failure(e.toIapException()))
             }
         }
     }
@@ -120,35 +175,223 @@ class IapPlugin(activity: Activity): Plugin(activity) {
                     val result = implementation.completePurchase(purchase)
                     callback(Result.success(result))
                 } else {
-                    callback(Result.failure(Exception("Purchase not found")))
+                    callback(Result.failure(IapException("itemNotOwned", "Purchase not found")))
                 }
             } catch (e: Exception) {
-                callback(Result.failure(e))
+                callback(Result.failure(e.toIapException()))
             }
         }
     }
 
+    /**
+     * Replaces the subscription bought with `oldPurchaseToken` by `productId`,
+     * which the Rust core picked among the active subscriptions. Resolves
+     * with `{ purchase }`, the new `PurchaseDetails`, or without one while
+     * the purchase is pending.
+     */
+    @CommandHandler
+    fun buyUpgrade(args: JSObject, callback: (Result<JSObject>) -> Unit) {
+        scope.launch {
+            try {
+                val purchase = implementation.changeSubscription(
+                    productId = args.getString("productId"),
+                    oldPurchaseToken = args.getString("oldPurchaseToken"),
+                    replacementMode = replacementMode(args.getString("replacementMode")),
+                )
+                callback(Result.success(JSObject().apply {
+                    purchase?.let { put("purchase", purchaseDetails(it)) }
+                }))
+            } catch (e: Exception) {
+                callback(Result.failure(e.toIapException()))
+            }
+        }
+    }
+
+    /**
+     * Returns the owned purchases as `PurchaseDetails`, which the Rust core
+     * reports like the desktop restores. Google Play has no restore flow, so
+     * this is the same query as [ownedPurchases].
+     */
     @CommandHandler
     fun restorePurchases(args: JSObject?, callback: (Result<JSObject>) -> Unit) {
+        ownedPurchases(callback)
+    }
+
+    /**
+     * Returns the owned purchases as `PurchaseDetails`, for the
+     * `autoRestoreOnInit` query. Unlike a restore, nothing is shown to the user.
+     */
+    @CommandHandler
+    fun ownedPurchases(callback: (Result<JSObject>) -> Unit) {
         scope.launch {
             try {
-                val purchases = implementation.restorePurchases()
-                val response = JSObject().apply {
-                    put("purchases", JSONArray().apply {
-                        purchases.forEach { purchase ->
-                            put(JSONObject().apply {
-                                put("purchaseId", purchase.orderId)
-                                put("productId", purchase.products.firstOrNull())
-                                put("purchaseToken", purchase.purchaseToken)
-                                put("purchaseTime", purchase.purchaseTime)
-                                put("purchaseState", purchase.purchaseState)
-                            })
-                        }
-                    })
+                callback(Result.success(ownedPurchasesResponse(implementation.restorePurchases())))
+            } catch (e: Exception) {
+                callback(Result.failure(e.toIapException()))
+            }
+        }
+    }
+
+    /**
+     * Returns the owned in-app products and active subscriptions as
+     * `PurchaseDetails`, without any UI.
+     */
+    @CommandHandler
+    fun currentEntitlements(callback: (Result<JSObject>) -> Unit) {
+        scope.launch {
+            try {
+                callback(Result.success(ownedPurchasesResponse(implementation.currentEntitlements())))
+            } catch (e: Exception) {
+                callback(Result.failure(e.toIapException()))
+            }
+        }
+    }
+
+    /**
+     * Returns the `SubscriptionStatus` of a subscription the user owns. Google
+     * Play only returns the subscriptions granting access and shows no grace
+     * period on device, so they are reported as active, and those on account
+     * hold or expired as not owned.
+     */
+    @CommandHandler
+    fun subscriptionStatus(args: JSObject, callback: (Result<JSObject>) -> Unit) {
+        scope.launch {
+            try {
+                val productId = args.getString("productId")
+                val purchase = implementation.activeSubscriptions().find { productId in it.products }
+                    ?: throw IapException("itemNotOwned", "Item not owned: $productId")
+                callback(Result.success(JSObject().apply {
+                    put("productId", productId)
+                    put("isActive", true)
+                    put("state", "active")
+                    put("willAutoRenew", purchase.isAutoRenewing)
+                }))
+            } catch (e: Exception) {
+                callback(Result.failure(e.toIapException()))
+            }
+        }
+    }
+
+    /**
+     * The `{ purchases }` response of the owned purchase queries, with the
+     * owned ones reported as restored.
+     */
+    private fun ownedPurchasesResponse(purchases: List<Purchase>): JSObject = JSObject().apply {
+        put("purchases", JSONArray().apply {
+            purchases.forEach { purchase ->
+                val status = when (purchase.purchaseState) {
+                    Purchase.PurchaseState.PURCHASED -> "restored"
+                    else -> purchaseStatus(purchase)
                 }
-                callback(Result.success(response))
+                put(purchaseDetails(purchase, status))
+            }
+        })
+    }
+
+    /**
+     * A purchase in the shape of the Rust `PurchaseDetails`.
+     */
+    private fun purchaseDetails(purchase: Purchase, status: String = purchaseStatus(purchase)): JSONObject =
+        JSONObject().apply {
+            put("purchaseId", purchase.orderId)
+            put("productId", purchase.products.firstOrNull())
+            put("verificationData", verificationData(purchase))
+            put("transactionDateMs", purchase.purchaseTime)
+            put("status", status)
+            put("pendingCompletePurchase", !purchase.isAcknowledged)
+            subscriptionState(purchase)?.let { put("subscriptionState", it) }
+        }
+
+    @CommandHandler
+    fun isFeatureSupported(args: JSObject, callback: (Result<JSObject>) -> Unit) {
+        try {
+            val featureType = when (val feature = args.getString("feature")) {
+                "subscriptions" -> BillingClient.FeatureType.SUBSCRIPTIONS
+                "subscriptionsUpdate" -> BillingClient.FeatureType.SUBSCRIPTIONS_UPDATE
+                "inAppMessaging" -> BillingClient.FeatureType.IN_APP_MESSAGING
+                "externalOffer" -> BillingClient.FeatureType.EXTERNAL_OFFER
+                // Billing 6 removed the confirmation flow, Play notifies users of price changes.
+                "priceChangeConfirmation" -> null
+                else -> throw IapException("featureNotSupported", "Unknown store feature: $feature")
+            }
+            val supported = featureType != null && implementation.isFeatureSupported(featureType)
+            callback(Result.success(JSObject().apply { put("supported", supported) }))
+        } catch (e: Exception) {
+            callback(Result.failure(e.toIapException()))
+        }
+    }
+
+    @CommandHandler
+    fun diagnostics(callback: (Result<JSObject>) -> Unit) {
+        callback(Result.success(JSObject().apply {
+            put("billingLibraryVersion", BuildConfig.BILLING_LIBRARY_VERSION)
+        }))
+    }
+
+    // The Rust core maps the installer to an `InstallSource`.
+    @CommandHandler
+    fun installSource(callback: (Result<JSObject>) -> Unit) {
+        try {
+            callback(Result.success(JSObject().apply {
+                put("installerPackageName", implementation.installerPackageName())
+                put("debuggable", implementation.isDebuggable)
+            }))
+        } catch (e: Exception) {
+            callback(Result.failure(e.toIapException()))
+        }
+    }
+
+    @CommandHandler
+    fun openManageSubscriptions(callback: (Result<Boolean>) -> Unit) {
+        try {
+            implementation.openManageSubscriptions()
+            callback(Result.success(true))
+        } catch (e: Exception) {
+            callback(Result.failure(e.toIapException()))
+        }
+    }
+
+    @CommandHandler
+    fun openStoreProductPage(args: JSObject, callback: (Result<Boolean>) -> Unit) {
+        try {
+            val productId = if (args.isNull("productId")) null else args.getString("productId")
+            implementation.openStoreProductPage(productId)
+            callback(Result.success(true))
+        } catch (e: Exception) {
+            callback(Result.failure(e.toIapException()))
+        }
+    }
+
+    @CommandHandler
+    fun isAlternativeBillingAvailable(callback: (Result<Boolean>) -> Unit) {
+        scope.launch {
+            try {
+                callback(Result.success(implementation.isAlternativeBillingAvailable()))
+            } catch (e: Exception) {
+                callback(Result.failure(e.toIapException()))
+            }
+        }
+    }
+
+    @CommandHandler
+    fun showAlternativeBillingDialog(callback: (Result<Boolean>) -> Unit) {
+        scope.launch {
+            try {
+                callback(Result.success(implementation.showAlternativeBillingDialog()))
             } catch (e: Exception) {
-                callback(Result.failure(e))
+                callback(Result.failure(e.toIapException()))
+            }
+        }
+    }
+
+    @CommandHandler
+    fun createAlternativeBillingToken(callback: (Result<JSObject>) -> Unit) {
+        scope.launch {
+            try {
+                val token = implementation.createAlternativeBillingToken()
+                callback(Result.success(JSObject().apply { put("externalTransactionToken", token) }))
+            } catch (e: Exception) {
+                callback(Result.failure(e.toIapException()))
             }
         }
     }
@@ -165,34 +408,95 @@ class IapPlugin(activity: Activity): Plugin(activity) {
                 put("price", offer.formattedPrice)
                 put("rawPrice", offer.priceAmountMicros / 1_000_000.0)
                 put("currencyCode", offer.priceCurrencyCode)
+                put("currencySymbol", Currency.getInstance(offer.priceCurrencyCode).symbol)
             }
         }
     }
 
+    /**
+     * Maps the Play purchase state to the `PurchaseStatus` of the Rust core.
+     */
+    private fun purchaseStatus(purchase: Purchase): String = when (purchase.purchaseState) {
+        Purchase.PurchaseState.PURCHASED -> "purchased"
+        Purchase.PurchaseState.PENDING -> "pending"
+        else -> "error"
+    }
+
+    /**
+     * The `SubscriptionState` of a subscription purchase, told apart from
+     * one-time products by the `autoRenewing` field only subscriptions have.
+     * Google Play only reports subscriptions granting access, so a purchased
+     * one is active, which includes the grace period.
+     */
+    private fun subscriptionState(purchase: Purchase): String? {
+        val isSubscription = JSONObject(purchase.originalJson).has("autoRenewing")
+        return if (isSubscription && purchase.purchaseState == Purchase.PurchaseState.PURCHASED) "active" else null
+    }
+
+    /**
+     * The purchase data for verification. The Rust core verifies the signature
+     * over the original JSON, byte for byte.
+     */
+    private fun verificationData(purchase: Purchase): JSONObject = JSONObject().apply {
+        put("localVerificationData", purchase.originalJson)
+        put("serverVerificationData", purchase.purchaseToken)
+        put("source", "google")
+        put("signature", purchase.signature)
+    }
+
     /**
      * Retrieves cached ProductDetails by product ID.
      *
      * @param productId The ID of the product to retrieve
-     * @throws IllegalStateException if the product details are not found in cache
+     * @throws IapException if the product details are not found in cache
      */
     private fun getProductDetails(productId: String): ProductDetails {
         return productDetailsCache[productId]
-            ?: throw IllegalStateException("Product details not found for $productId. Call queryProductDetails first.")
+            ?: throw IapException(
+                "developerError",
+                "Product details not found for $productId. Call queryProductDetails first."
+            )
     }
 
     init {
-        implementation.setPurchaseUpdateListener { purchases ->
+        IapBridge.reportLocaleChanges(activity)
+        implementation.setConnectionStateListener { state, attempt ->
+            IapBridge.reportConnectionState(state, attempt)
+        }
+        implementation.setPurchaseErrorListener { error ->
+            IapBridge.reportError(error)
+        }
+        implementation.setPurchaseUpdateListener { updated ->
+            // Purchases awaiting payment are reported again once paid.
+            val purchases = updated.filter {
+                implementation.pendingPurchases.enabled ||
+                    it.purchaseState != Purchase.PurchaseState.PENDING
+            }
+            if (purchases.isEmpty()) return@setPurchaseUpdateListener
             Log.d(TAG, "Purchase update received: ${purchases.size} purchases")
+            // The Rust core verifies, records and emits them to the webview.
+            IapBridge.reportPurchases(JSONArray().apply {
+                purchases.forEach { put(purchaseDetails(it)) }
+            })
             val event = JSObject().apply {
                 put("purchases", JSONArray().apply {
                     purchases.forEach { purchase ->
                         put(JSONObject().apply {
                             put("purchaseId", purchase.orderId)
                             put("productId", purchase.products.firstOrNull())
-                            put("purchaseToken", purchase.purchaseToken)
                             put("purchaseTime", purchase.purchaseTime)
                             put("purchaseState", purchase.purchaseState)
                             put("isAcknowledged", purchase.isAcknowledged)
+                            put("transactionDateMs", purchase.purchaseTime)
+                            put("status", purchaseStatus(purchase))
+                            put("pendingCompletePurchase", !purchase.isAcknowledged)
+                            subscriptionState(purchase)?.let { put("subscriptionState", it) }
+                            if (redactVerificationData) {
+                                put("verificationAvailable", purchase.purchaseToken.isNotEmpty())
+                            } else {
+                                put("purchaseToken", purchase.purchaseToken)
+                                put("verificationData", verificationData(purchase))
+                            }
                         })
                     }
                 })
@@ -1,5 +1,7 @@
 package com.plugin.iap
 
+import com.android.billingclient.api.BillingClient.BillingResponseCode
+import kotlinx.coroutines.runBlocking
 import org.junit.Test
 
 import org.junit.Assert.*
@@ -10,4 +12,103 @@ import org.junit.Assert.*
  * See [testing documentation](http://d.android.com/tools/testing).
  */
 class IapUnitTest {
+    /**
+     * Billing connection answering with [responseCodes] in turn, recording
+     * the reported states and the delays waited between attempts.
+     */
+    private class ScriptedConnection(vararg responseCodes: Int) {
+        private val remaining = ArrayDeque(responseCodes.toList())
+        val attempts get() = reported.count { it.first == "reconnecting" }
+        val reported = mutableListOf<Pair<String, Int>>()
+        val delays = mutableListOf<Long>()
+
+        fun reconnect(policy: ConnectionRetryPolicy): Int = runBlocking {
+            policy.reconnect(
+                connect = { remaining.removeFirst() },
+                report = { state, attempt -> reported.add(state to attempt) },
+                sleep = { delays.add(it) }
+            )
+        }
+    }
+
+    private val policy = ConnectionRetryPolicy(
+        maxAttempts = 5,
+        baseDelayMs = 100,
+        maxDelayMs = 350,
+        jitter = false
+    )
+
+    @Test
+    fun reconnectsAfterFailedAttempts() {
+        val connection = ScriptedConnection(
+            BillingResponseCode.SERVICE_UNAVAILABLE,
+            BillingResponseCode.SERVICE_DISCONNECTED,
+            BillingResponseCode.NETWORK_ERROR,
+            BillingResponseCode.OK
+        )
+
+        assertEquals(BillingResponseCode.OK, connection.reconnect(policy))
+
+        assertEquals(listOf(100L, 200L, 350L, 350L), connection.delays)
+        assertEquals(
+            listOf(
+                "reconnecting" to 1,
+                "reconnecting" to 2,
+                "reconnecting" to 3,
+                "reconnecting" to 4,
+                "ready" to 0
+            ),
+            connection.reported
+        )
+    }
+
+    @Test
+    fun givesUpAfterTheLastAttempt() {
+        val connection = ScriptedConnection(
+            *IntArray(5) { BillingResponseCode.SERVICE_UNAVAILABLE }
+        )
+
+        assertEquals(BillingResponseCode.SERVICE_UNAVAILABLE, connection.reconnect(policy))
+
+        assertEquals(5, connection.attempts)
+        assertEquals("uninitialized" to 0, connection.reported.last())
+    }
+
+    @Test
+    fun retriesUntilConnectedWithoutMaxAttempts() {
+        val connection = ScriptedConnection(
+            *IntArray(9) { BillingResponseCode.SERVICE_UNAVAILABLE },
+            BillingResponseCode.OK
+        )
+
+        connection.reconnect(policy.copy(maxAttempts = null))
+
+        assertEquals(10, connection.attempts)
+        assertEquals("ready" to 0, connection.reported.last())
+    }
+
+    @Test
+    fun stopsOnceBillingIsUnavailable() {
+        val connection = ScriptedConnection(
+            BillingResponseCode.SERVICE_UNAVAILABLE,
+            BillingResponseCode.BILLING_UNAVAILABLE,
+            BillingResponseCode.OK
+        )
+
+        assertEquals(BillingResponseCode.BILLING_UNAVAILABLE, connection.reconnect(policy))
+
+        assertEquals(2, connection.attempts)
+        assertEquals("uninitialized" to 0, connection.reported.last())
+    }
+
+    @Test
+    fun jitteredDelaysStayWithinHalfOfTheBackoff() {
+        val jittered = policy.copy(jitter = true)
+        for (attempt in 1..6) {
+            val delay = policy.backoff(attempt)
+            repeat(20) {
+                assertTrue(jittered.backoff(attempt) in delay / 2..delay)
+            }
+        }
+    }
 }
@@ -1,17 +1,94 @@
+/// Commands of the in-app purchase flows, in the default permission set
+/// except for the mock store controls.
 const COMMANDS: &[&str] = &[
     "initialize",
     "is_available",
+    "connection_state",
+    "end_connection",
     "query_product_details",
     "buy_non_consumable",
     "buy_consumable",
+    "buy_upgrade",
     "complete_purchase",
+    "finalize_after",
     "restore_purchases",
+    "is_owned",
+    "owned_products",
+    "current_entitlements",
+    "set_application_user_name",
     "country_code",
+    "is_feature_supported",
+    "install_source",
+    "diagnostics",
+    "metrics",
+    "reset_metrics",
+    "app_license",
+    "get_app_receipt",
+    "export_verification_payload",
+    "export_entitlements",
+    "import_entitlements",
+    "subscription_status",
+    "subscription_time_remaining",
+    "is_alternative_billing_available",
+    "show_alternative_billing_dialog",
+    "create_alternative_billing_token",
+    "external_purchase_available",
+    "open_external_purchase_link",
+    "external_purchase_token",
+    "open_store_product_page",
+    "show_price_consent_if_needed",
+    "mock_set_failure",
+    "mock_set_latency",
+    "mock_clear_purchases",
+    "mock_emit_purchase",
 ];
 
+/// Commands opening the store's subscription management flows, only allowed
+/// through the `subscription-management` set.
+const SUBSCRIPTION_MANAGEMENT_COMMANDS: &[&str] = &["open_manage_subscriptions"];
+
+/// Bundle of the guest bindings injected when `withGlobalTauri` is set.
+const GLOBAL_API_SCRIPT: &str = "./api-iife.js";
+
+/// Writes the `subscription-management` permission set, keeping it in sync
+/// with `SUBSCRIPTION_MANAGEMENT_COMMANDS`.
+fn write_subscription_management_set() {
+    let permissions = SUBSCRIPTION_MANAGEMENT_COMMANDS
+        .iter()
+        .map(|command| format!("    \"allow-{}\",\n", command.replace('_', "-")))
+        .collect::<String>();
+    let set = format!(
+        "# Automatically generated by build.rs - DO NOT EDIT!\n\n\
+         [[set]]\n\
+         identifier = \"subscription-management\"\n\
+         description = \"Allows the commands opening the store's subscription management flows. \
+         Not part of the default permissions.\"\n\
+         permissions = [\n{permissions}]\n"
+    );
+    let path = std::path::Path::new("permissions/subscription-management.toml");
+    if std::fs::read_to_string(path).ok().as_deref() != Some(set.as_str()) {
+        std::fs::write(path, set).expect("failed to write the subscription-management set");
+    }
+}
+
 fn main() {
-    tauri_plugin::Builder::new(COMMANDS)
+    #[cfg(feature = "mac-app-store")]
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("macos") {
+        swift_rs::SwiftLinker::new("12.0")
+            .with_package("tauri-plugin-iap-macos", "./macos/")
+            .link();
+    }
+
+    write_subscription_management_set();
+    let commands = [COMMANDS, SUBSCRIPTION_MANAGEMENT_COMMANDS].concat();
+    let mut builder = tauri_plugin::Builder::new(&commands)
         .android_path("android")
-        .ios_path("ios")
-        .build();
+        .ios_path("ios");
+    // Built from guest-js by `pnpm build`, and shipped with the crate.
+    // Checkouts that haven't built it yet just lack `window.__TAURI__.iap`.
+    println!("cargo:rerun-if-changed={GLOBAL_API_SCRIPT}");
+    if std::path::Path::new(GLOBAL_API_SCRIPT).exists() {
+        builder = builder.global_api_script_path(GLOBAL_API_SCRIPT);
+    }
+    builder.build();
 }
@@ -1,13 +1,8 @@
-// Learn more about Tauri commands at https://v2.tauri.app/develop/calling-rust/#commands
-#[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
-}
-
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![greet])
+        // On desktop the mock store sells the `mockProducts` of
+        // `tauri.conf.json`; mobile builds use the platform store.
         .plugin(tauri_plugin_iap::init())
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,5 +1,8 @@
 import { invoke } from '@tauri-apps/api/core'
 import { listen, UnlistenFn } from '@tauri-apps/api/event'
+import { IapFake, type IapMockOptions } from './mock'
+
+export { IapFake, type IapMockCall, type IapMockOptions } from './mock'
 
 // --- Types and Interfaces ---
 
@@ -22,6 +25,12 @@ export interface ProductDetails {
   currencyCode: string;
   /** Currency symbol (e.g., "$") */
   currencySymbol: string;
+  /** ISO 8601 duration of the billing period, for subscriptions (e.g., "P1M") */
+  subscriptionPeriod?: string;
+  /** ISO 8601 duration of the free trial offered, for subscriptions (optional) */
+  freeTrialPeriod?: string;
+  /** App Store subscription group of a subscription (optional) */
+  subscriptionGroupId?: string;
 }
 
 /**
@@ -33,8 +42,106 @@ export interface PurchaseVerificationData {
   localVerificationData: string;
   /** Platform-specific server verification data */
   serverVerificationData: string;
-  /** Source platform ("apple" or "google") */
+  /** Source platform ("apple", "google" or "amazon") */
   source: string;
+  /** Google Play signature of `localVerificationData` (optional) */
+  signature?: string;
+}
+
+/**
+ * State of the connection to the store
+ * @enum {string}
+ */
+export enum ConnectionState {
+  /** `initialize()` has not succeeded yet, or reconnecting gave up */
+  uninitialized = "uninitialized",
+  /** `initialize()` is connecting to the store */
+  initializing = "initializing",
+  /** Connected to the store */
+  ready = "ready",
+  /** The connection dropped and is being re-established */
+  reconnecting = "reconnecting",
+  /** `endConnection()` was called or the plugin was torn down */
+  closed = "closed",
+}
+
+/**
+ * Store capability checked with `isFeatureSupported`
+ * @enum {string}
+ */
+export enum StoreFeature {
+  /** Selling subscriptions */
+  subscriptions = "subscriptions",
+  /** Upgrading or downgrading a subscription the user has */
+  subscriptionsUpdate = "subscriptionsUpdate",
+  /** Asking the user to accept a subscription price increase */
+  priceChangeConfirmation = "priceChangeConfirmation",
+  /** In-app messages of the store, e.g. about a declined payment */
+  inAppMessaging = "inAppMessaging",
+  /** Linking to offers outside of the store */
+  externalOffer = "externalOffer",
+}
+
+/**
+ * Where the app was installed from, returned by `installSource`
+ * @enum {string}
+ */
+export enum InstallSource {
+  /** Google Play */
+  playStore = "playStore",
+  /** The Amazon Appstore */
+  amazonStore = "amazonStore",
+  /** An APK installed outside of any store, which can't buy through Google Play */
+  sideload = "sideload",
+  /** A TestFlight build, buying in the App Store sandbox */
+  testFlight = "testFlight",
+  /** The App Store on iOS */
+  appStore = "appStore",
+  /** The Mac App Store */
+  macAppStore = "macAppStore",
+  /** A debuggable, Simulator, Xcode or ad hoc build */
+  devBuild = "devBuild",
+  /** Anything else, e.g. another store or a desktop build outside of a store */
+  unknown = "unknown",
+}
+
+/**
+ * How Google Play bills a change from one subscription to another. The App Store
+ * decides by itself, upgrading right away and downgrading at the next renewal.
+ * @enum {string}
+ */
+export enum ReplacementMode {
+  /** Switches right away, crediting the unused time of the old subscription as time on the new one */
+  withTimeProration = "withTimeProration",
+  /** Switches right away and charges the price difference for the rest of the period, for upgrades only */
+  chargeProratedPrice = "chargeProratedPrice",
+  /** Switches right away without charging, billing the new price from the next renewal */
+  withoutProration = "withoutProration",
+  /** Switches right away and charges the full price, starting a new billing period */
+  chargeFullPrice = "chargeFullPrice",
+  /** Switches and bills the new price when the current subscription expires, e.g. for downgrades */
+  deferred = "deferred",
+}
+
+/**
+ * Connection state transition, emitted to `onConnectionStateChange`
+ * @interface ConnectionStateChange
+ */
+export interface ConnectionStateChange {
+  /** State before the transition */
+  previous: ConnectionState;
+  /** State after the transition */
+  state: ConnectionState;
+  /**
+   * Reconnection attempt about to be made, starting at 1. Emitted again for
+   * each attempt while `reconnecting`.
+   */
+  attempt?: number;
+  /**
+   * Whether the device has a store to buy from, `false` once connecting
+   * failed with `storeUnavailable`, e.g. without Google Play
+   */
+  storePresent: boolean;
 }
 
 /**
@@ -54,8 +161,28 @@ export enum PurchaseStatus {
   canceled = "canceled",
 }
 
+/**
+ * Billing state of a subscription
+ * @enum {string}
+ */
+export enum SubscriptionState {
+  /** Paid for the current period */
+  active = "active",
+  /** A renewal payment failed and the store is retrying it, while the user keeps access until the grace period ends */
+  gracePeriod = "gracePeriod",
+  /** The grace period ended without a payment: account hold on Google Play, billing retry on the App Store. No access until the user fixes their payment method */
+  onHold = "onHold",
+  /** Ended without renewing */
+  expired = "expired",
+  /** Refunded or revoked by the store */
+  revoked = "revoked",
+}
+
 /**
  * Error information for IAP operations
+ *
+ * Payload of error events and failed purchases. Binding functions throw it
+ * as an {@link IapError}.
  * @interface IAPError
  */
 export interface IAPError {
@@ -65,6 +192,320 @@ export interface IAPError {
   message: string;
   /** Additional error details (optional) */
   details?: any;
+  /** Raw response code reported by the native store (optional) */
+  nativeCode?: number;
+}
+
+/**
+ * Stable error codes, mirroring `IapErrorCode` in Rust
+ *
+ * Kept in sync with the Rust codes through `guest-js/error-codes.json`.
+ * @enum {string}
+ * @example
+ * ```ts
+ * switch (error.code) {
+ *   case IapErrorCode.UserCancelled:
+ *     return;
+ *   case IapErrorCode.ItemAlreadyOwned:
+ *     return unlock();
+ * }
+ * ```
+ */
+export enum IapErrorCode {
+  /** Reading or writing a file failed */
+  Io = "io",
+  /** Calling the native plugin failed */
+  PluginInvoke = "pluginInvoke",
+  /** In-app purchases are not supported on this platform */
+  PlatformNotSupported = "platformNotSupported",
+  /** `initialize()` has not succeeded yet */
+  NotInitialized = "notInitialized",
+  /** The store billing client failed to initialize */
+  BillingClientInitError = "billingClientInitError",
+  /** The device has no store to buy from, e.g. no Google Play */
+  StoreUnavailable = "storeUnavailable",
+  /** Querying product details failed */
+  ProductQueryError = "productQueryError",
+  /** The purchase flow failed */
+  PurchaseError = "purchaseError",
+  /** Consuming a purchase failed */
+  ConsumptionError = "consumptionError",
+  /** Restoring purchases failed */
+  RestoreError = "restoreError",
+  /** The purchase token or receipt is invalid */
+  InvalidPurchaseToken = "invalidPurchaseToken",
+  /** The store could not be reached, worth retrying */
+  NetworkError = "networkError",
+  /** A store API throttled the request, worth retrying */
+  RateLimited = "rateLimited",
+  /** The user cancelled the store UI */
+  UserCancelled = "userCancelled",
+  /** The user already owns the product */
+  ItemAlreadyOwned = "itemAlreadyOwned",
+  /** The user does not own the product */
+  ItemNotOwned = "itemNotOwned",
+  /** The purchase was never reported by the store */
+  UnknownPurchase = "unknownPurchase",
+  /** `buyUpgrade` found no active subscription to replace */
+  NoActiveSubscription = "noActiveSubscription",
+  /** `buyUpgrade` found several active subscriptions it could replace */
+  AmbiguousSubscription = "ambiguousSubscription",
+  /** The connection to the store dropped, worth retrying */
+  ServiceDisconnected = "serviceDisconnected",
+  /** The store did not answer in time, worth retrying */
+  ServiceTimeout = "serviceTimeout",
+  /** An operation exceeded its configured timeout */
+  TimedOut = "timedOut",
+  /** The command scope doesn't allow purchasing the product */
+  ScopeViolation = "scopeViolation",
+  /** The calling window is missing from `allowedWindows` */
+  WindowNotAllowed = "windowNotAllowed",
+  /** The App Store receipt is missing */
+  ReceiptUnavailable = "receiptUnavailable",
+  /** The store or platform does not support the operation */
+  FeatureNotSupported = "featureNotSupported",
+  /** The store rejected a misconfigured integration */
+  DeveloperError = "developerError",
+  /** The plugin configuration is invalid */
+  InvalidConfig = "invalidConfig",
+  /** An unexpected store or plugin failure */
+  InternalError = "internalError",
+  /** A code this version of the bindings does not know */
+  Unknown = "unknown",
+}
+
+/**
+ * Names of the events the plugin emits, mirroring the `*_EVENT` constants in Rust
+ *
+ * The `on*` helpers listen to them, e.g. `onPurchaseUpdate` to `IapEvent.PurchaseUpdate`.
+ * @enum {string}
+ */
+export enum IapEvent {
+  /** Purchases reported by the store, a `PurchaseDetails[]` payload */
+  PurchaseUpdate = "tauri-plugin-iap://purchase-update",
+  /** `restorePurchases()` reported all owned purchases, a `RestoreCompleted` payload */
+  RestoreCompleted = "tauri-plugin-iap://restore-completed",
+  /** The configured products were preloaded, a `ProductDetailsResponse` payload */
+  ProductsLoaded = "tauri-plugin-iap://products-loaded",
+  /** A background operation failed, an `IAPError` payload */
+  Error = "tauri-plugin-iap://error",
+  /** The store connection changed state, a `ConnectionStateChange` payload */
+  ConnectionState = "tauri-plugin-iap://connection-state",
+  /** The user's storefront or language changed, without payload */
+  StorefrontChanged = "tauri-plugin-iap://storefront-changed",
+}
+
+/** Codes of the failures worth retrying, like `Error::is_retryable` in Rust */
+const RETRYABLE_CODES: readonly string[] = [
+  IapErrorCode.NetworkError,
+  IapErrorCode.RateLimited,
+  IapErrorCode.ServiceDisconnected,
+  IapErrorCode.ServiceTimeout
+];
+
+/**
+ * Error thrown by every binding function, with the `code`, `details` and
+ * `nativeCode` of the Rust error
+ *
+ * Failures with a dedicated subclass, e.g. {@link UserCancelledError} or
+ * {@link NetworkError}, are thrown as that subclass, the others as `IapError`.
+ * @example
+ * ```ts
+ * try {
+ *   await buyNonConsumable({ productDetails });
+ * } catch (e) {
+ *   if (e instanceof UserCancelledError) return;
+ *   if (e instanceof IapError && e.retryable) scheduleRetry();
+ *   else throw e;
+ * }
+ * ```
+ */
+export class IapError extends Error implements IAPError {
+  /** Error code */
+  readonly code: string;
+  /** Additional error details (optional) */
+  readonly details?: any;
+  /** Raw response code reported by the native store (optional) */
+  readonly nativeCode?: number;
+
+  constructor(error: IAPError) {
+    super(error.message);
+    this.name = new.target.name;
+    this.code = error.code;
+    this.details = error.details;
+    this.nativeCode = error.nativeCode;
+  }
+
+  /** Whether retrying the operation may succeed */
+  get retryable(): boolean {
+    return RETRYABLE_CODES.includes(this.code);
+  }
+}
+
+/** The user cancelled the store UI, usually nothing to report */
+export class UserCancelledError extends IapError {}
+
+/** The store could not be reached, worth retrying */
+export class NetworkError extends IapError {}
+
+/** A store API throttled the request, worth retrying after `retryAfterMs` */
+export class RateLimitedError extends IapError {
+  /** Delay the store asked to wait before retrying, in milliseconds (optional) */
+  get retryAfterMs(): number | undefined {
+    return this.details?.retryAfterMs;
+  }
+}
+
+/** The connection to the store dropped, worth retrying */
+export class ServiceDisconnectedError extends IapError {}
+
+/** The store did not answer in time, worth retrying */
+export class ServiceTimeoutError extends IapError {}
+
+/** An operation exceeded its configured timeout, `details.operation` names it */
+export class TimedOutError extends IapError {}
+
+/** `initialize` has not succeeded yet */
+export class NotInitializedError extends IapError {}
+
+/** The device has no store to buy from, e.g. no Google Play; hide the store UI */
+export class StoreUnavailableError extends IapError {}
+
+/** The user already owns the product */
+export class ItemAlreadyOwnedError extends IapError {}
+
+/** The user does not own the product */
+export class ItemNotOwnedError extends IapError {}
+
+/** The store never reported the purchase */
+export class UnknownPurchaseError extends IapError {}
+
+/** `buyUpgrade` found no active subscription to replace */
+export class NoActiveSubscriptionError extends IapError {}
+
+/** `buyUpgrade` found several active subscriptions to replace */
+export class AmbiguousSubscriptionError extends IapError {
+  /** Product IDs of the active subscriptions */
+  get candidates(): string[] {
+    return this.details?.candidates ?? [];
+  }
+}
+
+/** The purchase token, receipt or signed transaction was rejected */
+export class InvalidPurchaseTokenError extends IapError {}
+
+/** The store or platform does not support the operation */
+export class FeatureNotSupportedError extends IapError {}
+
+/** The capability scope does not allow the product, `details.productId` names it */
+export class ScopeViolationError extends IapError {}
+
+/** The window may not call the command, `details.window` names it */
+export class WindowNotAllowedError extends IapError {}
+
+/** The plugin configuration or credentials are invalid */
+export class InvalidConfigError extends IapError {}
+
+const ERROR_CLASSES: Record<string, new (error: IAPError) => IapError> = {
+  [IapErrorCode.UserCancelled]: UserCancelledError,
+  [IapErrorCode.NetworkError]: NetworkError,
+  [IapErrorCode.RateLimited]: RateLimitedError,
+  [IapErrorCode.ServiceDisconnected]: ServiceDisconnectedError,
+  [IapErrorCode.ServiceTimeout]: ServiceTimeoutError,
+  [IapErrorCode.TimedOut]: TimedOutError,
+  [IapErrorCode.NotInitialized]: NotInitializedError,
+  [IapErrorCode.StoreUnavailable]: StoreUnavailableError,
+  [IapErrorCode.ItemAlreadyOwned]: ItemAlreadyOwnedError,
+  [IapErrorCode.ItemNotOwned]: ItemNotOwnedError,
+  [IapErrorCode.UnknownPurchase]: UnknownPurchaseError,
+  [IapErrorCode.NoActiveSubscription]: NoActiveSubscriptionError,
+  [IapErrorCode.AmbiguousSubscription]: AmbiguousSubscriptionError,
+  [IapErrorCode.InvalidPurchaseToken]: InvalidPurchaseTokenError,
+  [IapErrorCode.FeatureNotSupported]: FeatureNotSupportedError,
+  [IapErrorCode.ScopeViolation]: ScopeViolationError,
+  [IapErrorCode.WindowNotAllowed]: WindowNotAllowedError,
+  [IapErrorCode.InvalidConfig]: InvalidConfigError,
+};
+
+/**
+ * Converts a command rejection or an error event payload into an
+ * {@link IapError}, or the subclass of its code
+ *
+ * Rejections that aren't plugin errors, e.g. a command denied by the
+ * capabilities, get the `unknown` code.
+ * @param error - The rejection
+ * @returns The typed error
+ */
+export function toIapError(error: unknown): IapError {
+  if (error instanceof IapError) {
+    return error;
+  }
+  const payload: IAPError =
+    typeof error === 'object' && error !== null && typeof (error as IAPError).code === 'string'
+      ? (error as IAPError)
+      : { code: IapErrorCode.Unknown, message: error instanceof Error ? error.message : String(error) };
+  const ErrorClass = ERROR_CLASSES[payload.code] ?? IapError;
+  return new ErrorClass(payload);
+}
+
+/** Fake store installed by {@link setIapMock}, serving the commands and events instead of the plugin */
+let fake: IapFake | null = null;
+
+/**
+ * Serves every binding function and event helper from an in-memory fake store
+ * until {@link resetIapMock}, so frontend code can be tested without a Tauri app
+ *
+ * The fake behaves like the `mock` cargo feature: purchases succeed right away and
+ * are reported through `onPurchaseUpdate`, owned products can't be bought again,
+ * and injected failures reject with the same typed errors.
+ * @param options - Products, existing purchases and failures of the fake store
+ * @returns The fake store, to inspect the commands it received or push updates
+ * @example
+ * ```ts
+ * const store = setIapMock({
+ *   products: [premium],
+ *   failures: [{ operation: 'restore_purchases', code: 'networkError' }]
+ * });
+ * const result = await purchase(premium);
+ * expect(result.status).toBe('purchased');
+ * await expect(restorePurchases()).rejects.toBeInstanceOf(NetworkError);
+ * resetIapMock();
+ * ```
+ */
+export function setIapMock(options: IapMockOptions = {}): IapFake {
+  fake = new IapFake(options);
+  return fake;
+}
+
+/**
+ * Removes the fake store installed by {@link setIapMock}, calling the plugin again
+ *
+ * Handlers registered while the fake was installed stop receiving events.
+ */
+export function resetIapMock(): void {
+  fake = null;
+}
+
+/** Invokes a plugin command, rejecting with an {@link IapError}. */
+async function call<T>(command: string, args?: Record<string, unknown>): Promise<T> {
+  try {
+    if (fake) {
+      return await fake.invoke<T>(command, args);
+    }
+    return await invoke<T>(`plugin:iap|${command}`, args);
+  } catch (e) {
+    throw toIapError(e);
+  }
+}
+
+/** Listens to a plugin event, handing its payload to `handler`. */
+async function subscribe<T>(event: IapEvent, handler: (payload: T) => void): Promise<UnlistenFn> {
+  if (fake) {
+    return fake.listen(event, handler);
+  }
+  return await listen<T>(event, (e) => {
+    handler(e.payload);
+  });
 }
 
 /**
@@ -73,19 +514,33 @@ export interface IAPError {
  */
 export interface PurchaseDetails {
   /** Unique identifier for the purchase (optional) */
-  purchaseID?: string;
+  purchaseId?: string;
   /** Identifier of the purchased product */
-  productID: string;
-  /** Verification data for server-side validation */
-  verificationData: PurchaseVerificationData;
-  /** ISO datetime string of the transaction (optional) */
+  productId: string;
+  /** Verification data for server-side validation, left out with `redactVerificationData` */
+  verificationData?: PurchaseVerificationData;
+  /** Whether the store reported verification data, set instead of `verificationData` with `redactVerificationData` */
+  verificationAvailable?: boolean;
+  /** ISO datetime string of the transaction, to the second (optional) */
   transactionDate?: string;
+  /** Milliseconds since the Unix epoch of the transaction, as the store reports it (optional) */
+  transactionDateMs?: number;
+  /** ISO datetime string when the entitlement ends, for subscriptions and time-limited add-ons (optional) */
+  expirationDate?: string;
+  /** Billing state of the subscription, if the purchase is one and the store reports it (optional) */
+  subscriptionState?: SubscriptionState;
+  /** ISO datetime string when the grace period of the subscription ends, if it is in one and the store reports it (optional) */
+  gracePeriodExpirationDate?: string;
   /** Current status of the purchase */
   status: PurchaseStatus;
   /** Error information if status is 'error' (optional) */
   error?: IAPError;
   /** Whether the purchase needs to be completed */
   pendingCompletePurchase: boolean;
+  /** Whether the store signature was checked on device, against `googlePlayPublicKey` on Android and by StoreKit 2 on iOS */
+  verified: boolean;
+  /** ID of the {@link purchase} call the update settles, only set on purchase update events */
+  correlationId?: string;
 }
 
 /**
@@ -107,7 +562,7 @@ export interface ProductDetailsResponse {
   /** Array of found product details */
   productDetails: ProductDetails[];
   /** Array of product IDs that were not found */
-  notFoundIDs: string[];
+  notFoundIds: string[];
   /** Error information if the query partially failed (optional) */
   error?: IAPError;
 }
@@ -116,15 +571,19 @@ export interface ProductDetailsResponse {
 
 /**
  * Initializes the IAP plugin
+ *
+ * Must be called before any other method except `isAvailable()`. The store
+ * connection is shared by all windows, so each may call this: subsequent
+ * calls resolve immediately once the plugin is initialized.
  * @returns Promise that resolves when initialization is complete
- * @throws {IAPError} If initialization fails
+ * @throws {IapError} If initialization fails
  * @example
  * ```ts
  * await initialize();
  * ```
  */
 export async function initialize(): Promise<void> {
-  await invoke('plugin:iap|initialize');
+  await call('initialize');
 }
 
 /**
@@ -139,30 +598,66 @@ export async function initialize(): Promise<void> {
  * ```
  */
 export async function isAvailable(): Promise<boolean> {
-  return await invoke('plugin:iap|is_available');
+  return await call('is_available');
+}
+
+/**
+ * Gets the state of the connection to the store
+ * @returns Promise that resolves to the current state
+ * @example
+ * ```ts
+ * if ((await connectionState()) === ConnectionState.uninitialized) {
+ *   await initialize();
+ * }
+ * ```
+ */
+export async function connectionState(): Promise<ConnectionState> {
+  return await call('connection_state');
+}
+
+/**
+ * Closes the connection to the store
+ *
+ * Gives up pending retries and stops reconnecting and reporting purchase
+ * updates, e.g. while the user signs out. Other methods reject with
+ * `notInitialized` until `initialize()` connects again.
+ * @returns Promise that resolves once the connection is closed
+ * @example
+ * ```ts
+ * await endConnection();
+ * // Later, e.g. after the next sign in
+ * await initialize();
+ * ```
+ */
+export async function endConnection(): Promise<void> {
+  await call('end_connection');
 }
 
 /**
  * Queries details for multiple products from the store
+ *
+ * Transient network failures are retried according to the plugin's retry
+ * policy unless `noRetry` is set.
  * @param productIds - Array of product identifiers to query
+ * @param noRetry - Fail immediately instead of retrying transient errors
  * @returns Promise that resolves to product details and any not found products
- * @throws {IAPError} If the query fails
+ * @throws {IapError} If the query fails
  * @example
  * ```ts
  * const response = await queryProductDetails(['product_1', 'product_2']);
  * console.log('Found products:', response.productDetails);
- * console.log('Not found products:', response.notFoundIDs);
+ * console.log('Not found products:', response.notFoundIds);
  * ```
  */
-export async function queryProductDetails(productIds: string[]): Promise<ProductDetailsResponse> {
-  return await invoke('plugin:iap|query_product_details', { productIds });
+export async function queryProductDetails(productIds: string[], noRetry?: boolean): Promise<ProductDetailsResponse> {
+  return await call('query_product_details', { productIds, noRetry });
 }
 
 /**
  * Initiates purchase of a non-consumable product
  * @param purchaseParam - Parameters for the purchase
  * @returns Promise that resolves to true if purchase was successful
- * @throws {IAPError} If the purchase fails
+ * @throws {IapError} If the purchase fails
  * @example
  * ```ts
  * const success = await buyNonConsumable({
@@ -172,7 +667,7 @@ export async function queryProductDetails(productIds: string[]): Promise<Product
  * ```
  */
 export async function buyNonConsumable(purchaseParam: PurchaseParam): Promise<boolean> {
-  return await invoke('plugin:iap|buy_non_consumable', { purchaseParam });
+  return await call('buy_non_consumable', { purchaseParam });
 }
 
 /**
@@ -180,7 +675,7 @@ export async function buyNonConsumable(purchaseParam: PurchaseParam): Promise<bo
  * @param purchaseParam - Parameters for the purchase
  * @param autoConsume - Whether to automatically consume the purchase after successful transaction
  * @returns Promise that resolves to true if purchase was successful
- * @throws {IAPError} If the purchase fails
+ * @throws {IapError} If the purchase fails
  * @example
  * ```ts
  * const success = await buyConsumable({
@@ -190,72 +685,1504 @@ export async function buyNonConsumable(purchaseParam: PurchaseParam): Promise<bo
  * ```
  */
 export async function buyConsumable(purchaseParam: PurchaseParam, autoConsume?: boolean): Promise<boolean> {
-  return await invoke('plugin:iap|buy_consumable', { purchaseParam, autoConsume });
+  return await call('buy_consumable', { purchaseParam, autoConsume });
+}
+
+/**
+ * Switches the user's active subscription to another one
+ *
+ * The subscription to replace is found among the current entitlements: the
+ * one of the same App Store subscription group, or else the only active one.
+ * The new purchase is also reported through `onPurchaseUpdate`.
+ * @param newProductId - The subscription to switch to
+ * @param replacementMode - How Google Play bills the change, ignored by the App Store
+ * @returns Promise that resolves to the new purchase, or null while it is pending
+ * @throws {NoActiveSubscriptionError} If the user has no subscription to replace
+ * @throws {AmbiguousSubscriptionError} If several subscriptions could be replaced, listed in `candidates`
+ * @example
+ * ```ts
+ * const purchase = await buyUpgrade('premium_yearly', ReplacementMode.chargeProratedPrice);
+ * ```
+ */
+export async function buyUpgrade(
+  newProductId: string,
+  replacementMode: ReplacementMode = ReplacementMode.withTimeProration
+): Promise<PurchaseDetails | null> {
+  return await call('buy_upgrade', { newProductId, replacementMode });
 }
 
 /**
  * Completes a purchase transaction
+ *
+ * Calls made while the same purchase is being completed share its result.
  * @param purchase - Details of the purchase to complete
  * @returns Promise that resolves when the purchase is completed
- * @throws {IAPError} If completion fails
+ * @throws {IapError} If completion fails
  * @example
  * ```ts
  * await completePurchase(purchaseDetails);
  * ```
  */
 export async function completePurchase(purchase: PurchaseDetails): Promise<void> {
-  await invoke('plugin:iap|complete_purchase', { purchase });
+  await call('complete_purchase', { purchase });
+}
+
+/**
+ * Completes a purchase once the app's server accepted it
+ *
+ * POSTs the purchase as JSON to `url`, including its `verificationData`, and
+ * completes it if the server answers with a 2xx status, retrying like
+ * `finalize_after` in Rust. Otherwise it is left unfinished and kept by the
+ * plugin, so it is reported again, even after a restart, until it is
+ * completed. The request is sent from the webview, so `init` can add the
+ * app's credentials, e.g. headers.
+ * @param purchase - Details of the purchase to verify and complete
+ * @param url - Verification endpoint of the app's server
+ * @param init - Extra options of the `fetch` request (optional)
+ * @returns Promise that resolves when the purchase is completed
+ * @throws {IapError} With code `invalidPurchaseToken` if the server rejected
+ * the purchase, `networkError` if it couldn't be reached or failed, or the
+ * error of {@link completePurchase}
+ * @example
+ * ```ts
+ * await finalizeAfterVerification(purchaseDetails, 'https://api.example.com/iap/verify', {
+ *   headers: { Authorization: `Bearer ${sessionToken}` },
+ * });
+ * ```
+ */
+export async function finalizeAfterVerification(
+  purchase: PurchaseDetails,
+  url: string,
+  init?: RequestInit,
+): Promise<void> {
+  const headers = new Headers(init?.headers);
+  if (!headers.has('Content-Type')) {
+    headers.set('Content-Type', 'application/json');
+  }
+  let verificationError: IAPError | undefined;
+  try {
+    const response = await fetch(url, { ...init, method: 'POST', headers, body: JSON.stringify(purchase) });
+    if (!response.ok) {
+      verificationError = {
+        code: response.status >= 500 ? IapErrorCode.NetworkError : IapErrorCode.InvalidPurchaseToken,
+        message: `Verification rejected the purchase (${response.status})`,
+        details: { status: response.status },
+      };
+    }
+  } catch (e) {
+    verificationError = { code: IapErrorCode.NetworkError, message: `Verification request failed: ${e}` };
+  }
+  // The plugin keeps the purchase unfinished when the verification failed.
+  await call('finalize_after', { purchase, verificationError });
 }
 
 /**
  * Restores previously purchased items
- * @param applicationUserName - Optional user identifier for the restoration
+ *
+ * Calls made while a restore is running share it, and calls shortly after a
+ * successful restore resolve without restoring again. On iOS 15+ this syncs
+ * with the App Store, which may ask the user to sign in, so only call it when
+ * the user asks to restore.
+ * @param applicationUserName - Optional user identifier for the restoration,
+ *   ignored by the iOS 15+ sync
  * @returns Promise that resolves when restoration is complete
- * @throws {IAPError} If restoration fails
+ * @throws {IapError} If restoration fails
  * @example
  * ```ts
  * await restorePurchases('user123');
  * ```
  */
 export async function restorePurchases(applicationUserName?: string): Promise<void> {
-  await invoke('plugin:iap|restore_purchases', { applicationUserName });
+  await call('restore_purchases', { applicationUserName });
+}
+
+/**
+ * Checks if the user owns a product, according to the purchases the store
+ * reported since the app started
+ *
+ * Purchase updates, restores and the `autoRestoreOnInit` query all count,
+ * so the answer is only complete after one of them reported the owned purchases.
+ * While the store is unreachable, products kept by the entitlement cache count too.
+ * @param productId - Identifier of the product
+ * @returns Promise that resolves to true if a purchase or restore of the product was reported
+ * @example
+ * ```ts
+ * if (await isOwned('premium')) {
+ *   unlockPremium();
+ * }
+ * ```
+ */
+export async function isOwned(productId: string): Promise<boolean> {
+  return await call('is_owned', { productId });
+}
+
+/**
+ * A product the user owns
+ * @interface OwnedProduct
+ */
+export interface OwnedProduct {
+  /** Identifier of the owned product */
+  productId: string;
+  /** Identifier of the purchase granting it (optional) */
+  purchaseId?: string;
+  /** ISO datetime string when the entitlement ends (optional) */
+  expirationDate?: string;
+  /** `store` if reported since the app started, `cache` if read from the entitlement cache while the store is unreachable */
+  source: 'store' | 'cache';
+}
+
+/**
+ * Lists the products the user owns, according to the purchases the store
+ * reported since the app started
+ *
+ * While the store is unreachable, the products kept by the entitlement cache
+ * are added with the `cache` source.
+ * @returns Promise that resolves to the owned products
+ * @example
+ * ```ts
+ * const owned = await ownedProducts();
+ * const offline = owned.some((product) => product.source === 'cache');
+ * ```
+ */
+export async function ownedProducts(): Promise<OwnedProduct[]> {
+  return await call('owned_products');
+}
+
+/**
+ * A product in an {@link EntitlementsExport}
+ * @interface ExportedEntitlement
+ */
+export interface ExportedEntitlement {
+  /** Identifier of the owned product */
+  productId: string;
+  /** Status of the purchase granting it */
+  status: PurchaseStatus;
+  /** Billing state, for subscriptions the store reported one of (optional) */
+  subscriptionState?: SubscriptionState;
+  /** ISO datetime string of the transaction (optional) */
+  transactionDate?: string | null;
+  /** ISO datetime string when the entitlement ends (optional) */
+  expirationDate?: string | null;
+  /** ISO datetime string when the grace period ends (optional) */
+  gracePeriodExpirationDate?: string;
+  /** `store` if reported since the app started, `cache` if read from the entitlement cache */
+  source: 'store' | 'cache';
+}
+
+/**
+ * The products the plugin considers owned, as exported by `exportEntitlements`:
+ * product IDs, states and dates, without tokens, receipts or order IDs
+ * @interface EntitlementsExport
+ */
+export interface EntitlementsExport {
+  /** Layout version of the export */
+  schemaVersion: number;
+  /** Version of the plugin that exported it */
+  pluginVersion: string;
+  /** ISO datetime string of the export */
+  exportedAt: string;
+  /** The owned products, sorted by product ID */
+  entitlements: ExportedEntitlement[];
+}
+
+/**
+ * Exports what the plugin considers owned as an {@link EntitlementsExport} in
+ * JSON, e.g. for users to attach to a support ticket
+ *
+ * Products served by the entitlement cache are included with the `cache` source.
+ * @returns Promise that resolves to the JSON of the export
+ * @example
+ * ```ts
+ * const report = await exportEntitlements();
+ * await navigator.clipboard.writeText(report);
+ * ```
+ */
+export async function exportEntitlements(): Promise<string> {
+  return await call('export_entitlements');
+}
+
+/**
+ * Replaces the owned products with an export, e.g. a state crafted by QA
+ *
+ * The entitlement cache is rewritten with the imported products, and the change
+ * is reported through `onPurchaseUpdate`: products no longer owned with the
+ * `canceled` status, then the imported ones.
+ * @param json - JSON of an {@link EntitlementsExport}
+ * @returns Promise that resolves once the products are replaced
+ * @throws {IapError} With code `featureNotSupported` in release builds without the
+ * mock store, or `developerError` if the export is invalid or of another schema version
+ * @example
+ * ```ts
+ * await importEntitlements(await file.text());
+ * ```
+ */
+export async function importEntitlements(json: string): Promise<void> {
+  return await call('import_entitlements', { json });
+}
+
+/**
+ * Reads the purchases granting an entitlement right now from the store
+ *
+ * Nothing is shown to the user and no purchase update is emitted, so this can
+ * run on every launch. iOS 15+ reads StoreKit's current entitlements, with
+ * transactions failing its verification reported with `verified` unset, and
+ * Android the owned products and active subscriptions. Revoked, expired and
+ * pending purchases are left out.
+ * @returns Promise that resolves to the purchases, with their verification data
+ * @example
+ * ```ts
+ * const entitlements = await currentEntitlements();
+ * const premium = entitlements.some((purchase) => purchase.productId === 'premium' && purchase.verified);
+ * ```
+ */
+export async function currentEntitlements(): Promise<PurchaseDetails[]> {
+  return await call('current_entitlements');
+}
+
+/**
+ * Sets the application user name used by purchases and restores that don't pass one
+ *
+ * Names passed to `buyNonConsumable`, `buyConsumable` or `restorePurchases` still win.
+ * @param applicationUserName - User identifier, or null to clear it, e.g. on logout
+ * @returns Promise that resolves when the name is stored
+ * @example
+ * ```ts
+ * await setApplicationUserName(hashedAccountId);
+ * // On logout
+ * await setApplicationUserName(null);
+ * ```
+ */
+export async function setApplicationUserName(applicationUserName: string | null): Promise<void> {
+  return await call('set_application_user_name', { applicationUserName });
 }
 
 /**
- * Gets the store country/region code
+ * Gets the store country/region code, cached until the store connection
+ * changes
+ * @param refresh - Fetch the code again instead of using the cached one
  * @returns Promise that resolves to the ISO country code
- * @throws {IAPError} If retrieval fails
+ * @throws {IapError} If retrieval fails
  * @example
  * ```ts
  * const country = await countryCode();
  * console.log('Store region:', country);
  * ```
  */
-export async function countryCode(): Promise<string> {
-  return await invoke('plugin:iap|country_code');
+export async function countryCode(refresh?: boolean): Promise<string> {
+  return await call('country_code', { refresh });
 }
 
 /**
- * Registers a handler for purchase updates
- * @param handler - Callback function that receives purchase updates
- * @returns Promise that resolves to an unlisten function
+ * Checks if the store supports a feature on this device, e.g. subscription
+ * upgrades, which older Play Store versions don't
+ *
+ * Google Play asks the billing client once initialized. iOS answers from the OS
+ * version, desktop stores from what they sell, and the mock store supports every
+ * feature unless `MockStore::set_feature_supported` turned it off.
+ * @param feature - The feature to check
+ * @returns Promise that resolves to true if the feature is supported
+ * @throws {IapError} With code `featureNotSupported` for a feature the plugin
+ * doesn't know, e.g. with bindings newer than the plugin
  * @example
  * ```ts
- * const unsubscribe = await onPurchaseUpdate((purchases) => {
- *   for (const purchase of purchases) {
- *     console.log('Purchase updated:', purchase);
- *   }
- * });
+ * if (await isFeatureSupported(StoreFeature.subscriptionsUpdate)) {
+ *   showUpgradeButton();
+ * }
+ * ```
+ */
+export async function isFeatureSupported(feature: StoreFeature): Promise<boolean> {
+  return await call('is_feature_supported', { feature });
+}
+
+/**
+ * Finds out where the app was installed from, e.g. to explain that purchases
+ * fail in an APK shared outside of Google Play
  *
- * // Later: unsubscribe to stop receiving updates
- * unsubscribe();
+ * Android asks the package manager for the installer. iOS tells App Store,
+ * TestFlight and development builds apart from the receipt and provisioning
+ * profile, Mac App Store builds from the app transaction. Other desktop builds
+ * report `devBuild` under `tauri dev` and `unknown` otherwise, and the mock
+ * store `devBuild` unless `MockStore::set_install_source` changed it.
+ * @returns Promise that resolves to the install source
+ * @example
+ * ```ts
+ * if (await installSource() === InstallSource.sideload) {
+ *   showMessage('Install the app from Google Play to buy premium');
+ * }
  * ```
  */
-export async function onPurchaseUpdate(
-  handler: (purchases: PurchaseDetails[]) => void
-): Promise<UnlistenFn> {
-  return await listen<PurchaseDetails[]>('tauri-plugin-iap://purchase-update', (event) => {
-    handler(event.payload);
-  });
+export async function installSource(): Promise<InstallSource> {
+  return await call('install_source');
+}
+
+/**
+ * Plugin configuration in effect, reported by `diagnostics`, with lists reduced
+ * to their length and the Google Play key to whether it is set
+ * @interface ConfigDiagnostics
+ */
+export interface ConfigDiagnostics {
+  /** Number of configured `products` */
+  products: number;
+  autoFinishTransactions: boolean;
+  eventTarget?: string;
+  /** Whether purchases are restricted to the `allowedWindows` */
+  restrictedWindows: boolean;
+  /** Operation timeouts in milliseconds, null when unlimited */
+  timeouts: {
+    queryMs: number | null;
+    purchaseMs: number | null;
+    finalizeMs: number | null;
+    restoreMs: number | null;
+    sheetMs: number | null;
+  };
+  pendingPurchases: {
+    enabled: boolean;
+    prepaidPlans: boolean;
+  };
+  /** The restore cooldown, with the default applied */
+  restoreCooldownMs: number;
+  autoRestoreOnInit: boolean;
+  preloadProducts: boolean;
+  skipPreloadOnStorefrontChange: boolean;
+  persistProducts: boolean;
+  /** Whether a `googlePlayPublicKey` is set */
+  googlePlayPublicKey: boolean;
+  rejectUnverifiedPurchases: boolean;
+  redactVerificationData: boolean;
+  alternativeBillingOnly: boolean;
+  appStoreId?: string;
+  requireStoreInstall: boolean;
+  transactionListenerOnLoad: boolean;
+  ownedOnHold: boolean;
+  deferPriceConsent: boolean;
+  skipProductIdValidation: boolean;
+  /** Whether an `androidAppLicense` is set */
+  androidAppLicense: boolean;
+  /** Number of `mockProducts` */
+  mockProducts: number;
+}
+
+/**
+ * Environment details to attach to bug reports, returned by `diagnostics`
+ * @interface Diagnostics
+ */
+export interface Diagnostics {
+  /** Version of the plugin crate */
+  pluginVersion: string;
+  /** Operating system, e.g. "android" */
+  platform: string;
+  /** Store serving the calls, e.g. "Google Play", unset on desktop without one */
+  store?: string;
+  /** Google Play Billing Library version the Android plugin was built with */
+  billingLibraryVersion?: string;
+  /** Whether purchases go through StoreKit 2 rather than StoreKit 1, on iOS and macOS */
+  storeKit2?: boolean;
+  /** State of the store connection */
+  connectionState: ConnectionState;
+  /** Whether the listener for transaction updates, e.g. renewals, is running */
+  transactionListener: boolean;
+  /** How the store connection is retried */
+  connectionRetry: {
+    maxAttempts: number | null;
+    baseDelayMs: number;
+    maxDelayMs: number;
+    jitter: boolean;
+  };
+  /** The plugin configuration in effect */
+  config: ConfigDiagnostics;
+}
+
+/**
+ * Collects environment details for bug reports: the plugin and store library
+ * versions, the connection state and retry policy, and the configuration in
+ * effect. Holds no purchases, keys or user identifiers.
+ * @returns Promise that resolves to the diagnostics
+ * @example
+ * ```ts
+ * report.attach('iap.json', JSON.stringify(await diagnostics(), null, 2));
+ * ```
+ */
+export async function diagnostics(): Promise<Diagnostics> {
+  return await call('diagnostics');
+}
+
+/**
+ * Purchase funnel counts since the app started or the last `resetMetrics`,
+ * returned by `metrics`
+ * @interface Metrics
+ */
+export interface Metrics {
+  /** Product queries, including the ones answered from the product cache */
+  productQueries: number;
+  /** Purchase flows started by `buyNonConsumable`, `buyConsumable` or `buyUpgrade` */
+  purchasesStarted: number;
+  /** Purchase flows the store accepted, on Google Play once the flow launched */
+  purchased: number;
+  /** Purchase flows the user cancelled */
+  cancelled: number;
+  /** Purchase flows that failed, other than cancelled ones */
+  errored: number;
+  /** `errored`, keyed by the codes that occurred */
+  errorsByCode: Partial<Record<IapErrorCode, number>>;
+  /** Calls to `restorePurchases` */
+  restores: number;
+  /** Average time purchase flows took until they ended, unset before any ended */
+  averageFlowDurationMs?: number;
 }
 
+/**
+ * Reads the purchase funnel counts kept by the plugin, e.g. to report them
+ * without an analytics SDK. Counts are kept in memory, so they start over
+ * with the app.
+ * @returns Promise that resolves to the counts since the app started or the
+ * last `resetMetrics`
+ * @example
+ * ```ts
+ * const { purchasesStarted, purchased } = await metrics();
+ * console.log(`conversion: ${purchased}/${purchasesStarted}`);
+ * ```
+ */
+export async function metrics(): Promise<Metrics> {
+  return await call('metrics');
+}
+
+/**
+ * Zeroes the counts returned by `metrics`, e.g. between test runs
+ * @returns Promise that resolves once the counts are zeroed
+ */
+export async function resetMetrics(): Promise<void> {
+  await call('reset_metrics');
+}
+
+/**
+ * Retrieves the base64 encoded App Store receipt, for backends validating the
+ * legacy receipt
+ *
+ * Only supported on iOS and in Mac App Store builds on macOS.
+ * @param refresh - Request the receipt from the App Store if it is missing,
+ * which may ask the user to sign in
+ * @returns Promise that resolves to the base64 encoded receipt
+ * @throws {IapError} With code `receiptUnavailable` if there is no receipt, or
+ * `featureNotSupported` on other platforms
+ * @example
+ * ```ts
+ * const receipt = await getAppReceipt(true);
+ * await fetch('/api/validate-receipt', { method: 'POST', body: receipt });
+ * ```
+ */
+export async function getAppReceipt(refresh?: boolean): Promise<string> {
+  return await call('get_app_receipt', { refresh });
+}
+
+/**
+ * Verification data of a purchase in one format across stores, for app
+ * servers. Fields are only added within a `schema` version.
+ * @interface VerificationPayload
+ */
+export interface VerificationPayload {
+  /** Version of the format, currently 1 */
+  schema: number;
+  /** Store that issued the purchase */
+  store: 'apple' | 'google' | 'amazon';
+  /** Kind of `data` */
+  format: 'purchase_token' | 'jws' | 'receipt' | 'amazon_receipt';
+  /** The purchase token, JWS, receipt or receipt ID */
+  data: string;
+  /** Identifier of the purchased product */
+  productId: string;
+  /** Package name of the app, for Google Play (optional) */
+  packageName?: string;
+  /** Order ID on Google Play, transaction ID on the App Store (optional) */
+  transactionId?: string;
+  /** Amazon user ID the receipt belongs to, for the Amazon Appstore (optional) */
+  userId?: string;
+}
+
+/**
+ * Exports the verification data of an owned purchase in the
+ * {@link VerificationPayload} format
+ *
+ * Works with `redactVerificationData`, the data is looked up in Rust.
+ * @param purchaseId - Transaction ID of a purchase reported since the app started
+ * @returns Promise that resolves to the verification payload
+ * @throws {IapError} With code `unknownPurchase` if no owned purchase has the
+ * ID, or `featureNotSupported` for purchases of the Microsoft Store or the
+ * mock store
+ * @example
+ * ```ts
+ * const payload = await exportVerificationPayload(purchase.purchaseId!);
+ * await fetch('/api/verify', { method: 'POST', body: JSON.stringify(payload) });
+ * ```
+ */
+export async function exportVerificationPayload(purchaseId: string): Promise<VerificationPayload> {
+  return await call('export_verification_payload', { purchaseId });
+}
+
+/**
+ * License of an add-on, as reported by the Microsoft Store
+ * @interface AddOnLicense
+ */
+export interface AddOnLicense {
+  /** Identifier of the licensed product */
+  productId: string;
+  /** Store ID of the licensed SKU, the product ID followed by the SKU */
+  skuStoreId: string;
+  /** Developer defined product ID of the add-on */
+  inAppOfferToken: string;
+  /** Whether the license is valid */
+  isActive: boolean;
+  /** ISO datetime string when the license ends (optional) */
+  expirationDate?: string;
+}
+
+/**
+ * License of the app: the Microsoft Store license, the App Store app
+ * transaction, or the license declared with `androidAppLicense`
+ * @interface AppLicense
+ */
+export interface AppLicense {
+  /** Whether the license is valid and grants access to the app */
+  isActive: boolean;
+  /** Whether the license is a trial */
+  isTrial: boolean;
+  /** ISO datetime string when the trial ends (optional) */
+  trialExpirationDate?: string;
+  /**
+   * App version the user first bought or downloaded, the `CFBundleVersion`
+   * on iOS and `CFBundleShortVersionString` on macOS. Only reported by the
+   * App Store (optional)
+   */
+  originalVersion?: string;
+  /** ISO datetime string when the user first bought or downloaded the app. Only reported by the App Store (optional) */
+  originalPurchaseDate?: string;
+  /** Store ID of the licensed SKU of the app, empty outside of the Microsoft Store */
+  skuStoreId: string;
+  /** Licenses of the durable and subscription add-ons the user owns. Only reported by the Microsoft Store */
+  addOnLicenses: AddOnLicense[];
+}
+
+/**
+ * Retrieves the license of the app and of the add-ons the user owns
+ *
+ * Supported by the Microsoft Store on Windows and by the App Store on iOS 16+
+ * and macOS 13+, from the app transaction. Android returns the
+ * `androidAppLicense` configuration.
+ * @returns Promise that resolves to the app license
+ * @throws {IapError} With code `featureNotSupported` on other platforms, on
+ * older iOS and macOS versions, and on Android without `androidAppLicense`
+ * @example
+ * ```ts
+ * const license = await appLicense();
+ * if (license.isTrial) {
+ *   showTrialBanner(license.trialExpirationDate);
+ * }
+ * ```
+ */
+export async function appLicense(): Promise<AppLicense> {
+  return await call('app_license');
+}
+
+/**
+ * State of a subscription the user bought
+ * @interface SubscriptionStatus
+ */
+export interface SubscriptionStatus {
+  /** Identifier of the subscription product */
+  productId: string;
+  /** Whether the subscription currently grants access */
+  isActive: boolean;
+  /** Billing state of the subscription */
+  state: SubscriptionState;
+  /** ISO datetime string when the grace period ends, in the `gracePeriod` state if the store reports it (optional) */
+  gracePeriodExpirationDate?: string;
+  /** ISO datetime string when the current period ends (optional) */
+  expirationDate?: string;
+  /** Whether the subscription renews at the end of the period, if the store reports it (optional) */
+  willAutoRenew?: boolean;
+}
+
+/**
+ * Retrieves the state of a subscription the user bought
+ *
+ * Supported on Windows, Android and iOS 15+. iOS tells the grace period and
+ * billing retry apart; Google Play only reports subscriptions granting access,
+ * as `active`, so those on account hold fail with `itemNotOwned`.
+ * @param productId - Identifier of the subscription product
+ * @returns Promise that resolves to the subscription state
+ * @throws {IapError} With code `itemNotOwned` if the user doesn't own the subscription
+ * @example
+ * ```ts
+ * const status = await subscriptionStatus('pro_monthly');
+ * if (status.isActive && status.willAutoRenew === false) {
+ *   showRenewalReminder(status.expirationDate);
+ * }
+ * if (status.state === SubscriptionState.gracePeriod) {
+ *   askToUpdatePaymentMethod(status.gracePeriodExpirationDate);
+ * }
+ * ```
+ */
+export async function subscriptionStatus(productId: string): Promise<SubscriptionStatus> {
+  return await call('subscription_status', { productId });
+}
+
+/**
+ * Retrieves the time left on a subscription the user bought, e.g. for a
+ * "3 days left in your trial" banner
+ *
+ * Counts until the end of the grace period while in one, otherwise until the
+ * end of the current period, and is `0` once access ended. The status is
+ * queried from the store on every call, so renewals are taken into account.
+ * @param productId - Identifier of the subscription product
+ * @returns Promise that resolves to the seconds left, or `null` for products
+ * that aren't subscriptions, lifetime purchases and subscriptions the store
+ * reports no end for
+ * @throws {IapError} Like {@link subscriptionStatus}
+ * @example
+ * ```ts
+ * const remaining = await subscriptionTimeRemaining('pro_monthly');
+ * if (remaining !== null && remaining < 3 * 24 * 60 * 60) {
+ *   showRenewalReminder(Math.ceil(remaining / (24 * 60 * 60)));
+ * }
+ * ```
+ */
+export async function subscriptionTimeRemaining(productId: string): Promise<number | null> {
+  return await call('subscription_time_remaining', { productId });
+}
+
+/**
+ * Opens the store page of an app, or of this app without a product ID,
+ * e.g. to promote a companion app or ask for a review
+ *
+ * iOS presents the App Store sheet in the app when it can and otherwise opens
+ * the App Store, Android opens the Play Store app and falls back to the browser.
+ * @param productId - The app's package name on Google Play, numeric App Store ID
+ * on iOS and macOS or Store ID on Windows. Defaults to this app, which needs the
+ * `appStoreId` configuration on iOS and macOS
+ * @returns Promise that resolves once the page is shown
+ * @throws {IapError} With code `featureNotSupported` if neither the store app
+ * nor a browser can show the page or on desktop without a store, and
+ * `invalidConfig` on iOS and macOS without a product ID or `appStoreId`
+ * @example
+ * ```ts
+ * await openStoreProductPage('com.example.companion');
+ * ```
+ */
+export async function openStoreProductPage(productId?: string): Promise<void> {
+  return await call('open_store_product_page', { productId });
+}
+
+/**
+ * Opens the page where the user cancels or changes their subscriptions: the
+ * App Store sheet on iOS, the Google Play subscriptions page on Android and
+ * the Microsoft account services page on Windows
+ *
+ * Requires the `iap:subscription-management` permission, which `iap:default` leaves out.
+ * @returns Promise that resolves once the page is opened, or on iOS once the sheet is dismissed
+ * @throws {IapError} With code `featureNotSupported` on other platforms and before iOS 15
+ */
+export async function openManageSubscriptions(): Promise<void> {
+  return await call('open_manage_subscriptions');
+}
+
+/**
+ * Shows the sheet asking the user to consent to a subscription price
+ * increase, if they still have to, at a moment the app chooses
+ *
+ * Only iOS asks for consent. With `deferPriceConsent` set, StoreKit doesn't
+ * show the sheet on its own, e.g. at launch, and waits for this call.
+ * @returns Promise that resolves to whether a deferred sheet was pending,
+ * always `false` without `deferPriceConsent` and on other platforms
+ * @example
+ * ```ts
+ * // Once the user finished onboarding
+ * await showPriceConsentIfNeeded();
+ * ```
+ */
+export async function showPriceConsentIfNeeded(): Promise<boolean> {
+  return await call('show_price_consent_if_needed');
+}
+
+/**
+ * Details to report a purchase made through alternative billing to Google Play
+ * @interface AlternativeBillingReportingDetails
+ */
+export interface AlternativeBillingReportingDetails {
+  /** Token identifying the external transaction in the report */
+  externalTransactionToken: string;
+}
+
+/**
+ * Checks if the user can buy through Google Play's Alternative Billing Only program
+ *
+ * Requires `alternativeBillingOnly` in the plugin configuration.
+ * @returns Promise that resolves to true if alternative billing is available
+ * @throws {IapError} With code `invalidConfig` if alternative billing isn't configured,
+ * or `featureNotSupported` on other platforms than Android
+ */
+export async function isAlternativeBillingAvailable(): Promise<boolean> {
+  return await call('is_alternative_billing_available');
+}
+
+/**
+ * Shows the information dialog Google Play requires before the first
+ * alternative billing purchase
+ * @returns Promise that resolves to true if the user acknowledged the dialog, now or before
+ * @throws {IapError} With code `invalidConfig` if alternative billing isn't configured,
+ * or `featureNotSupported` on other platforms than Android
+ */
+export async function showAlternativeBillingDialog(): Promise<boolean> {
+  return await call('show_alternative_billing_dialog');
+}
+
+/**
+ * Creates the token reporting a purchase made through alternative billing,
+ * which the app's server sends to Google Play within 24 hours
+ * @returns Promise that resolves to the reporting details
+ * @throws {IapError} With code `invalidConfig` if alternative billing isn't configured,
+ * or `featureNotSupported` on other platforms than Android
+ * @example
+ * ```ts
+ * if (await isAlternativeBillingAvailable() && await showAlternativeBillingDialog()) {
+ *   await payWithOwnCheckout(product);
+ *   const { externalTransactionToken } = await createAlternativeBillingToken();
+ *   await reportToServer(externalTransactionToken);
+ * }
+ * ```
+ */
+export async function createAlternativeBillingToken(): Promise<AlternativeBillingReportingDetails> {
+  return await call('create_alternative_billing_token');
+}
+
+/**
+ * Token to report an external purchase to Apple
+ * @interface ExternalPurchaseToken
+ */
+export interface ExternalPurchaseToken {
+  /** Token issued by the App Store, sent in the External Purchase Server API report */
+  token: string;
+}
+
+/**
+ * Checks if the app can send the user to an external purchase link
+ *
+ * Requires iOS 17.4 and an App Store external purchase entitlement.
+ * @returns Promise that resolves to true if the notice sheet can be presented
+ * @throws {IapError} With code `featureNotSupported` without the entitlement or on other platforms
+ */
+export async function externalPurchaseAvailable(): Promise<boolean> {
+  return await call('external_purchase_available');
+}
+
+/**
+ * Shows the App Store notice sheet and opens the link if the user continues
+ * @param url - Purchase page outside of the App Store
+ * @returns Promise that resolves to true if the user continued to the link
+ * @throws {IapError} With code `featureNotSupported` without the entitlement or on other platforms
+ * @example
+ * ```ts
+ * if (await externalPurchaseAvailable() && await openExternalPurchaseLink('https://example.com/buy')) {
+ *   const { token } = await externalPurchaseToken();
+ *   await reportToServer(token);
+ * }
+ * ```
+ */
+export async function openExternalPurchaseLink(url: string): Promise<boolean> {
+  return await call('open_external_purchase_link', { url });
+}
+
+/**
+ * Retrieves the token of the external purchase the user last continued to
+ * @returns Promise that resolves to the token
+ * @throws {IapError} With code `developerError` if the user never continued to a link,
+ * or `featureNotSupported` without the entitlement or on other platforms
+ */
+export async function externalPurchaseToken(): Promise<ExternalPurchaseToken> {
+  return await call('external_purchase_token');
+}
+
+/**
+ * Registers a handler for purchase updates
+ * @param handler - Callback function that receives purchase updates
+ * @returns Promise that resolves to an unlisten function
+ * @example
+ * ```ts
+ * const unsubscribe = await onPurchaseUpdate((purchases) => {
+ *   for (const purchase of purchases) {
+ *     console.log('Purchase updated:', purchase);
+ *   }
+ * });
+ *
+ * // Later: unsubscribe to stop receiving updates
+ * unsubscribe();
+ * ```
+ */
+export async function onPurchaseUpdate(
+  handler: (purchases: PurchaseDetails[]) => void
+): Promise<UnlistenFn> {
+  return await subscribe<PurchaseDetails[]>(IapEvent.PurchaseUpdate, handler);
+}
+
+/**
+ * Options of {@link purchase}
+ * @interface PurchaseOptions
+ */
+export interface PurchaseOptions {
+  /** Buys the product as a consumable, defaults to false */
+  consumable?: boolean;
+  /** Consumes the purchase once bought, for consumables (optional) */
+  autoConsume?: boolean;
+  /** Application-specific user identifier (optional) */
+  applicationUserName?: string;
+  /** Time in milliseconds to wait for the store to report the purchase, 5 minutes by default */
+  timeoutMs?: number;
+}
+
+/**
+ * Outcome of {@link purchase}
+ * @interface PurchaseResult
+ */
+export interface PurchaseResult {
+  /**
+   * `purchased` once the store reported the purchase, `pending` if it
+   * awaits approval, e.g. Ask to Buy, or `cancelled` if the user cancelled
+   */
+  status: 'purchased' | 'pending' | 'cancelled';
+  /** The reported purchase, unless cancelled or the store reported no pending purchase */
+  purchase?: PurchaseDetails;
+}
+
+/** Default of `PurchaseOptions.timeoutMs`. */
+const PURCHASE_TIMEOUT_MS = 5 * 60 * 1000;
+
+/** Number of {@link purchase} calls made by the page, part of their correlation IDs. */
+let purchaseCount = 0;
+
+/**
+ * Buys a product and waits for the store to report the purchase
+ *
+ * Starts the purchase flow with a correlation ID, which the plugin adds to
+ * the purchase update or the error event settling the flow: resolves once
+ * it is purchased or cancelled, and rejects with an {@link IapError} if it
+ * fails. Any other update, e.g. of a purchase restored or reported again
+ * meanwhile, is ignored. Resolves as `pending` right away if the store
+ * returned without a purchase, e.g. for Ask to Buy. The listeners are removed
+ * once settled, on timeout and when the page is unloaded. The purchase still
+ * has to be completed with {@link completePurchase}, unless
+ * `autoFinishTransactions` is set.
+ * @param product - Details of the product to buy
+ * @param options - How to buy it and how long to wait (optional)
+ * @returns Promise that resolves to the outcome of the purchase
+ * @throws {IapError} If the purchase fails, or `TimedOutError` if the store
+ * reported nothing before the timeout
+ * @example
+ * ```ts
+ * const result = await purchase(product);
+ * if (result.status === 'purchased') {
+ *   await completePurchase(result.purchase!);
+ * }
+ * ```
+ */
+export async function purchase(product: ProductDetails, options: PurchaseOptions = {}): Promise<PurchaseResult> {
+  const correlationId = `${Date.now().toString(36)}-${Math.random().toString(36).slice(2)}-${++purchaseCount}`;
+  let pending: PurchaseDetails | undefined;
+  let settle: (result: PurchaseResult | IapError) => void = () => {};
+  const settled = new Promise<PurchaseResult>((resolve, reject) => {
+    settle = (result) => (result instanceof IapError ? reject(result) : resolve(result));
+  });
+  // Handled below, once the flow started.
+  settled.catch(() => {});
+  const fail = (error: IapError) => settle(error instanceof UserCancelledError ? { status: 'cancelled' } : error);
+
+  // Listens before starting the flow, so an update reported right away isn't missed.
+  const unlistenUpdates = await onPurchaseUpdate((purchases) => {
+    for (const update of purchases) {
+      if (update.correlationId !== correlationId) {
+        continue;
+      }
+      const { correlationId: _, ...purchase } = update;
+      switch (purchase.status) {
+        case 'purchased':
+          settle({ status: 'purchased', purchase });
+          return;
+        case 'pending':
+          pending = purchase;
+          break;
+        case 'canceled':
+          settle({ status: 'cancelled' });
+          return;
+        case 'error':
+          fail(toIapError(purchase.error ?? { code: IapErrorCode.PurchaseError, message: `Purchase of ${product.id} failed` }));
+          return;
+      }
+    }
+  });
+  // Failed Google Play flows are only reported through the error event.
+  const unlistenErrors = await onError((error) => {
+    if (error.details?.correlationId === correlationId) {
+      fail(error);
+    }
+  });
+  const timeout = setTimeout(() => {
+    settle(
+      pending
+        ? { status: 'pending', purchase: pending }
+        : toIapError({
+            code: IapErrorCode.TimedOut,
+            message: `The store reported no purchase of ${product.id}`,
+            details: { operation: 'purchase' },
+          })
+    );
+  }, options.timeoutMs ?? PURCHASE_TIMEOUT_MS);
+  const unload = () => settle(toIapError({ code: IapErrorCode.Unknown, message: 'The page was unloaded during the purchase' }));
+  window.addEventListener('pagehide', unload);
+
+  try {
+    const purchaseParam = { productDetails: product, applicationUserName: options.applicationUserName };
+    const bought = await (options.consumable
+      ? call<boolean>('buy_consumable', { purchaseParam, autoConsume: options.autoConsume, correlationId })
+      : call<boolean>('buy_non_consumable', { purchaseParam, correlationId }));
+    if (!bought) {
+      settle({ status: 'pending', purchase: pending });
+    }
+  } catch (e) {
+    fail(toIapError(e));
+  }
+
+  try {
+    return await settled;
+  } finally {
+    clearTimeout(timeout);
+    window.removeEventListener('pagehide', unload);
+    unlistenUpdates();
+    unlistenErrors();
+  }
+}
+
+
+/**
+ * Failure injected into the mock store used on desktop
+ * @interface MockFailure
+ */
+export interface MockFailure {
+  /** Command that fails, e.g. "query_product_details" */
+  operation:
+    | 'initialize'
+    | 'is_available'
+    | 'query_product_details'
+    | 'buy_non_consumable'
+    | 'buy_consumable'
+    | 'buy_upgrade'
+    | 'complete_purchase'
+    | 'restore_purchases'
+    | 'country_code'
+    | 'subscription_status'
+    | 'is_feature_supported'
+    | 'open_store_product_page';
+  /** Error code the command rejects with, e.g. "networkError" */
+  code: string;
+  /** Only fail calls involving this product (optional) */
+  productId?: string;
+  /** Keep failing until cleared instead of failing the next call only (optional) */
+  persistent?: boolean;
+}
+
+/**
+ * Injects a failure into the mock store, or clears all injected failures
+ *
+ * Only available with the `mock` cargo feature, and the `iap:allow-mock-set-failure`
+ * permission, which is not part of the default set.
+ * @param failure - Failure to inject, or `null` to clear injected failures
+ * @returns Promise that resolves when the failure is set
+ * @throws {IapError} If the mock store is not in use
+ * @example
+ * ```ts
+ * await mockSetFailure({ operation: 'buy_non_consumable', code: 'itemAlreadyOwned' });
+ * ```
+ */
+export async function mockSetFailure(failure: MockFailure | null): Promise<void> {
+  return await call('mock_set_failure', { failure });
+}
+
+/**
+ * Payload of the restore completed event
+ * @interface RestoreCompleted
+ */
+export interface RestoreCompleted {
+  /** Number of purchases reported with the `restored` status */
+  restoredCount: number;
+}
+
+/**
+ * Registers a handler called once `restorePurchases()` reported all owned purchases
+ *
+ * Emitted after the restored purchases were delivered to `onPurchaseUpdate`.
+ * @param handler - Callback function that receives the restore summary
+ * @returns Promise that resolves to an unlisten function
+ * @example
+ * ```ts
+ * const unsubscribe = await onRestoreCompleted(({ restoredCount }) => {
+ *   console.log(`Restored ${restoredCount} purchases`);
+ * });
+ * ```
+ */
+export async function onRestoreCompleted(
+  handler: (completed: RestoreCompleted) => void
+): Promise<UnlistenFn> {
+  return await subscribe<RestoreCompleted>(IapEvent.RestoreCompleted, handler);
+}
+
+/**
+ * Registers a handler called whenever the store connection changes state
+ *
+ * Android reports `reconnecting` when the billing service disconnects, then `ready`
+ * once reconnected or `uninitialized` when giving up, after which `initialize()`
+ * has to be called again.
+ * @param handler - Callback function that receives the transition
+ * @returns Promise that resolves to an unlisten function
+ * @example
+ * ```ts
+ * const unsubscribe = await onConnectionStateChange(({ state }) => {
+ *   showOfflineBanner(state !== ConnectionState.ready);
+ * });
+ * ```
+ */
+export async function onConnectionStateChange(
+  handler: (change: ConnectionStateChange) => void
+): Promise<UnlistenFn> {
+  return await subscribe<ConnectionStateChange>(IapEvent.ConnectionState, handler);
+}
+
+/**
+ * Registers a handler called once the products listed in the configuration were
+ * preloaded with `preloadProducts`
+ *
+ * Later `queryProductDetails()` calls for these products are answered from the cache.
+ * @param handler - Callback function that receives the query response
+ * @returns Promise that resolves to an unlisten function
+ * @example
+ * ```ts
+ * const unsubscribe = await onProductsLoaded(({ productDetails }) => {
+ *   renderPaywall(productDetails);
+ * });
+ * ```
+ */
+export async function onProductsLoaded(
+  handler: (response: ProductDetailsResponse) => void
+): Promise<UnlistenFn> {
+  return await subscribe<ProductDetailsResponse>(IapEvent.ProductsLoaded, handler);
+}
+
+/**
+ * Registers a handler called when the user's storefront or language changed,
+ * e.g. to show the prices of the new country
+ *
+ * Product details queried before are stale by then. The cached products and
+ * country code are dropped first, and the products preloaded with
+ * `preloadProducts` are queried again unless `skipPreloadOnStorefrontChange`
+ * is set, followed by `onProductsLoaded`.
+ * @param handler - Callback function called on every change
+ * @returns Promise that resolves to an unlisten function
+ * @example
+ * ```ts
+ * const unsubscribe = await onStorefrontChanged(async () => {
+ *   renderPaywall((await queryProductDetails(productIds)).productDetails);
+ * });
+ * ```
+ */
+export async function onStorefrontChanged(handler: () => void): Promise<UnlistenFn> {
+  return await subscribe<null>(IapEvent.StorefrontChanged, () => handler());
+}
+
+/**
+ * Registers a handler called when a background operation fails, e.g. `preloadProducts`
+ * @param handler - Callback function that receives the error
+ * @returns Promise that resolves to an unlisten function
+ * @example
+ * ```ts
+ * const unsubscribe = await onError((error) => {
+ *   console.error(`IAP error (${error.code}): ${error.message}`);
+ * });
+ * ```
+ */
+export async function onError(handler: (error: IapError) => void): Promise<UnlistenFn> {
+  return await subscribe<IAPError>(IapEvent.Error, (payload) => {
+    handler(toIapError(payload));
+  });
+}
+
+/**
+ * Latency simulated by the mock store used on desktop
+ * @interface MockLatency
+ */
+export interface MockLatency {
+  /** Shortest delay in milliseconds */
+  minMs: number;
+  /** Longest delay in milliseconds */
+  maxMs: number;
+}
+
+/**
+ * Sets the latency the mock store simulates before answering queries, purchases and restores
+ *
+ * Only available with the `mock` cargo feature, and the `iap:allow-mock-set-latency`
+ * permission, which is not part of the default set.
+ * @param latency - Delay range, or `null` to answer immediately
+ * @returns Promise that resolves when the latency is set
+ * @throws {IapError} If the mock store is not in use
+ * @example
+ * ```ts
+ * await mockSetLatency({ minMs: 300, maxMs: 3000 });
+ * ```
+ */
+export async function mockSetLatency(latency: MockLatency | null): Promise<void> {
+  return await call('mock_set_latency', { latency });
+}
+
+/**
+ * Wipes the purchases of the mock store, in memory and on disk, so products can be bought again
+ *
+ * The wiped purchases are reported through `onPurchaseUpdate` with the `canceled` status, and
+ * generated order IDs start over. Only available with the `mock` cargo feature, and the
+ * `iap:allow-mock-clear-purchases` permission, which is not part of the default set.
+ * @returns Promise that resolves when the purchases are wiped
+ * @throws {IapError} If the mock store is not in use
+ * @example
+ * ```ts
+ * await mockClearPurchases();
+ * ```
+ */
+export async function mockClearPurchases(): Promise<void> {
+  return await call('mock_clear_purchases');
+}
+
+/**
+ * Reports a purchase update as if the store did, e.g. a renewal, a revocation or an approved
+ * pending purchase, without going through a purchase
+ *
+ * The purchase takes the same path as the store's updates: it updates the owned products and
+ * reaches `onPurchaseUpdate` and Rust listeners. Only available in debug builds with the `mock`
+ * cargo feature, and the `iap:allow-mock-emit-purchase` permission, which is not part of the
+ * default set.
+ * @param details - Purchase to report
+ * @returns Promise that resolves once the update was emitted
+ * @throws {IapError} If the mock store is not in use
+ * @example
+ * ```ts
+ * await mockEmitPurchase({ ...purchase, status: PurchaseStatus.canceled });
+ * ```
+ */
+export async function mockEmitPurchase(details: PurchaseDetails): Promise<void> {
+  return await call('mock_emit_purchase', { details });
+}
+
+/**
+ * What the user has of a product, according to an {@link EntitlementStore}
+ *
+ * - `owned`: bought or restored, and not expired
+ * - `pending`: bought, awaiting approval or payment
+ * - `expired`: owned until its expiration date, which passed
+ * - `revoked`: owned, then no longer reported by the store, e.g. refunded
+ * - `onHold`: a subscription whose renewal payment failed past the grace period,
+ *   until the user fixes their payment method
+ * - `notOwned`: never seen owned
+ */
+export type EntitlementState = 'owned' | 'pending' | 'expired' | 'revoked' | 'onHold' | 'notOwned';
+
+/**
+ * Entitlement of a product, from an {@link EntitlementStore}
+ * @interface Entitlement
+ */
+export interface Entitlement {
+  /** Identifier of the product */
+  productId: string;
+  /** What the user has of the product */
+  state: EntitlementState;
+  /** Identifier of the purchase the state comes from (optional) */
+  purchaseId?: string;
+  /** ISO datetime string when the entitlement ends (optional) */
+  expirationDate?: string;
+}
+
+/**
+ * Options of {@link createEntitlementStore}
+ * @interface EntitlementStoreOptions
+ */
+export interface EntitlementStoreOptions {
+  /** Whether to load the owned products when created, true by default */
+  refresh?: boolean;
+}
+
+/**
+ * Subscribable map of product identifiers to their {@link Entitlement}, kept up to
+ * date from the purchase events
+ *
+ * `subscribe` follows the Svelte store contract, and `getAll` returns the same array
+ * until something changes, so it can back React's `useSyncExternalStore`.
+ * @interface EntitlementStore
+ */
+export interface EntitlementStore {
+  /** Entitlement of a product, `notOwned` if unknown */
+  get(productId: string): Entitlement;
+  /** Entitlements of every product seen, sorted by product identifier */
+  getAll(): Entitlement[];
+  /** Calls `listener` with the entitlements now and whenever they change, returning a function to stop */
+  subscribe(listener: (entitlements: Entitlement[]) => void): () => void;
+  /** Reloads the owned products with `ownedProducts()`, revoking the ones no longer reported */
+  refresh(): Promise<void>;
+  /** Stops listening to the purchase events and expiring entitlements */
+  close(): void;
+}
+
+/** Longest delay `setTimeout` supports */
+const MAX_TIMEOUT_MS = 2 ** 31 - 1;
+
+interface Tracked extends Entitlement {
+  /** ISO datetime string of the purchase the state comes from (optional) */
+  transactionDate?: string;
+  /** Change counter when last updated, to spot updates made while refreshing */
+  version: number;
+}
+
+/**
+ * Creates an {@link EntitlementStore} listening to `onPurchaseUpdate` and
+ * `onRestoreCompleted`, for UI frameworks to wrap
+ *
+ * Purchase updates are applied as they arrive: duplicates are ignored, and so are
+ * updates older than the purchase the state comes from, e.g. a `pending` update
+ * delivered after the `purchased` one. Completed restores reload the owned products,
+ * and entitlements turn `expired` when their expiration date passes.
+ * @param options - Whether to load the owned products right away
+ * @returns Promise that resolves to the store once listening
+ * @example
+ * ```ts
+ * const entitlements = await createEntitlementStore();
+ * const stop = entitlements.subscribe(() => {
+ *   showPremium(entitlements.get('premium').state === 'owned');
+ * });
+ * ```
+ */
+export async function createEntitlementStore(
+  options: EntitlementStoreOptions = {}
+): Promise<EntitlementStore> {
+  const tracked = new Map<string, Tracked>();
+  const listeners = new Set<(entitlements: Entitlement[]) => void>();
+  let snapshot: Entitlement[] = [];
+  let version = 0;
+  let expiryTimer: ReturnType<typeof setTimeout> | undefined;
+  let closed = false;
+
+  const set = (productId: string, entitlement: Omit<Tracked, 'productId' | 'version'>): boolean => {
+    const current = tracked.get(productId);
+    if (
+      current &&
+      current.state === entitlement.state &&
+      current.purchaseId === entitlement.purchaseId &&
+      current.expirationDate === entitlement.expirationDate
+    ) {
+      return false;
+    }
+    tracked.set(productId, { ...entitlement, productId, version: ++version });
+    return true;
+  };
+
+  const publish = () => {
+    expire();
+    snapshot = [...tracked.values()]
+      .sort((a, b) => (a.productId < b.productId ? -1 : a.productId > b.productId ? 1 : 0))
+      .map(({ productId, state, purchaseId, expirationDate }) => ({
+        productId,
+        state,
+        purchaseId,
+        expirationDate
+      }));
+    for (const listener of [...listeners]) {
+      listener(snapshot);
+    }
+  };
+
+  /** Expires the owned entitlements whose date passed, and schedules the next expiry. */
+  const expire = () => {
+    clearTimeout(expiryTimer);
+    expiryTimer = undefined;
+    const now = Date.now();
+    let next = Infinity;
+    for (const entitlement of tracked.values()) {
+      const end = entitlement.expirationDate ? Date.parse(entitlement.expirationDate) : NaN;
+      if (entitlement.state !== 'owned' || Number.isNaN(end)) {
+        continue;
+      }
+      if (end <= now) {
+        entitlement.state = 'expired';
+        entitlement.version = ++version;
+      } else {
+        next = Math.min(next, end);
+      }
+    }
+    if (next !== Infinity && !closed) {
+      expiryTimer = setTimeout(publish, Math.min(next - now, MAX_TIMEOUT_MS));
+    }
+  };
+
+  const apply = (purchases: PurchaseDetails[]) => {
+    let changed = false;
+    for (const purchase of purchases) {
+      const current = tracked.get(purchase.productId);
+      if (isStale(current, purchase)) {
+        continue;
+      }
+      switch (purchase.status) {
+        case PurchaseStatus.purchased:
+        case PurchaseStatus.restored: {
+          // Subscriptions in the grace period are owned until it ends.
+          const expirationDate = purchase.gracePeriodExpirationDate ?? purchase.expirationDate;
+          changed =
+            set(purchase.productId, {
+              state: subscriptionEntitlementState(purchase.subscriptionState) ?? ownedState(expirationDate),
+              purchaseId: purchase.purchaseId,
+              expirationDate,
+              transactionDate: purchase.transactionDate
+            }) || changed;
+          break;
+        }
+        case PurchaseStatus.pending:
+          // A renewal or upgrade awaiting approval doesn't take away the current purchase.
+          if (current?.state !== 'owned') {
+            changed =
+              set(purchase.productId, {
+                state: 'pending',
+                purchaseId: purchase.purchaseId,
+                transactionDate: purchase.transactionDate
+              }) || changed;
+          }
+          break;
+        case PurchaseStatus.canceled:
+        case PurchaseStatus.error:
+          if (current?.state === 'pending' && current.purchaseId === purchase.purchaseId) {
+            tracked.delete(purchase.productId);
+            version++;
+            changed = true;
+          }
+          break;
+      }
+    }
+    if (changed) {
+      publish();
+    }
+  };
+
+  const reload = (owned: OwnedProduct[], since: number) => {
+    let changed = false;
+    for (const product of owned) {
+      const current = tracked.get(product.productId);
+      // Updates received while loading are newer than the loaded products.
+      if (current && current.version > since) {
+        continue;
+      }
+      changed =
+        set(product.productId, {
+          state: ownedState(product.expirationDate),
+          purchaseId: product.purchaseId,
+          expirationDate: product.expirationDate,
+          transactionDate: current?.purchaseId === product.purchaseId ? current.transactionDate : undefined
+        }) || changed;
+    }
+    for (const [productId, current] of tracked) {
+      if (
+        current.state === 'owned' &&
+        current.version <= since &&
+        !owned.some((product) => product.productId === productId)
+      ) {
+        changed = set(productId, { ...current, state: 'revoked' }) || changed;
+      }
+    }
+    if (changed) {
+      publish();
+    }
+  };
+
+  const refresh = async () => {
+    const since = version;
+    const owned = await ownedProducts();
+    if (!closed) {
+      reload(owned, since);
+    }
+  };
+
+  const unlisten: UnlistenFn[] = [];
+  try {
+    unlisten.push(await onPurchaseUpdate(apply));
+    unlisten.push(
+      await onRestoreCompleted(() => {
+        refresh().catch((e) => console.warn('Failed to reload the owned products after a restore', e));
+      })
+    );
+    if (options.refresh ?? true) {
+      await refresh();
+    }
+  } catch (e) {
+    unlisten.forEach((stop) => stop());
+    throw e;
+  }
+
+  return {
+    get(productId: string): Entitlement {
+      return snapshot.find((entitlement) => entitlement.productId === productId) ?? {
+        productId,
+        state: 'notOwned'
+      };
+    },
+    getAll(): Entitlement[] {
+      return snapshot;
+    },
+    subscribe(listener: (entitlements: Entitlement[]) => void): () => void {
+      listeners.add(listener);
+      listener(snapshot);
+      return () => {
+        listeners.delete(listener);
+      };
+    },
+    refresh,
+    close(): void {
+      closed = true;
+      clearTimeout(expiryTimer);
+      listeners.clear();
+      unlisten.forEach((stop) => stop());
+    }
+  };
+}
+
+/**
+ * Whether `purchase` is older than the purchase the entitlement comes from: a
+ * `pending` update for an owned purchase, or an earlier transaction.
+ */
+function isStale(current: Tracked | undefined, purchase: PurchaseDetails): boolean {
+  if (!current) {
+    return false;
+  }
+  if (current.purchaseId !== undefined && current.purchaseId === purchase.purchaseId) {
+    return purchase.status === PurchaseStatus.pending && current.state !== 'pending';
+  }
+  const currentDate = current.transactionDate ? Date.parse(current.transactionDate) : NaN;
+  const date = purchase.transactionDate ? Date.parse(purchase.transactionDate) : NaN;
+  return !Number.isNaN(currentDate) && !Number.isNaN(date) && date < currentDate;
+}
+
+/** `owned`, or `expired` once `expirationDate` passed. */
+function ownedState(expirationDate: string | undefined): EntitlementState {
+  const end = expirationDate ? Date.parse(expirationDate) : NaN;
+  return end <= Date.now() ? 'expired' : 'owned';
+}
+
+/** The state of a subscription that lapsed, or undefined to go by its dates. */
+function subscriptionEntitlementState(state: SubscriptionState | undefined): EntitlementState | undefined {
+  switch (state) {
+    case SubscriptionState.onHold:
+      return 'onHold';
+    case SubscriptionState.expired:
+      return 'expired';
+    case SubscriptionState.revoked:
+      return 'revoked';
+    default:
+      return undefined;
+  }
+}
@@ -0,0 +1,492 @@
+import type { UnlistenFn } from '@tauri-apps/api/event'
+import type {
+  EntitlementsExport,
+  ExportedEntitlement,
+  IAPError,
+  InstallSource,
+  MockFailure,
+  OwnedProduct,
+  ProductDetails,
+  ProductDetailsResponse,
+  PurchaseDetails,
+  PurchaseParam,
+  RestoreCompleted,
+  StoreFeature,
+  SubscriptionStatus
+} from './index'
+
+/** `EntitlementsExport::SCHEMA_VERSION` of the plugin */
+const ENTITLEMENTS_SCHEMA_VERSION = 1;
+
+// Listed here since importing values from index would make the modules
+// import each other.
+const STORE_FEATURES: string[] = [
+  'subscriptions',
+  'subscriptionsUpdate',
+  'priceChangeConfirmation',
+  'inAppMessaging',
+  'externalOffer'
+] satisfies `${StoreFeature}`[];
+
+/**
+ * Initial state of the fake store installed by `setIapMock`
+ * @interface IapMockOptions
+ */
+export interface IapMockOptions {
+  /** Products sold by the fake store */
+  products?: ProductDetails[];
+  /** Purchases the user already made, e.g. owned non-consumables */
+  purchases?: PurchaseDetails[];
+  /** Failures injected into the commands, like `mockSetFailure` */
+  failures?: MockFailure[];
+  /** Country code reported by `countryCode`, "US" by default */
+  countryCode?: string;
+  /** Source reported by `installSource`, "devBuild" by default */
+  installSource?: `${InstallSource}`;
+}
+
+/**
+ * A command received by the fake store
+ * @interface IapMockCall
+ */
+export interface IapMockCall {
+  /** Name of the command, e.g. "buy_consumable" */
+  command: string;
+  /** Arguments of the command */
+  args: Record<string, any>;
+}
+
+interface FakePurchase {
+  details: PurchaseDetails;
+  consumable: boolean;
+  applicationUserName?: string;
+}
+
+/**
+ * In-memory store serving the binding functions while installed with
+ * `setIapMock`, following the contract of the Rust mock store: purchases
+ * succeed right away with `mock-token-*` tokens and are reported through
+ * `onPurchaseUpdate`, owned products can't be bought again, completing
+ * consumes consumables, and restores report owned non-consumables.
+ */
+export class IapFake {
+  /** Commands received, in order */
+  readonly calls: IapMockCall[] = [];
+  private products: ProductDetails[];
+  private purchases: FakePurchase[];
+  private failures: MockFailure[];
+  private countryCode: string;
+  private installSource: `${InstallSource}`;
+  /** `ConnectionState` reported by the fake */
+  private state: 'uninitialized' | 'ready' | 'closed' = 'uninitialized';
+  private applicationUserName?: string;
+  private lastId = 0;
+  private listeners = new Map<string, Set<(payload: any) => void>>();
+
+  constructor(options: IapMockOptions = {}) {
+    this.products = [...(options.products ?? [])];
+    this.purchases = (options.purchases ?? []).map((details) => ({ details, consumable: false }));
+    this.failures = [...(options.failures ?? [])];
+    this.countryCode = options.countryCode ?? 'US';
+    this.installSource = options.installSource ?? 'devBuild';
+  }
+
+  /** Purchases the fake store holds: owned, pending or awaiting completion */
+  get ownedPurchases(): PurchaseDetails[] {
+    return this.purchases.map((purchase) => purchase.details);
+  }
+
+  /** Makes a command fail, like `mockSetFailure` */
+  injectFailure(failure: MockFailure): void {
+    this.failures.push(failure);
+  }
+
+  /** Reports purchase updates as if the store did, e.g. an approved pending purchase */
+  pushPurchaseUpdates(purchases: PurchaseDetails[]): void {
+    this.emit('tauri-plugin-iap://purchase-update', purchases);
+  }
+
+  /** Serves a command, rejecting with an `IAPError` payload like the plugin. */
+  async invoke<T>(command: string, args: Record<string, any> = {}): Promise<T> {
+    this.calls.push({ command, args });
+    return this.handle(command, args) as T;
+  }
+
+  /** Registers an event handler, called with the payload of the events the fake emits. */
+  listen<T>(event: string, handler: (payload: T) => void): UnlistenFn {
+    const handlers = this.listeners.get(event) ?? new Set();
+    handlers.add(handler);
+    this.listeners.set(event, handlers);
+    return () => {
+      handlers.delete(handler);
+    };
+  }
+
+  private emit(event: string, payload: unknown): void {
+    for (const handler of [...(this.listeners.get(event) ?? [])]) {
+      handler(payload);
+    }
+  }
+
+  private handle(command: string, args: Record<string, any>): unknown {
+    switch (command) {
+      case 'initialize': {
+        this.fail('initialize', []);
+        if (this.state !== 'ready') {
+          const previous = this.state;
+          this.state = 'ready';
+          this.emit('tauri-plugin-iap://connection-state', { previous, state: this.state });
+        }
+        return null;
+      }
+      case 'is_available':
+        this.fail('is_available', []);
+        return true;
+      case 'connection_state':
+        return this.state;
+      case 'end_connection': {
+        if (this.state !== 'closed') {
+          const previous = this.state;
+          this.state = 'closed';
+          this.emit('tauri-plugin-iap://connection-state', { previous, state: this.state });
+        }
+        return null;
+      }
+      case 'query_product_details': {
+        const productIds: string[] = args.productIds ?? [];
+        this.fail('query_product_details', productIds);
+        const response: ProductDetailsResponse = { productDetails: [], notFoundIds: [] };
+        for (const id of productIds) {
+          const product = this.products.find((product) => product.id === id);
+          if (product) {
+            response.productDetails.push(product);
+          } else {
+            response.notFoundIds.push(id);
+          }
+        }
+        return response;
+      }
+      case 'buy_non_consumable':
+        this.fail('buy_non_consumable', [args.purchaseParam.productDetails.id]);
+        this.reportBought(this.buy(args.purchaseParam, false, false), args.correlationId);
+        return true;
+      case 'buy_consumable':
+        this.fail('buy_consumable', [args.purchaseParam.productDetails.id]);
+        this.reportBought(this.buy(args.purchaseParam, true, args.autoConsume ?? false), args.correlationId);
+        return true;
+      case 'buy_upgrade':
+        this.fail('buy_upgrade', [args.newProductId]);
+        return this.upgrade(args.newProductId);
+      case 'complete_purchase':
+        this.fail('complete_purchase', [args.purchase.productId]);
+        this.complete(args.purchase);
+        return null;
+      case 'finalize_after':
+        if (args.verificationError) {
+          throw args.verificationError;
+        }
+        this.fail('complete_purchase', [args.purchase.productId]);
+        this.complete(args.purchase);
+        return null;
+      case 'restore_purchases': {
+        this.fail('restore_purchases', []);
+        const name: string | undefined = args.applicationUserName ?? this.applicationUserName;
+        const restored = this.purchases
+          .filter((purchase) => !purchase.consumable)
+          .filter((purchase) => name === undefined || purchase.applicationUserName === name)
+          .map((purchase): PurchaseDetails => ({
+            ...purchase.details,
+            status: purchase.details.status === 'pending' ? 'pending' : 'restored'
+          }));
+        this.pushPurchaseUpdates(restored);
+        const completed: RestoreCompleted = {
+          restoredCount: restored.filter((purchase) => purchase.status === 'restored').length
+        };
+        this.emit('tauri-plugin-iap://restore-completed', completed);
+        return null;
+      }
+      case 'is_owned':
+        return this.owned().some((purchase) => purchase.productId === args.productId);
+      case 'current_entitlements':
+        // The mock store reads the owned purchases with a restore.
+        this.fail('restore_purchases', []);
+        return this.purchases
+          .filter((purchase) => !purchase.consumable && purchase.details.status !== 'pending')
+          .map((purchase): PurchaseDetails => ({ ...purchase.details, status: 'restored' }));
+      case 'owned_products':
+        return this.owned().map(
+          (purchase): OwnedProduct => ({
+            productId: purchase.productId,
+            purchaseId: purchase.purchaseId,
+            expirationDate: purchase.expirationDate,
+            source: 'store'
+          })
+        );
+      case 'export_entitlements': {
+        const exported: EntitlementsExport = {
+          schemaVersion: ENTITLEMENTS_SCHEMA_VERSION,
+          pluginVersion: 'mock',
+          exportedAt: new Date().toISOString().replace(/\.\d+Z$/, 'Z'),
+          entitlements: this.owned()
+            .map(
+              (purchase): ExportedEntitlement => ({
+                productId: purchase.productId,
+                status: purchase.status,
+                subscriptionState: purchase.subscriptionState,
+                transactionDate: purchase.transactionDate ?? null,
+                expirationDate: purchase.expirationDate ?? null,
+                gracePeriodExpirationDate: purchase.gracePeriodExpirationDate,
+                source: 'store'
+              })
+            )
+            .sort((a, b) => a.productId.localeCompare(b.productId))
+        };
+        return JSON.stringify(exported, null, 2);
+      }
+      case 'import_entitlements': {
+        let imported: EntitlementsExport;
+        try {
+          imported = JSON.parse(args.json);
+        } catch (e) {
+          throw error('developerError', `Invalid entitlements export: ${e}`);
+        }
+        if (imported?.schemaVersion !== ENTITLEMENTS_SCHEMA_VERSION) {
+          throw error(
+            'developerError',
+            `Entitlements export of schema version ${imported?.schemaVersion}, this plugin reads version ${ENTITLEMENTS_SCHEMA_VERSION}`
+          );
+        }
+        const purchases = imported.entitlements.map(
+          (entitlement): PurchaseDetails => ({
+            productId: entitlement.productId,
+            verificationData: { localVerificationData: '', serverVerificationData: '', source: 'mock' },
+            transactionDate: entitlement.transactionDate ?? undefined,
+            expirationDate: entitlement.expirationDate ?? undefined,
+            subscriptionState: entitlement.subscriptionState,
+            gracePeriodExpirationDate: entitlement.gracePeriodExpirationDate,
+            status: entitlement.status,
+            pendingCompletePurchase: false,
+            verified: false
+          })
+        );
+        const dropped = this.purchases
+          .filter((purchase) => !purchases.some((known) => known.productId === purchase.details.productId))
+          .map(
+            (purchase): PurchaseDetails => ({ ...purchase.details, status: 'canceled', pendingCompletePurchase: false })
+          );
+        this.purchases = purchases.map((details) => ({ details, consumable: false }));
+        this.emit('tauri-plugin-iap://purchase-update', [...dropped, ...purchases]);
+        return null;
+      }
+      case 'set_application_user_name':
+        this.applicationUserName = args.applicationUserName ?? undefined;
+        return null;
+      case 'country_code':
+        this.fail('country_code', []);
+        return this.countryCode;
+      case 'subscription_status': {
+        this.fail('subscription_status', [args.productId]);
+        const isSubscription = this.products.some(
+          (product) => product.id === args.productId && product.subscriptionPeriod !== undefined
+        );
+        if (!isSubscription || !this.owns(args.productId)) {
+          throw error('itemNotOwned', `Item not owned: ${args.productId}`);
+        }
+        const status: SubscriptionStatus = {
+          productId: args.productId,
+          isActive: true,
+          state: 'active',
+          willAutoRenew: true
+        };
+        return status;
+      }
+      case 'subscription_time_remaining': {
+        this.fail('subscription_status', [args.productId]);
+        const isSubscription = this.products.some(
+          (product) => product.id === args.productId && product.subscriptionPeriod !== undefined
+        );
+        if (!isSubscription) {
+          return null;
+        }
+        if (!this.owns(args.productId)) {
+          throw error('itemNotOwned', `Item not owned: ${args.productId}`);
+        }
+        // Mock subscriptions renew forever, so they report no end.
+        return null;
+      }
+      case 'is_feature_supported':
+        this.fail('is_feature_supported', []);
+        if (!STORE_FEATURES.includes(args.feature)) {
+          throw error('featureNotSupported', `Feature not supported: Unknown store feature: ${args.feature}`);
+        }
+        return true;
+      case 'install_source':
+        return this.installSource;
+      case 'show_price_consent_if_needed':
+        return false;
+      case 'open_store_product_page':
+        this.fail('open_store_product_page', args.productId ? [args.productId] : []);
+        return null;
+      case 'mock_set_failure':
+        if (args.failure) {
+          this.failures.push(args.failure);
+        } else {
+          this.failures = [];
+        }
+        return null;
+      case 'mock_set_latency':
+        return null;
+      case 'mock_emit_purchase':
+        this.emit('tauri-plugin-iap://purchase-update', [args.details]);
+        return null;
+      case 'mock_clear_purchases': {
+        const revoked = this.purchases.map(
+          (purchase): PurchaseDetails => ({ ...purchase.details, status: 'canceled', pendingCompletePurchase: false })
+        );
+        this.purchases = [];
+        this.lastId = 0;
+        if (revoked.length > 0) {
+          this.emit('tauri-plugin-iap://purchase-update', revoked);
+        }
+        return null;
+      }
+      default:
+        throw error('featureNotSupported', `${command} is not supported by the mock`);
+    }
+  }
+
+  /** Throws the first injected failure matching the command, consuming it unless persistent. */
+  private fail(operation: MockFailure['operation'], productIds: string[]): void {
+    const index = this.failures.findIndex(
+      (failure) =>
+        failure.operation === operation &&
+        (failure.productId === undefined || productIds.includes(failure.productId))
+    );
+    if (index === -1) {
+      return;
+    }
+    const failure = this.failures[index];
+    if (!failure.persistent) {
+      this.failures.splice(index, 1);
+    }
+    throw error(failure.code, `Injected ${failure.code} failure of ${operation}`);
+  }
+
+  /** Reports a bought purchase, with the correlation ID of `purchase()` like the plugin. */
+  private reportBought(purchase: PurchaseDetails, correlationId?: string): void {
+    this.pushPurchaseUpdates([correlationId === undefined ? purchase : { ...purchase, correlationId }]);
+  }
+
+  private buy(purchaseParam: PurchaseParam, consumable: boolean, autoConsume: boolean): PurchaseDetails {
+    const productId = purchaseParam.productDetails.id;
+    if (!this.products.some((product) => product.id === productId)) {
+      throw error('purchaseError', `Item unavailable: ${productId}`);
+    }
+    if (this.owns(productId)) {
+      throw error('itemAlreadyOwned', 'Item already owned');
+    }
+    const id = ++this.lastId;
+    const token = `mock-token-${id}`;
+    const autoConsumed = consumable && autoConsume;
+    const now = Date.now();
+    const details: PurchaseDetails = {
+      purchaseId: `mock-order-${id}`,
+      productId,
+      verificationData: { localVerificationData: token, serverVerificationData: token, source: 'mock' },
+      transactionDate: new Date(now).toISOString().replace(/\.\d+Z$/, 'Z'),
+      transactionDateMs: now,
+      status: 'purchased',
+      pendingCompletePurchase: !autoConsumed,
+      verified: false
+    };
+    // Consumed purchases aren't owned, so the product can be bought again.
+    if (!autoConsumed) {
+      this.purchases.push({
+        details,
+        consumable,
+        applicationUserName: purchaseParam.applicationUserName ?? this.applicationUserName
+      });
+    }
+    return details;
+  }
+
+  /**
+   * Replaces the active subscription of the same group, or the only one, by
+   * `productId`, like `buyUpgrade`.
+   */
+  private upgrade(productId: string): PurchaseDetails {
+    const product = this.products.find((known) => known.id === productId);
+    if (!product) {
+      throw error('purchaseError', `Item unavailable: ${productId}`);
+    }
+    if (!isSubscription(product)) {
+      throw error('purchaseError', `${productId} is not a subscription`);
+    }
+    const subscriptions = this.owned().filter((purchase) =>
+      this.products.some((known) => known.id === purchase.productId && isSubscription(known))
+    );
+    if (subscriptions.some((purchase) => purchase.productId === productId)) {
+      throw error('itemAlreadyOwned', 'Item already owned');
+    }
+    const candidates = subscriptions.filter(
+      (purchase) =>
+        product.subscriptionGroupId === undefined ||
+        this.products.some(
+          (known) => known.id === purchase.productId && known.subscriptionGroupId === product.subscriptionGroupId
+        )
+    );
+    if (candidates.length === 0) {
+      throw error('noActiveSubscription', `No active subscription to replace with ${productId}`);
+    }
+    if (candidates.length > 1) {
+      const ids = candidates.map((purchase) => purchase.productId);
+      throw error('ambiguousSubscription', `Several active subscriptions could be replaced: ${ids.join(', ')}`, {
+        candidates: ids
+      });
+    }
+    const purchase = this.buy({ productDetails: product }, false, false);
+    this.purchases = this.purchases.filter((known) => known.details.productId !== candidates[0].productId);
+    this.pushPurchaseUpdates([purchase]);
+    return purchase;
+  }
+
+  private complete(purchase: PurchaseDetails): void {
+    const index = this.purchases.findIndex(
+      (known) =>
+        known.details.purchaseId === purchase.purchaseId &&
+        known.details.verificationData?.serverVerificationData ===
+          purchase.verificationData?.serverVerificationData
+    );
+    if (index === -1) {
+      throw error('unknownPurchase', `Unknown purchase: ${purchase.purchaseId ?? purchase.productId}`);
+    }
+    const known = this.purchases[index];
+    if (known.details.status === 'pending') {
+      throw error('purchaseError', `Purchase of ${purchase.productId} is still pending`);
+    }
+    if (known.consumable) {
+      this.purchases.splice(index, 1);
+    } else {
+      known.details = { ...known.details, pendingCompletePurchase: false };
+    }
+  }
+
+  private owns(productId: string): boolean {
+    return this.purchases.some((purchase) => purchase.details.productId === productId);
+  }
+
+  private owned(): PurchaseDetails[] {
+    return this.purchases
+      .map((purchase) => purchase.details)
+      .filter((purchase) => purchase.status === 'purchased' || purchase.status === 'restored');
+  }
+}
+
+function error(code: string, message: string, details?: Record<string, unknown>): IAPError {
+  return details === undefined ? { code, message } : { code, message, details };
+}
+
+/** Whether the product is a subscription, like `ProductDetails::is_subscription` */
+function isSubscription(product: ProductDetails): boolean {
+  return product.subscriptionPeriod !== undefined || product.subscriptionGroupId !== undefined;
+}
@@ -0,0 +1,78 @@
+import assert from 'node:assert/strict'
+import { readFileSync } from 'node:fs'
+import { afterEach, test } from 'node:test'
+
+import * as iap from '../../dist-js/index.js'
+import { clearMocks, mockPlugin } from './ipc.js'
+
+// Also invoked on the plugin by `accepts_the_guest_js_payloads` in
+// tests/commands.rs, so the argument names can't drift from the Rust ones.
+const payloads = JSON.parse(readFileSync(new URL('payloads.json', import.meta.url), 'utf8'))
+
+afterEach(clearMocks)
+
+test('bindings invoke the commands with the arguments they deserialize', async () => {
+  for (const { binding, params, command, args, result } of payloads) {
+    const calls = mockPlugin(() => result)
+
+    assert.deepEqual(await iap[binding](...params), result ?? undefined, binding)
+    assert.deepEqual(calls, [{ command, args }], binding)
+  }
+})
+
+test('optional arguments are left out', async () => {
+  const calls = mockPlugin(() => null)
+
+  await iap.queryProductDetails(['premium'])
+  await iap.restorePurchases()
+  await iap.countryCode()
+
+  assert.deepEqual(calls, [
+    { command: 'query_product_details', args: { productIds: ['premium'] } },
+    { command: 'restore_purchases', args: {} },
+    { command: 'country_code', args: {} }
+  ])
+})
+
+test('finalizeAfterVerification hands the verification outcome to the plugin', async (t) => {
+  const purchase = payloads.find(({ binding }) => binding === 'completePurchase').params[0]
+  const statuses = [200, 403, 503]
+  const requests = []
+  t.mock.method(globalThis, 'fetch', async (url, init) => {
+    requests.push({ url, init })
+    if (statuses.length === 0) {
+      throw new TypeError('fetch failed')
+    }
+    return new Response(null, { status: statuses.shift() })
+  })
+  const calls = mockPlugin((command, args) => {
+    if (args.verificationError) {
+      throw args.verificationError
+    }
+    return null
+  })
+  const finalize = () =>
+    iap.finalizeAfterVerification(purchase, 'https://api.example.com/iap/verify', { headers: { Authorization: 'Bearer t' } })
+
+  await finalize()
+  await assert.rejects(finalize(), iap.InvalidPurchaseTokenError)
+  await assert.rejects(finalize(), iap.NetworkError)
+  await assert.rejects(finalize(), iap.NetworkError)
+
+  assert.equal(requests[0].url, 'https://api.example.com/iap/verify')
+  assert.equal(requests[0].init.method, 'POST')
+  assert.equal(requests[0].init.headers.get('Authorization'), 'Bearer t')
+  assert.equal(requests[0].init.headers.get('Content-Type'), 'application/json')
+  assert.deepEqual(JSON.parse(requests[0].init.body), purchase)
+  // Failed verifications reach the plugin too, which keeps the purchase unfinished.
+  assert.deepEqual(
+    calls.map(({ command, args }) => [command, args.purchase, args.verificationError?.code]),
+    [
+      ['finalize_after', purchase, undefined],
+      ['finalize_after', purchase, 'invalidPurchaseToken'],
+      ['finalize_after', purchase, 'networkError'],
+      ['finalize_after', purchase, 'networkError']
+    ]
+  )
+  assert.deepEqual(calls[1].args.verificationError.details, { status: 403 })
+})
@@ -0,0 +1,235 @@
+import assert from 'node:assert/strict'
+import { afterEach, mock, test } from 'node:test'
+
+import * as iap from '../../dist-js/index.js'
+import { clearMocks, emitEvent, mockPlugin } from './ipc.js'
+
+const NOW = Date.parse('2024-06-01T00:00:00Z')
+
+function purchase(productId, overrides = {}) {
+  return {
+    purchaseId: `order-${productId}`,
+    productId,
+    verificationData: { localVerificationData: 'token', serverVerificationData: 'token', source: 'mock' },
+    transactionDate: '2024-05-01T00:00:00Z',
+    status: 'purchased',
+    pendingCompletePurchase: false,
+    verified: false,
+    ...overrides
+  }
+}
+
+/** Lets the store handle the events and commands in flight. */
+const settle = () => new Promise((resolve) => setImmediate(resolve))
+
+afterEach(() => {
+  mock.timers.reset()
+  iap.resetIapMock()
+  clearMocks()
+})
+
+/** An entitlement store over the fake store, with the entitlements it published. */
+async function entitlementStore(options = {}) {
+  const fake = iap.setIapMock(options)
+  const store = await iap.createEntitlementStore()
+  const published = []
+  store.subscribe((entitlements) => published.push(entitlements))
+  return { fake, store, published }
+}
+
+test('loads the owned products when created', async () => {
+  const { store, published } = await entitlementStore({
+    purchases: [purchase('premium'), purchase('coins', { status: 'pending' })]
+  })
+
+  assert.deepEqual(store.getAll(), [
+    { productId: 'premium', state: 'owned', purchaseId: 'order-premium', expirationDate: undefined }
+  ])
+  assert.equal(store.get('premium').state, 'owned')
+  assert.deepEqual(store.get('coins'), { productId: 'coins', state: 'notOwned' })
+  assert.equal(published.length, 1)
+  store.close()
+})
+
+test('applies purchase updates once', async () => {
+  const { fake, store, published } = await entitlementStore()
+
+  fake.pushPurchaseUpdates([purchase('coins', { status: 'pending' })])
+  assert.equal(store.get('coins').state, 'pending')
+  fake.pushPurchaseUpdates([purchase('coins')])
+  fake.pushPurchaseUpdates([purchase('coins')])
+  fake.pushPurchaseUpdates([purchase('coins', { status: 'restored' })])
+
+  assert.deepEqual(
+    published.map((entitlements) => entitlements.map(({ productId, state }) => [productId, state])),
+    [[], [['coins', 'pending']], [['coins', 'owned']]]
+  )
+  // `getAll` keeps returning the published array until something changes.
+  assert.equal(store.getAll(), published[2])
+  store.close()
+})
+
+test('ignores updates older than the entitlement', async () => {
+  const { fake, store } = await entitlementStore()
+  fake.pushPurchaseUpdates([purchase('premium', { purchaseId: 'order-2', transactionDate: '2024-05-02T00:00:00Z' })])
+
+  // The pending update of the same purchase, delivered after it was bought.
+  fake.pushPurchaseUpdates([purchase('premium', { purchaseId: 'order-2', status: 'pending' })])
+  // An earlier purchase of the product.
+  fake.pushPurchaseUpdates([purchase('premium', { purchaseId: 'order-1', transactionDate: '2024-05-01T00:00:00Z' })])
+  // A renewal awaiting payment doesn't take the subscription away.
+  fake.pushPurchaseUpdates([purchase('premium', { purchaseId: 'order-3', status: 'pending', transactionDate: null })])
+
+  assert.deepEqual(store.get('premium'), {
+    productId: 'premium',
+    state: 'owned',
+    purchaseId: 'order-2',
+    expirationDate: undefined
+  })
+  store.close()
+})
+
+test('forgets pending purchases that were cancelled or failed', async () => {
+  const { fake, store } = await entitlementStore()
+  fake.pushPurchaseUpdates([
+    purchase('coins', { status: 'pending' }),
+    purchase('gems', { status: 'pending' }),
+    purchase('premium')
+  ])
+
+  fake.pushPurchaseUpdates([purchase('coins', { status: 'canceled' }), purchase('gems', { status: 'error' })])
+  fake.pushPurchaseUpdates([purchase('premium', { status: 'canceled', transactionDate: null })])
+
+  assert.equal(store.get('coins').state, 'notOwned')
+  assert.equal(store.get('gems').state, 'notOwned')
+  // Only a reload tells that an owned purchase was revoked.
+  assert.equal(store.get('premium').state, 'owned')
+  store.close()
+})
+
+test('revokes the products a reload no longer reports', async () => {
+  const { fake, store } = await entitlementStore({ purchases: [purchase('premium'), purchase('pro')] })
+
+  await iap.mockClearPurchases()
+  assert.equal(store.get('premium').state, 'owned')
+  await store.refresh()
+  assert.equal(store.get('premium').state, 'revoked')
+  assert.equal(store.get('pro').state, 'revoked')
+
+  // Completed restores reload the owned products.
+  fake.pushPurchaseUpdates([purchase('premium', { purchaseId: 'order-again', transactionDate: '2024-05-03T00:00:00Z' })])
+  assert.equal(store.get('premium').state, 'owned')
+  await iap.restorePurchases()
+  await settle()
+  assert.equal(store.get('premium').state, 'revoked')
+  assert.equal(store.get('pro').state, 'revoked')
+  store.close()
+})
+
+test('follows the subscription states the store reports', async () => {
+  const { fake, store } = await entitlementStore()
+  const subscription = (subscriptionState, purchaseId) =>
+    purchase('monthly', { purchaseId, subscriptionState, expirationDate: '2999-01-01T00:00:00Z' })
+
+  const states = []
+  for (const [state, purchaseId] of [
+    ['active', 'order-1'],
+    ['onHold', 'order-1'],
+    ['active', 'order-1'],
+    ['revoked', 'order-1'],
+    ['expired', 'order-1']
+  ]) {
+    fake.pushPurchaseUpdates([subscription(state, purchaseId)])
+    states.push(store.get('monthly').state)
+  }
+
+  assert.deepEqual(states, ['owned', 'onHold', 'owned', 'revoked', 'expired'])
+  store.close()
+})
+
+test('expires entitlements when their date passes', async () => {
+  mock.timers.enable({ apis: ['setTimeout', 'Date'], now: NOW })
+  const { fake, store, published } = await entitlementStore()
+  const inAnHour = new Date(NOW + 60 * 60 * 1000).toISOString()
+  const inADay = new Date(NOW + 24 * 60 * 60 * 1000).toISOString()
+
+  fake.pushPurchaseUpdates([
+    purchase('monthly', { expirationDate: inAnHour }),
+    // Owned until the grace period ends.
+    purchase('yearly', {
+      expirationDate: inAnHour,
+      gracePeriodExpirationDate: inADay,
+      subscriptionState: 'gracePeriod'
+    }),
+    purchase('weekly', { expirationDate: new Date(NOW - 1000).toISOString() })
+  ])
+  assert.deepEqual(
+    store.getAll().map(({ productId, state }) => [productId, state]),
+    [['monthly', 'owned'], ['weekly', 'expired'], ['yearly', 'owned']]
+  )
+  assert.equal(store.get('yearly').expirationDate, inADay)
+
+  mock.timers.tick(60 * 60 * 1000)
+  assert.equal(store.get('monthly').state, 'expired')
+  assert.equal(store.get('yearly').state, 'owned')
+  mock.timers.tick(23 * 60 * 60 * 1000)
+  assert.equal(store.get('yearly').state, 'expired')
+  assert.equal(published.length, 4)
+
+  // Renewals are owned again until their new date.
+  fake.pushPurchaseUpdates([
+    purchase('monthly', {
+      purchaseId: 'order-renewal',
+      transactionDate: '2024-06-01T01:00:00Z',
+      expirationDate: '2024-07-01T00:00:00Z'
+    })
+  ])
+  assert.equal(store.get('monthly').state, 'owned')
+  store.close()
+})
+
+test('keeps the updates received while reloading', async () => {
+  let owned
+  const calls = mockPlugin((command) =>
+    command === 'owned_products' ? new Promise((resolve) => (owned = resolve)) : null
+  )
+  const creating = iap.createEntitlementStore()
+  while (!owned) {
+    await settle()
+  }
+
+  // Bought while the owned products load, which lists the previous purchase.
+  await emitEvent(iap.IapEvent.PurchaseUpdate, [
+    purchase('premium', { purchaseId: 'order-new', transactionDate: '2024-05-02T00:00:00Z' }),
+    purchase('coins')
+  ])
+  owned([{ productId: 'premium', purchaseId: 'order-old', source: 'store' }])
+  const store = await creating
+
+  assert.equal(store.get('premium').purchaseId, 'order-new')
+  assert.equal(store.get('coins').state, 'owned')
+  assert.ok(calls.some((call) => call.command === 'owned_products'))
+  store.close()
+})
+
+test('stops following the events once closed', async () => {
+  const { fake, store, published } = await entitlementStore()
+
+  store.close()
+  fake.pushPurchaseUpdates([purchase('premium')])
+
+  assert.equal(store.get('premium').state, 'notOwned')
+  assert.equal(published.length, 1)
+})
+
+test('can skip loading the owned products', async () => {
+  const fake = iap.setIapMock({ purchases: [purchase('premium')] })
+
+  const store = await iap.createEntitlementStore({ refresh: false })
+
+  assert.deepEqual(store.getAll(), [])
+  assert.deepEqual(fake.calls, [])
+  await store.refresh()
+  assert.equal(store.get('premium').state, 'owned')
+  store.close()
+})
@@ -0,0 +1,97 @@
+import assert from 'node:assert/strict'
+import { readFileSync } from 'node:fs'
+import { afterEach, test } from 'node:test'
+
+import * as iap from '../../dist-js/index.js'
+import { clearMocks, mockPlugin } from './ipc.js'
+
+// Shared with tests/error_codes.rs, which checks them against the Rust codes.
+const { codes, retryable } = JSON.parse(readFileSync(new URL('../error-codes.json', import.meta.url), 'utf8'))
+
+const SUBCLASSES = {
+  userCancelled: iap.UserCancelledError,
+  networkError: iap.NetworkError,
+  rateLimited: iap.RateLimitedError,
+  serviceDisconnected: iap.ServiceDisconnectedError,
+  serviceTimeout: iap.ServiceTimeoutError,
+  timedOut: iap.TimedOutError,
+  notInitialized: iap.NotInitializedError,
+  storeUnavailable: iap.StoreUnavailableError,
+  itemAlreadyOwned: iap.ItemAlreadyOwnedError,
+  itemNotOwned: iap.ItemNotOwnedError,
+  unknownPurchase: iap.UnknownPurchaseError,
+  noActiveSubscription: iap.NoActiveSubscriptionError,
+  ambiguousSubscription: iap.AmbiguousSubscriptionError,
+  invalidPurchaseToken: iap.InvalidPurchaseTokenError,
+  featureNotSupported: iap.FeatureNotSupportedError,
+  scopeViolation: iap.ScopeViolationError,
+  windowNotAllowed: iap.WindowNotAllowedError,
+  invalidConfig: iap.InvalidConfigError
+}
+
+afterEach(clearMocks)
+
+test('command rejections are thrown as the class of their code', async () => {
+  for (const code of codes) {
+    const rejection = { code, message: `${code} failed`, details: { productId: 'premium' }, nativeCode: 7 }
+    mockPlugin(() => {
+      throw rejection
+    })
+
+    const error = await iap.restorePurchases().then(
+      () => assert.fail(`${code} resolved`),
+      (e) => e
+    )
+
+    const ErrorClass = SUBCLASSES[code] ?? iap.IapError
+    assert.equal(Object.getPrototypeOf(error), ErrorClass.prototype, code)
+    assert.ok(error instanceof iap.IapError, code)
+    assert.equal(error.name, ErrorClass.name, code)
+    assert.equal(error.code, code)
+    assert.equal(error.message, `${code} failed`)
+    assert.deepEqual(error.details, { productId: 'premium' })
+    assert.equal(error.nativeCode, 7)
+    assert.equal(error.retryable, retryable.includes(code), code)
+  }
+})
+
+test('every error subclass has a code', () => {
+  for (const code of Object.keys(SUBCLASSES)) {
+    assert.ok(codes.includes(code), code)
+  }
+})
+
+test('codes added after the bindings are thrown as IapError', () => {
+  const error = iap.toIapError({ code: 'somethingNew', message: 'New failure' })
+
+  assert.equal(Object.getPrototypeOf(error), iap.IapError.prototype)
+  assert.equal(error.code, 'somethingNew')
+  assert.equal(error.message, 'New failure')
+  assert.equal(error.retryable, false)
+})
+
+test('rejections that are not plugin errors get the unknown code', () => {
+  const denied = iap.toIapError('iap.buy_consumable not allowed')
+  assert.equal(denied.code, iap.IapErrorCode.Unknown)
+  assert.equal(denied.message, 'iap.buy_consumable not allowed')
+
+  const thrown = iap.toIapError(new TypeError('window is not defined'))
+  assert.equal(thrown.code, iap.IapErrorCode.Unknown)
+  assert.equal(thrown.message, 'window is not defined')
+
+  const typed = iap.toIapError({ code: 'networkError', message: 'offline' })
+  assert.equal(iap.toIapError(typed), typed)
+})
+
+test('error details are exposed by the subclasses', () => {
+  const limited = iap.toIapError({ code: 'rateLimited', message: 'Slow down', details: { retryAfterMs: 2000 } })
+  assert.equal(limited.retryAfterMs, 2000)
+
+  const ambiguous = iap.toIapError({
+    code: 'ambiguousSubscription',
+    message: 'Several subscriptions',
+    details: { candidates: ['monthly', 'yearly'] }
+  })
+  assert.deepEqual(ambiguous.candidates, ['monthly', 'yearly'])
+  assert.deepEqual(iap.toIapError({ code: 'ambiguousSubscription', message: '' }).candidates, [])
+})
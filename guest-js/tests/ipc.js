@@ -0,0 +1,32 @@
+// Mocked Tauri IPC for the tests of the bindings, which run against the built
+// package in dist-js like an app would import it.
+import { emit } from '@tauri-apps/api/event'
+import { clearMocks, mockIPC } from '@tauri-apps/api/mocks'
+
+// The bindings and the IPC mocks expect a browser window.
+globalThis.window ??= Object.assign(new EventTarget(), { crypto: globalThis.crypto })
+
+/**
+ * Serves the `plugin:iap|*` commands with `handler` and the event plugin like a
+ * webview does, returning the commands invoked with their JSON arguments.
+ */
+export function mockPlugin(handler = () => null) {
+  const calls = []
+  mockIPC(
+    (cmd, args) => {
+      const command = cmd.replace(/^plugin:iap\|/, '')
+      // Arguments cross the IPC boundary as JSON, which drops undefined ones.
+      calls.push({ command, args: JSON.parse(JSON.stringify(args ?? {})) })
+      return handler(command, args)
+    },
+    { shouldMockEvents: true }
+  )
+  return calls
+}
+
+/** Emits a plugin event, as the plugin does from Rust. */
+export async function emitEvent(event, payload) {
+  await emit(event, payload)
+}
+
+export { clearMocks }
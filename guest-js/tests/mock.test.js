@@ -0,0 +1,192 @@
+import assert from 'node:assert/strict'
+import { afterEach, beforeEach, test } from 'node:test'
+
+import * as iap from '../../dist-js/index.js'
+import { clearMocks, mockPlugin } from './ipc.js'
+
+const premium = {
+  id: 'premium',
+  title: 'Premium',
+  description: 'Unlocks everything',
+  price: '$4.99',
+  rawPrice: 4.99,
+  currencyCode: 'USD',
+  currencySymbol: '$'
+}
+const coins = { ...premium, id: 'coins', title: '100 coins', price: '$0.99', rawPrice: 0.99 }
+const monthly = { ...premium, id: 'monthly', title: 'Monthly', subscriptionPeriod: 'P1M' }
+
+function owned(productId) {
+  return {
+    purchaseId: `order-${productId}`,
+    productId,
+    verificationData: { localVerificationData: 'token', serverVerificationData: 'token', source: 'mock' },
+    transactionDate: '2024-01-01T00:00:00Z',
+    transactionDateMs: 1704067200000,
+    status: 'purchased',
+    pendingCompletePurchase: false,
+    verified: false
+  }
+}
+
+/** Commands that reached the plugin instead of the fake. */
+let plugin
+
+beforeEach(() => {
+  plugin = mockPlugin(() => {
+    throw { code: 'internalError', message: 'Reached the plugin' }
+  })
+})
+
+afterEach(() => {
+  iap.resetIapMock()
+  clearMocks()
+})
+
+/** Collects the payloads of the purchase update events. */
+async function purchaseUpdates() {
+  const updates = []
+  await iap.onPurchaseUpdate((purchases) => updates.push(purchases))
+  return updates
+}
+
+test('serves the products and purchases it was given', async () => {
+  iap.setIapMock({ products: [premium, coins], purchases: [owned('premium')] })
+
+  assert.deepEqual(await iap.queryProductDetails(['premium', 'missing']), {
+    productDetails: [premium],
+    notFoundIds: ['missing']
+  })
+  assert.equal(await iap.isOwned('premium'), true)
+  assert.equal(await iap.isOwned('coins'), false)
+  assert.deepEqual(await iap.ownedProducts(), [
+    { productId: 'premium', purchaseId: 'order-premium', expirationDate: undefined, source: 'store' }
+  ])
+  await assert.rejects(iap.buyNonConsumable({ productDetails: premium }), iap.ItemAlreadyOwnedError)
+  assert.deepEqual(plugin, [])
+})
+
+test('reports purchases like the mock store', async () => {
+  const store = iap.setIapMock({ products: [premium, coins] })
+  const updates = await purchaseUpdates()
+
+  assert.equal(await iap.buyNonConsumable({ productDetails: premium }), true)
+  const result = await iap.purchase(coins, { consumable: true })
+
+  assert.equal(result.status, 'purchased')
+  assert.equal(result.purchase.productId, 'coins')
+  assert.equal(result.purchase.verificationData.serverVerificationData, 'mock-token-2')
+  assert.equal(result.purchase.correlationId, undefined)
+  assert.deepEqual(
+    updates.map((purchases) => purchases.map(({ productId, status }) => [productId, status])),
+    [[['premium', 'purchased']], [['coins', 'purchased']]]
+  )
+  assert.equal(updates[0][0].correlationId, undefined)
+  assert.equal(typeof updates[1][0].correlationId, 'string')
+
+  // Completing consumes the coins, which can be bought again.
+  await assert.rejects(iap.buyConsumable({ productDetails: coins }), iap.ItemAlreadyOwnedError)
+  await iap.completePurchase(result.purchase)
+  assert.equal(await iap.buyConsumable({ productDetails: coins }), true)
+  await assert.rejects(iap.completePurchase(owned('premium')), iap.UnknownPurchaseError)
+  await assert.rejects(iap.buyNonConsumable({ productDetails: { ...premium, id: 'missing' } }), (e) => e.code === 'purchaseError')
+
+  assert.deepEqual(
+    store.calls.map((call) => call.command),
+    [
+      'buy_non_consumable',
+      'buy_consumable',
+      'buy_consumable',
+      'complete_purchase',
+      'buy_consumable',
+      'complete_purchase',
+      'buy_non_consumable'
+    ]
+  )
+  assert.deepEqual(store.calls[0].args, { purchaseParam: { productDetails: premium } })
+  assert.deepEqual(plugin, [])
+})
+
+test('restores the owned non-consumables', async () => {
+  iap.setIapMock({ products: [premium, coins], purchases: [owned('premium')] })
+  await iap.buyConsumable({ productDetails: coins })
+  const updates = await purchaseUpdates()
+  const completed = []
+  await iap.onRestoreCompleted((payload) => completed.push(payload))
+
+  await iap.restorePurchases()
+
+  assert.deepEqual(updates, [[{ ...owned('premium'), status: 'restored' }]])
+  assert.deepEqual(completed, [{ restoredCount: 1 }])
+})
+
+test('rejects with the injected failures', async () => {
+  const store = iap.setIapMock({
+    products: [premium, coins],
+    failures: [
+      { operation: 'restore_purchases', code: 'networkError' },
+      { operation: 'buy_consumable', code: 'userCancelled', productId: 'coins', persistent: true }
+    ]
+  })
+
+  await assert.rejects(iap.restorePurchases(), iap.NetworkError)
+  await iap.restorePurchases()
+  for (let attempt = 0; attempt < 2; attempt++) {
+    await assert.rejects(iap.buyConsumable({ productDetails: coins }), iap.UserCancelledError)
+  }
+  assert.deepEqual(await iap.purchase(coins, { consumable: true }), { status: 'cancelled' })
+  assert.equal(await iap.buyConsumable({ productDetails: premium }), true)
+
+  await iap.mockSetFailure(null)
+  assert.equal(await iap.buyConsumable({ productDetails: coins }), true)
+  store.injectFailure({ operation: 'query_product_details', code: 'serviceTimeout' })
+  await assert.rejects(iap.queryProductDetails(['premium']), iap.ServiceTimeoutError)
+  assert.deepEqual(plugin, [])
+})
+
+test('pushed updates reach the handlers until they unlisten', async () => {
+  const store = iap.setIapMock({ products: [monthly] })
+  const updates = []
+  const unlisten = await iap.onPurchaseUpdate((purchases) => updates.push(purchases))
+  const changes = []
+  await iap.onConnectionStateChange((change) => changes.push(change))
+
+  await iap.initialize()
+  const renewed = { ...owned('monthly'), subscriptionState: 'active' }
+  store.pushPurchaseUpdates([renewed])
+  unlisten()
+  store.pushPurchaseUpdates([renewed])
+
+  assert.deepEqual(updates, [[renewed]])
+  assert.deepEqual(changes, [{ previous: 'uninitialized', state: 'ready' }])
+  assert.equal(await iap.connectionState(), 'ready')
+})
+
+test('clears the purchases, revoking them', async () => {
+  const store = iap.setIapMock({ products: [premium], purchases: [owned('premium')] })
+  const updates = await purchaseUpdates()
+
+  await iap.mockClearPurchases()
+
+  assert.deepEqual(updates, [[{ ...owned('premium'), status: 'canceled', pendingCompletePurchase: false }]])
+  assert.deepEqual(store.ownedPurchases, [])
+  assert.equal(await iap.isOwned('premium'), false)
+})
+
+test('calls the plugin again once reset', async () => {
+  const updates = []
+  iap.setIapMock({ products: [premium] })
+  await iap.onPurchaseUpdate((purchases) => updates.push(purchases))
+
+  iap.resetIapMock()
+
+  await assert.rejects(iap.queryProductDetails(['premium']), (e) => e.message === 'Reached the plugin')
+  assert.deepEqual(
+    plugin.map((call) => call.command),
+    ['query_product_details']
+  )
+  // Handlers registered with the fake installed don't receive events anymore.
+  iap.setIapMock({ products: [premium] })
+  await iap.buyNonConsumable({ productDetails: premium })
+  assert.deepEqual(updates, [])
+})
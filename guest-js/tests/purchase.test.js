@@ -0,0 +1,165 @@
+import assert from 'node:assert/strict'
+import { afterEach, test } from 'node:test'
+
+import * as iap from '../../dist-js/index.js'
+import { clearMocks, emitEvent, mockPlugin } from './ipc.js'
+
+const premium = { id: 'premium', title: 'Premium', description: '', price: '$4.99', rawPrice: 4.99, currencyCode: 'USD' }
+
+function purchase(overrides = {}) {
+  return {
+    purchaseId: 'order-1',
+    productId: 'premium',
+    status: 'purchased',
+    pendingCompletePurchase: true,
+    verified: false,
+    ...overrides
+  }
+}
+
+/** Serves the buy commands with `buy`, called with their correlation ID. */
+function mockBuy(buy) {
+  return mockPlugin((command, args) => (command.startsWith('buy_') ? buy(args.correlationId, args) : null))
+}
+
+const reportUpdates = (purchases) => emitEvent(iap.IapEvent.PurchaseUpdate, purchases)
+const reportError = (error) => emitEvent(iap.IapEvent.Error, error)
+
+afterEach(clearMocks)
+
+test('resolves with the purchase carrying its correlation ID', async () => {
+  const calls = mockBuy(async (correlationId) => {
+    await reportUpdates([
+      // Reported again by a restore or the owned purchases query meanwhile.
+      purchase({ purchaseId: 'order-owned' }),
+      purchase({ purchaseId: 'order-restored', status: 'restored' }),
+      purchase({ purchaseId: 'order-other', correlationId: 'another-call' }),
+      purchase({ correlationId })
+    ])
+    return true
+  })
+
+  const result = await iap.purchase(premium, { applicationUserName: 'user' })
+
+  assert.deepEqual(result, { status: 'purchased', purchase: purchase() })
+  const buys = calls.filter((call) => call.command.startsWith('buy_'))
+  assert.equal(buys.length, 1)
+  assert.equal(buys[0].command, 'buy_non_consumable')
+  assert.deepEqual(buys[0].args.purchaseParam, { productDetails: premium, applicationUserName: 'user' })
+  assert.equal(typeof buys[0].args.correlationId, 'string')
+})
+
+test('correlation IDs differ between calls', async () => {
+  const ids = []
+  const calls = mockBuy(async (correlationId) => {
+    ids.push(correlationId)
+    await reportUpdates([purchase({ correlationId })])
+    return true
+  })
+
+  await iap.purchase(premium)
+  await iap.purchase(premium, { consumable: true, autoConsume: true })
+
+  assert.notEqual(ids[0], ids[1])
+  assert.deepEqual(
+    calls.filter((call) => call.command.startsWith('buy_')).map((call) => [call.command, call.args.autoConsume]),
+    [['buy_non_consumable', undefined], ['buy_consumable', true]]
+  )
+})
+
+test('waits for the purchase when the command returns first', async () => {
+  let correlated
+  mockBuy((correlationId) => {
+    correlated = correlationId
+    return true
+  })
+
+  const result = iap.purchase(premium)
+  await new Promise((resolve) => setTimeout(resolve, 10))
+  await reportUpdates([purchase({ correlationId: correlated })])
+
+  assert.deepEqual(await result, { status: 'purchased', purchase: purchase() })
+})
+
+test('settles as cancelled when the user cancels', async () => {
+  mockBuy(async (correlationId) => {
+    await reportUpdates([purchase({ status: 'canceled', correlationId })])
+    return true
+  })
+  assert.deepEqual(await iap.purchase(premium), { status: 'cancelled' })
+
+  mockBuy(() => {
+    throw { code: 'userCancelled', message: 'User cancelled the purchase' }
+  })
+  assert.deepEqual(await iap.purchase(premium), { status: 'cancelled' })
+
+  // Google Play reports the flows the user left through the error event.
+  mockBuy(async (correlationId) => {
+    await reportError({ code: 'userCancelled', message: 'Cancelled', details: { correlationId } })
+    return true
+  })
+  assert.deepEqual(await iap.purchase(premium), { status: 'cancelled' })
+})
+
+test('rejects with the failure of its flow', async () => {
+  mockBuy(async (correlationId) => {
+    await reportError({ code: 'networkError', message: 'Other flow', details: { correlationId: 'another-call' } })
+    await reportError({ code: 'networkError', message: 'No correlation' })
+    await reportError({ code: 'networkError', message: 'Offline', details: { correlationId } })
+    return true
+  })
+  await assert.rejects(iap.purchase(premium), (e) => e instanceof iap.NetworkError && e.message === 'Offline')
+
+  mockBuy(async (correlationId) => {
+    const error = { code: 'invalidPurchaseToken', message: 'Signature mismatch' }
+    await reportUpdates([purchase({ status: 'error', error, correlationId })])
+    return true
+  })
+  await assert.rejects(iap.purchase(premium), iap.InvalidPurchaseTokenError)
+
+  mockBuy(() => {
+    throw { code: 'itemAlreadyOwned', message: 'Item already owned' }
+  })
+  await assert.rejects(iap.purchase(premium), iap.ItemAlreadyOwnedError)
+})
+
+test('settles as pending when the store returns without a purchase', { timeout: 5000 }, async () => {
+  mockBuy(() => false)
+  assert.deepEqual(await iap.purchase(premium), { status: 'pending', purchase: undefined })
+
+  mockBuy(async (correlationId) => {
+    await reportUpdates([purchase({ status: 'pending', correlationId })])
+    return false
+  })
+  assert.deepEqual(await iap.purchase(premium), { status: 'pending', purchase: purchase({ status: 'pending' }) })
+})
+
+test('times out unless its flow is settled', async () => {
+  mockBuy(async (correlationId) => {
+    await reportUpdates([purchase({ status: 'pending', correlationId })])
+    return true
+  })
+  assert.deepEqual(await iap.purchase(premium, { timeoutMs: 20 }), {
+    status: 'pending',
+    purchase: purchase({ status: 'pending' })
+  })
+
+  mockBuy(async () => {
+    await reportUpdates([purchase()])
+    return true
+  })
+  await assert.rejects(
+    iap.purchase(premium, { timeoutMs: 20 }),
+    (e) => e instanceof iap.TimedOutError && e.details.operation === 'purchase'
+  )
+})
+
+test('rejects when the page is unloaded', async () => {
+  mockBuy(() => true)
+
+  const result = iap.purchase(premium)
+  await new Promise((resolve) => setTimeout(resolve, 10))
+  window.dispatchEvent(new Event('pagehide'))
+
+  await assert.rejects(result, (e) => e.code === iap.IapErrorCode.Unknown)
+})
@@ -31,7 +31,11 @@
 ///    - Error code 5: Restore failed
 ///    Solution: Check network and user's Apple ID
 ///
-/// 6. Common Solutions:
+/// 6. Configuration Issues:
+///    - Error code 7: None of the requested product IDs are valid (developerError)
+///    Solution: Check the bundle ID, paid apps agreement and product IDs in App Store Connect
+///
+/// 7. Common Solutions:
 ///    - Verify App Store Connect configuration
 ///    - Check network connectivity
 ///    - Validate product IDs
@@ -333,6 +337,7 @@ import SwiftRs
 import Tauri
 import UIKit
 import StoreKit
+import WebKit
 
 /// Callback type for communicating with Rust
 /// - Parameters:
@@ -378,18 +383,1114 @@ struct ProductDetails: Codable {
     let currencyCode: String
     /// Currency symbol
     let currencySymbol: String
+    /// Subscription group of a subscription
+    var subscriptionGroupId: String? = nil
+}
+
+// MARK: - Error Codes
+
+/// Error codes shared with the Rust `IapErrorCode` enum.
+/// The raw values must match the strings produced by `IapErrorCode::as_str`.
+enum IapErrorCode: String {
+    case userCancelled
+    case receiptUnavailable
+    case developerError
+    case featureNotSupported
+    case itemNotOwned
+    case internalError
+}
+
+extension Invoke {
+    /// Reject with the JSON `{ code, message, details, nativeCode }` body
+    /// that the Rust core parses into the matching error variant. The code
+    /// is also the rejection code, so the mapping never depends on the
+    /// localized message.
+    func reject(code: IapErrorCode, message: String, details: [String: Any]? = nil, nativeCode: Int? = nil) {
+        var body: [String: Any] = ["code": code.rawValue, "message": message]
+        if let details = details {
+            body["details"] = details
+        }
+        if let nativeCode = nativeCode {
+            body["nativeCode"] = nativeCode
+        }
+        let json = (try? JSONSerialization.data(withJSONObject: body))
+            .flatMap { String(data: $0, encoding: .utf8) }
+        reject(json ?? message, code: code.rawValue)
+    }
+}
+
+/// Check whether an error means the user dismissed the payment sheet
+/// or the App Store sign-in prompt
+///
+/// Matches on error domains and codes rather than localized messages so the
+/// result doesn't depend on the device language or OS version.
+///
+/// Covers:
+/// - StoreKit 1 `SKError.paymentCancelled`
+/// - StoreKit 2 `StoreKitError.userCancelled`
+/// - Cancelling the Apple ID sign-in prompt (`AKAuthenticationError` -7003),
+///   which StoreKit reports wrapped in an underlying error
+func isUserCancellation(_ error: Error) -> Bool {
+    if let skError = error as? SKError, skError.code == .paymentCancelled {
+        return true
+    }
+    if #available(iOS 15.0, *),
+       let storeKitError = error as? StoreKitError,
+       case .userCancelled = storeKitError {
+        return true
+    }
+    let nsError = error as NSError
+    if nsError.domain == "AKAuthenticationError" && nsError.code == -7003 {
+        return true
+    }
+    if let underlying = nsError.userInfo[NSUnderlyingErrorKey] as? Error {
+        return isUserCancellation(underlying)
+    }
+    return false
 }
 
 // MARK: - Plugin Implementation
 
+/// Arguments of `open_external_purchase_link`
+struct OpenExternalPurchaseLinkArgs: Decodable {
+    /// Link to the purchase page outside of the App Store
+    let url: String
+}
+
+/// Arguments of `is_feature_supported`
+struct IsFeatureSupportedArgs: Decodable {
+    /// camelCase `StoreFeature` of the Rust core
+    let feature: String
+}
+
+/// Arguments of `open_store_product_page`
+struct OpenStoreProductPageArgs: Decodable {
+    /// Numeric App Store ID of the app, resolved from the configuration by
+    /// the Rust core when not given
+    let productId: String
+}
+
+/// Arguments of `restore_purchases`
+struct RestorePurchasesArgs: Decodable {
+    /// Only restores the transactions made with this name, on StoreKit 1
+    let applicationUserName: String?
+}
+
+/// Arguments of `get_app_receipt`
+struct GetAppReceiptArgs: Decodable {
+    /// Whether to request the receipt from the App Store if it is missing
+    let refresh: Bool?
+}
+
+/// Arguments of `subscription_status`
+struct SubscriptionStatusArgs: Decodable {
+    /// Identifier of the subscription product
+    let productId: String
+}
+
+/// Arguments of `buy_upgrade`
+struct BuyUpgradeArgs: Decodable {
+    /// Identifier of the subscription to switch to
+    let productId: String
+}
+
+/// Arguments of `buy_consumable`
+struct BuyConsumableArgs: Decodable {
+    let purchaseParam: PurchaseParam
+}
+
+/// Arguments of `complete_purchase`, the purchase to complete
+struct CompletePurchaseArgs: Decodable {
+    /// Transaction ID of the purchase
+    let purchaseId: String?
+}
+
+/// Arguments of `start_transaction_updates`
+struct StartTransactionUpdatesArgs: Decodable {
+    /// Channel of the Rust core receiving the updated purchases
+    let onUpdate: Channel
+    /// Channel of the Rust core told about storefront and locale changes
+    let onStorefrontChange: Channel?
+}
+
+/// The parts of the `plugins.iap` configuration read natively
+struct IapConfig: Decodable {
+    let transactionListenerOnLoad: Bool?
+}
+
+/// Holds back the price consent sheet StoreKit shows on its own, with
+/// `deferPriceConsent`, until `show_price_consent_if_needed` shows it
+@available(iOS 13.4, *)
+private final class PriceConsentDelegate: NSObject, SKPaymentQueueDelegate {
+    /// Whether StoreKit wanted to show the sheet since it was last shown
+    var pending = false
+
+    func paymentQueueShouldShowPriceConsent(_ paymentQueue: SKPaymentQueue) -> Bool {
+        pending = true
+        return false
+    }
+}
+
+/// Runs an `SKReceiptRefreshRequest`, which may ask the user to sign in
+private final class ReceiptRefresh: NSObject, SKRequestDelegate {
+    private var request: SKReceiptRefreshRequest?
+    private var continuation: CheckedContinuation<Error?, Never>?
+
+    /// Refresh the receipt, returning the error if the request failed
+    func run() async -> Error? {
+        await withCheckedContinuation { continuation in
+            self.continuation = continuation
+            let request = SKReceiptRefreshRequest()
+            request.delegate = self
+            self.request = request
+            request.start()
+        }
+    }
+
+    func requestDidFinish(_ request: SKRequest) {
+        finish(nil)
+    }
+
+    func request(_ request: SKRequest, didFailWithError error: Error) {
+        finish(error)
+    }
+
+    private func finish(_ error: Error?) {
+        continuation?.resume(returning: error)
+        continuation = nil
+        request = nil
+    }
+}
+
+/// Restores the completed transactions with StoreKit 1, for iOS versions
+/// without `AppStore.sync()`. The restored transactions are finished, as they
+/// were delivered when first bought.
+private final class LegacyRestore: NSObject, SKPaymentTransactionObserver {
+    private var purchases: [[String: Any]] = []
+    private var continuation: CheckedContinuation<Result<[[String: Any]], Error>, Never>?
+
+    /// Restore the transactions, returning them as restored purchases
+    func run(applicationUserName: String?) async -> Result<[[String: Any]], Error> {
+        await withCheckedContinuation { continuation in
+            self.continuation = continuation
+            SKPaymentQueue.default().add(self)
+            if let applicationUserName = applicationUserName, !applicationUserName.isEmpty {
+                SKPaymentQueue.default().restoreCompletedTransactions(withApplicationUsername: applicationUserName)
+            } else {
+                SKPaymentQueue.default().restoreCompletedTransactions()
+            }
+        }
+    }
+
+    func paymentQueue(_ queue: SKPaymentQueue, updatedTransactions transactions: [SKPaymentTransaction]) {
+        let receipt = appReceiptData() ?? ""
+        for transaction in transactions where transaction.transactionState == .restored {
+            var purchase: [String: Any] = [
+                "purchaseId": transaction.original?.transactionIdentifier
+                    ?? transaction.transactionIdentifier ?? "",
+                "productId": transaction.payment.productIdentifier,
+                "verificationData": [
+                    "localVerificationData": receipt,
+                    "serverVerificationData": receipt,
+                    "source": "apple",
+                ],
+                "status": "restored",
+                "pendingCompletePurchase": false,
+            ]
+            if let date = transaction.original?.transactionDate ?? transaction.transactionDate {
+                purchase["transactionDateMs"] = epochMillis(date)
+            }
+            purchases.append(purchase)
+            queue.finishTransaction(transaction)
+        }
+    }
+
+    func paymentQueueRestoreCompletedTransactionsFinished(_ queue: SKPaymentQueue) {
+        finish(.success(purchases))
+    }
+
+    func paymentQueue(_ queue: SKPaymentQueue, restoreCompletedTransactionsFailedWithError error: Error) {
+        finish(.failure(error))
+    }
+
+    private func finish(_ result: Result<[[String: Any]], Error>) {
+        SKPaymentQueue.default().remove(self)
+        continuation?.resume(returning: result)
+        continuation = nil
+    }
+}
+
+/// The current entitlements as restored purchases, without revoked or
+/// expired ones, though subscriptions in the grace period are kept.
+/// Transactions failing the StoreKit verification are skipped unless
+/// `includeUnverified` is set, which reports them with `verified` unset.
+@available(iOS 15.0, *)
+private func currentEntitlementPurchases(includeUnverified: Bool = false) async -> [[String: Any]] {
+    var purchases: [[String: Any]] = []
+    for await result in Transaction.currentEntitlements {
+        let transaction: Transaction
+        let verified: Bool
+        switch result {
+        case .verified(let checked):
+            transaction = checked
+            verified = true
+        case .unverified(let unchecked, _):
+            guard includeUnverified else { continue }
+            transaction = unchecked
+            verified = false
+        }
+        if transaction.revocationDate != nil {
+            continue
+        }
+        let subscription = await subscriptionFields(transaction)
+        let inGracePeriod = subscription["subscriptionState"] as? String == "gracePeriod"
+        if let expiration = transaction.expirationDate, expiration <= Date(), !inGracePeriod {
+            continue
+        }
+        var purchase = purchaseDetails(
+            transaction,
+            jws: result.jwsRepresentation,
+            status: "restored",
+            pendingCompletePurchase: false,
+            verified: verified
+        )
+        purchase.merge(subscription) { $1 }
+        purchases.append(purchase)
+    }
+    return purchases
+}
+
+/// The `subscriptionState` and `gracePeriodExpirationDate` of an
+/// auto-renewable subscription transaction, read from the status of its
+/// subscription group. Empty for other products, or if the status can't be
+/// read.
+@available(iOS 15.0, *)
+private func subscriptionFields(_ transaction: Transaction) async -> [String: Any] {
+    guard transaction.productType == .autoRenewable,
+          let groupID = transaction.subscriptionGroupID,
+          let statuses = try? await Product.SubscriptionInfo.status(for: groupID),
+          let status = statuses.first(where: { status in
+              guard case .verified(let latest) = status.transaction else { return false }
+              return latest.originalID == transaction.originalID
+          })
+    else {
+        return [:]
+    }
+    return subscriptionFields(status)
+}
+
+@available(iOS 15.0, *)
+private func subscriptionFields(_ status: Product.SubscriptionInfo.Status) -> [String: Any] {
+    var fields: [String: Any] = ["subscriptionState": subscriptionStateName(status.state)]
+    if status.state == .inGracePeriod,
+       case .verified(let renewal) = status.renewalInfo,
+       let gracePeriodEnd = renewal.gracePeriodExpirationDate {
+        fields["gracePeriodExpirationDate"] = ISO8601DateFormatter().string(from: gracePeriodEnd)
+    }
+    return fields
+}
+
+/// The Rust `SubscriptionState` of a StoreKit renewal state. Billing retry
+/// is what Google Play calls account hold.
+@available(iOS 15.0, *)
+private func subscriptionStateName(_ state: Product.SubscriptionInfo.RenewalState) -> String {
+    switch state {
+    case .subscribed:
+        return "active"
+    case .inGracePeriod:
+        return "gracePeriod"
+    case .inBillingRetryPeriod:
+        return "onHold"
+    case .revoked:
+        return "revoked"
+    default:
+        return "expired"
+    }
+}
+
+/// A transaction from `Transaction.updates`, e.g. a renewal or an approved
+/// Ask to Buy purchase, as a purchase. It stays unfinished until the app
+/// completes it, and revoked ones are reported as canceled.
+@available(iOS 15.0, *)
+private func transactionUpdatePurchase(_ result: VerificationResult<Transaction>) async -> [String: Any] {
+    let transaction: Transaction
+    let verified: Bool
+    switch result {
+    case .verified(let checked):
+        transaction = checked
+        verified = true
+    case .unverified(let unchecked, _):
+        transaction = unchecked
+        verified = false
+    }
+    let revoked = transaction.revocationDate != nil
+    var purchase = purchaseDetails(
+        transaction,
+        jws: result.jwsRepresentation,
+        status: revoked ? "canceled" : "purchased",
+        pendingCompletePurchase: !revoked,
+        verified: verified
+    )
+    purchase.merge(await subscriptionFields(transaction)) { $1 }
+    return purchase
+}
+
+/// A StoreKit 2 transaction in the shape of the Rust `PurchaseDetails`
+@available(iOS 15.0, *)
+private func purchaseDetails(
+    _ transaction: Transaction,
+    jws: String,
+    status: String,
+    pendingCompletePurchase: Bool,
+    verified: Bool
+) -> [String: Any] {
+    let formatter = ISO8601DateFormatter()
+    var purchase: [String: Any] = [
+        "purchaseId": String(transaction.id),
+        "productId": transaction.productID,
+        "verificationData": [
+            "localVerificationData": jws,
+            "serverVerificationData": jws,
+            "source": "apple",
+        ],
+        "transactionDateMs": epochMillis(transaction.purchaseDate),
+        "status": status,
+        "pendingCompletePurchase": pendingCompletePurchase,
+        "verified": verified,
+    ]
+    if let expiration = transaction.expirationDate {
+        purchase["expirationDate"] = formatter.string(from: expiration)
+    }
+    return purchase
+}
+
+/// Milliseconds since the Unix epoch of a date, the Rust side derives the
+/// ISO `transactionDate` from it
+private func epochMillis(_ date: Date) -> Int64 {
+    return Int64((date.timeIntervalSince1970 * 1000).rounded())
+}
+
+/// The base64 encoded receipt of the app, if it has one
+private func appReceiptData() -> String? {
+    guard let url = Bundle.main.appStoreReceiptURL,
+          let data = try? Data(contentsOf: url, options: .alwaysMapped)
+    else {
+        return nil
+    }
+    return data.base64EncodedString()
+}
+
+/// Info.plist keys that come with the external purchase entitlements
+/// (`com.apple.developer.storekit.external-purchase*`), which iOS apps
+/// can't read at runtime.
+private let externalPurchaseInfoKeys = [
+    "SKExternalPurchase",
+    "SKExternalPurchaseLink",
+    "SKExternalPurchaseMultiLink",
+]
+
 /// Main plugin class registered with Tauri
+///
+/// Tauri invokes the commands off the main thread. The ones presenting
+/// StoreKit UI, i.e. `buyUpgrade`, `openExternalPurchaseLink`,
+/// `openManageSubscriptions`, `openStoreProductPage` and
+/// `showPriceConsentIfNeeded`, hop to the main
+/// actor with `presentOnMainActor`, where StoreKit finds the window scene to
+/// present in, and resolve once the UI is done however long that takes.
+/// `deferPriceConsent` and the listeners of `startTransactionUpdates` and
+/// `endConnection` also run there, as the main actor owns their state. The
+/// other commands only query StoreKit and resolve from any thread.
 class IapPlugin: Plugin {
     /// Shared payment manager instance
     private static var shared: PaymentManager?
+
+    /// Token from the last notice sheet the user continued from, reported to
+    /// Apple for the external purchase
+    private var externalPurchaseToken: String?
+
+    /// The single `Transaction.updates` listener, while it runs
+    private var transactionUpdates: Task<Void, Never>?
+
+    /// Channel the listener reports to, set by `start_transaction_updates`
+    private var updatesChannel: Channel?
+
+    /// Updates received before a channel was attached, when the listener
+    /// was started on load
+    private var pendingUpdates: [[String: Any]] = []
+
+    /// The `Storefront.updates` listener, while it runs
+    private var storefrontUpdates: Task<Void, Never>?
+
+    /// Observer of `NSLocale.currentLocaleDidChangeNotification`
+    private var localeObserver: NSObjectProtocol?
+
+    /// The `PriceConsentDelegate`, kept here as the payment queue holds its
+    /// delegate weakly
+    private var priceConsentDelegate: NSObject?
     
     override init() {
         super.init()
     }
+
+    /// Start the transaction listener right away with
+    /// `transactionListenerOnLoad`, so updates delivered at launch aren't
+    /// missed before `initialize`.
+    override func load(webview: WKWebView) {
+        let config = try? parseConfig(IapConfig.self)
+        guard config?.transactionListenerOnLoad == true else { return }
+        if #available(iOS 15.0, *) {
+            listenForTransactions()
+        }
+    }
+
+    /// Attach the channel of the Rust core and start the transaction
+    /// listener if it isn't running, reporting the updates held since load.
+    /// Resolves whether a listener runs, which requires StoreKit 2.
+    @objc public func startTransactionUpdates(_ invoke: Invoke) {
+        guard let args = try? invoke.parseArgs(StartTransactionUpdatesArgs.self) else {
+            invoke.reject(code: .developerError, message: "Missing transaction update channel")
+            return
+        }
+        guard #available(iOS 15.0, *) else {
+            invoke.resolve(["running": false])
+            return
+        }
+        Task { @MainActor in
+            updatesChannel = args.onUpdate
+            listenForTransactions()
+            if let channel = args.onStorefrontChange {
+                listenForStorefrontChanges(channel)
+            }
+            if !pendingUpdates.isEmpty {
+                args.onUpdate.send(["purchases": pendingUpdates])
+                pendingUpdates = []
+            }
+            invoke.resolve(["running": true])
+        }
+    }
+
+    /// Report storefront changes, e.g. after the user moved their account to
+    /// another country, and locale changes to `channel`, as both change the
+    /// prices and titles of the products.
+    @available(iOS 15.0, *)
+    @MainActor
+    private func listenForStorefrontChanges(_ channel: Channel) {
+        storefrontUpdates?.cancel()
+        storefrontUpdates = Task {
+            for await _ in Storefront.updates {
+                channel.send([:])
+            }
+        }
+        if let observer = localeObserver {
+            NotificationCenter.default.removeObserver(observer)
+        }
+        localeObserver = NotificationCenter.default.addObserver(
+            forName: NSLocale.currentLocaleDidChangeNotification,
+            object: nil,
+            queue: .main
+        ) { _ in
+            channel.send([:])
+        }
+    }
+
+    /// Start the `Transaction.updates` listener unless it runs already, as a
+    /// second one would report every update twice.
+    @available(iOS 15.0, *)
+    @MainActor
+    private func listenForTransactions() {
+        guard transactionUpdates == nil else { return }
+        transactionUpdates = Task { [weak self] in
+            for await result in Transaction.updates {
+                let purchase = await transactionUpdatePurchase(result)
+                await MainActor.run {
+                    guard let self = self else { return }
+                    if let channel = self.updatesChannel {
+                        channel.send(["purchases": [purchase]])
+                    } else {
+                        self.pendingUpdates.append(purchase)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Defer the price consent sheet, with `deferPriceConsent`. Called by
+    /// the Rust core at setup, before StoreKit shows the sheet at launch.
+    @objc public func deferPriceConsent(_ invoke: Invoke) {
+        guard #available(iOS 13.4, *) else {
+            invoke.resolve(["deferred": false])
+            return
+        }
+        DispatchQueue.main.async {
+            let delegate = PriceConsentDelegate()
+            self.priceConsentDelegate = delegate
+            SKPaymentQueue.default().delegate = delegate
+            invoke.resolve(["deferred": true])
+        }
+    }
+
+    /// Show the price consent sheet if the user still has to respond to a
+    /// subscription price increase, which bypasses the delegate. Resolves
+    /// whether the deferred sheet was pending, which is only known with
+    /// `deferPriceConsent`.
+    @objc public func showPriceConsentIfNeeded(_ invoke: Invoke) {
+        guard #available(iOS 13.4, *) else {
+            invoke.resolve(["pending": false])
+            return
+        }
+        presentOnMainActor(invoke) {
+            let delegate = self.priceConsentDelegate as? PriceConsentDelegate
+            let pending = delegate?.pending ?? false
+            delegate?.pending = false
+            SKPaymentQueue.default().showPriceConsentIfNeeded()
+            invoke.resolve(["pending": pending])
+        }
+    }
+
+    /// Stop observing transactions until the next `initialize`, so no
+    /// purchase updates are reported meanwhile. Answers right away, as the
+    /// Rust core calls this while the plugin is dropped.
+    @objc public func endConnection(_ invoke: Invoke) {
+        IapPlugin.shared?.stop()
+        IapPlugin.shared = nil
+        Task { @MainActor in
+            transactionUpdates?.cancel()
+            transactionUpdates = nil
+            updatesChannel = nil
+            pendingUpdates = []
+            storefrontUpdates?.cancel()
+            storefrontUpdates = nil
+            if let observer = localeObserver {
+                NotificationCenter.default.removeObserver(observer)
+                localeObserver = nil
+            }
+        }
+        invoke.resolve()
+    }
+
+    /// Return the current entitlements as restored purchases, for the
+    /// `autoRestoreOnInit` query. Unlike a restore, the user isn't asked to
+    /// sign in. Requires StoreKit 2, older versions report none.
+    @objc public func ownedPurchases(_ invoke: Invoke) {
+        guard #available(iOS 15.0, *) else {
+            invoke.resolve(["purchases": []])
+            return
+        }
+        Task {
+            invoke.resolve(["purchases": await currentEntitlementPurchases()])
+        }
+    }
+
+    /// Return the current entitlements with their verification state, for
+    /// `current_entitlements`. Nothing is shown to the user. Requires
+    /// StoreKit 2, older versions report none.
+    @objc public func currentEntitlements(_ invoke: Invoke) {
+        guard #available(iOS 15.0, *) else {
+            invoke.resolve(["purchases": []])
+            return
+        }
+        Task {
+            invoke.resolve(["purchases": await currentEntitlementPurchases(includeUnverified: true)])
+        }
+    }
+
+    /// Return the StoreKit 2 status of a subscription the user bought, which
+    /// tells the grace period and billing retry apart. Requires iOS 15.
+    @objc public func subscriptionStatus(_ invoke: Invoke) {
+        guard let args = try? invoke.parseArgs(SubscriptionStatusArgs.self) else {
+            invoke.reject(code: .developerError, message: "Missing product ID")
+            return
+        }
+        guard #available(iOS 15.0, *) else {
+            invoke.reject(code: .featureNotSupported, message: "Subscription status requires iOS 15")
+            return
+        }
+        Task {
+            let statuses: [Product.SubscriptionInfo.Status]
+            do {
+                statuses = try await Product.products(for: [args.productId]).first?.subscription?.status ?? []
+            } catch {
+                invoke.reject(code: .internalError, message: error.localizedDescription)
+                return
+            }
+            // The group reports a status per subscription of the account,
+            // found by its latest transaction.
+            var found: (status: Product.SubscriptionInfo.Status, latest: Transaction)?
+            for status in statuses {
+                if case .verified(let latest) = status.transaction, latest.productID == args.productId {
+                    found = (status, latest)
+                    break
+                }
+            }
+            guard let status = found?.status, let latest = found?.latest else {
+                invoke.reject(code: .itemNotOwned, message: "Item not owned: \(args.productId)")
+                return
+            }
+            var response: [String: Any] = [
+                "productId": args.productId,
+                "isActive": status.state == .subscribed || status.state == .inGracePeriod,
+                "state": subscriptionStateName(status.state),
+            ]
+            response.merge(subscriptionFields(status).filter { $0.key != "subscriptionState" }) { $1 }
+            if let expiration = latest.expirationDate {
+                response["expirationDate"] = ISO8601DateFormatter().string(from: expiration)
+            }
+            if case .verified(let renewal) = status.renewalInfo {
+                response["willAutoRenew"] = renewal.willAutoRenew
+            }
+            invoke.resolve(response)
+        }
+    }
+
+    /// Switch to another subscription of the group the user subscribes to,
+    /// which the Rust core checked. Resolves with `{ purchase }`, or without
+    /// one while it awaits approval. The App Store decides when the switch
+    /// takes effect, so the replacement mode is ignored.
+    @objc public func buyUpgrade(_ invoke: Invoke) {
+        guard let args = try? invoke.parseArgs(BuyUpgradeArgs.self) else {
+            invoke.reject(code: .developerError, message: "Missing product ID")
+            return
+        }
+        guard #available(iOS 15.0, *) else {
+            invoke.reject(code: .featureNotSupported, message: "Subscription upgrades require iOS 15")
+            return
+        }
+        presentOnMainActor(invoke) {
+            guard let product = try await Product.products(for: [args.productId]).first else {
+                invoke.reject(code: .developerError, message: "Invalid product identifier: \(args.productId)")
+                return
+            }
+            let result: Product.PurchaseResult
+            if #available(iOS 17.0, *), let scene = self.foregroundWindowScene() {
+                result = try await product.purchase(confirmIn: scene)
+            } else {
+                result = try await product.purchase()
+            }
+            switch result {
+            case .success(let verification):
+                invoke.resolve(["purchase": await transactionUpdatePurchase(verification)])
+            case .userCancelled:
+                invoke.reject(code: .userCancelled, message: "User cancelled the purchase")
+            case .pending:
+                invoke.resolve([:])
+            @unknown default:
+                invoke.reject(code: .internalError, message: "Unknown purchase result")
+            }
+        }
+    }
+
+    /// Check that the device can make payments. Purchases are reported by
+    /// the buy commands and `startTransactionUpdates`.
+    @objc public func initialize(_ invoke: Invoke) {
+        guard SKPaymentQueue.canMakePayments() else {
+            invoke.reject(code: .featureNotSupported, message: "In-app purchases are disabled on this device")
+            return
+        }
+        invoke.resolve()
+    }
+
+    @objc public func buyNonConsumable(_ invoke: Invoke) {
+        guard let param = try? invoke.parseArgs(PurchaseParam.self) else {
+            invoke.reject(code: .developerError, message: "Missing purchase parameters")
+            return
+        }
+        buy(invoke, param)
+    }
+
+    /// Consumables are consumed once completed, so `autoConsume` doesn't
+    /// apply to the App Store.
+    @objc public func buyConsumable(_ invoke: Invoke) {
+        guard let args = try? invoke.parseArgs(BuyConsumableArgs.self) else {
+            invoke.reject(code: .developerError, message: "Missing purchase parameters")
+            return
+        }
+        buy(invoke, args.purchaseParam)
+    }
+
+    /// Buy a product. Resolves with `{ purchased: true, purchase }`, the
+    /// purchase the Rust core records and reports, or `{ purchased: false }`
+    /// while it awaits approval, e.g. with Ask to Buy, in which case
+    /// `Transaction.updates` delivers it later. Requires StoreKit 2.
+    private func buy(_ invoke: Invoke, _ param: PurchaseParam) {
+        guard #available(iOS 15.0, *) else {
+            invoke.reject(code: .featureNotSupported, message: "Purchases require iOS 15")
+            return
+        }
+        let productId = param.productDetails.id
+        presentOnMainActor(invoke) {
+            guard let product = try await Product.products(for: [productId]).first else {
+                invoke.reject(code: .developerError, message: "Invalid product identifier: \(productId)")
+                return
+            }
+            let result: Product.PurchaseResult
+            if #available(iOS 17.0, *), let scene = self.foregroundWindowScene() {
+                result = try await product.purchase(confirmIn: scene)
+            } else {
+                result = try await product.purchase()
+            }
+            switch result {
+            case .success(let verification):
+                invoke.resolve([
+                    "purchased": true,
+                    "purchase": await transactionUpdatePurchase(verification),
+                ])
+            case .userCancelled:
+                invoke.reject(code: .userCancelled, message: "User cancelled the purchase")
+            case .pending:
+                invoke.resolve(["purchased": false])
+            @unknown default:
+                invoke.reject(code: .internalError, message: "Unknown purchase result")
+            }
+        }
+    }
+
+    /// Finish the transaction of a purchase the Rust core checked was
+    /// reported. Purchases that are already finished are left alone.
+    @objc public func completePurchase(_ invoke: Invoke) {
+        guard let args = try? invoke.parseArgs(CompletePurchaseArgs.self),
+              let purchaseId = args.purchaseId,
+              let transactionId = UInt64(purchaseId)
+        else {
+            invoke.reject(code: .developerError, message: "Missing transaction ID")
+            return
+        }
+        guard #available(iOS 15.0, *) else {
+            IapPlugin.shared?.completeTransaction(purchaseId)
+            invoke.resolve()
+            return
+        }
+        Task {
+            for await result in Transaction.unfinished {
+                let transaction = result.unsafePayloadValue
+                if transaction.id == transactionId {
+                    await transaction.finish()
+                    break
+                }
+            }
+            invoke.resolve()
+        }
+    }
+
+    /// Restore the purchases of the App Store account as restored purchases.
+    ///
+    /// On iOS 15+ `AppStore.sync()` fetches the transactions of the signed in
+    /// account, which may ask the user to sign in, then the current
+    /// entitlements are returned. Older versions restore completed
+    /// transactions with StoreKit 1, for the application user name if given.
+    @objc public func restorePurchases(_ invoke: Invoke) {
+        let args = try? invoke.parseArgs(RestorePurchasesArgs.self)
+        guard #available(iOS 15.0, *) else {
+            Task {
+                switch await LegacyRestore().run(applicationUserName: args?.applicationUserName) {
+                case .success(let purchases):
+                    invoke.resolve(["purchases": purchases])
+                case .failure(let error):
+                    rejectRestore(invoke, error)
+                }
+            }
+            return
+        }
+        Task {
+            do {
+                try await AppStore.sync()
+            } catch {
+                rejectRestore(invoke, error)
+                return
+            }
+            invoke.resolve(["purchases": await currentEntitlementPurchases()])
+        }
+    }
+
+    private func rejectRestore(_ invoke: Invoke, _ error: Error) {
+        if isUserCancellation(error) {
+            invoke.reject(code: .userCancelled, message: "The user cancelled the restore")
+        } else {
+            invoke.reject(code: .internalError, message: error.localizedDescription)
+        }
+    }
+
+    /// Return the base64 encoded App Store receipt, refreshing it first if
+    /// requested and missing. The refresh runs in a task, so the main thread
+    /// isn't blocked while the user signs in.
+    @objc public func getAppReceipt(_ invoke: Invoke) {
+        let refresh = (try? invoke.parseArgs(GetAppReceiptArgs.self))?.refresh ?? false
+        if let receipt = appReceiptData() {
+            invoke.resolve(["receipt": receipt])
+            return
+        }
+        guard refresh else {
+            invoke.reject(code: .receiptUnavailable, message: "The app has no App Store receipt")
+            return
+        }
+        Task {
+            if let error = await ReceiptRefresh().run() {
+                let code: IapErrorCode = isUserCancellation(error) ? .userCancelled : .receiptUnavailable
+                invoke.reject(code: code, message: error.localizedDescription)
+                return
+            }
+            guard let receipt = appReceiptData() else {
+                invoke.reject(code: .receiptUnavailable, message: "The App Store issued no receipt")
+                return
+            }
+            invoke.resolve(["receipt": receipt])
+        }
+    }
+
+    /// Report the app transaction, mapped to an `AppLicense` by the Rust
+    /// core. An unverified transaction is reported too, without access.
+    /// Fetching it may ask the user to sign in, so it runs in a task.
+    @objc public func appLicense(_ invoke: Invoke) {
+        guard #available(iOS 16.0, *) else {
+            invoke.reject(code: .featureNotSupported, message: "App transactions require iOS 16")
+            return
+        }
+        Task {
+            let result: VerificationResult<AppTransaction>
+            do {
+                result = try await AppTransaction.shared
+            } catch {
+                let code: IapErrorCode = isUserCancellation(error) ? .userCancelled : .receiptUnavailable
+                invoke.reject(code: code, message: error.localizedDescription)
+                return
+            }
+            let (transaction, verified): (AppTransaction, Bool)
+            switch result {
+            case .verified(let signed):
+                (transaction, verified) = (signed, true)
+            case .unverified(let signed, _):
+                (transaction, verified) = (signed, false)
+            }
+            invoke.resolve([
+                "verified": verified,
+                "originalAppVersion": transaction.originalAppVersion,
+                "originalPurchaseDate": ISO8601DateFormatter().string(from: transaction.originalPurchaseDate),
+            ])
+        }
+    }
+
+    /// Check if the App Store supports a `StoreFeature` on this OS version.
+    /// Subscriptions and switching between those of a group are always
+    /// supported, price increase consent sheets from iOS 13.4 and StoreKit
+    /// messages from iOS 16.
+    @objc public func isFeatureSupported(_ invoke: Invoke) {
+        guard let args = try? invoke.parseArgs(IsFeatureSupportedArgs.self) else {
+            invoke.reject(code: .developerError, message: "Missing store feature")
+            return
+        }
+        let supported: Bool
+        switch args.feature {
+        case "subscriptions", "subscriptionsUpdate":
+            supported = true
+        case "priceChangeConfirmation":
+            if #available(iOS 13.4, *) { supported = true } else { supported = false }
+        case "inAppMessaging":
+            if #available(iOS 16.0, *) { supported = true } else { supported = false }
+        case "externalOffer":
+            if #available(iOS 17.4, *) { supported = hasExternalPurchaseEntitlement() } else { supported = false }
+        default:
+            invoke.reject(code: .featureNotSupported, message: "Unknown store feature: \(args.feature)")
+            return
+        }
+        invoke.resolve(["supported": supported])
+    }
+
+    /// Check if the app can send the user to an external purchase link
+    @objc public func externalPurchaseAvailable(_ invoke: Invoke) {
+        guard #available(iOS 17.4, *), hasExternalPurchaseEntitlement() else {
+            rejectExternalPurchaseUnsupported(invoke)
+            return
+        }
+        Task {
+            invoke.resolve(["available": await ExternalPurchase.canPresent])
+        }
+    }
+
+    /// Show the App Store notice sheet, then open the link if the user continues
+    @objc public func openExternalPurchaseLink(_ invoke: Invoke) {
+        guard #available(iOS 17.4, *), hasExternalPurchaseEntitlement() else {
+            rejectExternalPurchaseUnsupported(invoke)
+            return
+        }
+        guard let args = try? invoke.parseArgs(OpenExternalPurchaseLinkArgs.self),
+              let url = URL(string: args.url) else {
+            invoke.reject(code: .developerError, message: "Invalid external purchase link")
+            return
+        }
+        presentOnMainActor(invoke) {
+            switch try await ExternalPurchase.presentNoticeSheet() {
+            case .continuedWithExternalPurchaseToken(let token):
+                self.externalPurchaseToken = token
+                await UIApplication.shared.open(url)
+                invoke.resolve(["continued": true])
+            case .cancelled:
+                invoke.resolve(["continued": false])
+            @unknown default:
+                invoke.resolve(["continued": false])
+            }
+        }
+    }
+
+    /// Get the token of the external purchase the user last continued to
+    @objc public func externalPurchaseToken(_ invoke: Invoke) {
+        guard #available(iOS 17.4, *), hasExternalPurchaseEntitlement() else {
+            rejectExternalPurchaseUnsupported(invoke)
+            return
+        }
+        guard let token = externalPurchaseToken else {
+            invoke.reject(
+                code: .developerError,
+                message: "No external purchase token, call openExternalPurchaseLink first"
+            )
+            return
+        }
+        invoke.resolve(["token": token])
+    }
+
+    /// Report the native part of the diagnostics: whether purchases go
+    /// through StoreKit 2, which `initialize` picks from iOS 15
+    @objc public func diagnostics(_ invoke: Invoke) {
+        if #available(iOS 15.0, *) {
+            invoke.resolve(["storeKit2": true])
+        } else {
+            invoke.resolve(["storeKit2": false])
+        }
+    }
+
+    /// Report what tells App Store, TestFlight and development builds apart,
+    /// mapped to an `InstallSource` by the Rust core. TestFlight and Xcode
+    /// builds have a sandbox receipt, and only development and ad hoc builds
+    /// embed their provisioning profile
+    @objc public func installSource(_ invoke: Invoke) {
+        #if targetEnvironment(simulator)
+        let debuggable = true
+        #else
+        let debuggable = Bundle.main.path(forResource: "embedded", ofType: "mobileprovision") != nil
+        #endif
+        var installation: [String: Any] = ["debuggable": debuggable]
+        switch Bundle.main.appStoreReceiptURL?.lastPathComponent {
+        case "sandboxReceipt":
+            installation["environment"] = "sandbox"
+        case "receipt":
+            installation["environment"] = "production"
+        default:
+            break
+        }
+        invoke.resolve(installation)
+    }
+
+    /// Present the App Store page of an app in a sheet, or open it in the App
+    /// Store if the sheet can't load it. Resolves once the page is shown
+    @objc public func openStoreProductPage(_ invoke: Invoke) {
+        guard let args = try? invoke.parseArgs(OpenStoreProductPageArgs.self),
+              let appStoreId = Int(args.productId) else {
+            invoke.reject(code: .developerError, message: "Invalid App Store ID")
+            return
+        }
+        presentOnMainActor(invoke) {
+            guard let presenter = self.manager.viewController else {
+                self.openStorePageURL(appStoreId, invoke)
+                return
+            }
+            let storeViewController = SKStoreProductViewController()
+            storeViewController.delegate = self
+            storeViewController.loadProduct(
+                withParameters: [SKStoreProductParameterITunesItemIdentifier: NSNumber(value: appStoreId)]
+            ) { loaded, _ in
+                guard loaded else {
+                    self.openStorePageURL(appStoreId, invoke)
+                    return
+                }
+                presenter.present(storeViewController, animated: true) {
+                    invoke.resolve(["opened": true])
+                }
+            }
+        }
+    }
+
+    /// Present the App Store sheet where the user manages their subscriptions
+    /// in the foreground window scene. Resolves once the sheet is dismissed,
+    /// which the Rust core bounds with the sheet timeout. Requires iOS 15
+    @objc public func openManageSubscriptions(_ invoke: Invoke) {
+        guard #available(iOS 15.0, *) else {
+            invoke.reject(code: .featureNotSupported, message: "Managing subscriptions in the app needs iOS 15")
+            return
+        }
+        presentOnMainActor(invoke) {
+            guard let scene = self.foregroundWindowScene() else {
+                invoke.reject(code: .internalError, message: "No window scene in the foreground to present the subscriptions in")
+                return
+            }
+            try await AppStore.showManageSubscriptions(in: scene)
+            invoke.resolve()
+        }
+    }
+
+    /// Open the App Store page of an app in the App Store, or in Safari if the
+    /// App Store app was removed
+    private func openStorePageURL(_ appStoreId: Int, _ invoke: Invoke, schemes: [String] = ["itms-apps", "https"]) {
+        guard let scheme = schemes.first else {
+            invoke.reject(code: .featureNotSupported, message: "No app can open the App Store page of \(appStoreId)")
+            return
+        }
+        guard let url = URL(string: "\(scheme)://apps.apple.com/app/id\(appStoreId)") else {
+            openStorePageURL(appStoreId, invoke, schemes: Array(schemes.dropFirst()))
+            return
+        }
+        UIApplication.shared.open(url) { opened in
+            if opened {
+                invoke.resolve(["opened": true])
+            } else {
+                self.openStorePageURL(appStoreId, invoke, schemes: Array(schemes.dropFirst()))
+            }
+        }
+    }
+
+    private func hasExternalPurchaseEntitlement() -> Bool {
+        externalPurchaseInfoKeys.contains {
+            Bundle.main.object(forInfoDictionaryKey: $0) != nil
+        }
+    }
+
+    /// Run a command presenting StoreKit UI on the main actor, which it
+    /// needs to find a window scene, resolving the invoke whenever the UI is
+    /// done. Errors thrown meanwhile reject it.
+    private func presentOnMainActor(
+        _ invoke: Invoke,
+        _ present: @escaping @MainActor () async throws -> Void
+    ) {
+        Task { @MainActor in
+            do {
+                try await present()
+            } catch {
+                invoke.reject(
+                    code: isUserCancellation(error) ? .userCancelled : .internalError,
+                    message: error.localizedDescription,
+                    nativeCode: (error as? SKError)?.code.rawValue
+                )
+            }
+        }
+    }
+
+    /// The window scene in the foreground, for StoreKit to present in
+    @MainActor
+    private func foregroundWindowScene() -> UIWindowScene? {
+        UIApplication.shared.connectedScenes
+            .first { $0.activationState == .foregroundActive } as? UIWindowScene
+    }
+
+    private func rejectExternalPurchaseUnsupported(_ invoke: Invoke) {
+        invoke.reject(
+            code: .featureNotSupported,
+            message: "External purchase links need iOS 17.4 and an App Store external purchase entitlement"
+        )
+    }
+}
+
+/// Dismisses the App Store sheet of `openStoreProductPage` when the user closes it
+extension IapPlugin: SKStoreProductViewControllerDelegate {
+    public func productViewControllerDidFinish(_ viewController: SKStoreProductViewController) {
+        viewController.dismiss(animated: true)
+    }
 }
 
 // MARK: - Bridge Functions
@@ -556,6 +1657,9 @@ protocol PaymentManager {
     
     /// Get the App Store receipt data
     func getReceiptData() -> String?
+
+    /// Stop observing transactions
+    func stop()
 }
 
 // MARK: - StoreKit 1 Implementation
@@ -582,6 +1686,10 @@ class StoreKit1PaymentManager: NSObject, PaymentManager {
         super.init()
         SKPaymentQueue.default().add(self)
     }
+
+    func stop() {
+        SKPaymentQueue.default().remove(self)
+    }
     
     func queryProducts(_ productIds: Set<String>) {
         // Create new product request with given IDs
@@ -704,13 +1812,20 @@ class StoreKit1PaymentManager: NSObject, PaymentManager {
         return receiptData.base64EncodedString(options: [])
     }
     
-    private func reportError(type: String, code: Int8, message: String) {
+    private func reportError(type: String, code: Int8, message: String, errorCode: IapErrorCode? = nil, nativeCode: Int? = nil) {
+        var payload: [String: Any] = [
+            "code": code,
+            "message": message
+        ]
+        if let errorCode = errorCode {
+            payload["errorCode"] = errorCode.rawValue
+        }
+        if let nativeCode = nativeCode {
+            payload["nativeCode"] = nativeCode
+        }
         let error: [String: Any] = [
             "type": type,
-            "payload": [
-                "code": code,
-                "message": message
-            ]
+            "payload": payload
         ]
         
         do {
@@ -729,6 +1844,19 @@ class StoreKit1PaymentManager: NSObject, PaymentManager {
 /// SKProductsRequestDelegate implementation for StoreKit 1
 extension StoreKit1PaymentManager: SKProductsRequestDelegate {
     func productsRequest(_ request: SKProductsRequest, didReceive response: SKProductsResponse) {
+        // No valid product at all almost always means a configuration problem
+        // (bundle ID, agreements, or product IDs in App Store Connect)
+        if response.products.isEmpty && !response.invalidProductIdentifiers.isEmpty {
+            let ids = response.invalidProductIdentifiers.joined(separator: ", ")
+            reportError(
+                type: "ProductQuery",
+                code: 7,
+                message: "Invalid product identifiers: \(ids)",
+                errorCode: .developerError
+            )
+            return
+        }
+        
         var productDetails: [ProductDetails] = []
         
         for product in response.products {
@@ -808,6 +1936,16 @@ extension StoreKit1PaymentManager: SKPaymentTransactionObserver {
                 "applicationUserName": transaction.payment.applicationUsername ?? "" // User ID
             ]
             
+            // Flag cancellations with a stable code so Rust can report them as UserCancelled
+            if transaction.transactionState == .failed,
+               let error = transaction.error,
+               isUserCancellation(error) {
+                details["errorCode"] = IapErrorCode.userCancelled.rawValue
+            }
+            if let skError = transaction.error as? SKError {
+                details["nativeCode"] = skError.code.rawValue
+            }
+            
             // Add receipt data only for successful transactions
             if transaction.transactionState == .purchased || transaction.transactionState == .restored {
                 details["receiptData"] = receiptData // For server validation
@@ -846,7 +1984,6 @@ class StoreKit2PaymentManager: PaymentManager {
     private let onProductsUpdated: PaymentCallback
     private let onTransactionUpdated: PaymentCallback
     private let onError: PaymentCallback
-    private var task: Task<Void, Never>?
     
     init(
         onProductsUpdated: @escaping PaymentCallback,
@@ -856,37 +1993,11 @@ class StoreKit2PaymentManager: PaymentManager {
         self.onProductsUpdated = onProductsUpdated
         self.onTransactionUpdated = onTransactionUpdated
         self.onError = onError
-        setupTransactionListener()
-    }
-    
-    deinit {
-        task?.cancel()
-    }
-    
-    /// Set up async transaction listener for StoreKit 2
-    /// Handles real-time transaction updates and verification
-    ///
-    /// Flow:
-    /// 1. Create long-running async task
-    /// 2. Listen for transaction updates
-    /// 3. Process verified transactions
-    /// 4. Update purchase state
-    /// 5. Notify callbacks
-    ///
-    /// Note:
-    /// - Task runs until cancellation
-    /// - Handles background updates
-    /// - Automatic verification
-    private func setupTransactionListener() {
-        // Create background task for transaction monitoring
-        task = Task {
-            // Listen for transaction updates indefinitely
-            for await verificationResult in Transaction.updates {
-                // Process each transaction with verification
-                await handleVerificationResult(verificationResult)
-            }
-        }
     }
+
+    /// Nothing to stop: `Transaction.updates` is consumed by the single
+    /// listener of `IapPlugin`, which `endConnection` cancels.
+    func stop() {}
     
     func queryProducts(_ productIds: Set<String>) {
         // Create async task for StoreKit 2 product query
@@ -894,6 +2005,17 @@ class StoreKit2PaymentManager: PaymentManager {
             do {
                 // Query App Store for products using modern API
                 let products = try await Product.products(for: productIds)
+                // No valid product at all almost always means a configuration problem
+                if products.isEmpty && !productIds.isEmpty {
+                    let ids = productIds.sorted().joined(separator: ", ")
+                    reportError(
+                        type: "ProductQuery",
+                        code: 7,
+                        message: "Invalid product identifiers: \(ids)",
+                        errorCode: .developerError
+                    )
+                    return
+                }
                 var productDetails: [ProductDetails] = []
                 
                 // Convert StoreKit 2 products to our common format
@@ -906,7 +2028,8 @@ class StoreKit2PaymentManager: PaymentManager {
                         price: product.displayPrice,       // Formatted price string
                         rawPrice: product.price,          // Decimal price value
                         currencyCode: product.priceFormatStyle.currencyCode,  // ISO currency
-                        currencySymbol: product.priceFormatStyle.currencySymbol ?? "" // Currency symbol
+                        currencySymbol: product.priceFormatStyle.currencySymbol ?? "", // Currency symbol
+                        subscriptionGroupId: product.subscription?.subscriptionGroupID
                     ))
                 }
                 
@@ -925,8 +2048,8 @@ class StoreKit2PaymentManager: PaymentManager {
     }
     
     func initiatePurchase(productId: String, quantity: Int, applicationUserName: String, isConsumable: Bool) {
-        // Create async task for purchase flow
-        Task {
+        // The payment sheet is presented from the main actor
+        Task { @MainActor in
             do {
                 // Fetch product details using StoreKit 2
                 let products = try await Product.products(for: [productId])
@@ -944,14 +2067,19 @@ class StoreKit2PaymentManager: PaymentManager {
                 case .success(let verification):
                     await handleVerificationResult(verification)
                 case .userCancelled:
-                    reportError(type: "Purchase", code: 2, message: "User cancelled the purchase")
+                    reportError(type: "Purchase", code: 2, message: "User cancelled the purchase", errorCode: .userCancelled)
                 case .pending:
                     reportError(type: "Purchase", code: 3, message: "Purchase is pending")
                 @unknown default:
                     reportError(type: "Purchase", code: 4, message: "Unknown purchase result")
                 }
             } catch {
-                reportError(type: "Purchase", code: 5, message: error.localizedDescription)
+                let nativeCode = (error as? SKError)?.code.rawValue
+                if isUserCancellation(error) {
+                    reportError(type: "Purchase", code: 2, message: error.localizedDescription, errorCode: .userCancelled, nativeCode: nativeCode)
+                } else {
+                    reportError(type: "Purchase", code: 5, message: error.localizedDescription, nativeCode: nativeCode)
+                }
             }
         }
     }
@@ -1068,16 +2196,25 @@ class StoreKit2PaymentManager: PaymentManager {
     ///   "type": "<error_type>",
     ///   "payload": {
     ///     "code": <error_code>,
-    ///     "message": "<error_message>"
+    ///     "message": "<error_message>",
+    ///     "errorCode": "<IapErrorCode, when known>",
+    ///     "nativeCode": <SKError code, when known>
     ///   }
     /// }
-    private func reportError(type: String, code: Int8, message: String) {
+    private func reportError(type: String, code: Int8, message: String, errorCode: IapErrorCode? = nil, nativeCode: Int? = nil) {
+        var payload: [String: Any] = [
+            "code": code,
+            "message": message
+        ]
+        if let errorCode = errorCode {
+            payload["errorCode"] = errorCode.rawValue
+        }
+        if let nativeCode = nativeCode {
+            payload["nativeCode"] = nativeCode
+        }
         let error: [String: Any] = [
             "type": type,
-            "payload": [
-                "code": code,
-                "message": message
-            ]
+            "payload": payload
         ]
         
         do {
@@ -383,4 +383,39 @@ class IapPluginTests: XCTestCase {
         // Then
         XCTAssertFalse(result, "Buy should fail with invalid purchase param")
     }
-}
\ No newline at end of file
+    
+    // MARK: - Cancellation Mapping Tests
+    
+    /// StoreKit 1 payment sheet cancellation maps to userCancelled
+    func testPaymentCancelledIsUserCancellation() {
+        let error = SKError(.paymentCancelled)
+        XCTAssertTrue(isUserCancellation(error), "SKError.paymentCancelled should be a cancellation")
+    }
+    
+    /// StoreKit 2 cancellation maps to userCancelled
+    @available(iOS 15.0, *)
+    func testStoreKitUserCancelledIsUserCancellation() {
+        XCTAssertTrue(isUserCancellation(StoreKitError.userCancelled), "StoreKitError.userCancelled should be a cancellation")
+    }
+    
+    /// Cancelling the sign-in prompt is reported as an underlying AuthKit error
+    func testSignInCancellationIsUserCancellation() {
+        let authError = NSError(domain: "AKAuthenticationError", code: -7003)
+        let error = NSError(
+            domain: SKErrorDomain,
+            code: SKError.unknown.rawValue,
+            userInfo: [NSUnderlyingErrorKey: authError]
+        )
+        XCTAssertTrue(isUserCancellation(error), "Sign-in cancellation should be a cancellation")
+    }
+    
+    /// Other failures must not be mistaken for cancellations, whatever their message says
+    func testOtherErrorsAreNotUserCancellation() {
+        let error = NSError(
+            domain: SKErrorDomain,
+            code: SKError.paymentInvalid.rawValue,
+            userInfo: [NSLocalizedDescriptionKey: "Purchase cancelled"]
+        )
+        XCTAssertFalse(isUserCancellation(error), "Only cancellation codes should match")
+    }
+}
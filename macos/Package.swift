@@ -0,0 +1,29 @@
+// swift-tools-version:5.5
+// The swift-tools-version declares the minimum version of Swift required to build this package.
+
+import PackageDescription
+
+let package = Package(
+    name: "tauri-plugin-iap-macos",
+    platforms: [
+        .macOS(.v12),
+    ],
+    products: [
+        // StoreKit bridge linked into macOS desktop builds by the `mac-app-store` cargo feature.
+        .library(
+            name: "tauri-plugin-iap-macos",
+            type: .static,
+            targets: ["IapMacOS"]),
+    ],
+    dependencies: [
+        .package(url: "https://github.com/Brendonovich/swift-rs", from: "1.0.5")
+    ],
+    targets: [
+        .target(
+            name: "IapMacOS",
+            dependencies: [
+                .product(name: "SwiftRs", package: "swift-rs")
+            ],
+            path: "Sources/IapMacOS")
+    ]
+)
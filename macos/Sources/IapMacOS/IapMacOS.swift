@@ -0,0 +1,407 @@
+import AppKit
+import Foundation
+import Security
+import StoreKit
+import SwiftRs
+
+// MARK: - Bridge Conventions
+//
+// Every function is called from a Rust worker thread and blocks until StoreKit
+// answers. Results are JSON strings using the same models as the iOS plugin:
+//
+//   { "ok": <value> }
+//   { "error": { "code": "<IapErrorCode>", "message": "...", "nativeCode": <StoreKit code> } }
+
+/// Error codes shared with the Rust `IapErrorCode` enum.
+/// The raw values must match the strings produced by `IapErrorCode::as_str`.
+enum IapErrorCode: String {
+    case productQueryError
+    case purchaseError
+    case restoreError
+    case invalidPurchaseToken
+    case networkError
+    case userCancelled
+    case itemNotOwned
+    case receiptUnavailable
+    case developerError
+    case featureNotSupported
+    case internalError
+}
+
+/// Run an async StoreKit call to completion on a background task
+private func blocking<T>(_ operation: @escaping () async -> T) -> T {
+    let semaphore = DispatchSemaphore(value: 0)
+    var result: T?
+    Task.detached {
+        result = await operation()
+        semaphore.signal()
+    }
+    semaphore.wait()
+    return result!
+}
+
+private func json(_ object: [String: Any]) -> SRString {
+    guard let data = try? JSONSerialization.data(withJSONObject: object),
+          let string = String(data: data, encoding: .utf8)
+    else {
+        return SRString("{\"error\":{\"code\":\"internalError\",\"message\":\"Failed to encode the StoreKit response\"}}")
+    }
+    return SRString(string)
+}
+
+private func ok(_ value: Any) -> SRString {
+    return json(["ok": value])
+}
+
+private func failure(_ code: IapErrorCode, _ message: String, nativeCode: Int? = nil) -> SRString {
+    var error: [String: Any] = ["code": code.rawValue, "message": message]
+    if let nativeCode = nativeCode {
+        error["nativeCode"] = nativeCode
+    }
+    return json(["error": error])
+}
+
+/// Map a StoreKit error, keeping its code as the native code
+private func failure(_ fallback: IapErrorCode, _ error: Error) -> SRString {
+    switch error {
+    case let error as StoreKitError:
+        switch error {
+        case .userCancelled:
+            return failure(.userCancelled, error.localizedDescription)
+        case .networkError(let underlying):
+            return failure(.networkError, error.localizedDescription, nativeCode: underlying.code.rawValue)
+        default:
+            return failure(fallback, error.localizedDescription)
+        }
+    case let error as SKError:
+        let code: IapErrorCode = error.code == .paymentCancelled ? .userCancelled : fallback
+        return failure(code, error.localizedDescription, nativeCode: error.code.rawValue)
+    default:
+        return failure(fallback, error.localizedDescription)
+    }
+}
+
+private func productDetails(_ product: Product) -> [String: Any] {
+    var details: [String: Any] = [
+        "id": product.id,
+        "title": product.displayName,
+        "description": product.description,
+        "price": product.displayPrice,
+        "rawPrice": NSDecimalNumber(decimal: product.price).doubleValue,
+        "currencyCode": product.priceFormatStyle.currencyCode,
+        "currencySymbol": product.priceFormatStyle.locale.currencySymbol ?? ""
+    ]
+    if let groupID = product.subscription?.subscriptionGroupID {
+        details["subscriptionGroupId"] = groupID
+    }
+    return details
+}
+
+private func purchaseDetails(_ transaction: Transaction, jws: String, status: String) -> [String: Any] {
+    return [
+        "purchaseId": String(transaction.id),
+        "productId": transaction.productID,
+        "verificationData": [
+            "localVerificationData": jws,
+            "serverVerificationData": jws,
+            "source": "apple"
+        ],
+        "transactionDateMs": Int64((transaction.purchaseDate.timeIntervalSince1970 * 1000).rounded()),
+        "status": status,
+        "pendingCompletePurchase": true
+    ]
+}
+
+// MARK: - Bridge Functions
+
+/// Whether the app can use StoreKit: it must be a sandboxed Mac App Store
+/// build with a receipt, and the user must be allowed to make payments
+@_cdecl("iap_macos_is_available")
+func isAvailable() -> Bool {
+    let sandboxed = ProcessInfo.processInfo.environment["APP_SANDBOX_CONTAINER_ID"] != nil
+    let hasReceipt = Bundle.main.appStoreReceiptURL.map {
+        FileManager.default.fileExists(atPath: $0.path)
+    } ?? false
+    return sandboxed && hasReceipt && AppStore.canMakePayments
+}
+
+/// Query products, where `productIds` is a JSON array of identifiers
+@_cdecl("iap_macos_query_products")
+func queryProducts(productIds: SRString) -> SRString {
+    guard let data = productIds.toString().data(using: .utf8),
+          let ids = try? JSONDecoder().decode([String].self, from: data)
+    else {
+        return failure(.internalError, "Malformed product identifiers")
+    }
+    return blocking {
+        do {
+            let products = try await Product.products(for: ids)
+            let found = Set(products.map(\.id))
+            return ok([
+                "productDetails": products.map(productDetails),
+                "notFoundIds": ids.filter { !found.contains($0) }
+            ])
+        } catch {
+            return failure(.productQueryError, error)
+        }
+    }
+}
+
+/// Purchase a product, resolving with the purchase details once StoreKit
+/// verified the transaction, or null while the purchase is pending
+@_cdecl("iap_macos_purchase")
+func purchase(productId: SRString, applicationUserName: SRString) -> SRString {
+    let productId = productId.toString()
+    let applicationUserName = applicationUserName.toString()
+    return blocking {
+        do {
+            guard let product = try await Product.products(for: [productId]).first else {
+                return failure(.developerError, "Invalid product identifier: \(productId)")
+            }
+            var options: Set<Product.PurchaseOption> = []
+            if let token = UUID(uuidString: applicationUserName) {
+                options.insert(.appAccountToken(token))
+            }
+            switch try await product.purchase(options: options) {
+            case .success(let verification):
+                switch verification {
+                case .verified(let transaction):
+                    return ok(purchaseDetails(transaction, jws: verification.jwsRepresentation, status: "purchased"))
+                case .unverified(_, let error):
+                    return failure(.invalidPurchaseToken, error.localizedDescription)
+                }
+            case .userCancelled:
+                return failure(.userCancelled, "User cancelled the purchase")
+            case .pending:
+                // Resolved later through Transaction.updates, e.g. after Ask to Buy
+                return ok(NSNull())
+            @unknown default:
+                return failure(.purchaseError, "Unknown purchase result")
+            }
+        } catch {
+            return failure(.purchaseError, error)
+        }
+    }
+}
+
+/// Finish an unfinished transaction by its identifier
+@_cdecl("iap_macos_finish")
+func finish(transactionId: SRString) -> SRString {
+    let transactionId = transactionId.toString()
+    return blocking {
+        for await result in Transaction.unfinished {
+            if case .verified(let transaction) = result, String(transaction.id) == transactionId {
+                await transaction.finish()
+                return ok(true)
+            }
+        }
+        return failure(.itemNotOwned, "No unfinished transaction \(transactionId)")
+    }
+}
+
+/// Sync with the App Store and return the current entitlements as restored purchases
+@_cdecl("iap_macos_restore")
+func restore() -> SRString {
+    return blocking {
+        do {
+            try await AppStore.sync()
+        } catch {
+            return failure(.restoreError, error)
+        }
+        return ok(await currentEntitlements())
+    }
+}
+
+/// Return the current entitlements as restored purchases, without the
+/// App Store sync and its sign-in prompt
+@_cdecl("iap_macos_owned_purchases")
+func ownedPurchases() -> SRString {
+    return blocking {
+        return ok(await currentEntitlements())
+    }
+}
+
+private func currentEntitlements() async -> [[String: Any]] {
+    var purchases: [[String: Any]] = []
+    for await result in Transaction.currentEntitlements {
+        if case .verified(let transaction) = result {
+            purchases.append(purchaseDetails(transaction, jws: result.jwsRepresentation, status: "restored"))
+        }
+    }
+    return purchases
+}
+
+/// The App Store storefront country code
+@_cdecl("iap_macos_country_code")
+func countryCode() -> SRString {
+    return blocking {
+        guard let storefront = await Storefront.current else {
+            return failure(.networkError, "The App Store storefront is unavailable")
+        }
+        return ok(storefront.countryCode)
+    }
+}
+
+/// Runs an `SKReceiptRefreshRequest`, which may ask the user to sign in
+private final class ReceiptRefresh: NSObject, SKRequestDelegate {
+    private var request: SKReceiptRefreshRequest?
+    private var continuation: CheckedContinuation<Error?, Never>?
+
+    /// Refresh the receipt, returning the error if the request failed
+    func run() async -> Error? {
+        await withCheckedContinuation { continuation in
+            self.continuation = continuation
+            let request = SKReceiptRefreshRequest()
+            request.delegate = self
+            self.request = request
+            request.start()
+        }
+    }
+
+    func requestDidFinish(_ request: SKRequest) {
+        finish(nil)
+    }
+
+    func request(_ request: SKRequest, didFailWithError error: Error) {
+        finish(error)
+    }
+
+    private func finish(_ error: Error?) {
+        continuation?.resume(returning: error)
+        continuation = nil
+        request = nil
+    }
+}
+
+/// The base64 encoded receipt of the app, if it has one
+private func receiptData() -> String? {
+    guard let url = Bundle.main.appStoreReceiptURL,
+          let data = try? Data(contentsOf: url, options: .alwaysMapped)
+    else {
+        return nil
+    }
+    return data.base64EncodedString()
+}
+
+/// The base64 encoded App Store receipt, refreshed first if `refresh` is set
+/// and the app has none
+@_cdecl("iap_macos_app_receipt")
+func appReceipt(refresh: Bool) -> SRString {
+    if let receipt = receiptData() {
+        return ok(receipt)
+    }
+    guard refresh else {
+        return failure(.receiptUnavailable, "The app has no App Store receipt")
+    }
+    return blocking {
+        if let error = await ReceiptRefresh().run() {
+            return failure(.receiptUnavailable, error)
+        }
+        guard let receipt = receiptData() else {
+            return failure(.receiptUnavailable, "The App Store issued no receipt")
+        }
+        return ok(receipt)
+    }
+}
+
+/// The App Store environment the app was signed for: `xcode`, `sandbox` for
+/// TestFlight or `production`. Builds reaching StoreKit have a receipt, so
+/// they count as App Store installs when the app transaction is unavailable,
+/// e.g. before macOS 13
+@_cdecl("iap_macos_app_store_environment")
+func appStoreEnvironment() -> SRString {
+    guard #available(macOS 13.0, *) else {
+        return ok("production")
+    }
+    return blocking {
+        guard case .verified(let transaction)? = try? await AppTransaction.shared else {
+            return ok("production")
+        }
+        switch transaction.environment {
+        case .xcode:
+            return ok("xcode")
+        case .sandbox:
+            return ok("sandbox")
+        default:
+            return ok("production")
+        }
+    }
+}
+
+/// The app transaction, mapped to an `AppLicense` by the Rust core. An
+/// unverified transaction is reported too, without access
+@_cdecl("iap_macos_app_license")
+func appLicense() -> SRString {
+    guard #available(macOS 13.0, *) else {
+        return failure(.featureNotSupported, "App transactions require macOS 13")
+    }
+    return blocking {
+        let result: VerificationResult<AppTransaction>
+        do {
+            result = try await AppTransaction.shared
+        } catch {
+            return failure(.receiptUnavailable, error.localizedDescription)
+        }
+        let (transaction, verified): (AppTransaction, Bool)
+        switch result {
+        case .verified(let signed):
+            (transaction, verified) = (signed, true)
+        case .unverified(let signed, _):
+            (transaction, verified) = (signed, false)
+        }
+        return ok([
+            "verified": verified,
+            "originalAppVersion": transaction.originalAppVersion,
+            "originalPurchaseDate": ISO8601DateFormatter().string(from: transaction.originalPurchaseDate),
+        ])
+    }
+}
+
+/// Open the page of an app in the App Store app, or in the browser if no app
+/// handles App Store links
+@_cdecl("iap_macos_open_store_page")
+func openStorePage(appStoreId: SRString) -> SRString {
+    let appStoreId = appStoreId.toString()
+    for scheme in ["macappstore", "https"] {
+        if let url = URL(string: "\(scheme)://apps.apple.com/app/id\(appStoreId)"),
+           NSWorkspace.shared.open(url) {
+            return ok(true)
+        }
+    }
+    return failure(.featureNotSupported, "No app can open the App Store page of \(appStoreId)")
+}
+
+/// Check that the receipt at `path` is signed by a certificate chaining up to
+/// the Apple Root CA
+@_cdecl("iap_macos_verify_receipt_signature")
+func verifyReceiptSignature(path: SRString) -> SRString {
+    guard let data = FileManager.default.contents(atPath: path.toString()) else {
+        return failure(.invalidPurchaseToken, "Failed to read the receipt")
+    }
+    var decoder: CMSDecoder?
+    guard CMSDecoderCreate(&decoder) == errSecSuccess, let decoder = decoder else {
+        return failure(.internalError, "Failed to create a CMS decoder")
+    }
+    let updated = data.withUnsafeBytes { bytes in
+        CMSDecoderUpdateMessage(decoder, bytes.baseAddress!, bytes.count)
+    }
+    guard updated == errSecSuccess, CMSDecoderFinalizeMessage(decoder) == errSecSuccess else {
+        return failure(.invalidPurchaseToken, "Receipt is not a signed CMS message")
+    }
+
+    var signerStatus = CMSSignerStatus.unsigned
+    var trust: SecTrust?
+    var certificateStatus: OSStatus = errSecSuccess
+    let status = CMSDecoderCopySignerStatus(
+        decoder, 0, SecPolicyCreateBasicX509(), true, &signerStatus, &trust, &certificateStatus)
+    guard status == errSecSuccess, signerStatus == .valid, let trust = trust else {
+        return failure(.invalidPurchaseToken, "Receipt signature is invalid", nativeCode: Int(certificateStatus))
+    }
+    let chain = SecTrustCopyCertificateChain(trust) as? [SecCertificate] ?? []
+    guard let anchor = chain.last,
+          SecCertificateCopySubjectSummary(anchor) as String? == "Apple Root CA"
+    else {
+        return failure(.invalidPurchaseToken, "Receipt is not signed by Apple")
+    }
+    return ok(true)
+}
@@ -2,10 +2,41 @@ import { readFileSync } from 'node:fs'
 import { dirname, join } from 'node:path'
 import { cwd } from 'node:process'
 import typescript from '@rollup/plugin-typescript'
+import { nodeResolve } from '@rollup/plugin-node-resolve'
+import terser from '@rollup/plugin-terser'
 
 const pkg = JSON.parse(readFileSync(join(cwd(), 'package.json'), 'utf8'))
+// Error codes of the Rust crate, which its tests check against `IapErrorCode`.
+const errorCodes = JSON.parse(readFileSync(join(cwd(), 'guest-js/error-codes.json'), 'utf8'))
 
-export default {
+// Fails the build when `IapErrorCode` or the retryable codes in index.ts
+// drift from error-codes.json.
+function checkErrorCodes() {
+  return {
+    name: 'check-error-codes',
+    transform(code, id) {
+      if (!id.endsWith('index.ts')) {
+        return null
+      }
+      const members = new Map(
+        [...(code.match(/export enum IapErrorCode \{([^}]*)\}/)?.[1] ?? '').matchAll(/(\w+) = ["'](\w+)["']/g)]
+          .map(([, name, value]) => [name, value])
+      )
+      const retryable = [...(code.match(/const RETRYABLE_CODES[^=]*= \[([^\]]*)\]/)?.[1] ?? '').matchAll(/IapErrorCode\.(\w+)/g)]
+        .map(([, name]) => members.get(name))
+      const same = (a, b) => a.length === b.length && a.every((value) => b.includes(value))
+      if (!same([...members.values()], errorCodes.codes)) {
+        this.error(`IapErrorCode doesn't match guest-js/error-codes.json: ${[...members.values()]}`)
+      }
+      if (!same(retryable, errorCodes.retryable)) {
+        this.error(`RETRYABLE_CODES doesn't match guest-js/error-codes.json: ${retryable}`)
+      }
+      return null
+    }
+  }
+}
+
+export default [{
   input: 'guest-js/index.ts',
   output: [
     {
@@ -18,6 +49,7 @@ export default {
     }
   ],
   plugins: [
+    checkErrorCodes(),
     typescript({
       declaration: true,
       declarationDir: dirname(pkg.exports.import)
@@ -28,4 +60,18 @@ export default {
     ...Object.keys(pkg.dependencies || {}),
     ...Object.keys(pkg.peerDependencies || {})
   ]
-}
+}, {
+  // Injected by build.rs as `window.__TAURI__.iap` when `withGlobalTauri` is set.
+  input: 'guest-js/index.ts',
+  output: {
+    format: 'iife',
+    name: '__TAURI_PLUGIN_IAP__',
+    banner: "if ('__TAURI__' in window) {",
+    footer: "Object.defineProperty(window.__TAURI__, 'iap', { value: __TAURI_PLUGIN_IAP__ }) }",
+    file: 'api-iife.js'
+  },
+  plugins: [typescript(), nodeResolve(), terser({ keep_classnames: true })],
+  onwarn: (warning) => {
+    throw Object.assign(new Error(), warning)
+  }
+}]
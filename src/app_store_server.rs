@@ -0,0 +1,679 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use p256::pkcs8::DecodePrivateKey;
+use serde::Deserialize;
+use tauri::http::{header, Method, Request, Response, StatusCode};
+
+use crate::http_client::{encode, parse, retry_after, send, HttpClient};
+use crate::jws::{
+    date, in_environment, invalid, verified_payload, verify, APPLE_ROOT_CA_G3_SHA256,
+};
+use crate::{Error, Result, TransactionEnvironment, VerifiedTransaction};
+
+const PRODUCTION_URL: &str = "https://api.storekit.itunes.apple.com";
+const SANDBOX_URL: &str = "https://api.storekit-sandbox.itunes.apple.com";
+/// Lifetime of the API tokens, which Apple caps at an hour.
+const TOKEN_LIFETIME_SECS: u64 = 300;
+
+/// An App Store Connect in-app purchase API key, from Users and Access >
+/// Integrations > In-App Purchase.
+#[derive(Clone)]
+pub struct AppStoreServerCredentials {
+    /// Issuer ID shown above the keys
+    pub issuer_id: String,
+    /// ID of the key
+    pub key_id: String,
+    /// Contents of the downloaded `.p8` key file
+    pub private_key: String,
+    /// Bundle identifier of the app
+    pub bundle_id: String,
+    /// `Production` or `Sandbox`, the App Store Server API environment to
+    /// call
+    pub environment: TransactionEnvironment,
+}
+
+impl fmt::Debug for AppStoreServerCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AppStoreServerCredentials")
+            .field("issuer_id", &self.issuer_id)
+            .field("key_id", &self.key_id)
+            .field("private_key", &"<redacted>")
+            .field("bundle_id", &self.bundle_id)
+            .field("environment", &self.environment)
+            .finish()
+    }
+}
+
+/// `status` of an auto-renewable subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppleSubscriptionState {
+    /// Entitled, and renewing unless turned off
+    Active,
+    /// Expired, not entitled
+    Expired,
+    /// Renewal failed and the App Store is retrying it, not entitled
+    BillingRetry,
+    /// Renewal failed, still entitled during the grace period
+    GracePeriod,
+    /// Refunded or revoked, e.g. by Family Sharing, not entitled
+    Revoked,
+    /// A status added to the API since
+    Unknown,
+}
+
+/// Renewal information of an auto-renewable subscription.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppleRenewalInfo {
+    /// Product identifier of the current period
+    pub product_id: String,
+    /// Product identifier the subscription renews to (optional)
+    pub auto_renew_product_id: Option<String>,
+    /// Whether the subscription renews at the end of the period
+    pub auto_renew: bool,
+    /// Why the subscription expired, Apple's `expirationIntent` (optional)
+    pub expiration_intent: Option<i32>,
+    /// ISO datetime string when the grace period ends (optional)
+    pub grace_period_expiration_date: Option<String>,
+    /// Whether the App Store is retrying a failed renewal
+    pub in_billing_retry: bool,
+    /// ISO datetime string of the next renewal (optional)
+    pub renewal_date: Option<String>,
+}
+
+/// Latest state of a subscription from [`get_subscription_statuses`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppleSubscriptionStatus {
+    /// Subscription group the subscription belongs to
+    pub subscription_group_id: String,
+    /// Original transaction identifier of the subscription
+    pub original_transaction_id: String,
+    /// Whether the subscription is active, expired...
+    pub state: AppleSubscriptionState,
+    /// Latest transaction of the subscription
+    pub transaction: VerifiedTransaction,
+    /// Renewal information of the subscription (optional)
+    pub renewal_info: Option<AppleRenewalInfo>,
+}
+
+/// Looks up a transaction with the App Store Server API (`Get Transaction
+/// Info`), verifying the signed transaction it returns.
+///
+/// # Errors
+///
+/// - `Error::InvalidPurchaseToken` if the App Store doesn't know the
+///   transaction, or its signed data fails verification.
+/// - `Error::InvalidConfig` if the credentials are malformed or rejected.
+/// - `Error::RateLimited` if Apple throttled the request.
+/// - `Error::NetworkError` if no response was received, or the API is
+///   unavailable.
+pub async fn get_transaction_info(
+    client: &dyn HttpClient,
+    transaction_id: &str,
+    credentials: &AppStoreServerCredentials,
+) -> Result<VerifiedTransaction> {
+    transaction_info(
+        client,
+        transaction_id,
+        credentials,
+        &APPLE_ROOT_CA_G3_SHA256,
+    )
+    .await
+}
+
+/// [`get_transaction_info`], with the signed data ending with the root
+/// certificate whose SHA-256 fingerprint is `root_sha256`.
+async fn transaction_info(
+    client: &dyn HttpClient,
+    transaction_id: &str,
+    credentials: &AppStoreServerCredentials,
+    root_sha256: &[u8; 32],
+) -> Result<VerifiedTransaction> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct TransactionInfoResponse {
+        signed_transaction_info: String,
+    }
+
+    let path = format!("/inApps/v1/transactions/{}", encode(transaction_id));
+    let response: TransactionInfoResponse = get(client, &path, credentials).await?;
+    verify_transaction(&response.signed_transaction_info, credentials, root_sha256)
+}
+
+/// Looks up the statuses of every subscription in the groups of a
+/// subscription with the App Store Server API (`Get All Subscription
+/// Statuses`), verifying the signed data it returns.
+///
+/// # Errors
+///
+/// Fails like [`get_transaction_info`].
+pub async fn get_subscription_statuses(
+    client: &dyn HttpClient,
+    original_transaction_id: &str,
+    credentials: &AppStoreServerCredentials,
+) -> Result<Vec<AppleSubscriptionStatus>> {
+    subscription_statuses(
+        client,
+        original_transaction_id,
+        credentials,
+        &APPLE_ROOT_CA_G3_SHA256,
+    )
+    .await
+}
+
+/// [`get_subscription_statuses`], with the signed data ending with the root
+/// certificate whose SHA-256 fingerprint is `root_sha256`.
+async fn subscription_statuses(
+    client: &dyn HttpClient,
+    original_transaction_id: &str,
+    credentials: &AppStoreServerCredentials,
+    root_sha256: &[u8; 32],
+) -> Result<Vec<AppleSubscriptionStatus>> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct StatusResponse {
+        #[serde(default)]
+        data: Vec<SubscriptionGroup>,
+    }
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct SubscriptionGroup {
+        subscription_group_identifier: String,
+        #[serde(default)]
+        last_transactions: Vec<LastTransaction>,
+    }
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct LastTransaction {
+        status: i32,
+        original_transaction_id: String,
+        signed_transaction_info: String,
+        signed_renewal_info: Option<String>,
+    }
+
+    let path = format!(
+        "/inApps/v1/subscriptions/{}",
+        encode(original_transaction_id)
+    );
+    let response: StatusResponse = get(client, &path, credentials).await?;
+    let mut statuses = Vec::new();
+    for group in response.data {
+        for last in group.last_transactions {
+            statuses.push(AppleSubscriptionStatus {
+                subscription_group_id: group.subscription_group_identifier.clone(),
+                original_transaction_id: last.original_transaction_id,
+                state: match last.status {
+                    1 => AppleSubscriptionState::Active,
+                    2 => AppleSubscriptionState::Expired,
+                    3 => AppleSubscriptionState::BillingRetry,
+                    4 => AppleSubscriptionState::GracePeriod,
+                    5 => AppleSubscriptionState::Revoked,
+                    _ => AppleSubscriptionState::Unknown,
+                },
+                transaction: verify_transaction(
+                    &last.signed_transaction_info,
+                    credentials,
+                    root_sha256,
+                )?,
+                renewal_info: last
+                    .signed_renewal_info
+                    .as_deref()
+                    .map(|jws| verify_renewal_info(jws, root_sha256))
+                    .transpose()?,
+            });
+        }
+    }
+    Ok(statuses)
+}
+
+/// Sends an authorized GET request to the API and deserializes the response.
+async fn get<T: serde::de::DeserializeOwned>(
+    client: &dyn HttpClient,
+    path: &str,
+    credentials: &AppStoreServerCredentials,
+) -> Result<T> {
+    let base_url = match credentials.environment {
+        TransactionEnvironment::Production => PRODUCTION_URL,
+        TransactionEnvironment::Sandbox => SANDBOX_URL,
+        environment => {
+            return Err(Error::InvalidConfig(format!(
+                "The App Store Server API has no {environment:?} environment"
+            )))
+        }
+    };
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(format!("{base_url}{path}"))
+        .header(
+            header::AUTHORIZATION,
+            format!("Bearer {}", api_token(credentials)?),
+        )
+        .header(header::ACCEPT, "application/json")
+        .body(Vec::new())
+        .map_err(|e| Error::InternalError(format!("Invalid App Store Server API request: {e}")))?;
+    let response = send(client, request).await?;
+    match response.status() {
+        status if status.is_success() => parse(&response),
+        StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND => {
+            Err(Error::InvalidPurchaseToken(format!(
+                "The App Store doesn't know the transaction: {}",
+                api_message(&response)
+            )))
+        }
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(Error::InvalidConfig(format!(
+            "The App Store Server API rejected the credentials: {}",
+            api_message(&response)
+        ))),
+        StatusCode::TOO_MANY_REQUESTS => Err(Error::RateLimited {
+            retry_after: retry_after(&response),
+        }),
+        status if status.is_server_error() => Err(Error::NetworkError(format!(
+            "App Store Server API unavailable ({status}): {}",
+            api_message(&response)
+        ))),
+        status => Err(Error::InternalError(format!(
+            "App Store Server API failed ({status}): {}",
+            api_message(&response)
+        ))),
+    }
+}
+
+/// A JWT authorizing API requests, signed with the API key.
+fn api_token(credentials: &AppStoreServerCredentials) -> Result<String> {
+    let key = SigningKey::from_pkcs8_pem(credentials.private_key.trim())
+        .map_err(|e| Error::InvalidConfig(format!("Invalid App Store Server API key: {e}")))?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let header = URL_SAFE_NO_PAD.encode(
+        serde_json::json!({ "alg": "ES256", "kid": credentials.key_id, "typ": "JWT" }).to_string(),
+    );
+    let claims = URL_SAFE_NO_PAD.encode(
+        serde_json::json!({
+            "iss": credentials.issuer_id,
+            "iat": now,
+            "exp": now + TOKEN_LIFETIME_SECS,
+            "aud": "appstoreconnect-v1",
+            "bid": credentials.bundle_id,
+        })
+        .to_string(),
+    );
+    let signing_input = format!("{header}.{claims}");
+    // ES256 signatures are the raw big endian `r` and `s`, with a nonce
+    // derived as in RFC 6979.
+    let signature: Signature = key.sign(signing_input.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(signature.to_bytes());
+    Ok(format!("{signing_input}.{signature}"))
+}
+
+/// Verifies a signed transaction, which must belong to the app and the
+/// environment called.
+fn verify_transaction(
+    jws: &str,
+    credentials: &AppStoreServerCredentials,
+    root_sha256: &[u8; 32],
+) -> Result<VerifiedTransaction> {
+    let transaction = verify(jws, root_sha256).map_err(invalid)?;
+    let transaction = in_environment(transaction, credentials.environment)?;
+    if transaction.bundle_id != credentials.bundle_id {
+        return Err(Error::InvalidPurchaseToken(format!(
+            "The transaction belongs to {}, not {}",
+            transaction.bundle_id, credentials.bundle_id
+        )));
+    }
+    Ok(transaction)
+}
+
+fn verify_renewal_info(jws: &str, root_sha256: &[u8; 32]) -> Result<AppleRenewalInfo> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Claims {
+        product_id: String,
+        auto_renew_product_id: Option<String>,
+        #[serde(default)]
+        auto_renew_status: i32,
+        expiration_intent: Option<i32>,
+        grace_period_expires_date: Option<i64>,
+        #[serde(default)]
+        is_in_billing_retry_period: bool,
+        renewal_date: Option<i64>,
+    }
+
+    let claims: Claims = verified_payload(jws, root_sha256).map_err(|reason| {
+        Error::InvalidPurchaseToken(format!("Invalid renewal info JWS: {reason}"))
+    })?;
+    Ok(AppleRenewalInfo {
+        product_id: claims.product_id,
+        auto_renew_product_id: claims.auto_renew_product_id,
+        auto_renew: claims.auto_renew_status == 1,
+        expiration_intent: claims.expiration_intent,
+        grace_period_expiration_date: claims.grace_period_expires_date.map(date),
+        in_billing_retry: claims.is_in_billing_retry_period,
+        renewal_date: claims.renewal_date.map(date),
+    })
+}
+
+/// The `errorMessage` of an API error response, or its body.
+fn api_message(response: &Response<Vec<u8>>) -> String {
+    serde_json::from_slice::<serde_json::Value>(response.body())
+        .ok()
+        .and_then(|body| body["errorMessage"].as_str().map(str::to_string))
+        .unwrap_or_else(|| String::from_utf8_lossy(response.body()).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use base64::engine::general_purpose::STANDARD;
+    use p256::ecdsa::{signature::Verifier, VerifyingKey};
+    use serde_json::Value;
+    use sha2::{Digest, Sha256};
+    use tauri::async_runtime::block_on;
+
+    use super::*;
+    use crate::http_client::mock::MockClient;
+    use crate::IapErrorCode;
+
+    // Responses shaped like Apple's examples, whose signed data is signed by
+    // the test chain of `tests/fixtures/jws/vectors.json`.
+    const TRANSACTION_INFO: &str =
+        include_str!("../tests/fixtures/app_store_server/transaction_info.json");
+    const SUBSCRIPTION_STATUSES: &str =
+        include_str!("../tests/fixtures/app_store_server/subscription_statuses.json");
+    const ERROR: &str = include_str!("../tests/fixtures/app_store_server/error.json");
+    const PRIVATE_KEY: &str =
+        include_str!("../tests/fixtures/app_store_server/SubscriptionKey_TEST123456.p8");
+
+    fn credentials(environment: TransactionEnvironment) -> AppStoreServerCredentials {
+        AppStoreServerCredentials {
+            issuer_id: "57246542-96fe-1a63-e053-0824d011072a".into(),
+            key_id: "TEST123456".into(),
+            private_key: PRIVATE_KEY.into(),
+            bundle_id: "com.example.app".into(),
+            environment,
+        }
+    }
+
+    /// The SHA-256 fingerprint of the root certificate of the test chain.
+    fn test_root() -> [u8; 32] {
+        let response: Value = serde_json::from_str(TRANSACTION_INFO).unwrap();
+        let jws = response["signedTransactionInfo"].as_str().unwrap();
+        let header = URL_SAFE_NO_PAD
+            .decode(jws.split('.').next().unwrap())
+            .unwrap();
+        let header: Value = serde_json::from_slice(&header).unwrap();
+        let root = STANDARD.decode(header["x5c"][2].as_str().unwrap()).unwrap();
+        Sha256::digest(root).into()
+    }
+
+    fn segment(jwt: &str, index: usize) -> Value {
+        let segment = URL_SAFE_NO_PAD
+            .decode(jwt.split('.').nth(index).unwrap())
+            .unwrap();
+        serde_json::from_slice(&segment).unwrap()
+    }
+
+    #[test]
+    fn signs_api_tokens_with_the_key() {
+        let credentials = credentials(TransactionEnvironment::Sandbox);
+        let token = api_token(&credentials).unwrap();
+
+        assert_eq!(
+            segment(&token, 0),
+            serde_json::json!({ "alg": "ES256", "kid": "TEST123456", "typ": "JWT" })
+        );
+        let claims = segment(&token, 1);
+        assert_eq!(claims["iss"], "57246542-96fe-1a63-e053-0824d011072a");
+        assert_eq!(claims["aud"], "appstoreconnect-v1");
+        assert_eq!(claims["bid"], "com.example.app");
+        assert_eq!(
+            claims["exp"].as_u64().unwrap() - claims["iat"].as_u64().unwrap(),
+            TOKEN_LIFETIME_SECS
+        );
+
+        let (signing_input, signature) = token.rsplit_once('.').unwrap();
+        let signature = URL_SAFE_NO_PAD.decode(signature).unwrap();
+        let key = SigningKey::from_pkcs8_pem(PRIVATE_KEY).unwrap();
+        VerifyingKey::from(&key)
+            .verify(
+                signing_input.as_bytes(),
+                &Signature::from_slice(&signature).unwrap(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_keys() {
+        for private_key in ["", "not a key", &PRIVATE_KEY.replace('A', "B")] {
+            let credentials = AppStoreServerCredentials {
+                private_key: private_key.into(),
+                ..credentials(TransactionEnvironment::Sandbox)
+            };
+            let client = MockClient::default();
+
+            let error = block_on(get_transaction_info(&client, "1", &credentials)).unwrap_err();
+            assert_eq!(error.code(), IapErrorCode::InvalidConfig, "{error}");
+            assert!(client.requests.lock().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn gets_transaction_info() {
+        let client = MockClient::answering(StatusCode::OK, TRANSACTION_INFO);
+
+        let transaction = block_on(transaction_info(
+            &client,
+            "2000000456789012",
+            &credentials(TransactionEnvironment::Sandbox),
+            &test_root(),
+        ))
+        .unwrap();
+
+        let request = client.request();
+        assert_eq!(request.method(), Method::GET);
+        assert_eq!(
+            request.uri(),
+            "https://api.storekit-sandbox.itunes.apple.com/inApps/v1/transactions/2000000456789012"
+        );
+        let authorization = request.headers()[header::AUTHORIZATION].to_str().unwrap();
+        assert!(authorization.starts_with("Bearer ey"), "{authorization}");
+        assert_eq!(request.headers()[header::ACCEPT], "application/json");
+
+        assert_eq!(transaction.transaction_id, "2000000456789012");
+        assert_eq!(transaction.original_transaction_id, "2000000123456789");
+        assert_eq!(transaction.product_id, "pro_monthly");
+        assert_eq!(transaction.purchase_date, "2025-06-01T00:00:00Z");
+        assert_eq!(
+            transaction.expiration_date.as_deref(),
+            Some("2025-07-01T00:00:00Z")
+        );
+        assert_eq!(
+            transaction.app_account_token.as_deref(),
+            Some("7e3fb20b-4cdb-47cc-936d-99d65f608138")
+        );
+        assert_eq!(transaction.environment, TransactionEnvironment::Sandbox);
+    }
+
+    #[test]
+    fn encodes_transaction_ids_in_the_path() {
+        let client = MockClient::answering(StatusCode::NOT_FOUND, ERROR);
+
+        let _ = block_on(get_transaction_info(
+            &client,
+            "../1?x",
+            &credentials(TransactionEnvironment::Production),
+        ));
+
+        assert_eq!(
+            client.request().uri(),
+            "https://api.storekit.itunes.apple.com/inApps/v1/transactions/..%2F1%3Fx"
+        );
+    }
+
+    #[test]
+    fn rejects_transactions_of_other_apps_and_environments() {
+        let client = MockClient::answering(StatusCode::OK, TRANSACTION_INFO);
+        let other_app = AppStoreServerCredentials {
+            bundle_id: "com.example.other".into(),
+            ..credentials(TransactionEnvironment::Sandbox)
+        };
+        let error = block_on(transaction_info(&client, "1", &other_app, &test_root())).unwrap_err();
+        assert!(
+            error.to_string().contains("belongs to com.example.app"),
+            "{error}"
+        );
+
+        let client = MockClient::answering(StatusCode::OK, TRANSACTION_INFO);
+        let production = credentials(TransactionEnvironment::Production);
+        let error =
+            block_on(transaction_info(&client, "1", &production, &test_root())).unwrap_err();
+        assert_eq!(error.code(), IapErrorCode::InvalidPurchaseToken);
+        assert!(error.to_string().contains("not Production"), "{error}");
+    }
+
+    #[test]
+    fn rejects_data_not_signed_by_apple() {
+        let client = MockClient::answering(StatusCode::OK, TRANSACTION_INFO);
+
+        let error = block_on(get_transaction_info(
+            &client,
+            "1",
+            &credentials(TransactionEnvironment::Sandbox),
+        ))
+        .unwrap_err();
+
+        assert_eq!(error.code(), IapErrorCode::InvalidPurchaseToken);
+        assert!(error.to_string().contains("Apple Root CA - G3"), "{error}");
+    }
+
+    #[test]
+    fn gets_subscription_statuses() {
+        let client = MockClient::answering(StatusCode::OK, SUBSCRIPTION_STATUSES);
+
+        let statuses = block_on(subscription_statuses(
+            &client,
+            "2000000123456789",
+            &credentials(TransactionEnvironment::Sandbox),
+            &test_root(),
+        ))
+        .unwrap();
+
+        assert_eq!(
+            client.request().uri(),
+            "https://api.storekit-sandbox.itunes.apple.com/inApps/v1/subscriptions/2000000123456789"
+        );
+        assert_eq!(statuses.len(), 2);
+        let [active, expired] = statuses.as_slice() else {
+            unreachable!()
+        };
+
+        assert_eq!(active.subscription_group_id, "21345678");
+        assert_eq!(active.original_transaction_id, "2000000123456789");
+        assert_eq!(active.state, AppleSubscriptionState::Active);
+        assert_eq!(active.transaction.product_id, "pro_monthly");
+        assert_eq!(
+            active.renewal_info,
+            Some(AppleRenewalInfo {
+                product_id: "pro_monthly".into(),
+                auto_renew_product_id: Some("pro_yearly".into()),
+                auto_renew: true,
+                expiration_intent: None,
+                grace_period_expiration_date: None,
+                in_billing_retry: false,
+                renewal_date: Some("2025-07-01T00:00:00Z".into()),
+            })
+        );
+
+        assert_eq!(expired.state, AppleSubscriptionState::Expired);
+        assert_eq!(expired.transaction.product_id, "pro_weekly");
+        let renewal = expired.renewal_info.as_ref().unwrap();
+        assert!(!renewal.auto_renew);
+        assert_eq!(renewal.expiration_intent, Some(1));
+        assert_eq!(renewal.renewal_date, None);
+    }
+
+    #[test]
+    fn reads_empty_and_unknown_statuses() {
+        let client = MockClient::answering(StatusCode::OK, r#"{ "environment": "Sandbox" }"#);
+        let credentials = credentials(TransactionEnvironment::Sandbox);
+        assert!(block_on(subscription_statuses(
+            &client,
+            "1",
+            &credentials,
+            &test_root()
+        ))
+        .unwrap()
+        .is_empty());
+
+        let unknown = SUBSCRIPTION_STATUSES.replace("\"status\": 1", "\"status\": 9");
+        let client = MockClient::answering(StatusCode::OK, &unknown);
+        let statuses = block_on(subscription_statuses(
+            &client,
+            "1",
+            &credentials,
+            &test_root(),
+        ))
+        .unwrap();
+        assert_eq!(statuses[0].state, AppleSubscriptionState::Unknown);
+    }
+
+    #[test]
+    fn maps_api_errors() {
+        let credentials = credentials(TransactionEnvironment::Sandbox);
+        let error = |status: StatusCode, headers: &[(&str, &str)], body: &str| {
+            let client = MockClient::default();
+            client.respond(status, headers, body);
+            block_on(get_transaction_info(&client, "1", &credentials)).unwrap_err()
+        };
+
+        let not_found = error(StatusCode::NOT_FOUND, &[], ERROR);
+        assert_eq!(not_found.code(), IapErrorCode::InvalidPurchaseToken);
+        assert!(not_found.to_string().contains("Transaction id not found."));
+        assert_eq!(
+            error(StatusCode::UNAUTHORIZED, &[], "").code(),
+            IapErrorCode::InvalidConfig
+        );
+        assert!(matches!(
+            error(StatusCode::TOO_MANY_REQUESTS, &[("retry-after", "30")], ""),
+            Error::RateLimited { retry_after: Some(delay) } if delay == Duration::from_secs(30)
+        ));
+        let unavailable = error(StatusCode::SERVICE_UNAVAILABLE, &[], "down");
+        assert_eq!(unavailable.code(), IapErrorCode::NetworkError);
+        assert!(unavailable.to_string().contains("down"));
+        assert_eq!(
+            error(StatusCode::IM_A_TEAPOT, &[], "").code(),
+            IapErrorCode::InternalError
+        );
+        assert_eq!(
+            error(StatusCode::OK, &[], "{ not json").code(),
+            IapErrorCode::InternalError
+        );
+
+        let client = MockClient::default();
+        client
+            .responses
+            .lock()
+            .unwrap()
+            .push_back(Err(std::io::ErrorKind::TimedOut.into()));
+        let error = block_on(get_transaction_info(&client, "1", &credentials)).unwrap_err();
+        assert_eq!(error.code(), IapErrorCode::NetworkError);
+    }
+
+    #[test]
+    fn rejects_environments_without_an_api() {
+        let client = MockClient::default();
+
+        let error = block_on(get_transaction_info(
+            &client,
+            "1",
+            &credentials(TransactionEnvironment::Xcode),
+        ))
+        .unwrap_err();
+
+        assert_eq!(error.code(), IapErrorCode::InvalidConfig);
+        assert!(client.requests.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,235 @@
+use std::future::Future;
+use std::panic::resume_unwind;
+use std::time::{Duration, SystemTime};
+
+use tauri::Runtime;
+
+use crate::models::*;
+use crate::{Diagnostics, Iap, Result, RetryPolicy};
+
+impl<R: Runtime> Iap<R> {
+    /// Blocking versions of the async store methods, for synchronous code
+    /// such as a `setup` hook.
+    ///
+    /// ```no_run
+    /// # fn setup<R: tauri::Runtime>(app: &tauri::App<R>) -> tauri_plugin_iap::Result<()> {
+    /// use tauri_plugin_iap::IapExt;
+    ///
+    /// let iap = app.iap().blocking();
+    /// iap.initialize()?;
+    /// let response = iap.query_product_details(vec!["premium".into()])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn blocking(&self) -> BlockingIap<'_, R> {
+        BlockingIap { iap: self }
+    }
+}
+
+/// The async store methods of [`Iap`], each blocking the calling thread
+/// until the store answers. Created with [`Iap::blocking`].
+///
+/// The calls are awaited with `tauri::async_runtime::block_on`, which panics
+/// when called from a thread already driving an async runtime, e.g. inside
+/// an async command or a spawned task. From such threads the call is awaited
+/// on a separate thread instead, though awaiting the [`Iap`] method directly
+/// doesn't hold up the runtime.
+///
+/// # Deadlocks
+///
+/// Never call these from the main thread on iOS, where `setup` hooks run:
+/// StoreKit answers on the main thread, so waiting for it there never
+/// returns. On Android, blocking the main thread on the billing
+/// service makes the app stop responding. Spawn a thread, or await the
+/// [`Iap`] method from a task, instead.
+pub struct BlockingIap<'a, R: Runtime> {
+    iap: &'a Iap<R>,
+}
+
+/// Awaits `future` on the calling thread, or on a scoped thread when the
+/// calling thread is driving a runtime, where `block_on` would panic.
+fn wait<F>(future: F) -> F::Output
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    if tokio::runtime::Handle::try_current().is_err() {
+        return tauri::async_runtime::block_on(future);
+    }
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| tauri::async_runtime::block_on(future))
+            .join()
+            .unwrap_or_else(|panic| resume_unwind(panic))
+    })
+}
+
+impl<R: Runtime> BlockingIap<'_, R> {
+    /// See [`Iap::initialize`].
+    pub fn initialize(&self) -> Result<()> {
+        wait(self.iap.initialize())
+    }
+
+    /// See [`Iap::current_entitlements`].
+    pub fn current_entitlements(&self) -> Result<Vec<PurchaseDetails>> {
+        wait(self.iap.current_entitlements())
+    }
+
+    /// See [`Iap::is_available`].
+    pub fn is_available(&self) -> Result<bool> {
+        wait(self.iap.is_available())
+    }
+
+    /// See [`Iap::diagnostics`].
+    pub fn diagnostics(&self) -> Result<Diagnostics> {
+        wait(self.iap.diagnostics())
+    }
+
+    /// See [`Iap::install_source`].
+    pub fn install_source(&self) -> Result<InstallSource> {
+        wait(self.iap.install_source())
+    }
+
+    /// See [`Iap::query_product_details`].
+    pub fn query_product_details(&self, product_ids: Vec<String>) -> Result<ProductDetailsResponse> {
+        wait(self.iap.query_product_details(product_ids))
+    }
+
+    /// See [`Iap::query_product_details_with_policy`].
+    pub fn query_product_details_with_policy(
+        &self,
+        product_ids: Vec<String>,
+        retry_policy: &RetryPolicy,
+    ) -> Result<ProductDetailsResponse> {
+        wait(self.iap.query_product_details_with_policy(product_ids, retry_policy))
+    }
+
+    /// See [`Iap::buy_non_consumable`].
+    pub fn buy_non_consumable(&self, purchase_param: PurchaseParam) -> Result<bool> {
+        wait(self.iap.buy_non_consumable(purchase_param))
+    }
+
+    /// See [`Iap::buy_consumable`].
+    pub fn buy_consumable(&self, purchase_param: PurchaseParam, auto_consume: bool) -> Result<bool> {
+        wait(self.iap.buy_consumable(purchase_param, auto_consume))
+    }
+
+    /// See [`Iap::buy_upgrade`].
+    pub fn buy_upgrade(
+        &self,
+        new_product_id: String,
+        replacement_mode: ReplacementMode,
+    ) -> Result<Option<PurchaseDetails>> {
+        wait(self.iap.buy_upgrade(new_product_id, replacement_mode))
+    }
+
+    /// See [`Iap::complete_purchase`].
+    pub fn complete_purchase(&self, purchase: PurchaseDetails) -> Result<()> {
+        wait(self.iap.complete_purchase(purchase))
+    }
+
+    /// See [`Iap::finalize_after`]. `verify` is awaited on the thread
+    /// awaiting the call, without an async runtime when called from
+    /// synchronous code.
+    pub fn finalize_after<F, Fut>(&self, purchase: PurchaseDetails, verify: F) -> Result<()>
+    where
+        F: FnOnce(PurchaseDetails) -> Fut + Send,
+        Fut: Future<Output = Result<()>> + Send,
+    {
+        wait(self.iap.finalize_after(purchase, verify))
+    }
+
+    /// See [`Iap::restore_purchases`].
+    pub fn restore_purchases(&self, application_user_name: Option<String>) -> Result<()> {
+        wait(self.iap.restore_purchases(application_user_name))
+    }
+
+    /// See [`Iap::country_code`].
+    pub fn country_code(&self) -> Result<String> {
+        wait(self.iap.country_code())
+    }
+
+    /// See [`Iap::refresh_country_code`].
+    pub fn refresh_country_code(&self) -> Result<String> {
+        wait(self.iap.refresh_country_code())
+    }
+
+    /// See [`Iap::is_feature_supported`].
+    pub fn is_feature_supported(&self, feature: StoreFeature) -> Result<bool> {
+        wait(self.iap.is_feature_supported(feature))
+    }
+
+    /// See [`Iap::is_alternative_billing_available`].
+    pub fn is_alternative_billing_available(&self) -> Result<bool> {
+        wait(self.iap.is_alternative_billing_available())
+    }
+
+    /// See [`Iap::show_alternative_billing_dialog`].
+    pub fn show_alternative_billing_dialog(&self) -> Result<bool> {
+        wait(self.iap.show_alternative_billing_dialog())
+    }
+
+    /// See [`Iap::create_alternative_billing_token`].
+    pub fn create_alternative_billing_token(&self) -> Result<AlternativeBillingReportingDetails> {
+        wait(self.iap.create_alternative_billing_token())
+    }
+
+    /// See [`Iap::external_purchase_available`].
+    pub fn external_purchase_available(&self) -> Result<bool> {
+        wait(self.iap.external_purchase_available())
+    }
+
+    /// See [`Iap::open_external_purchase_link`].
+    pub fn open_external_purchase_link(&self, url: &str) -> Result<bool> {
+        wait(self.iap.open_external_purchase_link(url))
+    }
+
+    /// See [`Iap::external_purchase_token`].
+    pub fn external_purchase_token(&self) -> Result<ExternalPurchaseToken> {
+        wait(self.iap.external_purchase_token())
+    }
+
+    /// See [`Iap::app_license`].
+    pub fn app_license(&self) -> Result<AppLicense> {
+        wait(self.iap.app_license())
+    }
+
+    /// See [`Iap::get_app_receipt`].
+    pub fn get_app_receipt(&self, refresh: bool) -> Result<String> {
+        wait(self.iap.get_app_receipt(refresh))
+    }
+
+    /// See [`Iap::subscription_status`].
+    pub fn subscription_status(&self, product_id: &str) -> Result<SubscriptionStatus> {
+        wait(self.iap.subscription_status(product_id))
+    }
+
+    /// See [`Iap::subscription_time_remaining`].
+    pub fn subscription_time_remaining(&self, product_id: &str) -> Result<Option<Duration>> {
+        wait(self.iap.subscription_time_remaining(product_id))
+    }
+
+    /// See [`Iap::subscription_time_remaining_at`].
+    pub fn subscription_time_remaining_at(
+        &self,
+        product_id: &str,
+        now: SystemTime,
+    ) -> Result<Option<Duration>> {
+        wait(self.iap.subscription_time_remaining_at(product_id, now))
+    }
+
+    /// See [`Iap::open_manage_subscriptions`].
+    pub fn open_manage_subscriptions(&self) -> Result<()> {
+        wait(self.iap.open_manage_subscriptions())
+    }
+
+    /// See [`Iap::show_price_consent_if_needed`].
+    pub fn show_price_consent_if_needed(&self) -> Result<bool> {
+        wait(self.iap.show_price_consent_if_needed())
+    }
+
+    /// See [`Iap::open_store_product_page`].
+    pub fn open_store_product_page(&self, product_id: Option<&str>) -> Result<()> {
+        wait(self.iap.open_store_product_page(product_id))
+    }
+}
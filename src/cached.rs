@@ -0,0 +1,53 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// A value fetched from the store once, until cleared.
+pub(crate) struct Cached<T>(Mutex<Slot<T>>);
+
+struct Slot<T> {
+    value: Option<T>,
+    /// Bumped by `clear`, so a fetch that raced with it isn't cached.
+    generation: u64,
+}
+
+impl<T> Default for Cached<T> {
+    fn default() -> Self {
+        Self(Mutex::new(Slot {
+            value: None,
+            generation: 0,
+        }))
+    }
+}
+
+impl<T: Clone> Cached<T> {
+    /// Returns the cached value, or fetches and caches it.
+    pub(crate) fn get_or_fetch(&self, fetch: impl FnOnce() -> crate::Result<T>) -> crate::Result<T> {
+        if let Some(value) = self.lock().value.clone() {
+            return Ok(value);
+        }
+        self.refresh(fetch)
+    }
+
+    /// Fetches the value and caches it, replacing the cached one.
+    ///
+    /// The lock isn't held while fetching, as the native callbacks that
+    /// clear the cache may have to run for the fetch to finish.
+    pub(crate) fn refresh(&self, fetch: impl FnOnce() -> crate::Result<T>) -> crate::Result<T> {
+        let generation = self.lock().generation;
+        let value = fetch()?;
+        let mut slot = self.lock();
+        if slot.generation == generation {
+            slot.value = Some(value.clone());
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn clear(&self) {
+        let mut slot = self.lock();
+        slot.value = None;
+        slot.generation = slot.generation.wrapping_add(1);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Slot<T>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -1,53 +1,314 @@
-use tauri::{AppHandle, command, Runtime};
+use std::{
+    future::Future,
+    panic::{catch_unwind, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+use tauri::{ipc::CommandScope, AppHandle, command, Manager, Runtime, Window};
 use crate::models::*;
-use crate::Result;
-use crate::IapExt;
+use crate::scope::{self, ProductScope};
+use crate::{Diagnostics, Error, Metrics, Result, RetryPolicy, Timeouts};
+use crate::{CommandErrorHandler, IapExt};
+
+/// Runs a command body and reports its error, if any, to the registered error hook.
+///
+/// With the `tracing` feature, the body runs in an `iap.command` span.
+async fn run<R: Runtime, T>(
+    app: &AppHandle<R>,
+    command: &str,
+    body: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let started = Instant::now();
+    #[cfg(feature = "tracing")]
+    let span = crate::instrument::command(command);
+    #[cfg(feature = "tracing")]
+    let body = tracing::Instrument::instrument(body, span.clone());
+    let result = body.await;
+    #[cfg(feature = "tracing")]
+    crate::instrument::command_finished(&span, started.elapsed(), &result);
+    if let Err(error) = &result {
+        if let Some(handler) = app.try_state::<CommandErrorHandler>() {
+            // A panicking hook must not take the command down with it.
+            let reported = catch_unwind(AssertUnwindSafe(|| {
+                handler.call(command, error, started.elapsed())
+            }));
+            if reported.is_err() {
+                log::error!("Command error hook panicked while reporting {command}");
+            }
+        }
+    }
+    result
+}
+
+/// Store operations with their own timeout.
+#[derive(Clone, Copy)]
+enum Operation {
+    Query,
+    Purchase,
+    Finalize,
+    Restore,
+    /// Store UI other than purchases, open until the user closes it.
+    Sheet,
+}
+
+impl Operation {
+    /// Name reported in `Error::TimedOut`.
+    fn name(self) -> &'static str {
+        match self {
+            Operation::Query => "query",
+            Operation::Purchase => "purchase",
+            Operation::Finalize => "finalize",
+            Operation::Restore => "restore",
+            Operation::Sheet => "sheet",
+        }
+    }
+
+    fn timeout(self, timeouts: &Timeouts) -> Option<Duration> {
+        match self {
+            Operation::Query => timeouts.query(),
+            Operation::Purchase => timeouts.purchase(),
+            Operation::Finalize => timeouts.finalize(),
+            Operation::Restore => timeouts.restore(),
+            Operation::Sheet => timeouts.sheet(),
+        }
+    }
+}
+
+/// Awaits a store call after the simulated mock latency for queries,
+/// purchases and restores, failing with `Error::TimedOut` if both don't
+/// finish within the operation's timeout.
+///
+/// The call keeps running on its worker thread after timing out, as the
+/// stores can't cancel it. It still updates the plugin's state when it
+/// returns, so nothing is left waiting for it and later calls run normally.
+async fn with_timeout<R: Runtime, T>(
+    app: &AppHandle<R>,
+    operation: Operation,
+    call: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let timeout = operation.timeout(&app.iap().config().timeouts);
+    let body = async {
+        if !matches!(operation, Operation::Finalize | Operation::Sheet) {
+            simulate_latency(app).await;
+        }
+        call.await
+    };
+    let Some(timeout) = timeout else {
+        return body.await;
+    };
+    tokio::time::timeout(timeout, body)
+        .await
+        .unwrap_or_else(|_| {
+            Err(Error::TimedOut {
+                operation: operation.name().into(),
+            })
+        })
+}
+
+/// Runs a blocking call on a worker thread, so it doesn't hold up the IPC
+/// handler.
+async fn blocking<T: Send + 'static>(
+    call: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    match tauri::async_runtime::spawn_blocking(call).await {
+        Ok(result) => result,
+        Err(e) => Err(Error::InternalError(e.to_string())),
+    }
+}
+
+/// Awaits a buy command, following its flow with `correlation_id` so the
+/// purchase update settling it carries the ID. The flow ends right away if
+/// the command fails or returns without a purchase.
+async fn correlated<R: Runtime>(
+    app: &AppHandle<R>,
+    product_id: &str,
+    correlation_id: Option<String>,
+    buy: impl Future<Output = Result<bool>>,
+) -> Result<bool> {
+    let Some(correlation_id) = correlation_id else {
+        return buy.await;
+    };
+    let iap = app.iap();
+    iap.purchase_flows().start(product_id, correlation_id.clone());
+    let bought = buy.await;
+    if !matches!(bought, Ok(true)) {
+        iap.purchase_flows().end(&correlation_id);
+    }
+    bought
+}
+
+/// Waits out the latency simulated by the mock store, if it serves calls.
+async fn simulate_latency<R: Runtime>(app: &AppHandle<R>) {
+    #[cfg(all(desktop, feature = "mock"))]
+    if let Some(mock) = app.iap().mock_store() {
+        mock.simulate_latency().await;
+    }
+    #[cfg(not(all(desktop, feature = "mock")))]
+    let _ = app;
+}
 
 #[command]
 pub(crate) async fn initialize<R: Runtime>(
     app: AppHandle<R>,
 ) -> Result<()> {
-    app.iap().initialize()
+    run(&app, "initialize", async {
+        app.iap().initialize().await
+    })
+    .await
 }
 
 #[command]
 pub(crate) async fn is_available<R: Runtime>(
     app: AppHandle<R>,
 ) -> Result<bool> {
-    app.iap().is_available()
+    run(&app, "is_available", async {
+        app.iap().is_available().await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn connection_state<R: Runtime>(app: AppHandle<R>) -> Result<ConnectionState> {
+    run(&app, "connection_state", async { Ok(app.iap().connection_state()) }).await
+}
+
+#[command]
+pub(crate) async fn end_connection<R: Runtime>(app: AppHandle<R>) -> Result<()> {
+    run(&app, "end_connection", async {
+        let handle = app.clone();
+        blocking(move || {
+            handle.iap().end_connection();
+            Ok(())
+        })
+        .await
+    })
+    .await
 }
 
 #[command]
 pub(crate) async fn query_product_details<R: Runtime>(
     app: AppHandle<R>,
     product_ids: Vec<String>,
+    no_retry: Option<bool>,
 ) -> Result<ProductDetailsResponse> {
-    app.iap().query_product_details(product_ids)
+    run(&app, "query_product_details", async {
+        #[cfg(feature = "tracing")]
+        crate::instrument::products_queried(&product_ids);
+        let retry_policy = if no_retry.unwrap_or(false) {
+            RetryPolicy::none()
+        } else {
+            *app.iap().retry_policy()
+        };
+        with_timeout(
+            &app,
+            Operation::Query,
+            app.iap().query_product_details_with_policy(product_ids, &retry_policy),
+        )
+        .await
+    })
+    .await
 }
 
 #[command]
 pub(crate) async fn buy_non_consumable<R: Runtime>(
     app: AppHandle<R>,
+    window: Window<R>,
     purchase_param: PurchaseParam,
+    correlation_id: Option<String>,
+    scope: CommandScope<ProductScope>,
 ) -> Result<bool> {
-    app.iap().buy_non_consumable(purchase_param)
+    run(&app, "buy_non_consumable", async {
+        app.iap().config().ensure_window_allowed(window.label())?;
+        let product_id = purchase_param.product_details.id.clone();
+        scope::ensure_allowed(&scope, &product_id)?;
+        #[cfg(feature = "tracing")]
+        crate::instrument::purchase_started(&product_id);
+        let buy = app.iap().buy_non_consumable(purchase_param);
+        let buy = with_timeout(&app, Operation::Purchase, buy);
+        correlated(&app, &product_id, correlation_id, buy).await
+    })
+    .await
 }
 
 #[command]
 pub(crate) async fn buy_consumable<R: Runtime>(
     app: AppHandle<R>,
+    window: Window<R>,
     purchase_param: PurchaseParam,
     auto_consume: Option<bool>,
+    correlation_id: Option<String>,
+    scope: CommandScope<ProductScope>,
 ) -> Result<bool> {
-    app.iap().buy_consumable(purchase_param, auto_consume.unwrap_or(false))
+    run(&app, "buy_consumable", async {
+        app.iap().config().ensure_window_allowed(window.label())?;
+        let product_id = purchase_param.product_details.id.clone();
+        scope::ensure_allowed(&scope, &product_id)?;
+        #[cfg(feature = "tracing")]
+        crate::instrument::purchase_started(&product_id);
+        let buy = app.iap().buy_consumable(purchase_param, auto_consume.unwrap_or(false));
+        let buy = with_timeout(&app, Operation::Purchase, buy);
+        correlated(&app, &product_id, correlation_id, buy).await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn buy_upgrade<R: Runtime>(
+    app: AppHandle<R>,
+    window: Window<R>,
+    new_product_id: String,
+    replacement_mode: ReplacementMode,
+    scope: CommandScope<ProductScope>,
+) -> Result<serde_json::Value> {
+    run(&app, "buy_upgrade", async {
+        app.iap().config().ensure_window_allowed(window.label())?;
+        scope::ensure_allowed(&scope, &new_product_id)?;
+        #[cfg(feature = "tracing")]
+        crate::instrument::purchase_started(&new_product_id);
+        let purchase = with_timeout(
+            &app,
+            Operation::Purchase,
+            app.iap().buy_upgrade(new_product_id, replacement_mode),
+        )
+        .await?;
+        app.iap().config().webview_result(purchase)
+    })
+    .await
 }
 
 #[command]
 pub(crate) async fn complete_purchase<R: Runtime>(
     app: AppHandle<R>,
+    window: Window<R>,
     purchase: PurchaseDetails,
 ) -> Result<()> {
-    app.iap().complete_purchase(purchase)
+    run(&app, "complete_purchase", async {
+        app.iap().config().ensure_window_allowed(window.label())?;
+        #[cfg(feature = "tracing")]
+        crate::instrument::purchase_completing(&purchase);
+        with_timeout(&app, Operation::Finalize, app.iap().complete_purchase(purchase)).await
+    })
+    .await
+}
+
+/// Completes a purchase the webview sent to the app's server, unless the
+/// server rejected it with `verification_error`, in which case it's kept
+/// among the unfinished purchases.
+#[command]
+pub(crate) async fn finalize_after<R: Runtime>(
+    app: AppHandle<R>,
+    window: Window<R>,
+    purchase: PurchaseDetails,
+    verification_error: Option<IAPError>,
+) -> Result<()> {
+    run(&app, "finalize_after", async {
+        app.iap().config().ensure_window_allowed(window.label())?;
+        #[cfg(feature = "tracing")]
+        crate::instrument::purchase_completing(&purchase);
+        let verify = |_| async move { verification_error.map_or(Ok(()), |e| Err(e.into())) };
+        with_timeout(&app, Operation::Finalize, app.iap().finalize_after(purchase, verify)).await
+    })
+    .await
 }
 
 #[command]
@@ -55,12 +316,339 @@ pub(crate) async fn restore_purchases<R: Runtime>(
     app: AppHandle<R>,
     application_user_name: Option<String>,
 ) -> Result<()> {
-    app.iap().restore_purchases(application_user_name)
+    run(&app, "restore_purchases", async {
+        with_timeout(
+            &app,
+            Operation::Restore,
+            app.iap().restore_purchases(application_user_name),
+        )
+        .await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn is_owned<R: Runtime>(app: AppHandle<R>, product_id: String) -> Result<bool> {
+    run(&app, "is_owned", async { Ok(app.iap().is_owned(&product_id)) }).await
+}
+
+#[command]
+pub(crate) async fn owned_products<R: Runtime>(app: AppHandle<R>) -> Result<Vec<OwnedProduct>> {
+    run(&app, "owned_products", async { Ok(app.iap().owned_products()) }).await
+}
+
+/// The owned products as an `EntitlementsExport` in JSON.
+#[command]
+pub(crate) async fn export_entitlements<R: Runtime>(app: AppHandle<R>) -> Result<String> {
+    run(&app, "export_entitlements", async {
+        Ok(app.iap().export_entitlements().to_json())
+    })
+    .await
+}
+
+/// Replaces the owned products with an `EntitlementsExport` in JSON, in
+/// debug builds or with the mock store.
+#[command]
+pub(crate) async fn import_entitlements<R: Runtime>(app: AppHandle<R>, json: String) -> Result<()> {
+    run(&app, "import_entitlements", async {
+        app.iap().import_entitlements(EntitlementsExport::parse(&json)?)
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn current_entitlements<R: Runtime>(
+    app: AppHandle<R>,
+) -> Result<serde_json::Value> {
+    run(&app, "current_entitlements", async {
+        let purchases = app.iap().current_entitlements().await?;
+        app.iap().config().webview_result(purchases)
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn set_application_user_name<R: Runtime>(
+    app: AppHandle<R>,
+    application_user_name: Option<String>,
+) -> Result<()> {
+    run(&app, "set_application_user_name", async {
+        app.iap().set_application_user_name(application_user_name);
+        Ok(())
+    })
+    .await
 }
 
 #[command]
 pub(crate) async fn country_code<R: Runtime>(
     app: AppHandle<R>,
+    refresh: Option<bool>,
+) -> Result<String> {
+    run(&app, "country_code", async {
+        if refresh.unwrap_or(false) {
+            app.iap().refresh_country_code().await
+        } else {
+            app.iap().country_code().await
+        }
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn is_feature_supported<R: Runtime>(
+    app: AppHandle<R>,
+    feature: String,
+) -> Result<bool> {
+    run(&app, "is_feature_supported", async {
+        // Parsed here so a feature unknown to this version is an IapError.
+        let feature: StoreFeature = feature.parse()?;
+        app.iap().is_feature_supported(feature).await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn install_source<R: Runtime>(app: AppHandle<R>) -> Result<InstallSource> {
+    run(&app, "install_source", async {
+        app.iap().install_source().await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn diagnostics<R: Runtime>(app: AppHandle<R>) -> Result<Diagnostics> {
+    run(&app, "diagnostics", async {
+        app.iap().diagnostics().await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn metrics<R: Runtime>(app: AppHandle<R>) -> Result<Metrics> {
+    run(&app, "metrics", async { Ok(app.iap().metrics()) }).await
+}
+
+#[command]
+pub(crate) async fn reset_metrics<R: Runtime>(app: AppHandle<R>) -> Result<()> {
+    run(&app, "reset_metrics", async {
+        app.iap().reset_metrics();
+        Ok(())
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn app_license<R: Runtime>(
+    app: AppHandle<R>,
+) -> Result<AppLicense> {
+    run(&app, "app_license", async {
+        app.iap().app_license().await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn get_app_receipt<R: Runtime>(
+    app: AppHandle<R>,
+    refresh: Option<bool>,
 ) -> Result<String> {
-    app.iap().country_code()
+    run(&app, "get_app_receipt", async {
+        app.iap().get_app_receipt(refresh.unwrap_or(false)).await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn export_verification_payload<R: Runtime>(
+    app: AppHandle<R>,
+    purchase_id: String,
+) -> Result<VerificationPayload> {
+    run(&app, "export_verification_payload", async {
+        app.iap().export_verification_payload(&purchase_id)
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn is_alternative_billing_available<R: Runtime>(
+    app: AppHandle<R>,
+) -> Result<bool> {
+    run(&app, "is_alternative_billing_available", async {
+        app.iap().is_alternative_billing_available().await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn show_alternative_billing_dialog<R: Runtime>(
+    app: AppHandle<R>,
+) -> Result<bool> {
+    run(&app, "show_alternative_billing_dialog", async {
+        with_timeout(&app, Operation::Sheet, app.iap().show_alternative_billing_dialog()).await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn create_alternative_billing_token<R: Runtime>(
+    app: AppHandle<R>,
+) -> Result<AlternativeBillingReportingDetails> {
+    run(&app, "create_alternative_billing_token", async {
+        app.iap().create_alternative_billing_token().await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn external_purchase_available<R: Runtime>(app: AppHandle<R>) -> Result<bool> {
+    run(&app, "external_purchase_available", async {
+        app.iap().external_purchase_available().await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn open_external_purchase_link<R: Runtime>(
+    app: AppHandle<R>,
+    url: String,
+) -> Result<bool> {
+    run(&app, "open_external_purchase_link", async {
+        with_timeout(&app, Operation::Sheet, app.iap().open_external_purchase_link(&url)).await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn external_purchase_token<R: Runtime>(
+    app: AppHandle<R>,
+) -> Result<ExternalPurchaseToken> {
+    run(&app, "external_purchase_token", async {
+        app.iap().external_purchase_token().await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn open_store_product_page<R: Runtime>(
+    app: AppHandle<R>,
+    product_id: Option<String>,
+) -> Result<()> {
+    run(&app, "open_store_product_page", async {
+        with_timeout(
+            &app,
+            Operation::Sheet,
+            app.iap().open_store_product_page(product_id.as_deref()),
+        )
+        .await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn subscription_status<R: Runtime>(
+    app: AppHandle<R>,
+    product_id: String,
+) -> Result<SubscriptionStatus> {
+    run(&app, "subscription_status", async {
+        app.iap().subscription_status(&product_id).await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn subscription_time_remaining<R: Runtime>(
+    app: AppHandle<R>,
+    product_id: String,
+) -> Result<Option<u64>> {
+    run(&app, "subscription_time_remaining", async {
+        app.iap()
+            .subscription_time_remaining(&product_id)
+            .await
+            .map(|remaining| remaining.map(|remaining| remaining.as_secs()))
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn open_manage_subscriptions<R: Runtime>(
+    app: AppHandle<R>,
+) -> Result<()> {
+    run(&app, "open_manage_subscriptions", async {
+        with_timeout(&app, Operation::Sheet, app.iap().open_manage_subscriptions()).await
+    })
+    .await
+}
+
+#[command]
+pub(crate) async fn show_price_consent_if_needed<R: Runtime>(app: AppHandle<R>) -> Result<bool> {
+    run(&app, "show_price_consent_if_needed", async {
+        with_timeout(&app, Operation::Sheet, app.iap().show_price_consent_if_needed()).await
+    })
+    .await
+}
+
+/// Injects a failure into the mock store, or clears all injected failures
+/// when `failure` is null, so error handling can be driven from the webview.
+#[cfg(all(desktop, feature = "mock"))]
+#[command]
+pub(crate) async fn mock_set_failure<R: Runtime>(
+    app: AppHandle<R>,
+    failure: Option<crate::MockFailure>,
+) -> Result<()> {
+    run(&app, "mock_set_failure", async {
+        let mock = app.iap().mock_store().ok_or_else(|| {
+            crate::Error::FeatureNotSupported("The mock store is not in use".into())
+        })?;
+        match failure {
+            Some(failure) => mock.inject_failure(failure),
+            None => mock.clear_failures(),
+        }
+        Ok(())
+    })
+    .await
+}
+
+/// Wipes the purchases of the mock store, so products can be bought again.
+#[cfg(all(desktop, feature = "mock"))]
+#[command]
+pub(crate) async fn mock_clear_purchases<R: Runtime>(app: AppHandle<R>) -> Result<()> {
+    run(&app, "mock_clear_purchases", async { app.iap().mock_clear_purchases() }).await
+}
+
+/// Reports a purchase update as if the store did, e.g. a renewal or a
+/// revocation, through the same path as the store's own updates. Only
+/// registered in debug builds.
+#[cfg(all(desktop, feature = "mock", debug_assertions))]
+#[command]
+pub(crate) async fn mock_emit_purchase<R: Runtime>(
+    app: AppHandle<R>,
+    details: PurchaseDetails,
+) -> Result<()> {
+    run(&app, "mock_emit_purchase", async {
+        let iap = app.iap();
+        if iap.mock_store().is_none() {
+            return Err(Error::FeatureNotSupported("The mock store is not in use".into()));
+        }
+        iap.push_purchase_updates(&[details]);
+        Ok(())
+    })
+    .await
+}
+
+/// Sets the latency simulated by the mock store, or answers immediately when
+/// `latency` is null.
+#[cfg(all(desktop, feature = "mock"))]
+#[command]
+pub(crate) async fn mock_set_latency<R: Runtime>(
+    app: AppHandle<R>,
+    latency: Option<crate::MockLatency>,
+) -> Result<()> {
+    run(&app, "mock_set_latency", async {
+        let mock = app.iap().mock_store().ok_or_else(|| {
+            crate::Error::FeatureNotSupported("The mock store is not in use".into())
+        })?;
+        mock.set_latency(latency);
+        Ok(())
+    })
+    .await
 }
@@ -0,0 +1,498 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Runtime};
+
+use crate::models::{AppLicense, PurchaseDetails, PurchaseStatus};
+use crate::ConnectionRetryPolicy;
+
+/// Plugin configuration, read from `plugins.iap` in `tauri.conf.json`.
+///
+/// Unknown keys fail the plugin setup, so typos are caught at startup.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Config {
+    /// Product IDs queried when `query_product_details` is called without
+    /// any.
+    #[serde(default)]
+    pub products: Vec<String>,
+    /// Completes purchases before reporting them, so the app doesn't have to
+    /// call `complete_purchase`. Only purchases reported through Rust are
+    /// completed, i.e. on desktop and Android.
+    #[serde(default)]
+    pub auto_finish_transactions: bool,
+    /// Label of the webview or window that receives the plugin events, or
+    /// every one if unset. Only applies to events emitted from Rust, i.e. on
+    /// desktop and Android.
+    #[serde(default)]
+    pub event_target: Option<String>,
+    /// Labels of the windows allowed to buy and complete purchases, or every
+    /// window if unset. Others fail with `Error::WindowNotAllowed`.
+    #[serde(default)]
+    pub allowed_windows: Option<Vec<String>>,
+    /// How long operations may take before failing with `Error::TimedOut`.
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    /// How purchases awaiting payment are handled.
+    #[serde(default)]
+    pub pending_purchases: PendingPurchases,
+    /// Time in milliseconds after a successful `restore_purchases` during
+    /// which further restores return its result instead of restoring again,
+    /// 3 seconds if unset. `0` disables it.
+    #[serde(default)]
+    pub restore_cooldown_ms: Option<u64>,
+    /// Reports the owned purchases through purchase update events after the
+    /// first successful `initialize`, without store UI. Failures are logged
+    /// and don't fail `initialize`.
+    #[serde(default)]
+    pub auto_restore_on_init: bool,
+    /// Queries the `products` after the first successful `initialize` and
+    /// caches them, so later queries for them don't reach the store.
+    /// Failures are retried, then reported through the error event, and
+    /// don't fail `initialize`.
+    #[serde(default)]
+    pub preload_products: bool,
+    /// Leaves the products dropped from the `preload_products` cache when the
+    /// storefront or the language changes to the next query, instead of
+    /// querying them again right away.
+    #[serde(default)]
+    pub skip_preload_on_storefront_change: bool,
+    /// Keeps the products cached by `preload_products` in the app data
+    /// directory, so queries are answered from them on the next run before
+    /// the store is reached, e.g. for an offline paywall.
+    #[serde(default)]
+    pub persist_products: bool,
+    /// How the store connection is retried when `initialize` connects and
+    /// when it drops afterwards.
+    #[serde(default)]
+    pub connection_retry: ConnectionRetryPolicy,
+    /// Base64 encoded Google Play licensing key of the app, from the Play
+    /// Console. When set, the signature of every purchase reported on
+    /// Android is verified before it is emitted.
+    #[serde(default)]
+    pub google_play_public_key: Option<String>,
+    /// Reports purchases failing the `google_play_public_key` verification
+    /// as errors instead of only leaving `verified` unset.
+    #[serde(default)]
+    pub reject_unverified_purchases: bool,
+    /// Replaces the `verificationData` of purchases sent to the webview with
+    /// a `verificationAvailable` flag, for apps that validate purchases from
+    /// Rust. Rust APIs still receive the full data.
+    #[serde(default)]
+    pub redact_verification_data: bool,
+    /// Sets up the Google Play billing client for the Alternative Billing
+    /// Only program, where the app sells through its own payment system.
+    #[serde(default)]
+    pub alternative_billing_only: bool,
+    /// Numeric App Store ID of the app, from App Store Connect. Pages opened
+    /// with `open_store_product_page` without a product ID show this app on
+    /// iOS and macOS.
+    #[serde(default)]
+    pub app_store_id: Option<String>,
+    /// Makes `is_available` return `false` for builds installed outside of a
+    /// store, e.g. APKs shared as files, which can't buy. The install source
+    /// is logged as the reason.
+    #[serde(default)]
+    pub require_store_install: bool,
+    /// Starts consuming the StoreKit 2 transaction updates when the plugin
+    /// loads on iOS instead of on the first `initialize`, so updates arriving
+    /// at launch are kept until then.
+    #[serde(default)]
+    pub transaction_listener_on_load: bool,
+    /// Keeps subscriptions on account hold or in billing retry owned, e.g.
+    /// for apps that only lock out users once the store gives up. By default
+    /// they are owned during the grace period but not on hold.
+    #[serde(default)]
+    pub owned_on_hold: bool,
+    /// Holds back the sheet asking iOS users to consent to a subscription
+    /// price increase, which StoreKit otherwise shows on its own at launch,
+    /// until the app calls `show_price_consent_if_needed`.
+    #[serde(default)]
+    pub defer_price_consent: bool,
+    /// Sends product IDs to the store as they are, for IDs the
+    /// `ProductIdRules` of the store reject although it accepts them. By
+    /// default IDs breaking the rules fail with `Error::ProductQueryError`
+    /// before reaching the store.
+    #[serde(default)]
+    pub skip_product_id_validation: bool,
+    /// License returned by `app_license` on Android, e.g. `{ "isActive":
+    /// true, "isTrial": false }` for a paid app that Google Play only
+    /// installs for buyers. Google Play checks licenses through the separate
+    /// License Verification Library, so without it `app_license` fails with
+    /// `Error::FeatureNotSupported`.
+    #[serde(default)]
+    pub android_app_license: Option<AppLicense>,
+    /// Serves calls from the mock store on desktop with the `mock` feature,
+    /// ahead of custom and bundled backends, or never if `false`.
+    /// `TAURI_IAP_MOCK` takes precedence. By default the mock store is only
+    /// used when the build has no other backend.
+    #[serde(default)]
+    pub mock: Option<bool>,
+    /// Products sold by the mock store on desktop, used with the `mock`
+    /// feature. Entries are validated when the plugin is set up.
+    #[serde(default)]
+    pub mock_products: Vec<serde_json::Value>,
+    /// Latency simulated by the mock store in milliseconds, fixed (`"500"`)
+    /// or a random range (`"300-3000"`).
+    #[serde(default)]
+    pub mock_latency: Option<String>,
+}
+
+/// Operation timeouts in milliseconds, unlimited if `None`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// Timeout of `query_product_details`, 30 seconds by default.
+    pub query_ms: Option<u64>,
+    /// Timeout of `buy_non_consumable` and `buy_consumable`, including the
+    /// time the user spends in the store's purchase sheet. Unlimited by
+    /// default.
+    pub purchase_ms: Option<u64>,
+    /// Timeout of `complete_purchase`, 30 seconds by default.
+    pub finalize_ms: Option<u64>,
+    /// Timeout of `restore_purchases`, 60 seconds by default.
+    pub restore_ms: Option<u64>,
+    /// Timeout of the commands presenting store UI other than purchases,
+    /// e.g. `open_store_product_page` or `show_price_consent_if_needed`,
+    /// which wait for the user to close it. Unlimited by default.
+    pub sheet_ms: Option<u64>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            query_ms: Some(30_000),
+            purchase_ms: None,
+            finalize_ms: Some(30_000),
+            restore_ms: Some(60_000),
+            sheet_ms: None,
+        }
+    }
+}
+
+impl Timeouts {
+    /// The product query timeout.
+    pub fn query(&self) -> Option<Duration> {
+        self.query_ms.map(Duration::from_millis)
+    }
+
+    /// The purchase timeout.
+    pub fn purchase(&self) -> Option<Duration> {
+        self.purchase_ms.map(Duration::from_millis)
+    }
+
+    /// The purchase completion timeout.
+    pub fn finalize(&self) -> Option<Duration> {
+        self.finalize_ms.map(Duration::from_millis)
+    }
+
+    /// The restore timeout.
+    pub fn restore(&self) -> Option<Duration> {
+        self.restore_ms.map(Duration::from_millis)
+    }
+
+    /// The timeout of store UI other than purchases.
+    pub fn sheet(&self) -> Option<Duration> {
+        self.sheet_ms.map(Duration::from_millis)
+    }
+}
+
+/// Pending purchase settings, passed to the Google Play billing client.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PendingPurchases {
+    /// Reports purchases awaiting payment, e.g. cash payments, with the
+    /// `Pending` status and again once paid. When disabled they are only
+    /// reported once paid. Enabled by default.
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    /// Allows pending purchases of prepaid subscription plans on Android.
+    #[serde(default)]
+    pub prepaid_plans: bool,
+}
+
+impl Default for PendingPurchases {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prepaid_plans: false,
+        }
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Settings made with the [`Builder`](crate::Builder), which take precedence
+/// over the configuration file.
+#[derive(Default)]
+pub(crate) struct ConfigOverrides {
+    pub(crate) products: Option<Vec<String>>,
+    pub(crate) auto_finish_transactions: Option<bool>,
+    pub(crate) event_target: Option<String>,
+    pub(crate) purchase_timeout: Option<Duration>,
+    pub(crate) connection_retry: Option<ConnectionRetryPolicy>,
+    pub(crate) defer_price_consent: Option<bool>,
+    pub(crate) skip_product_id_validation: Option<bool>,
+    pub(crate) skip_preload_on_storefront_change: Option<bool>,
+}
+
+impl ConfigOverrides {
+    /// Replaces the values of `config` that were set with the builder.
+    pub(crate) fn apply(self, mut config: Config) -> Config {
+        if let Some(products) = self.products {
+            config.products = products;
+        }
+        if let Some(auto_finish) = self.auto_finish_transactions {
+            config.auto_finish_transactions = auto_finish;
+        }
+        if let Some(label) = self.event_target {
+            config.event_target = Some(label);
+        }
+        if let Some(timeout) = self.purchase_timeout {
+            config.timeouts.purchase_ms = Some(timeout.as_millis() as u64);
+        }
+        if let Some(policy) = self.connection_retry {
+            config.connection_retry = policy;
+        }
+        if let Some(defer) = self.defer_price_consent {
+            config.defer_price_consent = defer;
+        }
+        if let Some(skip) = self.skip_product_id_validation {
+            config.skip_product_id_validation = skip;
+        }
+        if let Some(skip) = self.skip_preload_on_storefront_change {
+            config.skip_preload_on_storefront_change = skip;
+        }
+        config
+    }
+}
+
+impl Config {
+    /// Whether the `products` are queried again after a storefront change,
+    /// as they were preloaded.
+    pub(crate) fn preloads_on_storefront_change(&self) -> bool {
+        self.preload_products && !self.products.is_empty() && !self.skip_preload_on_storefront_change
+    }
+
+    /// How long a successful restore is returned to further restores.
+    pub fn restore_cooldown(&self) -> Duration {
+        Duration::from_millis(self.restore_cooldown_ms.unwrap_or(3_000))
+    }
+
+    /// Fails with `Error::WindowNotAllowed` unless purchases may be made from
+    /// the window labeled `window`.
+    pub(crate) fn ensure_window_allowed(&self, window: &str) -> crate::Result<()> {
+        match &self.allowed_windows {
+            Some(allowed) if !allowed.iter().any(|label| label == window) => {
+                Err(crate::Error::WindowNotAllowed {
+                    window: window.into(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Emits a plugin event to the configured target, without verification
+    /// data if it is redacted.
+    pub(crate) fn emit<R: Runtime, S: Serialize + Clone>(
+        &self,
+        app: &AppHandle<R>,
+        event: &str,
+        payload: S,
+    ) -> tauri::Result<()> {
+        if self.redact_verification_data {
+            let mut payload = serde_json::to_value(payload)?;
+            redact_verification_data(&mut payload);
+            return self.emit_to_target(app, event, payload);
+        }
+        self.emit_to_target(app, event, payload)
+    }
+
+    /// A command result as returned to the webview, without verification
+    /// data if it is redacted.
+    pub(crate) fn webview_result<S: Serialize>(
+        &self,
+        result: S,
+    ) -> crate::Result<serde_json::Value> {
+        let mut result = serde_json::to_value(result).map_err(|e| {
+            crate::Error::InternalError(format!("Failed to serialize the result: {e}"))
+        })?;
+        if self.redact_verification_data {
+            redact_verification_data(&mut result);
+        }
+        Ok(result)
+    }
+
+    fn emit_to_target<R: Runtime, S: Serialize + Clone>(
+        &self,
+        app: &AppHandle<R>,
+        event: &str,
+        payload: S,
+    ) -> tauri::Result<()> {
+        match &self.event_target {
+            Some(label) => app.emit_to(label.as_str(), event, payload),
+            None => app.emit(event, payload),
+        }
+    }
+
+    /// The purchases to report, without pending ones if they are disabled.
+    pub(crate) fn reported_purchases(&self, purchases: &[PurchaseDetails]) -> Vec<PurchaseDetails> {
+        purchases
+            .iter()
+            .filter(|purchase| {
+                self.pending_purchases.enabled
+                    || !matches!(purchase.status, PurchaseStatus::Pending)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Completes the purchases awaiting completion with `complete` if
+    /// `auto_finish_transactions` is set, returning them as reported after.
+    ///
+    /// Purchases that fail to complete are reported as still pending, so the
+    /// app can complete them itself.
+    pub(crate) fn finish_purchases(
+        &self,
+        purchases: &[PurchaseDetails],
+        complete: impl Fn(&PurchaseDetails) -> crate::Result<()>,
+    ) -> Vec<PurchaseDetails> {
+        let mut purchases = purchases.to_vec();
+        if !self.auto_finish_transactions {
+            return purchases;
+        }
+        for purchase in &mut purchases {
+            let finishable = matches!(
+                purchase.status,
+                PurchaseStatus::Purchased | PurchaseStatus::Restored
+            );
+            if !finishable || !purchase.pending_complete_purchase {
+                continue;
+            }
+            match complete(purchase) {
+                Ok(()) => purchase.pending_complete_purchase = false,
+                Err(e) => log::warn!(
+                    "Failed to finish the purchase of {}: {}",
+                    purchase.product_id,
+                    e
+                ),
+            }
+        }
+        purchases
+    }
+}
+
+/// Replaces every `verificationData` object in `value` with
+/// `verificationAvailable`, whether it held a token or receipt.
+fn redact_verification_data(value: &mut serde_json::Value) {
+    use serde_json::Value;
+
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(redact_verification_data),
+        Value::Object(object) => {
+            if let Some(data) = object.remove("verificationData") {
+                let available = ["localVerificationData", "serverVerificationData"]
+                    .iter()
+                    .any(|key| data.get(key).and_then(Value::as_str).is_some_and(|s| !s.is_empty()));
+                object.insert("verificationAvailable".into(), available.into());
+            }
+            object.values_mut().for_each(redact_verification_data);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn parse(config: serde_json::Value) -> Result<Option<Config>, serde_json::Error> {
+        serde_json::from_value(config)
+    }
+
+    #[test]
+    fn parses_a_full_config() {
+        let config = parse(json!({
+            "products": ["premium", "coins"],
+            "autoFinishTransactions": true,
+            "autoRestoreOnInit": true,
+            "eventTarget": "main",
+            "allowedWindows": ["main"],
+            "timeouts": { "queryMs": 5000, "purchaseMs": 120000, "sheetMs": null },
+            "pendingPurchases": { "enabled": false, "prepaidPlans": true },
+            "restoreCooldownMs": 0,
+            "preloadProducts": true,
+            "connectionRetry": { "maxAttempts": 3, "baseDelayMs": 100 },
+            "redactVerificationData": true,
+            "appStoreId": "1234567890",
+            "androidAppLicense": { "isActive": true, "isTrial": false },
+            "mockProducts": [{ "id": "premium", "price": 4.99 }],
+            "mockLatency": "300-3000",
+        }))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(config.products, ["premium", "coins"]);
+        assert!(config.auto_finish_transactions && config.auto_restore_on_init);
+        assert_eq!(config.event_target.as_deref(), Some("main"));
+        assert_eq!(config.allowed_windows, Some(vec!["main".into()]));
+        assert_eq!(config.timeouts.query(), Some(Duration::from_secs(5)));
+        assert_eq!(config.timeouts.purchase(), Some(Duration::from_secs(120)));
+        // Unset timeouts keep their default, explicit nulls are unlimited.
+        assert_eq!(config.timeouts.finalize(), Some(Duration::from_secs(30)));
+        assert_eq!(config.timeouts.sheet(), None);
+        assert!(!config.pending_purchases.enabled && config.pending_purchases.prepaid_plans);
+        assert_eq!(config.restore_cooldown(), Duration::ZERO);
+        assert!(config.preloads_on_storefront_change());
+        assert_eq!(config.connection_retry.max_attempts, Some(3));
+        assert_eq!(config.connection_retry.base_delay_ms, 100);
+        assert_eq!(
+            config.connection_retry.max_delay_ms,
+            ConnectionRetryPolicy::default().max_delay_ms
+        );
+        assert!(config.redact_verification_data);
+        assert_eq!(config.app_store_id.as_deref(), Some("1234567890"));
+        assert!(config.android_app_license.unwrap().is_active);
+        assert_eq!(config.mock_products.len(), 1);
+        assert_eq!(config.mock_latency.as_deref(), Some("300-3000"));
+    }
+
+    #[test]
+    fn parses_a_minimal_config() {
+        // Without a `plugins.iap` section, `init` falls back to the default.
+        assert!(parse(json!(null)).unwrap().is_none());
+
+        let config = parse(json!({})).unwrap().unwrap();
+        assert!(config.products.is_empty());
+        assert!(!config.auto_finish_transactions && !config.auto_restore_on_init);
+        assert!(config.event_target.is_none() && config.allowed_windows.is_none());
+        assert_eq!(config.timeouts.query(), Some(Duration::from_secs(30)));
+        assert_eq!(config.timeouts.purchase(), None);
+        assert_eq!(config.timeouts.restore(), Some(Duration::from_secs(60)));
+        assert!(config.pending_purchases.enabled && !config.pending_purchases.prepaid_plans);
+        assert_eq!(config.restore_cooldown(), Duration::from_secs(3));
+        assert_eq!(config.connection_retry, ConnectionRetryPolicy::default());
+        assert!(config.mock_products.is_empty() && config.mock_latency.is_none());
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let cases = [
+            (
+                json!({ "autoFinishTransaction": true }),
+                "autoFinishTransaction",
+            ),
+            (json!({ "timeouts": { "queryMS": 5000 } }), "queryMS"),
+            (json!({ "pendingPurchases": { "enable": false } }), "enable"),
+            (json!({ "connectionRetry": { "attempts": 3 } }), "attempts"),
+        ];
+        for (config, key) in cases {
+            let error = parse(config).unwrap_err().to_string();
+            assert!(error.contains(&format!("unknown field `{key}`")), "{error}");
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use crate::models::{ConnectionState, ConnectionStateChange};
+use crate::Error;
+
+type Listener = Box<dyn Fn(&ConnectionStateChange) + Send + Sync>;
+
+/// Tracks the [`ConnectionState`], notifying a listener of every transition.
+pub(crate) struct Connection {
+    state: Mutex<ConnectionState>,
+    /// Whether the last connection attempt found a store on the device.
+    store_present: AtomicBool,
+    on_change: Listener,
+}
+
+impl Connection {
+    pub(crate) fn new(on_change: impl Fn(&ConnectionStateChange) + Send + Sync + 'static) -> Self {
+        Self {
+            state: Mutex::new(ConnectionState::Uninitialized),
+            store_present: AtomicBool::new(true),
+            on_change: Box::new(on_change),
+        }
+    }
+
+    pub(crate) fn state(&self) -> ConnectionState {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Whether a store was found on the device, i.e. unless the last
+    /// connection attempt failed with `Error::StoreUnavailable`.
+    pub(crate) fn store_present(&self) -> bool {
+        self.store_present.load(Ordering::Relaxed)
+    }
+
+    /// Moves to the state a connection attempt ended in, recording whether
+    /// it found a store before notifying the listener.
+    pub(crate) fn connected(&self, result: &crate::Result<()>) -> crate::Result<()> {
+        let store_missing =
+            matches!(result, Err(e) if e.code() == crate::IapErrorCode::StoreUnavailable);
+        self.store_present.store(!store_missing, Ordering::Relaxed);
+        self.transition(match result {
+            Ok(()) => ConnectionState::Ready,
+            Err(_) => ConnectionState::Uninitialized,
+        })
+    }
+
+    /// Moves to `next` and notifies the listener, unless already there.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InternalError` if the current state can't move to
+    /// `next`, e.g. from `Ready` back to `Initializing`.
+    pub(crate) fn transition(&self, next: ConnectionState) -> crate::Result<()> {
+        self.change(next, None)
+    }
+
+    fn change(&self, next: ConnectionState, attempt: Option<u32>) -> crate::Result<()> {
+        // Held while notifying, so listeners see transitions in order.
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if *state == next && attempt.is_none() {
+            return Ok(());
+        }
+        if *state != next && !state.can_transition_to(next) {
+            return Err(Error::InternalError(format!(
+                "Invalid connection state transition from {:?} to {:?}",
+                *state, next
+            )));
+        }
+        let previous = std::mem::replace(&mut *state, next);
+        (self.on_change)(&ConnectionStateChange {
+            previous,
+            state: next,
+            attempt,
+            store_present: self.store_present(),
+        });
+        Ok(())
+    }
+
+    /// Like [`transition`](Self::transition), logging an invalid transition
+    /// instead of failing, for state changes reported by the stores.
+    pub(crate) fn report(&self, next: ConnectionState) {
+        if let Err(e) = self.transition(next) {
+            log::warn!("Ignoring connection state change: {}", e);
+        }
+    }
+
+    /// Reports a background reconnection attempt, notifying the listener of
+    /// every attempt even though the state stays `Reconnecting`.
+    #[cfg_attr(not(target_os = "android"), allow(dead_code))]
+    pub(crate) fn report_attempt(&self, attempt: u32) {
+        if let Err(e) = self.change(ConnectionState::Reconnecting, Some(attempt)) {
+            log::warn!("Ignoring reconnection attempt: {}", e);
+        }
+    }
+
+    /// Fails with `Error::NotInitialized` unless connected or reconnecting,
+    /// in which case store calls are retried.
+    pub(crate) fn ensure_ready(&self) -> crate::Result<()> {
+        match self.state() {
+            ConnectionState::Ready | ConnectionState::Reconnecting => Ok(()),
+            _ => Err(Error::NotInitialized),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use ConnectionState::*;
+
+    const STATES: [ConnectionState; 5] = [Uninitialized, Initializing, Ready, Reconnecting, Closed];
+
+    /// A connection recording the changes it notifies.
+    fn connection() -> (Connection, Arc<Mutex<Vec<ConnectionStateChange>>>) {
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = changes.clone();
+        let connection =
+            Connection::new(move |change| recorded.lock().unwrap().push(change.clone()));
+        (connection, changes)
+    }
+
+    /// A connection moved to `state` through legal transitions.
+    fn connection_in(
+        state: ConnectionState,
+    ) -> (Connection, Arc<Mutex<Vec<ConnectionStateChange>>>) {
+        let (connection, changes) = connection();
+        let path: &[ConnectionState] = match state {
+            Uninitialized => &[],
+            Initializing => &[Initializing],
+            Ready => &[Initializing, Ready],
+            Reconnecting => &[Initializing, Ready, Reconnecting],
+            Closed => &[Closed],
+        };
+        for next in path {
+            connection.transition(*next).unwrap();
+        }
+        changes.lock().unwrap().clear();
+        (connection, changes)
+    }
+
+    #[test]
+    fn allows_the_lifecycle_transitions() {
+        let legal = [
+            (Uninitialized, Initializing),
+            (Initializing, Ready),
+            (Initializing, Uninitialized),
+            (Ready, Reconnecting),
+            (Reconnecting, Ready),
+            (Reconnecting, Uninitialized),
+            (Uninitialized, Closed),
+            (Initializing, Closed),
+            (Ready, Closed),
+            (Reconnecting, Closed),
+            (Closed, Initializing),
+        ];
+        for (from, to) in legal {
+            let (connection, changes) = connection_in(from);
+            assert!(connection.transition(to).is_ok(), "{from:?} -> {to:?}");
+            assert_eq!(connection.state(), to);
+            let changes = changes.lock().unwrap();
+            assert_eq!(changes.len(), 1);
+            assert_eq!((changes[0].previous, changes[0].state), (from, to));
+        }
+    }
+
+    #[test]
+    fn rejects_other_transitions() {
+        for from in STATES {
+            for to in STATES {
+                if from == to || from.can_transition_to(to) {
+                    continue;
+                }
+                let (connection, changes) = connection_in(from);
+                assert!(
+                    matches!(connection.transition(to), Err(Error::InternalError(_))),
+                    "{from:?} -> {to:?}"
+                );
+                assert_eq!(connection.state(), from);
+                assert!(changes.lock().unwrap().is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn staying_in_a_state_is_not_a_change() {
+        for state in STATES {
+            let (connection, changes) = connection_in(state);
+            assert!(connection.transition(state).is_ok());
+            assert!(changes.lock().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn guards_store_calls_until_connected() {
+        for state in STATES {
+            let (connection, _) = connection_in(state);
+            let ready = connection.ensure_ready();
+            match state {
+                Ready | Reconnecting => assert!(ready.is_ok(), "{state:?}"),
+                _ => assert!(matches!(ready, Err(Error::NotInitialized)), "{state:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn reports_every_reconnection_attempt() {
+        let (connection, changes) = connection_in(Ready);
+
+        connection.report(Reconnecting);
+        for attempt in 1..=3 {
+            connection.report_attempt(attempt);
+        }
+        connection.report(Ready);
+
+        let changes = changes.lock().unwrap();
+        let reported: Vec<_> = changes
+            .iter()
+            .map(|change| (change.previous, change.state, change.attempt))
+            .collect();
+        assert_eq!(
+            reported,
+            [
+                (Ready, Reconnecting, None),
+                (Reconnecting, Reconnecting, Some(1)),
+                (Reconnecting, Reconnecting, Some(2)),
+                (Reconnecting, Reconnecting, Some(3)),
+                (Reconnecting, Ready, None),
+            ]
+        );
+        // Attempts after `end_connection` are ignored.
+        drop(changes);
+        connection.report(Closed);
+        connection.report_attempt(4);
+        assert_eq!(connection.state(), Closed);
+    }
+
+    #[test]
+    fn records_whether_connecting_found_a_store() {
+        let (connection, changes) = connection_in(Initializing);
+
+        let missing = Error::StoreUnavailable("no Play Store".into());
+        assert!(connection.connected(&Err(missing)).is_ok());
+        assert_eq!(connection.state(), Uninitialized);
+        assert!(!connection.store_present());
+
+        connection.transition(Initializing).unwrap();
+        assert!(connection.connected(&Ok(())).is_ok());
+        assert_eq!(connection.state(), Ready);
+        assert!(connection.store_present());
+        let changes = changes.lock().unwrap();
+        assert!(!changes[0].store_present);
+        assert!(changes[2].store_present);
+    }
+}
@@ -1,109 +1,1579 @@
-use serde::de::DeserializeOwned;
-use tauri::{plugin::PluginApi, AppHandle, Runtime};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once, PoisonError};
+use std::time::{Duration, SystemTime};
 
+#[cfg(feature = "mock")]
+use tauri::Manager;
+use tauri::{AppHandle, Runtime};
+
+#[cfg(all(target_os = "macos", feature = "mac-app-store"))]
+use crate::mac_app_store::MacAppStore;
+#[cfg(all(windows, feature = "microsoft-store"))]
+use crate::microsoft_store::MicrosoftStore;
+#[cfg(feature = "mock")]
+use crate::mock::{MockOptions, MockStore, MOCK_PURCHASES_FILE};
+use crate::cached::Cached;
+use crate::connection::Connection;
+use crate::entitlements::Entitlements;
 use crate::models::*;
+use crate::in_flight::InFlight;
+use crate::metrics::Counters;
+use crate::product_cache::ProductCache;
+use crate::purchase_flows::PurchaseFlows;
+use crate::purchase_stream::PurchaseStreams;
+use crate::transaction_updates::TransactionUpdates;
+use crate::unfinished::UnfinishedPurchases;
+use crate::{
+  retry, retry_connection, CancellationToken, Config, ConnectionRetryPolicy, DesktopIapBackend, Diagnostics, Error,
+  IapErrorCode, Metrics, PurchaseEventSink, PurchaseUpdate, RetryPolicy, UNKNOWN_COUNTRY_CODE,
+};
 
-pub fn init<R: Runtime, C: DeserializeOwned>(
+pub fn init<R: Runtime>(
   app: &AppHandle<R>,
-  _api: PluginApi<R, C>,
+  config: Config,
+  retry_policy: RetryPolicy,
+  reject_unknown_purchases: bool,
+  backend: Option<Box<dyn DesktopIapBackend>>,
+  locale: Option<LocaleProvider>,
+  #[cfg(feature = "mock")] mock_options: MockOptions,
 ) -> crate::Result<Iap<R>> {
-  Ok(Iap(app.clone()))
+  let locale = locale.unwrap_or_else(|| Arc::new(sys_locale::get_locale));
+
+  // A custom backend wins, then the first bundled one that is available.
+  #[cfg(feature = "mock")]
+  let mock_options = mock_options
+    .with_config(&config)?
+    .with_env()?;
+  // Release builds with a store backend must not sell through the mock
+  // store just because the feature was left on, so it has to be opted into.
+  #[cfg(feature = "mock")]
+  let store_backend = cfg!(any(
+    all(windows, feature = "microsoft-store"),
+    all(target_os = "macos", feature = "mac-app-store")
+  ));
+  #[cfg(feature = "mock")]
+  let mock = mock_options
+    .enabled
+    .unwrap_or(backend.is_none() && !store_backend)
+    .then(|| Arc::new(mock_store(app, mock_options, locale.clone())));
+  #[cfg(feature = "mock")]
+  let backend = match &mock {
+    Some(mock) => Some(Box::new(mock.clone()) as Box<dyn DesktopIapBackend>),
+    None => backend,
+  };
+  #[cfg(all(windows, feature = "microsoft-store"))]
+  let backend = backend.or_else(|| {
+    MicrosoftStore::new(app).map(|store| Box::new(store) as Box<dyn DesktopIapBackend>)
+  });
+  #[cfg(all(target_os = "macos", feature = "mac-app-store"))]
+  let backend = backend.or_else(|| {
+    MacAppStore::new(config.app_store_id.clone())
+      .map(|store| Box::new(store) as Box<dyn DesktopIapBackend>)
+  });
+
+  let store = backend.is_some();
+  let iap = Iap::new(
+    app,
+    backend.unwrap_or_else(|| Box::new(Unsupported { locale })),
+    UnfinishedPurchases::in_app_data_dir(app),
+    config,
+    retry_policy,
+    reject_unknown_purchases,
+    #[cfg(feature = "mock")]
+    mock,
+  );
+  Ok(Iap { store, ..iap })
+}
+
+/// Creates the mock store, keeping purchases in the app data directory unless
+/// another file was configured, and confirming them with a dialog unless
+/// headless.
+#[cfg(feature = "mock")]
+fn mock_store<R: Runtime>(
+  app: &AppHandle<R>,
+  options: MockOptions,
+  locale: LocaleProvider,
+) -> MockStore {
+  let path = options.storage_path.or_else(|| {
+    app
+      .path()
+      .app_data_dir()
+      .map(|dir| dir.join(MOCK_PURCHASES_FILE))
+      .inspect_err(|e| log::warn!("Mock purchases won't persist: {}", e))
+      .ok()
+  });
+  let store = match path {
+    Some(path) => MockStore::persistent(options.catalog, path),
+    None => MockStore::new(options.catalog),
+  }
+  .with_locale_provider(locale);
+  for product_id in &options.owned {
+    store.grant(product_id);
+  }
+  store.set_latency(options.latency);
+  if options.headless {
+    return store;
+  }
+  let app = app.clone();
+  store.with_confirmation(move |product| crate::mock_dialog::confirm_purchase(&app, product))
+}
+
+/// Queries the configured products and caches them, reporting failures
+/// through the error event as they must not fail `initialize`.
+fn spawn_preload<R: Runtime>(
+    backend: &Arc<dyn DesktopIapBackend>,
+    products: &Arc<ProductCache>,
+    config: &Arc<Config>,
+    app: &AppHandle<R>,
+    streams: &Arc<PurchaseStreams>,
+    retry_policy: RetryPolicy,
+    shutdown: CancellationToken,
+) {
+    let (backend, products) = (backend.clone(), products.clone());
+    let (config, app, streams) = (config.clone(), app.clone(), streams.clone());
+    std::thread::spawn(move || {
+        let result = retry(&retry_policy, &shutdown, || {
+            backend.query_product_details(&config.products)
+        });
+        let emitted = match result {
+            Ok(response) => {
+                products.insert(&response.product_details);
+                config.emit(&app, crate::PRODUCTS_LOADED_EVENT, &response)
+            }
+            Err(e) => {
+                log::warn!("Failed to preload products: {}", e);
+                let error = IAPError::from(&e);
+                streams.send(PurchaseUpdate::Error(error.clone()));
+                config.emit(&app, crate::ERROR_EVENT, error)
+            }
+        };
+        if let Err(e) = emitted {
+            log::error!("Failed to emit preloaded products: {}", e);
+        }
+    });
 }
 
 /// Access to the iap APIs.
-pub struct Iap<R: Runtime>(AppHandle<R>);
+///
+/// Calls are served by the backend registered with
+/// [`Builder::desktop_backend`](crate::Builder::desktop_backend), or else by
+/// the first available bundled backend:
+///
+/// - the [`MockStore`](crate::MockStore), with the `mock` feature enabled;
+/// - the Microsoft Store, with the `microsoft-store` feature enabled on
+///   Windows, for apps installed from the Store;
+/// - StoreKit, with the `mac-app-store` feature enabled on macOS, for
+///   sandboxed Mac App Store builds.
+///
+/// Without a backend there is no store on desktop, and every method that
+/// reaches the store returns `Error::PlatformNotSupported`.
+///
+/// Methods reaching the store are async and run the store call on a worker
+/// thread, so they can be awaited from any async runtime. Clones share the
+/// same connection and state.
+pub struct Iap<R: Runtime> {
+    backend: Arc<dyn DesktopIapBackend>,
+    events: PurchaseEventSink,
+    config: Arc<Config>,
+    connection: Arc<Connection>,
+    /// Cleared on every connection state change.
+    country_code: Arc<Cached<String>>,
+    /// Serializes `initialize` so concurrent callers share a single connection.
+    init_lock: Arc<Mutex<()>>,
+    /// Products owned according to the reported purchases.
+    entitlements: Arc<Entitlements>,
+    /// Runs the `autoRestoreOnInit` query after the first initialization.
+    auto_restore: Arc<Once>,
+    /// Purchases awaiting completion, kept across restarts.
+    unfinished: Arc<UnfinishedPurchases>,
+    /// Reports the unfinished purchases after the first initialization.
+    reconcile: Arc<Once>,
+    /// Product details cached by `preloadProducts`.
+    products: Arc<ProductCache>,
+    /// Product queries in progress, keyed by the sorted product IDs.
+    queries: Arc<InFlight<Vec<String>, ProductDetailsResponse>>,
+    /// Restores in progress or within the cooldown, keyed by the application
+    /// user name.
+    restores: Arc<InFlight<Option<String>, ()>>,
+    /// Purchases being completed, keyed by transaction ID and purchase token,
+    /// so completing one twice at once makes a single store call.
+    completions: Arc<InFlight<(Option<String>, String), ()>>,
+    /// Purchase funnel counts reported by `metrics`.
+    metrics: Arc<Counters>,
+    /// Streams handed out by `purchase_stream`.
+    streams: Arc<PurchaseStreams>,
+    /// Buy flows of `purchase()` in guest-js awaiting their purchase.
+    flows: Arc<PurchaseFlows>,
+    /// Runs the `preloadProducts` query after the first initialization.
+    preload: Arc<Once>,
+    /// Started by `initialize` and stopped by `end_connection`.
+    transaction_updates: Arc<TransactionUpdates>,
+    /// Used by purchases and restores that don't pass an application user
+    /// name.
+    application_user_name: Arc<Mutex<Option<String>>>,
+    retry_policy: RetryPolicy,
+    reject_unknown_purchases: bool,
+    /// Whether a store serves the calls, rather than the `Unsupported`
+    /// fallback set up by `init`.
+    store: bool,
+    /// Cancelled by `end_connection` so pending retries give up, and
+    /// replaced when `initialize` connects again.
+    shutdown: Arc<Mutex<CancellationToken>>,
+    #[cfg(feature = "mock")]
+    mock: Option<Arc<MockStore>>,
+    app: AppHandle<R>,
+}
+
+impl<R: Runtime> Clone for Iap<R> {
+    fn clone(&self) -> Self {
+        Self {
+            backend: self.backend.clone(),
+            events: self.events.clone(),
+            config: self.config.clone(),
+            connection: self.connection.clone(),
+            country_code: self.country_code.clone(),
+            init_lock: self.init_lock.clone(),
+            entitlements: self.entitlements.clone(),
+            auto_restore: self.auto_restore.clone(),
+            unfinished: self.unfinished.clone(),
+            reconcile: self.reconcile.clone(),
+            products: self.products.clone(),
+            queries: self.queries.clone(),
+            restores: self.restores.clone(),
+            completions: self.completions.clone(),
+            metrics: self.metrics.clone(),
+            streams: self.streams.clone(),
+            flows: self.flows.clone(),
+            preload: self.preload.clone(),
+            transaction_updates: self.transaction_updates.clone(),
+            application_user_name: self.application_user_name.clone(),
+            retry_policy: self.retry_policy,
+            reject_unknown_purchases: self.reject_unknown_purchases,
+            store: self.store,
+            shutdown: self.shutdown.clone(),
+            #[cfg(feature = "mock")]
+            mock: self.mock.clone(),
+            app: self.app.clone(),
+        }
+    }
+}
 
 impl<R: Runtime> Iap<R> {
+    fn new(
+        app: &AppHandle<R>,
+        backend: Box<dyn DesktopIapBackend>,
+        unfinished: UnfinishedPurchases,
+        config: Config,
+        retry_policy: RetryPolicy,
+        reject_unknown_purchases: bool,
+        #[cfg(feature = "mock")] mock: Option<Arc<MockStore>>,
+    ) -> Self {
+        let backend: Arc<dyn DesktopIapBackend> = Arc::from(backend);
+        let config = Arc::new(config);
+        let entitlements = Arc::new(Entitlements::new(config.owned_on_hold));
+        let unfinished = Arc::new(unfinished);
+        let country_code = Arc::new(Cached::default());
+        let products = Arc::new(if config.persist_products {
+            ProductCache::in_app_data_dir(app)
+        } else {
+            ProductCache::default()
+        });
+        let shutdown = Arc::new(Mutex::new(CancellationToken::new()));
+        let streams = Arc::new(PurchaseStreams::default());
+        let flows = Arc::new(PurchaseFlows::default());
+        let connection = {
+            let (handle, config) = (app.clone(), config.clone());
+            let country_code = country_code.clone();
+            Arc::new(Connection::new(move |change| {
+                country_code.clear();
+                if let Err(e) = config.emit(&handle, crate::CONNECTION_STATE_EVENT, change) {
+                    log::error!("Failed to emit connection state: {}", e);
+                }
+            }))
+        };
+        let storefront_changed = {
+            let (handle, backend, config) = (app.clone(), backend.clone(), config.clone());
+            let (products, country_code) = (products.clone(), country_code.clone());
+            let (connection, shutdown, streams) = (connection.clone(), shutdown.clone(), streams.clone());
+            move || {
+                country_code.clear();
+                products.clear();
+                if let Err(e) = config.emit(&handle, crate::STOREFRONT_CHANGED_EVENT, ()) {
+                    log::error!("Failed to emit storefront change: {}", e);
+                }
+                // Before the first initialization, `initialize` preloads them.
+                let connected = matches!(
+                    connection.state(),
+                    ConnectionState::Ready | ConnectionState::Reconnecting
+                );
+                if connected && config.preloads_on_storefront_change() {
+                    let shutdown = shutdown.lock().unwrap_or_else(PoisonError::into_inner).clone();
+                    spawn_preload(&backend, &products, &config, &handle, &streams, retry_policy, shutdown);
+                }
+            }
+        };
+        let events = {
+            let (handle, backend, config) = (app.clone(), backend.clone(), config.clone());
+            let (entitlements, unfinished) = (entitlements.clone(), unfinished.clone());
+            let (connection, streams, flows) = (connection.clone(), streams.clone(), flows.clone());
+            PurchaseEventSink::new(move |purchases| {
+                // Late updates, e.g. of a purchase the store was still
+                // processing, aren't reported once the connection is closed.
+                if connection.state() == ConnectionState::Closed {
+                    log::debug!("Dropping {} purchase updates after end_connection", purchases.len());
+                    return;
+                }
+                let purchases = config.reported_purchases(purchases);
+                let purchases =
+                    config.finish_purchases(&purchases, |purchase| backend.complete_purchase(purchase));
+                entitlements.update(&purchases);
+                unfinished.update(&purchases);
+                #[cfg(feature = "tracing")]
+                crate::instrument::purchases_reported(&purchases);
+                if purchases.is_empty() {
+                    return;
+                }
+                let correlated = flows.correlate(&purchases);
+                if let Err(e) = config.emit(&handle, crate::PURCHASE_UPDATE_EVENT, correlated) {
+                    log::error!("Failed to emit purchase update: {}", e);
+                }
+                streams.send(PurchaseUpdate::Purchases(purchases));
+            })
+            .on_storefront_change(storefront_changed)
+        };
+        #[cfg(feature = "mock")]
+        if let Some(mock) = &mock {
+            mock.set_storefront_listener(events.clone());
+        }
+        let restores = Arc::new(InFlight::with_cooldown(config.restore_cooldown()));
+        Self {
+            backend,
+            events,
+            config,
+            connection,
+            country_code,
+            init_lock: Arc::default(),
+            entitlements,
+            auto_restore: Arc::new(Once::new()),
+            unfinished,
+            reconcile: Arc::new(Once::new()),
+            products,
+            queries: Arc::default(),
+            restores,
+            completions: Arc::default(),
+            metrics: Arc::default(),
+            streams,
+            flows,
+            preload: Arc::new(Once::new()),
+            transaction_updates: Arc::default(),
+            application_user_name: Arc::default(),
+            retry_policy,
+            reject_unknown_purchases,
+            store: true,
+            shutdown,
+            #[cfg(feature = "mock")]
+            mock,
+            app: app.clone(),
+        }
+    }
+
+    /// Creates the iap APIs served by a headless, in-memory [`MockStore`]
+    /// selling `catalog`, for unit testing code that takes `&Iap<R>` without
+    /// registering the plugin.
+    ///
+    /// Failed queries aren't retried, and purchases are reported through
+    /// [`PURCHASE_UPDATE_EVENT`](crate::PURCHASE_UPDATE_EVENT) events on `app`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use tauri::Listener;
+    /// use tauri_plugin_iap::{
+    ///     Iap, MockOperation, ProductDetails, PurchaseParam, PURCHASE_UPDATE_EVENT,
+    /// };
+    ///
+    /// // Code under test.
+    /// async fn unlock_premium<R: tauri::Runtime>(iap: &Iap<R>, premium: ProductDetails) -> bool {
+    ///     iap.buy_non_consumable(PurchaseParam {
+    ///         product_details: premium,
+    ///         application_user_name: None,
+    ///     })
+    ///     .await
+    ///     .is_ok()
+    /// }
+    ///
+    /// let premium = ProductDetails {
+    ///     id: "premium".into(),
+    ///     title: "Premium".into(),
+    ///     description: "Unlocks all features".into(),
+    ///     price: "$4.99".into(),
+    ///     raw_price: 4.99,
+    ///     currency_code: "USD".into(),
+    ///     currency_symbol: "$".into(),
+    ///     subscription_period: None,
+    ///     free_trial_period: None,
+    ///     subscription_group_id: None,
+    /// };
+    ///
+    /// let app = tauri::test::mock_app();
+    /// let iap = Iap::mock(app.handle(), vec![premium.clone()]);
+    /// app.listen(PURCHASE_UPDATE_EVENT, |event| println!("purchases: {}", event.payload()));
+    ///
+    /// tauri::async_runtime::block_on(iap.initialize()).unwrap();
+    /// assert!(tauri::async_runtime::block_on(unlock_premium(&iap, premium)));
+    /// let mock = iap.mock_store().unwrap();
+    /// assert_eq!(mock.purchases().len(), 1);
+    /// assert_eq!(mock.calls()[1].operation, MockOperation::BuyNonConsumable);
+    /// ```
+    #[cfg(feature = "mock")]
+    pub fn mock(app: &AppHandle<R>, catalog: Vec<ProductDetails>) -> Self {
+        let mock = Arc::new(MockStore::new(catalog));
+        Self::new(
+            app,
+            Box::new(mock.clone()),
+            UnfinishedPurchases::load(None),
+            Config::default(),
+            RetryPolicy::none(),
+            true,
+            Some(mock),
+        )
+    }
+
+    /// Emits purchase updates as if the store reported them, e.g. a pending
+    /// purchase that was approved. They go through the same path as the
+    /// store's own updates: entitlements and unfinished purchases are
+    /// updated, `autoFinishTransactions` applies, and the purchase update
+    /// event is emitted and sent to the purchase streams.
+    #[cfg(feature = "mock")]
+    pub fn push_purchase_updates(&self, purchases: &[PurchaseDetails]) {
+        self.events.emit(purchases);
+    }
+
+    /// The plugin configuration from `tauri.conf.json`.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The policy used to retry transient store failures.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// The policy used to retry the store connection.
+    pub fn connection_retry_policy(&self) -> &ConnectionRetryPolicy {
+        &self.config.connection_retry
+    }
+
+    /// Whether `complete_purchase` rejects purchases the store never reported.
+    pub fn rejects_unknown_purchases(&self) -> bool {
+        self.reject_unknown_purchases
+    }
+
+    /// Sets the application user name used by purchases and restores that
+    /// don't pass one, or clears it with `None`, e.g. when the user logs out.
+    pub fn set_application_user_name(&self, name: Option<String>) {
+        *self
+            .application_user_name
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = name;
+    }
+
+    /// The application user name used by purchases and restores that don't
+    /// pass one.
+    pub fn application_user_name(&self) -> Option<String> {
+        self.application_user_name
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// `purchase_param`, with the stored application user name unless it
+    /// has one.
+    fn with_application_user_name(&self, mut purchase_param: PurchaseParam) -> PurchaseParam {
+        if purchase_param.application_user_name.is_none() {
+            purchase_param.application_user_name = self.application_user_name();
+        }
+        purchase_param
+    }
+
+    /// Checks product IDs against the rules of the backend's store, unless
+    /// `skip_product_id_validation` is set.
+    fn validate_product_ids(&self, product_ids: &[String]) -> crate::Result<()> {
+        match self.backend.product_id_rules() {
+            Some(rules) if !self.config.skip_product_id_validation => rules.validate(product_ids),
+            _ => Ok(()),
+        }
+    }
+
+    /// Closes the store connection, gives up pending retries and stops
+    /// reporting purchase updates. Store calls then fail with
+    /// `Error::NotInitialized` until `initialize` connects again. This also
+    /// runs when the plugin is dropped.
+    pub fn end_connection(&self) {
+        self.connection.report(ConnectionState::Closed);
+        self.shutdown().cancel();
+        self.transaction_updates
+            .stop(|| self.backend.stop_transaction_updates());
+    }
+
+    /// Opens a stream of the purchase updates, restore completions and
+    /// background errors reported from now on, with the same purchases as the
+    /// purchase update event but their verification data always included.
+    /// The stream ends when the plugin is dropped.
+    ///
+    /// Any number of streams can be open next to the webview events. Each
+    /// buffers [`PURCHASE_STREAM_BUFFER`](crate::PURCHASE_STREAM_BUFFER)
+    /// updates: reporting never waits for a slow receiver, whose stream
+    /// misses the updates while full and then gets a
+    /// [`PurchaseUpdate::Lagged`] with their count.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # async fn sync<R: tauri::Runtime>(app: tauri::AppHandle<R>) {
+    /// use tauri_plugin_iap::{IapExt, PurchaseUpdate};
+    ///
+    /// let mut updates = app.iap().purchase_stream();
+    /// while let Some(update) = updates.recv().await {
+    ///     match update {
+    ///         PurchaseUpdate::Purchases(purchases) => println!("{} purchases", purchases.len()),
+    ///         // Missed purchases show up in the next restore.
+    ///         PurchaseUpdate::Lagged(_) => {
+    ///             app.iap().restore_purchases(None).await.ok();
+    ///         }
+    ///         _ => {}
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn purchase_stream(&self) -> tokio::sync::mpsc::Receiver<PurchaseUpdate> {
+        self.streams.subscribe()
+    }
+
+    /// Ends the purchase streams, when the plugin is dropped.
+    pub(crate) fn close_purchase_streams(&self) {
+        self.streams.close();
+    }
+
+    /// The buy flows the purchase update events are correlated with.
+    pub(crate) fn purchase_flows(&self) -> &PurchaseFlows {
+        &self.flows
+    }
+
+    /// Runs a blocking store call on a worker thread with a clone of `self`,
+    /// so awaiting it doesn't hold up the caller's runtime. Nothing is locked
+    /// around the call: concurrent calls only wait for each other where an
+    /// operation shares one store call, e.g. initializing.
+    async fn on_worker<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Self) -> crate::Result<T> + Send + 'static,
+    ) -> crate::Result<T> {
+        let iap = self.clone();
+        match tauri::async_runtime::spawn_blocking(move || call(&iap)).await {
+            Ok(result) => result,
+            Err(e) => Err(Error::InternalError(e.to_string())),
+        }
+    }
+
+    /// The token cancelled by the next `end_connection`.
+    fn shutdown(&self) -> CancellationToken {
+        self.shutdown
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// The state of the store connection.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.connection.state()
+    }
+
+    /// Fails with `Error::NotInitialized` until `initialize` has succeeded, or
+    /// after `end_connection`.
+    fn ensure_initialized(&self) -> crate::Result<()> {
+        self.connection.ensure_ready()
+    }
+
+    /// Like `ensure_initialized`, except that without a store the country
+    /// code is derived from the locale, which needs no connection.
+    fn ensure_country_code_ready(&self) -> crate::Result<()> {
+        if self.store {
+            self.ensure_initialized()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The mock store, unless a custom backend serves calls instead.
+    #[cfg(feature = "mock")]
+    pub fn mock_store(&self) -> Option<&MockStore> {
+        self.mock.as_deref()
+    }
+
+    /// Wipes the purchases of the mock store, in memory and in its storage
+    /// file, and restarts its generated order IDs, e.g. to go through a
+    /// purchase flow again. The wiped purchases are reported as `Canceled`
+    /// through the purchase update event, and no product is owned after.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported` unless the mock store serves calls.
+    #[cfg(feature = "mock")]
+    pub fn mock_clear_purchases(&self) -> crate::Result<()> {
+        let mock = self
+            .mock_store()
+            .ok_or_else(|| Error::FeatureNotSupported("The mock store is not in use".into()))?;
+        let revoked: Vec<PurchaseDetails> = mock
+            .clear_purchases()
+            .into_iter()
+            .map(|purchase| PurchaseDetails {
+                status: PurchaseStatus::Canceled,
+                pending_complete_purchase: false,
+                ..purchase
+            })
+            .collect();
+        self.entitlements.replace(&[]);
+        self.events.emit(&revoked);
+        Ok(())
+    }
+
     /// Initialize the in-app purchase system.
     ///
+    /// The connection is shared by every window, so each may call this:
+    /// calling it again after a successful initialization is a no-op, and
+    /// concurrent calls wait for the first one. Transient failures are retried according to the connection retry
+    /// policy. After `end_connection`, this connects again. After the first success, the owned purchases are reported in
+    /// the background with `autoRestoreOnInit`, or else only the ones left
+    /// unfinished by a previous run, and the configured products are cached
+    /// with `preloadProducts`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::PlatformNotSupported` without a store backend.
+    pub async fn initialize(&self) -> crate::Result<()> {
+        self.on_worker(move |iap| iap.initialize_blocking()).await
+    }
+
+    fn initialize_blocking(&self) -> crate::Result<()> {
+        let _guard = self.init_lock.lock().unwrap_or_else(PoisonError::into_inner);
+        match self.connection.state() {
+            ConnectionState::Ready | ConnectionState::Reconnecting => return Ok(()),
+            ConnectionState::Closed => {
+                *self.shutdown.lock().unwrap_or_else(PoisonError::into_inner) = CancellationToken::new();
+            }
+            ConnectionState::Uninitialized | ConnectionState::Initializing => {}
+        }
+        self.connection.transition(ConnectionState::Initializing)?;
+        let connected = retry_connection(&self.config.connection_retry, &self.shutdown(), || {
+            self.backend.initialize()
+        });
+        #[cfg(feature = "tracing")]
+        crate::instrument::connection(&connected);
+        // `end_connection` was called while connecting.
+        if self.connection.state() == ConnectionState::Closed {
+            return Err(Error::NotInitialized);
+        }
+        self.connection.connected(&connected)?;
+        connected?;
+        // Failures must not fail `initialize`; purchases are still reported
+        // by the calls that make them.
+        if let Err(e) = self
+            .transaction_updates
+            .start(|| self.backend.start_transaction_updates(&self.events))
+        {
+            log::warn!("Failed to listen for transaction updates: {}", e);
+        }
+        if self.config.auto_restore_on_init {
+            self.auto_restore.call_once(|| self.spawn_auto_restore());
+        } else if !self.unfinished.is_empty() {
+            self.reconcile.call_once(|| self.spawn_reconcile());
+        }
+        if self.config.preload_products && !self.config.products.is_empty() {
+            self.preload.call_once(|| {
+                let shutdown = self.shutdown();
+                spawn_preload(
+                    &self.backend,
+                    &self.products,
+                    &self.config,
+                    &self.app,
+                    &self.streams,
+                    self.retry_policy,
+                    shutdown,
+                )
+            });
+        }
+        Ok(())
+    }
+
+    /// Reports the owned purchases without store UI, logging failures as
+    /// they must not fail `initialize`.
+    fn spawn_auto_restore(&self) {
+        let (backend, events) = (self.backend.clone(), self.events.clone());
+        let (entitlements, unfinished) = (self.entitlements.clone(), self.unfinished.clone());
+        std::thread::spawn(move || {
+            match owned_purchases(backend.as_ref(), &events) {
+                Ok(owned) => {
+                    entitlements.replace(&owned);
+                    unfinished.reconcile(&owned);
+                }
+                Err(e) => log::warn!("Failed to restore purchases on initialize: {}", e),
+            }
+        });
+    }
+
+    /// Reports the purchases a previous run left unfinished that the store
+    /// still has awaiting completion, forgetting the others.
+    fn spawn_reconcile(&self) {
+        let (backend, events) = (self.backend.clone(), self.events.clone());
+        let unfinished = self.unfinished.clone();
+        std::thread::spawn(move || {
+            match owned_purchases(backend.as_ref(), &PurchaseEventSink::new(|_| {})) {
+                Ok(owned) => events.emit(&unfinished.reconcile(&owned)),
+                Err(e) => log::warn!("Failed to check unfinished purchases on initialize: {}", e),
+            }
+        });
+    }
+
+    /// Whether the user owns the product, according to the purchases the
+    /// store reported since the app started, including restores and the
+    /// `autoRestoreOnInit` query. While the store is unreachable, products
+    /// kept by the entitlement cache count as owned too.
+    pub fn is_owned(&self, product_id: &str) -> bool {
+        self.entitlements.is_owned(product_id)
+            || (self.is_offline() && self.entitlements.is_cached(product_id))
+    }
+
+    /// The products the user owns, according to the purchases the store
+    /// reported since the app started. While the store is unreachable, the
+    /// products the entitlement cache kept from a previous run are added with
+    /// the `Cache` source.
+    pub fn owned_products(&self) -> Vec<OwnedProduct> {
+        self.entitlements.owned_products(self.is_offline())
+    }
+
+    /// What the plugin considers owned: the owned products with the states
+    /// and dates of their purchases, and the cached ones the store didn't
+    /// report, without tokens or receipts, e.g. to attach to a support
+    /// ticket.
+    pub fn export_entitlements(&self) -> EntitlementsExport {
+        EntitlementsExport::new(self.entitlements.export())
+    }
+
+    /// Replaces the owned products with an export, e.g. a state crafted by
+    /// QA, rewriting the entitlement cache. The change is reported through
+    /// the purchase update event: products no longer owned as `Canceled`,
+    /// then the imported ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported` in release builds unless the
+    /// mock store serves calls, and `Error::DeveloperError` if the export is
+    /// of another schema version.
+    pub fn import_entitlements(&self, export: EntitlementsExport) -> crate::Result<()> {
+        #[cfg(feature = "mock")]
+        let mock = self.mock.is_some();
+        #[cfg(not(feature = "mock"))]
+        let mock = false;
+        EntitlementsExport::check_import_allowed(cfg!(debug_assertions), mock)?;
+        export.check_schema_version()?;
+        self.events.emit(&self.entitlements.import(&export.entitlements));
+        Ok(())
+    }
+
+    /// The purchases granting an entitlement right now, read from the store
+    /// without any UI or purchase update events.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::PlatformNotSupported` without a store backend.
+    pub async fn current_entitlements(&self) -> crate::Result<Vec<PurchaseDetails>> {
+        self.on_worker(move |iap| iap.current_entitlements_blocking()).await
+    }
+
+    fn current_entitlements_blocking(&self) -> crate::Result<Vec<PurchaseDetails>> {
+        self.ensure_initialized()?;
+        let owned = Arc::new(Mutex::new(Vec::new()));
+        let collect = {
+            let owned = owned.clone();
+            PurchaseEventSink::new(move |purchases| {
+                owned
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .extend_from_slice(purchases);
+            })
+        };
+        self.backend.owned_purchases(&collect)?;
+        let owned = std::mem::take(&mut *owned.lock().unwrap_or_else(PoisonError::into_inner));
+        Ok(owned
+            .into_iter()
+            .filter(|purchase| {
+                matches!(purchase.status, PurchaseStatus::Purchased | PurchaseStatus::Restored)
+            })
+            .collect())
+    }
+
+    /// Keeps the owned products in `cache`, set up by the plugin builder.
+    #[cfg(feature = "entitlement-cache")]
+    pub(crate) fn set_entitlement_cache(&self, cache: crate::entitlement_cache::EntitlementCache) {
+        self.entitlements.set_cache(cache);
+    }
+
+    /// Whether the store can't be reached, so the cached entitlements are
+    /// used.
+    fn is_offline(&self) -> bool {
+        self.connection.state() != ConnectionState::Ready
+    }
+
+    /// The verification payload of an owned purchase, by its transaction ID.
+    ///
     /// # Errors
     ///
-    /// Always returns `Error::PlatformNotSupported` on desktop platforms.
-    pub fn initialize(&self) -> crate::Result<()> {
-        Err(crate::Error::PlatformNotSupported)
+    /// `Error::UnknownPurchase` if the store reported no owned purchase with
+    /// the ID since the app started, or the errors of
+    /// [`PurchaseDetails::verification_payload`].
+    pub fn export_verification_payload(
+        &self,
+        purchase_id: &str,
+    ) -> crate::Result<VerificationPayload> {
+        self.entitlements
+            .find(purchase_id)
+            .ok_or_else(|| {
+                crate::Error::UnknownPurchase(format!("No owned purchase has the ID {purchase_id}"))
+            })?
+            .verification_payload()
     }
 
     /// Check if in-app purchases are available on this platform.
     ///
     /// # Returns
     ///
-    /// Returns `Error::PlatformNotSupported` as IAP is not available on desktop.
-    pub fn is_available(&self) -> crate::Result<bool> {
-        Err(crate::Error::PlatformNotSupported)
+    /// Returns `false` without a store backend, so the same check can decide
+    /// whether to show store UI on every platform, for sideloaded builds
+    /// with `require_store_install`, and once the store reported it is
+    /// unavailable.
+    pub async fn is_available(&self) -> crate::Result<bool> {
+        self.on_worker(move |iap| iap.is_available_blocking()).await
+    }
+
+    fn is_available_blocking(&self) -> crate::Result<bool> {
+        if self.config.require_store_install && self.backend.install_source()? == InstallSource::Sideload {
+            log::warn!("In-app purchases are unavailable, the app wasn't installed from a store");
+            return Ok(false);
+        }
+        if !self.connection.store_present() {
+            return Ok(false);
+        }
+        match self.backend.is_available() {
+            Err(e) if e.code() == IapErrorCode::StoreUnavailable => Ok(false),
+            available => available,
+        }
+    }
+
+    /// Environment details to attach to bug reports: the plugin version,
+    /// the store serving the calls, the connection and the configuration in
+    /// effect, without keys or purchases.
+    pub async fn diagnostics(&self) -> crate::Result<Diagnostics> {
+        self.on_worker(move |iap| iap.diagnostics_blocking()).await
+    }
+
+    fn diagnostics_blocking(&self) -> crate::Result<Diagnostics> {
+        let mut diagnostics = Diagnostics::new(&self.config, self.connection_state());
+        diagnostics.transaction_listener = self.transaction_updates.is_running();
+        self.backend.describe(&mut diagnostics);
+        Ok(diagnostics)
+    }
+
+    /// Purchase funnel counts since the app started or the last
+    /// [`reset_metrics`](Self::reset_metrics).
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.snapshot()
+    }
+
+    /// Zeroes the counts reported by [`metrics`](Self::metrics), e.g. between
+    /// test runs.
+    pub fn reset_metrics(&self) {
+        self.metrics.reset();
+    }
+
+    /// Where the app was installed from, e.g. to explain why purchases fail.
+    /// Mac App Store builds tell App Store and TestFlight installs apart;
+    /// other stores report `InstallSource::Unknown`, and development builds
+    /// `InstallSource::DevBuild`.
+    ///
+    /// Unlike the other store calls this works before `initialize`, like
+    /// [`is_available`](Self::is_available) which relies on it, so the app
+    /// can tell why the store can't be used.
+    pub async fn install_source(&self) -> crate::Result<InstallSource> {
+        self.on_worker(move |iap| iap.backend.install_source()).await
     }
 
     /// Query details for multiple products from the store.
     ///
+    /// Products cached by `preloadProducts` are returned without querying
+    /// the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `product_ids` - List of product identifiers to query, or none to
+    ///   query the products listed in the configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::PlatformNotSupported` without a store backend.
+    pub async fn query_product_details(&self, product_ids: Vec<String>) -> crate::Result<ProductDetailsResponse> {
+        self.query_product_details_with_policy(product_ids, &self.retry_policy).await
+    }
+
+    /// Query details for multiple products, retrying transient failures according to `retry_policy`.
+    ///
     /// # Arguments
     ///
     /// * `product_ids` - List of product identifiers to query
+    /// * `retry_policy` - Policy used to retry transient failures
     ///
     /// # Errors
     ///
-    /// Always returns `Error::PlatformNotSupported` on desktop platforms.
-    pub fn query_product_details(&self, _product_ids: Vec<String>) -> crate::Result<ProductDetailsResponse> {
-        Err(crate::Error::PlatformNotSupported)
+    /// Returns `Error::PlatformNotSupported` without a store backend.
+    pub async fn query_product_details_with_policy(
+        &self,
+        product_ids: Vec<String>,
+        retry_policy: &RetryPolicy,
+    ) -> crate::Result<ProductDetailsResponse> {
+        let retry_policy = *retry_policy;
+        self.on_worker(move |iap| iap.query_product_details_with_policy_blocking(product_ids, &retry_policy))
+            .await
+    }
+
+    fn query_product_details_with_policy_blocking(
+        &self,
+        product_ids: Vec<String>,
+        retry_policy: &RetryPolicy,
+    ) -> crate::Result<ProductDetailsResponse> {
+        self.ensure_initialized()?;
+        self.metrics.product_queried();
+        let product_ids = if product_ids.is_empty() {
+            self.config.products.clone()
+        } else {
+            product_ids
+        };
+        self.validate_product_ids(&product_ids)?;
+        let (cached, missing) = self.products.lookup(&product_ids);
+        if missing.is_empty() {
+            return Ok(ProductDetailsResponse {
+                product_details: cached,
+                ..Default::default()
+            });
+        }
+        let mut key = missing.clone();
+        key.sort();
+        let mut response = self.queries.run(key, || {
+            retry(retry_policy, &self.shutdown(), || {
+                self.backend.query_product_details(&missing)
+            })
+        })?;
+        response.product_details.splice(0..0, cached);
+        Ok(response)
     }
 
     /// Initiate purchase of a non-consumable product.
     ///
     /// # Arguments
     ///
-    /// * `purchase_param` - Parameters for the purchase
+    /// * `purchase_param` - Parameters for the purchase, using the stored
+    ///   application user name if it has none
     ///
     /// # Errors
     ///
-    /// Always returns `Error::PlatformNotSupported` on desktop platforms.
-    pub fn buy_non_consumable(&self, _purchase_param: PurchaseParam) -> crate::Result<bool> {
-        Err(crate::Error::PlatformNotSupported)
+    /// Returns `Error::PlatformNotSupported` without a store backend.
+    pub async fn buy_non_consumable(&self, purchase_param: PurchaseParam) -> crate::Result<bool> {
+        self.on_worker(move |iap| iap.buy_non_consumable_blocking(purchase_param)).await
+    }
+
+    fn buy_non_consumable_blocking(&self, purchase_param: PurchaseParam) -> crate::Result<bool> {
+        self.ensure_initialized()?;
+        self.metrics.purchase_flow(
+            |purchased| *purchased,
+            || {
+                self.validate_product_ids(std::slice::from_ref(&purchase_param.product_details.id))?;
+                let purchase_param = self.with_application_user_name(purchase_param);
+                self.backend.buy_non_consumable(&purchase_param, &self.events)
+            },
+        )
     }
 
     /// Initiate purchase of a consumable product.
     ///
     /// # Arguments
     ///
-    /// * `purchase_param` - Parameters for the purchase
+    /// * `purchase_param` - Parameters for the purchase, using the stored
+    ///   application user name if it has none
     /// * `auto_consume` - Whether to automatically consume the purchase after successful transaction
     ///
     /// # Errors
     ///
-    /// Always returns `Error::PlatformNotSupported` on desktop platforms.
-    pub fn buy_consumable(&self, _purchase_param: PurchaseParam, _auto_consume: bool) -> crate::Result<bool> {
-        Err(crate::Error::PlatformNotSupported)
+    /// Returns `Error::PlatformNotSupported` without a store backend.
+    pub async fn buy_consumable(&self, purchase_param: PurchaseParam, auto_consume: bool) -> crate::Result<bool> {
+        self.on_worker(move |iap| iap.buy_consumable_blocking(purchase_param, auto_consume)).await
+    }
+
+    fn buy_consumable_blocking(&self, purchase_param: PurchaseParam, auto_consume: bool) -> crate::Result<bool> {
+        self.ensure_initialized()?;
+        self.metrics.purchase_flow(
+            |purchased| *purchased,
+            || {
+                self.validate_product_ids(std::slice::from_ref(&purchase_param.product_details.id))?;
+                let purchase_param = self.with_application_user_name(purchase_param);
+                self.backend
+                    .buy_consumable(&purchase_param, auto_consume, &self.events)
+            },
+        )
+    }
+
+    /// Switch the user's active subscription to `new_product_id`.
+    ///
+    /// The subscription to replace is found among the current entitlements:
+    /// the one of the same subscription group when the store reports groups,
+    /// or else the only active one. The new purchase is reported like any
+    /// other and returned, or `None` while it's pending.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_product_id` - The subscription to switch to
+    /// * `replacement_mode` - How the change is billed, where the store lets
+    ///   the app decide
+    ///
+    /// # Errors
+    ///
+    /// - `Error::NoActiveSubscription` if the user has no subscription to
+    ///   replace.
+    /// - `Error::AmbiguousSubscription` listing the candidates if several
+    ///   subscriptions could be replaced.
+    /// - `Error::FeatureNotSupported` if the store doesn't switch
+    ///   subscriptions.
+    pub async fn buy_upgrade(
+        &self,
+        new_product_id: String,
+        replacement_mode: ReplacementMode,
+    ) -> crate::Result<Option<PurchaseDetails>> {
+        self.on_worker(move |iap| iap.buy_upgrade_blocking(new_product_id, replacement_mode)).await
+    }
+
+    fn buy_upgrade_blocking(
+        &self,
+        new_product_id: String,
+        replacement_mode: ReplacementMode,
+    ) -> crate::Result<Option<PurchaseDetails>> {
+        self.ensure_initialized()?;
+        self.metrics
+            .purchase_flow(Option::is_some, || self.upgrade(new_product_id, replacement_mode))
+    }
+
+    fn upgrade(
+        &self,
+        new_product_id: String,
+        replacement_mode: ReplacementMode,
+    ) -> crate::Result<Option<PurchaseDetails>> {
+        self.validate_product_ids(std::slice::from_ref(&new_product_id))?;
+        let owned = self.current_entitlements_blocking()?;
+        let products = self
+            .query_product_details_with_policy_blocking(
+                crate::upgrade::products_to_query(&new_product_id, &owned),
+                &self.retry_policy,
+            )?
+            .product_details;
+        let new_product = products
+            .iter()
+            .find(|product| product.id == new_product_id)
+            .cloned()
+            .ok_or_else(|| Error::ProductQueryError(format!("Product not found: {new_product_id}")))?;
+        let old_purchase = crate::upgrade::subscription_to_replace(&new_product_id, owned, &products)?;
+        let purchase_param = self.with_application_user_name(PurchaseParam {
+            product_details: new_product,
+            application_user_name: None,
+        });
+        self.backend
+            .change_subscription(&purchase_param, &old_purchase, replacement_mode, &self.events)
     }
 
     /// Complete a purchase transaction.
     ///
+    /// Concurrent calls for the same purchase share a single store call and
+    /// its result.
+    ///
     /// # Arguments
     ///
     /// * `purchase` - Details of the purchase to complete
     ///
     /// # Errors
     ///
-    /// Always returns `Error::PlatformNotSupported` on desktop platforms.
-    pub fn complete_purchase(&self, _purchase: PurchaseDetails) -> crate::Result<()> {
-        Err(crate::Error::PlatformNotSupported)
+    /// Returns `Error::PlatformNotSupported` without a store backend, and
+    /// `Error::UnknownPurchase` if the store never sold the purchase.
+    pub async fn complete_purchase(&self, purchase: PurchaseDetails) -> crate::Result<()> {
+        self.on_worker(move |iap| iap.complete_purchase_blocking(purchase)).await
+    }
+
+    fn complete_purchase_blocking(&self, purchase: PurchaseDetails) -> crate::Result<()> {
+        self.ensure_initialized()?;
+        let purchase = self.entitlements.with_verification_data(purchase);
+        self.completions.run(purchase.identifiers(), || {
+            match self.backend.complete_purchase(&purchase) {
+                Err(e)
+                    if e.code() == IapErrorCode::UnknownPurchase && !self.reject_unknown_purchases =>
+                {
+                    Ok(())
+                }
+                Ok(()) => {
+                    self.unfinished.remove(&purchase);
+                    Ok(())
+                }
+                result => result,
+            }
+        })
+    }
+
+    /// Completes a purchase once `verify` accepted it, e.g. after the app's
+    /// server validated its verification data.
+    ///
+    /// Completing is retried with the [`RetryPolicy`]. A purchase failing
+    /// verification is left unfinished and kept in the unfinished purchases,
+    /// so it's reported again after a restart until it's completed.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `verify`, or of [`complete_purchase`](Self::complete_purchase).
+    pub async fn finalize_after<F, Fut>(&self, purchase: PurchaseDetails, verify: F) -> crate::Result<()>
+    where
+        F: FnOnce(PurchaseDetails) -> Fut,
+        Fut: Future<Output = crate::Result<()>>,
+    {
+        if let Err(e) = verify(purchase.clone()).await {
+            log::warn!(
+                "Leaving the purchase of {} unfinished, its verification failed: {}",
+                purchase.product_id,
+                e
+            );
+            self.unfinished.update(std::slice::from_ref(&purchase));
+            return Err(e);
+        }
+        self.on_worker(move |iap| {
+            retry(&iap.retry_policy, &iap.shutdown(), || {
+                iap.complete_purchase_blocking(purchase.clone())
+            })
+        })
+        .await
     }
 
     /// Restore previously purchased items.
     ///
     /// # Arguments
     ///
-    /// * `application_user_name` - Optional user identifier for the restoration
+    /// * `application_user_name` - Optional user identifier for the restoration,
+    ///   or else the stored one
+    ///
+    /// Owned purchases are emitted again with the `Restored` status, followed
+    /// by a [`RESTORE_COMPLETED_EVENT`](crate::RESTORE_COMPLETED_EVENT).
+    /// Concurrent restores for the same user share a single one, and restores
+    /// within `restoreCooldownMs` of a successful one return without
+    /// restoring or emitting again.
     ///
     /// # Errors
     ///
-    /// Always returns `Error::PlatformNotSupported` on desktop platforms.
-    pub fn restore_purchases(&self, _application_user_name: Option<String>) -> crate::Result<()> {
-        Err(crate::Error::PlatformNotSupported)
+    /// Returns `Error::PlatformNotSupported` without a store backend.
+    pub async fn restore_purchases(&self, application_user_name: Option<String>) -> crate::Result<()> {
+        self.on_worker(move |iap| iap.restore_purchases_blocking(application_user_name)).await
+    }
+
+    fn restore_purchases_blocking(&self, application_user_name: Option<String>) -> crate::Result<()> {
+        self.ensure_initialized()?;
+        self.metrics.restore_started();
+        let application_user_name = application_user_name.or_else(|| self.application_user_name());
+        self.restores
+            .run(application_user_name.clone(), || self.restore(application_user_name))
+    }
+
+    fn restore(&self, application_user_name: Option<String>) -> crate::Result<()> {
+        let restored_count = Arc::new(AtomicUsize::new(0));
+        let restored = Arc::new(Mutex::new(Vec::new()));
+        let events = {
+            let (events, restored_count) = (self.events.clone(), restored_count.clone());
+            let restored = restored.clone();
+            PurchaseEventSink::new(move |purchases| {
+                restored_count.fetch_add(purchases.len(), Ordering::Relaxed);
+                restored
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .extend_from_slice(purchases);
+                events.emit(purchases);
+            })
+        };
+        self.backend
+            .restore_purchases(application_user_name.as_deref(), &events)?;
+        // Only a restore of every user's purchases lists all owned products.
+        if application_user_name.is_none() {
+            self.entitlements
+                .replace(&restored.lock().unwrap_or_else(PoisonError::into_inner));
+        }
+
+        let completed = RestoreCompleted {
+            restored_count: restored_count.load(Ordering::Relaxed),
+        };
+        self.streams.send(PurchaseUpdate::RestoreCompleted(completed.clone()));
+        if let Err(e) = self
+            .config
+            .emit(&self.app, crate::RESTORE_COMPLETED_EVENT, completed)
+        {
+            log::error!("Failed to emit restore completed: {}", e);
+        }
+        Ok(())
     }
 
     /// Get the store country/region code.
     ///
+    /// The Microsoft Store reports the user's market and the Mac App Store its
+    /// storefront. Without a store, the region is taken from the system locale.
+    ///
+    /// # Returns
+    ///
+    /// The uppercase ISO 3166-1 alpha-2 region, or [`UNKNOWN_COUNTRY_CODE`] if
+    /// the locale doesn't name a region.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InternalError` if the system locale can't be determined.
+    pub async fn country_code(&self) -> crate::Result<String> {
+        self.on_worker(move |iap| {
+            iap.ensure_country_code_ready()?;
+            iap.country_code.get_or_fetch(|| iap.backend.country_code())
+        })
+        .await
+    }
+
+    /// Fetches the country code again, bypassing and updating the cache.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::PlatformNotSupported` without a store backend.
+    pub async fn refresh_country_code(&self) -> crate::Result<String> {
+        self.on_worker(move |iap| {
+            iap.ensure_country_code_ready()?;
+            iap.country_code.refresh(|| iap.backend.country_code())
+        })
+        .await
+    }
+
+    /// Check if the store supports a feature, e.g. subscription upgrades,
+    /// before showing the UI relying on it.
+    ///
+    /// The Microsoft Store supports subscriptions and the Mac App Store also
+    /// subscription upgrades. Without a store nothing is supported.
+    pub async fn is_feature_supported(&self, feature: StoreFeature) -> crate::Result<bool> {
+        self.on_worker(move |iap| {
+            iap.ensure_initialized()?;
+            iap.backend.is_feature_supported(feature)
+        })
+        .await
+    }
+
+    /// Check if the user can buy through Google Play's Alternative Billing
+    /// Only program.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported`, as the program is Android only.
+    pub async fn is_alternative_billing_available(&self) -> crate::Result<bool> {
+        Err(alternative_billing_unsupported())
+    }
+
+    /// Show Google Play's alternative billing information dialog.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported`, as the program is Android only.
+    pub async fn show_alternative_billing_dialog(&self) -> crate::Result<bool> {
+        Err(alternative_billing_unsupported())
+    }
+
+    /// Create the token reporting an alternative billing purchase to Google
+    /// Play.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported`, as the program is Android only.
+    pub async fn create_alternative_billing_token(
+        &self,
+    ) -> crate::Result<AlternativeBillingReportingDetails> {
+        Err(alternative_billing_unsupported())
+    }
+
+    /// Check if the app can send the user to an App Store external purchase
+    /// link.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported`, as the program is iOS only.
+    pub async fn external_purchase_available(&self) -> crate::Result<bool> {
+        Err(external_purchase_unsupported())
+    }
+
+    /// Open an App Store external purchase link.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported`, as the program is iOS only.
+    pub async fn open_external_purchase_link(&self, _url: &str) -> crate::Result<bool> {
+        Err(external_purchase_unsupported())
+    }
+
+    /// Get the token to report an external purchase to Apple.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported`, as the program is iOS only.
+    pub async fn external_purchase_token(&self) -> crate::Result<ExternalPurchaseToken> {
+        Err(external_purchase_unsupported())
+    }
+
+    /// Get the license of the app and of the add-ons the user owns.
+    ///
+    /// Reports whether the app is a trial on Windows, and the version first
+    /// bought or downloaded from the Mac App Store on macOS 13+.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported` unless the Microsoft Store or the
+    /// Mac App Store serves calls.
+    pub async fn app_license(&self) -> crate::Result<AppLicense> {
+        self.on_worker(move |iap| {
+            iap.ensure_initialized()?;
+            iap.backend.app_license()
+        })
+        .await
+    }
+
+    /// Get the base64 encoded App Store receipt, for backends validating the
+    /// legacy receipt. With `refresh`, a missing receipt is requested from
+    /// the App Store first, which may ask the user to sign in.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ReceiptUnavailable` if there is still no receipt, or
+    /// `Error::FeatureNotSupported` unless the Mac App Store serves calls.
+    pub async fn get_app_receipt(&self, refresh: bool) -> crate::Result<String> {
+        self.on_worker(move |iap| iap.get_app_receipt_blocking(refresh)).await
+    }
+
+    fn get_app_receipt_blocking(&self, refresh: bool) -> crate::Result<String> {
+        self.ensure_initialized()?;
+        self.backend.app_receipt(refresh)
+    }
+
+    /// Get the state of a subscription the user bought, including when the
+    /// current period ends and whether it renews.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ItemNotOwned` if the user doesn't own the subscription,
+    /// or `Error::FeatureNotSupported` if the store doesn't sell
+    /// subscriptions.
+    pub async fn subscription_status(&self, product_id: &str) -> crate::Result<SubscriptionStatus> {
+        let product_id = product_id.to_owned();
+        self.on_worker(move |iap| {
+            iap.ensure_initialized()?;
+            iap.backend.subscription_status(&product_id)
+        })
+        .await
+    }
+
+    /// Time left on a subscription the user bought, e.g. for a "3 days left
+    /// in your trial" banner: until the end of the grace period while in
+    /// one, otherwise until the end of the current period, and zero once
+    /// access ended. `None` for products that aren't subscriptions, i.e.
+    /// preloaded as such or owned through a purchase that never ends, and
+    /// for subscriptions the store reports no end for.
+    ///
+    /// The status is queried from the store on every call, so renewals made
+    /// since are taken into account.
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`Iap::subscription_status`].
+    pub async fn subscription_time_remaining(&self, product_id: &str) -> crate::Result<Option<Duration>> {
+        self.subscription_time_remaining_at(product_id, SystemTime::now()).await
+    }
+
+    /// [`Iap::subscription_time_remaining`] at `now` instead of the current
+    /// time, e.g. in tests.
+    pub async fn subscription_time_remaining_at(
+        &self,
+        product_id: &str,
+        now: SystemTime,
+    ) -> crate::Result<Option<Duration>> {
+        let product_id = product_id.to_owned();
+        self.on_worker(move |iap| iap.subscription_time_remaining_at_blocking(&product_id, now)).await
+    }
+
+    fn subscription_time_remaining_at_blocking(
+        &self,
+        product_id: &str,
+        now: SystemTime,
+    ) -> crate::Result<Option<Duration>> {
+        self.ensure_initialized()?;
+        let (known, _) = self.products.lookup(&[product_id.to_string()]);
+        if known.iter().any(|product| !product.is_subscription()) {
+            return Ok(None);
+        }
+        match self.backend.subscription_status(product_id) {
+            Ok(status) => Ok(status.time_remaining(now)),
+            // Stores don't know a status for products that aren't
+            // subscriptions, e.g. a lifetime unlock that wasn't preloaded.
+            Err(crate::Error::ItemNotOwned(_)) if self.entitlements.owns_for_life(product_id) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Open the store page where the user manages their subscriptions, the
+    /// Microsoft account services page on Windows.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported` if the store doesn't sell
+    /// subscriptions.
+    pub async fn open_manage_subscriptions(&self) -> crate::Result<()> {
+        self.on_worker(move |iap| {
+            iap.ensure_initialized()?;
+            iap.backend.open_manage_subscriptions()
+        })
+        .await
+    }
+
+    /// Show the sheet asking the user to consent to a subscription price
+    /// increase, which only iOS has. Returns `false`.
+    pub async fn show_price_consent_if_needed(&self) -> crate::Result<bool> {
+        Ok(false)
+    }
+
+    /// Open the store page of an app, or of this app when `product_id` is
+    /// `None`. IDs are the Store IDs on Windows and the numeric App Store
+    /// IDs on macOS, where this app's page needs the `app_store_id`
+    /// configuration.
+    ///
     /// # Errors
     ///
-    /// Always returns `Error::PlatformNotSupported` on desktop platforms.
-    pub fn country_code(&self) -> crate::Result<String> {
-        Err(crate::Error::PlatformNotSupported)
+    /// Returns `Error::FeatureNotSupported` if the store doesn't open pages
+    /// or no app can show it, and `Error::InvalidConfig` on macOS without a
+    /// product ID or `app_store_id`.
+    pub async fn open_store_product_page(&self, product_id: Option<&str>) -> crate::Result<()> {
+        let product_id = product_id.map(str::to_owned);
+        self.on_worker(move |iap| {
+            iap.ensure_initialized()?;
+            iap.backend.open_store_product_page(product_id.as_deref())
+        })
+        .await
+    }
+}
+
+/// Backend used when no store is available: purchases are unavailable and
+/// every call that reaches the store fails.
+struct Unsupported {
+    locale: LocaleProvider,
+}
+
+impl DesktopIapBackend for Unsupported {
+    fn initialize(&self) -> crate::Result<()> {
+        Err(Error::PlatformNotSupported)
+    }
+
+    fn is_available(&self) -> crate::Result<bool> {
+        Ok(false)
+    }
+
+    fn query_product_details(&self, _product_ids: &[String]) -> crate::Result<ProductDetailsResponse> {
+        Err(Error::PlatformNotSupported)
+    }
+
+    fn buy_non_consumable(
+        &self,
+        _purchase_param: &PurchaseParam,
+        _events: &PurchaseEventSink,
+    ) -> crate::Result<bool> {
+        Err(Error::PlatformNotSupported)
+    }
+
+    fn buy_consumable(
+        &self,
+        _purchase_param: &PurchaseParam,
+        _auto_consume: bool,
+        _events: &PurchaseEventSink,
+    ) -> crate::Result<bool> {
+        Err(Error::PlatformNotSupported)
     }
+
+    fn complete_purchase(&self, _purchase: &PurchaseDetails) -> crate::Result<()> {
+        Err(Error::PlatformNotSupported)
+    }
+
+    fn restore_purchases(
+        &self,
+        _application_user_name: Option<&str>,
+        _events: &PurchaseEventSink,
+    ) -> crate::Result<()> {
+        Err(Error::PlatformNotSupported)
+    }
+
+    fn country_code(&self) -> crate::Result<String> {
+        locale_country_code(&self.locale)
+    }
+}
+
+/// Reports the owned purchases through `events`, returning them.
+fn owned_purchases(
+    backend: &dyn DesktopIapBackend,
+    events: &PurchaseEventSink,
+) -> crate::Result<Vec<PurchaseDetails>> {
+    let owned = Arc::new(Mutex::new(Vec::new()));
+    let collect = {
+        let (events, owned) = (events.clone(), owned.clone());
+        PurchaseEventSink::new(move |purchases| {
+            owned
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .extend_from_slice(purchases);
+            events.emit(purchases);
+        })
+    };
+    backend.owned_purchases(&collect)?;
+    let owned = std::mem::take(&mut *owned.lock().unwrap_or_else(PoisonError::into_inner));
+    Ok(owned)
+}
+
+fn alternative_billing_unsupported() -> Error {
+    Error::FeatureNotSupported("Alternative billing only is a Google Play program".into())
+}
+
+fn external_purchase_unsupported() -> Error {
+    Error::FeatureNotSupported("External purchase links are an App Store program".into())
+}
+
+/// Returns the user's locale as a BCP 47 (`en-US`) or POSIX (`en_US.UTF-8`)
+/// tag, or `None` if it can't be determined.
+pub(crate) type LocaleProvider = Arc<dyn Fn() -> Option<String> + Send + Sync>;
+
+/// The region of the locale returned by `locale`, or [`UNKNOWN_COUNTRY_CODE`]
+/// if the locale doesn't name one.
+pub(crate) fn locale_country_code(locale: &LocaleProvider) -> crate::Result<String> {
+    let locale = locale()
+        .filter(|locale| !locale.is_empty())
+        .ok_or_else(|| Error::InternalError("The system locale could not be determined".into()))?;
+    Ok(locale_region(&locale).unwrap_or_else(|| UNKNOWN_COUNTRY_CODE.to_string()))
+}
+
+/// Returns the region of a POSIX (`en_US.UTF-8`) or BCP 47 (`en-US`) locale.
+fn locale_region(locale: &str) -> Option<String> {
+    let locale = locale.split(['.', '@']).next()?;
+    locale
+        .split(['_', '-'])
+        .skip(1)
+        .find(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_alphabetic()))
+        .map(|region| region.to_ascii_uppercase())
 }
@@ -0,0 +1,750 @@
+use std::sync::Arc;
+
+use crate::models::*;
+use crate::{Diagnostics, Error, ProductIdRules, Result};
+
+/// Callback receiving every batch of reported purchases.
+type EmitPurchases = dyn Fn(&[PurchaseDetails]) + Send + Sync;
+
+/// Callback invoked when the storefront or the locale changed.
+type StorefrontChanged = dyn Fn() + Send + Sync;
+
+/// Reports purchases to the webview as `tauri-plugin-iap://purchase-update`
+/// events, the same way the mobile plugins do.
+///
+/// Cloning the sink is cheap, so backends can keep a clone to report purchases
+/// that complete later, e.g. after a pending approval.
+#[derive(Clone)]
+pub struct PurchaseEventSink {
+    emit: Arc<EmitPurchases>,
+    storefront_changed: Option<Arc<StorefrontChanged>>,
+}
+
+impl PurchaseEventSink {
+    /// Creates a sink calling `emit` with every batch of purchases.
+    pub fn new(emit: impl Fn(&[PurchaseDetails]) + Send + Sync + 'static) -> Self {
+        Self {
+            emit: Arc::new(emit),
+            storefront_changed: None,
+        }
+    }
+
+    /// Calls `changed` whenever the backend reports a storefront change.
+    pub(crate) fn on_storefront_change(mut self, changed: impl Fn() + Send + Sync + 'static) -> Self {
+        self.storefront_changed = Some(Arc::new(changed));
+        self
+    }
+
+    /// Reports purchases that were made, restored or changed state.
+    pub fn emit(&self, purchases: &[PurchaseDetails]) {
+        if !purchases.is_empty() {
+            (self.emit)(purchases)
+        }
+    }
+
+    /// Reports that the user's storefront or language changed, so prices and
+    /// localized titles of the products queried so far are stale. The
+    /// plugin drops its cached products and country code, and queries the
+    /// `preloadProducts` again.
+    pub fn storefront_changed(&self) {
+        if let Some(changed) = &self.storefront_changed {
+            changed()
+        }
+    }
+}
+
+/// A store serving the iap APIs on desktop.
+///
+/// The plugin picks the backend registered with
+/// [`Builder::desktop_backend`](crate::Builder::desktop_backend) if any, and
+/// otherwise the first available bundled one (the mock store, the Microsoft
+/// Store or the Mac App Store, depending on the enabled features).
+///
+/// Methods are called from command handlers on worker threads and may block.
+/// Failed product queries are retried by the plugin according to its
+/// [`RetryPolicy`](crate::RetryPolicy), and a `complete_purchase` failing with
+/// [`Error::UnknownPurchase`](crate::Error::UnknownPurchase) is ignored when
+/// the app opted out with `Builder::reject_unknown_purchases(false)`.
+///
+/// # Example
+///
+/// A backend selling through a licensing server:
+///
+/// ```
+/// use tauri_plugin_iap::{
+///     DesktopIapBackend, Error, ProductDetails, ProductDetailsResponse, PurchaseDetails,
+///     PurchaseEventSink, PurchaseParam, PurchaseStatus, PurchaseVerificationData, Result,
+/// };
+///
+/// struct LicenseServer {
+///     products: Vec<ProductDetails>,
+/// }
+///
+/// impl LicenseServer {
+///     fn checkout(&self, product_id: &str) -> Result<String> {
+///         // Open the checkout page and wait for the license key.
+///         Ok(format!("license-{product_id}"))
+///     }
+///
+///     fn purchase(&self, product_id: &str, license: String) -> PurchaseDetails {
+///         PurchaseDetails {
+///             purchase_id: Some(license.clone()),
+///             product_id: product_id.to_string(),
+///             verification_data: PurchaseVerificationData {
+///                 local_verification_data: license.clone(),
+///                 server_verification_data: license,
+///                 source: "license-server".into(),
+///                 signature: None,
+///             },
+///             transaction_date: None,
+///             transaction_date_ms: None,
+///             expiration_date: None,
+///             subscription_state: None,
+///             grace_period_expiration_date: None,
+///             status: PurchaseStatus::Purchased,
+///             error: None,
+///             pending_complete_purchase: false,
+///             verified: false,
+///         }
+///     }
+/// }
+///
+/// impl DesktopIapBackend for LicenseServer {
+///     fn initialize(&self) -> Result<()> {
+///         Ok(())
+///     }
+///
+///     fn is_available(&self) -> Result<bool> {
+///         Ok(true)
+///     }
+///
+///     fn query_product_details(&self, product_ids: &[String]) -> Result<ProductDetailsResponse> {
+///         let (found, not_found) = product_ids
+///             .iter()
+///             .partition::<Vec<_>, _>(|id| self.products.iter().any(|p| &p.id == *id));
+///         Ok(ProductDetailsResponse {
+///             product_details: self
+///                 .products
+///                 .iter()
+///                 .filter(|p| found.contains(&&p.id))
+///                 .cloned()
+///                 .collect(),
+///             not_found_ids: not_found.into_iter().cloned().collect(),
+///             error: None,
+///         })
+///     }
+///
+///     fn buy_non_consumable(
+///         &self,
+///         purchase_param: &PurchaseParam,
+///         events: &PurchaseEventSink,
+///     ) -> Result<bool> {
+///         let product_id = &purchase_param.product_details.id;
+///         let license = self.checkout(product_id)?;
+///         events.emit(&[self.purchase(product_id, license)]);
+///         Ok(true)
+///     }
+///
+///     fn buy_consumable(
+///         &self,
+///         _purchase_param: &PurchaseParam,
+///         _auto_consume: bool,
+///         _events: &PurchaseEventSink,
+///     ) -> Result<bool> {
+///         Err(Error::FeatureNotSupported("Consumables are not sold on desktop".into()))
+///     }
+///
+///     fn complete_purchase(&self, _purchase: &PurchaseDetails) -> Result<()> {
+///         Ok(())
+///     }
+///
+///     fn restore_purchases(
+///         &self,
+///         _application_user_name: Option<&str>,
+///         _events: &PurchaseEventSink,
+///     ) -> Result<()> {
+///         Ok(())
+///     }
+///
+///     fn country_code(&self) -> Result<String> {
+///         Ok("US".into())
+///     }
+/// }
+///
+/// let plugin = tauri_plugin_iap::Builder::new()
+///     .desktop_backend(LicenseServer { products: Vec::new() })
+///     .build::<tauri::Wry>();
+/// # let _ = plugin;
+/// ```
+pub trait DesktopIapBackend: Send + Sync + 'static {
+    /// Prepares the store for use.
+    fn initialize(&self) -> Result<()>;
+
+    /// Whether purchases can be made, e.g. false for builds not installed
+    /// from the store.
+    fn is_available(&self) -> Result<bool>;
+
+    /// Queries product details, listing unknown products in `not_found_ids`.
+    fn query_product_details(&self, product_ids: &[String]) -> Result<ProductDetailsResponse>;
+
+    /// Buys a non-consumable product, reporting the purchase through `events`.
+    fn buy_non_consumable(
+        &self,
+        purchase_param: &PurchaseParam,
+        events: &PurchaseEventSink,
+    ) -> Result<bool>;
+
+    /// Buys a consumable product, reporting the purchase through `events`.
+    fn buy_consumable(
+        &self,
+        purchase_param: &PurchaseParam,
+        auto_consume: bool,
+        events: &PurchaseEventSink,
+    ) -> Result<bool>;
+
+    /// Acknowledges or consumes a purchase reported earlier.
+    fn complete_purchase(&self, purchase: &PurchaseDetails) -> Result<()>;
+
+    /// Reports owned purchases through `events` with the `Restored` status.
+    fn restore_purchases(
+        &self,
+        application_user_name: Option<&str>,
+        events: &PurchaseEventSink,
+    ) -> Result<()>;
+
+    /// Reports the owned purchases through `events` with the `Restored`
+    /// status without showing any store UI, e.g. a sign-in prompt. Used by
+    /// `autoRestoreOnInit`.
+    ///
+    /// Defaults to [`DesktopIapBackend::restore_purchases`] for every user.
+    fn owned_purchases(&self, events: &PurchaseEventSink) -> Result<()> {
+        self.restore_purchases(None, events)
+    }
+
+    /// The store country/region code.
+    fn country_code(&self) -> Result<String>;
+
+    /// The product ID format of the store, checked before product IDs are
+    /// queried or bought.
+    ///
+    /// Leaves IDs unchecked by default.
+    fn product_id_rules(&self) -> Option<ProductIdRules> {
+        None
+    }
+
+    /// Whether the store supports `feature`.
+    ///
+    /// Reports every feature as unsupported by default.
+    fn is_feature_supported(&self, _feature: StoreFeature) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// The license of the app and its add-ons, for stores that sell
+    /// through licenses.
+    ///
+    /// Fails with `Error::FeatureNotSupported` by default.
+    fn app_license(&self) -> Result<AppLicense> {
+        Err(Error::FeatureNotSupported(
+            "App licenses are only reported by the Microsoft Store and the Mac App Store".into(),
+        ))
+    }
+
+    /// The base64 encoded App Store receipt of the app, refreshed from the
+    /// store first if `refresh` is set and it is missing.
+    ///
+    /// Fails with `Error::FeatureNotSupported` by default.
+    fn app_receipt(&self, _refresh: bool) -> Result<String> {
+        Err(Error::FeatureNotSupported(
+            "App receipts are only issued by the App Store".into(),
+        ))
+    }
+
+    /// The state of a subscription the user bought.
+    ///
+    /// Fails with `Error::FeatureNotSupported` by default.
+    fn subscription_status(&self, _product_id: &str) -> Result<SubscriptionStatus> {
+        Err(Error::FeatureNotSupported(
+            "Subscriptions are not supported by this store".into(),
+        ))
+    }
+
+    /// Replaces the subscription `old_purchase` with the product of
+    /// `purchase_param`, reporting the new purchase through `events` and
+    /// returning it, or `None` while it's pending.
+    ///
+    /// Fails with `Error::FeatureNotSupported` by default.
+    fn change_subscription(
+        &self,
+        _purchase_param: &PurchaseParam,
+        _old_purchase: &PurchaseDetails,
+        _replacement_mode: ReplacementMode,
+        _events: &PurchaseEventSink,
+    ) -> Result<Option<PurchaseDetails>> {
+        Err(Error::FeatureNotSupported(
+            "Subscription upgrades are not supported by this store".into(),
+        ))
+    }
+
+    /// Opens the store page where the user manages their subscriptions.
+    ///
+    /// Fails with `Error::FeatureNotSupported` by default.
+    fn open_manage_subscriptions(&self) -> Result<()> {
+        Err(Error::FeatureNotSupported(
+            "Subscriptions are not supported by this store".into(),
+        ))
+    }
+
+    /// Where the app was installed from.
+    ///
+    /// Returns `InstallSource::DevBuild` for development builds and
+    /// `InstallSource::Unknown` otherwise by default.
+    fn install_source(&self) -> Result<InstallSource> {
+        Ok(if tauri::is_dev() {
+            InstallSource::DevBuild
+        } else {
+            InstallSource::Unknown
+        })
+    }
+
+    /// Fills in the store's part of the `diagnostics`, e.g. its name.
+    ///
+    /// Leaves them unchanged by default.
+    fn describe(&self, _diagnostics: &mut Diagnostics) {}
+
+    /// Opens the store page of an app, or of this app when `product_id` is
+    /// `None`.
+    ///
+    /// Fails with `Error::FeatureNotSupported` by default.
+    fn open_store_product_page(&self, _product_id: Option<&str>) -> Result<()> {
+        Err(Error::FeatureNotSupported(
+            "Store pages are not opened by this store".into(),
+        ))
+    }
+
+    /// Starts reporting the purchases the store changes outside of the
+    /// plugin's calls, e.g. renewals or approved Ask to Buy purchases, and
+    /// storefront changes through `events` until
+    /// [`stop_transaction_updates`](Self::stop_transaction_updates). Called
+    /// after `initialize` succeeds unless the listener is already running,
+    /// and returns whether the store has one.
+    ///
+    /// Returns `false` by default.
+    fn start_transaction_updates(&self, _events: &PurchaseEventSink) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Stops the listener started by `start_transaction_updates`, when the
+    /// connection is closed.
+    fn stop_transaction_updates(&self) {}
+}
+
+impl<T: DesktopIapBackend> DesktopIapBackend for Arc<T> {
+    fn initialize(&self) -> Result<()> {
+        (**self).initialize()
+    }
+
+    fn is_available(&self) -> Result<bool> {
+        (**self).is_available()
+    }
+
+    fn query_product_details(&self, product_ids: &[String]) -> Result<ProductDetailsResponse> {
+        (**self).query_product_details(product_ids)
+    }
+
+    fn buy_non_consumable(
+        &self,
+        purchase_param: &PurchaseParam,
+        events: &PurchaseEventSink,
+    ) -> Result<bool> {
+        (**self).buy_non_consumable(purchase_param, events)
+    }
+
+    fn buy_consumable(
+        &self,
+        purchase_param: &PurchaseParam,
+        auto_consume: bool,
+        events: &PurchaseEventSink,
+    ) -> Result<bool> {
+        (**self).buy_consumable(purchase_param, auto_consume, events)
+    }
+
+    fn complete_purchase(&self, purchase: &PurchaseDetails) -> Result<()> {
+        (**self).complete_purchase(purchase)
+    }
+
+    fn restore_purchases(
+        &self,
+        application_user_name: Option<&str>,
+        events: &PurchaseEventSink,
+    ) -> Result<()> {
+        (**self).restore_purchases(application_user_name, events)
+    }
+
+    fn owned_purchases(&self, events: &PurchaseEventSink) -> Result<()> {
+        (**self).owned_purchases(events)
+    }
+
+    fn country_code(&self) -> Result<String> {
+        (**self).country_code()
+    }
+
+    fn is_feature_supported(&self, feature: StoreFeature) -> Result<bool> {
+        (**self).is_feature_supported(feature)
+    }
+
+    fn product_id_rules(&self) -> Option<ProductIdRules> {
+        (**self).product_id_rules()
+    }
+
+    fn app_license(&self) -> Result<AppLicense> {
+        (**self).app_license()
+    }
+
+    fn app_receipt(&self, refresh: bool) -> Result<String> {
+        (**self).app_receipt(refresh)
+    }
+
+    fn subscription_status(&self, product_id: &str) -> Result<SubscriptionStatus> {
+        (**self).subscription_status(product_id)
+    }
+
+    fn change_subscription(
+        &self,
+        purchase_param: &PurchaseParam,
+        old_purchase: &PurchaseDetails,
+        replacement_mode: ReplacementMode,
+        events: &PurchaseEventSink,
+    ) -> Result<Option<PurchaseDetails>> {
+        (**self).change_subscription(purchase_param, old_purchase, replacement_mode, events)
+    }
+
+    fn open_manage_subscriptions(&self) -> Result<()> {
+        (**self).open_manage_subscriptions()
+    }
+
+    fn open_store_product_page(&self, product_id: Option<&str>) -> Result<()> {
+        (**self).open_store_product_page(product_id)
+    }
+
+    fn install_source(&self) -> Result<InstallSource> {
+        (**self).install_source()
+    }
+
+    fn describe(&self, diagnostics: &mut Diagnostics) {
+        (**self).describe(diagnostics)
+    }
+
+    fn start_transaction_updates(&self, events: &PurchaseEventSink) -> Result<bool> {
+        (**self).start_transaction_updates(events)
+    }
+
+    fn stop_transaction_updates(&self) {
+        (**self).stop_transaction_updates()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    fn purchase(product_id: &str) -> PurchaseDetails {
+        PurchaseDetails {
+            purchase_id: Some(format!("{product_id}-1")),
+            product_id: product_id.into(),
+            verification_data: PurchaseVerificationData::default(),
+            transaction_date: None,
+            transaction_date_ms: None,
+            expiration_date: None,
+            subscription_state: None,
+            grace_period_expiration_date: None,
+            status: PurchaseStatus::Restored,
+            error: None,
+            pending_complete_purchase: false,
+            verified: false,
+        }
+    }
+
+    fn param(product_id: &str) -> PurchaseParam {
+        PurchaseParam {
+            product_details: ProductDetails {
+                id: product_id.into(),
+                title: product_id.into(),
+                description: String::new(),
+                price: "$1.99".into(),
+                raw_price: 1.99,
+                currency_code: "USD".into(),
+                currency_symbol: "$".into(),
+                subscription_period: Some("P1M".into()),
+                free_trial_period: None,
+                subscription_group_id: None,
+            },
+            application_user_name: None,
+        }
+    }
+
+    /// A backend implementing only the required methods, recording the
+    /// users it restores purchases for.
+    #[derive(Default)]
+    struct Minimal {
+        restored_for: Mutex<Vec<Option<String>>>,
+    }
+
+    impl DesktopIapBackend for Minimal {
+        fn initialize(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn is_available(&self) -> Result<bool> {
+            Ok(true)
+        }
+
+        fn query_product_details(&self, product_ids: &[String]) -> Result<ProductDetailsResponse> {
+            Ok(ProductDetailsResponse {
+                not_found_ids: product_ids.to_vec(),
+                ..Default::default()
+            })
+        }
+
+        fn buy_non_consumable(
+            &self,
+            _purchase_param: &PurchaseParam,
+            _events: &PurchaseEventSink,
+        ) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn buy_consumable(
+            &self,
+            _purchase_param: &PurchaseParam,
+            _auto_consume: bool,
+            _events: &PurchaseEventSink,
+        ) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn complete_purchase(&self, _purchase: &PurchaseDetails) -> Result<()> {
+            Ok(())
+        }
+
+        fn restore_purchases(
+            &self,
+            application_user_name: Option<&str>,
+            events: &PurchaseEventSink,
+        ) -> Result<()> {
+            self.restored_for.lock().unwrap().push(application_user_name.map(Into::into));
+            events.emit(&[purchase("premium")]);
+            Ok(())
+        }
+
+        fn country_code(&self) -> Result<String> {
+            Ok("US".into())
+        }
+    }
+
+    /// A backend overriding the optional methods too.
+    #[derive(Default)]
+    struct Full {
+        minimal: Minimal,
+        listening: Mutex<bool>,
+    }
+
+    impl DesktopIapBackend for Full {
+        fn initialize(&self) -> Result<()> {
+            self.minimal.initialize()
+        }
+
+        fn is_available(&self) -> Result<bool> {
+            self.minimal.is_available()
+        }
+
+        fn query_product_details(&self, product_ids: &[String]) -> Result<ProductDetailsResponse> {
+            self.minimal.query_product_details(product_ids)
+        }
+
+        fn buy_non_consumable(
+            &self,
+            purchase_param: &PurchaseParam,
+            events: &PurchaseEventSink,
+        ) -> Result<bool> {
+            self.minimal.buy_non_consumable(purchase_param, events)
+        }
+
+        fn buy_consumable(
+            &self,
+            purchase_param: &PurchaseParam,
+            auto_consume: bool,
+            events: &PurchaseEventSink,
+        ) -> Result<bool> {
+            self.minimal.buy_consumable(purchase_param, auto_consume, events)
+        }
+
+        fn complete_purchase(&self, purchase: &PurchaseDetails) -> Result<()> {
+            self.minimal.complete_purchase(purchase)
+        }
+
+        fn restore_purchases(
+            &self,
+            application_user_name: Option<&str>,
+            events: &PurchaseEventSink,
+        ) -> Result<()> {
+            self.minimal.restore_purchases(application_user_name, events)
+        }
+
+        fn owned_purchases(&self, _events: &PurchaseEventSink) -> Result<()> {
+            Err(Error::StoreUnavailable("signed out".into()))
+        }
+
+        fn country_code(&self) -> Result<String> {
+            self.minimal.country_code()
+        }
+
+        fn product_id_rules(&self) -> Option<ProductIdRules> {
+            Some(ProductIdRules::AppStore)
+        }
+
+        fn is_feature_supported(&self, feature: StoreFeature) -> Result<bool> {
+            Ok(feature == StoreFeature::Subscriptions)
+        }
+
+        fn app_receipt(&self, refresh: bool) -> Result<String> {
+            Ok(format!("receipt refreshed={refresh}"))
+        }
+
+        fn change_subscription(
+            &self,
+            purchase_param: &PurchaseParam,
+            _old_purchase: &PurchaseDetails,
+            _replacement_mode: ReplacementMode,
+            _events: &PurchaseEventSink,
+        ) -> Result<Option<PurchaseDetails>> {
+            Ok(Some(purchase(&purchase_param.product_details.id)))
+        }
+
+        fn open_manage_subscriptions(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn open_store_product_page(&self, product_id: Option<&str>) -> Result<()> {
+            match product_id {
+                None => Ok(()),
+                Some(id) => Err(Error::ProductQueryError(id.into())),
+            }
+        }
+
+        fn start_transaction_updates(&self, _events: &PurchaseEventSink) -> Result<bool> {
+            *self.listening.lock().unwrap() = true;
+            Ok(true)
+        }
+
+        fn stop_transaction_updates(&self) {
+            *self.listening.lock().unwrap() = false;
+        }
+    }
+
+    /// A sink collecting the product IDs of the emitted purchases.
+    fn sink() -> (PurchaseEventSink, Arc<Mutex<Vec<String>>>) {
+        let emitted = Arc::new(Mutex::new(Vec::new()));
+        let recorded = emitted.clone();
+        let sink = PurchaseEventSink::new(move |purchases| {
+            let mut recorded = recorded.lock().unwrap();
+            recorded.extend(purchases.iter().map(|p| p.product_id.clone()));
+        });
+        (sink, emitted)
+    }
+
+    #[test]
+    fn optional_methods_have_unsupported_defaults() {
+        let minimal = Arc::new(Minimal::default());
+        let backend: Box<dyn DesktopIapBackend> = Box::new(minimal.clone());
+        let (events, emitted) = sink();
+
+        assert!(backend.product_id_rules().is_none());
+        assert!(!backend.is_feature_supported(StoreFeature::Subscriptions).unwrap());
+        assert!(matches!(backend.app_license(), Err(Error::FeatureNotSupported(_))));
+        assert!(matches!(backend.app_receipt(true), Err(Error::FeatureNotSupported(_))));
+        assert!(matches!(
+            backend.subscription_status("monthly"),
+            Err(Error::FeatureNotSupported(_))
+        ));
+        assert!(matches!(
+            backend.change_subscription(
+                &param("yearly"),
+                &purchase("monthly"),
+                ReplacementMode::WithTimeProration,
+                &events,
+            ),
+            Err(Error::FeatureNotSupported(_))
+        ));
+        assert!(matches!(
+            backend.open_manage_subscriptions(),
+            Err(Error::FeatureNotSupported(_))
+        ));
+        assert!(matches!(
+            backend.open_store_product_page(None),
+            Err(Error::FeatureNotSupported(_))
+        ));
+        assert!(!backend.start_transaction_updates(&events).unwrap());
+
+        // Owned purchases are restored for every user.
+        backend.owned_purchases(&events).unwrap();
+        assert_eq!(*minimal.restored_for.lock().unwrap(), [None]);
+        assert_eq!(*emitted.lock().unwrap(), ["premium"]);
+    }
+
+    #[test]
+    fn arc_forwards_every_override() {
+        let full = Arc::new(Full::default());
+        let backend: Box<dyn DesktopIapBackend> = Box::new(full.clone());
+        let (events, emitted) = sink();
+
+        assert_eq!(backend.product_id_rules(), Some(ProductIdRules::AppStore));
+        assert!(backend.is_feature_supported(StoreFeature::Subscriptions).unwrap());
+        assert!(!backend.is_feature_supported(StoreFeature::InAppMessaging).unwrap());
+        assert_eq!(backend.app_receipt(true).unwrap(), "receipt refreshed=true");
+        let changed = backend
+            .change_subscription(
+                &param("yearly"),
+                &purchase("monthly"),
+                ReplacementMode::WithTimeProration,
+                &events,
+            )
+            .unwrap();
+        assert_eq!(changed.unwrap().product_id, "yearly");
+        assert!(backend.open_manage_subscriptions().is_ok());
+        assert!(backend.open_store_product_page(None).is_ok());
+        assert!(matches!(
+            backend.open_store_product_page(Some("other")),
+            Err(Error::ProductQueryError(_))
+        ));
+        assert!(matches!(
+            backend.owned_purchases(&events),
+            Err(Error::StoreUnavailable(_))
+        ));
+
+        assert!(backend.start_transaction_updates(&events).unwrap());
+        assert!(*full.listening.lock().unwrap());
+        backend.stop_transaction_updates();
+        assert!(!*full.listening.lock().unwrap());
+
+        backend.restore_purchases(Some("user"), &events).unwrap();
+        assert_eq!(*full.minimal.restored_for.lock().unwrap(), [Some("user".into())]);
+        assert_eq!(*emitted.lock().unwrap(), ["premium"]);
+    }
+
+    #[test]
+    fn sink_skips_empty_batches() {
+        let (events, emitted) = sink();
+        let changed = Arc::new(Mutex::new(0));
+        let count = changed.clone();
+        let events = events.on_storefront_change(move || *count.lock().unwrap() += 1);
+
+        events.emit(&[]);
+        events.clone().emit(&[purchase("premium")]);
+        events.storefront_changed();
+
+        assert_eq!(*emitted.lock().unwrap(), ["premium"]);
+        assert_eq!(*changed.lock().unwrap(), 1);
+    }
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::ConnectionState;
+use crate::{Config, ConnectionRetryPolicy, PendingPurchases, Timeouts};
+
+/// Environment details to attach to bug reports, returned by `diagnostics`.
+/// Holds no purchases, keys or user identifiers.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostics {
+    /// Version of the plugin crate
+    pub plugin_version: String,
+    /// Operating system, e.g. `android`, as named by `std::env::consts::OS`
+    pub platform: String,
+    /// Store serving the calls, e.g. `Google Play`, or `None` on desktop
+    /// without one
+    pub store: Option<String>,
+    /// Version of the store library the Android plugin was built with, the
+    /// Google Play Billing Library or the Amazon Appstore SDK
+    pub billing_library_version: Option<String>,
+    /// Whether purchases go through StoreKit 2 rather than StoreKit 1, on iOS
+    /// and macOS
+    #[serde(rename = "storeKit2")]
+    pub store_kit_2: Option<bool>,
+    /// State of the store connection
+    pub connection_state: ConnectionState,
+    /// Whether the store's listener for transaction updates outside of
+    /// purchases, e.g. renewals, is running
+    pub transaction_listener: bool,
+    /// How the store connection is retried
+    pub connection_retry: ConnectionRetryPolicy,
+    /// The plugin configuration in effect
+    pub config: ConfigDiagnostics,
+}
+
+impl Diagnostics {
+    /// The details known without asking the store, which the platform fills
+    /// in.
+    pub(crate) fn new(config: &Config, connection_state: ConnectionState) -> Self {
+        Self {
+            plugin_version: env!("CARGO_PKG_VERSION").into(),
+            platform: std::env::consts::OS.into(),
+            store: None,
+            billing_library_version: None,
+            store_kit_2: None,
+            connection_state,
+            transaction_listener: false,
+            connection_retry: config.connection_retry,
+            config: ConfigDiagnostics::from(config),
+        }
+    }
+}
+
+/// The plugin configuration in effect, with lists reduced to their length
+/// and the Google Play key to whether it is set.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigDiagnostics {
+    /// Number of `products`
+    pub products: usize,
+    /// `auto_finish_transactions`
+    pub auto_finish_transactions: bool,
+    /// `event_target`
+    pub event_target: Option<String>,
+    /// Whether purchases are restricted to the `allowed_windows`
+    pub restricted_windows: bool,
+    /// `timeouts`
+    pub timeouts: Timeouts,
+    /// `pending_purchases`
+    pub pending_purchases: PendingPurchases,
+    /// The restore cooldown, with the default applied
+    pub restore_cooldown_ms: u64,
+    /// `auto_restore_on_init`
+    pub auto_restore_on_init: bool,
+    /// `preload_products`
+    pub preload_products: bool,
+    /// `skip_preload_on_storefront_change`
+    pub skip_preload_on_storefront_change: bool,
+    /// `persist_products`
+    pub persist_products: bool,
+    /// Whether a `google_play_public_key` is set
+    pub google_play_public_key: bool,
+    /// `reject_unverified_purchases`
+    pub reject_unverified_purchases: bool,
+    /// `redact_verification_data`
+    pub redact_verification_data: bool,
+    /// `alternative_billing_only`
+    pub alternative_billing_only: bool,
+    /// `app_store_id`
+    pub app_store_id: Option<String>,
+    /// `require_store_install`
+    pub require_store_install: bool,
+    /// `transaction_listener_on_load`
+    pub transaction_listener_on_load: bool,
+    /// `owned_on_hold`
+    pub owned_on_hold: bool,
+    /// `defer_price_consent`
+    pub defer_price_consent: bool,
+    /// `skip_product_id_validation`
+    pub skip_product_id_validation: bool,
+    /// Whether `android_app_license` is set
+    pub android_app_license: bool,
+    /// Number of `mock_products`
+    pub mock_products: usize,
+}
+
+impl From<&Config> for ConfigDiagnostics {
+    fn from(config: &Config) -> Self {
+        Self {
+            products: config.products.len(),
+            auto_finish_transactions: config.auto_finish_transactions,
+            event_target: config.event_target.clone(),
+            restricted_windows: config.allowed_windows.is_some(),
+            timeouts: config.timeouts.clone(),
+            pending_purchases: config.pending_purchases.clone(),
+            restore_cooldown_ms: config.restore_cooldown().as_millis() as u64,
+            auto_restore_on_init: config.auto_restore_on_init,
+            preload_products: config.preload_products,
+            skip_preload_on_storefront_change: config.skip_preload_on_storefront_change,
+            persist_products: config.persist_products,
+            google_play_public_key: config.google_play_public_key.is_some(),
+            reject_unverified_purchases: config.reject_unverified_purchases,
+            redact_verification_data: config.redact_verification_data,
+            alternative_billing_only: config.alternative_billing_only,
+            app_store_id: config.app_store_id.clone(),
+            require_store_install: config.require_store_install,
+            transaction_listener_on_load: config.transaction_listener_on_load,
+            owned_on_hold: config.owned_on_hold,
+            defer_price_consent: config.defer_price_consent,
+            skip_product_id_validation: config.skip_product_id_validation,
+            android_app_license: config.android_app_license.is_some(),
+            mock_products: config.mock_products.len(),
+        }
+    }
+}
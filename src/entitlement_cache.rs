@@ -0,0 +1,316 @@
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::hmac::hmac;
+use crate::models::PurchaseDetails;
+use crate::persist::write_atomically;
+use crate::time::parse_iso8601;
+
+/// File in the app data directory keeping the owned products.
+const ENTITLEMENT_CACHE_FILE: &str = "iap-entitlements.json";
+
+/// Derives the MAC key from the app secret, so the key isn't the secret used
+/// elsewhere by the app.
+const KEY_CONTEXT: &[u8] = b"tauri-plugin-iap entitlement cache v1";
+
+/// What is kept of an owned product.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CachedEntitlement {
+    pub(crate) product_id: String,
+    pub(crate) purchase_id: Option<String>,
+    pub(crate) expiration_date: Option<String>,
+}
+
+impl CachedEntitlement {
+    fn of(purchase: &PurchaseDetails) -> Self {
+        Self {
+            product_id: purchase.product_id.clone(),
+            purchase_id: purchase.purchase_id.clone(),
+            expiration_date: purchase.entitlement_end(),
+        }
+    }
+
+    /// Whether the entitlement ended, or its end can't be read.
+    fn is_expired(&self, now: i64) -> bool {
+        self.expiration_date
+            .as_deref()
+            .is_some_and(|date| !parse_iso8601(date).is_some_and(|end| end > now))
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheFile {
+    entitlements: Vec<CachedEntitlement>,
+    /// Hex encoded HMAC-SHA256 of the JSON encoded `entitlements`.
+    mac: String,
+}
+
+/// Products the store last reported as owned, kept across restarts with a
+/// MAC so editing the file doesn't grant products.
+pub(crate) struct EntitlementCache {
+    path: Option<PathBuf>,
+    key: [u8; 32],
+    entitlements: Mutex<Vec<CachedEntitlement>>,
+}
+
+impl EntitlementCache {
+    /// Loads the entitlements kept in the app data directory.
+    pub(crate) fn in_app_data_dir<R: Runtime>(app: &AppHandle<R>, secret: &[u8]) -> Self {
+        let path = app
+            .path()
+            .app_data_dir()
+            .map(|dir| dir.join(ENTITLEMENT_CACHE_FILE))
+            .inspect_err(|e| log::warn!("Entitlements won't be cached: {}", e))
+            .ok();
+        Self::load(path, secret)
+    }
+
+    /// Loads the entitlements from `path`, ignoring them unless their MAC
+    /// matches, or keeps them in memory without one.
+    pub(crate) fn load(path: Option<PathBuf>, secret: &[u8]) -> Self {
+        let key = Sha256::new()
+            .chain_update(KEY_CONTEXT)
+            .chain_update(secret)
+            .finalize()
+            .into();
+        let cache = Self {
+            path,
+            key,
+            entitlements: Mutex::new(Vec::new()),
+        };
+        let Some(path) = &cache.path else {
+            return cache;
+        };
+        let file = match std::fs::read(path) {
+            Ok(json) => serde_json::from_slice::<CacheFile>(&json).map_err(|e| e.to_string()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return cache,
+            Err(e) => Err(e.to_string()),
+        };
+        match file {
+            Ok(file) if cache.verifies(&file) => {
+                *cache.lock() = file.entitlements;
+            }
+            Ok(_) => log::warn!(
+                "Ignoring cached entitlements at {}: the MAC doesn't match",
+                path.display()
+            ),
+            Err(e) => log::warn!("Ignoring cached entitlements at {}: {}", path.display(), e),
+        }
+        cache
+    }
+
+    /// Replaces the cached entitlements with the owned purchases.
+    pub(crate) fn store<'a>(&self, owned: impl IntoIterator<Item = &'a PurchaseDetails>) {
+        let mut owned: Vec<CachedEntitlement> =
+            owned.into_iter().map(CachedEntitlement::of).collect();
+        owned.sort_by(|a, b| a.product_id.cmp(&b.product_id));
+        let mut entitlements = self.lock();
+        if *entitlements != owned {
+            *entitlements = owned;
+            self.save(&entitlements);
+        }
+    }
+
+    /// The cached entitlements that haven't expired.
+    pub(crate) fn owned(&self) -> Vec<CachedEntitlement> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
+        self.lock()
+            .iter()
+            .filter(|entitlement| !entitlement.is_expired(now))
+            .cloned()
+            .collect()
+    }
+
+    /// Whether the MAC of the file matches its entitlements, compared in
+    /// constant time so the time taken doesn't tell how much of a forged MAC
+    /// is right.
+    fn verifies(&self, file: &CacheFile) -> bool {
+        self.mac(&file.entitlements)
+            .as_bytes()
+            .ct_eq(file.mac.as_bytes())
+            .into()
+    }
+
+    fn mac(&self, entitlements: &[CachedEntitlement]) -> String {
+        let json = serde_json::to_vec(entitlements).unwrap_or_default();
+        hmac(&self.key, &[&json])
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    /// Writes the entitlements with their MAC. Failures only log a warning,
+    /// as the entitlements are still kept in memory.
+    fn save(&self, entitlements: &[CachedEntitlement]) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let file = CacheFile {
+            entitlements: entitlements.to_vec(),
+            mac: self.mac(entitlements),
+        };
+        let result = serde_json::to_vec_pretty(&file)
+            .map_err(std::io::Error::other)
+            .and_then(|json| write_atomically(path, &json));
+        if let Err(e) = result {
+            log::warn!("Failed to cache entitlements at {}: {}", path.display(), e);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<CachedEntitlement>> {
+        self.entitlements
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const SECRET: &[u8] = b"app secret";
+
+    fn purchase(product_id: &str, expiration_date: Option<&str>) -> PurchaseDetails {
+        serde_json::from_value(json!({
+            "purchaseId": format!("GPA.{product_id}"),
+            "productId": product_id,
+            "verificationData": {
+                "localVerificationData": "receipt",
+                "serverVerificationData": "token",
+                "source": "google",
+            },
+            "expirationDate": expiration_date,
+            "status": "purchased",
+            "pendingCompletePurchase": false,
+        }))
+        .unwrap()
+    }
+
+    /// The path of the cache file in an empty directory of its own for each
+    /// test.
+    fn path(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tauri-plugin-iap-entitlements-{test}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join(ENTITLEMENT_CACHE_FILE)
+    }
+
+    fn products(cache: &EntitlementCache) -> Vec<String> {
+        cache
+            .owned()
+            .into_iter()
+            .map(|entitlement| entitlement.product_id)
+            .collect()
+    }
+
+    /// Rewrites the cache file at `path` with `edit`.
+    fn edit(path: &PathBuf, edit: impl FnOnce(&mut Value)) {
+        let mut file: Value = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        edit(&mut file);
+        std::fs::write(path, serde_json::to_vec(&file).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reads_back_what_it_wrote() {
+        let path = path("round-trip");
+        let cache = EntitlementCache::load(Some(path.clone()), SECRET);
+        cache.store(&[
+            purchase("pro_monthly", Some("2999-01-01T00:00:00Z")),
+            purchase("premium", None),
+        ]);
+
+        // Sorted, without tokens or receipts.
+        let json = std::fs::read_to_string(&path).unwrap();
+        assert!(!json.contains("token") && !json.contains("receipt"));
+        let relaunched = EntitlementCache::load(Some(path.clone()), SECRET);
+        assert_eq!(relaunched.owned(), cache.owned());
+        assert_eq!(
+            relaunched.owned(),
+            [
+                CachedEntitlement {
+                    product_id: "premium".into(),
+                    purchase_id: Some("GPA.premium".into()),
+                    expiration_date: None,
+                },
+                CachedEntitlement {
+                    product_id: "pro_monthly".into(),
+                    purchase_id: Some("GPA.pro_monthly".into()),
+                    expiration_date: Some("2999-01-01T00:00:00Z".into()),
+                },
+            ]
+        );
+        assert!(!path.with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn ignores_edited_files() {
+        let path = path("tampered");
+        EntitlementCache::load(Some(path.clone()), SECRET).store(&[purchase("premium", None)]);
+
+        // A product added without updating the MAC.
+        edit(&path, |file| {
+            file["entitlements"].as_array_mut().unwrap().push(
+                json!({ "productId": "lifetime", "purchaseId": null, "expirationDate": null }),
+            )
+        });
+        assert!(products(&EntitlementCache::load(Some(path.clone()), SECRET)).is_empty());
+
+        // An expiration date pushed back, then a forged MAC.
+        EntitlementCache::load(Some(path.clone()), SECRET)
+            .store(&[purchase("pro_monthly", Some("2000-01-01T00:00:00Z"))]);
+        edit(&path, |file| {
+            file["entitlements"][0]["expirationDate"] = json!("2999-01-01T00:00:00Z")
+        });
+        assert!(products(&EntitlementCache::load(Some(path.clone()), SECRET)).is_empty());
+        edit(&path, |file| file["mac"] = json!("00".repeat(32)));
+        assert!(products(&EntitlementCache::load(Some(path.clone()), SECRET)).is_empty());
+        edit(&path, |file| file["mac"] = json!(""));
+        assert!(products(&EntitlementCache::load(Some(path.clone()), SECRET)).is_empty());
+    }
+
+    #[test]
+    fn ignores_files_written_with_another_secret() {
+        let path = path("other-secret");
+        EntitlementCache::load(Some(path.clone()), SECRET).store(&[purchase("premium", None)]);
+
+        assert!(products(&EntitlementCache::load(Some(path.clone()), b"other secret")).is_empty());
+        assert_eq!(
+            products(&EntitlementCache::load(Some(path), SECRET)),
+            ["premium"]
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_files() {
+        let path = path("malformed");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        for contents in [&b""[..], b"{ not json", br#"{"entitlements":[]}"#] {
+            std::fs::write(&path, contents).unwrap();
+            assert!(products(&EntitlementCache::load(Some(path.clone()), SECRET)).is_empty());
+        }
+    }
+
+    #[test]
+    fn skips_expired_entitlements() {
+        let cache = EntitlementCache::load(None, SECRET);
+        cache.store(&[
+            purchase("expired", Some("2000-01-01T00:00:00Z")),
+            purchase("unreadable", Some("next year")),
+            purchase("premium", None),
+            purchase("pro_monthly", Some("2999-01-01T00:00:00Z")),
+        ]);
+
+        assert_eq!(products(&cache), ["premium", "pro_monthly"]);
+    }
+}
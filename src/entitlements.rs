@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+#[cfg(feature = "entitlement-cache")]
+use std::sync::OnceLock;
+use std::sync::{Mutex, PoisonError};
+
+#[cfg(feature = "entitlement-cache")]
+use crate::entitlement_cache::EntitlementCache;
+use crate::models::{
+    EntitlementSource, ExportedEntitlement, OwnedProduct, PurchaseDetails, PurchaseStatus,
+    SubscriptionState,
+};
+
+/// Products the user owns, from the purchases the store reported since the
+/// app started.
+#[derive(Default)]
+pub(crate) struct Entitlements {
+    owned: Mutex<HashMap<String, PurchaseDetails>>,
+    /// Whether subscriptions on hold are owned, the `owned_on_hold` setting.
+    owned_on_hold: bool,
+    /// Keeps the owned products for when the store is unreachable.
+    #[cfg(feature = "entitlement-cache")]
+    cache: OnceLock<EntitlementCache>,
+}
+
+impl Entitlements {
+    pub(crate) fn new(owned_on_hold: bool) -> Self {
+        Self {
+            owned_on_hold,
+            ..Self::default()
+        }
+    }
+
+    /// Keeps the owned products in `cache` whenever they change. Only the
+    /// first cache set is used.
+    #[cfg(feature = "entitlement-cache")]
+    pub(crate) fn set_cache(&self, cache: EntitlementCache) {
+        if self.cache.set(cache).is_err() {
+            log::warn!("Entitlement cache already set, ignoring");
+        }
+    }
+
+    /// Adds the purchased and restored products, and removes subscriptions
+    /// that lapsed, e.g. went on hold.
+    pub(crate) fn update(&self, purchases: &[PurchaseDetails]) {
+        let mut owned = self.owned.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut changed = false;
+        for purchase in purchases {
+            if self.grants(purchase) {
+                owned.insert(purchase.product_id.clone(), purchase.clone());
+                changed = true;
+            } else if purchase.subscription_state.is_some() {
+                changed |= owned.remove(&purchase.product_id).is_some();
+            }
+        }
+        if changed {
+            self.cache(&owned);
+        }
+    }
+
+    /// Replaces the owned products with a full report of them, e.g. the
+    /// result of a restore, dropping products that are no longer owned.
+    pub(crate) fn replace(&self, purchases: &[PurchaseDetails]) {
+        let mut owned = self.owned.lock().unwrap_or_else(PoisonError::into_inner);
+        *owned = purchases
+            .iter()
+            .filter(|purchase| self.grants(purchase))
+            .map(|purchase| (purchase.product_id.clone(), purchase.clone()))
+            .collect();
+        self.cache(&owned);
+    }
+
+    /// Fills in the verification data withheld from the webview with
+    /// `redactVerificationData`, from the owned purchase with the same
+    /// transaction. Purchases that carry their own are returned unchanged.
+    pub(crate) fn with_verification_data(&self, mut purchase: PurchaseDetails) -> PurchaseDetails {
+        if !purchase.verification_data.server_verification_data.is_empty() {
+            return purchase;
+        }
+        let owned = self.owned.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(known) = owned
+            .get(&purchase.product_id)
+            .filter(|known| known.purchase_id == purchase.purchase_id)
+        {
+            purchase.verification_data = known.verification_data.clone();
+        }
+        purchase
+    }
+
+    /// The owned purchase with the transaction ID.
+    pub(crate) fn find(&self, purchase_id: &str) -> Option<PurchaseDetails> {
+        self.owned
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .find(|purchase| purchase.purchase_id.as_deref() == Some(purchase_id))
+            .cloned()
+    }
+
+    pub(crate) fn is_owned(&self, product_id: &str) -> bool {
+        self.owned
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(product_id)
+    }
+
+    /// Whether the product is owned through a purchase that never ends, i.e.
+    /// not a subscription or a time-limited add-on.
+    pub(crate) fn owns_for_life(&self, product_id: &str) -> bool {
+        self.owned
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(product_id)
+            .is_some_and(|purchase| {
+                purchase.subscription_state.is_none() && purchase.entitlement_end().is_none()
+            })
+    }
+
+    /// Whether the cache has the product as owned and unexpired. Always
+    /// false without the `entitlement-cache` feature or a secret.
+    pub(crate) fn is_cached(&self, product_id: &str) -> bool {
+        self.cached()
+            .iter()
+            .any(|product| product.product_id == product_id)
+    }
+
+    /// The owned products reported by the store, plus the cached ones it
+    /// didn't report if `offline`.
+    pub(crate) fn owned_products(&self, offline: bool) -> Vec<OwnedProduct> {
+        let mut products: Vec<OwnedProduct> = self
+            .owned
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .map(|purchase| OwnedProduct {
+                product_id: purchase.product_id.clone(),
+                purchase_id: purchase.purchase_id.clone(),
+                expiration_date: purchase.entitlement_end(),
+                source: EntitlementSource::Store,
+            })
+            .collect();
+        if offline {
+            for product in self.cached() {
+                if !products
+                    .iter()
+                    .any(|known| known.product_id == product.product_id)
+                {
+                    products.push(product);
+                }
+            }
+        }
+        products.sort_by(|a, b| a.product_id.cmp(&b.product_id));
+        products
+    }
+
+    /// The owned products with their states and dates, plus the cached ones
+    /// the store didn't report, without tokens or receipts.
+    pub(crate) fn export(&self) -> Vec<ExportedEntitlement> {
+        let mut entitlements: Vec<ExportedEntitlement> = self
+            .owned
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .map(|purchase| ExportedEntitlement {
+                product_id: purchase.product_id.clone(),
+                status: purchase.status.clone(),
+                subscription_state: purchase.subscription_state,
+                transaction_date: purchase.transaction_date.clone(),
+                expiration_date: purchase.entitlement_end(),
+                grace_period_expiration_date: purchase.grace_period_expiration_date.clone(),
+                source: EntitlementSource::Store,
+            })
+            .collect();
+        for product in self.cached() {
+            if !entitlements
+                .iter()
+                .any(|known| known.product_id == product.product_id)
+            {
+                entitlements.push(ExportedEntitlement {
+                    product_id: product.product_id,
+                    status: PurchaseStatus::Restored,
+                    subscription_state: None,
+                    transaction_date: None,
+                    expiration_date: product.expiration_date,
+                    grace_period_expiration_date: None,
+                    source: EntitlementSource::Cache,
+                });
+            }
+        }
+        entitlements.sort_by(|a, b| a.product_id.cmp(&b.product_id));
+        entitlements
+    }
+
+    /// Replaces the owned products with imported ones, returning the
+    /// purchases to report: the products no longer owned as `Canceled`,
+    /// followed by the imported ones.
+    pub(crate) fn import(&self, entitlements: &[ExportedEntitlement]) -> Vec<PurchaseDetails> {
+        let imported: Vec<PurchaseDetails> = entitlements
+            .iter()
+            .map(ExportedEntitlement::to_purchase)
+            .collect();
+        let mut owned = self.owned.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut purchases: Vec<PurchaseDetails> = owned
+            .values()
+            .filter(|purchase| {
+                !imported
+                    .iter()
+                    .any(|known| known.product_id == purchase.product_id)
+            })
+            .map(|purchase| PurchaseDetails {
+                status: PurchaseStatus::Canceled,
+                pending_complete_purchase: false,
+                ..purchase.clone()
+            })
+            .collect();
+        *owned = imported
+            .iter()
+            .filter(|purchase| self.grants(purchase))
+            .map(|purchase| (purchase.product_id.clone(), purchase.clone()))
+            .collect();
+        self.cache(&owned);
+        purchases.extend(imported);
+        purchases
+    }
+
+    /// The unexpired cached products.
+    fn cached(&self) -> Vec<OwnedProduct> {
+        #[cfg(feature = "entitlement-cache")]
+        if let Some(cache) = self.cache.get() {
+            return cache
+                .owned()
+                .into_iter()
+                .map(|entitlement| OwnedProduct {
+                    product_id: entitlement.product_id,
+                    purchase_id: entitlement.purchase_id,
+                    expiration_date: entitlement.expiration_date,
+                    source: EntitlementSource::Cache,
+                })
+                .collect();
+        }
+        Vec::new()
+    }
+
+    /// Whether the purchase makes the product owned: it was purchased or
+    /// restored, and isn't a subscription that lapsed. Subscriptions in the
+    /// grace period stay owned, and on hold if `owned_on_hold` is set.
+    fn grants(&self, purchase: &PurchaseDetails) -> bool {
+        let paid = matches!(
+            purchase.status,
+            PurchaseStatus::Purchased | PurchaseStatus::Restored
+        );
+        paid && match purchase.subscription_state {
+            Some(SubscriptionState::OnHold) => self.owned_on_hold,
+            Some(state) => state.is_entitled(),
+            None => true,
+        }
+    }
+
+    #[cfg_attr(not(feature = "entitlement-cache"), allow(unused_variables))]
+    fn cache(&self, owned: &HashMap<String, PurchaseDetails>) {
+        #[cfg(feature = "entitlement-cache")]
+        if let Some(cache) = self.cache.get() {
+            cache.store(owned.values());
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use serde::{ser::Serializer, Serialize};
+use std::{convert::Infallible, fmt, str::FromStr, time::Duration};
+
+use serde::{de::Deserializer, ser::Serializer, Deserialize, Serialize};
+
+use crate::models::IAPError;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -9,14 +13,22 @@ pub enum Error {
 
     #[cfg(mobile)]
     #[error(transparent)]
-    PluginInvoke(#[from] tauri::plugin::mobile::PluginInvokeError),
+    PluginInvoke(tauri::plugin::mobile::PluginInvokeError),
 
     #[error("In-app purchases are not supported on this platform")]
     PlatformNotSupported,
 
+    #[error("In-app purchases have not been initialized, call initialize() first")]
+    NotInitialized,
+
     #[error("Failed to initialize billing client: {0}")]
     BillingClientInitError(String),
 
+    /// The device has no store to buy from, e.g. Google Play is missing, so
+    /// connecting again won't help.
+    #[error("The store is unavailable on this device: {0}")]
+    StoreUnavailable(String),
+
     #[error("Product details query failed: {0}")]
     ProductQueryError(String),
 
@@ -35,66 +47,713 @@ pub enum Error {
     #[error("Network error during billing operation: {0}")]
     NetworkError(String),
 
+    /// A store server API throttled the request.
+    #[error("The store server API rate limited the request")]
+    RateLimited {
+        /// How long the server asked to wait before retrying (optional)
+        retry_after: Option<Duration>,
+    },
+
     #[error("User cancelled the purchase")]
     UserCancelled,
 
     #[error("Item already owned")]
     ItemAlreadyOwned,
 
+    #[error("Item not owned: {0}")]
+    ItemNotOwned(String),
+
+    /// `complete_purchase` was called with details the store never reported.
+    #[error("Unknown purchase: {0} was not reported by the store")]
+    UnknownPurchase(String),
+
+    /// `buy_upgrade` found no active subscription to replace.
+    #[error("No active subscription to replace with {0}")]
+    NoActiveSubscription(String),
+
+    /// `buy_upgrade` found several active subscriptions it could replace.
+    #[error("Several active subscriptions could be replaced: {}", candidates.join(", "))]
+    AmbiguousSubscription {
+        /// Product IDs of the active subscriptions
+        candidates: Vec<String>,
+    },
+
     #[error("Service disconnected")]
     ServiceDisconnected,
 
+    #[error("Service timed out")]
+    ServiceTimeout,
+
+    /// An operation exceeded its configured timeout. It may still complete in
+    /// the store afterwards.
+    #[error("The {operation} operation timed out")]
+    TimedOut {
+        /// The timed out operation: `query`, `purchase`, `finalize`,
+        /// `restore` or `sheet`
+        operation: String,
+    },
+
+    /// The command scope of the calling window doesn't allow purchasing the
+    /// product.
+    #[error("Purchasing {product_id} is not allowed by the command scope")]
+    ScopeViolation {
+        /// The product the purchase was started for
+        product_id: String,
+    },
+
+    /// A purchase command was invoked from a window missing from
+    /// `allowedWindows`.
+    #[error("Purchases can't be made from the {window} window")]
+    WindowNotAllowed {
+        /// Label of the invoking window
+        window: String,
+    },
+
+    /// The App Store receipt is missing, even after a refresh if one was
+    /// requested.
+    #[error("The App Store receipt is unavailable")]
+    ReceiptUnavailable,
+
     #[error("Feature not supported: {0}")]
     FeatureNotSupported(String),
 
+    /// The store rejected the request because the integration is misconfigured.
+    #[error(
+        "Developer error: {0}. Check that the app is signed with the release key and installed \
+         from the store, that the product IDs exist in the store console, and that the product \
+         type matches the purchase method"
+    )]
+    DeveloperError(String),
+
+    /// The plugin configuration, e.g. the mock catalog, is invalid.
+    #[error("Invalid plugin configuration: {0}")]
+    InvalidConfig(String),
+
     #[error("Internal billing error: {0}")]
     InternalError(String),
+
+    /// An error mapped from a native store response, keeping the raw code the
+    /// store reported (a Google Play `BillingResponseCode` or an `SKError` code).
+    ///
+    /// Use [`Error::code`] to match on the mapped error regardless of whether
+    /// it carries a native code.
+    #[error("{source}")]
+    Native {
+        source: Box<Error>,
+        native_code: i32,
+    },
+}
+
+impl Error {
+    /// Attaches the raw native response code the error was mapped from.
+    pub fn with_native_code(self, native_code: i32) -> Self {
+        match self {
+            Error::Native { source, .. } => Error::Native {
+                source,
+                native_code,
+            },
+            error => Error::Native {
+                source: Box::new(error),
+                native_code,
+            },
+        }
+    }
+
+    /// Returns the raw native response code, if the error came from the store.
+    pub fn native_code(&self) -> Option<i32> {
+        match self {
+            Error::Native { native_code, .. } => Some(*native_code),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the failure is transient and the operation may succeed when retried.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Native { source, .. } => source.is_retryable(),
+            error => matches!(
+                error,
+                Error::NetworkError(_)
+                    | Error::RateLimited { .. }
+                    | Error::ServiceDisconnected
+                    | Error::ServiceTimeout
+            ),
+        }
+    }
+
+    /// Returns the stable code identifying this error.
+    pub fn code(&self) -> IapErrorCode {
+        match self {
+            Error::Native { source, .. } => source.code(),
+            Error::Io(_) => IapErrorCode::Io,
+            #[cfg(mobile)]
+            Error::PluginInvoke(_) => IapErrorCode::PluginInvoke,
+            Error::PlatformNotSupported => IapErrorCode::PlatformNotSupported,
+            Error::NotInitialized => IapErrorCode::NotInitialized,
+            Error::BillingClientInitError(_) => IapErrorCode::BillingClientInitError,
+            Error::StoreUnavailable(_) => IapErrorCode::StoreUnavailable,
+            Error::ProductQueryError(_) => IapErrorCode::ProductQueryError,
+            Error::PurchaseError(_) => IapErrorCode::PurchaseError,
+            Error::ConsumptionError(_) => IapErrorCode::ConsumptionError,
+            Error::RestoreError(_) => IapErrorCode::RestoreError,
+            Error::InvalidPurchaseToken(_) => IapErrorCode::InvalidPurchaseToken,
+            Error::NetworkError(_) => IapErrorCode::NetworkError,
+            Error::RateLimited { .. } => IapErrorCode::RateLimited,
+            Error::UserCancelled => IapErrorCode::UserCancelled,
+            Error::ItemAlreadyOwned => IapErrorCode::ItemAlreadyOwned,
+            Error::ItemNotOwned(_) => IapErrorCode::ItemNotOwned,
+            Error::UnknownPurchase(_) => IapErrorCode::UnknownPurchase,
+            Error::NoActiveSubscription(_) => IapErrorCode::NoActiveSubscription,
+            Error::AmbiguousSubscription { .. } => IapErrorCode::AmbiguousSubscription,
+            Error::ServiceDisconnected => IapErrorCode::ServiceDisconnected,
+            Error::ServiceTimeout => IapErrorCode::ServiceTimeout,
+            Error::TimedOut { .. } => IapErrorCode::TimedOut,
+            Error::ScopeViolation { .. } => IapErrorCode::ScopeViolation,
+            Error::WindowNotAllowed { .. } => IapErrorCode::WindowNotAllowed,
+            Error::ReceiptUnavailable => IapErrorCode::ReceiptUnavailable,
+            Error::FeatureNotSupported(_) => IapErrorCode::FeatureNotSupported,
+            Error::DeveloperError(_) => IapErrorCode::DeveloperError,
+            Error::InvalidConfig(_) => IapErrorCode::InvalidConfig,
+            Error::InternalError(_) => IapErrorCode::InternalError,
+        }
+    }
+}
+
+impl From<IAPError> for Error {
+    fn from(error: IAPError) -> Self {
+        let message = error.message;
+        let detail = |key: &str| {
+            error
+                .details
+                .as_ref()
+                .and_then(|details| details.get(key))
+                .and_then(|value| value.as_str())
+                .map(str::to_owned)
+        };
+        let operation = detail("operation");
+        let product_id = detail("productId");
+        let window = detail("window");
+        let candidates = error
+            .details
+            .as_ref()
+            .and_then(|details| details.get("candidates"))
+            .and_then(|value| serde_json::from_value::<Vec<String>>(value.clone()).ok());
+        let retry_after = error
+            .details
+            .as_ref()
+            .and_then(|details| details.get("retryAfterMs"))
+            .and_then(|value| value.as_u64())
+            .map(Duration::from_millis);
+        let mapped = match error.code.parse().unwrap_or(IapErrorCode::Unknown) {
+            IapErrorCode::Io => Error::Io(std::io::Error::other(message)),
+            IapErrorCode::PlatformNotSupported => Error::PlatformNotSupported,
+            IapErrorCode::NotInitialized => Error::NotInitialized,
+            IapErrorCode::BillingClientInitError => Error::BillingClientInitError(message),
+            IapErrorCode::StoreUnavailable => Error::StoreUnavailable(message),
+            IapErrorCode::ProductQueryError => Error::ProductQueryError(message),
+            IapErrorCode::PurchaseError => Error::PurchaseError(message),
+            IapErrorCode::ConsumptionError => Error::ConsumptionError(message),
+            IapErrorCode::RestoreError => Error::RestoreError(message),
+            IapErrorCode::InvalidPurchaseToken => Error::InvalidPurchaseToken(message),
+            IapErrorCode::NetworkError => Error::NetworkError(message),
+            IapErrorCode::RateLimited => Error::RateLimited { retry_after },
+            IapErrorCode::UserCancelled => Error::UserCancelled,
+            IapErrorCode::ItemAlreadyOwned => Error::ItemAlreadyOwned,
+            IapErrorCode::ItemNotOwned => Error::ItemNotOwned(message),
+            IapErrorCode::UnknownPurchase => Error::UnknownPurchase(message),
+            IapErrorCode::NoActiveSubscription => Error::NoActiveSubscription(message),
+            IapErrorCode::AmbiguousSubscription => Error::AmbiguousSubscription {
+                candidates: candidates.unwrap_or_default(),
+            },
+            IapErrorCode::ServiceDisconnected => Error::ServiceDisconnected,
+            IapErrorCode::ServiceTimeout => Error::ServiceTimeout,
+            IapErrorCode::TimedOut => Error::TimedOut {
+                operation: operation.unwrap_or(message),
+            },
+            IapErrorCode::ScopeViolation => Error::ScopeViolation {
+                product_id: product_id.unwrap_or(message),
+            },
+            IapErrorCode::WindowNotAllowed => Error::WindowNotAllowed {
+                window: window.unwrap_or(message),
+            },
+            IapErrorCode::ReceiptUnavailable => Error::ReceiptUnavailable,
+            IapErrorCode::FeatureNotSupported => Error::FeatureNotSupported(message),
+            IapErrorCode::DeveloperError => Error::DeveloperError(message),
+            IapErrorCode::InvalidConfig => Error::InvalidConfig(message),
+            // An invoke error can only be produced locally, so one arriving in a
+            // payload carries nothing more than its message.
+            IapErrorCode::PluginInvoke | IapErrorCode::InternalError | IapErrorCode::Unknown => {
+                Error::InternalError(message)
+            }
+        };
+        match error.native_code {
+            Some(native_code) => mapped.with_native_code(native_code),
+            None => mapped,
+        }
+    }
+}
+
+#[cfg(mobile)]
+impl Error {
+    /// Maps a failed `run_mobile_plugin` call to the matching error variant,
+    /// see [`Error::from_rejection`].
+    pub(crate) fn from_invoke_error(error: tauri::plugin::mobile::PluginInvokeError) -> Self {
+        use tauri::plugin::mobile::PluginInvokeError;
+
+        match error {
+            PluginInvokeError::InvokeRejected(response) => {
+                Error::from_rejection(response.code, response.message.unwrap_or_default())
+            }
+            error => Error::PluginInvoke(error),
+        }
+    }
+}
+
+// Only the mobile plugins reject invokes, the tests run everywhere.
+#[cfg(any(mobile, test))]
+impl Error {
+    /// Maps the code and message of a rejected native call.
+    ///
+    /// The native layers reject with a JSON `{ code, message, details,
+    /// nativeCode }` body as the rejection message. Rejections that only set a
+    /// code are mapped from that code, whatever the message says in the
+    /// device's language, and anything else becomes `InternalError`.
+    pub(crate) fn from_rejection(code: Option<String>, message: String) -> Self {
+        if let Ok(error) = serde_json::from_str::<IAPError>(&message) {
+            #[cfg(feature = "tracing")]
+            crate::instrument::native_error(&error);
+            return error.into();
+        }
+        match code {
+            Some(code) => IAPError {
+                code,
+                message,
+                details: None,
+                native_code: None,
+            }
+            .into(),
+            None if message.is_empty() => {
+                Error::InternalError("Native call rejected without a reason".into())
+            }
+            None => Error::InternalError(message),
+        }
+    }
+}
+
+impl From<&Error> for IAPError {
+    fn from(error: &Error) -> Self {
+        let details = match error {
+            Error::TimedOut { operation } => Some(serde_json::json!({ "operation": operation })),
+            Error::ScopeViolation { product_id } => {
+                Some(serde_json::json!({ "productId": product_id }))
+            }
+            Error::WindowNotAllowed { window } => Some(serde_json::json!({ "window": window })),
+            Error::AmbiguousSubscription { candidates } => {
+                Some(serde_json::json!({ "candidates": candidates }))
+            }
+            Error::RateLimited {
+                retry_after: Some(retry_after),
+            } => Some(serde_json::json!({ "retryAfterMs": retry_after.as_millis() })),
+            _ => None,
+        };
+        IAPError {
+            code: error.code().to_string(),
+            message: error.to_string(),
+            details,
+            native_code: error.native_code(),
+        }
+    }
 }
 
+/// Errors serialize with the same shape as [`IAPError`] payloads:
+/// `{ "code": "networkError", "message": "...", "nativeCode": 6 }`.
 impl Serialize for Error {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_str(self.to_string().as_ref())
+        IAPError::from(self).serialize(serializer)
     }
 }
 
-#[cfg(target_os = "android")]
+// Only Google Play reports response codes, the tests run everywhere.
+#[cfg(any(target_os = "android", test))]
 impl Error {
+    /// Maps a Google Play `BillingResponseCode` to an error, keeping the raw code.
     pub(crate) fn from_response_code(code: i32, message: Option<String>) -> Self {
         use std::format as f;
-        match code {
-            0 => {
-                Error::InternalError(message.unwrap_or_else(|| f!("Unknown error code: {}", code)))
-            }
-            1 => Error::UserCancelled,
-            2 => Error::ServiceDisconnected,
-            3 => Error::BillingClientInitError(
-                message.unwrap_or_else(|| "Billing unavailable".into()),
-            ),
-            4 => Error::ItemAlreadyOwned,
-            5 => Error::ItemNotOwned(message.unwrap_or_else(|| "Item not owned".into())),
-            6 => Error::NetworkError(message.unwrap_or_else(|| "Network error".into())),
-            7 => Error::FeatureNotSupported(
+        let mapped = match code {
+            // SERVICE_TIMEOUT
+            -3 => Error::ServiceTimeout,
+            // FEATURE_NOT_SUPPORTED
+            -2 => Error::FeatureNotSupported(
                 message.unwrap_or_else(|| "Feature not supported".into()),
             ),
+            // SERVICE_DISCONNECTED
+            -1 => Error::ServiceDisconnected,
+            // USER_CANCELED
+            1 => Error::UserCancelled,
+            // SERVICE_UNAVAILABLE, the network connection is down
+            2 => Error::NetworkError(message.unwrap_or_else(|| "Service unavailable".into())),
+            // BILLING_UNAVAILABLE, e.g. on devices without Google Play
+            3 => Error::StoreUnavailable(message.unwrap_or_else(|| "Billing unavailable".into())),
+            // ITEM_UNAVAILABLE
+            4 => Error::PurchaseError(message.unwrap_or_else(|| "Item unavailable".into())),
+            // DEVELOPER_ERROR
+            5 => Error::DeveloperError(
+                message.unwrap_or_else(|| "Invalid arguments provided to the API".into()),
+            ),
+            // ITEM_ALREADY_OWNED
+            7 => Error::ItemAlreadyOwned,
+            // ITEM_NOT_OWNED
+            8 => Error::ItemNotOwned(message.unwrap_or_else(|| "Item not owned".into())),
+            // NETWORK_ERROR
+            12 => Error::NetworkError(message.unwrap_or_else(|| "Network error".into())),
+            // OK never reaches here, and ERROR (6) is a fatal error on Google's side.
             _ => {
                 Error::InternalError(message.unwrap_or_else(|| f!("Unknown error code: {}", code)))
             }
+        };
+        mapped.with_native_code(code)
+    }
+}
+
+/// Machine-readable code for every [`Error`] variant.
+///
+/// Serializes to the same camelCase strings used in the `code` field of
+/// [`IAPError`] payloads, so errors can be matched on regardless of whether
+/// they originated in Rust or in the native layer. Codes this version of the
+/// plugin does not know about deserialize to [`IapErrorCode::Unknown`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IapErrorCode {
+    Io,
+    PluginInvoke,
+    PlatformNotSupported,
+    NotInitialized,
+    BillingClientInitError,
+    StoreUnavailable,
+    ProductQueryError,
+    PurchaseError,
+    ConsumptionError,
+    RestoreError,
+    InvalidPurchaseToken,
+    NetworkError,
+    RateLimited,
+    UserCancelled,
+    ItemAlreadyOwned,
+    ItemNotOwned,
+    UnknownPurchase,
+    NoActiveSubscription,
+    AmbiguousSubscription,
+    ServiceDisconnected,
+    ServiceTimeout,
+    TimedOut,
+    ScopeViolation,
+    WindowNotAllowed,
+    ReceiptUnavailable,
+    FeatureNotSupported,
+    DeveloperError,
+    InvalidConfig,
+    InternalError,
+    Unknown,
+}
+
+impl IapErrorCode {
+    /// Every code, in declaration order.
+    pub const ALL: &'static [IapErrorCode] = &[
+        IapErrorCode::Io,
+        IapErrorCode::PluginInvoke,
+        IapErrorCode::PlatformNotSupported,
+        IapErrorCode::NotInitialized,
+        IapErrorCode::BillingClientInitError,
+        IapErrorCode::StoreUnavailable,
+        IapErrorCode::ProductQueryError,
+        IapErrorCode::PurchaseError,
+        IapErrorCode::ConsumptionError,
+        IapErrorCode::RestoreError,
+        IapErrorCode::InvalidPurchaseToken,
+        IapErrorCode::NetworkError,
+        IapErrorCode::RateLimited,
+        IapErrorCode::UserCancelled,
+        IapErrorCode::ItemAlreadyOwned,
+        IapErrorCode::ItemNotOwned,
+        IapErrorCode::UnknownPurchase,
+        IapErrorCode::NoActiveSubscription,
+        IapErrorCode::AmbiguousSubscription,
+        IapErrorCode::ServiceDisconnected,
+        IapErrorCode::ServiceTimeout,
+        IapErrorCode::TimedOut,
+        IapErrorCode::ScopeViolation,
+        IapErrorCode::WindowNotAllowed,
+        IapErrorCode::ReceiptUnavailable,
+        IapErrorCode::FeatureNotSupported,
+        IapErrorCode::DeveloperError,
+        IapErrorCode::InvalidConfig,
+        IapErrorCode::InternalError,
+        IapErrorCode::Unknown,
+    ];
+
+    /// The wire representation of this code.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IapErrorCode::Io => "io",
+            IapErrorCode::PluginInvoke => "pluginInvoke",
+            IapErrorCode::PlatformNotSupported => "platformNotSupported",
+            IapErrorCode::NotInitialized => "notInitialized",
+            IapErrorCode::BillingClientInitError => "billingClientInitError",
+            IapErrorCode::StoreUnavailable => "storeUnavailable",
+            IapErrorCode::ProductQueryError => "productQueryError",
+            IapErrorCode::PurchaseError => "purchaseError",
+            IapErrorCode::ConsumptionError => "consumptionError",
+            IapErrorCode::RestoreError => "restoreError",
+            IapErrorCode::InvalidPurchaseToken => "invalidPurchaseToken",
+            IapErrorCode::NetworkError => "networkError",
+            IapErrorCode::RateLimited => "rateLimited",
+            IapErrorCode::UserCancelled => "userCancelled",
+            IapErrorCode::ItemAlreadyOwned => "itemAlreadyOwned",
+            IapErrorCode::ItemNotOwned => "itemNotOwned",
+            IapErrorCode::UnknownPurchase => "unknownPurchase",
+            IapErrorCode::NoActiveSubscription => "noActiveSubscription",
+            IapErrorCode::AmbiguousSubscription => "ambiguousSubscription",
+            IapErrorCode::ServiceDisconnected => "serviceDisconnected",
+            IapErrorCode::ServiceTimeout => "serviceTimeout",
+            IapErrorCode::TimedOut => "timedOut",
+            IapErrorCode::ScopeViolation => "scopeViolation",
+            IapErrorCode::WindowNotAllowed => "windowNotAllowed",
+            IapErrorCode::ReceiptUnavailable => "receiptUnavailable",
+            IapErrorCode::FeatureNotSupported => "featureNotSupported",
+            IapErrorCode::DeveloperError => "developerError",
+            IapErrorCode::InvalidConfig => "invalidConfig",
+            IapErrorCode::InternalError => "internalError",
+            IapErrorCode::Unknown => "unknown",
         }
     }
 }
 
-#[derive(Debug, thiserror::Error)]
-#[error("Item not owned: {0}")]
-pub struct ItemNotOwned(String);
+impl fmt::Display for IapErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for IapErrorCode {
+    type Err = Infallible;
 
-impl Serialize for ItemNotOwned {
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(IapErrorCode::ALL
+            .iter()
+            .copied()
+            .find(|code| code.as_str() == s)
+            .unwrap_or(IapErrorCode::Unknown))
+    }
+}
+
+impl Serialize for IapErrorCode {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        serializer.serialize_str(self.0.as_ref())
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for IapErrorCode {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let code = String::deserialize(deserializer)?;
+        Ok(code.parse().unwrap_or(IapErrorCode::Unknown))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn cancelled_rejections_are_user_cancelled() {
+        // The JSON body of `Invoke.reject(code:message:)`, with the SKError code.
+        let body = json!({
+            "code": "userCancelled",
+            "message": "Annulé",
+            "nativeCode": 2,
+        });
+        let error = Error::from_rejection(Some("userCancelled".into()), body.to_string());
+        assert_eq!(error.code(), IapErrorCode::UserCancelled);
+        assert_eq!(error.native_code(), Some(2));
+        assert!(matches!(
+            error,
+            Error::Native { ref source, .. } if matches!(**source, Error::UserCancelled)
+        ));
+
+        // Whatever the message, in any language.
+        for message in ["Vorgang abgebrochen", "", "cancelled"] {
+            let error = Error::from_rejection(Some("userCancelled".into()), message.into());
+            assert!(matches!(error, Error::UserCancelled), "{message:?}");
+        }
+    }
+
+    #[test]
+    fn rejections_without_a_code_are_internal_errors() {
+        let error = Error::from_rejection(None, "The operation couldn't be completed".into());
+        assert_eq!(error.code(), IapErrorCode::InternalError);
+        // A cancellation told only by the message isn't one.
+        let error = Error::from_rejection(None, "User cancelled".into());
+        assert_eq!(error.code(), IapErrorCode::InternalError);
+        assert!(matches!(
+            Error::from_rejection(None, String::new()),
+            Error::InternalError(message) if message == "Native call rejected without a reason"
+        ));
+    }
+
+    #[test]
+    fn rejection_bodies_win_over_the_code() {
+        let body = json!({
+            "code": "itemNotOwned",
+            "message": "Not owned",
+            "details": { "productId": "premium" },
+        });
+        let error = Error::from_rejection(Some("internalError".into()), body.to_string());
+        assert_eq!(error.code(), IapErrorCode::ItemNotOwned);
+    }
+
+    #[test]
+    fn play_response_codes_keep_their_native_code() {
+        for (code, expected) in [
+            (-3, IapErrorCode::ServiceTimeout),
+            (1, IapErrorCode::UserCancelled),
+            (2, IapErrorCode::NetworkError),
+            (5, IapErrorCode::DeveloperError),
+            // ERROR, a fatal error on Google's side.
+            (6, IapErrorCode::InternalError),
+            (12, IapErrorCode::NetworkError),
+        ] {
+            let error = Error::from_response_code(code, None);
+            assert_eq!(error.code(), expected, "{code}");
+
+            let json = serde_json::to_value(&error).unwrap();
+            assert_eq!(json["code"], expected.as_str(), "{code}");
+            assert_eq!(json["nativeCode"], code, "{code}");
+        }
+    }
+
+    #[test]
+    fn unknown_play_response_codes_keep_their_native_code() {
+        let error = Error::from_response_code(99, None);
+
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({
+                "code": "internalError",
+                "message": "Internal billing error: Unknown error code: 99",
+                "nativeCode": 99,
+            })
+        );
+        // A message from the billing client wins.
+        let error = Error::from_response_code(99, Some("Billing is broken".into()));
+        assert_eq!(
+            serde_json::to_value(&error).unwrap()["message"],
+            "Internal billing error: Billing is broken"
+        );
+        // And the code survives a trip through the webview.
+        let back = Error::from(
+            serde_json::from_value::<IAPError>(serde_json::to_value(&error).unwrap()).unwrap(),
+        );
+        assert_eq!(back.native_code(), Some(99));
+    }
+
+    /// An error with the `code`, carrying details where the variant has any.
+    /// Matched exhaustively so new codes get a round trip.
+    fn sample(code: IapErrorCode) -> Option<Error> {
+        let message = || String::from("message");
+        Some(match code {
+            IapErrorCode::Io => Error::Io(std::io::Error::other("disk full")),
+            // Only produced locally by failed mobile invokes.
+            IapErrorCode::PluginInvoke | IapErrorCode::Unknown => return None,
+            IapErrorCode::PlatformNotSupported => Error::PlatformNotSupported,
+            IapErrorCode::NotInitialized => Error::NotInitialized,
+            IapErrorCode::BillingClientInitError => Error::BillingClientInitError(message()),
+            IapErrorCode::StoreUnavailable => Error::StoreUnavailable(message()),
+            IapErrorCode::ProductQueryError => Error::ProductQueryError(message()),
+            IapErrorCode::PurchaseError => Error::PurchaseError(message()),
+            IapErrorCode::ConsumptionError => Error::ConsumptionError(message()),
+            IapErrorCode::RestoreError => Error::RestoreError(message()),
+            IapErrorCode::InvalidPurchaseToken => Error::InvalidPurchaseToken(message()),
+            IapErrorCode::NetworkError => Error::NetworkError(message()),
+            IapErrorCode::RateLimited => Error::RateLimited {
+                retry_after: Some(Duration::from_millis(1500)),
+            },
+            IapErrorCode::UserCancelled => Error::UserCancelled,
+            IapErrorCode::ItemAlreadyOwned => Error::ItemAlreadyOwned,
+            IapErrorCode::ItemNotOwned => Error::ItemNotOwned(message()),
+            IapErrorCode::UnknownPurchase => Error::UnknownPurchase(message()),
+            IapErrorCode::NoActiveSubscription => Error::NoActiveSubscription(message()),
+            IapErrorCode::AmbiguousSubscription => Error::AmbiguousSubscription {
+                candidates: vec!["monthly".into(), "yearly".into()],
+            },
+            IapErrorCode::ServiceDisconnected => Error::ServiceDisconnected,
+            IapErrorCode::ServiceTimeout => Error::ServiceTimeout,
+            IapErrorCode::TimedOut => Error::TimedOut {
+                operation: "purchase".into(),
+            },
+            IapErrorCode::ScopeViolation => Error::ScopeViolation {
+                product_id: "premium".into(),
+            },
+            IapErrorCode::WindowNotAllowed => Error::WindowNotAllowed {
+                window: "settings".into(),
+            },
+            IapErrorCode::ReceiptUnavailable => Error::ReceiptUnavailable,
+            IapErrorCode::FeatureNotSupported => Error::FeatureNotSupported(message()),
+            IapErrorCode::DeveloperError => Error::DeveloperError(message()),
+            IapErrorCode::InvalidConfig => Error::InvalidConfig(message()),
+            IapErrorCode::InternalError => Error::InternalError(message()),
+        })
+    }
+
+    #[test]
+    fn every_code_maps_back_from_a_payload() {
+        for &code in IapErrorCode::ALL {
+            let payload = IAPError {
+                code: code.to_string(),
+                message: "message".into(),
+                details: None,
+                native_code: None,
+            };
+            let error = Error::from(payload);
+
+            let expected = match code {
+                IapErrorCode::PluginInvoke | IapErrorCode::Unknown => IapErrorCode::InternalError,
+                code => code,
+            };
+            assert_eq!(error.code(), expected, "{code}");
+            assert_eq!(IAPError::from(&error).code, expected.as_str(), "{code}");
+            assert_eq!(error.native_code(), None, "{code}");
+        }
+    }
+
+    #[test]
+    fn errors_round_trip_through_payloads() {
+        for &code in IapErrorCode::ALL {
+            let Some(error) = sample(code) else {
+                continue;
+            };
+            for error in [error, sample(code).unwrap().with_native_code(-3)] {
+                let payload = IAPError::from(&error);
+                assert_eq!(payload.code, code.as_str());
+
+                // Through JSON, as payloads cross the native bridges.
+                let json = serde_json::to_value(&error).unwrap();
+                let back = Error::from(serde_json::from_value::<IAPError>(json).unwrap());
+
+                assert_eq!(back.code(), code, "{code}");
+                assert_eq!(back.native_code(), error.native_code(), "{code}");
+                let back_payload = IAPError::from(&back);
+                assert_eq!(back_payload.details, payload.details, "{code}");
+                assert_eq!(back.is_retryable(), error.is_retryable(), "{code}");
+            }
+        }
     }
 }
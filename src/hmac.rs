@@ -0,0 +1,20 @@
+use sha2::{Digest, Sha256};
+
+/// HMAC-SHA256 of the concatenated `parts`.
+pub(crate) fn hmac(key: &[u8; 32], parts: &[&[u8]]) -> [u8; 32] {
+    // Keys shorter than the 64 bytes block are zero padded.
+    let pad = |byte: u8| {
+        let mut pad = [byte; 64];
+        pad.iter_mut().zip(key).for_each(|(pad, key)| *pad ^= key);
+        pad
+    };
+    let mut inner = Sha256::new().chain_update(pad(0x36));
+    for part in parts {
+        inner.update(part);
+    }
+    Sha256::new()
+        .chain_update(pad(0x5C))
+        .chain_update(inner.finalize())
+        .finalize()
+        .into()
+}
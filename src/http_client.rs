@@ -0,0 +1,117 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use tauri::http::{header, Request, Response};
+
+use crate::{Error, Result};
+
+/// Future returned by [`HttpClient::send`].
+pub type HttpFuture<'a> =
+    Pin<Box<dyn Future<Output = std::io::Result<Response<Vec<u8>>>> + Send + 'a>>;
+
+/// Sends the HTTPS requests of the store server API helpers, so the app
+/// picks the HTTP client and TLS stack, and tests can answer without a
+/// network.
+pub trait HttpClient: Send + Sync {
+    /// Sends `request`, failing only when no response was received.
+    fn send(&self, request: Request<Vec<u8>>) -> HttpFuture<'_>;
+}
+
+/// Sends `request`, mapping a missing response to `Error::NetworkError`.
+pub(crate) async fn send(
+    client: &dyn HttpClient,
+    request: Request<Vec<u8>>,
+) -> Result<Response<Vec<u8>>> {
+    let uri = request.uri().clone();
+    client
+        .send(request)
+        .await
+        .map_err(|e| Error::NetworkError(format!("Request to {uri} failed: {e}")))
+}
+
+/// Deserializes a JSON response body.
+pub(crate) fn parse<T: DeserializeOwned>(response: &Response<Vec<u8>>) -> Result<T> {
+    serde_json::from_slice(response.body())
+        .map_err(|e| Error::InternalError(format!("Unexpected store API response: {e}")))
+}
+
+/// The delay of a `Retry-After` header given in seconds. HTTP dates aren't
+/// supported and are ignored.
+pub(crate) fn retry_after(response: &Response<Vec<u8>>) -> Option<Duration> {
+    response
+        .headers()
+        .get(header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Percent-encodes a URL path segment.
+pub(crate) fn encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// An [`HttpClient`] answering with queued responses, for the tests of the
+/// store server API helpers.
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use tauri::http::{Request, Response, StatusCode};
+
+    use super::{HttpClient, HttpFuture};
+
+    /// Answers requests with queued responses, keeping the requests.
+    #[derive(Default)]
+    pub(crate) struct MockClient {
+        pub(crate) responses: Mutex<VecDeque<std::io::Result<Response<Vec<u8>>>>>,
+        pub(crate) requests: Mutex<Vec<Request<Vec<u8>>>>,
+    }
+
+    impl MockClient {
+        pub(crate) fn answering(status: StatusCode, body: &str) -> Self {
+            let client = Self::default();
+            client.respond(status, &[], body);
+            client
+        }
+
+        pub(crate) fn respond(&self, status: StatusCode, headers: &[(&str, &str)], body: &str) {
+            let mut response = Response::builder().status(status);
+            for (name, value) in headers {
+                response = response.header(*name, *value);
+            }
+            self.responses
+                .lock()
+                .unwrap()
+                .push_back(Ok(response.body(body.as_bytes().to_vec()).unwrap()));
+        }
+
+        pub(crate) fn request(&self) -> Request<Vec<u8>> {
+            let mut requests = self.requests.lock().unwrap();
+            assert_eq!(requests.len(), 1);
+            requests.pop().unwrap()
+        }
+    }
+
+    impl HttpClient for MockClient {
+        fn send(&self, request: Request<Vec<u8>>) -> HttpFuture<'_> {
+            self.requests.lock().unwrap().push(request);
+            let response = self.responses.lock().unwrap().pop_front();
+            Box::pin(async move { response.expect("unexpected request") })
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::models::IAPError;
+use crate::{Error, Result};
+
+/// Coalesces concurrent identical store calls: the first caller for a key
+/// makes the call and the others wait for its result. A failure is only
+/// shared with the callers that were already waiting, while a success is
+/// also returned to the callers within the cooldown after it.
+pub(crate) struct InFlight<K, T> {
+    calls: Mutex<HashMap<K, Arc<Call<T>>>>,
+    cooldown: Duration,
+    /// Last success per key and when it finished, kept for the cooldown.
+    recent: Mutex<HashMap<K, (Instant, T)>>,
+}
+
+struct Call<T> {
+    result: Mutex<Option<Result<T>>>,
+    done: Condvar,
+}
+
+impl<K, T> Default for InFlight<K, T> {
+    fn default() -> Self {
+        Self::with_cooldown(Duration::ZERO)
+    }
+}
+
+impl<K, T> InFlight<K, T> {
+    pub(crate) fn with_cooldown(cooldown: Duration) -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+            cooldown,
+            recent: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, T: Clone> InFlight<K, T> {
+    /// Runs `call`, or waits for the in-flight call with the same key, or
+    /// returns the result of the one that succeeded within the cooldown.
+    pub(crate) fn run(&self, key: K, call: impl FnOnce() -> Result<T>) -> Result<T> {
+        let (pending, leader) = {
+            let mut calls = lock(&self.calls);
+            // Checked with the calls locked: a leader records its success
+            // before leaving them, so a caller either waits for it or finds
+            // its success, and never calls the store again.
+            if let Some(value) = self.recent_success(&key) {
+                return Ok(value);
+            }
+            match calls.get(&key) {
+                Some(pending) => (pending.clone(), false),
+                None => {
+                    let pending = Arc::new(Call {
+                        result: Mutex::new(None),
+                        done: Condvar::new(),
+                    });
+                    calls.insert(key.clone(), pending.clone());
+                    (pending, true)
+                }
+            }
+        };
+        if !leader {
+            let mut result = lock(&pending.result);
+            loop {
+                if let Some(result) = result.as_ref() {
+                    return share(result);
+                }
+                result = pending
+                    .done
+                    .wait(result)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+        }
+
+        // Removes the entry and wakes the waiters even if `call` panics.
+        let finish = Finish {
+            in_flight: self,
+            key,
+            pending: &pending,
+        };
+        let result = call();
+        if let (Ok(value), false) = (&result, self.cooldown.is_zero()) {
+            lock(&self.recent).insert(finish.key.clone(), (Instant::now(), value.clone()));
+        }
+        *lock(&pending.result) = Some(share(&result));
+        drop(finish);
+        result
+    }
+
+    /// The success of the call with `key` that finished within the cooldown.
+    fn recent_success(&self, key: &K) -> Option<T> {
+        if self.cooldown.is_zero() {
+            return None;
+        }
+        let mut recent = lock(&self.recent);
+        recent.retain(|_, (finished, _)| finished.elapsed() < self.cooldown);
+        recent.get(key).map(|(_, value)| value.clone())
+    }
+}
+
+struct Finish<'a, K: Eq + Hash, T> {
+    in_flight: &'a InFlight<K, T>,
+    key: K,
+    pending: &'a Call<T>,
+}
+
+impl<K: Eq + Hash, T> Drop for Finish<'_, K, T> {
+    fn drop(&mut self) {
+        lock(&self.in_flight.calls).remove(&self.key);
+        let mut result = lock(&self.pending.result);
+        if result.is_none() {
+            *result = Some(Err(Error::InternalError(
+                "The coalesced store call panicked".into(),
+            )));
+        }
+        self.pending.done.notify_all();
+    }
+}
+
+/// Copies a result for a waiting caller. `Error` isn't `Clone`, its payload
+/// form keeps the code, details and native code.
+fn share<T: Clone>(result: &Result<T>) -> Result<T> {
+    match result {
+        Ok(value) => Ok(value.clone()),
+        Err(error) => Err(Error::from(IAPError::from(error))),
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+    use std::thread;
+
+    use super::*;
+
+    /// Runs `call` for `callers` threads at once, returning their results.
+    fn race<T: Clone + Send + 'static>(
+        in_flight: &Arc<InFlight<&'static str, T>>,
+        callers: usize,
+        call: impl Fn() -> Result<T> + Send + Sync + 'static,
+    ) -> Vec<Result<T>> {
+        let barrier = Arc::new(Barrier::new(callers));
+        let call = Arc::new(call);
+        let threads: Vec<_> = (0..callers)
+            .map(|_| {
+                let (in_flight, barrier, call) = (in_flight.clone(), barrier.clone(), call.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    in_flight.run("key", || call())
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn coalesces_concurrent_calls() {
+        let in_flight = Arc::new(InFlight::default());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+
+        let results = race(&in_flight, 8, move || {
+            counted.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(50));
+            Ok(7)
+        });
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(results.iter().all(|result| matches!(result, Ok(7))));
+        // Without a cooldown, the next call goes to the store again.
+        assert_eq!(in_flight.run("key", || Ok(8)).unwrap(), 8);
+    }
+
+    #[test]
+    fn shares_failures_with_the_waiting_callers_only() {
+        let in_flight = Arc::new(InFlight::with_cooldown(Duration::from_secs(60)));
+
+        let results = race(&in_flight, 4, || {
+            thread::sleep(Duration::from_millis(50));
+            Err::<u32, _>(Error::NetworkError("offline".into()))
+        });
+
+        for result in results {
+            assert_eq!(
+                result.unwrap_err().code(),
+                crate::IapErrorCode::NetworkError
+            );
+        }
+        // Failures aren't kept for the cooldown.
+        assert_eq!(in_flight.run("key", || Ok(1)).unwrap(), 1);
+    }
+
+    #[test]
+    fn returns_recent_successes_within_the_cooldown() {
+        let in_flight = InFlight::with_cooldown(Duration::from_secs(60));
+
+        assert_eq!(in_flight.run("key", || Ok(1)).unwrap(), 1);
+        assert_eq!(in_flight.run("key", || Ok(2)).unwrap(), 1);
+        assert_eq!(in_flight.run("other", || Ok(3)).unwrap(), 3);
+    }
+
+    #[test]
+    fn returns_a_success_recorded_while_waiting_to_call() {
+        let in_flight = Arc::new(InFlight::with_cooldown(Duration::from_secs(60)));
+        // A caller finding no recent success waits for the calls while the
+        // leader records its success and leaves.
+        let calls = lock(&in_flight.calls);
+        let caller = {
+            let in_flight = in_flight.clone();
+            thread::spawn(move || in_flight.run("key", || Ok(2)))
+        };
+        thread::sleep(Duration::from_millis(50));
+        lock(&in_flight.recent).insert("key", (Instant::now(), 1));
+        drop(calls);
+
+        assert_eq!(caller.join().unwrap().unwrap(), 1);
+    }
+
+    #[test]
+    fn wakes_the_waiters_when_the_call_panics() {
+        let in_flight = Arc::new(InFlight::<&str, u32>::default());
+        let leader = {
+            let in_flight = in_flight.clone();
+            thread::spawn(move || {
+                in_flight.run("key", || {
+                    thread::sleep(Duration::from_millis(100));
+                    panic!("store call failed")
+                })
+            })
+        };
+        thread::sleep(Duration::from_millis(20));
+
+        let error = in_flight.run("key", || Ok(1)).unwrap_err();
+        assert_eq!(error.code(), crate::IapErrorCode::InternalError);
+        assert!(leader.join().is_err());
+        assert_eq!(in_flight.run("key", || Ok(2)).unwrap(), 2);
+    }
+}
@@ -0,0 +1,290 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tracing::{debug, field, info, info_span, trace, warn, Span};
+
+use crate::models::PurchaseDetails;
+use crate::{Error, Result};
+
+/// Characters of a purchase token kept in TRACE events, unless changed with
+/// [`Builder::trace_token_chars`](crate::Builder::trace_token_chars).
+pub(crate) const DEFAULT_TOKEN_CHARS: usize = 8;
+
+/// Set once at setup, as the event sinks and native callbacks that trace
+/// tokens have no access to the builder.
+static TOKEN_CHARS: AtomicUsize = AtomicUsize::new(DEFAULT_TOKEN_CHARS);
+
+pub(crate) fn set_token_chars(chars: Option<usize>) {
+    TOKEN_CHARS.store(chars.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+/// `token` cut to the configured length, for TRACE events only.
+fn token(token: &str) -> String {
+    let chars = TOKEN_CHARS.load(Ordering::Relaxed);
+    match token.char_indices().nth(chars) {
+        Some((end, _)) => format!("{}…", &token[..end]),
+        None => token.to_owned(),
+    }
+}
+
+/// Span covering a command invoked from the webview.
+pub(crate) fn command(command: &str) -> Span {
+    info_span!(
+        "iap.command",
+        command,
+        duration_ms = field::Empty,
+        error = field::Empty
+    )
+}
+
+pub(crate) fn command_finished<T>(span: &Span, elapsed: Duration, result: &Result<T>) {
+    span.record("duration_ms", elapsed.as_millis() as u64);
+    match result {
+        Ok(_) => debug!(parent: span, "command succeeded"),
+        Err(error) => {
+            span.record("error", error.code().as_str());
+            warn!(parent: span, code = error.code().as_str(), "command failed: {error}");
+        }
+    }
+}
+
+pub(crate) fn products_queried(product_ids: &[String]) {
+    info!(product_ids = ?product_ids, "querying products");
+}
+
+pub(crate) fn purchase_started(product_id: &str) {
+    info!(product_id, "starting purchase");
+}
+
+pub(crate) fn purchase_completing(purchase: &PurchaseDetails) {
+    info!(product_id = purchase.product_id, "completing purchase");
+    trace!(
+        product_id = purchase.product_id,
+        token = token(&purchase.verification_data.server_verification_data),
+        "completing purchase"
+    );
+}
+
+/// Purchases reported by the store, before they are emitted.
+pub(crate) fn purchases_reported(purchases: &[PurchaseDetails]) {
+    for purchase in purchases {
+        debug!(
+            product_id = purchase.product_id,
+            status = ?purchase.status,
+            verified = purchase.verified,
+            "purchase reported"
+        );
+        trace!(
+            product_id = purchase.product_id,
+            purchase_id = purchase.purchase_id.as_deref().map(token),
+            token = token(&purchase.verification_data.server_verification_data),
+            "purchase reported"
+        );
+    }
+}
+
+pub(crate) fn connection<T>(result: &Result<T>) {
+    match result {
+        Ok(_) => info!(state = "connected", "store connection"),
+        Err(error) => warn!(
+            state = "failed",
+            code = error.code().as_str(),
+            "store connection failed: {error}"
+        ),
+    }
+}
+
+pub(crate) fn retrying(attempt: u32, delay: Duration, error: &Error) {
+    debug!(
+        attempt,
+        delay_ms = delay.as_millis() as u64,
+        code = error.code().as_str(),
+        "retrying"
+    );
+}
+
+/// Error returned by the native store, with its own response code.
+#[cfg(any(mobile, test))]
+pub(crate) fn native_error(error: &crate::models::IAPError) {
+    debug!(
+        code = error.code,
+        native_code = error.native_code,
+        "native call rejected: {}",
+        error.message
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Level, Metadata, Subscriber};
+
+    use super::*;
+    use crate::models::{PurchaseStatus, PurchaseVerificationData};
+
+    type Fields = BTreeMap<String, String>;
+
+    struct Recorder<'a>(&'a mut Fields);
+
+    impl Visit for Recorder<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().into(), value.into());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name().into(), format!("{value:?}"));
+        }
+    }
+
+    #[derive(Default)]
+    struct Trace {
+        /// Span names and fields, the span `Id` being the index plus one.
+        spans: Vec<(&'static str, Fields)>,
+        events: Vec<(Level, Option<u64>, Fields)>,
+    }
+
+    /// Keeps every span and event, with their fields as recorded.
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Trace>>);
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = Fields::new();
+            span.record(&mut Recorder(&mut fields));
+            let mut trace = self.0.lock().unwrap();
+            trace.spans.push((span.metadata().name(), fields));
+            Id::from_u64(trace.spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut trace = self.0.lock().unwrap();
+            let fields = &mut trace.spans[span.into_u64() as usize - 1].1;
+            values.record(&mut Recorder(fields));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields::new();
+            event.record(&mut Recorder(&mut fields));
+            let parent = event.parent().map(Id::into_u64);
+            let level = *event.metadata().level();
+            self.0.lock().unwrap().events.push((level, parent, fields));
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    /// Runs `f` with a [`Capture`] as the default subscriber, returning what
+    /// it saw.
+    fn capture(f: impl FnOnce()) -> Trace {
+        let capture = Capture::default();
+        tracing::subscriber::with_default(capture.clone(), f);
+        let trace = std::mem::take(&mut *capture.0.lock().unwrap());
+        trace
+    }
+
+    fn purchase(token: &str) -> PurchaseDetails {
+        PurchaseDetails {
+            purchase_id: Some("GPA.1234-5678-9012-34567".into()),
+            product_id: "premium".into(),
+            verification_data: PurchaseVerificationData {
+                local_verification_data: token.into(),
+                server_verification_data: token.into(),
+                source: "google_play".into(),
+                signature: None,
+            },
+            transaction_date: None,
+            transaction_date_ms: None,
+            expiration_date: None,
+            subscription_state: None,
+            grace_period_expiration_date: None,
+            status: PurchaseStatus::Purchased,
+            error: None,
+            pending_complete_purchase: true,
+            verified: false,
+        }
+    }
+
+    #[test]
+    fn commands_are_spans_recording_their_outcome() {
+        let trace = capture(|| {
+            let span = command("buy_non_consumable");
+            command_finished(
+                &span,
+                Duration::from_millis(42),
+                &Err::<(), _>(Error::UserCancelled),
+            );
+            let span = command("is_available");
+            command_finished(&span, Duration::from_millis(3), &Ok(true));
+        });
+
+        let (name, fields) = &trace.spans[0];
+        assert_eq!(*name, "iap.command");
+        assert_eq!(fields["command"], "buy_non_consumable");
+        assert_eq!(fields["duration_ms"], "42");
+        assert_eq!(fields["error"], "userCancelled");
+        assert!(!trace.spans[1].1.contains_key("error"));
+
+        let (level, parent, fields) = &trace.events[0];
+        assert_eq!(*level, Level::WARN);
+        assert_eq!(*parent, Some(1));
+        assert_eq!(fields["code"], "userCancelled");
+        assert_eq!(trace.events[1].0, Level::DEBUG);
+        assert_eq!(trace.events[1].1, Some(2));
+    }
+
+    #[test]
+    fn tokens_only_appear_truncated_at_trace_level() {
+        let token = "opaque-purchase-token";
+        let trace = capture(|| {
+            purchase_completing(&purchase(token));
+            purchases_reported(&[purchase(token)]);
+            set_token_chars(None);
+            purchase_completing(&purchase(token));
+            set_token_chars(Some(DEFAULT_TOKEN_CHARS));
+        });
+
+        for (level, _, fields) in &trace.events {
+            assert_eq!(fields["product_id"], "premium");
+            let leaked = fields.values().any(|value| value.contains(token));
+            match level {
+                &Level::TRACE => assert!(fields.contains_key("token")),
+                _ => assert!(!fields.contains_key("token") && !leaked, "{fields:?}"),
+            }
+        }
+        let traced: Vec<_> = trace
+            .events
+            .iter()
+            .filter(|(level, ..)| *level == Level::TRACE)
+            .map(|(_, _, fields)| fields["token"].as_str())
+            .collect();
+        assert_eq!(traced, ["opaque-p…", "opaque-p…", token]);
+    }
+
+    #[test]
+    fn native_errors_carry_the_store_response_code() {
+        let error = crate::models::IAPError {
+            code: "networkError".into(),
+            message: "Service unavailable.".into(),
+            details: None,
+            native_code: Some(2),
+        };
+        let trace = capture(|| native_error(&error));
+
+        let (level, _, fields) = &trace.events[0];
+        assert_eq!(*level, Level::DEBUG);
+        assert_eq!(fields["code"], "networkError");
+        assert_eq!(fields["native_code"], "2");
+    }
+}
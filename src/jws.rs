@@ -0,0 +1,684 @@
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use p256::ecdsa::signature::{hazmat::PrehashVerifier, Verifier};
+use serde::{de::DeserializeOwned, Deserialize};
+use sha2::{Digest, Sha256, Sha384};
+
+use crate::time::{iso8601, unix_time};
+use crate::{Error, Result};
+
+/// SHA-256 fingerprint of the Apple Root CA - G3 certificate, which the
+/// certificate chain of StoreKit 2 signed data ends with.
+pub(crate) const APPLE_ROOT_CA_G3_SHA256: [u8; 32] = [
+    0x63, 0x34, 0x3A, 0xBF, 0xB8, 0x9A, 0x6A, 0x03, 0xEB, 0xB5, 0x7E, 0x9B, 0x3F, 0x5F, 0xA7, 0xBE,
+    0x7C, 0x4F, 0x5C, 0x75, 0x6F, 0x30, 0x17, 0xB3, 0xA8, 0xC4, 0x88, 0xC3, 0x65, 0x3E, 0x91, 0x79,
+];
+
+/// OID 1.2.840.113635.100.6.11.1, marking the certificates Apple signs
+/// StoreKit data with.
+const STOREKIT_SIGNING_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x63, 0x64, 0x06, 0x0B, 0x01];
+/// OID 1.2.840.113635.100.6.2.1, marking the Apple Worldwide Developer
+/// Relations intermediate certificates.
+const WWDR_INTERMEDIATE_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x63, 0x64, 0x06, 0x02, 0x01];
+
+// OIDs of the elliptic curve algorithms Apple signs with.
+const EC_PUBLIC_KEY_OID: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+const P256_OID: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+const P384_OID: &[u8] = &[0x2B, 0x81, 0x04, 0x00, 0x22];
+const ECDSA_SHA256_OID: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x02];
+const ECDSA_SHA384_OID: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x03];
+
+// DER tags of the elements making up a certificate.
+const BOOLEAN: u8 = 0x01;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const SEQUENCE: u8 = 0x30;
+const EXPLICIT_0: u8 = 0xA0;
+const EXPLICIT_3: u8 = 0xA3;
+
+/// Environment a StoreKit 2 transaction was made in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum TransactionEnvironment {
+    /// App Store purchases
+    Production,
+    /// Sandbox Apple Accounts, TestFlight and App Review
+    Sandbox,
+    /// StoreKit testing in Xcode, signed by a local certificate
+    Xcode,
+    /// Local StoreKit testing, signed by a local certificate
+    LocalTesting,
+}
+
+/// Claims of a StoreKit 2 transaction whose signature was verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedTransaction {
+    /// Unique identifier of the transaction
+    pub transaction_id: String,
+    /// Identifier of the first transaction of the product, e.g. the first
+    /// period of a subscription
+    pub original_transaction_id: String,
+    /// Product identifier of the purchase
+    pub product_id: String,
+    /// Bundle identifier of the app the transaction belongs to
+    pub bundle_id: String,
+    /// ISO datetime string of the purchase
+    pub purchase_date: String,
+    /// ISO datetime string of the original purchase (optional)
+    pub original_purchase_date: Option<String>,
+    /// ISO datetime string when the subscription expires (optional)
+    pub expiration_date: Option<String>,
+    /// ISO datetime string when the App Store refunded or revoked the
+    /// transaction (optional)
+    pub revocation_date: Option<String>,
+    /// UUID the app attached to the purchase (optional)
+    pub app_account_token: Option<String>,
+    /// Environment the transaction was made in
+    pub environment: TransactionEnvironment,
+    /// ISO datetime string when the App Store signed the transaction
+    pub signed_date: String,
+}
+
+/// Verifies a StoreKit 2 signed transaction (`jwsRepresentation`) and
+/// returns its claims.
+///
+/// The `x5c` certificate chain must end with the Apple Root CA - G3 and be
+/// valid when the transaction was signed, and the leaf certificate must sign
+/// the JWS with ES256. Transactions made with StoreKit testing in Xcode are
+/// signed by a local certificate, so they're rejected.
+///
+/// # Errors
+///
+/// Returns `Error::InvalidPurchaseToken` if the JWS is malformed, its
+/// certificate chain isn't Apple's or its signature doesn't match.
+///
+/// # Example
+///
+/// ```
+/// use tauri_plugin_iap::verify_jws_transaction;
+///
+/// assert!(verify_jws_transaction("not.a.jws").is_err());
+/// ```
+pub fn verify_jws_transaction(jws: &str) -> Result<VerifiedTransaction> {
+    verify(jws, &APPLE_ROOT_CA_G3_SHA256).map_err(invalid)
+}
+
+/// Like [`verify_jws_transaction`], also rejecting transactions made in
+/// another environment, e.g. sandbox transactions in production builds.
+///
+/// # Errors
+///
+/// Returns `Error::InvalidPurchaseToken` if the transaction fails
+/// verification or was made in another environment.
+pub fn verify_jws_transaction_in(
+    jws: &str,
+    environment: TransactionEnvironment,
+) -> Result<VerifiedTransaction> {
+    in_environment(verify_jws_transaction(jws)?, environment)
+}
+
+/// The error of a JWS failing verification for `reason`.
+pub(crate) fn invalid(reason: String) -> Error {
+    Error::InvalidPurchaseToken(format!("Invalid JWS: {reason}"))
+}
+
+/// Rejects `transaction` unless it was made in `environment`.
+pub(crate) fn in_environment(
+    transaction: VerifiedTransaction,
+    environment: TransactionEnvironment,
+) -> Result<VerifiedTransaction> {
+    if transaction.environment != environment {
+        return Err(Error::InvalidPurchaseToken(format!(
+            "The transaction was made in the {:?} environment, not {:?}",
+            transaction.environment, environment
+        )));
+    }
+    Ok(transaction)
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    x5c: Vec<String>,
+}
+
+/// The JWS payload of a transaction, with dates in milliseconds since the
+/// Unix epoch.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Claims {
+    transaction_id: String,
+    original_transaction_id: String,
+    product_id: String,
+    bundle_id: String,
+    purchase_date: i64,
+    original_purchase_date: Option<i64>,
+    expires_date: Option<i64>,
+    revocation_date: Option<i64>,
+    app_account_token: Option<String>,
+    environment: TransactionEnvironment,
+    signed_date: i64,
+}
+
+pub(crate) fn verify(
+    jws: &str,
+    root_sha256: &[u8; 32],
+) -> std::result::Result<VerifiedTransaction, String> {
+    let claims: Claims = verified_payload(jws, root_sha256)?;
+    Ok(VerifiedTransaction {
+        transaction_id: claims.transaction_id,
+        original_transaction_id: claims.original_transaction_id,
+        product_id: claims.product_id,
+        bundle_id: claims.bundle_id,
+        purchase_date: date(claims.purchase_date),
+        original_purchase_date: claims.original_purchase_date.map(date),
+        expiration_date: claims.expires_date.map(date),
+        revocation_date: claims.revocation_date.map(date),
+        app_account_token: claims.app_account_token,
+        environment: claims.environment,
+        signed_date: date(claims.signed_date),
+    })
+}
+
+/// Checks the signature and certificate chain of data signed by the App
+/// Store, and deserializes its payload, which must have a `signedDate`. The
+/// chain must end with the root certificate whose SHA-256 fingerprint is
+/// `root_sha256`.
+pub(crate) fn verified_payload<T: DeserializeOwned>(
+    jws: &str,
+    root_sha256: &[u8; 32],
+) -> std::result::Result<T, String> {
+    let jws = jws.trim();
+    let mut segments = jws.split('.');
+    let (Some(header), Some(payload), Some(signature), None) = (
+        segments.next(),
+        segments.next(),
+        segments.next(),
+        segments.next(),
+    ) else {
+        return Err("expected three dot separated segments".into());
+    };
+    let signing_input = &jws[..header.len() + 1 + payload.len()];
+
+    let header: Header = serde_json::from_slice(&decode_segment(header)?)
+        .map_err(|e| format!("malformed header: {e}"))?;
+    if header.alg != "ES256" {
+        return Err(format!("unsupported algorithm {}", header.alg));
+    }
+    let chain = header
+        .x5c
+        .iter()
+        .map(|cert| STANDARD.decode(cert))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| format!("malformed x5c certificate: {e}"))?;
+    let [leaf, intermediate, root] = chain.as_slice() else {
+        return Err(format!(
+            "expected 3 x5c certificates, found {}",
+            chain.len()
+        ));
+    };
+    if Sha256::digest(root).as_slice() != root_sha256 {
+        return Err("the certificate chain doesn't end with the Apple Root CA - G3".into());
+    }
+    let leaf = Certificate::parse(leaf).map_err(|e| format!("malformed leaf certificate: {e}"))?;
+    let intermediate = Certificate::parse(intermediate)
+        .map_err(|e| format!("malformed intermediate certificate: {e}"))?;
+    let root = Certificate::parse(root).map_err(|e| format!("malformed root certificate: {e}"))?;
+
+    // ES256 signatures are the raw big endian `r` and `s`.
+    let signature = decode_segment(signature)?;
+    let PublicKey::P256(leaf_key) = &leaf.key else {
+        return Err("the JWS isn't signed with ES256".into());
+    };
+    let signature = p256::ecdsa::Signature::from_slice(&signature)
+        .map_err(|_| "the JWS isn't signed with ES256")?;
+    leaf_key
+        .verify(signing_input.as_bytes(), &signature)
+        .map_err(|_| "the JWS signature doesn't match the leaf certificate")?;
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Signed {
+        signed_date: i64,
+    }
+    let payload = decode_segment(payload)?;
+    let signed: Signed =
+        serde_json::from_slice(&payload).map_err(|e| format!("malformed payload: {e}"))?;
+
+    // Checked when signed rather than now, like Apple's libraries do, so the
+    // data stays verifiable once the leaf certificate expires.
+    let signed_at = signed.signed_date.div_euclid(1_000);
+    for (name, cert) in [
+        ("leaf", &leaf),
+        ("intermediate", &intermediate),
+        ("root", &root),
+    ] {
+        if signed_at < cert.not_before || signed_at > cert.not_after {
+            return Err(format!(
+                "the {name} certificate wasn't valid when the data was signed"
+            ));
+        }
+    }
+    if !leaf.extensions.contains(&STOREKIT_SIGNING_OID) {
+        return Err("the leaf certificate isn't an App Store signing certificate".into());
+    }
+    if !intermediate.extensions.contains(&WWDR_INTERMEDIATE_OID) {
+        return Err("the intermediate certificate isn't an Apple WWDR certificate".into());
+    }
+    if leaf.issuer != intermediate.subject || !intermediate.signed(&leaf) {
+        return Err("the leaf certificate isn't signed by the intermediate".into());
+    }
+    if intermediate.issuer != root.subject || !root.signed(&intermediate) {
+        return Err("the intermediate certificate isn't signed by the root".into());
+    }
+
+    serde_json::from_slice(&payload).map_err(|e| format!("malformed payload: {e}"))
+}
+
+fn decode_segment(segment: &str) -> std::result::Result<Vec<u8>, String> {
+    URL_SAFE_NO_PAD
+        .decode(segment)
+        .map_err(|e| format!("malformed base64url segment: {e}"))
+}
+
+/// Formats milliseconds since the Unix epoch, as App Store dates are.
+pub(crate) fn date(millis: i64) -> String {
+    iso8601(millis.div_euclid(1_000))
+}
+
+/// The parts of an X.509 certificate needed to check a chain.
+struct Certificate<'a> {
+    /// The DER encoded `tbsCertificate`, which the issuer signs
+    tbs: &'a [u8],
+    signature_algorithm: &'a [u8],
+    signature: &'a [u8],
+    issuer: &'a [u8],
+    subject: &'a [u8],
+    not_before: i64,
+    not_after: i64,
+    key: PublicKey,
+    /// OIDs of the extensions
+    extensions: Vec<&'a [u8]>,
+}
+
+impl<'a> Certificate<'a> {
+    fn parse(der: &'a [u8]) -> std::result::Result<Self, String> {
+        let mut certificate = Reader::new(Reader::new(der).expect(SEQUENCE)?);
+        let (tbs, tbs_content) = certificate.expect_raw(SEQUENCE)?;
+        let signature_algorithm =
+            Reader::new(certificate.expect(SEQUENCE)?).expect(OBJECT_IDENTIFIER)?;
+        let signature = bit_string(certificate.expect(BIT_STRING)?)?;
+
+        let mut tbs_fields = Reader::new(tbs_content);
+        if tbs_fields.peek() == Some(EXPLICIT_0) {
+            tbs_fields.expect(EXPLICIT_0)?;
+        }
+        tbs_fields.expect(INTEGER)?;
+        tbs_fields.expect(SEQUENCE)?;
+        let issuer = tbs_fields.expect(SEQUENCE)?;
+        let mut validity = Reader::new(tbs_fields.expect(SEQUENCE)?);
+        let not_before = time(validity.next()?)?;
+        let not_after = time(validity.next()?)?;
+        let subject = tbs_fields.expect(SEQUENCE)?;
+        let key = PublicKey::parse(tbs_fields.expect(SEQUENCE)?)?;
+
+        let mut extensions = Vec::new();
+        while !tbs_fields.is_empty() {
+            let (tag, content) = tbs_fields.next()?;
+            if tag != EXPLICIT_3 {
+                continue;
+            }
+            let mut list = Reader::new(Reader::new(content).expect(SEQUENCE)?);
+            while !list.is_empty() {
+                let mut extension = Reader::new(list.expect(SEQUENCE)?);
+                extensions.push(extension.expect(OBJECT_IDENTIFIER)?);
+                if extension.peek() == Some(BOOLEAN) {
+                    extension.expect(BOOLEAN)?;
+                }
+                extension.expect(OCTET_STRING)?;
+            }
+        }
+
+        Ok(Self {
+            tbs,
+            signature_algorithm,
+            signature,
+            issuer,
+            subject,
+            not_before,
+            not_after,
+            key,
+            extensions,
+        })
+    }
+
+    /// Whether this certificate's key signed `cert`.
+    fn signed(&self, cert: &Certificate<'_>) -> bool {
+        let digest = match cert.signature_algorithm {
+            ECDSA_SHA256_OID => Sha256::digest(cert.tbs).to_vec(),
+            ECDSA_SHA384_OID => Sha384::digest(cert.tbs).to_vec(),
+            _ => return false,
+        };
+        self.key.verify_der(&digest, cert.signature)
+    }
+}
+
+/// An ECDSA public key on one of the NIST curves Apple signs with.
+enum PublicKey {
+    P256(p256::ecdsa::VerifyingKey),
+    P384(p384::ecdsa::VerifyingKey),
+}
+
+impl PublicKey {
+    /// Parses a `SubjectPublicKeyInfo` holding an elliptic curve point.
+    fn parse(spki: &[u8]) -> std::result::Result<Self, String> {
+        let mut spki = Reader::new(spki);
+        let mut algorithm = Reader::new(spki.expect(SEQUENCE)?);
+        if algorithm.expect(OBJECT_IDENTIFIER)? != EC_PUBLIC_KEY_OID {
+            return Err("not an elliptic curve public key".into());
+        }
+        let curve = algorithm.expect(OBJECT_IDENTIFIER)?;
+        let point = bit_string(spki.expect(BIT_STRING)?)?;
+        let key = match curve {
+            P256_OID => p256::ecdsa::VerifyingKey::from_sec1_bytes(point).map(Self::P256),
+            P384_OID => p384::ecdsa::VerifyingKey::from_sec1_bytes(point).map(Self::P384),
+            _ => return Err("unsupported elliptic curve".into()),
+        };
+        key.map_err(|_| "the public key isn't a point of its curve".into())
+    }
+
+    /// Checks the DER encoded ECDSA signature of `digest`. Digests longer
+    /// than the curve order are truncated to its leftmost bits.
+    fn verify_der(&self, digest: &[u8], signature: &[u8]) -> bool {
+        match self {
+            Self::P256(key) => p256::ecdsa::Signature::from_der(signature)
+                .is_ok_and(|signature| key.verify_prehash(digest, &signature).is_ok()),
+            Self::P384(key) => p384::ecdsa::Signature::from_der(signature)
+                .is_ok_and(|signature| key.verify_prehash(digest, &signature).is_ok()),
+        }
+    }
+}
+
+/// The bytes of a bit string, which must have no unused bits.
+fn bit_string(content: &[u8]) -> std::result::Result<&[u8], String> {
+    match content {
+        [0, bytes @ ..] => Ok(bytes),
+        _ => Err("unsupported bit string".into()),
+    }
+}
+
+/// Seconds since the Unix epoch of a `UTCTime` or `GeneralizedTime`.
+fn time((tag, content): (u8, &[u8])) -> std::result::Result<i64, String> {
+    let digits = match (tag, content) {
+        (UTC_TIME, [digits @ .., b'Z']) if digits.len() == 12 => digits,
+        (GENERALIZED_TIME, [digits @ .., b'Z']) if digits.len() == 14 => digits,
+        _ => return Err("unsupported certificate validity time".into()),
+    };
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return Err("malformed certificate validity time".into());
+    }
+    let number = |range: std::ops::Range<usize>| {
+        digits[range]
+            .iter()
+            .fold(0, |value, digit| value * 10 + i64::from(digit - b'0'))
+    };
+    // Two digit years stand for 1950 to 2049.
+    let (year, rest) = match tag {
+        UTC_TIME => match number(0..2) {
+            year @ 0..=49 => (2000 + year, 2),
+            year => (1900 + year, 2),
+        },
+        _ => (number(0..4), 4),
+    };
+    Ok(unix_time(
+        year,
+        number(rest..rest + 2),
+        number(rest + 2..rest + 4),
+        number(rest + 4..rest + 6),
+        number(rest + 6..rest + 8),
+        number(rest + 8..rest + 10),
+    ))
+}
+
+/// Reads consecutive DER elements.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Reads the next element, returning its tag and content.
+    fn next(&mut self) -> std::result::Result<(u8, &'a [u8]), String> {
+        let (tag, _, content) = self.next_raw()?;
+        Ok((tag, content))
+    }
+
+    /// Reads the next element, failing unless it has the `expected` tag.
+    fn expect(&mut self, expected: u8) -> std::result::Result<&'a [u8], String> {
+        self.expect_raw(expected).map(|(_, content)| content)
+    }
+
+    /// Like [`expect`](Self::expect), also returning the whole encoded
+    /// element.
+    fn expect_raw(&mut self, expected: u8) -> std::result::Result<(&'a [u8], &'a [u8]), String> {
+        match self.next_raw()? {
+            (tag, raw, content) if tag == expected => Ok((raw, content)),
+            (tag, _, _) => Err(format!("expected tag {expected:#04x}, found {tag:#04x}")),
+        }
+    }
+
+    fn next_raw(&mut self) -> std::result::Result<(u8, &'a [u8], &'a [u8]), String> {
+        let [tag, first, rest @ ..] = self.data else {
+            return Err("truncated element".into());
+        };
+        let (len, header) = if first & 0x80 == 0 {
+            (usize::from(*first), 2)
+        } else {
+            let count = usize::from(first & 0x7F);
+            if count == 0 || count > 4 || rest.len() < count {
+                return Err("invalid length".into());
+            }
+            let len = rest[..count]
+                .iter()
+                .fold(0, |len, byte| len << 8 | usize::from(*byte));
+            (len, 2 + count)
+        };
+        let raw = self.data.get(..header + len).ok_or("truncated element")?;
+        self.data = &self.data[header + len..];
+        Ok((*tag, raw, &raw[header..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IapErrorCode;
+
+    /// A transaction signed by a test chain with the Apple extensions, see
+    /// `generatedWith` in the fixture.
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Vectors {
+        transaction: String,
+        /// Signed in 2026, after the leaf certificate expired
+        expired: String,
+        /// Ends with another root than the one that signed the intermediate
+        other_root: String,
+    }
+
+    fn vectors() -> Vectors {
+        serde_json::from_str(include_str!("../tests/fixtures/jws/vectors.json")).unwrap()
+    }
+
+    /// The SHA-256 fingerprint of the root certificate of `jws`.
+    fn root_of(jws: &str) -> [u8; 32] {
+        let header = decode_segment(jws.split('.').next().unwrap()).unwrap();
+        let header: Header = serde_json::from_slice(&header).unwrap();
+        Sha256::digest(STANDARD.decode(&header.x5c[2]).unwrap()).into()
+    }
+
+    /// Verifies `jws` against the test root.
+    fn verified(jws: &str) -> Result<VerifiedTransaction> {
+        verify(jws, &root_of(&vectors().transaction)).map_err(invalid)
+    }
+
+    fn reason(result: Result<VerifiedTransaction>) -> String {
+        match result {
+            Err(Error::InvalidPurchaseToken(reason)) => reason,
+            other => panic!("expected an invalid purchase token, got {other:?}"),
+        }
+    }
+
+    fn reason_of(jws: &str) -> String {
+        reason(verified(jws))
+    }
+
+    /// Replaces the segment at `index` of `jws`.
+    fn with_segment(jws: &str, index: usize, segment: &str) -> String {
+        let mut segments: Vec<&str> = jws.split('.').collect();
+        segments[index] = segment;
+        segments.join(".")
+    }
+
+    #[test]
+    fn verifies_a_signed_transaction() {
+        let transaction = verified(&vectors().transaction).unwrap();
+
+        assert_eq!(
+            transaction,
+            VerifiedTransaction {
+                transaction_id: "2000000123456789".into(),
+                original_transaction_id: "2000000123456789".into(),
+                product_id: "premium".into(),
+                bundle_id: "com.example.app".into(),
+                purchase_date: "2025-06-01T00:00:00Z".into(),
+                original_purchase_date: Some("2025-06-01T00:00:00Z".into()),
+                expiration_date: None,
+                revocation_date: None,
+                app_account_token: None,
+                environment: TransactionEnvironment::Sandbox,
+                signed_date: "2025-06-01T00:00:05Z".into(),
+            }
+        );
+        // With surrounding whitespace, e.g. read from a file.
+        verified(&format!(" {}\n", vectors().transaction)).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_tampered_payload() {
+        let jws = vectors().transaction;
+        let payload = decode_segment(jws.split('.').nth(1).unwrap()).unwrap();
+        let tampered = String::from_utf8(payload)
+            .unwrap()
+            .replace("\"premium\"", "\"premium_yearly\"");
+
+        let message = reason(verified(&with_segment(
+            &jws,
+            1,
+            &URL_SAFE_NO_PAD.encode(tampered),
+        )));
+        assert!(message.contains("signature doesn't match"), "{message}");
+    }
+
+    #[test]
+    fn rejects_a_tampered_signature() {
+        let jws = vectors().transaction;
+        let mut signature = decode_segment(jws.split('.').nth(2).unwrap()).unwrap();
+        signature[10] ^= 1;
+
+        let message = reason(verified(&with_segment(
+            &jws,
+            2,
+            &URL_SAFE_NO_PAD.encode(&signature),
+        )));
+        assert!(message.contains("signature doesn't match"), "{message}");
+
+        // Truncated, or DER encoded instead of the raw `r` and `s`.
+        for signature in [
+            &signature[..63],
+            &[0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01],
+        ] {
+            let jws = with_segment(&jws, 2, &URL_SAFE_NO_PAD.encode(signature));
+            assert!(reason_of(&jws).contains("isn't signed with ES256"));
+        }
+    }
+
+    #[test]
+    fn rejects_other_roots() {
+        let vectors = vectors();
+
+        // The chain doesn't end with the pinned root.
+        let message = reason(verify_jws_transaction(&vectors.transaction));
+        assert!(message.contains("Apple Root CA - G3"), "{message}");
+        let message = reason_of(&vectors.other_root);
+        assert!(message.contains("doesn't end with"), "{message}");
+
+        // Pinning the other root, which didn't sign the intermediate.
+        let message =
+            reason(verify(&vectors.other_root, &root_of(&vectors.other_root)).map_err(invalid));
+        assert!(message.contains("isn't signed by the root"), "{message}");
+    }
+
+    #[test]
+    fn rejects_transactions_signed_after_the_chain_expired() {
+        let message = reason_of(&vectors().expired);
+
+        assert!(
+            message.contains("leaf certificate wasn't valid when the data was signed"),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn rejects_transactions_of_another_environment() {
+        let transaction = verified(&vectors().transaction).unwrap();
+
+        in_environment(transaction.clone(), TransactionEnvironment::Sandbox).unwrap();
+        let error = in_environment(transaction, TransactionEnvironment::Production).unwrap_err();
+        assert_eq!(error.code(), IapErrorCode::InvalidPurchaseToken);
+        assert!(error
+            .to_string()
+            .contains("Sandbox environment, not Production"));
+    }
+
+    #[test]
+    fn rejects_malformed_jws() {
+        let jws = vectors().transaction;
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","x5c":[]}"#);
+
+        for (jws, expected) in [
+            ("", "three dot separated segments"),
+            ("a.b", "three dot separated segments"),
+            ("a.b.c.d", "three dot separated segments"),
+            ("!.b.c", "malformed base64url segment"),
+            (
+                &with_segment(&jws, 0, &header),
+                "unsupported algorithm HS256",
+            ),
+            (
+                &with_segment(&jws, 0, &URL_SAFE_NO_PAD.encode(r#"{"alg":"ES256"}"#)),
+                "expected 3 x5c certificates, found 0",
+            ),
+        ] {
+            let message = reason_of(jws);
+            assert!(message.contains(expected), "{jws:?}: {message}");
+        }
+    }
+}
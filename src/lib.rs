@@ -1,5 +1,7 @@
+use std::time::Duration;
+
 use tauri::{
-    plugin::{Builder, TauriPlugin},
+    plugin::{Builder as PluginBuilder, TauriPlugin},
     Manager, Runtime,
 };
 
@@ -7,19 +9,141 @@ pub use models::*;
 
 #[cfg(desktop)]
 mod desktop;
+#[cfg(desktop)]
+mod desktop_backend;
 #[cfg(mobile)]
 mod mobile;
 
+#[cfg(feature = "appstore-verify")]
+mod app_store_server;
+#[cfg(feature = "blocking")]
+mod blocking;
+mod cached;
 mod commands;
+mod config;
+mod connection;
+mod diagnostics;
+#[cfg(feature = "entitlement-cache")]
+mod entitlement_cache;
+mod entitlements;
 mod error;
+#[cfg(feature = "entitlement-cache")]
+mod hmac;
+#[cfg(any(feature = "play-verify", feature = "appstore-verify"))]
+mod http_client;
+mod in_flight;
+#[cfg(feature = "tracing")]
+mod instrument;
+#[cfg(feature = "verify-jws")]
+mod jws;
+#[cfg(all(target_os = "macos", feature = "mac-app-store"))]
+mod mac_app_store;
+mod metrics;
+#[cfg(all(windows, feature = "microsoft-store"))]
+mod microsoft_store;
+#[cfg(all(desktop, feature = "mock"))]
+mod mock;
+#[cfg(all(desktop, feature = "mock"))]
+mod mock_dialog;
 mod models;
+mod persist;
+#[cfg(any(target_os = "android", test))]
+mod play_signature;
+#[cfg(feature = "play-verify")]
+mod play_verify;
+mod product_cache;
+mod product_ids;
+// Only the mobile stores are queried in chunks, the tests run everywhere.
+#[cfg(any(mobile, test))]
+mod product_query;
+mod purchase_feed;
+mod purchase_flows;
+mod purchase_stream;
+#[cfg(any(target_os = "macos", feature = "receipt-parse"))]
+mod receipt;
+// Tested on every platform, used by the mobile backend.
+#[cfg(any(mobile, test))]
+mod registry;
+mod retry;
+mod scope;
+mod time;
+mod transaction_updates;
+mod unfinished;
+mod upgrade;
 
-pub use error::{Error, Result};
+#[cfg(feature = "appstore-verify")]
+pub use app_store_server::{
+  get_subscription_statuses, get_transaction_info, AppStoreServerCredentials, AppleRenewalInfo,
+  AppleSubscriptionState, AppleSubscriptionStatus,
+};
+#[cfg(feature = "blocking")]
+pub use blocking::BlockingIap;
+#[cfg(desktop)]
+pub use desktop_backend::{DesktopIapBackend, PurchaseEventSink};
+pub use config::{Config, PendingPurchases, Timeouts};
+pub use diagnostics::{ConfigDiagnostics, Diagnostics};
+pub use error::{Error, IapErrorCode, Result};
+pub use metrics::Metrics;
+#[cfg(any(feature = "play-verify", feature = "appstore-verify"))]
+pub use http_client::{HttpClient, HttpFuture};
+#[cfg(all(desktop, feature = "mock"))]
+pub use mock::{
+  MockCall, MockFailure, MockLatency, MockOperation, MockStore, MOCK_CATALOG_ENV, MOCK_ENV,
+  MOCK_LATENCY_ENV, MOCK_OWNED_ENV, MOCK_PURCHASES_FILE, MOCK_SOURCE,
+};
+#[cfg(target_os = "macos")]
+pub use receipt::{exit_for_receipt_refresh, validate_app_receipt, RECEIPT_REFRESH_EXIT_CODE};
+#[cfg(feature = "receipt-parse")]
+pub use receipt::parse_app_receipt;
+#[cfg(any(target_os = "macos", feature = "receipt-parse"))]
+pub use receipt::{validate_receipt_data, AppReceipt, InAppPurchaseReceipt, ReceiptValidation};
+#[cfg(feature = "verify-jws")]
+pub use jws::{
+  verify_jws_transaction, verify_jws_transaction_in, TransactionEnvironment, VerifiedTransaction,
+};
+#[cfg(feature = "play-verify")]
+pub use play_verify::{
+  verify_google_purchase, GoogleProductPurchase, GooglePurchaseState, GooglePurchaseType,
+  GoogleSubscriptionPurchase, GoogleSubscriptionState, GoogleVerification,
+};
+pub use product_cache::CachedProducts;
+pub use product_ids::ProductIdRules;
+pub use purchase_stream::{PurchaseUpdate, PURCHASE_STREAM_BUFFER};
+pub use retry::{retry, retry_connection, CancellationToken, ConnectionRetryPolicy, RetryPolicy};
 
 #[cfg(desktop)]
-use desktop::Iap;
+pub use desktop::Iap;
 #[cfg(mobile)]
-use mobile::Iap;
+pub use mobile::Iap;
+
+/// Event emitted to the webview with the purchases reported by the store.
+///
+/// Purchases settling a buy command called with a `correlationId` carry it.
+pub const PURCHASE_UPDATE_EVENT: &str = "tauri-plugin-iap://purchase-update";
+
+/// Event emitted to the webview with a [`RestoreCompleted`] payload once
+/// `restore_purchases` reported all owned purchases.
+pub const RESTORE_COMPLETED_EVENT: &str = "tauri-plugin-iap://restore-completed";
+
+/// Event emitted to the webview with the [`ProductDetailsResponse`] of the
+/// configured products once `preloadProducts` cached them.
+pub const PRODUCTS_LOADED_EVENT: &str = "tauri-plugin-iap://products-loaded";
+
+/// Event emitted to the webview with an [`IAPError`] when a background
+/// operation, e.g. `preloadProducts`, fails.
+pub const ERROR_EVENT: &str = "tauri-plugin-iap://error";
+
+/// Event emitted to the webview with a [`ConnectionStateChange`] whenever
+/// the store connection changes state.
+pub const CONNECTION_STATE_EVENT: &str = "tauri-plugin-iap://connection-state";
+
+/// Event emitted to the webview when the user's storefront or language
+/// changed, after the cached products and country code were dropped.
+pub const STOREFRONT_CHANGED_EVENT: &str = "tauri-plugin-iap://storefront-changed";
+
+/// Country code reported when the region can't be determined, the CLDR code
+/// for "Unknown Region".
+pub const UNKNOWN_COUNTRY_CODE: &str = "ZZ";
 
 /// Extensions to [`tauri::App`], [`tauri::AppHandle`] and [`tauri::Window`] to access the iap APIs.
 pub trait IapExt<R: Runtime> {
@@ -32,26 +156,336 @@ impl<R: Runtime, T: Manager<R>> crate::IapExt<R> for T {
   }
 }
 
-/// Initializes the plugin.
-pub fn init<R: Runtime>() -> TauriPlugin<R> {
-  Builder::new("iap")
-    .invoke_handler(tauri::generate_handler![
-      commands::initialize,
-      commands::is_available,
-      commands::query_product_details,
-      commands::buy_non_consumable,
-      commands::buy_consumable,
-      commands::complete_purchase,
-      commands::restore_purchases,
-      commands::country_code,
-    ])
-    .setup(|app, api| {
-      #[cfg(mobile)]
-      let iap = mobile::init(app, api)?;
+/// Callback invoked with the command name, the error and how long the command ran.
+type CommandErrorHook = Box<dyn Fn(&str, &Error, Duration) + Send + Sync>;
+
+/// Managed state holding the hook registered with [`Builder::on_command_error`].
+pub(crate) struct CommandErrorHandler(CommandErrorHook);
+
+impl CommandErrorHandler {
+  pub(crate) fn call(&self, command: &str, error: &Error, elapsed: Duration) {
+    (self.0)(command, error, elapsed)
+  }
+}
+
+/// Builder for the iap plugin, used to configure it before registering it with the app.
+pub struct Builder {
+  retry_policy: RetryPolicy,
+  reject_unknown_purchases: bool,
+  on_command_error: Option<CommandErrorHook>,
+  config: config::ConfigOverrides,
+  application_user_name: Option<String>,
+  #[cfg(feature = "tracing")]
+  trace_token_chars: Option<usize>,
+  #[cfg(feature = "entitlement-cache")]
+  entitlement_cache_secret: Option<Vec<u8>>,
+  #[cfg(desktop)]
+  desktop_backend: Option<Box<dyn DesktopIapBackend>>,
+  #[cfg(desktop)]
+  locale_provider: Option<desktop::LocaleProvider>,
+  #[cfg(all(desktop, feature = "mock"))]
+  mock: mock::MockOptions,
+}
+
+impl Default for Builder {
+  fn default() -> Self {
+    Self {
+      retry_policy: RetryPolicy::default(),
+      reject_unknown_purchases: true,
+      on_command_error: None,
+      config: Default::default(),
+      application_user_name: None,
+      #[cfg(feature = "tracing")]
+      trace_token_chars: Some(instrument::DEFAULT_TOKEN_CHARS),
+      #[cfg(feature = "entitlement-cache")]
+      entitlement_cache_secret: None,
       #[cfg(desktop)]
-      let iap = desktop::init(app, api)?;
-      app.manage(iap);
-      Ok(())
-    })
-    .build()
+      desktop_backend: None,
+      #[cfg(desktop)]
+      locale_provider: None,
+      #[cfg(all(desktop, feature = "mock"))]
+      mock: Default::default(),
+    }
+  }
+}
+
+impl Builder {
+  /// Creates a builder with the default settings.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Sets the policy used to retry transient store failures.
+  pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+    self.retry_policy = policy;
+    self
+  }
+
+  /// Whether `complete_purchase` rejects purchases the store never reported to
+  /// the plugin with [`Error::UnknownPurchase`]. Enabled by default.
+  ///
+  /// Disable this if the app builds purchase details itself, e.g. from a
+  /// server that tracks transactions.
+  pub fn reject_unknown_purchases(mut self, reject: bool) -> Self {
+    self.reject_unknown_purchases = reject;
+    self
+  }
+
+  /// Registers a hook called whenever a command invoked from the webview fails,
+  /// e.g. to forward errors to crash reporting.
+  ///
+  /// The hook receives the command name, the error and the time the command
+  /// took. It only observes the error, the command result is returned unchanged.
+  pub fn on_command_error<F>(mut self, hook: F) -> Self
+  where
+    F: Fn(&str, &Error, Duration) + Send + Sync + 'static,
+  {
+    self.on_command_error = Some(Box::new(hook));
+    self
+  }
+
+  /// Sets the product IDs queried when `query_product_details` is called
+  /// without any, overriding `products` from the configuration file.
+  pub fn products<I: IntoIterator<Item = S>, S: Into<String>>(mut self, products: I) -> Self {
+    self.config.products = Some(products.into_iter().map(Into::into).collect());
+    self
+  }
+
+  /// Completes purchases before reporting them, overriding
+  /// `autoFinishTransactions` from the configuration file.
+  pub fn auto_finish(mut self, auto_finish: bool) -> Self {
+    self.config.auto_finish_transactions = Some(auto_finish);
+    self
+  }
+
+  /// Sends the plugin events to the window or webview with `label` only,
+  /// overriding `eventTarget` from the configuration file.
+  pub fn event_target(mut self, label: impl Into<String>) -> Self {
+    self.config.event_target = Some(label.into());
+    self
+  }
+
+  /// Fails purchases that take longer than `timeout` with
+  /// [`Error::TimedOut`], overriding `timeouts.purchaseMs` from the
+  /// configuration file.
+  ///
+  /// The purchase may still complete in the store afterwards, in which case
+  /// it is reported through the purchase update event.
+  pub fn purchase_timeout(mut self, timeout: Duration) -> Self {
+    self.config.purchase_timeout = Some(timeout);
+    self
+  }
+
+  /// Sets how the store connection is retried, overriding `connectionRetry`
+  /// from the configuration file.
+  ///
+  /// Unlike [`Builder::retry_policy`], which retries individual operations,
+  /// this applies to connecting in `initialize` and reconnecting after the
+  /// connection drops.
+  pub fn connection_retry_policy(mut self, policy: ConnectionRetryPolicy) -> Self {
+    self.config.connection_retry = Some(policy);
+    self
+  }
+
+  /// Holds back the iOS price consent sheet until the app calls
+  /// `show_price_consent_if_needed`, overriding `deferPriceConsent` from the
+  /// configuration file.
+  pub fn defer_price_consent(mut self, defer: bool) -> Self {
+    self.config.defer_price_consent = Some(defer);
+    self
+  }
+
+  /// Leaves the preloaded products dropped on a storefront or language
+  /// change to the next query instead of querying them again, overriding
+  /// `skipPreloadOnStorefrontChange` from the configuration file.
+  pub fn skip_preload_on_storefront_change(mut self, skip: bool) -> Self {
+    self.config.skip_preload_on_storefront_change = Some(skip);
+    self
+  }
+
+  /// Sends product IDs to the store without checking them against its
+  /// [`ProductIdRules`], overriding `skipProductIdValidation` from the
+  /// configuration file.
+  pub fn skip_product_id_validation(mut self, skip: bool) -> Self {
+    self.config.skip_product_id_validation = Some(skip);
+    self
+  }
+
+  /// Cuts purchase tokens in TRACE events to `chars` characters, or keeps
+  /// them whole with `None`. Defaults to 8. Tokens never appear at other
+  /// levels.
+  #[cfg(feature = "tracing")]
+  pub fn trace_token_chars(mut self, chars: Option<usize>) -> Self {
+    self.trace_token_chars = chars;
+    self
+  }
+
+  /// Keeps the owned products in `iap-entitlements.json` in the app data
+  /// directory, MACed with a key derived from `secret`, so `is_owned` keeps
+  /// granting them while the store is unreachable, e.g. offline.
+  ///
+  /// Edited files are ignored, as are products past their expiration date.
+  /// The secret ships with the app, so this stops casual edits rather than a
+  /// user extracting it.
+  #[cfg(feature = "entitlement-cache")]
+  pub fn entitlement_cache_secret(mut self, secret: impl Into<Vec<u8>>) -> Self {
+    self.entitlement_cache_secret = Some(secret.into());
+    self
+  }
+
+  /// Sets the application user name used by purchases and restores that
+  /// don't pass one, e.g. the hashed account ID of a user logged in at
+  /// startup. Change or clear it later with `Iap::set_application_user_name`.
+  pub fn application_user_name(mut self, name: impl Into<String>) -> Self {
+    self.application_user_name = Some(name.into());
+    self
+  }
+
+  /// Serves the iap APIs on desktop with a custom store, e.g. a licensing
+  /// server, instead of the bundled backends.
+  #[cfg(desktop)]
+  pub fn desktop_backend<B: DesktopIapBackend>(mut self, backend: B) -> Self {
+    self.desktop_backend = Some(Box::new(backend));
+    self
+  }
+
+  /// Replaces the system locale used to derive the country code on desktop
+  /// when no store reports one, e.g. to test region specific pricing.
+  ///
+  /// The provider returns a BCP 47 (`en-US`) or POSIX (`en_US.UTF-8`) locale
+  /// tag, or `None` if it can't be determined.
+  #[cfg(desktop)]
+  pub fn locale_provider<F>(mut self, provider: F) -> Self
+  where
+    F: Fn() -> Option<String> + Send + Sync + 'static,
+  {
+    self.locale_provider = Some(std::sync::Arc::new(provider));
+    self
+  }
+
+  /// Sets the products sold by the mock store used on desktop.
+  #[cfg(all(desktop, feature = "mock"))]
+  pub fn mock_catalog(mut self, catalog: Vec<ProductDetails>) -> Self {
+    self.mock.catalog = catalog;
+    self
+  }
+
+  /// Sets the file the mock store keeps purchases in, instead of
+  /// [`MOCK_PURCHASES_FILE`] in the app data directory.
+  #[cfg(all(desktop, feature = "mock"))]
+  pub fn mock_storage_path(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+    self.mock.storage_path = Some(path.into());
+    self
+  }
+
+  /// Approves mock purchases without asking, e.g. for automated tests on CI.
+  ///
+  /// By default the mock store shows a dialog to buy or cancel each purchase.
+  #[cfg(all(desktop, feature = "mock"))]
+  pub fn mock_headless(mut self, headless: bool) -> Self {
+    self.mock.headless = headless;
+    self
+  }
+
+  /// Simulates store latency in the mock store, see [`MockLatency`].
+  #[cfg(all(desktop, feature = "mock"))]
+  pub fn mock_latency(mut self, latency: MockLatency) -> Self {
+    self.mock.latency = Some(latency);
+    self
+  }
+
+  /// Builds the plugin.
+  pub fn build<R: Runtime>(self) -> TauriPlugin<R, Option<Config>> {
+    PluginBuilder::<R, Option<Config>>::new("iap")
+      .invoke_handler(tauri::generate_handler![
+        commands::initialize,
+        commands::is_available,
+        commands::connection_state,
+        commands::end_connection,
+        commands::query_product_details,
+        commands::buy_non_consumable,
+        commands::buy_consumable,
+        commands::buy_upgrade,
+        commands::complete_purchase,
+        commands::finalize_after,
+        commands::restore_purchases,
+        commands::is_owned,
+        commands::owned_products,
+        commands::current_entitlements,
+        commands::set_application_user_name,
+        commands::country_code,
+        commands::is_feature_supported,
+        commands::install_source,
+        commands::diagnostics,
+        commands::metrics,
+        commands::reset_metrics,
+        commands::app_license,
+        commands::get_app_receipt,
+        commands::export_verification_payload,
+        commands::export_entitlements,
+        commands::import_entitlements,
+        commands::subscription_status,
+        commands::subscription_time_remaining,
+        commands::open_manage_subscriptions,
+        commands::show_price_consent_if_needed,
+        commands::is_alternative_billing_available,
+        commands::show_alternative_billing_dialog,
+        commands::create_alternative_billing_token,
+        commands::external_purchase_available,
+        commands::open_external_purchase_link,
+        commands::external_purchase_token,
+        commands::open_store_product_page,
+        #[cfg(all(desktop, feature = "mock"))]
+        commands::mock_set_failure,
+        #[cfg(all(desktop, feature = "mock"))]
+        commands::mock_set_latency,
+        #[cfg(all(desktop, feature = "mock"))]
+        commands::mock_clear_purchases,
+        #[cfg(all(desktop, feature = "mock", debug_assertions))]
+        commands::mock_emit_purchase,
+      ])
+      .setup(move |app, api| {
+        // Builder settings win over the configuration file.
+        let config = self.config.apply(api.config().clone().unwrap_or_default());
+        #[cfg(feature = "tracing")]
+        instrument::set_token_chars(self.trace_token_chars);
+        #[cfg(mobile)]
+        let iap = mobile::init(app, api, config, self.retry_policy, self.reject_unknown_purchases)?;
+        #[cfg(desktop)]
+        let iap = desktop::init(
+          app,
+          config,
+          self.retry_policy,
+          self.reject_unknown_purchases,
+          self.desktop_backend,
+          self.locale_provider,
+          #[cfg(feature = "mock")]
+          self.mock,
+        )?;
+        iap.set_application_user_name(self.application_user_name);
+        #[cfg(feature = "entitlement-cache")]
+        if let Some(secret) = &self.entitlement_cache_secret {
+          iap.set_entitlement_cache(entitlement_cache::EntitlementCache::in_app_data_dir(
+            app, secret,
+          ));
+        }
+        app.manage(iap);
+        if let Some(hook) = self.on_command_error {
+          app.manage(CommandErrorHandler(hook));
+        }
+        Ok(())
+      })
+      .on_drop(|app| {
+        if let Some(iap) = app.try_state::<Iap<R>>() {
+          iap.end_connection();
+          iap.close_purchase_streams();
+        }
+      })
+      .build()
+  }
+}
+
+/// Initializes the plugin with the default settings.
+pub fn init<R: Runtime>() -> TauriPlugin<R, Option<Config>> {
+  Builder::new().build()
 }
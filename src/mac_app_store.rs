@@ -0,0 +1,296 @@
+use serde::{de::DeserializeOwned, Deserialize};
+use swift_rs::{swift, Bool, SRString};
+
+use crate::models::*;
+use crate::{DesktopIapBackend, Diagnostics, Error, ProductIdRules, PurchaseEventSink, Result};
+
+swift!(fn iap_macos_is_available() -> Bool);
+swift!(fn iap_macos_query_products(product_ids: &SRString) -> SRString);
+swift!(fn iap_macos_purchase(product_id: &SRString, application_user_name: &SRString) -> SRString);
+swift!(fn iap_macos_finish(transaction_id: &SRString) -> SRString);
+swift!(fn iap_macos_restore() -> SRString);
+swift!(fn iap_macos_owned_purchases() -> SRString);
+swift!(fn iap_macos_country_code() -> SRString);
+swift!(fn iap_macos_app_receipt(refresh: Bool) -> SRString);
+swift!(fn iap_macos_open_store_page(app_store_id: &SRString) -> SRString);
+swift!(fn iap_macos_app_store_environment() -> SRString);
+swift!(fn iap_macos_app_license() -> SRString);
+#[cfg(feature = "receipt-verification")]
+swift!(fn iap_macos_verify_receipt_signature(path: &SRString) -> SRString);
+
+/// Result of a call into the Swift bridge in `macos/`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum Reply<T> {
+    Ok(T),
+    Error(IAPError),
+}
+
+fn parse<T: DeserializeOwned>(reply: SRString) -> Result<T> {
+    match serde_json::from_str(reply.as_str()) {
+        Ok(Reply::Ok(value)) => Ok(value),
+        Ok(Reply::Error(error)) => Err(error.into()),
+        Err(e) => Err(Error::InternalError(format!(
+            "Malformed StoreKit response: {e}"
+        ))),
+    }
+}
+
+/// Checks that the receipt at `path` is signed by a certificate chaining up to
+/// the Apple Root CA.
+#[cfg(feature = "receipt-verification")]
+pub(crate) fn verify_receipt_signature(path: &std::path::Path) -> Result<()> {
+    let path = SRString::from(path.to_string_lossy().as_ref());
+    parse::<bool>(unsafe { iap_macos_verify_receipt_signature(&path) }).map(|_| ())
+}
+
+/// Mac App Store purchases through StoreKit, used on macOS when the
+/// `mac-app-store` feature is enabled.
+///
+/// Payloads use the same models and error codes as the iOS plugin.
+pub(crate) struct MacAppStore {
+    /// The `app_store_id` configuration, the page opened for this app.
+    app_store_id: Option<String>,
+}
+
+impl MacAppStore {
+    /// Returns `None` unless the app is a sandboxed Mac App Store build with a
+    /// receipt, as StoreKit can't be used by other builds.
+    pub(crate) fn new(app_store_id: Option<String>) -> Option<Self> {
+        if unsafe { iap_macos_is_available() } {
+            Some(Self { app_store_id })
+        } else {
+            log::debug!("Not a Mac App Store build, StoreKit purchases are unavailable");
+            None
+        }
+    }
+
+    /// Shows the purchase sheet and returns the purchase, or `None` while it
+    /// is pending approval.
+    fn buy(&self, purchase_param: &PurchaseParam) -> Result<Option<PurchaseDetails>> {
+        let product_id = SRString::from(purchase_param.product_details.id.as_str());
+        let application_user_name = SRString::from(
+            purchase_param
+                .application_user_name
+                .as_deref()
+                .unwrap_or_default(),
+        );
+        parse(unsafe { iap_macos_purchase(&product_id, &application_user_name) })
+    }
+}
+
+impl DesktopIapBackend for MacAppStore {
+    fn initialize(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn is_available(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn query_product_details(&self, product_ids: &[String]) -> Result<ProductDetailsResponse> {
+        let product_ids = serde_json::to_string(product_ids)
+            .map_err(|e| Error::InternalError(e.to_string()))?;
+        parse(unsafe { iap_macos_query_products(&SRString::from(product_ids.as_str())) })
+    }
+
+    fn buy_non_consumable(
+        &self,
+        purchase_param: &PurchaseParam,
+        events: &PurchaseEventSink,
+    ) -> Result<bool> {
+        if let Some(purchase) = self.buy(purchase_param)? {
+            events.emit(&[purchase]);
+        }
+        Ok(true)
+    }
+
+    fn buy_consumable(
+        &self,
+        purchase_param: &PurchaseParam,
+        auto_consume: bool,
+        events: &PurchaseEventSink,
+    ) -> Result<bool> {
+        if let Some(mut purchase) = self.buy(purchase_param)? {
+            if auto_consume {
+                self.complete_purchase(&purchase)?;
+                purchase.pending_complete_purchase = false;
+            }
+            events.emit(&[purchase]);
+        }
+        Ok(true)
+    }
+
+    /// Buys the new subscription, which StoreKit switches to within the
+    /// subscription group. The App Store decides when the switch takes
+    /// effect, so the replacement mode is ignored.
+    fn change_subscription(
+        &self,
+        purchase_param: &PurchaseParam,
+        _old_purchase: &PurchaseDetails,
+        _replacement_mode: ReplacementMode,
+        events: &PurchaseEventSink,
+    ) -> Result<Option<PurchaseDetails>> {
+        let purchase = self.buy(purchase_param)?;
+        if let Some(purchase) = &purchase {
+            events.emit(std::slice::from_ref(purchase));
+        }
+        Ok(purchase)
+    }
+
+    /// Finishes the transaction so StoreKit stops reporting it.
+    fn complete_purchase(&self, purchase: &PurchaseDetails) -> Result<()> {
+        let transaction_id = purchase
+            .purchase_id
+            .as_deref()
+            .ok_or_else(|| Error::InvalidPurchaseToken("Missing transaction ID".into()))?;
+        parse::<bool>(unsafe { iap_macos_finish(&SRString::from(transaction_id)) }).map(|_| ())
+    }
+
+    /// Syncs with the App Store and reports the current entitlements with the
+    /// `Restored` status.
+    fn restore_purchases(
+        &self,
+        _application_user_name: Option<&str>,
+        events: &PurchaseEventSink,
+    ) -> Result<()> {
+        let restored: Vec<PurchaseDetails> = parse(unsafe { iap_macos_restore() })?;
+        events.emit(&restored);
+        Ok(())
+    }
+
+    /// Reports the current entitlements without syncing with the App Store,
+    /// which may ask the user to sign in.
+    fn owned_purchases(&self, events: &PurchaseEventSink) -> Result<()> {
+        let owned: Vec<PurchaseDetails> = parse(unsafe { iap_macos_owned_purchases() })?;
+        events.emit(&owned);
+        Ok(())
+    }
+
+    /// The App Store storefront country code.
+    fn country_code(&self) -> Result<String> {
+        parse(unsafe { iap_macos_country_code() })
+    }
+
+    fn app_receipt(&self, refresh: bool) -> Result<String> {
+        parse(unsafe { iap_macos_app_receipt(refresh) })
+    }
+
+    /// StoreKit sells subscriptions and switches between those of a group,
+    /// the other features are iOS or Google Play only.
+    fn is_feature_supported(&self, feature: StoreFeature) -> Result<bool> {
+        Ok(matches!(
+            feature,
+            StoreFeature::Subscriptions | StoreFeature::SubscriptionsUpdate
+        ))
+    }
+
+    fn describe(&self, diagnostics: &mut Diagnostics) {
+        diagnostics.store = Some("Mac App Store".into());
+        diagnostics.store_kit_2 = Some(true);
+    }
+
+    fn product_id_rules(&self) -> Option<ProductIdRules> {
+        Some(ProductIdRules::AppStore)
+    }
+
+    /// The license from the app transaction, on macOS 13+.
+    fn app_license(&self) -> Result<AppLicense> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct AppTransaction {
+            verified: bool,
+            original_app_version: String,
+            original_purchase_date: String,
+        }
+
+        let transaction: AppTransaction = parse(unsafe { iap_macos_app_license() })?;
+        Ok(AppLicense::from_app_transaction(
+            transaction.verified,
+            transaction.original_app_version,
+            transaction.original_purchase_date,
+        ))
+    }
+
+    /// Tells App Store and TestFlight installs apart from the app
+    /// transaction.
+    fn install_source(&self) -> Result<InstallSource> {
+        let environment: String = parse(unsafe { iap_macos_app_store_environment() })?;
+        Ok(InstallSource::from_app_store_environment(
+            Some(&environment),
+            tauri::is_dev(),
+            true,
+        ))
+    }
+
+    /// Opens the page in the App Store app, or in the browser if it can't.
+    fn open_store_product_page(&self, product_id: Option<&str>) -> Result<()> {
+        let app_store_id = product_id
+            .or(self.app_store_id.as_deref())
+            .ok_or_else(|| {
+                Error::InvalidConfig(
+                    "Set appStoreId to open the app's App Store page without a product ID".into(),
+                )
+            })?;
+        parse::<bool>(unsafe { iap_macos_open_store_page(&SRString::from(app_store_id)) })
+            .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::IapErrorCode;
+
+    use super::*;
+
+    const OWNED_PURCHASES: &str = include_str!("../tests/fixtures/ios/owned_purchases.json");
+
+    fn reply(json: &str) -> SRString {
+        SRString::from(json)
+    }
+
+    #[test]
+    fn reads_purchases_in_the_ios_format() {
+        let ios: serde_json::Value = serde_json::from_str(OWNED_PURCHASES).unwrap();
+        let json = serde_json::json!({ "ok": ios["purchases"] }).to_string();
+
+        let purchases: Vec<PurchaseDetails> = parse(reply(&json)).unwrap();
+
+        // The same purchases as the iOS plugin reports, once deserialized.
+        let reported: Vec<PurchaseDetails> =
+            serde_json::from_value(ios["purchases"].clone()).unwrap();
+        assert!(!purchases.is_empty());
+        assert_eq!(
+            serde_json::to_value(&purchases).unwrap(),
+            serde_json::to_value(&reported).unwrap()
+        );
+    }
+
+    #[test]
+    fn maps_errors_like_ios() {
+        let errors = [
+            ("error_user_cancelled", IapErrorCode::UserCancelled),
+            ("error_receipt_unavailable", IapErrorCode::ReceiptUnavailable),
+            ("error_developer", IapErrorCode::DeveloperError),
+        ];
+        for (fixture, code) in errors {
+            let path = format!("{}/tests/fixtures/ios/{fixture}.json", env!("CARGO_MANIFEST_DIR"));
+            let error = std::fs::read_to_string(path).unwrap();
+            let json = format!(r#"{{"error":{error}}}"#);
+
+            let error = parse::<bool>(reply(&json)).unwrap_err();
+
+            assert_eq!(error.code(), code, "{fixture}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_replies() {
+        for json in ["", "true", r#"{"ok":"yes"}"#] {
+            assert!(
+                matches!(parse::<bool>(reply(json)), Err(Error::InternalError(_))),
+                "{json}"
+            );
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, IapErrorCode, Result};
+
+/// Purchase funnel counts since the app started or the last `reset_metrics`,
+/// returned by `metrics`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metrics {
+    /// Calls to `query_product_details`, including the ones answered from
+    /// the product cache
+    pub product_queries: u64,
+    /// Purchase flows started by `buy_non_consumable`, `buy_consumable` or
+    /// `buy_upgrade`
+    pub purchases_started: u64,
+    /// Purchase flows the store accepted. Google Play accepts a purchase
+    /// once its flow launched, and reports it later.
+    pub purchased: u64,
+    /// Purchase flows the user cancelled
+    pub cancelled: u64,
+    /// Purchase flows that failed, other than cancelled ones
+    pub errored: u64,
+    /// `errored`, keyed by the codes that occurred
+    pub errors_by_code: BTreeMap<String, u64>,
+    /// Calls to `restore_purchases`
+    pub restores: u64,
+    /// Average time purchase flows took until they were accepted, cancelled
+    /// or failed, or `None` before any ended
+    pub average_flow_duration_ms: Option<u64>,
+}
+
+/// The counters behind [`Metrics`], updated without locking.
+pub(crate) struct Counters {
+    product_queries: AtomicU64,
+    purchases_started: AtomicU64,
+    purchased: AtomicU64,
+    cancelled: AtomicU64,
+    /// Indexed like `IapErrorCode::ALL`.
+    errors: [AtomicU64; IapErrorCode::ALL.len()],
+    restores: AtomicU64,
+    flows_ended: AtomicU64,
+    flow_duration_ms: AtomicU64,
+}
+
+impl Default for Counters {
+    fn default() -> Self {
+        Self {
+            product_queries: AtomicU64::new(0),
+            purchases_started: AtomicU64::new(0),
+            purchased: AtomicU64::new(0),
+            cancelled: AtomicU64::new(0),
+            errors: std::array::from_fn(|_| AtomicU64::new(0)),
+            restores: AtomicU64::new(0),
+            flows_ended: AtomicU64::new(0),
+            flow_duration_ms: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Counters {
+    pub(crate) fn product_queried(&self) {
+        self.product_queries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn restore_started(&self) {
+        self.restores.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Runs a purchase flow, counting its outcome: an error, or else
+    /// `accepted` of its result.
+    pub(crate) fn purchase_flow<T>(
+        &self,
+        accepted: impl FnOnce(&T) -> bool,
+        flow: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        self.purchases_started.fetch_add(1, Ordering::Relaxed);
+        let started = Instant::now();
+        let result = flow();
+        match &result {
+            Ok(value) => {
+                if accepted(value) {
+                    self.purchased.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            Err(Error::UserCancelled) => {
+                self.cancelled.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(error) => {
+                let code = error.code();
+                let index = IapErrorCode::ALL.iter().position(|known| *known == code);
+                if let Some(index) = index {
+                    self.errors[index].fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        self.flow_duration_ms
+            .fetch_add(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+        self.flows_ended.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    pub(crate) fn snapshot(&self) -> Metrics {
+        let errors_by_code: BTreeMap<String, u64> = IapErrorCode::ALL
+            .iter()
+            .zip(&self.errors)
+            .map(|(code, count)| (code.as_str().to_owned(), count.load(Ordering::Relaxed)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        let flows_ended = self.flows_ended.load(Ordering::Relaxed);
+        Metrics {
+            product_queries: self.product_queries.load(Ordering::Relaxed),
+            purchases_started: self.purchases_started.load(Ordering::Relaxed),
+            purchased: self.purchased.load(Ordering::Relaxed),
+            cancelled: self.cancelled.load(Ordering::Relaxed),
+            errored: errors_by_code.values().sum(),
+            errors_by_code,
+            restores: self.restores.load(Ordering::Relaxed),
+            average_flow_duration_ms: (flows_ended > 0)
+                .then(|| self.flow_duration_ms.load(Ordering::Relaxed) / flows_ended),
+        }
+    }
+
+    /// Zeroes every counter. Flows ending meanwhile may be counted in part.
+    pub(crate) fn reset(&self) {
+        let counters = [
+            &self.product_queries,
+            &self.purchases_started,
+            &self.purchased,
+            &self.cancelled,
+            &self.restores,
+            &self.flows_ended,
+            &self.flow_duration_ms,
+        ];
+        for counter in counters.into_iter().chain(&self.errors) {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,717 @@
+use std::collections::HashMap;
+use std::sync::{mpsc, Mutex, PoisonError};
+
+use tauri::{AppHandle, Manager, Runtime};
+use windows::core::{Interface, GUID, HRESULT, HSTRING};
+use windows::ApplicationModel::Package;
+use windows::Foundation::Collections::IIterable;
+use windows::Foundation::{DateTime, Uri};
+use windows::Services::Store::{
+    StoreAppLicense, StoreConsumableStatus, StoreContext, StoreDurationUnit, StoreLicense,
+    StoreProduct, StorePurchaseResult, StorePurchaseStatus, StoreSku,
+};
+use windows::System::Launcher;
+use windows::System::UserProfile::GlobalizationPreferences;
+use windows::Win32::System::Com::CoIncrementMTAUsage;
+use windows::Win32::UI::Shell::IInitializeWithWindow;
+
+use crate::models::*;
+use crate::time::iso8601;
+use crate::{DesktopIapBackend, Diagnostics, Error, PurchaseEventSink, Result};
+
+/// Verification source reported for Microsoft Store purchases.
+pub const MICROSOFT_STORE_SOURCE: &str = "microsoft";
+
+/// Add-on kinds queried from the store.
+const PRODUCT_KINDS: [&str; 3] = ["Durable", "Consumable", "UnmanagedConsumable"];
+
+/// Microsoft account page where users cancel or change their subscriptions.
+const MANAGE_SUBSCRIPTIONS_URL: &str = "https://account.microsoft.com/services";
+
+/// Microsoft Store add-ons, used on Windows when the `microsoft-store`
+/// feature is enabled and the app was installed from the Store.
+///
+/// Product IDs are the Store IDs of the add-ons. Purchases are reported with
+/// the Store ID as both the purchase ID and the token, since ownership is
+/// verified server side through the Microsoft Store collections API.
+pub(crate) struct MicrosoftStore<R: Runtime> {
+    app: AppHandle<R>,
+    /// Product kinds by Store ID, filled by queries so completing a purchase
+    /// knows whether it must report consumable fulfillment.
+    kinds: Mutex<HashMap<String, String>>,
+}
+
+impl<R: Runtime> MicrosoftStore<R> {
+    /// Returns `None` if the app isn't packaged, as the Store APIs only work for
+    /// apps installed from the Store.
+    pub(crate) fn new(app: &AppHandle<R>) -> Option<Self> {
+        if Package::Current().is_err() {
+            log::debug!("App is not packaged, Microsoft Store purchases are unavailable");
+            return None;
+        }
+        // WinRT calls are made from worker threads that never initialized COM.
+        if let Err(e) = unsafe { CoIncrementMTAUsage() } {
+            log::error!("Failed to initialize COM for the Microsoft Store: {}", e);
+            return None;
+        }
+        Some(Self {
+            app: app.clone(),
+            kinds: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Creates a store context associated with the app's window, which the
+    /// Store requires to show its dialogs in desktop apps.
+    fn context(&self) -> Result<StoreContext> {
+        let window = self
+            .app
+            .webview_windows()
+            .into_values()
+            .next()
+            .ok_or_else(|| Error::InternalError("No window to show the Store dialog on".into()))?;
+        let hwnd = window
+            .hwnd()
+            .map_err(|e| Error::InternalError(e.to_string()))?;
+
+        let context = StoreContext::GetDefault().map_err(map_error)?;
+        let init = context.cast::<IInitializeWithWindow>().map_err(map_error)?;
+        unsafe { init.Initialize(hwnd) }.map_err(map_error)?;
+        Ok(context)
+    }
+
+    /// Reads the license of the app and of the add-ons the user owns.
+    fn license(&self) -> Result<StoreAppLicense> {
+        self.context()?
+            .GetAppLicenseAsync()
+            .and_then(|operation| operation.get())
+            .map_err(map_error)
+    }
+
+    /// Shows the Store purchase dialog and returns the purchased add-on.
+    fn buy(&self, purchase_param: &PurchaseParam) -> Result<PurchaseDetails> {
+        let store_id = HSTRING::from(&purchase_param.product_details.id);
+
+        // The dialog must be requested from the UI thread, while waiting for
+        // the result has to happen off it.
+        let (tx, rx) = mpsc::channel();
+        let context = self.context()?;
+        self.app
+            .run_on_main_thread(move || {
+                let _ = tx.send(context.RequestPurchaseAsync(&store_id));
+            })
+            .map_err(|e| Error::InternalError(e.to_string()))?;
+        let result = rx
+            .recv()
+            .map_err(|e| Error::InternalError(e.to_string()))?
+            .and_then(|operation| operation.get())
+            .map_err(map_error)?;
+
+        purchase_result(&purchase_param.product_details.id, &result)
+    }
+
+    /// Reads whether the user's subscription to `product_id` renews, from the
+    /// collection data the Store keeps for it. `None` if it isn't reported.
+    fn will_auto_renew(&self, product_id: &str) -> Result<Option<bool>> {
+        let kinds: IIterable<HSTRING> = vec![HSTRING::from("Durable")].into();
+        let result = self
+            .context()?
+            .GetUserCollectionAsync(&kinds)
+            .and_then(|operation| operation.get())
+            .map_err(map_error)?;
+        check(result.ExtendedError().map_err(map_error)?)?;
+
+        let Ok(product) = result
+            .Products()
+            .and_then(|products| products.Lookup(&HSTRING::from(product_id)))
+        else {
+            return Ok(None);
+        };
+        for sku in product.Skus().and_then(|skus| skus.First()).map_err(map_error)? {
+            if !sku.IsSubscription().map_err(map_error)?
+                || !sku.IsInUserCollection().map_err(map_error)?
+            {
+                continue;
+            }
+            let json = sku
+                .CollectionData()
+                .and_then(|data| data.ExtendedJsonData())
+                .map_err(map_error)?
+                .to_string();
+            return Ok(serde_json::from_str::<serde_json::Value>(&json)
+                .ok()
+                .and_then(|data| data.get("autoRenew")?.as_bool()));
+        }
+        Ok(None)
+    }
+}
+
+impl<R: Runtime> DesktopIapBackend for MicrosoftStore<R> {
+    fn initialize(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn is_available(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn query_product_details(&self, product_ids: &[String]) -> Result<ProductDetailsResponse> {
+        let ids: IIterable<HSTRING> = product_ids
+            .iter()
+            .map(HSTRING::from)
+            .collect::<Vec<_>>()
+            .into();
+        let result = self
+            .context()?
+            .GetStoreProductsAsync(&product_kinds(), &ids)
+            .and_then(|operation| operation.get())
+            .map_err(map_error)?;
+        check(result.ExtendedError().map_err(map_error)?)?;
+
+        let mut response = ProductDetailsResponse::default();
+        let mut kinds = self.kinds.lock().unwrap_or_else(PoisonError::into_inner);
+        for pair in result.Products().and_then(|p| p.First()).map_err(map_error)? {
+            let product = pair.Value().map_err(map_error)?;
+            let details = product_details(&product)?;
+            kinds.insert(
+                details.id.clone(),
+                product.ProductKind().map_err(map_error)?.to_string(),
+            );
+            response.product_details.push(details);
+        }
+        response.not_found_ids = product_ids
+            .iter()
+            .filter(|id| !response.product_details.iter().any(|d| &d.id == *id))
+            .cloned()
+            .collect();
+        Ok(response)
+    }
+
+    fn buy_non_consumable(
+        &self,
+        purchase_param: &PurchaseParam,
+        events: &PurchaseEventSink,
+    ) -> Result<bool> {
+        events.emit(&[self.buy(purchase_param)?]);
+        Ok(true)
+    }
+
+    fn buy_consumable(
+        &self,
+        purchase_param: &PurchaseParam,
+        auto_consume: bool,
+        events: &PurchaseEventSink,
+    ) -> Result<bool> {
+        let mut purchase = self.buy(purchase_param)?;
+        if auto_consume {
+            self.complete_purchase(&purchase)?;
+            purchase.pending_complete_purchase = false;
+        }
+        events.emit(&[purchase]);
+        Ok(true)
+    }
+
+    /// Reports consumable add-ons as fulfilled so they can be bought again.
+    /// Durable add-ons need no completion.
+    fn complete_purchase(&self, purchase: &PurchaseDetails) -> Result<()> {
+        if purchase.verification_data.source != MICROSOFT_STORE_SOURCE {
+            return Err(Error::UnknownPurchase(
+                purchase
+                    .purchase_id
+                    .clone()
+                    .unwrap_or_else(|| purchase.product_id.clone()),
+            ));
+        }
+
+        let kind = self
+            .kinds
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&purchase.product_id)
+            .cloned();
+        if kind.as_deref() != Some("Consumable") {
+            return Ok(());
+        }
+
+        let tracking_id = GUID::new().map_err(map_error)?;
+        let result = self
+            .context()?
+            .ReportConsumableFulfillmentAsync(&HSTRING::from(&purchase.product_id), 1, tracking_id)
+            .and_then(|operation| operation.get())
+            .map_err(map_error)?;
+        let status = result.Status().map_err(map_error)?;
+        if status == StoreConsumableStatus::Succeeded {
+            Ok(())
+        } else if status == StoreConsumableStatus::InsufficentQuantity {
+            Err(Error::ItemNotOwned(purchase.product_id.clone()))
+        } else if status == StoreConsumableStatus::NetworkError {
+            Err(Error::NetworkError(extended_message(result.ExtendedError())))
+        } else {
+            Err(Error::ConsumptionError(extended_message(result.ExtendedError())))
+        }
+    }
+
+    /// Reports the durable and subscription add-ons the user holds an active
+    /// license for with the `Restored` status.
+    fn restore_purchases(
+        &self,
+        _application_user_name: Option<&str>,
+        events: &PurchaseEventSink,
+    ) -> Result<()> {
+        events.emit(&restored_purchases(self.app_license()?.add_on_licenses));
+        Ok(())
+    }
+
+    fn app_license(&self) -> Result<AppLicense> {
+        let license = self.license()?;
+        let is_trial = license.IsTrial().map_err(map_error)?;
+        let add_on_licenses = license
+            .AddOnLicenses()
+            .and_then(|licenses| licenses.First())
+            .map_err(map_error)?
+            .map(|pair| add_on_license(&pair.Value().map_err(map_error)?))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(AppLicense {
+            is_active: license.IsActive().map_err(map_error)?,
+            is_trial,
+            trial_expiration_date: if is_trial {
+                expiration_date(license.ExpirationDate().map_err(map_error)?)
+            } else {
+                None
+            },
+            original_version: None,
+            original_purchase_date: None,
+            sku_store_id: license.SkuStoreId().map_err(map_error)?.to_string(),
+            add_on_licenses,
+        })
+    }
+
+    /// Reads the expiration from the add-on license, and the auto-renew state
+    /// from the user's collection.
+    fn subscription_status(&self, product_id: &str) -> Result<SubscriptionStatus> {
+        let license = self
+            .app_license()?
+            .add_on_licenses
+            .into_iter()
+            .find(|license| license.product_id == product_id)
+            .ok_or_else(|| Error::ItemNotOwned(product_id.to_string()))?;
+        Ok(SubscriptionStatus {
+            product_id: product_id.to_string(),
+            is_active: license.is_active,
+            // The Microsoft Store doesn't report failed renewals.
+            state: if license.is_active {
+                SubscriptionState::Active
+            } else {
+                SubscriptionState::Expired
+            },
+            grace_period_expiration_date: None,
+            expiration_date: license.expiration_date,
+            will_auto_renew: self.will_auto_renew(product_id)?,
+        })
+    }
+
+    /// Opens the Microsoft account services page, as subscriptions are
+    /// cancelled there rather than in the app.
+    fn open_manage_subscriptions(&self) -> Result<()> {
+        if launch(MANAGE_SUBSCRIPTIONS_URL)? {
+            Ok(())
+        } else {
+            Err(Error::InternalError(format!(
+                "Failed to open {MANAGE_SUBSCRIPTIONS_URL}"
+            )))
+        }
+    }
+
+    fn describe(&self, diagnostics: &mut Diagnostics) {
+        diagnostics.store = Some("Microsoft Store".into());
+    }
+
+    /// Opens the page in the Store app, or on the web if no app handles Store
+    /// links.
+    fn open_store_product_page(&self, product_id: Option<&str>) -> Result<()> {
+        let store_id = match product_id {
+            Some(id) => id.to_string(),
+            None => {
+                let result = self
+                    .context()?
+                    .GetStoreProductForCurrentAppAsync()
+                    .and_then(|operation| operation.get())
+                    .map_err(map_error)?;
+                check(result.ExtendedError().map_err(map_error)?)?;
+                result
+                    .Product()
+                    .and_then(|product| product.StoreId())
+                    .map_err(map_error)?
+                    .to_string()
+            }
+        };
+        let web_uri = format!("https://apps.microsoft.com/detail/{store_id}");
+        if launch(&format!("ms-windows-store://pdp/?ProductId={store_id}"))? || launch(&web_uri)? {
+            Ok(())
+        } else {
+            Err(Error::FeatureNotSupported(format!(
+                "No app can open the Store page {web_uri}"
+            )))
+        }
+    }
+
+    /// The user's home region, which the Store uses as their market.
+    fn country_code(&self) -> Result<String> {
+        GlobalizationPreferences::HomeGeographicRegion()
+            .map(|region| region.to_string())
+            .map_err(map_error)
+    }
+
+    /// Subscription add-ons are sold like other add-ons, but can't be
+    /// upgraded or downgraded from the app.
+    fn is_feature_supported(&self, feature: StoreFeature) -> Result<bool> {
+        Ok(feature == StoreFeature::Subscriptions)
+    }
+}
+
+fn product_kinds() -> IIterable<HSTRING> {
+    PRODUCT_KINDS
+        .iter()
+        .map(HSTRING::from)
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Maps the outcome of a purchase dialog.
+fn purchase_result(store_id: &str, result: &StorePurchaseResult) -> Result<PurchaseDetails> {
+    purchase_status(store_id, result.Status().map_err(map_error)?, || {
+        extended_message(result.ExtendedError())
+    })
+}
+
+/// Maps a purchase status, with `reason` describing failures. `NotPurchased`
+/// is reported when the user closed the dialog, so it counts as cancelling.
+fn purchase_status(
+    store_id: &str,
+    status: StorePurchaseStatus,
+    reason: impl FnOnce() -> String,
+) -> Result<PurchaseDetails> {
+    if status == StorePurchaseStatus::Succeeded {
+        Ok(purchase_details(store_id, PurchaseStatus::Purchased))
+    } else if status == StorePurchaseStatus::AlreadyPurchased {
+        Err(Error::ItemAlreadyOwned)
+    } else if status == StorePurchaseStatus::NotPurchased {
+        Err(Error::UserCancelled)
+    } else if status == StorePurchaseStatus::NetworkError {
+        Err(Error::NetworkError(reason()))
+    } else {
+        Err(Error::PurchaseError(reason()))
+    }
+}
+
+fn product_details(product: &StoreProduct) -> Result<ProductDetails> {
+    let price = product.Price().map_err(map_error)?;
+    let formatted = price.FormattedPrice().map_err(map_error)?.to_string();
+    let (subscription_period, free_trial_period) = match subscription_sku(product)? {
+        Some(sku) => subscription_periods(&sku)?,
+        None => (None, None),
+    };
+    Ok(ProductDetails {
+        id: product.StoreId().map_err(map_error)?.to_string(),
+        title: product.Title().map_err(map_error)?.to_string(),
+        description: product.Description().map_err(map_error)?.to_string(),
+        raw_price: raw_price(&formatted),
+        currency_symbol: formatted
+            .chars()
+            .filter(|c| !c.is_ascii_digit() && !c.is_whitespace() && !matches!(c, '.' | ','))
+            .collect(),
+        currency_code: price.CurrencyCode().map_err(map_error)?.to_string(),
+        price: formatted,
+        subscription_period,
+        free_trial_period,
+        subscription_group_id: None,
+    })
+}
+
+/// The first SKU of the product that is a subscription. Subscription add-ons
+/// are durables whose SKUs carry the billing details.
+fn subscription_sku(product: &StoreProduct) -> Result<Option<StoreSku>> {
+    for sku in product.Skus().and_then(|skus| skus.First()).map_err(map_error)? {
+        if sku.IsSubscription().map_err(map_error)? {
+            return Ok(Some(sku));
+        }
+    }
+    Ok(None)
+}
+
+/// The billing period and free trial of a subscription SKU.
+fn subscription_periods(sku: &StoreSku) -> Result<(Option<String>, Option<String>)> {
+    let info = sku.SubscriptionInfo().map_err(map_error)?;
+    let billing_period = iso8601_duration(
+        info.BillingPeriod().map_err(map_error)?,
+        info.BillingPeriodUnit().map_err(map_error)?,
+    );
+    let trial_period = if info.HasTrialPeriod().map_err(map_error)? {
+        iso8601_duration(
+            info.TrialPeriod().map_err(map_error)?,
+            info.TrialPeriodUnit().map_err(map_error)?,
+        )
+    } else {
+        None
+    };
+    Ok((billing_period, trial_period))
+}
+
+/// Formats a Store billing or trial period as an ISO 8601 duration.
+fn iso8601_duration(length: u32, unit: StoreDurationUnit) -> Option<String> {
+    let (time, designator) = if unit == StoreDurationUnit::Minute {
+        ("T", 'M')
+    } else if unit == StoreDurationUnit::Hour {
+        ("T", 'H')
+    } else if unit == StoreDurationUnit::Day {
+        ("", 'D')
+    } else if unit == StoreDurationUnit::Week {
+        ("", 'W')
+    } else if unit == StoreDurationUnit::Month {
+        ("", 'M')
+    } else if unit == StoreDurationUnit::Year {
+        ("", 'Y')
+    } else {
+        return None;
+    };
+    Some(format!("P{time}{length}{designator}"))
+}
+
+/// The Store only provides a formatted price, so the amount is parsed back
+/// from it, treating the last separator as the decimal one.
+fn raw_price(formatted: &str) -> f64 {
+    let digits: String = formatted
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '.' | ','))
+        .collect();
+    let normalized = match digits.rfind(['.', ',']) {
+        Some(i) if digits.len() - i <= 3 => {
+            let (whole, fraction) = digits.split_at(i);
+            format!("{}.{}", whole.replace(['.', ','], ""), &fraction[1..])
+        }
+        _ => digits.replace(['.', ','], ""),
+    };
+    normalized.parse().unwrap_or_default()
+}
+
+fn add_on_license(license: &StoreLicense) -> Result<AddOnLicense> {
+    let sku_store_id = license.SkuStoreId().map_err(map_error)?.to_string();
+    Ok(AddOnLicense {
+        // SKU Store IDs are the product's Store ID followed by `/` and the SKU.
+        product_id: sku_store_id
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        sku_store_id,
+        in_app_offer_token: license.InAppOfferToken().map_err(map_error)?.to_string(),
+        is_active: license.IsActive().map_err(map_error)?,
+        expiration_date: expiration_date(license.ExpirationDate().map_err(map_error)?),
+    })
+}
+
+/// The purchases of the add-ons with an active license, expiring with it.
+fn restored_purchases(licenses: Vec<AddOnLicense>) -> Vec<PurchaseDetails> {
+    licenses
+        .into_iter()
+        .filter(|license| license.is_active)
+        .map(|license| PurchaseDetails {
+            expiration_date: license.expiration_date,
+            ..purchase_details(&license.product_id, PurchaseStatus::Restored)
+        })
+        .collect()
+}
+
+/// Converts a license expiration date, which is the end of year 9999 for
+/// licenses that never expire.
+fn expiration_date(date: DateTime) -> Option<String> {
+    // 100ns ticks between 1601-01-01, the Windows epoch, and the Unix epoch.
+    const UNIX_EPOCH_TICKS: i64 = 116_444_736_000_000_000;
+    // 9999-12-31T00:00:00Z
+    const NEVER: i64 = 253_402_214_400;
+    let secs = (date.UniversalTime - UNIX_EPOCH_TICKS).div_euclid(10_000_000);
+    (secs < NEVER).then(|| iso8601(secs))
+}
+
+fn purchase_details(store_id: &str, status: PurchaseStatus) -> PurchaseDetails {
+    PurchaseDetails {
+        purchase_id: Some(store_id.to_string()),
+        product_id: store_id.to_string(),
+        verification_data: PurchaseVerificationData {
+            local_verification_data: store_id.to_string(),
+            server_verification_data: store_id.to_string(),
+            source: MICROSOFT_STORE_SOURCE.into(),
+            signature: None,
+        },
+        transaction_date: None,
+        transaction_date_ms: None,
+        expiration_date: None,
+        subscription_state: None,
+        grace_period_expiration_date: None,
+        status,
+        error: None,
+        pending_complete_purchase: true,
+        verified: false,
+    }
+}
+
+/// Fails with the error the Store reported alongside a result, if any.
+fn check(extended_error: HRESULT) -> Result<()> {
+    if extended_error.is_ok() {
+        Ok(())
+    } else {
+        Err(map_error(extended_error.into()))
+    }
+}
+
+fn extended_message(extended_error: windows::core::Result<HRESULT>) -> String {
+    match extended_error {
+        Ok(hresult) if hresult.is_err() => hresult.message(),
+        _ => "The Store did not report a reason".into(),
+    }
+}
+
+/// Launches the app handling `uri`, returning whether one was found.
+fn launch(uri: &str) -> Result<bool> {
+    let uri = Uri::CreateUri(&HSTRING::from(uri)).map_err(map_error)?;
+    Launcher::LaunchUriAsync(&uri)
+        .and_then(|operation| operation.get())
+        .map_err(map_error)
+}
+
+/// Maps a WinRT error, keeping the `HRESULT` as the native code.
+fn map_error(error: windows::core::Error) -> Error {
+    // HRESULT_FROM_WIN32 of the WinINet connection errors 12000 to 12031.
+    const NETWORK_ERRORS: i32 = 0x8007_2EE0_u32 as i32;
+    let code = error.code().0;
+    let mapped = if code & !0x1F == NETWORK_ERRORS {
+        Error::NetworkError(error.message())
+    } else {
+        Error::InternalError(error.message())
+    };
+    mapped.with_native_code(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::IapErrorCode;
+
+    use super::*;
+
+    #[test]
+    fn parses_formatted_prices() {
+        let prices = [
+            ("$1.99", 1.99),
+            ("1.234,56 €", 1234.56),
+            ("CHF 1'000.50", 1000.5),
+            ("¥1,200", 1200.0),
+            ("₹ 80", 80.0),
+            ("Free", 0.0),
+        ];
+        for (formatted, expected) in prices {
+            assert_eq!(raw_price(formatted), expected, "{formatted}");
+        }
+    }
+
+    #[test]
+    fn maps_connection_errors_to_network_errors() {
+        // HRESULT_FROM_WIN32(ERROR_INTERNET_NAME_NOT_RESOLVED)
+        let offline = HRESULT(0x8007_2EE7_u32 as i32);
+        let error = map_error(offline.into());
+        assert_eq!(error.code(), IapErrorCode::NetworkError);
+        assert_eq!(error.native_code(), Some(offline.0));
+
+        // E_ACCESSDENIED
+        let denied = HRESULT(0x8007_0005_u32 as i32);
+        let error = map_error(denied.into());
+        assert_eq!(error.code(), IapErrorCode::InternalError);
+        assert_eq!(error.native_code(), Some(denied.0));
+    }
+
+    #[test]
+    fn converts_license_expiration_dates() {
+        // 100ns ticks since 1601-01-01.
+        let date = |secs: i64| DateTime {
+            UniversalTime: 116_444_736_000_000_000 + secs * 10_000_000,
+        };
+
+        assert_eq!(
+            expiration_date(date(1_706_702_400)).as_deref(),
+            Some("2024-01-31T12:00:00Z")
+        );
+        assert_eq!(expiration_date(date(0)).as_deref(), Some("1970-01-01T00:00:00Z"));
+        // Licenses that never expire end with year 9999.
+        assert_eq!(expiration_date(date(253_402_300_799)), None);
+    }
+
+    #[test]
+    fn restores_active_add_on_licenses() {
+        let license = |product_id: &str, is_active, expiration_date: Option<&str>| AddOnLicense {
+            product_id: product_id.into(),
+            sku_store_id: format!("{product_id}/0010"),
+            in_app_offer_token: product_id.into(),
+            is_active,
+            expiration_date: expiration_date.map(Into::into),
+        };
+
+        let restored = restored_purchases(vec![
+            license("9NBLGGH4R315", true, None),
+            license("9NBLGGH4R316", false, Some("2024-01-31T12:00:00Z")),
+            license("9NBLGGH4R317", true, Some("2025-01-31T12:00:00Z")),
+        ]);
+
+        let products: Vec<_> = restored.iter().map(|p| p.product_id.as_str()).collect();
+        assert_eq!(products, ["9NBLGGH4R315", "9NBLGGH4R317"]);
+        assert!(restored
+            .iter()
+            .all(|p| matches!(p.status, PurchaseStatus::Restored)));
+        assert_eq!(restored[0].expiration_date, None);
+        assert_eq!(
+            restored[1].expiration_date.as_deref(),
+            Some("2025-01-31T12:00:00Z")
+        );
+        assert_eq!(
+            restored[1].verification_data.source,
+            MICROSOFT_STORE_SOURCE
+        );
+    }
+
+    #[test]
+    fn maps_purchase_statuses() {
+        let reason = || "Store reason".to_string();
+
+        let bought = purchase_status("9NBLGGH4R315", StorePurchaseStatus::Succeeded, reason);
+        let bought = bought.unwrap();
+        assert_eq!(bought.product_id, "9NBLGGH4R315");
+        assert!(matches!(bought.status, PurchaseStatus::Purchased));
+        assert!(bought.pending_complete_purchase);
+
+        let failures = [
+            (StorePurchaseStatus::AlreadyPurchased, IapErrorCode::ItemAlreadyOwned),
+            (StorePurchaseStatus::NotPurchased, IapErrorCode::UserCancelled),
+            (StorePurchaseStatus::NetworkError, IapErrorCode::NetworkError),
+            (StorePurchaseStatus::ServerError, IapErrorCode::PurchaseError),
+        ];
+        for (status, code) in failures {
+            let error = purchase_status("9NBLGGH4R315", status, reason).unwrap_err();
+            assert_eq!(error.code(), code, "{status:?}");
+        }
+        let error = purchase_status("9NBLGGH4R315", StorePurchaseStatus::ServerError, reason);
+        assert!(error.unwrap_err().to_string().contains("Store reason"));
+    }
+
+    #[test]
+    fn formats_subscription_periods() {
+        let periods = [
+            (1, StoreDurationUnit::Month, Some("P1M")),
+            (1, StoreDurationUnit::Year, Some("P1Y")),
+            (2, StoreDurationUnit::Week, Some("P2W")),
+            (7, StoreDurationUnit::Day, Some("P7D")),
+            (12, StoreDurationUnit::Hour, Some("PT12H")),
+            (30, StoreDurationUnit::Minute, Some("PT30M")),
+            (1, StoreDurationUnit(42), None),
+        ];
+        for (length, unit, expected) in periods {
+            assert_eq!(iso8601_duration(length, unit).as_deref(), expected, "{unit:?}");
+        }
+    }
+}
@@ -1,174 +1,1911 @@
 use jni::objects::{JClass, JObject, JString, JValue};
 use jni::JNIEnv;
-use serde::de::DeserializeOwned;
 use serde_json::json;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
 use tauri::{
     plugin::{PluginApi, PluginHandle},
     AppHandle, Runtime,
 };
 
+use crate::cached::Cached;
+use crate::connection::Connection;
+use crate::entitlements::Entitlements;
 use crate::models::*;
+use crate::in_flight::InFlight;
+use crate::metrics::Counters;
+use crate::product_cache::ProductCache;
+use crate::product_query::query_chunks;
+use crate::purchase_flows::PurchaseFlows;
+use crate::purchase_stream::PurchaseStreams;
+use crate::transaction_updates::TransactionUpdates;
+use crate::unfinished::UnfinishedPurchases;
+use crate::registry::PurchaseRegistry;
+use crate::{
+    retry, retry_connection, CancellationToken, Config, ConnectionRetryPolicy, Diagnostics, Error,
+    IapErrorCode, Metrics, ProductIdRules, PurchaseUpdate, RetryPolicy,
+};
 
 #[cfg(target_os = "ios")]
 tauri::ios_plugin_binding!(init_plugin_iap);
 
+/// Kotlin plugin class, from the `amazon` flavor of the Android library with
+/// the `amazon-appstore` feature.
+#[cfg(target_os = "android")]
+const ANDROID_PLUGIN: &str = if cfg!(feature = "amazon-appstore") {
+    "AmazonIapPlugin"
+} else {
+    "IapPlugin"
+};
+
+/// Name of the store the mobile builds sell through.
+const STORE: &str = if cfg!(target_os = "ios") {
+    "App Store"
+} else if cfg!(feature = "amazon-appstore") {
+    "Amazon Appstore"
+} else {
+    "Google Play"
+};
+
 // initializes the Kotlin or Swift plugin classes
-pub fn init<R: Runtime, C: DeserializeOwned>(
-    _app: &AppHandle<R>,
-    api: PluginApi<R, C>,
+pub fn init<R: Runtime>(
+    app: &AppHandle<R>,
+    api: PluginApi<R, Option<Config>>,
+    config: Config,
+    retry_policy: RetryPolicy,
+    reject_unknown_purchases: bool,
 ) -> crate::Result<Iap<R>> {
+    let config = Arc::new(config);
     #[cfg(target_os = "android")]
-    let handle = api.register_android_plugin("com.plugin.iap", "IapPlugin")?;
+    let handle = api
+        .register_android_plugin("com.plugin.iap", ANDROID_PLUGIN)
+        .map_err(Error::from_invoke_error)?;
     #[cfg(target_os = "ios")]
-    let handle = api.register_ios_plugin(init_plugin_iap)?;
-    Ok(Iap(handle))
+    let handle = api
+        .register_ios_plugin(init_plugin_iap)
+        .map_err(Error::from_invoke_error)?;
+
+    // Installed at setup, before StoreKit shows the sheet at launch.
+    #[cfg(target_os = "ios")]
+    if config.defer_price_consent {
+        if let Err(e) = handle.run_mobile_plugin::<serde_json::Value>("defer_price_consent", ()) {
+            log::warn!("Failed to defer the price consent sheet: {}", e);
+        }
+    }
+
+    let registry = Arc::new(PurchaseRegistry::default());
+    let streams = Arc::new(PurchaseStreams::default());
+    let reporter = Arc::new(PurchaseReporter {
+        app: app.clone(),
+        handle: handle.clone(),
+        config: config.clone(),
+        registry: registry.clone(),
+        streams: streams.clone(),
+        flows: PurchaseFlows::default(),
+        entitlements: Arc::new(Entitlements::new(config.owned_on_hold)),
+        unfinished: Arc::new(UnfinishedPurchases::in_app_data_dir(app)),
+        // An invalid key fails the setup, like any configuration error.
+        #[cfg(target_os = "android")]
+        public_key: config
+            .google_play_public_key
+            .as_deref()
+            .map(crate::play_signature::PlayPublicKey::parse)
+            .transpose()?,
+    });
+    let country_code = Arc::new(Cached::default());
+    let connection = {
+        let (handle, config) = (app.clone(), config.clone());
+        let country_code = country_code.clone();
+        Arc::new(Connection::new(move |change| {
+            // The storefront may have changed while disconnected.
+            country_code.clear();
+            if let Err(e) = config.emit(&handle, crate::CONNECTION_STATE_EVENT, change) {
+                log::error!("Failed to emit connection state: {}", e);
+            }
+        }))
+    };
+    let products = Arc::new(if config.persist_products {
+        ProductCache::in_app_data_dir(app)
+    } else {
+        ProductCache::default()
+    });
+    let shutdown = Arc::new(Mutex::new(CancellationToken::new()));
+    let storefront_changed: Arc<StorefrontChanged> = {
+        let (app, handle, config) = (app.clone(), handle.clone(), config.clone());
+        let (products, country_code) = (products.clone(), country_code.clone());
+        let (connection, shutdown) = (connection.clone(), shutdown.clone());
+        let streams = streams.clone();
+        Arc::new(move || {
+            country_code.clear();
+            products.clear();
+            if let Err(e) = config.emit(&app, crate::STOREFRONT_CHANGED_EVENT, ()) {
+                log::error!("Failed to emit storefront change: {}", e);
+            }
+            // Before the first initialization, `initialize` preloads them.
+            if connection.ensure_ready().is_ok() && config.preloads_on_storefront_change() {
+                let shutdown = shutdown
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone();
+                spawn_preload(
+                    &handle,
+                    &products,
+                    &config,
+                    &app,
+                    &streams,
+                    retry_policy,
+                    shutdown,
+                );
+            }
+        })
+    };
+    #[cfg(target_os = "android")]
+    android::set_locale_listener(storefront_changed);
+    #[cfg(target_os = "android")]
+    {
+        let connection = connection.clone();
+        android::set_connection_listener(move |state, attempt| match attempt {
+            Some(attempt) => connection.report_attempt(attempt),
+            None => connection.report(state),
+        });
+    }
+    #[cfg(target_os = "android")]
+    {
+        let (reporter, connection) = (reporter.clone(), connection.clone());
+        android::set_purchase_listener(move |mut purchases| {
+            // The billing client may still deliver updates while closing.
+            if connection.state() == ConnectionState::Closed {
+                log::debug!("Dropping {} purchase updates after end_connection", purchases.len());
+                return;
+            }
+            reporter.record(&mut purchases);
+            reporter.emit(purchases);
+        });
+    }
+    #[cfg(target_os = "android")]
+    {
+        let reporter = reporter.clone();
+        android::set_error_listener(move |error| reporter.emit_error(error));
+    }
+
+    let restores = Arc::new(InFlight::with_cooldown(config.restore_cooldown()));
+    Ok(Iap {
+        handle,
+        connection,
+        country_code,
+        init_lock: Arc::default(),
+        config,
+        retry_policy,
+        reject_unknown_purchases,
+        application_user_name: Arc::default(),
+        registry,
+        reporter,
+        products,
+        queries: Arc::default(),
+        restores,
+        completions: Arc::default(),
+        metrics: Arc::default(),
+        transaction_updates: Arc::default(),
+        #[cfg(target_os = "ios")]
+        storefront_changed,
+        shutdown,
+    })
+}
+
+/// Callback dropping what depends on the storefront or the language when
+/// either changes.
+type StorefrontChanged = dyn Fn() + Send + Sync;
+
+/// Queries the configured products and caches them, reporting failures
+/// through the error event as they must not fail `initialize`.
+fn spawn_preload<R: Runtime>(
+    handle: &PluginHandle<R>,
+    products: &Arc<ProductCache>,
+    config: &Arc<Config>,
+    app: &AppHandle<R>,
+    streams: &Arc<PurchaseStreams>,
+    retry_policy: RetryPolicy,
+    shutdown: CancellationToken,
+) {
+    let (handle, products) = (handle.clone(), products.clone());
+    let (config, app, streams) = (config.clone(), app.clone(), streams.clone());
+    std::thread::spawn(move || {
+        let query = |chunk: &[String]| {
+            handle
+                .run_mobile_plugin("query_product_details", json!({ "productIds": chunk }))
+                .map_err(Error::from_invoke_error)
+        };
+        let emitted = match query_chunks(&retry_policy, &shutdown, &config.products, query) {
+            Ok(response) => {
+                products.insert(&response.product_details);
+                config.emit(&app, crate::PRODUCTS_LOADED_EVENT, &response)
+            }
+            Err(e) => {
+                log::warn!("Failed to preload products: {}", e);
+                let error = IAPError::from(&e);
+                streams.send(PurchaseUpdate::Error(error.clone()));
+                config.emit(&app, crate::ERROR_EVENT, error)
+            }
+        };
+        if let Err(e) = emitted {
+            log::error!("Failed to emit preloaded products: {}", e);
+        }
+    });
+}
+
+/// Reports the purchases the store returned to the app.
+struct PurchaseReporter<R: Runtime> {
+    app: AppHandle<R>,
+    handle: PluginHandle<R>,
+    config: Arc<Config>,
+    registry: Arc<PurchaseRegistry>,
+    /// Streams handed out by `purchase_stream`.
+    streams: Arc<PurchaseStreams>,
+    /// Buy flows of `purchase()` in guest-js awaiting their purchase.
+    flows: PurchaseFlows,
+    /// Products owned according to the reported purchases.
+    entitlements: Arc<Entitlements>,
+    /// Purchases awaiting completion, kept across restarts.
+    unfinished: Arc<UnfinishedPurchases>,
+    #[cfg(target_os = "android")]
+    public_key: Option<crate::play_signature::PlayPublicKey>,
+}
+
+impl<R: Runtime> PurchaseReporter<R> {
+    /// Verifies the purchases if a key is configured and records them as
+    /// reported by the store, before anything may try to complete them.
+    fn record(&self, purchases: &mut [PurchaseDetails]) {
+        #[cfg(target_os = "android")]
+        if let Some(key) = &self.public_key {
+            key.verify_purchases(purchases, self.config.reject_unverified_purchases);
+        }
+        // Purchases rejected by the verification can't be completed.
+        self.registry
+            .record(purchases.iter().filter(|purchase| purchase.error.is_none()));
+    }
+
+    /// Completes the purchases if configured and emits them. Blocks while
+    /// completing.
+    fn emit(&self, purchases: Vec<PurchaseDetails>) {
+        let purchases = self.config.reported_purchases(&purchases);
+        let purchases = self.config.finish_purchases(&purchases, |purchase| {
+            self.handle
+                .run_mobile_plugin::<()>("complete_purchase", purchase)
+                .map_err(Error::from_invoke_error)
+        });
+        self.entitlements.update(&purchases);
+        self.unfinished.update(&purchases);
+        #[cfg(feature = "tracing")]
+        crate::instrument::purchases_reported(&purchases);
+        if purchases.is_empty() {
+            return;
+        }
+        let correlated = self.flows.correlate(&purchases);
+        if let Err(e) = self
+            .config
+            .emit(&self.app, crate::PURCHASE_UPDATE_EVENT, correlated)
+        {
+            log::error!("Failed to emit purchase update: {}", e);
+        }
+        self.streams.send(PurchaseUpdate::Purchases(purchases));
+    }
+
+    /// Reports a failure no command returns, e.g. a failed purchase flow,
+    /// through the error event and the purchase streams.
+    // Only the Android bridge reports such failures.
+    #[cfg_attr(not(target_os = "android"), allow(dead_code))]
+    fn emit_error(&self, error: IAPError) {
+        let error = self.flows.correlate_error(error);
+        self.streams.send(PurchaseUpdate::Error(error.clone()));
+        if let Err(e) = self.config.emit(&self.app, crate::ERROR_EVENT, error) {
+            log::error!("Failed to emit error: {}", e);
+        }
+    }
+
+    /// Queries the owned purchases without store UI and reports them,
+    /// replacing the entitlements.
+    fn report_owned(&self) -> crate::Result<()> {
+        let purchases = self.owned()?;
+        self.entitlements.replace(&purchases);
+        self.unfinished.reconcile(&purchases);
+        self.emit(purchases);
+        Ok(())
+    }
+
+    /// Reports the purchases a previous run left unfinished that the store
+    /// still has awaiting completion, forgetting the others.
+    fn report_unfinished(&self) -> crate::Result<()> {
+        let purchases = self.owned()?;
+        self.emit(self.unfinished.reconcile(&purchases));
+        Ok(())
+    }
+
+    /// Queries the owned purchases without store UI and records them.
+    fn owned(&self) -> crate::Result<Vec<PurchaseDetails>> {
+        #[derive(serde::Deserialize)]
+        struct Owned {
+            purchases: Vec<PurchaseDetails>,
+        }
+
+        let owned: Owned = self
+            .handle
+            .run_mobile_plugin("owned_purchases", ())
+            .map_err(Error::from_invoke_error)?;
+        let mut purchases = owned.purchases;
+        self.record(&mut purchases);
+        Ok(purchases)
+    }
 }
 
 /// Access to the iap APIs.
-pub struct Iap<R: Runtime>(PluginHandle<R>);
+///
+/// Methods reaching the store are async and run the native call on a worker
+/// thread, so they can be awaited from any async runtime. Clones share the
+/// same connection and state.
+pub struct Iap<R: Runtime> {
+    handle: PluginHandle<R>,
+    /// Set to `Ready` once the native billing client reported a successful
+    /// setup, then driven by the native connection callbacks.
+    connection: Arc<Connection>,
+    /// Cleared on every connection state change.
+    country_code: Arc<Cached<String>>,
+    /// Serializes `initialize` so concurrent callers share a single native call.
+    init_lock: Arc<Mutex<()>>,
+    config: Arc<Config>,
+    /// Used by purchases and restores that don't pass an application user
+    /// name.
+    application_user_name: Arc<Mutex<Option<String>>>,
+    retry_policy: RetryPolicy,
+    reject_unknown_purchases: bool,
+    /// Purchases reported by the store, checked before completing one.
+    registry: Arc<PurchaseRegistry>,
+    reporter: Arc<PurchaseReporter<R>>,
+    /// Product details cached by `preloadProducts`.
+    products: Arc<ProductCache>,
+    /// Product queries in progress, keyed by the sorted product IDs.
+    queries: Arc<InFlight<Vec<String>, ProductDetailsResponse>>,
+    /// Restores in progress or within the cooldown, keyed by the application
+    /// user name.
+    restores: Arc<InFlight<Option<String>, ()>>,
+    /// Purchases being completed, keyed by transaction ID and purchase token,
+    /// so completing one twice at once makes a single store call.
+    completions: Arc<InFlight<(Option<String>, String), ()>>,
+    /// Purchase funnel counts reported by `metrics`.
+    metrics: Arc<Counters>,
+    /// Started by `initialize` and stopped by `end_connection`.
+    transaction_updates: Arc<TransactionUpdates>,
+    /// Called by the storefront and locale listener of iOS, while Android
+    /// reports locale changes through `onLocaleChanged`.
+    #[cfg(target_os = "ios")]
+    storefront_changed: Arc<StorefrontChanged>,
+    /// Cancelled by `end_connection` so pending retries give up, and
+    /// replaced when `initialize` connects again.
+    shutdown: Arc<Mutex<CancellationToken>>,
+}
+
+impl<R: Runtime> Clone for Iap<R> {
+    fn clone(&self) -> Self {
+        Self {
+            handle: self.handle.clone(),
+            connection: self.connection.clone(),
+            country_code: self.country_code.clone(),
+            init_lock: self.init_lock.clone(),
+            config: self.config.clone(),
+            application_user_name: self.application_user_name.clone(),
+            retry_policy: self.retry_policy,
+            reject_unknown_purchases: self.reject_unknown_purchases,
+            registry: self.registry.clone(),
+            reporter: self.reporter.clone(),
+            products: self.products.clone(),
+            queries: self.queries.clone(),
+            restores: self.restores.clone(),
+            completions: self.completions.clone(),
+            metrics: self.metrics.clone(),
+            transaction_updates: self.transaction_updates.clone(),
+            #[cfg(target_os = "ios")]
+            storefront_changed: self.storefront_changed.clone(),
+            shutdown: self.shutdown.clone(),
+        }
+    }
+}
 
 impl<R: Runtime> Iap<R> {
     /// Initialize the in-app purchase system.
     ///
+    /// The billing client and transaction observer are shared by every
+    /// window, so each may call this: calling it again after a successful
+    /// initialization is a no-op, and concurrent calls wait for the first one
+    /// instead of reaching the native side twice. Transient connection
+    /// failures are retried according to the connection retry policy, which
+    /// Android also follows to reconnect when the billing service disconnects
+    /// later. Then the owned purchases are reported in the background with
+    /// `autoRestoreOnInit`, and the configured products are cached with
+    /// `preloadProducts`. After `end_connection`, this sets up the native
+    /// billing client again.
+    ///
     /// # Errors
     ///
-    /// Returns an error if the initialization fails on the native platform.
-    pub fn initialize(&self) -> crate::Result<()> {
-        self.0
-            .run_mobile_plugin("initialize", ())
-            .map_err(Into::into)
+    /// Returns `Error::StoreUnavailable` without retrying if the device has
+    /// no store, e.g. no Google Play, and another error if the initialization
+    /// fails on the native platform.
+    pub async fn initialize(&self) -> crate::Result<()> {
+        self.on_worker(move |iap| iap.initialize_blocking()).await
+    }
+
+    fn initialize_blocking(&self) -> crate::Result<()> {
+        if self.connection.ensure_ready().is_ok() {
+            return Ok(());
+        }
+
+        let _guard = self
+            .init_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // Another caller may have finished initializing while we were waiting.
+        match self.connection.state() {
+            ConnectionState::Ready | ConnectionState::Reconnecting => return Ok(()),
+            ConnectionState::Closed => {
+                *self.shutdown.lock().unwrap_or_else(PoisonError::into_inner) =
+                    CancellationToken::new();
+            }
+            ConnectionState::Uninitialized | ConnectionState::Initializing => {}
+        }
+        self.connection.transition(ConnectionState::Initializing)?;
+
+        let args = json!({
+            "pendingPurchases": self.config.pending_purchases,
+            "alternativeBillingOnly": self.config.alternative_billing_only,
+            "connectionRetry": self.config.connection_retry,
+            "redactVerificationData": self.config.redact_verification_data,
+        });
+        let connected = retry_connection(&self.config.connection_retry, &self.shutdown(), || {
+            self.handle
+                .run_mobile_plugin::<()>("initialize", &args)
+                .map_err(Error::from_invoke_error)
+        });
+        #[cfg(feature = "tracing")]
+        crate::instrument::connection(&connected);
+        // `end_connection` was called while connecting.
+        if self.connection.state() == ConnectionState::Closed {
+            return Err(Error::NotInitialized);
+        }
+        self.connection.connected(&connected)?;
+        connected?;
+        // Failures must not fail `initialize`; purchases are still reported
+        // by the calls that make them.
+        if let Err(e) = self
+            .transaction_updates
+            .start(|| self.start_transaction_updates())
+        {
+            log::warn!("Failed to listen for transaction updates: {}", e);
+        }
+        if self.config.auto_restore_on_init {
+            let reporter = self.reporter.clone();
+            std::thread::spawn(move || {
+                // Failures must not fail `initialize`, which already succeeded.
+                if let Err(e) = reporter.report_owned() {
+                    log::warn!("Failed to restore purchases on initialize: {}", e);
+                }
+            });
+        } else if !self.reporter.unfinished.is_empty() {
+            let reporter = self.reporter.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = reporter.report_unfinished() {
+                    log::warn!("Failed to check unfinished purchases on initialize: {}", e);
+                }
+            });
+        }
+        if self.config.preload_products && !self.config.products.is_empty() {
+            spawn_preload(
+                &self.handle,
+                &self.products,
+                &self.config,
+                &self.reporter.app,
+                &self.reporter.streams,
+                self.retry_policy,
+                self.shutdown(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether the user owns the product, according to the purchases the
+    /// store reported since the app started, including the
+    /// `autoRestoreOnInit` query. While the store is unreachable, products
+    /// kept by the entitlement cache count as owned too.
+    pub fn is_owned(&self, product_id: &str) -> bool {
+        self.reporter.entitlements.is_owned(product_id)
+            || (self.is_offline() && self.reporter.entitlements.is_cached(product_id))
+    }
+
+    /// The products the user owns, according to the purchases the store
+    /// reported since the app started. While the store is unreachable, the
+    /// products the entitlement cache kept from a previous run are added with
+    /// the `Cache` source.
+    pub fn owned_products(&self) -> Vec<OwnedProduct> {
+        self.reporter.entitlements.owned_products(self.is_offline())
+    }
+
+    /// What the plugin considers owned: the owned products with the states
+    /// and dates of their purchases, and the cached ones the store didn't
+    /// report, without tokens or receipts, e.g. to attach to a support
+    /// ticket.
+    pub fn export_entitlements(&self) -> EntitlementsExport {
+        EntitlementsExport::new(self.reporter.entitlements.export())
+    }
+
+    /// Replaces the owned products with an export, e.g. a state crafted by
+    /// QA, rewriting the entitlement cache. The change is reported through
+    /// the purchase update event: products no longer owned as `Canceled`,
+    /// then the imported ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported` in release builds, and
+    /// `Error::DeveloperError` if the export is of another schema version.
+    pub fn import_entitlements(&self, export: EntitlementsExport) -> crate::Result<()> {
+        EntitlementsExport::check_import_allowed(cfg!(debug_assertions), false)?;
+        export.check_schema_version()?;
+        self.reporter
+            .emit(self.reporter.entitlements.import(&export.entitlements));
+        Ok(())
+    }
+
+    /// The purchases granting an entitlement right now, read from the store
+    /// without any UI or purchase update events.
+    ///
+    /// iOS 15+ reads `Transaction.currentEntitlements`, reporting transactions
+    /// failing the StoreKit verification with `verified` unset, and older
+    /// versions report none. Android queries the owned products and active
+    /// subscriptions. Revoked, expired and pending purchases are left out.
+    pub async fn current_entitlements(&self) -> crate::Result<Vec<PurchaseDetails>> {
+        self.on_worker(move |iap| iap.current_entitlements_blocking()).await
+    }
+
+    fn current_entitlements_blocking(&self) -> crate::Result<Vec<PurchaseDetails>> {
+        #[derive(serde::Deserialize)]
+        struct Entitlements {
+            purchases: Vec<PurchaseDetails>,
+        }
+
+        self.ensure_initialized()?;
+        let entitlements: Entitlements = self
+            .handle
+            .run_mobile_plugin("current_entitlements", ())
+            .map_err(Error::from_invoke_error)?;
+        let mut purchases = entitlements.purchases;
+        self.reporter.record(&mut purchases);
+        Ok(purchases)
+    }
+
+    /// Keeps the owned products in `cache`, set up by the plugin builder.
+    #[cfg(feature = "entitlement-cache")]
+    pub(crate) fn set_entitlement_cache(&self, cache: crate::entitlement_cache::EntitlementCache) {
+        self.reporter.entitlements.set_cache(cache);
+    }
+
+    /// Whether the store can't be reached, so the cached entitlements are
+    /// used.
+    fn is_offline(&self) -> bool {
+        self.connection.state() != ConnectionState::Ready
+    }
+
+    /// The verification payload of an owned purchase, by its transaction ID.
+    ///
+    /// # Errors
+    ///
+    /// `Error::UnknownPurchase` if the store reported no owned purchase with
+    /// the ID since the app started, or the errors of
+    /// [`PurchaseDetails::verification_payload`].
+    pub fn export_verification_payload(
+        &self,
+        purchase_id: &str,
+    ) -> crate::Result<VerificationPayload> {
+        self.reporter
+            .entitlements
+            .find(purchase_id)
+            .ok_or_else(|| {
+                crate::Error::UnknownPurchase(format!("No owned purchase has the ID {purchase_id}"))
+            })?
+            .verification_payload()
+    }
+
+    /// The plugin configuration from `tauri.conf.json`.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The policy used to retry transient store failures.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// The policy used to retry the store connection.
+    pub fn connection_retry_policy(&self) -> &ConnectionRetryPolicy {
+        &self.config.connection_retry
+    }
+
+    /// Whether `complete_purchase` rejects purchases the store never reported.
+    pub fn rejects_unknown_purchases(&self) -> bool {
+        self.reject_unknown_purchases
+    }
+
+    /// Sets the application user name used by purchases and restores that
+    /// don't pass one, or clears it with `None`, e.g. when the user logs out.
+    pub fn set_application_user_name(&self, name: Option<String>) {
+        *self
+            .application_user_name
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = name;
+    }
+
+    /// The application user name used by purchases and restores that don't
+    /// pass one.
+    pub fn application_user_name(&self) -> Option<String> {
+        self.application_user_name
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// `purchase_param`, with the stored application user name unless it
+    /// has one.
+    fn with_application_user_name(&self, mut purchase_param: PurchaseParam) -> PurchaseParam {
+        if purchase_param.application_user_name.is_none() {
+            purchase_param.application_user_name = self.application_user_name();
+        }
+        purchase_param
+    }
+
+    /// Checks product IDs against the rules of the platform's store, unless
+    /// `skip_product_id_validation` is set.
+    fn validate_product_ids(&self, product_ids: &[String]) -> crate::Result<()> {
+        match ProductIdRules::platform() {
+            Some(rules) if !self.config.skip_product_id_validation => rules.validate(product_ids),
+            _ => Ok(()),
+        }
+    }
+
+    /// Closes the store connection and gives up pending retries. Android
+    /// ends the billing client and its background reconnection, iOS stops
+    /// observing transactions, and purchase updates are no longer reported.
+    /// Store calls then fail with `Error::NotInitialized` until `initialize`
+    /// connects again. This also runs when the plugin is dropped.
+    pub fn end_connection(&self) {
+        self.connection.report(ConnectionState::Closed);
+        self.shutdown().cancel();
+        // Ending the native connection below stops the listener too.
+        self.transaction_updates.stop(|| {});
+        if let Err(e) = self
+            .handle
+            .run_mobile_plugin::<serde_json::Value>("end_connection", ())
+        {
+            log::warn!("Failed to end the billing connection: {}", e);
+        }
+    }
+
+    /// Opens a stream of the purchase updates, restore completions and
+    /// background errors reported from now on, with the same purchases as the
+    /// purchase update event but their verification data always included.
+    /// The stream ends when the plugin is dropped.
+    ///
+    /// Any number of streams can be open next to the webview events. Each
+    /// buffers [`PURCHASE_STREAM_BUFFER`](crate::PURCHASE_STREAM_BUFFER)
+    /// updates: reporting never waits for a slow receiver, whose stream
+    /// misses the updates while full and then gets a
+    /// [`PurchaseUpdate::Lagged`] with their count.
+    pub fn purchase_stream(&self) -> tokio::sync::mpsc::Receiver<PurchaseUpdate> {
+        self.reporter.streams.subscribe()
+    }
+
+    /// Ends the purchase streams, when the plugin is dropped.
+    pub(crate) fn close_purchase_streams(&self) {
+        self.reporter.streams.close();
+    }
+
+    /// The buy flows the purchase update events are correlated with.
+    pub(crate) fn purchase_flows(&self) -> &PurchaseFlows {
+        &self.reporter.flows
+    }
+
+    /// Starts reporting the purchases the store changes outside of a
+    /// purchase, e.g. renewals, returning whether a listener runs.
+    ///
+    /// The Google Play billing client reports them to its purchase listener
+    /// while connected. iOS 15+ consumes `Transaction.updates` in a single
+    /// task, started here or when the plugin loads with
+    /// `transactionListenerOnLoad`, which keeps the updates received until
+    /// this attaches the channel. iOS also reports `Storefront.updates` and
+    /// locale changes from here on.
+    fn start_transaction_updates(&self) -> crate::Result<bool> {
+        #[cfg(target_os = "android")]
+        return Ok(true);
+
+        #[cfg(target_os = "ios")]
+        {
+            #[derive(serde::Deserialize)]
+            struct Listener {
+                running: bool,
+            }
+
+            #[derive(serde::Deserialize)]
+            struct Updates {
+                purchases: Vec<PurchaseDetails>,
+            }
+
+            let (reporter, connection) = (self.reporter.clone(), self.connection.clone());
+            let on_update = tauri::ipc::Channel::<serde_json::Value>::new(move |body| {
+                if connection.state() == ConnectionState::Closed {
+                    return Ok(());
+                }
+                let mut purchases = body.deserialize::<Updates>()?.purchases;
+                reporter.record(&mut purchases);
+                reporter.emit(purchases);
+                Ok(())
+            });
+            let storefront_changed = self.storefront_changed.clone();
+            let on_storefront_change = tauri::ipc::Channel::<serde_json::Value>::new(move |_| {
+                storefront_changed();
+                Ok(())
+            });
+            let listener: Listener = self
+                .handle
+                .run_mobile_plugin(
+                    "start_transaction_updates",
+                    json!({
+                        "onUpdate": on_update,
+                        "onStorefrontChange": on_storefront_change,
+                    }),
+                )
+                .map_err(Error::from_invoke_error)?;
+            Ok(listener.running)
+        }
+    }
+
+    /// Runs a blocking native call on a worker thread with a clone of
+    /// `self`, so awaiting it doesn't hold up the caller's runtime. Nothing
+    /// is locked around the call: concurrent calls only wait for each other
+    /// where an operation shares one native call, e.g. initializing.
+    async fn on_worker<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&Self) -> crate::Result<T> + Send + 'static,
+    ) -> crate::Result<T> {
+        let iap = self.clone();
+        match tauri::async_runtime::spawn_blocking(move || call(&iap)).await {
+            Ok(result) => result,
+            Err(e) => Err(Error::InternalError(e.to_string())),
+        }
+    }
+
+    /// The token cancelled by the next `end_connection`.
+    fn shutdown(&self) -> CancellationToken {
+        self.shutdown
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// The state of the store connection.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.connection.state()
+    }
+
+    /// Fails with `Error::NotInitialized` until `initialize` has succeeded, or
+    /// after reconnecting gave up.
+    fn ensure_initialized(&self) -> crate::Result<()> {
+        self.connection.ensure_ready()
     }
 
     /// Check if in-app purchases are available on this platform.
     ///
     /// # Returns
     ///
-    /// Returns true if IAP is available on this platform.
-    pub fn is_available(&self) -> crate::Result<bool> {
-        self.0
+    /// Returns true if IAP is available on this platform. False for sideloaded
+    /// builds with `require_store_install`, for `amazon-appstore` builds on
+    /// devices without the Amazon Appstore, which can't buy, and on devices
+    /// without Google Play or where connecting found no store.
+    pub async fn is_available(&self) -> crate::Result<bool> {
+        self.on_worker(move |iap| iap.is_available_blocking()).await
+    }
+
+    fn is_available_blocking(&self) -> crate::Result<bool> {
+        if self.config.require_store_install
+            && self.install_source_blocking()? == InstallSource::Sideload
+        {
+            log::warn!("In-app purchases are unavailable, the app wasn't installed from a store");
+            return Ok(false);
+        }
+        if !self.connection.store_present() {
+            return Ok(false);
+        }
+        match self
+            .handle
             .run_mobile_plugin("is_available", ())
-            .map_err(Into::into)
+            .map_err(Error::from_invoke_error)
+        {
+            Err(e) if e.code() == IapErrorCode::StoreUnavailable => Ok(false),
+            available => available,
+        }
+    }
+
+    /// Environment details to attach to bug reports: the plugin and store
+    /// library versions, the connection and the configuration in effect,
+    /// without keys or purchases.
+    pub async fn diagnostics(&self) -> crate::Result<Diagnostics> {
+        self.on_worker(move |iap| iap.diagnostics_blocking()).await
+    }
+
+    fn diagnostics_blocking(&self) -> crate::Result<Diagnostics> {
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Native {
+            /// Reported on Android, the Amazon Appstore SDK version in
+            /// `amazon` builds.
+            billing_library_version: Option<String>,
+            /// Reported on iOS.
+            #[serde(rename = "storeKit2")]
+            store_kit_2: Option<bool>,
+        }
+
+        let native: Native = self
+            .handle
+            .run_mobile_plugin("diagnostics", ())
+            .map_err(Error::from_invoke_error)?;
+        let mut diagnostics = Diagnostics::new(&self.config, self.connection_state());
+        diagnostics.store = Some(STORE.into());
+        diagnostics.billing_library_version = native.billing_library_version;
+        diagnostics.store_kit_2 = native.store_kit_2;
+        diagnostics.transaction_listener = self.transaction_updates.is_running();
+        Ok(diagnostics)
+    }
+
+    /// Purchase funnel counts since the app started or the last
+    /// [`reset_metrics`](Self::reset_metrics).
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.snapshot()
+    }
+
+    /// Zeroes the counts reported by [`metrics`](Self::metrics), e.g. between
+    /// test runs.
+    pub fn reset_metrics(&self) {
+        self.metrics.reset();
+    }
+
+    /// Where the app was installed from, e.g. to explain why purchases fail
+    /// in sideloaded builds. Android asks `PackageManager` for the installer,
+    /// iOS tells App Store, TestFlight and development builds apart from the
+    /// receipt and provisioning profile.
+    ///
+    /// Unlike the other store calls this works before `initialize`, so the
+    /// app can tell why the store can't be used.
+    pub async fn install_source(&self) -> crate::Result<InstallSource> {
+        self.on_worker(move |iap| iap.install_source_blocking()).await
+    }
+
+    fn install_source_blocking(&self) -> crate::Result<InstallSource> {
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Installation {
+            /// Reported on Android.
+            installer_package_name: Option<String>,
+            /// Reported on iOS.
+            environment: Option<String>,
+            debuggable: bool,
+        }
+
+        let installation: Installation = self
+            .handle
+            .run_mobile_plugin("install_source", ())
+            .map_err(Error::from_invoke_error)?;
+        Ok(if cfg!(target_os = "android") {
+            InstallSource::from_android_installer(
+                installation.installer_package_name.as_deref(),
+                installation.debuggable,
+            )
+        } else {
+            InstallSource::from_app_store_environment(
+                installation.environment.as_deref(),
+                installation.debuggable,
+                false,
+            )
+        })
     }
 
     /// Query details for multiple products from the store.
     ///
+    /// Products cached by `preloadProducts` are returned without querying
+    /// the store. Transient failures are retried according to the configured retry policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `product_ids` - List of product identifiers to query, or none to
+    ///   query the products listed in the configuration
+    pub async fn query_product_details(
+        &self,
+        product_ids: Vec<String>,
+    ) -> crate::Result<ProductDetailsResponse> {
+        self.query_product_details_with_policy(product_ids, &self.retry_policy).await
+    }
+
+    /// Query details for multiple products from the store, retrying transient
+    /// failures according to `retry_policy` instead of the configured one.
+    ///
+    /// Products are queried in chunks of 20 product IDs. Only the chunk
+    /// that failed is retried, so products found by earlier chunks are never
+    /// queried or returned twice.
+    ///
     /// # Arguments
     ///
     /// * `product_ids` - List of product identifiers to query
-    pub fn query_product_details(
+    /// * `retry_policy` - Policy used to retry transient failures
+    pub async fn query_product_details_with_policy(
         &self,
         product_ids: Vec<String>,
+        retry_policy: &RetryPolicy,
     ) -> crate::Result<ProductDetailsResponse> {
-        self.0
-            .run_mobile_plugin(
-                "query_product_details",
-                json!({ "productIds": product_ids }),
-            )
-            .map_err(Into::into)
+        let retry_policy = *retry_policy;
+        self.on_worker(move |iap| {
+            iap.query_product_details_with_policy_blocking(product_ids, &retry_policy)
+        })
+        .await
+    }
+
+    fn query_product_details_with_policy_blocking(
+        &self,
+        product_ids: Vec<String>,
+        retry_policy: &RetryPolicy,
+    ) -> crate::Result<ProductDetailsResponse> {
+        self.ensure_initialized()?;
+        self.metrics.product_queried();
+
+        let mut product_ids = if product_ids.is_empty() {
+            self.config.products.clone()
+        } else {
+            product_ids
+        };
+        let mut seen = HashSet::new();
+        product_ids.retain(|id| seen.insert(id.clone()));
+        self.validate_product_ids(&product_ids)?;
+
+        let (cached, missing) = self.products.lookup(&product_ids);
+        let mut key = missing.clone();
+        key.sort();
+        let mut response = self.queries.run(key, || {
+            query_chunks(retry_policy, &self.shutdown(), &missing, |chunk| {
+                self.handle
+                    .run_mobile_plugin("query_product_details", json!({ "productIds": chunk }))
+                    .map_err(Error::from_invoke_error)
+            })
+        })?;
+        response.product_details.splice(0..0, cached);
+        Ok(response)
     }
 
     /// Initiate purchase of a non-consumable product.
     ///
     /// # Arguments
     ///
-    /// * `purchase_param` - Parameters for the purchase
-    pub fn buy_non_consumable(&self, purchase_param: PurchaseParam) -> crate::Result<bool> {
-        self.0
-            .run_mobile_plugin("buy_non_consumable", purchase_param)
-            .map_err(Into::into)
+    /// * `purchase_param` - Parameters for the purchase, using the stored
+    ///   application user name if it has none
+    pub async fn buy_non_consumable(&self, purchase_param: PurchaseParam) -> crate::Result<bool> {
+        self.on_worker(move |iap| iap.buy_non_consumable_blocking(purchase_param)).await
+    }
+
+    fn buy_non_consumable_blocking(&self, purchase_param: PurchaseParam) -> crate::Result<bool> {
+        self.ensure_initialized()?;
+        self.metrics.purchase_flow(
+            |purchased| *purchased,
+            || {
+                self.validate_product_ids(std::slice::from_ref(
+                    &purchase_param.product_details.id,
+                ))?;
+                let purchase_param = self.with_application_user_name(purchase_param);
+                self.buy("buy_non_consumable", json!(purchase_param))
+            },
+        )
     }
 
     /// Initiate purchase of a consumable product.
     ///
     /// # Arguments
     ///
-    /// * `purchase_param` - Parameters for the purchase
+    /// * `purchase_param` - Parameters for the purchase, using the stored
+    ///   application user name if it has none
     /// * `auto_consume` - Whether to automatically consume the purchase after successful transaction
-    pub fn buy_consumable(
+    pub async fn buy_consumable(
         &self,
         purchase_param: PurchaseParam,
         auto_consume: bool,
     ) -> crate::Result<bool> {
-        self.0
+        self.on_worker(move |iap| iap.buy_consumable_blocking(purchase_param, auto_consume)).await
+    }
+
+    fn buy_consumable_blocking(
+        &self,
+        purchase_param: PurchaseParam,
+        auto_consume: bool,
+    ) -> crate::Result<bool> {
+        self.ensure_initialized()?;
+        self.metrics.purchase_flow(
+            |purchased| *purchased,
+            || {
+                self.validate_product_ids(std::slice::from_ref(
+                    &purchase_param.product_details.id,
+                ))?;
+                let purchase_param = self.with_application_user_name(purchase_param);
+                self.buy(
+                    "buy_consumable",
+                    json!({
+                        "purchaseParam": purchase_param,
+                        "autoConsume": auto_consume
+                    }),
+                )
+            },
+        )
+    }
+
+    /// Runs a buy command, returning whether the product was bought.
+    ///
+    /// The App Store returns the purchase, which is recorded and reported
+    /// here. The Android stores return once the flow launched and report the
+    /// purchase through the purchase listener.
+    fn buy(&self, command: &str, args: serde_json::Value) -> crate::Result<bool> {
+        #[cfg(target_os = "ios")]
+        {
+            #[derive(serde::Deserialize)]
+            struct Bought {
+                purchased: bool,
+                purchase: Option<PurchaseDetails>,
+            }
+
+            let bought: Bought = self
+                .handle
+                .run_mobile_plugin(command, args)
+                .map_err(Error::from_invoke_error)?;
+            if let Some(mut purchase) = bought.purchase {
+                self.reporter.record(std::slice::from_mut(&mut purchase));
+                self.reporter.emit(vec![purchase]);
+            }
+            Ok(bought.purchased)
+        }
+        #[cfg(not(target_os = "ios"))]
+        self.handle
+            .run_mobile_plugin(command, args)
+            .map_err(Error::from_invoke_error)
+    }
+
+    /// Switch the user's active subscription to `new_product_id`.
+    ///
+    /// The subscription to replace is found among the current entitlements:
+    /// the one of the same subscription group on iOS, or the only active one
+    /// on Android, which launches the flow with the base plan of the new
+    /// subscription. The new purchase is reported like any other and
+    /// returned, or `None` while it's pending.
+    ///
+    /// # Arguments
+    ///
+    /// * `new_product_id` - The subscription to switch to
+    /// * `replacement_mode` - How Google Play bills the change, ignored by
+    ///   the App Store
+    ///
+    /// # Errors
+    ///
+    /// - `Error::NoActiveSubscription` if the user has no subscription to
+    ///   replace.
+    /// - `Error::AmbiguousSubscription` listing the candidates if several
+    ///   subscriptions could be replaced.
+    /// - `Error::FeatureNotSupported` on the Amazon Appstore, where
+    ///   subscriptions are changed in the store.
+    pub async fn buy_upgrade(
+        &self,
+        new_product_id: String,
+        replacement_mode: ReplacementMode,
+    ) -> crate::Result<Option<PurchaseDetails>> {
+        self.on_worker(move |iap| iap.buy_upgrade_blocking(new_product_id, replacement_mode)).await
+    }
+
+    fn buy_upgrade_blocking(
+        &self,
+        new_product_id: String,
+        replacement_mode: ReplacementMode,
+    ) -> crate::Result<Option<PurchaseDetails>> {
+        self.ensure_initialized()?;
+        self.metrics.purchase_flow(Option::is_some, || {
+            self.upgrade(new_product_id, replacement_mode)
+        })
+    }
+
+    fn upgrade(
+        &self,
+        new_product_id: String,
+        replacement_mode: ReplacementMode,
+    ) -> crate::Result<Option<PurchaseDetails>> {
+        #[derive(serde::Deserialize)]
+        struct Upgrade {
+            purchase: Option<PurchaseDetails>,
+        }
+
+        self.validate_product_ids(std::slice::from_ref(&new_product_id))?;
+        let owned = self.current_entitlements_blocking()?;
+        let products = self
+            .query_product_details_with_policy_blocking(
+                crate::upgrade::products_to_query(&new_product_id, &owned),
+                &self.retry_policy,
+            )?
+            .product_details;
+        let old_purchase = crate::upgrade::subscription_to_replace(&new_product_id, owned, &products)?;
+        let upgrade: Upgrade = self
+            .handle
             .run_mobile_plugin(
-                "buy_consumable",
+                "buy_upgrade",
                 json!({
-                    "purchaseParam": purchase_param,
-                    "autoConsume": auto_consume
+                    "productId": new_product_id,
+                    "oldProductId": old_purchase.product_id,
+                    "oldPurchaseToken": old_purchase.verification_data.server_verification_data,
+                    "replacementMode": replacement_mode,
+                    "applicationUserName": self.application_user_name(),
                 }),
             )
-            .map_err(Into::into)
+            .map_err(Error::from_invoke_error)?;
+        let Some(mut purchase) = upgrade.purchase else {
+            return Ok(None);
+        };
+        self.reporter.record(std::slice::from_mut(&mut purchase));
+        // Android reports the purchase through the purchase listener too.
+        #[cfg(target_os = "ios")]
+        self.reporter.emit(vec![purchase.clone()]);
+        Ok(Some(purchase))
     }
 
     /// Complete a purchase transaction.
     ///
+    /// Unless disabled with `Builder::reject_unknown_purchases`, the purchase
+    /// must have been reported by the store first, with the same transaction ID
+    /// and purchase token. Concurrent calls for the same purchase share a
+    /// single store call and its result.
+    ///
     /// # Arguments
     ///
     /// * `purchase` - Details of the purchase to complete
-    pub fn complete_purchase(&self, purchase: PurchaseDetails) -> crate::Result<()> {
-        self.0
-            .run_mobile_plugin("complete_purchase", purchase)
-            .map_err(Into::into)
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UnknownPurchase` if the store never reported the purchase.
+    pub async fn complete_purchase(&self, purchase: PurchaseDetails) -> crate::Result<()> {
+        self.on_worker(move |iap| iap.complete_purchase_blocking(purchase)).await
+    }
+
+    fn complete_purchase_blocking(&self, purchase: PurchaseDetails) -> crate::Result<()> {
+        self.ensure_initialized()?;
+        let purchase = self.reporter.entitlements.with_verification_data(purchase);
+        self.registry.check(&purchase, self.reject_unknown_purchases)?;
+        self.completions.run(purchase.identifiers(), || {
+            retry(&self.retry_policy, &self.shutdown(), || {
+                self.handle
+                    .run_mobile_plugin("complete_purchase", &purchase)
+                    .map_err(Error::from_invoke_error)
+            })?;
+            self.reporter.unfinished.remove(&purchase);
+            Ok(())
+        })
+    }
+
+    /// Completes a purchase once `verify` accepted it, e.g. after the app's
+    /// server validated its verification data.
+    ///
+    /// Completing is retried like [`complete_purchase`](Self::complete_purchase).
+    /// A purchase failing verification is left unfinished and kept in the
+    /// unfinished purchases, so it's reported again after a restart until
+    /// it's completed.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `verify`, or of [`complete_purchase`](Self::complete_purchase).
+    pub async fn finalize_after<F, Fut>(
+        &self,
+        purchase: PurchaseDetails,
+        verify: F,
+    ) -> crate::Result<()>
+    where
+        F: FnOnce(PurchaseDetails) -> Fut,
+        Fut: Future<Output = crate::Result<()>>,
+    {
+        if let Err(e) = verify(purchase.clone()).await {
+            log::warn!(
+                "Leaving the purchase of {} unfinished, its verification failed: {}",
+                purchase.product_id,
+                e
+            );
+            self.reporter
+                .unfinished
+                .update(std::slice::from_ref(&purchase));
+            return Err(e);
+        }
+        self.complete_purchase(purchase).await
     }
 
     /// Restore previously purchased items.
     ///
     /// # Arguments
     ///
-    /// * `application_user_name` - Optional user identifier for the restoration
-    pub fn restore_purchases(&self, application_user_name: Option<String>) -> crate::Result<()> {
-        self.0
+    /// * `application_user_name` - Optional user identifier for the restoration,
+    ///   or else the stored one
+    ///
+    /// Owned purchases are emitted again with the `Restored` status, followed
+    /// by a [`RESTORE_COMPLETED_EVENT`](crate::RESTORE_COMPLETED_EVENT). iOS
+    /// 15+ syncs with `AppStore.sync()`, which may ask the user to sign in,
+    /// and reports the current entitlements, ignoring the user name; older
+    /// versions restore the completed transactions made with it. Google Play
+    /// reports the owned purchases.
+    ///
+    /// Concurrent restores for the same user share a single native restore,
+    /// and restores within `restoreCooldownMs` of a successful one return
+    /// without restoring again.
+    pub async fn restore_purchases(
+        &self,
+        application_user_name: Option<String>,
+    ) -> crate::Result<()> {
+        self.on_worker(move |iap| iap.restore_purchases_blocking(application_user_name)).await
+    }
+
+    fn restore_purchases_blocking(&self, application_user_name: Option<String>) -> crate::Result<()> {
+        self.ensure_initialized()?;
+        self.metrics.restore_started();
+        let application_user_name = application_user_name.or_else(|| self.application_user_name());
+        self.restores
+            .run(application_user_name.clone(), || self.restore(application_user_name))
+    }
+
+    fn restore(&self, application_user_name: Option<String>) -> crate::Result<()> {
+        #[derive(serde::Deserialize)]
+        struct Restored {
+            purchases: Vec<PurchaseDetails>,
+        }
+
+        let restored: Restored = self
+            .handle
             .run_mobile_plugin(
                 "restore_purchases",
                 json!({ "applicationUserName": application_user_name }),
             )
-            .map_err(Into::into)
+            .map_err(Error::from_invoke_error)?;
+        let mut purchases = restored.purchases;
+        self.reporter.record(&mut purchases);
+        // Only a restore of every user's purchases lists all owned products.
+        if application_user_name.is_none() {
+            self.reporter.entitlements.replace(&purchases);
+        }
+        let completed = RestoreCompleted {
+            restored_count: purchases.len(),
+        };
+        self.reporter.emit(purchases);
+        self.reporter
+            .streams
+            .send(PurchaseUpdate::RestoreCompleted(completed.clone()));
+        if let Err(e) = self
+            .config
+            .emit(&self.reporter.app, crate::RESTORE_COMPLETED_EVENT, completed)
+        {
+            log::error!("Failed to emit restore completed: {}", e);
+        }
+        Ok(())
     }
 
     /// Get the store country/region code.
-    pub fn country_code(&self) -> crate::Result<String> {
-        self.0
+    pub async fn country_code(&self) -> crate::Result<String> {
+        self.on_worker(move |iap| iap.country_code_blocking()).await
+    }
+
+    fn country_code_blocking(&self) -> crate::Result<String> {
+        self.ensure_initialized()?;
+        self.country_code.get_or_fetch(|| self.fetch_country_code())
+    }
+
+    /// Fetches the country code again, bypassing and updating the cache.
+    pub async fn refresh_country_code(&self) -> crate::Result<String> {
+        self.on_worker(move |iap| iap.refresh_country_code_blocking()).await
+    }
+
+    fn refresh_country_code_blocking(&self) -> crate::Result<String> {
+        self.ensure_initialized()?;
+        self.country_code.refresh(|| self.fetch_country_code())
+    }
+
+    fn fetch_country_code(&self) -> crate::Result<String> {
+        self.handle
             .run_mobile_plugin("country_code", ())
-            .map_err(Into::into)
+            .map_err(Error::from_invoke_error)
+    }
+
+    /// Check if the store supports a feature on this device, e.g. subscription
+    /// upgrades, which older Play Store versions don't.
+    ///
+    /// Google Play asks the billing client, iOS answers from the OS version.
+    pub async fn is_feature_supported(&self, feature: StoreFeature) -> crate::Result<bool> {
+        self.on_worker(move |iap| iap.is_feature_supported_blocking(feature)).await
+    }
+
+    fn is_feature_supported_blocking(&self, feature: StoreFeature) -> crate::Result<bool> {
+        #[derive(serde::Deserialize)]
+        struct Support {
+            supported: bool,
+        }
+
+        self.ensure_initialized()?;
+        let support: Support = self
+            .handle
+            .run_mobile_plugin("is_feature_supported", json!({ "feature": feature }))
+            .map_err(Error::from_invoke_error)?;
+        Ok(support.supported)
     }
+
+    /// Check if the user can buy through Alternative Billing Only, which
+    /// Google Play offers in some markets.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidConfig` unless `alternativeBillingOnly` is
+    /// enabled in the configuration, and `Error::FeatureNotSupported` on iOS.
+    pub async fn is_alternative_billing_available(&self) -> crate::Result<bool> {
+        self.on_worker(move |iap| iap.is_alternative_billing_available_blocking()).await
+    }
+
+    fn is_alternative_billing_available_blocking(&self) -> crate::Result<bool> {
+        self.ensure_alternative_billing()?;
+        self.handle
+            .run_mobile_plugin("is_alternative_billing_available", ())
+            .map_err(Error::from_invoke_error)
+    }
+
+    /// Show the information dialog Google Play requires before the first
+    /// alternative billing purchase.
+    ///
+    /// # Returns
+    ///
+    /// Returns true if the user acknowledged the dialog, now or before, and
+    /// false if they dismissed it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidConfig` unless `alternativeBillingOnly` is
+    /// enabled in the configuration, and `Error::FeatureNotSupported` on iOS.
+    pub async fn show_alternative_billing_dialog(&self) -> crate::Result<bool> {
+        self.on_worker(move |iap| iap.show_alternative_billing_dialog_blocking()).await
+    }
+
+    fn show_alternative_billing_dialog_blocking(&self) -> crate::Result<bool> {
+        self.ensure_alternative_billing()?;
+        self.handle
+            .run_mobile_plugin("show_alternative_billing_dialog", ())
+            .map_err(Error::from_invoke_error)
+    }
+
+    /// Create the token reporting a purchase made through alternative billing
+    /// to Google Play, to be sent from the app's server within 24 hours.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidConfig` unless `alternativeBillingOnly` is
+    /// enabled in the configuration, and `Error::FeatureNotSupported` on iOS.
+    pub async fn create_alternative_billing_token(
+        &self,
+    ) -> crate::Result<AlternativeBillingReportingDetails> {
+        self.on_worker(move |iap| iap.create_alternative_billing_token_blocking()).await
+    }
+
+    fn create_alternative_billing_token_blocking(
+        &self,
+    ) -> crate::Result<AlternativeBillingReportingDetails> {
+        self.ensure_alternative_billing()?;
+        self.handle
+            .run_mobile_plugin("create_alternative_billing_token", ())
+            .map_err(Error::from_invoke_error)
+    }
+
+    /// Fails unless alternative billing can be used: on Android, with
+    /// `alternativeBillingOnly` configured, once initialized.
+    fn ensure_alternative_billing(&self) -> crate::Result<()> {
+        if cfg!(not(target_os = "android")) {
+            return Err(Error::FeatureNotSupported(
+                "Alternative billing only is a Google Play program".into(),
+            ));
+        }
+        if !self.config.alternative_billing_only {
+            return Err(Error::InvalidConfig(
+                "alternativeBillingOnly must be enabled in the iap plugin configuration".into(),
+            ));
+        }
+        self.ensure_initialized()
+    }
+
+    /// Check if the app can send the user to an external purchase link, which
+    /// requires one of the App Store external purchase entitlements.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported` on Android, before iOS 17.4 or
+    /// without the entitlement.
+    pub async fn external_purchase_available(&self) -> crate::Result<bool> {
+        self.on_worker(move |iap| iap.external_purchase_available_blocking()).await
+    }
+
+    fn external_purchase_available_blocking(&self) -> crate::Result<bool> {
+        #[derive(serde::Deserialize)]
+        struct Availability {
+            available: bool,
+        }
+
+        ensure_external_purchase()?;
+        let availability: Availability = self
+            .handle
+            .run_mobile_plugin("external_purchase_available", ())
+            .map_err(Error::from_invoke_error)?;
+        Ok(availability.available)
+    }
+
+    /// Show the App Store notice sheet and, if the user continues, open `url`
+    /// to purchase outside of the App Store.
+    ///
+    /// # Returns
+    ///
+    /// Returns true if the user continued to the link, false if they
+    /// cancelled.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported` on Android, before iOS 17.4 or
+    /// without the entitlement.
+    pub async fn open_external_purchase_link(&self, url: &str) -> crate::Result<bool> {
+        let url = url.to_owned();
+        self.on_worker(move |iap| iap.open_external_purchase_link_blocking(&url)).await
+    }
+
+    fn open_external_purchase_link_blocking(&self, url: &str) -> crate::Result<bool> {
+        ensure_external_purchase()?;
+        let result: ExternalPurchaseLinkResult = self
+            .handle
+            .run_mobile_plugin("open_external_purchase_link", json!({ "url": url }))
+            .map_err(Error::from_invoke_error)?;
+        Ok(result.continued)
+    }
+
+    /// Get the token to report the external purchase the user continued to
+    /// with [`Iap::open_external_purchase_link`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::DeveloperError` if the user never continued to a link,
+    /// and `Error::FeatureNotSupported` on Android, before iOS 17.4 or
+    /// without the entitlement.
+    pub async fn external_purchase_token(&self) -> crate::Result<ExternalPurchaseToken> {
+        self.on_worker(move |iap| iap.external_purchase_token_blocking()).await
+    }
+
+    fn external_purchase_token_blocking(&self) -> crate::Result<ExternalPurchaseToken> {
+        ensure_external_purchase()?;
+        self.handle
+            .run_mobile_plugin("external_purchase_token", ())
+            .map_err(Error::from_invoke_error)
+    }
+
+    /// Get the license of the app: the `android_app_license` configuration
+    /// on Android, the app transaction on iOS.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported` on Android without
+    /// `android_app_license` and before iOS 16.
+    pub async fn app_license(&self) -> crate::Result<AppLicense> {
+        self.on_worker(move |iap| iap.app_license_blocking()).await
+    }
+
+    fn app_license_blocking(&self) -> crate::Result<AppLicense> {
+        self.ensure_initialized()?;
+        #[cfg(target_os = "android")]
+        return self.config.android_app_license.clone().ok_or_else(|| {
+            Error::FeatureNotSupported(
+                "Google Play checks app licenses through the License Verification Library, set androidAppLicense"
+                    .into(),
+            )
+        });
+        #[cfg(target_os = "ios")]
+        {
+            #[derive(serde::Deserialize)]
+            #[serde(rename_all = "camelCase")]
+            struct AppTransaction {
+                verified: bool,
+                original_app_version: String,
+                original_purchase_date: String,
+            }
+
+            let transaction: AppTransaction = self
+                .handle
+                .run_mobile_plugin("app_license", ())
+                .map_err(Error::from_invoke_error)?;
+            Ok(AppLicense::from_app_transaction(
+                transaction.verified,
+                transaction.original_app_version,
+                transaction.original_purchase_date,
+            ))
+        }
+    }
+
+    /// Get the base64 encoded App Store receipt, for backends validating the
+    /// legacy receipt. With `refresh`, a missing receipt is requested from
+    /// the App Store first, which may ask the user to sign in.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ReceiptUnavailable` if there is still no receipt, or
+    /// `Error::FeatureNotSupported` on Android.
+    pub async fn get_app_receipt(&self, refresh: bool) -> crate::Result<String> {
+        self.on_worker(move |iap| iap.get_app_receipt_blocking(refresh)).await
+    }
+
+    fn get_app_receipt_blocking(&self, refresh: bool) -> crate::Result<String> {
+        #[derive(serde::Deserialize)]
+        struct Receipt {
+            receipt: String,
+        }
+
+        if cfg!(target_os = "android") {
+            return Err(Error::FeatureNotSupported(
+                "App receipts are only issued by the App Store".into(),
+            ));
+        }
+        self.ensure_initialized()?;
+        let receipt: Receipt = self
+            .handle
+            .run_mobile_plugin("get_app_receipt", json!({ "refresh": refresh }))
+            .map_err(Error::from_invoke_error)?;
+        Ok(receipt.receipt)
+    }
+
+    /// Get the state of a subscription the user bought.
+    ///
+    /// iOS 15+ reads the StoreKit 2 subscription status, which tells the
+    /// grace period and billing retry apart. Google Play only reports the
+    /// subscriptions that grant access, with no sign of a grace period, so
+    /// Android reports them as active and those on hold as not owned.
+    ///
+    /// # Errors
+    ///
+    /// - `Error::ItemNotOwned` if the user never bought the subscription, or
+    ///   on Android if it's on hold or expired.
+    /// - `Error::FeatureNotSupported` before iOS 15.
+    pub async fn subscription_status(&self, product_id: &str) -> crate::Result<SubscriptionStatus> {
+        let product_id = product_id.to_owned();
+        self.on_worker(move |iap| iap.subscription_status_blocking(&product_id)).await
+    }
+
+    fn subscription_status_blocking(&self, product_id: &str) -> crate::Result<SubscriptionStatus> {
+        self.ensure_initialized()?;
+        self.handle
+            .run_mobile_plugin("subscription_status", json!({ "productId": product_id }))
+            .map_err(Error::from_invoke_error)
+    }
+
+    /// Time left on a subscription the user bought, e.g. for a "3 days left
+    /// in your trial" banner: until the end of the grace period while in
+    /// one, otherwise until the end of the current period, and zero once
+    /// access ended. `None` for products that aren't subscriptions, i.e.
+    /// preloaded as such or owned through a purchase that never ends, and
+    /// for subscriptions the store reports no end for.
+    ///
+    /// The status is queried from the store on every call, so renewals made
+    /// since are taken into account.
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`Iap::subscription_status`].
+    pub async fn subscription_time_remaining(
+        &self,
+        product_id: &str,
+    ) -> crate::Result<Option<Duration>> {
+        self.subscription_time_remaining_at(product_id, SystemTime::now()).await
+    }
+
+    /// [`Iap::subscription_time_remaining`] at `now` instead of the current
+    /// time, e.g. in tests.
+    pub async fn subscription_time_remaining_at(
+        &self,
+        product_id: &str,
+        now: SystemTime,
+    ) -> crate::Result<Option<Duration>> {
+        let product_id = product_id.to_owned();
+        self.on_worker(move |iap| iap.subscription_time_remaining_at_blocking(&product_id, now))
+            .await
+    }
+
+    fn subscription_time_remaining_at_blocking(
+        &self,
+        product_id: &str,
+        now: SystemTime,
+    ) -> crate::Result<Option<Duration>> {
+        let (known, _) = self.products.lookup(&[product_id.to_string()]);
+        if known.iter().any(|product| !product.is_subscription()) {
+            return Ok(None);
+        }
+        match self.subscription_status_blocking(product_id) {
+            Ok(status) => Ok(status.time_remaining(now)),
+            // Stores don't know a status for products that aren't
+            // subscriptions, e.g. a lifetime unlock that wasn't preloaded.
+            Err(Error::ItemNotOwned(_)) if self.reporter.entitlements.owns_for_life(product_id) => {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Open the store page where the user manages their subscriptions.
+    ///
+    /// iOS presents the App Store sheet in the app from the main actor and
+    /// returns once it's dismissed, Android opens the Google Play
+    /// subscriptions page of the app.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported` before iOS 15, or if no app can
+    /// open the Google Play page.
+    pub async fn open_manage_subscriptions(&self) -> crate::Result<()> {
+        self.on_worker(move |iap| iap.open_manage_subscriptions_blocking()).await
+    }
+
+    fn open_manage_subscriptions_blocking(&self) -> crate::Result<()> {
+        self.ensure_initialized()?;
+        self.handle
+            .run_mobile_plugin::<serde_json::Value>("open_manage_subscriptions", ())
+            .map(|_| ())
+            .map_err(Error::from_invoke_error)
+    }
+
+    /// Show the sheet asking the user to consent to a subscription price
+    /// increase, if they still have to, at a moment the app chooses.
+    ///
+    /// Only iOS asks for consent; Google Play notifies users of price changes
+    /// itself, so this returns `false` on Android. With
+    /// `defer_price_consent`, StoreKit's own attempts to show the sheet are
+    /// held back, and this returns whether one was, i.e. whether consent is
+    /// pending. Without it, iOS doesn't tell and this returns `false`.
+    pub async fn show_price_consent_if_needed(&self) -> crate::Result<bool> {
+        self.on_worker(move |iap| iap.show_price_consent_if_needed_blocking()).await
+    }
+
+    fn show_price_consent_if_needed_blocking(&self) -> crate::Result<bool> {
+        #[cfg(target_os = "android")]
+        return Ok(false);
+
+        #[cfg(target_os = "ios")]
+        {
+            #[derive(serde::Deserialize)]
+            struct Consent {
+                pending: bool,
+            }
+
+            let consent: Consent = self
+                .handle
+                .run_mobile_plugin("show_price_consent_if_needed", ())
+                .map_err(Error::from_invoke_error)?;
+            Ok(consent.pending)
+        }
+    }
+
+    /// Open the store page of an app, or of this app when `product_id` is
+    /// `None`. IDs are package names on Android and numeric App Store IDs on
+    /// iOS, where this app's page needs the `app_store_id` configuration.
+    ///
+    /// iOS presents the App Store sheet in the app and falls back to opening
+    /// the App Store, Android opens the Play Store and falls back to the
+    /// browser. Returns once the page is shown.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::FeatureNotSupported` if no app can show the page, and
+    /// `Error::InvalidConfig` on iOS without a product ID or `app_store_id`.
+    pub async fn open_store_product_page(&self, product_id: Option<&str>) -> crate::Result<()> {
+        let product_id = product_id.map(str::to_owned);
+        self.on_worker(move |iap| iap.open_store_product_page_blocking(product_id.as_deref()))
+            .await
+    }
+
+    fn open_store_product_page_blocking(&self, product_id: Option<&str>) -> crate::Result<()> {
+        self.ensure_initialized()?;
+        let product_id = if cfg!(target_os = "ios") {
+            let app_store_id = product_id.or(self.config.app_store_id.as_deref());
+            Some(app_store_id.ok_or_else(|| {
+                Error::InvalidConfig(
+                    "Set appStoreId to open the app's App Store page without a product ID".into(),
+                )
+            })?)
+        } else {
+            product_id
+        };
+        self.handle
+            .run_mobile_plugin::<serde_json::Value>(
+                "open_store_product_page",
+                json!({ "productId": product_id }),
+            )
+            .map(|_| ())
+            .map_err(Error::from_invoke_error)
+    }
+}
+
+/// Fails on Android, the App Store external purchase APIs being iOS only. The
+/// iOS side checks the entitlement.
+fn ensure_external_purchase() -> crate::Result<()> {
+    if cfg!(target_os = "android") {
+        return Err(external_purchase_unsupported());
+    }
+    Ok(())
+}
+
+fn external_purchase_unsupported() -> Error {
+    Error::FeatureNotSupported("External purchase links are an App Store program".into())
 }
 
 #[cfg(target_os = "android")]
 #[allow(non_snake_case)]
 pub mod android {
     use super::*;
-    use jni::sys::jobject;
+    use crate::purchase_feed::PurchaseFeed;
+    use std::panic::AssertUnwindSafe;
+    use std::sync::OnceLock;
+
+    type ConnectionListener = Box<dyn Fn(ConnectionState, Option<u32>) + Send + Sync>;
+    type ErrorListener = Box<dyn Fn(IAPError) + Send + Sync>;
+
+    /// Receives the payloads of `onPurchaseUpdate`, started with the purchase
+    /// listener.
+    static PURCHASE_FEED: OnceLock<PurchaseFeed> = OnceLock::new();
+
+    /// Receives the connection states reported through
+    /// `onConnectionStateChanged`, registered by `init` like the purchase
+    /// listener.
+    static CONNECTION_LISTENER: OnceLock<ConnectionListener> = OnceLock::new();
+
+    /// Called on the locale changes reported through `onLocaleChanged`.
+    static LOCALE_LISTENER: OnceLock<Arc<StorefrontChanged>> = OnceLock::new();
 
+    /// Receives the failures reported through `handleError`.
+    static ERROR_LISTENER: OnceLock<ErrorListener> = OnceLock::new();
+
+    pub(crate) fn set_connection_listener(
+        listener: impl Fn(ConnectionState, Option<u32>) + Send + Sync + 'static,
+    ) {
+        if CONNECTION_LISTENER.set(Box::new(listener)).is_err() {
+            log::warn!("Connection listener already registered, ignoring");
+        }
+    }
+
+    pub(crate) fn set_locale_listener(listener: Arc<StorefrontChanged>) {
+        if LOCALE_LISTENER.set(listener).is_err() {
+            log::warn!("Locale listener already registered, ignoring");
+        }
+    }
+
+    pub(crate) fn set_error_listener(listener: impl Fn(IAPError) + Send + Sync + 'static) {
+        if ERROR_LISTENER.set(Box::new(listener)).is_err() {
+            log::warn!("Error listener already registered, ignoring");
+        }
+    }
+
+    /// Starts the worker receiving the purchases reported through
+    /// `onPurchaseUpdate`. The JNI callback has no access to the app, so
+    /// `init` registers this once at setup.
+    pub(crate) fn set_purchase_listener(
+        listener: impl Fn(Vec<PurchaseDetails>) + Send + 'static,
+    ) {
+        if PURCHASE_FEED.get().is_some() {
+            log::warn!("Purchase listener already registered, ignoring");
+            return;
+        }
+        match PurchaseFeed::start(listener) {
+            Ok(feed) => {
+                if PURCHASE_FEED.set(feed).is_err() {
+                    log::warn!("Purchase listener already registered, ignoring");
+                }
+            }
+            Err(e) => log::error!("Failed to start the purchase update worker: {}", e),
+        }
+    }
+
+    /// Reads a string argument of a JNI callback, which must not panic.
+    fn read_string(env: &mut JNIEnv, string: &JString) -> Option<String> {
+        env.get_string(string)
+            .inspect_err(|e| log::error!("Failed to read a string from Java: {}", e))
+            .ok()
+            .map(Into::into)
+    }
+
+    /// Called by `IapBridge` with the purchases the store reported as JSON.
+    /// Only queues the payload for the worker.
     #[no_mangle]
-    pub extern "system" fn Java_com_plugin_iap_IapPlugin_onPurchaseUpdate(
-        env: JNIEnv,
+    pub extern "system" fn Java_com_plugin_iap_IapBridge_onPurchaseUpdate(
+        mut env: JNIEnv,
         _class: JClass,
         purchases_json: JString,
     ) {
-        let purchases_str: String = env
-            .get_string(purchases_json)
-            .expect("Couldn't get java string!")
-            .into();
+        let Some(payload) = read_string(&mut env, &purchases_json) else {
+            return;
+        };
+        match PURCHASE_FEED.get() {
+            Some(feed) => {
+                if !feed.push(payload) {
+                    log::error!("Purchase update worker stopped, dropping purchases");
+                }
+            }
+            None => log::warn!("No purchase listener registered, dropping purchases"),
+        }
+    }
 
-        let purchases: Vec<PurchaseDetails> =
-            serde_json::from_str(&purchases_str).expect("Failed to parse purchase details");
+    /// Called by the billing client's connection callbacks with a camelCase
+    /// [`ConnectionState`], and the reconnection attempt or 0.
+    #[no_mangle]
+    pub extern "system" fn Java_com_plugin_iap_IapBridge_onConnectionStateChanged(
+        mut env: JNIEnv,
+        _class: JClass,
+        state: JString,
+        attempt: jni::sys::jint,
+    ) {
+        let Some(state) = read_string(&mut env, &state) else {
+            return;
+        };
 
-        // Here we would emit the purchase update event to the Tauri event system
-        // This needs to be implemented based on how Tauri handles plugin events
+        match serde_json::from_value(serde_json::Value::String(state)) {
+            Ok(state) => {
+                if let Some(listener) = CONNECTION_LISTENER.get() {
+                    let attempt = u32::try_from(attempt).ok().filter(|&n| n > 0);
+                    // Must not unwind into Java.
+                    if std::panic::catch_unwind(AssertUnwindSafe(|| listener(state, attempt)))
+                        .is_err()
+                    {
+                        log::error!("Reporting a connection state change panicked");
+                    }
+                }
+            }
+            Err(e) => log::error!("Unknown connection state: {}", e),
+        }
+    }
+
+    /// Called when the activity's configuration changed to another locale,
+    /// which localizes product titles and prices.
+    #[no_mangle]
+    pub extern "system" fn Java_com_plugin_iap_IapBridge_onLocaleChanged(
+        _env: JNIEnv,
+        _class: JClass,
+    ) {
+        if let Some(listener) = LOCALE_LISTENER.get() {
+            // Must not unwind into Java.
+            if std::panic::catch_unwind(AssertUnwindSafe(|| listener())).is_err() {
+                log::error!("Reporting a locale change panicked");
+            }
+        }
     }
 
+    /// Called with the JSON body of a failure no command returns, e.g. a
+    /// purchase the billing client reported as failed.
     #[no_mangle]
-    pub extern "system" fn Java_com_plugin_iap_IapPlugin_handleError(
-        env: JNIEnv,
+    pub extern "system" fn Java_com_plugin_iap_IapBridge_handleError(
+        mut env: JNIEnv,
         _class: JClass,
         error_json: JString,
     ) {
-        let error_str: String = env
-            .get_string(error_json)
-            .expect("Couldn't get java string!")
-            .into();
+        let Some(error_str) = read_string(&mut env, &error_json) else {
+            return;
+        };
 
-        // Here we would handle the error, possibly by emitting an error event
-        // This needs to be implemented based on how Tauri handles plugin errors
+        let error = match serde_json::from_str::<IAPError>(&error_str) {
+            Ok(error) => error,
+            Err(e) => IAPError::from(&Error::InternalError(format!("Malformed error payload: {e}"))),
+        };
+        log::error!("Billing error ({}): {}", error.code, error.message);
+        if let Some(listener) = ERROR_LISTENER.get() {
+            // Must not unwind into Java.
+            if std::panic::catch_unwind(AssertUnwindSafe(|| listener(error))).is_err() {
+                log::error!("Reporting a billing error panicked");
+            }
+        }
     }
 }
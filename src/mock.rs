@@ -0,0 +1,1334 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::{BuildHasher, Hasher};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::desktop::{locale_country_code, LocaleProvider};
+use crate::models::*;
+use crate::time::now_ms;
+use crate::{Config, DesktopIapBackend, Diagnostics, Error, IapErrorCode, PurchaseEventSink, Result};
+
+/// Verification source reported for purchases made in the mock store.
+pub const MOCK_SOURCE: &str = "mock";
+
+/// Name of the file in the app data directory that keeps mock purchases
+/// across restarts.
+pub const MOCK_PURCHASES_FILE: &str = "iap-mock-purchases.json";
+
+/// Environment variable forcing the mock store on (`1`) or off (`0`),
+/// overriding a custom desktop backend or the platform store.
+pub const MOCK_ENV: &str = "TAURI_IAP_MOCK";
+
+/// Environment variable with the path of a JSON file holding the mock
+/// catalog, an array of [`ProductDetails`].
+pub const MOCK_CATALOG_ENV: &str = "TAURI_IAP_MOCK_CATALOG";
+
+/// Environment variable with a comma separated list of product IDs the user
+/// owns in the mock store.
+pub const MOCK_OWNED_ENV: &str = "TAURI_IAP_MOCK_OWNED";
+
+/// Environment variable with the latency the mock store simulates, in the
+/// format of [`MockLatency`]'s `FromStr` implementation.
+pub const MOCK_LATENCY_ENV: &str = "TAURI_IAP_MOCK_LATENCY";
+
+/// Delay the mock store waits before answering queries, purchases and
+/// restores, like the real stores do, to surface loading state bugs.
+///
+/// Parses from milliseconds, either fixed (`"500"`) or a random range
+/// (`"300-3000"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MockLatency {
+    /// Shortest delay in milliseconds.
+    pub min_ms: u64,
+    /// Longest delay in milliseconds.
+    pub max_ms: u64,
+}
+
+impl MockLatency {
+    /// Always waits for `delay`.
+    pub fn fixed(delay: Duration) -> Self {
+        Self::between(delay, delay)
+    }
+
+    /// Waits for a random delay between `min` and `max`.
+    pub fn between(min: Duration, max: Duration) -> Self {
+        let (min_ms, max_ms) = (min.as_millis() as u64, max.as_millis() as u64);
+        Self {
+            min_ms: min_ms.min(max_ms),
+            max_ms: min_ms.max(max_ms),
+        }
+    }
+
+    /// Picks the delay for one operation.
+    fn sample(&self) -> Duration {
+        let (min, max) = (self.min_ms.min(self.max_ms), self.min_ms.max(self.max_ms));
+        // The randomly keyed std hasher is random enough to jitter a delay.
+        let random = RandomState::new().build_hasher().finish();
+        Duration::from_millis(min + random % (max - min).saturating_add(1))
+    }
+}
+
+impl FromStr for MockLatency {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |ms: &str| {
+            ms.trim().parse::<u64>().map(Duration::from_millis).map_err(|_| {
+                Error::InvalidConfig(format!(
+                    "mock latency must be milliseconds like 500 or 300-3000, got {s:?}"
+                ))
+            })
+        };
+        match s.split_once('-') {
+            Some((min, max)) => Ok(Self::between(parse(min)?, parse(max)?)),
+            None => Ok(Self::fixed(parse(s)?)),
+        }
+    }
+}
+
+/// Mock store settings collected by the plugin builder.
+#[derive(Default)]
+pub(crate) struct MockOptions {
+    /// Whether the mock store serves calls, or `None` to use it only when no
+    /// custom or bundled store backend is available.
+    pub(crate) enabled: Option<bool>,
+    pub(crate) catalog: Vec<ProductDetails>,
+    /// Product IDs the user owns from the start.
+    pub(crate) owned: Vec<String>,
+    /// Overrides the default [`MOCK_PURCHASES_FILE`] in the app data directory.
+    pub(crate) storage_path: Option<PathBuf>,
+    /// Approves purchases without the confirmation dialog, e.g. on CI.
+    pub(crate) headless: bool,
+    pub(crate) latency: Option<MockLatency>,
+}
+
+/// Operations of the mock store that failures can be injected into, named
+/// like the plugin commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MockOperation {
+    Initialize,
+    IsAvailable,
+    QueryProductDetails,
+    BuyNonConsumable,
+    BuyConsumable,
+    BuyUpgrade,
+    CompletePurchase,
+    RestorePurchases,
+    CountryCode,
+    SubscriptionStatus,
+    IsFeatureSupported,
+    OpenStoreProductPage,
+}
+
+/// A call made to the mock store, as listed by [`MockStore::calls`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MockCall {
+    pub operation: MockOperation,
+    /// The arguments, serialized like the command arguments, or null.
+    pub payload: serde_json::Value,
+}
+
+/// A failure injected into the mock store with [`MockStore::inject_failure`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MockFailure {
+    /// The operation that fails.
+    pub operation: MockOperation,
+    /// The code of the error it fails with.
+    pub code: IapErrorCode,
+    /// Only fail calls involving this product, or every call if `None`.
+    #[serde(default)]
+    pub product_id: Option<String>,
+    /// Keep failing until cleared, instead of failing the next call only.
+    #[serde(default)]
+    pub persistent: bool,
+}
+
+impl MockFailure {
+    /// Fails the next call of `operation` with an error of `code`.
+    pub fn new(operation: MockOperation, code: IapErrorCode) -> Self {
+        Self {
+            operation,
+            code,
+            product_id: None,
+            persistent: false,
+        }
+    }
+
+    /// Only fails calls involving `product_id`.
+    pub fn for_product(mut self, product_id: impl Into<String>) -> Self {
+        self.product_id = Some(product_id.into());
+        self
+    }
+
+    /// Fails every call until [`MockStore::clear_failures`] is called.
+    pub fn persistent(mut self) -> Self {
+        self.persistent = true;
+        self
+    }
+}
+
+/// Asks the user to approve the purchase of a product, returning `false` if
+/// they cancelled.
+type ConfirmPurchase = dyn Fn(&ProductDetails) -> Result<bool> + Send + Sync;
+
+impl MockOptions {
+    /// Adds the products declared in `mockProducts` of the plugin
+    /// configuration to the catalog and applies its `mock` switch.
+    pub(crate) fn with_config(mut self, config: &Config) -> Result<Self> {
+        if config.mock.is_some() {
+            self.enabled = config.mock;
+        }
+        for (index, entry) in config.mock_products.iter().enumerate() {
+            let product = MockProduct::deserialize(entry)
+                .map_err(|e| e.to_string())
+                .and_then(MockProduct::into_details)
+                .map_err(|reason| {
+                    let name = entry
+                        .get("id")
+                        .and_then(|id| id.as_str())
+                        .map_or_else(|| format!("#{index}"), |id| format!("\"{id}\""));
+                    Error::InvalidConfig(format!("mock product {name}: {reason}"))
+                })?;
+            self.catalog.push(product);
+        }
+        if let Some(latency) = &config.mock_latency {
+            self.latency = Some(latency.parse()?);
+        }
+        Ok(self)
+    }
+
+    /// Applies the [`MOCK_ENV`], [`MOCK_CATALOG_ENV`] and [`MOCK_OWNED_ENV`]
+    /// environment variables, which take precedence over the builder.
+    pub(crate) fn with_env(mut self) -> Result<Self> {
+        if let Some(value) = std::env::var_os(MOCK_ENV) {
+            self.enabled = match value.to_str() {
+                Some("1" | "true") => Some(true),
+                Some("0" | "false") => Some(false),
+                _ => {
+                    return Err(Error::InvalidConfig(format!(
+                        "{MOCK_ENV} must be 1 or 0, got {value:?}"
+                    )))
+                }
+            };
+        }
+        if let Some(path) = std::env::var_os(MOCK_CATALOG_ENV) {
+            let path = PathBuf::from(path);
+            let json = std::fs::read(&path).map_err(|e| {
+                Error::InvalidConfig(format!(
+                    "Failed to read the mock catalog {}: {e}",
+                    path.display()
+                ))
+            })?;
+            self.catalog = serde_json::from_slice(&json).map_err(|e| {
+                Error::InvalidConfig(format!(
+                    "Invalid mock catalog {}: {e}",
+                    path.display()
+                ))
+            })?;
+        }
+        if let Ok(latency) = std::env::var(MOCK_LATENCY_ENV) {
+            self.latency = Some(latency.parse()?);
+        }
+        if let Ok(owned) = std::env::var(MOCK_OWNED_ENV) {
+            self.owned = owned
+                .split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(String::from)
+                .collect();
+        }
+        Ok(self)
+    }
+}
+
+/// Kind of a product declared in `mockProducts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum MockProductType {
+    Consumable,
+    NonConsumable,
+    Subscription,
+}
+
+/// A product declared in `mockProducts` of the plugin configuration.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct MockProduct {
+    id: String,
+    title: String,
+    #[serde(default)]
+    description: String,
+    /// Price in the currency's major unit, e.g. `4.99`.
+    price: f64,
+    /// ISO 4217 currency code.
+    currency: String,
+    #[serde(rename = "type")]
+    product_type: MockProductType,
+    /// ISO 8601 duration of a subscription period, e.g. `P1M`.
+    subscription_period: Option<String>,
+}
+
+impl MockProduct {
+    fn into_details(self) -> std::result::Result<ProductDetails, String> {
+        if self.id.trim().is_empty() {
+            return Err("id must not be empty".into());
+        }
+        if !self.price.is_finite() || self.price < 0.0 {
+            return Err(format!("invalid price {}", self.price));
+        }
+        if self.currency.len() != 3 || !self.currency.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(format!(
+                "currency must be an ISO 4217 code like USD, got {:?}",
+                self.currency
+            ));
+        }
+        match (self.product_type, &self.subscription_period) {
+            (MockProductType::Subscription, None) => {
+                return Err("subscriptions need a subscriptionPeriod".into())
+            }
+            (MockProductType::Subscription, Some(period))
+                if !period.starts_with('P') || period.len() < 3 =>
+            {
+                return Err(format!(
+                    "subscriptionPeriod must be an ISO 8601 duration like P1M, got {period:?}"
+                ))
+            }
+            (MockProductType::Consumable | MockProductType::NonConsumable, Some(_)) => {
+                return Err("only subscriptions have a subscriptionPeriod".into())
+            }
+            _ => {}
+        }
+
+        let currency_symbol = match self.currency.as_str() {
+            "USD" | "AUD" | "CAD" | "NZD" | "MXN" => "$",
+            "EUR" => "€",
+            "GBP" => "£",
+            "JPY" | "CNY" => "¥",
+            "INR" => "₹",
+            "KRW" => "₩",
+            code => code,
+        }
+        .to_string();
+        let price = if currency_symbol.len() == 3 && currency_symbol.is_ascii() {
+            format!("{} {:.2}", currency_symbol, self.price)
+        } else {
+            format!("{}{:.2}", currency_symbol, self.price)
+        };
+        Ok(ProductDetails {
+            id: self.id,
+            title: self.title,
+            description: self.description,
+            price,
+            raw_price: self.price,
+            currency_code: self.currency,
+            currency_symbol,
+            subscription_period: self.subscription_period,
+            free_trial_period: None,
+            subscription_group_id: None,
+        })
+    }
+}
+
+/// A purchase made in the mock store.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MockPurchase {
+    details: PurchaseDetails,
+    consumable: bool,
+    /// The `application_user_name` the purchase was made with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    application_user_name: Option<String>,
+}
+
+/// Purchase state, as written to the storage file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MockState {
+    /// Last number used for generated order IDs and tokens.
+    last_id: u64,
+    purchases: Vec<MockPurchase>,
+}
+
+/// Fake store used on desktop when the `mock` feature is enabled.
+///
+/// Purchases succeed immediately with generated tokens, so the purchase UI can
+/// be developed without a device or a store account. Like real entitlements,
+/// purchases survive restarts when the store is backed by a storage file.
+pub struct MockStore {
+    catalog: Mutex<Vec<ProductDetails>>,
+    state: Mutex<MockState>,
+    storage_path: Option<PathBuf>,
+    confirm: Option<Box<ConfirmPurchase>>,
+    failures: Mutex<Vec<MockFailure>>,
+    country_code: Mutex<Option<String>>,
+    /// Sink of the plugin, told about country code changes like a real
+    /// store reports storefront changes.
+    storefront_listener: Mutex<Option<PurchaseEventSink>>,
+    locale: LocaleProvider,
+    latency: Mutex<Option<MockLatency>>,
+    pending: Mutex<bool>,
+    /// Features reported as unsupported, all others are supported.
+    unsupported_features: Mutex<HashSet<StoreFeature>>,
+    install_source: Mutex<InstallSource>,
+    /// Sink of the last purchase, reporting pending purchases that changed.
+    pending_events: Mutex<Option<PurchaseEventSink>>,
+    calls: Mutex<Vec<MockCall>>,
+}
+
+impl MockStore {
+    /// Creates a store selling the products in `catalog`, keeping purchases
+    /// in memory.
+    pub fn new(catalog: Vec<ProductDetails>) -> Self {
+        Self {
+            catalog: Mutex::new(catalog),
+            state: Mutex::new(MockState::default()),
+            storage_path: None,
+            confirm: None,
+            failures: Mutex::new(Vec::new()),
+            country_code: Mutex::new(None),
+            storefront_listener: Mutex::new(None),
+            locale: Arc::new(sys_locale::get_locale),
+            latency: Mutex::new(None),
+            pending: Mutex::new(false),
+            unsupported_features: Mutex::new(HashSet::new()),
+            install_source: Mutex::new(InstallSource::DevBuild),
+            pending_events: Mutex::new(None),
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Creates a store selling the products in `catalog`, keeping purchases in
+    /// the JSON file at `path`.
+    ///
+    /// Purchases are loaded from the file now and again by `initialize`, and
+    /// written back on every change.
+    pub fn persistent(catalog: Vec<ProductDetails>, path: impl Into<PathBuf>) -> Self {
+        let store = Self {
+            storage_path: Some(path.into()),
+            ..Self::new(catalog)
+        };
+        store.load();
+        store
+    }
+
+    /// Asks `confirm` to approve every purchase before it is made. Purchases it
+    /// declines fail with `Error::UserCancelled`, as the stores do.
+    pub fn with_confirmation<F>(mut self, confirm: F) -> Self
+    where
+        F: Fn(&ProductDetails) -> Result<bool> + Send + Sync + 'static,
+    {
+        self.confirm = Some(Box::new(confirm));
+        self
+    }
+
+    /// Uses `locale` instead of the system locale to derive the country code.
+    pub(crate) fn with_locale_provider(mut self, locale: LocaleProvider) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// Overrides the country code reported by the store, or derives it from
+    /// the system locale again if `None`, then reports a storefront change.
+    pub fn set_country_code(&self, country_code: Option<String>) {
+        *lock(&self.country_code) = country_code;
+        let listener = lock(&self.storefront_listener).clone();
+        if let Some(events) = listener {
+            events.storefront_changed();
+        }
+    }
+
+    /// Reports storefront changes to `events`.
+    pub(crate) fn set_storefront_listener(&self, events: PurchaseEventSink) {
+        *lock(&self.storefront_listener) = Some(events);
+    }
+
+    /// Sets the latency simulated before answering queries, purchases and
+    /// restores, or answers immediately if `None`.
+    pub fn set_latency(&self, latency: Option<MockLatency>) {
+        *lock(&self.latency) = latency;
+    }
+
+    /// The latency simulated before answering queries, purchases and restores.
+    pub fn latency(&self) -> Option<MockLatency> {
+        *lock(&self.latency)
+    }
+
+    /// Waits out the simulated latency without blocking the async runtime.
+    pub(crate) async fn simulate_latency(&self) {
+        if let Some(latency) = self.latency() {
+            tokio::time::sleep(latency.sample()).await;
+        }
+    }
+
+    /// The file purchases are kept in, if any.
+    pub fn storage_path(&self) -> Option<&Path> {
+        self.storage_path.as_deref()
+    }
+
+    /// The products the store sells.
+    pub fn catalog(&self) -> Vec<ProductDetails> {
+        lock(&self.catalog).clone()
+    }
+
+    /// Replaces the products the store sells.
+    pub fn set_catalog(&self, catalog: Vec<ProductDetails>) {
+        *lock(&self.catalog) = catalog;
+    }
+
+    /// Sets whether `is_feature_supported` reports `feature` as supported,
+    /// like a device with an older store app. Every feature is supported by
+    /// default.
+    pub fn set_feature_supported(&self, feature: StoreFeature, supported: bool) {
+        let mut unsupported = lock(&self.unsupported_features);
+        if supported {
+            unsupported.remove(&feature);
+        } else {
+            unsupported.insert(feature);
+        }
+    }
+
+    /// Sets the source reported by `install_source`, e.g. `Sideload` to test
+    /// the app's message for builds that can't buy. `DevBuild` by default.
+    pub fn set_install_source(&self, source: InstallSource) {
+        *lock(&self.install_source) = source;
+    }
+
+    /// Reports new purchases with the `Pending` status, like purchases
+    /// awaiting a cash payment, until they are approved with
+    /// [`MockStore::approve_pending`] or declined with
+    /// [`MockStore::decline_pending`].
+    pub fn set_pending_purchases(&self, pending: bool) {
+        *lock(&self.pending) = pending;
+    }
+
+    /// Completes the payment of a pending purchase of `product_id`, reporting
+    /// it again with the `Purchased` status.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ItemNotOwned` if there is no pending purchase of the
+    /// product.
+    pub fn approve_pending(&self, product_id: &str) -> Result<()> {
+        let mut state = lock(&self.state);
+        let index = pending_index(&state, product_id)?;
+        let details = &mut state.purchases[index].details;
+        details.status = PurchaseStatus::Purchased;
+        details.pending_complete_purchase = true;
+        let details = details.clone();
+        self.save(&state);
+        drop(state);
+        self.report_pending(details);
+        Ok(())
+    }
+
+    /// Cancels a pending purchase of `product_id`, e.g. because it was never
+    /// paid, reporting it again with the `Canceled` status.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ItemNotOwned` if there is no pending purchase of the
+    /// product.
+    pub fn decline_pending(&self, product_id: &str) -> Result<()> {
+        let mut state = lock(&self.state);
+        let index = pending_index(&state, product_id)?;
+        let details = PurchaseDetails {
+            status: PurchaseStatus::Canceled,
+            ..state.purchases.remove(index).details
+        };
+        self.save(&state);
+        drop(state);
+        self.report_pending(details);
+        Ok(())
+    }
+
+    /// Purchases that are owned or still waiting to be completed.
+    pub fn purchases(&self) -> Vec<PurchaseDetails> {
+        lock(&self.state)
+            .purchases
+            .iter()
+            .map(|purchase| purchase.details.clone())
+            .collect()
+    }
+
+    /// Makes the user own a non-consumable product, as if it was bought and
+    /// completed earlier. Products that are already owned are left alone.
+    pub fn grant(&self, product_id: &str) {
+        let mut state = lock(&self.state);
+        if owns(&state, product_id) {
+            return;
+        }
+        let details = new_purchase(&mut state, product_id, false);
+        state.purchases.push(MockPurchase {
+            details,
+            consumable: false,
+            application_user_name: None,
+        });
+        self.save(&state);
+    }
+
+    /// Forgets all purchases, deleting the storage file.
+    pub fn reset(&self) {
+        self.clear_purchases();
+    }
+
+    /// Forgets all purchases like [`MockStore::reset`], restarting the
+    /// generated order IDs, and returns the purchases that were forgotten.
+    pub fn clear_purchases(&self) -> Vec<PurchaseDetails> {
+        let state = std::mem::take(&mut *lock(&self.state));
+        if let Some(path) = &self.storage_path {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => {
+                    log::warn!("Failed to delete mock purchases at {}: {}", path.display(), e);
+                }
+                _ => {}
+            }
+        }
+        state
+            .purchases
+            .into_iter()
+            .map(|purchase| purchase.details)
+            .collect()
+    }
+
+    /// Makes an operation fail, e.g. to test how the app handles store
+    /// errors. Failures are consumed in the order they were injected.
+    pub fn inject_failure(&self, failure: MockFailure) {
+        lock(&self.failures).push(failure);
+    }
+
+    /// Fails the next call of `operation` with an error of `code`.
+    pub fn fail_next(&self, operation: MockOperation, code: IapErrorCode) {
+        self.inject_failure(MockFailure::new(operation, code));
+    }
+
+    /// Removes all injected failures.
+    pub fn clear_failures(&self) {
+        lock(&self.failures).clear();
+    }
+
+    /// The calls made to the store so far, oldest first.
+    pub fn calls(&self) -> Vec<MockCall> {
+        lock(&self.calls).clone()
+    }
+
+    /// Forgets the calls made so far.
+    pub fn clear_calls(&self) {
+        lock(&self.calls).clear();
+    }
+
+    /// Records a call, then fails it if a failure was injected.
+    fn call(
+        &self,
+        operation: MockOperation,
+        product_ids: &[String],
+        payload: serde_json::Value,
+    ) -> Result<()> {
+        lock(&self.calls).push(MockCall { operation, payload });
+        self.injected_failure(operation, product_ids)
+    }
+
+    /// Fails with the first failure injected into `operation` that applies to
+    /// one of `product_ids`. The error is built from an [`IAPError`] payload,
+    /// so it has the same shape as one reported by a native store.
+    fn injected_failure(&self, operation: MockOperation, product_ids: &[String]) -> Result<()> {
+        let mut failures = lock(&self.failures);
+        let Some(index) = failures.iter().position(|failure| {
+            failure.operation == operation
+                && failure
+                    .product_id
+                    .as_ref()
+                    .map_or(true, |id| product_ids.contains(id))
+        }) else {
+            return Ok(());
+        };
+        let failure = if failures[index].persistent {
+            failures[index].clone()
+        } else {
+            failures.remove(index)
+        };
+        Err(IAPError {
+            code: failure.code.as_str().into(),
+            message: format!("Injected {} failure", failure.code),
+            details: None,
+            native_code: None,
+        }
+        .into())
+    }
+
+    /// Replaces the purchases with those in the storage file. A missing file
+    /// means no purchases, and an unreadable one is discarded with a warning.
+    fn load(&self) {
+        let Some(path) = &self.storage_path else {
+            return;
+        };
+        let state = match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                log::warn!(
+                    "Ignoring corrupt mock purchases at {}: {}",
+                    path.display(),
+                    e
+                );
+                MockState::default()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => MockState::default(),
+            Err(e) => {
+                log::warn!("Failed to read mock purchases at {}: {}", path.display(), e);
+                MockState::default()
+            }
+        };
+        *lock(&self.state) = state;
+    }
+
+    /// Writes the purchases to the storage file. Failures only log a warning,
+    /// as the purchases are still served from memory.
+    fn save(&self, state: &MockState) {
+        let Some(path) = &self.storage_path else {
+            return;
+        };
+        let result = serde_json::to_vec_pretty(state)
+            .map_err(std::io::Error::other)
+            .and_then(|json| {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                std::fs::write(path, json)
+            });
+        if let Err(e) = result {
+            log::warn!("Failed to save mock purchases to {}: {}", path.display(), e);
+        }
+    }
+
+    fn report_pending(&self, purchase: PurchaseDetails) {
+        match lock(&self.pending_events).clone() {
+            Some(events) => events.emit(&[purchase]),
+            None => log::warn!("No purchase was made yet to report {}", purchase.product_id),
+        }
+    }
+
+    /// Buys a product, failing like the stores do for unknown products and
+    /// for products that are already owned.
+    fn buy(
+        &self,
+        purchase_param: &PurchaseParam,
+        consumable: bool,
+        auto_consume: bool,
+    ) -> Result<PurchaseDetails> {
+        let product_id = &purchase_param.product_details.id;
+        let Some(product) = lock(&self.catalog)
+            .iter()
+            .find(|product| &product.id == product_id)
+            .cloned()
+        else {
+            return Err(Error::PurchaseError(format!("Item unavailable: {product_id}")));
+        };
+        if owns(&lock(&self.state), product_id) {
+            return Err(Error::ItemAlreadyOwned);
+        }
+
+        // The state isn't locked while waiting for the user.
+        if let Some(confirm) = &self.confirm {
+            if !confirm(&product)? {
+                return Err(Error::UserCancelled);
+            }
+        }
+
+        let mut state = lock(&self.state);
+        if owns(&state, product_id) {
+            return Err(Error::ItemAlreadyOwned);
+        }
+
+        // Pending purchases are neither completed nor consumed until paid.
+        let pending = *lock(&self.pending);
+        let auto_consumed = consumable && auto_consume && !pending;
+        let mut details = new_purchase(&mut state, product_id, !auto_consumed && !pending);
+        if pending {
+            details.status = PurchaseStatus::Pending;
+        }
+        // Consumed purchases aren't owned, so the product can be bought again.
+        if !auto_consumed {
+            state.purchases.push(MockPurchase {
+                details: details.clone(),
+                consumable,
+                application_user_name: purchase_param.application_user_name.clone(),
+            });
+        }
+        self.save(&state);
+        Ok(details)
+    }
+}
+
+impl DesktopIapBackend for MockStore {
+    /// Loads the purchases kept in the storage file.
+    fn initialize(&self) -> Result<()> {
+        self.call(MockOperation::Initialize, &[], serde_json::Value::Null)?;
+        self.load();
+        Ok(())
+    }
+
+    fn is_available(&self) -> Result<bool> {
+        self.call(MockOperation::IsAvailable, &[], serde_json::Value::Null)?;
+        Ok(true)
+    }
+
+    fn query_product_details(&self, product_ids: &[String]) -> Result<ProductDetailsResponse> {
+        self.call(
+            MockOperation::QueryProductDetails,
+            product_ids,
+            serde_json::json!({ "productIds": product_ids }),
+        )?;
+        let catalog = lock(&self.catalog);
+        let mut response = ProductDetailsResponse::default();
+        for id in product_ids {
+            match catalog.iter().find(|product| &product.id == id) {
+                Some(product) => response.product_details.push(product.clone()),
+                None => response.not_found_ids.push(id.clone()),
+            }
+        }
+        Ok(response)
+    }
+
+    fn buy_non_consumable(
+        &self,
+        purchase_param: &PurchaseParam,
+        events: &PurchaseEventSink,
+    ) -> Result<bool> {
+        self.call(
+            MockOperation::BuyNonConsumable,
+            std::slice::from_ref(&purchase_param.product_details.id),
+            serde_json::json!({ "purchaseParam": purchase_param }),
+        )?;
+        *lock(&self.pending_events) = Some(events.clone());
+        events.emit(&[self.buy(purchase_param, false, false)?]);
+        Ok(true)
+    }
+
+    fn buy_consumable(
+        &self,
+        purchase_param: &PurchaseParam,
+        auto_consume: bool,
+        events: &PurchaseEventSink,
+    ) -> Result<bool> {
+        self.call(
+            MockOperation::BuyConsumable,
+            std::slice::from_ref(&purchase_param.product_details.id),
+            serde_json::json!({ "purchaseParam": purchase_param, "autoConsume": auto_consume }),
+        )?;
+        *lock(&self.pending_events) = Some(events.clone());
+        events.emit(&[self.buy(purchase_param, true, auto_consume)?]);
+        Ok(true)
+    }
+
+    /// Buys the new subscription like any product, then drops the purchase it
+    /// replaces. The switch takes effect at once whatever the replacement
+    /// mode, as mock subscriptions never renew.
+    fn change_subscription(
+        &self,
+        purchase_param: &PurchaseParam,
+        old_purchase: &PurchaseDetails,
+        replacement_mode: ReplacementMode,
+        events: &PurchaseEventSink,
+    ) -> Result<Option<PurchaseDetails>> {
+        self.call(
+            MockOperation::BuyUpgrade,
+            &[purchase_param.product_details.id.clone(), old_purchase.product_id.clone()],
+            serde_json::json!({
+                "purchaseParam": purchase_param,
+                "oldProductId": old_purchase.product_id,
+                "replacementMode": replacement_mode,
+            }),
+        )?;
+        if lock(&self.unsupported_features).contains(&StoreFeature::SubscriptionsUpdate) {
+            return Err(Error::FeatureNotSupported(
+                "Subscription upgrades are turned off in the mock store".into(),
+            ));
+        }
+        if !owns(&lock(&self.state), &old_purchase.product_id) {
+            return Err(Error::ItemNotOwned(old_purchase.product_id.clone()));
+        }
+        *lock(&self.pending_events) = Some(events.clone());
+        let purchase = self.buy(purchase_param, false, false)?;
+        let mut state = lock(&self.state);
+        state
+            .purchases
+            .retain(|known| known.details.product_id != old_purchase.product_id);
+        self.save(&state);
+        drop(state);
+        events.emit(std::slice::from_ref(&purchase));
+        Ok(Some(purchase))
+    }
+
+    /// Completes a purchase made in this store, consuming it if the product is
+    /// consumable.
+    fn complete_purchase(&self, purchase: &PurchaseDetails) -> Result<()> {
+        self.call(
+            MockOperation::CompletePurchase,
+            std::slice::from_ref(&purchase.product_id),
+            serde_json::json!({ "purchase": purchase }),
+        )?;
+        let mut state = lock(&self.state);
+        let Some(index) = state.purchases.iter().position(|known| {
+            known.details.purchase_id == purchase.purchase_id
+                && known.details.verification_data.server_verification_data
+                    == purchase.verification_data.server_verification_data
+        }) else {
+            return Err(Error::UnknownPurchase(
+                purchase
+                    .purchase_id
+                    .clone()
+                    .unwrap_or_else(|| purchase.product_id.clone()),
+            ));
+        };
+
+        if matches!(state.purchases[index].details.status, PurchaseStatus::Pending) {
+            return Err(Error::PurchaseError(format!(
+                "Purchase of {} is still pending",
+                purchase.product_id
+            )));
+        }
+        if state.purchases[index].consumable {
+            state.purchases.remove(index);
+        } else {
+            state.purchases[index].details.pending_complete_purchase = false;
+        }
+        self.save(&state);
+        Ok(())
+    }
+
+    /// Reports owned non-consumables and subscriptions with the `Restored`
+    /// status, only those bought with `application_user_name` if given.
+    fn restore_purchases(
+        &self,
+        application_user_name: Option<&str>,
+        events: &PurchaseEventSink,
+    ) -> Result<()> {
+        self.call(
+            MockOperation::RestorePurchases,
+            &[],
+            serde_json::json!({ "applicationUserName": application_user_name }),
+        )?;
+        let restored: Vec<_> = lock(&self.state)
+            .purchases
+            .iter()
+            .filter(|purchase| !purchase.consumable)
+            .filter(|purchase| {
+                application_user_name.is_none()
+                    || purchase.application_user_name.as_deref() == application_user_name
+            })
+            .map(|purchase| PurchaseDetails {
+                status: match purchase.details.status {
+                    PurchaseStatus::Pending => PurchaseStatus::Pending,
+                    _ => PurchaseStatus::Restored,
+                },
+                ..purchase.details.clone()
+            })
+            .collect();
+        events.emit(&restored);
+        Ok(())
+    }
+
+    /// The country code set with [`MockStore::set_country_code`], or else the
+    /// region of the system locale, as there is no storefront.
+    fn country_code(&self) -> Result<String> {
+        self.call(MockOperation::CountryCode, &[], serde_json::Value::Null)?;
+        match lock(&self.country_code).clone() {
+            Some(country_code) => Ok(country_code),
+            None => locale_country_code(&self.locale),
+        }
+    }
+
+    /// Reports owned subscriptions as active and renewing, since mock
+    /// subscriptions never lapse.
+    fn subscription_status(&self, product_id: &str) -> Result<SubscriptionStatus> {
+        self.call(
+            MockOperation::SubscriptionStatus,
+            &[product_id.to_string()],
+            serde_json::json!({ "productId": product_id }),
+        )?;
+        let is_subscription = lock(&self.catalog)
+            .iter()
+            .any(|product| product.id == product_id && product.subscription_period.is_some());
+        if !is_subscription || !owns(&lock(&self.state), product_id) {
+            return Err(Error::ItemNotOwned(product_id.to_string()));
+        }
+        Ok(SubscriptionStatus {
+            product_id: product_id.to_string(),
+            is_active: true,
+            state: SubscriptionState::Active,
+            grace_period_expiration_date: None,
+            expiration_date: None,
+            will_auto_renew: Some(true),
+        })
+    }
+
+    /// Supports every feature not turned off with
+    /// [`MockStore::set_feature_supported`].
+    fn is_feature_supported(&self, feature: StoreFeature) -> Result<bool> {
+        self.call(
+            MockOperation::IsFeatureSupported,
+            &[],
+            serde_json::json!({ "feature": feature }),
+        )?;
+        Ok(!lock(&self.unsupported_features).contains(&feature))
+    }
+
+    fn install_source(&self) -> Result<InstallSource> {
+        Ok(*lock(&self.install_source))
+    }
+
+    fn describe(&self, diagnostics: &mut Diagnostics) {
+        diagnostics.store = Some("Mock store".into());
+    }
+
+    /// Only records the call, as the mock store has no pages to show.
+    fn open_store_product_page(&self, product_id: Option<&str>) -> Result<()> {
+        self.call(
+            MockOperation::OpenStoreProductPage,
+            &product_id.map(str::to_string).into_iter().collect::<Vec<_>>(),
+            serde_json::json!({ "productId": product_id }),
+        )
+    }
+}
+
+/// Creates a purchase of the product with generated order ID and token.
+fn new_purchase(
+    state: &mut MockState,
+    product_id: &str,
+    pending_complete_purchase: bool,
+) -> PurchaseDetails {
+    state.last_id += 1;
+    let id = state.last_id;
+    let token = format!("mock-token-{id}");
+    let mut purchase = PurchaseDetails {
+        purchase_id: Some(format!("mock-order-{id}")),
+        product_id: product_id.to_string(),
+        verification_data: PurchaseVerificationData {
+            local_verification_data: token.clone(),
+            server_verification_data: token,
+            source: MOCK_SOURCE.into(),
+            signature: None,
+        },
+        transaction_date: None,
+        transaction_date_ms: None,
+        expiration_date: None,
+        subscription_state: None,
+        grace_period_expiration_date: None,
+        status: PurchaseStatus::Purchased,
+        error: None,
+        pending_complete_purchase,
+        verified: false,
+    };
+    purchase.set_transaction_date_ms(now_ms());
+    purchase
+}
+
+/// Whether a purchase of the product is owned or waiting to be completed.
+fn owns(state: &MockState, product_id: &str) -> bool {
+    state
+        .purchases
+        .iter()
+        .any(|purchase| purchase.details.product_id == product_id)
+}
+
+/// The index of the pending purchase of `product_id`.
+fn pending_index(state: &MockState, product_id: &str) -> Result<usize> {
+    state
+        .purchases
+        .iter()
+        .position(|purchase| {
+            purchase.details.product_id == product_id
+                && matches!(purchase.details.status, PurchaseStatus::Pending)
+        })
+        .ok_or_else(|| Error::ItemNotOwned(product_id.to_string()))
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn product(id: &str) -> ProductDetails {
+        ProductDetails {
+            id: id.into(),
+            title: id.into(),
+            description: String::new(),
+            price: "$1.99".into(),
+            raw_price: 1.99,
+            currency_code: "USD".into(),
+            currency_symbol: "$".into(),
+            subscription_period: None,
+            free_trial_period: None,
+            subscription_group_id: None,
+        }
+    }
+
+    /// The path of a storage file in an empty directory of its own for each
+    /// test.
+    fn storage(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tauri-plugin-iap-mock-{test}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join(MOCK_PURCHASES_FILE)
+    }
+
+    fn store(path: &Path) -> MockStore {
+        MockStore::persistent(vec![product("premium"), product("coins")], path)
+    }
+
+    fn param(product_id: &str) -> PurchaseParam {
+        PurchaseParam {
+            product_details: product(product_id),
+            application_user_name: None,
+        }
+    }
+
+    fn buy(store: &MockStore, product_id: &str) -> Result<PurchaseDetails> {
+        store.buy_non_consumable(&param(product_id), &PurchaseEventSink::new(|_| {}))?;
+        Ok(store.purchases().pop().unwrap())
+    }
+
+    /// The mock options of a plugin configured with `mock_products`.
+    fn configured(mock_products: serde_json::Value) -> Result<MockOptions> {
+        let config: Config = serde_json::from_value(json!({
+            "mockProducts": mock_products,
+            "mockLatency": "300-3000",
+        }))
+        .unwrap();
+        MockOptions::default().with_config(&config)
+    }
+
+    fn config_error(mock_products: serde_json::Value) -> String {
+        match configured(mock_products) {
+            Err(Error::InvalidConfig(message)) => message,
+            other => panic!("expected an invalid config, got {:?}", other.map(|o| o.catalog)),
+        }
+    }
+
+    #[test]
+    fn reads_products_from_the_config() {
+        let options = configured(json!([
+            {
+                "id": "premium",
+                "title": "Premium",
+                "description": "Unlocks everything",
+                "price": 4.99,
+                "currency": "EUR",
+                "type": "nonConsumable",
+            },
+            {
+                "id": "coins",
+                "title": "Coins",
+                "price": 0.5,
+                "currency": "CHF",
+                "type": "consumable",
+            },
+            {
+                "id": "pro_monthly",
+                "title": "Pro",
+                "price": 9.99,
+                "currency": "USD",
+                "type": "subscription",
+                "subscriptionPeriod": "P1M",
+            },
+        ]))
+        .unwrap();
+
+        let catalog = &options.catalog;
+        assert_eq!(catalog.len(), 3);
+        assert_eq!(catalog[0].id, "premium");
+        assert_eq!(catalog[0].description, "Unlocks everything");
+        assert_eq!(catalog[0].price, "€4.99");
+        assert_eq!(catalog[0].raw_price, 4.99);
+        assert_eq!(catalog[0].currency_code, "EUR");
+        assert_eq!(catalog[0].subscription_period, None);
+        // Without a known symbol, the price is shown with the code.
+        assert_eq!(catalog[1].price, "CHF 0.50");
+        assert_eq!(catalog[1].currency_symbol, "CHF");
+        assert_eq!(catalog[1].description, "");
+        assert_eq!(catalog[2].price, "$9.99");
+        assert_eq!(catalog[2].subscription_period.as_deref(), Some("P1M"));
+        assert_eq!(
+            options.latency,
+            Some(MockLatency {
+                min_ms: 300,
+                max_ms: 3000,
+            })
+        );
+    }
+
+    #[test]
+    fn names_the_invalid_product() {
+        let premium = json!({
+            "id": "premium",
+            "title": "Premium",
+            "price": 4.99,
+            "currency": "USD",
+            "type": "nonConsumable",
+        });
+        let cases = [
+            (json!({ "price": -1 }), "invalid price -1"),
+            (json!({ "currency": "usd" }), "currency must be an ISO 4217 code"),
+            (json!({ "type": "subscription" }), "subscriptions need a subscriptionPeriod"),
+            (json!({ "subscriptionPeriod": "P1M" }), "only subscriptions have"),
+            (json!({ "sku": "premium" }), "unknown field `sku`"),
+        ];
+        for (changes, reason) in cases {
+            let mut product = premium.clone();
+            for (key, value) in changes.as_object().unwrap() {
+                product[key] = value.clone();
+            }
+            let error = config_error(json!([product]));
+            assert!(error.starts_with("mock product \"premium\": "), "{error}");
+            assert!(error.contains(reason), "{error}");
+        }
+
+        // Entries without an ID are named by their position.
+        let error = config_error(json!([premium, { "title": "Nameless" }]));
+        assert!(error.starts_with("mock product #1: missing field `id`"), "{error}");
+    }
+
+    #[test]
+    fn is_opted_into_from_the_config() {
+        let with = |config: serde_json::Value| {
+            let config: Config = serde_json::from_value(config).unwrap();
+            MockOptions::default().with_config(&config).unwrap().enabled
+        };
+
+        assert_eq!(with(json!({})), None);
+        assert_eq!(with(json!({ "mock": true })), Some(true));
+        assert_eq!(with(json!({ "mock": false })), Some(false));
+    }
+
+    #[test]
+    fn reloads_written_purchases() {
+        let path = storage("reload");
+        let bought = buy(&store(&path), "premium").unwrap();
+
+        // The directory is created on the first write.
+        assert!(path.exists());
+        let relaunched = store(&path);
+        let purchases = relaunched.purchases();
+        assert_eq!(purchases.len(), 1);
+        assert_eq!(purchases[0].purchase_id, bought.purchase_id);
+        assert!(purchases[0].pending_complete_purchase);
+        assert!(matches!(buy(&relaunched, "premium"), Err(Error::ItemAlreadyOwned)));
+        // Generated IDs carry on where they were.
+        let next = buy(&relaunched, "coins").unwrap();
+        assert_eq!(next.purchase_id.as_deref(), Some("mock-order-2"));
+    }
+
+    #[test]
+    fn writes_every_change() {
+        let path = storage("changes");
+        let store = store(&path);
+        let bought = buy(&store, "premium").unwrap();
+
+        store.complete_purchase(&bought).unwrap();
+
+        let reloaded = self::store(&path).purchases();
+        assert!(!reloaded[0].pending_complete_purchase);
+    }
+
+    #[test]
+    fn initialize_reloads_the_file() {
+        let path = storage("initialize");
+        let store = store(&path);
+        // Written by another run of the app.
+        self::store(&path).grant("premium");
+
+        assert!(store.purchases().is_empty());
+        store.initialize().unwrap();
+        assert_eq!(store.purchases()[0].product_id, "premium");
+    }
+
+    #[test]
+    fn starts_fresh_from_a_corrupted_file() {
+        let path = storage("corrupted");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"{\"lastId\":3,\"purchases\":[{\"deta").unwrap();
+
+        let store = store(&path);
+
+        assert!(store.purchases().is_empty());
+        let bought = buy(&store, "premium").unwrap();
+        assert_eq!(bought.purchase_id.as_deref(), Some("mock-order-1"));
+        assert_eq!(self::store(&path).purchases().len(), 1);
+    }
+
+    #[test]
+    fn reset_deletes_the_file() {
+        let path = storage("reset");
+        let store = store(&path);
+        store.grant("premium");
+
+        store.reset();
+
+        assert!(!path.exists());
+        assert!(store.purchases().is_empty());
+        assert!(self::store(&path).purchases().is_empty());
+    }
+
+    #[test]
+    fn asks_for_confirmation_before_buying() {
+        let asked = Arc::new(Mutex::new(Vec::new()));
+        let answers = Arc::new(Mutex::new(vec![Ok(true), Ok(false)]));
+        let store = {
+            let asked = asked.clone();
+            let answers = answers.clone();
+            MockStore::new(vec![product("premium"), product("coins")]).with_confirmation(
+                move |product| {
+                    asked.lock().unwrap().push(product.id.clone());
+                    answers.lock().unwrap().pop().unwrap()
+                },
+            )
+        };
+        let emitted = Arc::new(Mutex::new(0));
+        let count = emitted.clone();
+        let events = PurchaseEventSink::new(move |_| *count.lock().unwrap() += 1);
+
+        // Cancel leaves the product unowned and reports nothing.
+        let declined = store.buy_non_consumable(&param("premium"), &events);
+        assert!(matches!(declined, Err(Error::UserCancelled)));
+        assert!(store.purchases().is_empty());
+        assert_eq!(*emitted.lock().unwrap(), 0);
+
+        // Buy goes on to make the purchase.
+        assert!(store.buy_non_consumable(&param("premium"), &events).unwrap());
+        assert!(matches!(store.purchases()[0].status, PurchaseStatus::Purchased));
+        assert_eq!(*emitted.lock().unwrap(), 1);
+
+        // Owned products and dialogs failing to show don't make purchases.
+        let owned = store.buy_non_consumable(&param("premium"), &events);
+        assert!(matches!(owned, Err(Error::ItemAlreadyOwned)));
+        answers
+            .lock()
+            .unwrap()
+            .push(Err(Error::InternalError("no display".into())));
+        let failed = store.buy_consumable(&param("coins"), true, &events);
+        assert!(matches!(failed, Err(Error::InternalError(_))));
+        assert_eq!(store.purchases().len(), 1);
+        assert_eq!(*asked.lock().unwrap(), ["premium", "premium", "coins"]);
+    }
+
+    #[test]
+    fn buys_without_confirmation_by_default() {
+        let store = store(&storage("headless"));
+
+        let bought = buy(&store, "premium").unwrap();
+
+        assert!(matches!(bought.status, PurchaseStatus::Purchased));
+    }
+}
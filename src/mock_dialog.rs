@@ -0,0 +1,155 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc;
+
+use tauri::{AppHandle, Runtime, Url, WebviewUrl, WebviewWindowBuilder, WindowEvent};
+
+use crate::models::ProductDetails;
+use crate::{Error, Result};
+
+/// Links behind the dialog buttons, intercepted before they load.
+const BUY_URL: &str = "http://iap-mock.localhost/buy";
+const CANCEL_URL: &str = "http://iap-mock.localhost/cancel";
+
+/// Numbers the dialog windows, as overlapping purchases need unique labels.
+static NEXT_DIALOG: AtomicU32 = AtomicU32::new(1);
+
+/// Shows a window asking to confirm the purchase of `product` with Buy and
+/// Cancel buttons, like the store sheets do, and waits for the answer.
+///
+/// Closing the window counts as cancelling.
+pub(crate) fn confirm_purchase<R: Runtime>(
+    app: &AppHandle<R>,
+    product: &ProductDetails,
+) -> Result<bool> {
+    let page = format!("data:text/html;charset=utf-8,{}", percent_encode(&page(product)));
+    let url = Url::parse(&page).map_err(|e| Error::InternalError(e.to_string()))?;
+    let label = format!(
+        "iap-mock-confirm-{}",
+        NEXT_DIALOG.fetch_add(1, Ordering::Relaxed)
+    );
+
+    let (tx, rx) = mpsc::channel();
+    let answer = tx.clone();
+    let window = WebviewWindowBuilder::new(app, label, WebviewUrl::External(url))
+        .title("Confirm Purchase")
+        .inner_size(380.0, 240.0)
+        .resizable(false)
+        .minimizable(false)
+        .maximizable(false)
+        .always_on_top(true)
+        .focused(true)
+        .center()
+        .on_navigation(move |url| match url.as_str() {
+            BUY_URL => {
+                let _ = answer.send(true);
+                false
+            }
+            CANCEL_URL => {
+                let _ = answer.send(false);
+                false
+            }
+            _ => url.scheme() == "data",
+        })
+        .build()
+        .map_err(|e| Error::InternalError(format!("Failed to show the mock purchase dialog: {e}")))?;
+    window.on_window_event(move |event| {
+        if let WindowEvent::Destroyed = event {
+            let _ = tx.send(false);
+        }
+    });
+
+    let approved = rx.recv().unwrap_or(false);
+    let _ = window.destroy();
+    Ok(approved)
+}
+
+fn page(product: &ProductDetails) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<style>
+  body {{ font-family: system-ui, sans-serif; margin: 20px; }}
+  p {{ color: #666; margin: 4px 0 0; }}
+  .price {{ color: inherit; font-size: 1.4em; margin: 16px 0; }}
+  .buttons {{ display: flex; gap: 8px; justify-content: flex-end; }}
+  a {{ border: 1px solid #999; border-radius: 6px; color: inherit; padding: 6px 18px; text-decoration: none; }}
+  a.buy {{ background: #0a64d8; border-color: #0a64d8; color: white; }}
+</style>
+</head>
+<body>
+<strong>{title}</strong>
+<p>{description}</p>
+<p class="price">{price}</p>
+<p>Mock store, no payment is made.</p>
+<div class="buttons">
+  <a href="{CANCEL_URL}">Cancel</a>
+  <a class="buy" href="{BUY_URL}">Buy</a>
+</div>
+</body>
+</html>"#,
+        title = escape_html(&product.title),
+        description = escape_html(&product.description),
+        price = escape_html(&product.price),
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(title: &str) -> ProductDetails {
+        ProductDetails {
+            id: "premium".into(),
+            title: title.into(),
+            description: "Unlocks <everything>".into(),
+            price: "4,99 €".into(),
+            raw_price: 4.99,
+            currency_code: "EUR".into(),
+            currency_symbol: "€".into(),
+            subscription_period: None,
+            free_trial_period: None,
+            subscription_group_id: None,
+        }
+    }
+
+    #[test]
+    fn shows_the_product_with_buy_and_cancel_buttons() {
+        let page = page(&product("Premium & \"Pro\""));
+
+        assert!(page.contains("<strong>Premium &amp; &quot;Pro&quot;</strong>"));
+        assert!(page.contains("<p>Unlocks &lt;everything&gt;</p>"));
+        assert!(page.contains("4,99 €"));
+        assert!(page.contains(&format!(r#"<a href="{CANCEL_URL}">Cancel</a>"#)));
+        assert!(page.contains(&format!(r#"<a class="buy" href="{BUY_URL}">Buy</a>"#)));
+    }
+
+    #[test]
+    fn encodes_the_page_into_a_data_url() {
+        let encoded = percent_encode("<p>4,99 €</p>");
+        assert_eq!(encoded, "%3Cp%3E4%2C99%20%E2%82%AC%3C%2Fp%3E");
+
+        let page = page(&product("Premium"));
+        let url = format!("data:text/html;charset=utf-8,{}", percent_encode(&page));
+        assert!(Url::parse(&url).is_ok());
+    }
+}
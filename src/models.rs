@@ -1,4 +1,9 @@
-use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::time::{iso8601, parse_iso8601};
+use crate::IapErrorCode;
 
 /// Product details from the app store (Apple App Store or Google Play)
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -18,18 +23,40 @@ pub struct ProductDetails {
     pub currency_code: String,
     /// Currency symbol (e.g., "$")
     pub currency_symbol: String,
+    /// ISO 8601 duration of the billing period, for subscriptions (e.g., "P1M")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subscription_period: Option<String>,
+    /// ISO 8601 duration of the free trial offered, for subscriptions
+    /// (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub free_trial_period: Option<String>,
+    /// App Store subscription group of a subscription (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subscription_group_id: Option<String>,
+}
+
+impl ProductDetails {
+    /// Whether the product is a subscription, which has a billing period or,
+    /// on the App Store, a subscription group.
+    pub fn is_subscription(&self) -> bool {
+        self.subscription_period.is_some() || self.subscription_group_id.is_some()
+    }
 }
 
 /// Purchase verification data used for server-side validation
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PurchaseVerificationData {
     /// Platform-specific local verification data
     pub local_verification_data: String,
     /// Platform-specific server verification data
     pub server_verification_data: String,
-    /// Source platform ("apple" or "google")
+    /// Source platform ("apple", "google" or "amazon")
     pub source: String,
+    /// Signature of `local_verification_data`, Google Play's base64 encoded
+    /// `INAPP_DATA_SIGNATURE` (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 /// Status of a purchase transaction
@@ -57,27 +84,386 @@ pub struct IAPError {
     /// Human-readable error message
     pub message: String,
     /// Additional error details (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub details: Option<serde_json::Value>,
+    /// Raw response code reported by the native store (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub native_code: Option<i32>,
+}
+
+impl IAPError {
+    /// Parses the `code` string into an [`IapErrorCode`].
+    pub fn error_code(&self) -> IapErrorCode {
+        self.code.parse().unwrap_or(IapErrorCode::Unknown)
+    }
 }
 
 /// Details of a purchase transaction
 #[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(remote = "Self", rename_all = "camelCase")]
 pub struct PurchaseDetails {
     /// Unique identifier for the purchase (optional)
     pub purchase_id: Option<String>,
     /// Identifier of the purchased product
     pub product_id: String,
-    /// Verification data for server-side validation
+    /// Verification data for server-side validation. Empty in purchases the
+    /// webview sends back when `redactVerificationData` withheld it.
+    #[serde(default)]
     pub verification_data: PurchaseVerificationData,
-    /// ISO datetime string of the transaction (optional)
+    /// ISO datetime string of the transaction, to the second (optional)
     pub transaction_date: Option<String>,
+    /// Milliseconds since the Unix epoch of the transaction, as the store
+    /// reports it (optional)
+    #[serde(default)]
+    pub transaction_date_ms: Option<i64>,
+    /// ISO datetime string when the entitlement ends, for subscriptions and
+    /// time-limited add-ons (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiration_date: Option<String>,
+    /// Billing state of the subscription, if the purchase is one and the
+    /// store reports it (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subscription_state: Option<SubscriptionState>,
+    /// ISO datetime string when the grace period of the subscription ends,
+    /// if it is in one and the store reports it (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grace_period_expiration_date: Option<String>,
     /// Current status of the purchase
     pub status: PurchaseStatus,
     /// Error information if status is 'error' (optional)
     pub error: Option<IAPError>,
     /// Whether the purchase needs to be completed
     pub pending_complete_purchase: bool,
+    /// Whether the store signature was checked on device, against
+    /// `googlePlayPublicKey` on Android and by StoreKit 2 on iOS
+    #[serde(default)]
+    pub verified: bool,
+}
+
+impl Serialize for PurchaseDetails {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PurchaseDetails::serialize(self, serializer)
+    }
+}
+
+/// The stores report `transactionDateMs`, from which `transactionDate` is
+/// derived. Payloads with only `transactionDate`, e.g. purchases kept by an
+/// older version, get `transactionDateMs` from it instead.
+impl<'de> Deserialize<'de> for PurchaseDetails {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut purchase = PurchaseDetails::deserialize(deserializer)?;
+        match purchase.transaction_date_ms {
+            Some(ms) => purchase.set_transaction_date_ms(ms),
+            None => {
+                purchase.transaction_date_ms = purchase
+                    .transaction_date
+                    .as_deref()
+                    .and_then(parse_iso8601)
+                    .map(|secs| secs * 1000);
+            }
+        }
+        Ok(purchase)
+    }
+}
+
+impl PurchaseDetails {
+    /// Sets the transaction time to `ms` milliseconds since the Unix epoch,
+    /// deriving `transaction_date` from it, so both always agree.
+    pub fn set_transaction_date_ms(&mut self, ms: i64) {
+        self.transaction_date_ms = Some(ms);
+        self.transaction_date = Some(iso8601(ms.div_euclid(1000)));
+    }
+
+    /// ISO datetime string when the purchase stops granting access: the end
+    /// of the grace period for subscriptions in one, else the expiration.
+    pub(crate) fn entitlement_end(&self) -> Option<String> {
+        self.grace_period_expiration_date
+            .clone()
+            .or_else(|| self.expiration_date.clone())
+    }
+
+    /// The transaction ID and the purchase token (the server verification
+    /// data on Google Play, the JWS or receipt on the App Store), which
+    /// together identify a purchase the store reported.
+    pub(crate) fn identifiers(&self) -> (Option<String>, String) {
+        (
+            self.purchase_id.clone(),
+            self.verification_data.server_verification_data.clone(),
+        )
+    }
+
+    /// The verification data in the [`VerificationPayload`] format, for
+    /// sending to the app's server.
+    ///
+    /// # Errors
+    ///
+    /// - `Error::InvalidPurchaseToken` if the verification data was withheld
+    ///   with `redactVerificationData`.
+    /// - `Error::FeatureNotSupported` for purchases of other stores, e.g. the
+    ///   Microsoft Store or the mock store.
+    pub fn verification_payload(&self) -> crate::Result<VerificationPayload> {
+        let data = &self.verification_data;
+        if data.server_verification_data.is_empty() {
+            return Err(crate::Error::InvalidPurchaseToken(format!(
+                "The purchase of {} has no verification data",
+                self.product_id
+            )));
+        }
+        let local = || {
+            serde_json::from_str::<serde_json::Value>(&data.local_verification_data)
+                .unwrap_or_default()
+        };
+        let (store, format, package_name, user_id) = match data.source.as_str() {
+            "google" => (
+                VerificationStore::Google,
+                VerificationFormat::PurchaseToken,
+                local()["packageName"].as_str().map(str::to_string),
+                None,
+            ),
+            "apple" => {
+                // A JWS has three base64url segments, while a receipt is a
+                // single base64 string.
+                let format = if data.server_verification_data.split('.').count() == 3 {
+                    VerificationFormat::Jws
+                } else {
+                    VerificationFormat::Receipt
+                };
+                (VerificationStore::Apple, format, None, None)
+            }
+            // The Receipt Verification Service looks receipts up by user.
+            "amazon" => (
+                VerificationStore::Amazon,
+                VerificationFormat::AmazonReceipt,
+                None,
+                local()["userId"].as_str().map(str::to_string),
+            ),
+            source => {
+                return Err(crate::Error::FeatureNotSupported(format!(
+                    "Purchases from the {source} store have no verification payload"
+                )))
+            }
+        };
+        Ok(VerificationPayload {
+            schema: VERIFICATION_PAYLOAD_SCHEMA,
+            store,
+            format,
+            data: data.server_verification_data.clone(),
+            product_id: self.product_id.clone(),
+            package_name,
+            transaction_id: self.purchase_id.clone(),
+            user_id,
+        })
+    }
+}
+
+/// Version of the [`VerificationPayload`] format, increased on breaking
+/// changes to it
+pub const VERIFICATION_PAYLOAD_SCHEMA: u32 = 1;
+
+/// Store that issued a [`VerificationPayload`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerificationStore {
+    /// The App Store
+    Apple,
+    /// Google Play
+    Google,
+    /// The Amazon Appstore
+    Amazon,
+}
+
+/// Kind of verification data of a [`VerificationPayload`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationFormat {
+    /// Google Play purchase token, for the Google Play Developer API
+    PurchaseToken,
+    /// StoreKit 2 signed transaction
+    Jws,
+    /// Base64 encoded App Store receipt, for `verifyReceipt`
+    Receipt,
+    /// Amazon Appstore receipt ID, for the Receipt Verification Service
+    AmazonReceipt,
+}
+
+/// Verification data of a purchase in one format across stores, for app
+/// servers. Fields are only added within a `schema` version.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationPayload {
+    /// Version of the format, [`VERIFICATION_PAYLOAD_SCHEMA`]
+    pub schema: u32,
+    /// Store that issued the purchase
+    pub store: VerificationStore,
+    /// Kind of `data`
+    pub format: VerificationFormat,
+    /// The purchase token, JWS, receipt or receipt ID
+    pub data: String,
+    /// Identifier of the purchased product
+    pub product_id: String,
+    /// Package name of the app, for Google Play (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_name: Option<String>,
+    /// Order ID on Google Play, transaction ID on the App Store (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<String>,
+    /// Amazon user ID the receipt belongs to, for the Amazon Appstore
+    /// (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+}
+
+/// Where an [`OwnedProduct`] comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntitlementSource {
+    /// Reported by the store since the app started
+    Store,
+    /// Read from the entitlement cache while the store is unreachable
+    Cache,
+}
+
+/// A product the user owns
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OwnedProduct {
+    /// Identifier of the owned product
+    pub product_id: String,
+    /// Identifier of the purchase granting it (optional)
+    pub purchase_id: Option<String>,
+    /// ISO datetime string when the entitlement ends (optional)
+    pub expiration_date: Option<String>,
+    /// Whether the store reported it or it was cached
+    pub source: EntitlementSource,
+}
+
+/// The products the plugin considers owned, exported by
+/// `export_entitlements` e.g. to attach to a support ticket, and read back by
+/// `import_entitlements`. Only product IDs, states and dates are kept: no
+/// tokens, receipts or order IDs.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntitlementsExport {
+    /// Layout version, `EntitlementsExport::SCHEMA_VERSION` when exported
+    /// by this plugin version
+    pub schema_version: u32,
+    /// Version of the plugin that exported the entitlements
+    pub plugin_version: String,
+    /// ISO datetime string of the export
+    pub exported_at: String,
+    /// The owned products, sorted by product ID
+    pub entitlements: Vec<ExportedEntitlement>,
+}
+
+impl EntitlementsExport {
+    /// Layout version written and read by this plugin version.
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    /// Exports `entitlements` as of now.
+    pub(crate) fn new(entitlements: Vec<ExportedEntitlement>) -> Self {
+        Self {
+            schema_version: Self::SCHEMA_VERSION,
+            plugin_version: env!("CARGO_PKG_VERSION").into(),
+            exported_at: iso8601(crate::time::now_ms().div_euclid(1000)),
+            entitlements,
+        }
+    }
+
+    /// Reads an export, checking its schema version before its content.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::DeveloperError` if `json` isn't an export of
+    /// `SCHEMA_VERSION`.
+    pub fn parse(json: &str) -> crate::Result<Self> {
+        let invalid = |e: serde_json::Error| {
+            crate::Error::DeveloperError(format!("Invalid entitlements export: {e}"))
+        };
+        let value: serde_json::Value = serde_json::from_str(json).map_err(invalid)?;
+        check_schema_version(&value["schemaVersion"])?;
+        serde_json::from_value(value).map_err(invalid)
+    }
+
+    /// Fails with `Error::DeveloperError` unless the export is of
+    /// `SCHEMA_VERSION`.
+    pub(crate) fn check_schema_version(&self) -> crate::Result<()> {
+        check_schema_version(&self.schema_version.into())
+    }
+
+    /// Fails with `Error::FeatureNotSupported` unless this is a debug build
+    /// or the mock store serves calls, as imports rewrite what the user owns.
+    pub(crate) fn check_import_allowed(debug_build: bool, mock_store: bool) -> crate::Result<()> {
+        if debug_build || mock_store {
+            return Ok(());
+        }
+        Err(crate::Error::FeatureNotSupported(
+            "Entitlements can only be imported in debug builds or into the mock store".into(),
+        ))
+    }
+
+    /// Serializes the export.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+fn check_schema_version(version: &serde_json::Value) -> crate::Result<()> {
+    if version.as_u64() == Some(EntitlementsExport::SCHEMA_VERSION.into()) {
+        return Ok(());
+    }
+    Err(crate::Error::DeveloperError(format!(
+        "Entitlements export of schema version {version}, this plugin reads version {}",
+        EntitlementsExport::SCHEMA_VERSION
+    )))
+}
+
+/// A product in an [`EntitlementsExport`]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedEntitlement {
+    /// Identifier of the owned product
+    pub product_id: String,
+    /// Status of the purchase granting it
+    pub status: PurchaseStatus,
+    /// Billing state, for subscriptions the store reported one of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subscription_state: Option<SubscriptionState>,
+    /// ISO datetime string of the transaction (optional)
+    #[serde(default)]
+    pub transaction_date: Option<String>,
+    /// ISO datetime string when the entitlement ends (optional)
+    #[serde(default)]
+    pub expiration_date: Option<String>,
+    /// ISO datetime string when the grace period ends (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grace_period_expiration_date: Option<String>,
+    /// Whether the store reported it or it was cached
+    pub source: EntitlementSource,
+}
+
+impl ExportedEntitlement {
+    /// The entitlement as a purchase without verification data, as the
+    /// store would report it.
+    pub(crate) fn to_purchase(&self) -> PurchaseDetails {
+        let mut purchase = PurchaseDetails {
+            purchase_id: None,
+            product_id: self.product_id.clone(),
+            verification_data: PurchaseVerificationData::default(),
+            transaction_date: None,
+            transaction_date_ms: None,
+            expiration_date: self.expiration_date.clone(),
+            subscription_state: self.subscription_state,
+            grace_period_expiration_date: self.grace_period_expiration_date.clone(),
+            status: self.status.clone(),
+            error: None,
+            pending_complete_purchase: false,
+            verified: false,
+        };
+        if let Some(secs) = self.transaction_date.as_deref().and_then(parse_iso8601) {
+            purchase.set_transaction_date_ms(secs * 1000);
+        }
+        purchase
+    }
 }
 
 /// Parameters for initiating a purchase
@@ -91,7 +477,7 @@ pub struct PurchaseParam {
 }
 
 /// Response from querying product details
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ProductDetailsResponse {
     /// Array of found product details
@@ -101,3 +487,445 @@ pub struct ProductDetailsResponse {
     /// Error information if the query partially failed (optional)
     pub error: Option<IAPError>,
 }
+
+/// Billing state of a subscription
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SubscriptionState {
+    /// Paid for the current period
+    Active,
+    /// A renewal payment failed and the store is retrying it, while the user
+    /// keeps access until the grace period ends. Ask the user to fix their
+    /// payment method rather than to buy again.
+    GracePeriod,
+    /// The grace period ended without a payment: account hold on Google
+    /// Play, billing retry on the App Store. The user has no access until
+    /// they fix their payment method, which resumes the subscription.
+    #[serde(alias = "billingRetry")]
+    OnHold,
+    /// Ended without renewing
+    Expired,
+    /// Refunded or revoked by the store
+    Revoked,
+}
+
+impl SubscriptionState {
+    /// Whether the subscription grants access in this state, which holds
+    /// during the grace period but not on hold.
+    pub fn is_entitled(self) -> bool {
+        matches!(self, Self::Active | Self::GracePeriod)
+    }
+}
+
+/// State of a subscription the user bought
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionStatus {
+    /// Identifier of the subscription product
+    pub product_id: String,
+    /// Whether the subscription currently grants access
+    pub is_active: bool,
+    /// Billing state of the subscription
+    pub state: SubscriptionState,
+    /// ISO datetime string when the grace period ends, in the `GracePeriod`
+    /// state if the store reports it (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grace_period_expiration_date: Option<String>,
+    /// ISO datetime string when the current period ends (optional)
+    pub expiration_date: Option<String>,
+    /// Whether the subscription renews at the end of the period, if the
+    /// store reports it (optional)
+    pub will_auto_renew: Option<bool>,
+}
+
+impl SubscriptionStatus {
+    /// Time left to the subscriber at `now`: until the end of the grace
+    /// period while in one, otherwise until the end of the current period.
+    /// Zero once access ended, e.g. expired or on hold, and `None` if the
+    /// store reported no end.
+    pub fn time_remaining(&self, now: SystemTime) -> Option<Duration> {
+        if !self.state.is_entitled() {
+            return Some(Duration::ZERO);
+        }
+        let end = match self.state {
+            SubscriptionState::GracePeriod => self.grace_period_expiration_date.as_deref(),
+            _ => self.expiration_date.as_deref(),
+        }?;
+        let end = UNIX_EPOCH + Duration::from_secs(parse_iso8601(end)?.try_into().ok()?);
+        Some(end.duration_since(now).unwrap_or(Duration::ZERO))
+    }
+}
+
+/// Store capability checked with `is_feature_supported`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StoreFeature {
+    /// Selling subscriptions
+    Subscriptions,
+    /// Upgrading or downgrading a subscription the user has
+    SubscriptionsUpdate,
+    /// Asking the user to accept a subscription price increase
+    PriceChangeConfirmation,
+    /// In-app messages of the store, e.g. about a declined payment
+    InAppMessaging,
+    /// Linking to offers outside of the store
+    ExternalOffer,
+}
+
+impl std::str::FromStr for StoreFeature {
+    type Err = crate::Error;
+
+    /// Parses the camelCase name. Fails with `Error::FeatureNotSupported`
+    /// for names this version doesn't know, e.g. sent by newer bindings.
+    fn from_str(s: &str) -> crate::Result<Self> {
+        serde_json::from_value(serde_json::Value::String(s.to_string()))
+            .map_err(|_| crate::Error::FeatureNotSupported(format!("Unknown store feature: {s}")))
+    }
+}
+
+/// Where the app was installed from, reported by `install_source`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InstallSource {
+    /// Google Play
+    PlayStore,
+    /// The Amazon Appstore
+    AmazonStore,
+    /// An APK installed outside of any store, e.g. shared as a file, which
+    /// can't buy through Google Play
+    Sideload,
+    /// A TestFlight build, buying in the App Store sandbox
+    TestFlight,
+    /// The App Store on iOS
+    AppStore,
+    /// The Mac App Store
+    MacAppStore,
+    /// A development build: debuggable, run from Xcode or the Simulator, or
+    /// signed with a development or ad hoc profile
+    DevBuild,
+    /// Anything else, e.g. another store or a desktop build outside of a
+    /// store
+    Unknown,
+}
+
+/// Installer package names of Google Play.
+const PLAY_STORE_INSTALLERS: &[&str] = &["com.android.vending", "com.google.android.feedback"];
+
+/// Installer package names of the Amazon Appstore.
+const AMAZON_STORE_INSTALLERS: &[&str] = &["com.amazon.venezia"];
+
+/// Installers of APKs from outside a store: the system package installer
+/// and `adb`.
+const SIDELOAD_INSTALLERS: &[&str] = &[
+    "com.android.packageinstaller",
+    "com.google.android.packageinstaller",
+    "com.android.shell",
+];
+
+impl InstallSource {
+    /// Maps the installer package reported by Android's `PackageManager`,
+    /// `None` when no app is recorded as the installer. Debuggable builds are
+    /// development builds whatever installed them.
+    pub fn from_android_installer(installer_package_name: Option<&str>, debuggable: bool) -> Self {
+        match installer_package_name {
+            _ if debuggable => Self::DevBuild,
+            Some(installer) if PLAY_STORE_INSTALLERS.contains(&installer) => Self::PlayStore,
+            Some(installer) if AMAZON_STORE_INSTALLERS.contains(&installer) => Self::AmazonStore,
+            Some(installer) if !SIDELOAD_INSTALLERS.contains(&installer) => Self::Unknown,
+            _ => Self::Sideload,
+        }
+    }
+
+    /// Maps the App Store environment the app was signed for, `xcode`,
+    /// `sandbox` for TestFlight or `production`, `None` when unknown.
+    /// Debuggable builds are development builds whatever their environment.
+    pub fn from_app_store_environment(environment: Option<&str>, debuggable: bool, macos: bool) -> Self {
+        match environment {
+            _ if debuggable => Self::DevBuild,
+            Some("xcode") => Self::DevBuild,
+            Some("sandbox") => Self::TestFlight,
+            Some("production") if macos => Self::MacAppStore,
+            Some("production") => Self::AppStore,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// How Google Play bills a change from one subscription to another, the
+/// `ReplacementMode` of the billing library. The App Store decides by itself,
+/// upgrading right away and downgrading at the next renewal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReplacementMode {
+    /// Switches right away. The unused time of the old subscription is
+    /// credited as time on the new one, and billing continues at the new
+    /// price once it runs out.
+    WithTimeProration,
+    /// Switches right away and charges the price difference for the rest of
+    /// the current period, keeping the billing date. Only for upgrades to a
+    /// more expensive subscription per unit of time.
+    ChargeProratedPrice,
+    /// Switches right away without charging, and bills the new price from
+    /// the next renewal date.
+    WithoutProration,
+    /// Switches right away and charges the full price of the new
+    /// subscription, starting a new billing period. The unused time of the
+    /// old one is credited as time on the new one.
+    ChargeFullPrice,
+    /// Switches and bills the new price when the current subscription
+    /// expires, e.g. for downgrades.
+    Deferred,
+}
+
+impl ReplacementMode {
+    /// Every replacement mode.
+    pub const ALL: &'static [ReplacementMode] = &[
+        ReplacementMode::WithTimeProration,
+        ReplacementMode::ChargeProratedPrice,
+        ReplacementMode::WithoutProration,
+        ReplacementMode::ChargeFullPrice,
+        ReplacementMode::Deferred,
+    ];
+
+    /// The camelCase name, as serialized.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReplacementMode::WithTimeProration => "withTimeProration",
+            ReplacementMode::ChargeProratedPrice => "chargeProratedPrice",
+            ReplacementMode::WithoutProration => "withoutProration",
+            ReplacementMode::ChargeFullPrice => "chargeFullPrice",
+            ReplacementMode::Deferred => "deferred",
+        }
+    }
+}
+
+/// Google's `SubscriptionUpdateParams.ReplacementMode` constant.
+impl From<ReplacementMode> for i32 {
+    fn from(mode: ReplacementMode) -> Self {
+        match mode {
+            ReplacementMode::WithTimeProration => 1,
+            ReplacementMode::ChargeProratedPrice => 2,
+            ReplacementMode::WithoutProration => 3,
+            ReplacementMode::ChargeFullPrice => 5,
+            ReplacementMode::Deferred => 6,
+        }
+    }
+}
+
+impl TryFrom<i32> for ReplacementMode {
+    type Error = crate::Error;
+
+    /// Maps Google's constant back. Fails with `Error::DeveloperError` for
+    /// `UNKNOWN_REPLACEMENT_MODE` (0), the retired `4` and anything else.
+    fn try_from(value: i32) -> crate::Result<Self> {
+        ReplacementMode::ALL
+            .iter()
+            .copied()
+            .find(|mode| i32::from(*mode) == value)
+            .ok_or_else(|| {
+                crate::Error::DeveloperError(format!("Unknown replacement mode constant: {value}"))
+            })
+    }
+}
+
+impl std::str::FromStr for ReplacementMode {
+    type Err = crate::Error;
+
+    /// Parses the camelCase name. Fails with `Error::DeveloperError` listing
+    /// the valid names otherwise.
+    fn from_str(s: &str) -> crate::Result<Self> {
+        ReplacementMode::ALL
+            .iter()
+            .copied()
+            .find(|mode| mode.as_str() == s)
+            .ok_or_else(|| {
+                let valid: Vec<_> = ReplacementMode::ALL.iter().map(|mode| mode.as_str()).collect();
+                crate::Error::DeveloperError(format!(
+                    "Unknown replacement mode `{s}`, expected one of: {}",
+                    valid.join(", ")
+                ))
+            })
+    }
+}
+
+impl std::fmt::Display for ReplacementMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Details to report a purchase made through alternative billing to Google
+/// Play
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlternativeBillingReportingDetails {
+    /// Token identifying the external transaction in the report
+    pub external_transaction_token: String,
+}
+
+/// Outcome of the App Store notice sheet shown before leaving the app for an
+/// external purchase
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalPurchaseLinkResult {
+    /// Whether the user continued to the external purchase link
+    pub continued: bool,
+}
+
+/// Token to report an external purchase to Apple
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalPurchaseToken {
+    /// Token issued by the App Store, sent from the app's server in the
+    /// External Purchase Server API report
+    pub token: String,
+}
+
+/// Payload of the restore completed event, emitted once a restore finished
+/// reporting the owned purchases.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreCompleted {
+    /// Number of purchases reported with the `Restored` status
+    pub restored_count: usize,
+}
+
+/// State of the connection to the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConnectionState {
+    /// `initialize` has not succeeded yet, or reconnecting gave up
+    Uninitialized,
+    /// `initialize` is connecting to the store
+    Initializing,
+    /// Connected to the store
+    Ready,
+    /// The connection dropped and is being re-established
+    Reconnecting,
+    /// `end_connection` was called or the plugin was torn down, until
+    /// `initialize` connects again
+    Closed,
+}
+
+impl ConnectionState {
+    /// Whether the connection may move from this state to `next`.
+    pub fn can_transition_to(self, next: ConnectionState) -> bool {
+        use ConnectionState::*;
+        matches!(
+            (self, next),
+            (Uninitialized, Initializing)
+                | (Initializing, Ready | Uninitialized)
+                | (Ready, Reconnecting)
+                | (Reconnecting, Ready | Uninitialized)
+                | (Uninitialized | Initializing | Ready | Reconnecting, Closed)
+                | (Closed, Initializing)
+        )
+    }
+}
+
+/// Payload of the connection state event, emitted on every transition
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionStateChange {
+    /// State before the transition
+    pub previous: ConnectionState,
+    /// State after the transition
+    pub state: ConnectionState,
+    /// Reconnection attempt about to be made, starting at 1, emitted again
+    /// for each attempt while `reconnecting`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attempt: Option<u32>,
+    /// Whether the device has a store to buy from, `false` once connecting
+    /// failed with `StoreUnavailable`, e.g. without Google Play
+    #[serde(default = "present")]
+    pub store_present: bool,
+}
+
+fn present() -> bool {
+    true
+}
+
+/// License of the app: the Microsoft Store license, the App Store app
+/// transaction, or the license declared with `androidAppLicense`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AppLicense {
+    /// Whether the license is valid and grants access to the app
+    pub is_active: bool,
+    /// Whether the license is a trial
+    pub is_trial: bool,
+    /// ISO datetime string when the trial ends (optional)
+    #[serde(default)]
+    pub trial_expiration_date: Option<String>,
+    /// App version the user first bought or downloaded, e.g. to keep
+    /// features for users of a paid version after the app went free. Only
+    /// reported by the App Store, as the `CFBundleVersion` on iOS and
+    /// `CFBundleShortVersionString` on macOS (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_version: Option<String>,
+    /// ISO datetime string when the user first bought or downloaded the app.
+    /// Only reported by the App Store (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_purchase_date: Option<String>,
+    /// Store ID of the licensed SKU of the app, empty outside of the
+    /// Microsoft Store
+    #[serde(default)]
+    pub sku_store_id: String,
+    /// Licenses of the durable and subscription add-ons the user owns. Only
+    /// reported by the Microsoft Store
+    #[serde(default)]
+    pub add_on_licenses: Vec<AddOnLicense>,
+}
+
+impl AppLicense {
+    /// Maps the app transaction of the App Store, which StoreKit only
+    /// reports for apps the user bought or downloaded. A transaction failing
+    /// the signature verification grants no access. The App Store has no
+    /// trial of the app itself.
+    pub fn from_app_transaction(
+        verified: bool,
+        original_version: String,
+        original_purchase_date: String,
+    ) -> Self {
+        Self {
+            is_active: verified,
+            is_trial: false,
+            trial_expiration_date: None,
+            original_version: Some(original_version),
+            original_purchase_date: Some(original_purchase_date),
+            sku_store_id: String::new(),
+            add_on_licenses: Vec::new(),
+        }
+    }
+}
+
+/// License of an add-on, as reported by the Microsoft Store
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddOnLicense {
+    /// Identifier of the licensed product
+    pub product_id: String,
+    /// Store ID of the licensed SKU, the product ID followed by the SKU
+    pub sku_store_id: String,
+    /// Developer defined product ID of the add-on
+    pub in_app_offer_token: String,
+    /// Whether the license is valid
+    pub is_active: bool,
+    /// ISO datetime string when the license ends, for subscriptions and
+    /// time-limited add-ons (optional)
+    pub expiration_date: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_are_rejected_in_release_builds_without_the_mock_store() {
+        let error = EntitlementsExport::check_import_allowed(false, false).unwrap_err();
+        assert_eq!(error.code(), IapErrorCode::FeatureNotSupported);
+
+        assert!(EntitlementsExport::check_import_allowed(true, false).is_ok());
+        assert!(EntitlementsExport::check_import_allowed(false, true).is_ok());
+    }
+}
@@ -0,0 +1,13 @@
+use std::path::Path;
+
+/// Replaces the file at `path` with `bytes` through a temporary file next to
+/// it, so a crash while writing leaves the previous contents. Creates the
+/// parent directory if needed.
+pub(crate) fn write_atomically(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("json.tmp");
+    std::fs::write(&temp, bytes)?;
+    std::fs::rename(&temp, path)
+}
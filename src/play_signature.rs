@@ -0,0 +1,228 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rsa::{
+    pkcs1v15::{Signature, VerifyingKey},
+    pkcs8::DecodePublicKey,
+    signature::Verifier,
+    RsaPublicKey,
+};
+use sha1::Sha1;
+
+use crate::models::{IAPError, PurchaseDetails, PurchaseStatus};
+use crate::{Error, IapErrorCode};
+
+/// The app's Google Play licensing key, which signs the purchase data
+/// (`INAPP_PURCHASE_DATA`) with RSA-SHA1.
+pub(crate) struct PlayPublicKey(VerifyingKey<Sha1>);
+
+impl PlayPublicKey {
+    /// Parses the base64 encoded key shown under Monetization setup in the
+    /// Play Console.
+    pub(crate) fn parse(key: &str) -> crate::Result<Self> {
+        let der = STANDARD
+            .decode(key.split_whitespace().collect::<String>())
+            .map_err(|e| invalid_key(&e))?;
+        let key = RsaPublicKey::from_public_key_der(&der).map_err(|e| invalid_key(&e))?;
+        Ok(Self(VerifyingKey::new(key)))
+    }
+
+    /// Checks `signature`, base64 encoded, against the purchase data exactly
+    /// as Google Play reported it.
+    pub(crate) fn verify(&self, data: &str, signature: &str) -> bool {
+        let Ok(signature) = STANDARD.decode(signature) else {
+            return false;
+        };
+        let Ok(signature) = Signature::try_from(signature.as_slice()) else {
+            return false;
+        };
+        self.0.verify(data.as_bytes(), &signature).is_ok()
+    }
+
+    /// Sets `verified` on each Google Play purchase, others are left as is.
+    /// With `reject`, purchases that fail
+    /// verification are reported with the `Error` status and an
+    /// `invalidPurchaseToken` error instead.
+    pub(crate) fn verify_purchases(&self, purchases: &mut [PurchaseDetails], reject: bool) {
+        for purchase in purchases.iter_mut().filter(|p| p.verification_data.source == "google") {
+            let data = &purchase.verification_data;
+            purchase.verified = data
+                .signature
+                .as_deref()
+                .is_some_and(|signature| self.verify(&data.local_verification_data, signature));
+            if purchase.verified || !reject {
+                continue;
+            }
+            log::warn!(
+                "Rejecting the purchase of {}, its Google Play signature is invalid",
+                purchase.product_id
+            );
+            purchase.status = PurchaseStatus::Error;
+            purchase.pending_complete_purchase = false;
+            purchase.error = Some(IAPError {
+                code: IapErrorCode::InvalidPurchaseToken.to_string(),
+                message: "The purchase signature doesn't match googlePlayPublicKey".into(),
+                details: None,
+                native_code: None,
+            });
+        }
+    }
+}
+
+fn invalid_key(error: &dyn std::fmt::Display) -> Error {
+    Error::InvalidConfig(format!("googlePlayPublicKey is not a valid RSA public key: {error}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    /// A purchase signed with a key generated for the tests, see the fixture.
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Vectors {
+        public_key: String,
+        other_public_key: String,
+        purchase_data: String,
+        signature: String,
+        other_signature: String,
+    }
+
+    fn vectors() -> Vectors {
+        serde_json::from_str(include_str!(
+            "../tests/fixtures/play_signature/vectors.json"
+        ))
+        .unwrap()
+    }
+
+    fn purchase(source: &str, data: &str, signature: Option<&str>) -> PurchaseDetails {
+        serde_json::from_value(json!({
+            "purchaseId": "GPA.3312-5678-9012-34567",
+            "productId": "premium",
+            "verificationData": {
+                "localVerificationData": data,
+                "serverVerificationData": "kdjfhgkdjfhgkdjf.AO-J1OxExampleToken",
+                "source": source,
+                "signature": signature,
+            },
+            "status": "purchased",
+            "pendingCompletePurchase": true,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn accepts_the_signature_of_the_purchase_data() {
+        let vectors = vectors();
+        let key = PlayPublicKey::parse(&vectors.public_key).unwrap();
+
+        assert!(key.verify(&vectors.purchase_data, &vectors.signature));
+    }
+
+    #[test]
+    fn parses_keys_pasted_over_several_lines() {
+        let vectors = vectors();
+        let wrapped: Vec<&str> = vectors
+            .public_key
+            .as_bytes()
+            .chunks(64)
+            .map(|line| std::str::from_utf8(line).unwrap())
+            .collect();
+        let key = PlayPublicKey::parse(&format!("  {}\n", wrapped.join("\n"))).unwrap();
+
+        assert!(key.verify(&vectors.purchase_data, &vectors.signature));
+    }
+
+    #[test]
+    fn rejects_tampered_data_and_signatures() {
+        let vectors = vectors();
+        let key = PlayPublicKey::parse(&vectors.public_key).unwrap();
+
+        let tampered = vectors.purchase_data.replace("premium", "premium_yearly");
+        assert!(!key.verify(&tampered, &vectors.signature));
+        // Google signs the data exactly as reported, reformatting breaks it.
+        let reformatted = serde_json::to_string_pretty(
+            &serde_json::from_str::<serde_json::Value>(&vectors.purchase_data).unwrap(),
+        )
+        .unwrap();
+        assert!(!key.verify(&reformatted, &vectors.signature));
+
+        let mut signature = STANDARD.decode(&vectors.signature).unwrap();
+        signature[17] ^= 1;
+        assert!(!key.verify(&vectors.purchase_data, &STANDARD.encode(&signature)));
+        // Signed by another key.
+        assert!(!key.verify(&vectors.purchase_data, &vectors.other_signature));
+        let other = PlayPublicKey::parse(&vectors.other_public_key).unwrap();
+        assert!(!other.verify(&vectors.purchase_data, &vectors.signature));
+    }
+
+    #[test]
+    fn rejects_malformed_signatures() {
+        let vectors = vectors();
+        let key = PlayPublicKey::parse(&vectors.public_key).unwrap();
+
+        for signature in ["", "not base64!", "AAAA", &vectors.signature[..100]] {
+            assert!(!key.verify(&vectors.purchase_data, signature), "{signature:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_keys() {
+        let vectors = vectors();
+        let truncated = STANDARD.encode(&STANDARD.decode(&vectors.public_key).unwrap()[..100]);
+
+        for key in ["", "not base64!", "AAAA", &truncated] {
+            let error = PlayPublicKey::parse(key).err().unwrap();
+            assert_eq!(error.code(), IapErrorCode::InvalidConfig, "{key:?}");
+        }
+    }
+
+    #[test]
+    fn marks_google_purchases_verified() {
+        let vectors = vectors();
+        let key = PlayPublicKey::parse(&vectors.public_key).unwrap();
+        let mut purchases = [
+            purchase("google", &vectors.purchase_data, Some(&vectors.signature)),
+            purchase("google", &vectors.purchase_data, Some(&vectors.other_signature)),
+            purchase("google", &vectors.purchase_data, None),
+            purchase("amazon", "{}", None),
+        ];
+
+        key.verify_purchases(&mut purchases, false);
+
+        let verified: Vec<bool> = purchases.iter().map(|p| p.verified).collect();
+        assert_eq!(verified, [true, false, false, false]);
+        // Reported as is, for the app to decide.
+        assert!(purchases
+            .iter()
+            .all(|p| matches!(p.status, PurchaseStatus::Purchased) && p.error.is_none()));
+    }
+
+    #[test]
+    fn rejects_unverified_google_purchases_when_configured() {
+        let vectors = vectors();
+        let key = PlayPublicKey::parse(&vectors.public_key).unwrap();
+        let tampered = vectors.purchase_data.replace("premium", "premium_yearly");
+        let mut purchases = [
+            purchase("google", &vectors.purchase_data, Some(&vectors.signature)),
+            purchase("google", &tampered, Some(&vectors.signature)),
+            purchase("amazon", "{}", None),
+        ];
+
+        key.verify_purchases(&mut purchases, true);
+
+        assert!(purchases[0].verified);
+        assert!(matches!(purchases[0].status, PurchaseStatus::Purchased));
+        let rejected = &purchases[1];
+        assert!(matches!(rejected.status, PurchaseStatus::Error));
+        assert!(!rejected.pending_complete_purchase);
+        assert_eq!(
+            rejected.error.as_ref().map(|e| e.code.as_str()),
+            Some("invalidPurchaseToken")
+        );
+        // Other stores are left to their own checks.
+        assert!(matches!(purchases[2].status, PurchaseStatus::Purchased));
+        assert!(purchases[2].error.is_none());
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::models::{ProductDetails, ProductDetailsResponse};
+use crate::persist::write_atomically;
+use crate::time::now_ms;
+
+/// File in the app data directory keeping the preloaded products.
+const PRODUCT_CACHE_FILE: &str = "iap-products.json";
+
+/// Migrates a file of schema version `n + 1` to version `n + 2`, so
+/// `MIGRATIONS.len() + 1` is always `CachedProducts::SCHEMA_VERSION`.
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[v1_to_v2];
+
+/// Version 2 added `cachedAtMs`. Version 1 files don't tell when they were
+/// written, so they count as cached at the Unix epoch.
+fn v1_to_v2(file: &mut Map<String, Value>) {
+    file.insert("cachedAtMs".into(), Value::from(0));
+}
+
+/// Product details kept on disk, e.g. for an offline paywall, with the
+/// version of their layout so a file written by another plugin version is
+/// migrated or discarded instead of misread.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedProducts {
+    /// Layout version of the file, `CachedProducts::SCHEMA_VERSION` when
+    /// written by this plugin version
+    pub schema_version: u32,
+    /// Version of the plugin that wrote the file
+    pub plugin_version: String,
+    /// Milliseconds since the Unix epoch when the products were cached, `0`
+    /// if unknown
+    pub cached_at_ms: i64,
+    /// The cached products
+    pub response: ProductDetailsResponse,
+}
+
+impl CachedProducts {
+    /// Layout version written by this plugin version. Raise it with a
+    /// migration in `MIGRATIONS` whenever a model change would make older
+    /// files read differently.
+    pub const SCHEMA_VERSION: u32 = 2;
+
+    /// Wraps `response` as cached now by this plugin version.
+    pub fn new(response: ProductDetailsResponse) -> Self {
+        Self {
+            schema_version: Self::SCHEMA_VERSION,
+            plugin_version: env!("CARGO_PKG_VERSION").into(),
+            cached_at_ms: now_ms(),
+            response,
+        }
+    }
+
+    /// Reads a cache file, migrating files of older schema versions. Files
+    /// that are unreadable, of an unknown or newer version, or fail to
+    /// migrate are discarded with a warning, returning `None`.
+    pub fn from_json(json: &[u8]) -> Option<Self> {
+        let mut file = match serde_json::from_slice::<Map<String, Value>>(json) {
+            Ok(file) => file,
+            Err(e) => {
+                log::warn!("Discarding cached products: {}", e);
+                return None;
+            }
+        };
+        let version = file
+            .get("schemaVersion")
+            .and_then(Value::as_u64)
+            .and_then(|version| u32::try_from(version).ok());
+        let version = match version {
+            Some(version @ 1..=Self::SCHEMA_VERSION) => version,
+            _ => {
+                log::warn!(
+                    "Discarding cached products of schema version {}, this plugin reads up to {}",
+                    file.get("schemaVersion").unwrap_or(&Value::Null),
+                    Self::SCHEMA_VERSION
+                );
+                return None;
+            }
+        };
+        for migrate in &MIGRATIONS[version as usize - 1..] {
+            migrate(&mut file);
+        }
+        file.insert("schemaVersion".into(), Value::from(Self::SCHEMA_VERSION));
+        serde_json::from_value(Value::Object(file))
+            .inspect_err(|e| log::warn!("Discarding cached products of schema version {}: {}", version, e))
+            .ok()
+    }
+
+    /// Serializes the cache file.
+    pub fn to_json(&self) -> serde_json::Result<Vec<u8>> {
+        serde_json::to_vec_pretty(self)
+    }
+}
+
+/// Product details preloaded with `preloadProducts`, served to queries
+/// without reaching the store. With `persistProducts` they are also kept in
+/// the app data directory for the next run.
+#[derive(Default)]
+pub(crate) struct ProductCache {
+    path: Option<PathBuf>,
+    products: Mutex<HashMap<String, ProductDetails>>,
+}
+
+impl ProductCache {
+    /// Loads the products kept in the app data directory.
+    pub(crate) fn in_app_data_dir<R: Runtime>(app: &AppHandle<R>) -> Self {
+        let path = app
+            .path()
+            .app_data_dir()
+            .map(|dir| dir.join(PRODUCT_CACHE_FILE))
+            .inspect_err(|e| log::warn!("Products won't persist: {}", e))
+            .ok();
+        Self::load(path)
+    }
+
+    /// Loads the products from `path`, or keeps them in memory without one.
+    pub(crate) fn load(path: Option<PathBuf>) -> Self {
+        let cached = match &path {
+            Some(path) => match std::fs::read(path) {
+                Ok(json) => CachedProducts::from_json(&json),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    log::warn!("Failed to read cached products at {}: {}", path.display(), e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let products = cached
+            .map(|cached| cached.response.product_details)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|product| (product.id.clone(), product))
+            .collect();
+        Self {
+            path,
+            products: Mutex::new(products),
+        }
+    }
+
+    pub(crate) fn insert(&self, products: &[ProductDetails]) {
+        let mut cached = self.lock();
+        for product in products {
+            cached.insert(product.id.clone(), product.clone());
+        }
+        self.save(&cached);
+    }
+
+    /// Drops every cached product, e.g. when the storefront changed and
+    /// their prices with it.
+    pub(crate) fn clear(&self) {
+        let mut cached = self.lock();
+        cached.clear();
+        self.save(&cached);
+    }
+
+    /// Splits `product_ids` into the cached products and the IDs that still
+    /// have to be queried, keeping their order.
+    pub(crate) fn lookup(&self, product_ids: &[String]) -> (Vec<ProductDetails>, Vec<String>) {
+        let cached = self.lock();
+        let mut found = Vec::new();
+        let mut missing = Vec::new();
+        for id in product_ids {
+            match cached.get(id) {
+                Some(product) => found.push(product.clone()),
+                None => missing.push(id.clone()),
+            }
+        }
+        (found, missing)
+    }
+
+    /// Writes the products to the file, or removes it once there are none.
+    /// Failures only log a warning, as the products are still kept in memory.
+    fn save(&self, products: &HashMap<String, ProductDetails>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = if products.is_empty() {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            }
+        } else {
+            let mut product_details: Vec<ProductDetails> = products.values().cloned().collect();
+            product_details.sort_by(|a, b| a.id.cmp(&b.id));
+            let cached = CachedProducts::new(ProductDetailsResponse {
+                product_details,
+                ..Default::default()
+            });
+            cached
+                .to_json()
+                .map_err(std::io::Error::other)
+                .and_then(|json| write_atomically(path, &json))
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to cache products at {}: {}", path.display(), e);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, ProductDetails>> {
+        self.products.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,87 @@
+use crate::{Error, Result};
+
+/// Longest product ID Google Play accepts.
+const GOOGLE_PLAY_MAX_LEN: usize = 139;
+
+/// The product ID format of a store, checked before IDs reach it so typos
+/// fail with the rule they break instead of a generic not found.
+///
+/// Stores without a documented format, like the Microsoft Store whose Store
+/// IDs are uppercase, aren't checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProductIdRules {
+    /// Lowercase letters, digits, underscores and dots, starting with a
+    /// letter or digit, at most 139 characters.
+    GooglePlay,
+    /// Letters, digits, underscores and dots.
+    AppStore,
+}
+
+impl ProductIdRules {
+    /// The rules of the store serving this platform, if it has any.
+    #[cfg(mobile)]
+    pub(crate) fn platform() -> Option<Self> {
+        if cfg!(target_os = "ios") {
+            Some(Self::AppStore)
+        } else if cfg!(feature = "amazon-appstore") {
+            None
+        } else {
+            Some(Self::GooglePlay)
+        }
+    }
+
+    /// Checks every product ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ProductQueryError` naming each invalid ID and the rule
+    /// it breaks.
+    pub fn validate<S: AsRef<str>>(self, product_ids: &[S]) -> Result<()> {
+        let invalid: Vec<String> = product_ids
+            .iter()
+            .map(AsRef::as_ref)
+            .filter_map(|id| self.violation(id).map(|rule| format!("{id:?} ({rule})")))
+            .collect();
+        if invalid.is_empty() {
+            return Ok(());
+        }
+        Err(Error::ProductQueryError(format!(
+            "Invalid product IDs: {}",
+            invalid.join(", ")
+        )))
+    }
+
+    /// The first rule `id` breaks.
+    fn violation(self, id: &str) -> Option<&'static str> {
+        if id.is_empty() {
+            return Some("empty");
+        }
+        if id.trim() != id {
+            return Some("leading or trailing whitespace");
+        }
+        match self {
+            Self::GooglePlay => {
+                let allowed = |c: char| {
+                    c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '.'
+                };
+                if id.len() > GOOGLE_PLAY_MAX_LEN {
+                    Some("Google Play product IDs are at most 139 characters")
+                } else if !id.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit()) {
+                    Some("Google Play product IDs start with a lowercase letter or digit")
+                } else if !id.chars().all(allowed) {
+                    Some("Google Play product IDs only contain lowercase letters, digits, underscores and dots")
+                } else {
+                    None
+                }
+            }
+            Self::AppStore => {
+                let allowed = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+                if id.chars().all(allowed) {
+                    None
+                } else {
+                    Some("App Store product IDs only contain letters, digits, underscores and dots")
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::models::ProductDetailsResponse;
+use crate::{retry, CancellationToken, RetryPolicy};
+
+/// Maximum number of product IDs sent to the store in a single query.
+pub(crate) const QUERY_CHUNK_SIZE: usize = 20;
+
+/// Queries the products in chunks of [`QUERY_CHUNK_SIZE`] with `query`,
+/// retrying each chunk that fails.
+///
+/// Only the chunk that failed is retried, so products found by earlier
+/// chunks are never queried or returned twice.
+pub(crate) fn query_chunks(
+    retry_policy: &RetryPolicy,
+    shutdown: &CancellationToken,
+    product_ids: &[String],
+    mut query: impl FnMut(&[String]) -> crate::Result<ProductDetailsResponse>,
+) -> crate::Result<ProductDetailsResponse> {
+    let mut response = ProductDetailsResponse::default();
+    for chunk in product_ids.chunks(QUERY_CHUNK_SIZE) {
+        let chunk_response = retry(retry_policy, shutdown, || {
+            let chunk_response = query(chunk)?;
+
+            // A query that failed outright is reported as an error so it can be
+            // retried; partial failures still return the products that were found.
+            match chunk_response.error {
+                Some(error) if chunk_response.product_details.is_empty() => Err(error.into()),
+                _ => Ok(chunk_response),
+            }
+        })?;
+        merge_query_response(&mut response, chunk_response);
+    }
+    Ok(response)
+}
+
+/// Appends the results of a chunk query, skipping products already present.
+fn merge_query_response(response: &mut ProductDetailsResponse, chunk: ProductDetailsResponse) {
+    for details in chunk.product_details {
+        if !response.product_details.iter().any(|d| d.id == details.id) {
+            response.product_details.push(details);
+        }
+    }
+    for id in chunk.not_found_ids {
+        if !response.not_found_ids.contains(&id) {
+            response.not_found_ids.push(id);
+        }
+    }
+    if chunk.error.is_some() {
+        response.error = chunk.error;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::models::{IAPError, ProductDetails};
+    use crate::Error;
+
+    fn product(id: &str) -> ProductDetails {
+        serde_json::from_value(json!({
+            "id": id,
+            "title": id,
+            "description": "",
+            "price": "$1.99",
+            "rawPrice": 1.99,
+            "currencyCode": "USD",
+            "currencySymbol": "$",
+        }))
+        .unwrap()
+    }
+
+    fn ids(count: usize) -> Vec<String> {
+        (0..count).map(|n| format!("product_{n}")).collect()
+    }
+
+    fn found(chunk: &[String]) -> ProductDetailsResponse {
+        ProductDetailsResponse {
+            product_details: chunk.iter().map(|id| product(id)).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn failed(code: &str) -> IAPError {
+        IAPError {
+            code: code.into(),
+            message: format!("{code} while querying"),
+            details: None,
+            native_code: None,
+        }
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+        }
+    }
+
+    /// Queries `product_ids` with `store`, retrying quickly.
+    fn query(
+        product_ids: &[String],
+        store: impl FnMut(&[String]) -> crate::Result<ProductDetailsResponse>,
+    ) -> crate::Result<ProductDetailsResponse> {
+        query_chunks(&policy(), &CancellationToken::new(), product_ids, store)
+    }
+
+    fn found_ids(response: &ProductDetailsResponse) -> Vec<String> {
+        response
+            .product_details
+            .iter()
+            .map(|p| p.id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn retries_a_failed_query_until_it_succeeds() {
+        let product_ids = ids(2);
+        let mut queried = Vec::new();
+        let response = query(&product_ids, |chunk| {
+            queried.push(chunk.to_vec());
+            match queried.len() {
+                1 => Err(Error::NetworkError("offline".into())),
+                2 => Ok(ProductDetailsResponse {
+                    error: Some(failed("serviceDisconnected")),
+                    ..Default::default()
+                }),
+                _ => Ok(found(chunk)),
+            }
+        })
+        .unwrap();
+
+        assert_eq!(queried, vec![product_ids.clone(); 3]);
+        assert_eq!(found_ids(&response), product_ids);
+        assert!(response.error.is_none());
+    }
+
+    #[test]
+    fn retries_only_the_failed_chunk() {
+        let product_ids = ids(QUERY_CHUNK_SIZE * 2 + 5);
+        let mut queried = Vec::new();
+        let response = query(&product_ids, |chunk| {
+            queried.push(chunk[0].clone());
+            let retries = queried.iter().filter(|first| **first == chunk[0]).count();
+            if chunk[0] == product_ids[QUERY_CHUNK_SIZE] && retries < 3 {
+                return Err(Error::ServiceTimeout);
+            }
+            Ok(found(chunk))
+        })
+        .unwrap();
+
+        let first = |n: usize| product_ids[n * QUERY_CHUNK_SIZE].clone();
+        assert_eq!(queried, [first(0), first(1), first(1), first(1), first(2)]);
+        assert_eq!(found_ids(&response), product_ids);
+    }
+
+    #[test]
+    fn keeps_partly_successful_chunks_without_retrying_them() {
+        let product_ids = ids(3);
+        let mut queries = 0;
+        let response = query(&product_ids, |chunk| {
+            queries += 1;
+            // One product is found before the query fails.
+            Ok(ProductDetailsResponse {
+                product_details: vec![product(&chunk[0]), product(&chunk[0])],
+                not_found_ids: vec![chunk[2].clone(), chunk[2].clone()],
+                error: Some(failed("networkError")),
+            })
+        })
+        .unwrap();
+
+        assert_eq!(queries, 1);
+        assert_eq!(found_ids(&response), ["product_0"]);
+        assert_eq!(response.not_found_ids, ["product_2"]);
+        assert_eq!(response.error.unwrap().code, "networkError");
+    }
+
+    #[test]
+    fn does_not_duplicate_products_reported_by_several_chunks() {
+        let product_ids = ids(QUERY_CHUNK_SIZE + 1);
+        let response = query(&product_ids, |chunk| {
+            let mut response = found(chunk);
+            // The store also reports a product of the first chunk again.
+            response.product_details.push(product("product_0"));
+            Ok(response)
+        })
+        .unwrap();
+
+        assert_eq!(found_ids(&response), product_ids);
+    }
+
+    #[test]
+    fn stops_at_a_permanent_failure() {
+        let product_ids = ids(QUERY_CHUNK_SIZE * 2);
+        let mut queries = 0;
+        let result = query(&product_ids, |_| {
+            queries += 1;
+            Ok(ProductDetailsResponse {
+                error: Some(failed("developerError")),
+                ..Default::default()
+            })
+        });
+
+        assert!(matches!(result, Err(Error::DeveloperError(_))));
+        assert_eq!(queries, 1);
+    }
+
+    #[test]
+    fn gives_up_after_the_retry_policy() {
+        let mut queries = 0;
+        let result = query_chunks(
+            &RetryPolicy::none(),
+            &CancellationToken::new(),
+            &ids(1),
+            |_| {
+                queries += 1;
+                Err::<ProductDetailsResponse, _>(Error::NetworkError("offline".into()))
+            },
+        );
+
+        assert!(matches!(result, Err(Error::NetworkError(_))));
+        assert_eq!(queries, 1);
+    }
+}
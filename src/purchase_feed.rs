@@ -0,0 +1,154 @@
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::{self, Sender};
+
+use crate::models::PurchaseDetails;
+
+/// Purchase payloads the Android billing client reports through
+/// `onPurchaseUpdate`, parsed and handed to a listener in order by a worker
+/// thread, so the billing client's callback returns without parsing receipts
+/// or waiting for completions.
+// Only the Android bridge feeds purchases, the tests drive it on desktop.
+#[cfg_attr(not(target_os = "android"), allow(dead_code))]
+pub(crate) struct PurchaseFeed {
+    payloads: Sender<String>,
+}
+
+#[cfg_attr(not(target_os = "android"), allow(dead_code))]
+impl PurchaseFeed {
+    /// Starts the worker calling `listener` with the purchases of each
+    /// payload. Malformed payloads are logged and skipped, and a panicking
+    /// listener doesn't stop the worker.
+    pub(crate) fn start(
+        listener: impl Fn(Vec<PurchaseDetails>) + Send + 'static,
+    ) -> std::io::Result<Self> {
+        let (sender, payloads) = mpsc::channel::<String>();
+        std::thread::Builder::new()
+            .name("iap-purchase-updates".into())
+            .spawn(move || {
+                for payload in payloads {
+                    let purchases = match serde_json::from_str::<Vec<PurchaseDetails>>(&payload) {
+                        Ok(purchases) => purchases,
+                        Err(e) => {
+                            log::error!("Ignoring malformed purchase update: {}", e);
+                            continue;
+                        }
+                    };
+                    // Keeps the worker alive for the next updates.
+                    if std::panic::catch_unwind(AssertUnwindSafe(|| listener(purchases))).is_err() {
+                        log::error!("Reporting a purchase update panicked");
+                    }
+                }
+            })?;
+        Ok(Self { payloads: sender })
+    }
+
+    /// Queues a JSON array of purchases for the worker, returning right away.
+    /// Returns `false` if the worker stopped.
+    pub(crate) fn push(&self, payload: String) -> bool {
+        self.payloads.send(payload).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// A payload of `count` purchases numbered from `first`, each carrying a
+    /// receipt of `receipt_len` bytes like a large StoreKit or Play payload.
+    fn payload(first: usize, count: usize, receipt_len: usize) -> String {
+        let receipt = "x".repeat(receipt_len);
+        let purchases: Vec<_> = (first..first + count)
+            .map(|n| {
+                json!({
+                    "purchaseId": format!("GPA.{n}"),
+                    "productId": "coins",
+                    "verificationData": {
+                        "localVerificationData": receipt,
+                        "serverVerificationData": format!("token-{n}"),
+                        "source": "google",
+                    },
+                    "transactionDateMs": 1_700_000_000_000u64 + n as u64,
+                    "status": "purchased",
+                    "pendingCompletePurchase": true,
+                })
+            })
+            .collect();
+        serde_json::to_string(&purchases).unwrap()
+    }
+
+    fn feed() -> (PurchaseFeed, mpsc::Receiver<Vec<PurchaseDetails>>) {
+        let (sender, received) = mpsc::channel();
+        let feed = PurchaseFeed::start(move |purchases| sender.send(purchases).unwrap()).unwrap();
+        (feed, received)
+    }
+
+    fn ids(purchases: &[PurchaseDetails]) -> Vec<String> {
+        purchases
+            .iter()
+            .map(|purchase| purchase.purchase_id.clone().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn reports_many_large_payloads_in_order() {
+        let (feed, received) = feed();
+        const PAYLOADS: usize = 200;
+        const PER_PAYLOAD: usize = 5;
+
+        // Queued without waiting for the worker, like the billing client's
+        // callbacks do.
+        for batch in 0..PAYLOADS {
+            assert!(feed.push(payload(batch * PER_PAYLOAD, PER_PAYLOAD, 64 * 1024)));
+        }
+
+        let mut expected = 0;
+        for _ in 0..PAYLOADS {
+            let purchases = received.recv_timeout(Duration::from_secs(30)).unwrap();
+            let want: Vec<String> = (expected..expected + PER_PAYLOAD)
+                .map(|n| format!("GPA.{n}"))
+                .collect();
+            assert_eq!(ids(&purchases), want);
+            assert_eq!(
+                purchases[0].verification_data.local_verification_data.len(),
+                64 * 1024
+            );
+            expected += PER_PAYLOAD;
+        }
+        assert!(received.try_recv().is_err());
+    }
+
+    #[test]
+    fn skips_malformed_payloads() {
+        let (feed, received) = feed();
+
+        feed.push("{ not json".into());
+        feed.push(json!([{ "productId": 3 }]).to_string());
+        feed.push(payload(0, 1, 16));
+
+        let purchases = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(ids(&purchases), ["GPA.0"]);
+    }
+
+    #[test]
+    fn keeps_reporting_after_a_listener_panic() {
+        let (sender, received) = mpsc::channel();
+        let feed = PurchaseFeed::start(move |purchases: Vec<PurchaseDetails>| {
+            if purchases[0].purchase_id.as_deref() == Some("GPA.0") {
+                panic!("listener failed");
+            }
+            sender.send(purchases).unwrap();
+        })
+        .unwrap();
+
+        feed.push(payload(0, 1, 16));
+        feed.push(payload(1, 1, 16));
+
+        let purchases = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(ids(&purchases), ["GPA.1"]);
+    }
+}
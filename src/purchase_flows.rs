@@ -0,0 +1,278 @@
+use std::collections::HashSet;
+use std::sync::{Mutex, PoisonError};
+
+use serde::Serialize;
+
+use crate::models::{IAPError, PurchaseDetails, PurchaseStatus};
+
+/// Transaction ID and purchase token, as returned by
+/// `PurchaseDetails::identifiers`.
+type Identifiers = (Option<String>, String);
+
+/// Buy flows started with a correlation ID, awaiting the purchase updates
+/// that settle them.
+///
+/// The purchase update events carry the correlation ID on the purchases of
+/// a flow, so `purchase()` in guest-js settles with its own purchase. Only
+/// purchases reported for the first time are correlated: an owned purchase
+/// reported again, e.g. by `autoRestoreOnInit` or a restore, never settles a
+/// flow.
+#[derive(Default)]
+pub(crate) struct PurchaseFlows {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Flows in the order they started.
+    flows: Vec<Flow>,
+    /// Purchases reported so far.
+    seen: HashSet<Identifiers>,
+}
+
+struct Flow {
+    correlation_id: String,
+    product_id: String,
+    /// Pending purchases of the flow, correlated again once they change
+    /// state.
+    pending: HashSet<Identifiers>,
+}
+
+/// A purchase as emitted in the purchase update event, with the correlation
+/// ID of the buy flow it settles.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CorrelatedPurchase<'a> {
+    #[serde(flatten)]
+    purchase: &'a PurchaseDetails,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+}
+
+impl PurchaseFlows {
+    /// Starts a flow buying `product_id`.
+    pub(crate) fn start(&self, product_id: &str, correlation_id: String) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state
+            .flows
+            .retain(|flow| flow.correlation_id != correlation_id);
+        state.flows.push(Flow {
+            correlation_id,
+            product_id: product_id.into(),
+            pending: HashSet::new(),
+        });
+    }
+
+    /// Forgets a flow, once its buy command failed or returned without a
+    /// purchase.
+    pub(crate) fn end(&self, correlation_id: &str) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state
+            .flows
+            .retain(|flow| flow.correlation_id != correlation_id);
+    }
+
+    /// Tags the purchases settling a flow with its correlation ID, ending it
+    /// unless the purchase is pending.
+    pub(crate) fn correlate<'a>(
+        &self,
+        purchases: &'a [PurchaseDetails],
+    ) -> Vec<CorrelatedPurchase<'a>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        purchases
+            .iter()
+            .map(|purchase| CorrelatedPurchase {
+                purchase,
+                correlation_id: state.correlate(purchase),
+            })
+            .collect()
+    }
+
+    /// Adds the correlation ID of the flow a failure without a product ends,
+    /// e.g. a Google Play purchase flow the user left, as
+    /// `details.correlationId`. The failure ends the latest flow that isn't
+    /// awaiting a pending purchase.
+    // Only the Android bridge reports such failures.
+    #[cfg_attr(not(target_os = "android"), allow(dead_code))]
+    pub(crate) fn correlate_error(&self, mut error: IAPError) -> IAPError {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(index) = state.flows.iter().rposition(|flow| flow.pending.is_empty()) else {
+            return error;
+        };
+        let details = error
+            .details
+            .get_or_insert_with(|| serde_json::Value::Object(Default::default()));
+        if let Some(details) = details.as_object_mut() {
+            let flow = state.flows.remove(index);
+            details.insert("correlationId".into(), flow.correlation_id.into());
+        }
+        error
+    }
+}
+
+impl State {
+    fn correlate(&mut self, purchase: &PurchaseDetails) -> Option<String> {
+        let identifiers = purchase.identifiers();
+        let first_report = self.seen.insert(identifiers.clone());
+        if matches!(purchase.status, PurchaseStatus::Restored) {
+            return None;
+        }
+        let index = self.flows.iter().rposition(|flow| {
+            flow.product_id == purchase.product_id
+                && (first_report || flow.pending.contains(&identifiers))
+        })?;
+        if matches!(purchase.status, PurchaseStatus::Pending) {
+            let flow = &mut self.flows[index];
+            flow.pending.insert(identifiers);
+            return Some(flow.correlation_id.clone());
+        }
+        Some(self.flows.remove(index).correlation_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn purchase(id: &str, product_id: &str, status: &str) -> PurchaseDetails {
+        serde_json::from_value(json!({
+            "purchaseId": id,
+            "productId": product_id,
+            "verificationData": {
+                "localVerificationData": "",
+                "serverVerificationData": format!("token-{id}"),
+                "source": "test"
+            },
+            "transactionDate": null,
+            "status": status,
+            "error": null,
+            "pendingCompletePurchase": true
+        }))
+        .unwrap()
+    }
+
+    fn correlation_ids(
+        flows: &PurchaseFlows,
+        purchases: &[PurchaseDetails],
+    ) -> Vec<Option<String>> {
+        flows
+            .correlate(purchases)
+            .into_iter()
+            .map(|purchase| purchase.correlation_id)
+            .collect()
+    }
+
+    #[test]
+    fn tags_the_purchase_settling_a_flow() {
+        let flows = PurchaseFlows::default();
+        flows.start("premium", "flow-1".into());
+
+        let purchases = [
+            purchase("order-1", "coins", "purchased"),
+            purchase("order-2", "premium", "purchased"),
+        ];
+        assert_eq!(
+            correlation_ids(&flows, &purchases),
+            [None, Some("flow-1".into())]
+        );
+
+        // The flow ended with its purchase.
+        let again = [purchase("order-3", "premium", "purchased")];
+        assert_eq!(correlation_ids(&flows, &again), [None]);
+    }
+
+    #[test]
+    fn serializes_the_correlation_id_with_the_purchase() {
+        let flows = PurchaseFlows::default();
+        flows.start("premium", "flow-1".into());
+        let purchases = [purchase("order-1", "premium", "purchased")];
+
+        let json = serde_json::to_value(flows.correlate(&purchases)).unwrap();
+        let mut expected = serde_json::to_value(&purchases).unwrap();
+        expected[0]["correlationId"] = "flow-1".into();
+        assert_eq!(json, expected);
+        // Purchases outside a flow are emitted unchanged.
+        let json = serde_json::to_value(flows.correlate(&purchases)).unwrap();
+        assert_eq!(json, serde_json::to_value(&purchases).unwrap());
+    }
+
+    #[test]
+    fn ignores_purchases_reported_before() {
+        let flows = PurchaseFlows::default();
+        let owned = purchase("order-1", "premium", "purchased");
+        flows.correlate(std::slice::from_ref(&owned));
+
+        flows.start("premium", "flow-1".into());
+        let restored = purchase("order-2", "premium", "restored");
+        assert_eq!(correlation_ids(&flows, &[owned, restored]), [None, None]);
+
+        let bought = [purchase("order-3", "premium", "purchased")];
+        assert_eq!(correlation_ids(&flows, &bought), [Some("flow-1".into())]);
+    }
+
+    #[test]
+    fn follows_pending_purchases_until_settled() {
+        let flows = PurchaseFlows::default();
+        flows.start("premium", "flow-1".into());
+        let pending = [purchase("order-1", "premium", "pending")];
+
+        assert_eq!(correlation_ids(&flows, &pending), [Some("flow-1".into())]);
+        assert_eq!(correlation_ids(&flows, &pending), [Some("flow-1".into())]);
+        let paid = [purchase("order-1", "premium", "purchased")];
+        assert_eq!(correlation_ids(&flows, &paid), [Some("flow-1".into())]);
+        assert_eq!(correlation_ids(&flows, &paid), [None]);
+    }
+
+    #[test]
+    fn settles_the_latest_flow_of_the_product() {
+        let flows = PurchaseFlows::default();
+        flows.start("coins", "flow-1".into());
+        flows.start("coins", "flow-2".into());
+        flows.start("premium", "flow-3".into());
+        flows.end("flow-3");
+
+        let purchases = [
+            purchase("order-1", "coins", "purchased"),
+            purchase("order-2", "coins", "canceled"),
+            purchase("order-3", "premium", "purchased"),
+        ];
+        assert_eq!(
+            correlation_ids(&flows, &purchases),
+            [Some("flow-2".into()), Some("flow-1".into()), None]
+        );
+    }
+
+    #[test]
+    fn adds_the_correlation_id_to_failures_ending_a_flow() {
+        let flows = PurchaseFlows::default();
+        let error = || IAPError {
+            code: "userCancelled".into(),
+            message: "Cancelled".into(),
+            details: None,
+            native_code: Some(1),
+        };
+        assert!(flows.correlate_error(error()).details.is_none());
+
+        flows.start("coins", "flow-1".into());
+        flows.start("premium", "flow-2".into());
+        flows.correlate(&[purchase("order-1", "premium", "pending")]);
+
+        let failed = flows.correlate_error(error());
+        assert_eq!(failed.details, Some(json!({ "correlationId": "flow-1" })));
+        assert_eq!(failed.native_code, Some(1));
+        // The pending purchase keeps its flow.
+        assert!(flows.correlate_error(error()).details.is_none());
+
+        flows.start("coins", "flow-3".into());
+        let limited = IAPError {
+            details: Some(json!({ "retryAfterMs": 1000 })),
+            ..error()
+        };
+        assert_eq!(
+            flows.correlate_error(limited).details,
+            Some(json!({ "retryAfterMs": 1000, "correlationId": "flow-3" }))
+        );
+    }
+}
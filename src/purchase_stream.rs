@@ -0,0 +1,210 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::models::{IAPError, PurchaseDetails, RestoreCompleted};
+
+/// Number of updates a purchase stream holds until its receiver reads them.
+pub const PURCHASE_STREAM_BUFFER: usize = 64;
+
+/// An update read from a purchase stream, see `Iap::purchase_stream`.
+#[derive(Debug, Clone)]
+pub enum PurchaseUpdate {
+    /// Purchases that were made, restored or changed state, as sent with the
+    /// purchase update event.
+    Purchases(Vec<PurchaseDetails>),
+    /// A restore finished reporting the owned purchases.
+    RestoreCompleted(RestoreCompleted),
+    /// A background operation failed, e.g. preloading the products, as sent
+    /// with the error event.
+    Error(IAPError),
+    /// The stream's buffer was full, so this many updates were dropped right
+    /// before the next one.
+    Lagged(usize),
+}
+
+/// The purchase streams handed out and not dropped yet.
+///
+/// Updates are sent without waiting: a stream whose buffer is full misses
+/// the updates until its receiver reads, then gets a `Lagged` update counting
+/// them. The webview and the other streams get every update either way.
+#[derive(Default)]
+pub(crate) struct PurchaseStreams {
+    streams: Mutex<Vec<Stream>>,
+}
+
+struct Stream {
+    sender: mpsc::Sender<PurchaseUpdate>,
+    /// Updates dropped since the last one sent.
+    missed: usize,
+}
+
+impl PurchaseStreams {
+    /// Opens a stream receiving the updates sent from now on.
+    pub(crate) fn subscribe(&self) -> mpsc::Receiver<PurchaseUpdate> {
+        let (sender, receiver) = mpsc::channel(PURCHASE_STREAM_BUFFER);
+        self.lock().push(Stream { sender, missed: 0 });
+        receiver
+    }
+
+    /// Sends `update` to every stream, forgetting the dropped ones.
+    pub(crate) fn send(&self, update: PurchaseUpdate) {
+        self.lock().retain_mut(|stream| stream.send(&update));
+    }
+
+    /// Ends the streams: receivers read what is buffered, then `None`.
+    pub(crate) fn close(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Stream>> {
+        self.streams.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Stream {
+    /// Sends `update` unless the buffer is full, reporting the updates missed
+    /// before it first. Returns `false` once the receiver is dropped.
+    fn send(&mut self, update: &PurchaseUpdate) -> bool {
+        if self.missed > 0 {
+            match self.sender.try_send(PurchaseUpdate::Lagged(self.missed)) {
+                Ok(()) => self.missed = 0,
+                Err(TrySendError::Full(_)) => {
+                    self.missed += 1;
+                    return true;
+                }
+                Err(TrySendError::Closed(_)) => return false,
+            }
+        }
+        match self.sender.try_send(update.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.missed += 1;
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::purchase_feed::PurchaseFeed;
+
+    fn purchase(id: &str) -> serde_json::Value {
+        json!({
+            "purchaseId": id,
+            "productId": "premium",
+            "verificationData": {
+                "localVerificationData": "{}",
+                "serverVerificationData": format!("token-{id}"),
+                "source": "google",
+            },
+            "status": "purchased",
+            "pendingCompletePurchase": true,
+        })
+    }
+
+    /// Waits for the next update, sent from another thread.
+    fn next(stream: &mut mpsc::Receiver<PurchaseUpdate>) -> PurchaseUpdate {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            match stream.try_recv() {
+                Ok(update) => return update,
+                Err(mpsc::error::TryRecvError::Empty) if Instant::now() < deadline => {
+                    std::thread::sleep(Duration::from_millis(1))
+                }
+                Err(e) => panic!("no update: {e}"),
+            }
+        }
+    }
+
+    fn purchase_ids(update: PurchaseUpdate) -> Vec<String> {
+        match update {
+            PurchaseUpdate::Purchases(purchases) => purchases
+                .into_iter()
+                .map(|purchase| purchase.purchase_id.unwrap())
+                .collect(),
+            other => panic!("expected purchases, got {other:?}"),
+        }
+    }
+
+    /// The mobile path: payloads the billing client pushes through the JNI
+    /// bridge reach every open stream, in order.
+    #[test]
+    fn bridge_purchases_reach_the_streams() {
+        let streams = Arc::new(PurchaseStreams::default());
+        let (mut first, mut second) = (streams.subscribe(), streams.subscribe());
+        let feed = {
+            let streams = streams.clone();
+            PurchaseFeed::start(move |purchases| streams.send(PurchaseUpdate::Purchases(purchases)))
+                .unwrap()
+        };
+
+        feed.push(json!([purchase("GPA.1")]).to_string());
+        feed.push(json!([purchase("GPA.2"), purchase("GPA.3")]).to_string());
+
+        for stream in [&mut first, &mut second] {
+            assert_eq!(purchase_ids(next(stream)), ["GPA.1"]);
+            assert_eq!(purchase_ids(next(stream)), ["GPA.2", "GPA.3"]);
+        }
+    }
+
+    #[test]
+    fn forgets_dropped_streams() {
+        let streams = PurchaseStreams::default();
+        let kept = streams.subscribe();
+        drop(streams.subscribe());
+
+        streams.send(PurchaseUpdate::Lagged(0));
+
+        assert_eq!(streams.lock().len(), 1);
+        drop(kept);
+        streams.send(PurchaseUpdate::Lagged(0));
+        assert!(streams.lock().is_empty());
+    }
+
+    #[test]
+    fn closed_streams_end_after_their_buffered_updates() {
+        let streams = PurchaseStreams::default();
+        let mut stream = streams.subscribe();
+        streams.send(PurchaseUpdate::Lagged(7));
+
+        streams.close();
+        streams.send(PurchaseUpdate::Lagged(8));
+
+        assert!(matches!(stream.try_recv(), Ok(PurchaseUpdate::Lagged(7))));
+        assert!(matches!(
+            stream.try_recv(),
+            Err(mpsc::error::TryRecvError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn full_streams_count_the_updates_they_miss() {
+        let streams = PurchaseStreams::default();
+        let mut stream = streams.subscribe();
+        let restored = || PurchaseUpdate::RestoreCompleted(RestoreCompleted { restored_count: 1 });
+
+        for _ in 0..PURCHASE_STREAM_BUFFER + 2 {
+            streams.send(restored());
+        }
+        for _ in 0..PURCHASE_STREAM_BUFFER {
+            assert!(matches!(
+                stream.try_recv(),
+                Ok(PurchaseUpdate::RestoreCompleted(_))
+            ));
+        }
+        streams.send(PurchaseUpdate::Lagged(0));
+
+        assert!(matches!(stream.try_recv(), Ok(PurchaseUpdate::Lagged(2))));
+        assert!(matches!(stream.try_recv(), Ok(PurchaseUpdate::Lagged(0))));
+        assert!(stream.try_recv().is_err());
+    }
+}
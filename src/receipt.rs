@@ -0,0 +1,774 @@
+use std::borrow::Cow;
+#[cfg(target_os = "macos")]
+use std::path::PathBuf;
+
+#[cfg(target_os = "macos")]
+use tauri::{AppHandle, Runtime};
+
+/// Exit code telling macOS to fetch a new receipt from the Mac App Store and
+/// relaunch the app.
+#[cfg(target_os = "macos")]
+pub const RECEIPT_REFRESH_EXIT_CODE: i32 = 173;
+
+/// OID 1.2.840.113549.1.7.2, PKCS #7 signed data.
+const SIGNED_DATA_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x02];
+
+// DER tags of the elements making up a receipt.
+const INTEGER: u8 = 0x02;
+const OCTET_STRING: u8 = 0x04;
+const CONSTRUCTED_OCTET_STRING: u8 = 0x24;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const UTF8_STRING: u8 = 0x0C;
+const IA5_STRING: u8 = 0x16;
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const EXPLICIT_0: u8 = 0xA0;
+
+// Receipt attribute types.
+const BUNDLE_ID: u64 = 2;
+const APP_VERSION: u64 = 3;
+const CREATION_DATE: u64 = 12;
+const IN_APP_PURCHASE: u64 = 17;
+const ORIGINAL_APP_VERSION: u64 = 19;
+
+// In-app purchase receipt attribute types.
+const QUANTITY: u64 = 1701;
+const PRODUCT_ID: u64 = 1702;
+const TRANSACTION_ID: u64 = 1703;
+const PURCHASE_DATE: u64 = 1704;
+const ORIGINAL_TRANSACTION_ID: u64 = 1705;
+const ORIGINAL_PURCHASE_DATE: u64 = 1706;
+const SUBSCRIPTION_EXPIRATION_DATE: u64 = 1708;
+const CANCELLATION_DATE: u64 = 1712;
+
+/// Nesting of indefinite length elements or octet string chunks beyond which
+/// a receipt is rejected, far deeper than a PKCS #7 container goes.
+const MAX_DEPTH: usize = 32;
+
+/// Fields of an App Store receipt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppReceipt {
+    /// Bundle identifier the receipt was issued for
+    pub bundle_id: String,
+    /// `CFBundleVersion` of the app the receipt was issued for
+    pub app_version: String,
+    /// `CFBundleVersion` of the app the user originally bought (optional)
+    pub original_app_version: Option<String>,
+    /// ISO datetime string when the receipt was created (optional)
+    pub creation_date: Option<String>,
+    /// In-app purchases recorded in the receipt: non-consumables,
+    /// subscription periods and consumables not finished yet
+    pub in_app_purchases: Vec<InAppPurchaseReceipt>,
+}
+
+/// An in-app purchase record of an [`AppReceipt`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InAppPurchaseReceipt {
+    /// Identifier of the purchased product
+    pub product_id: String,
+    /// Identifier of the transaction
+    pub transaction_id: String,
+    /// Transaction identifier of the original purchase, shared by the
+    /// renewals of a subscription
+    pub original_transaction_id: String,
+    /// Number of items purchased
+    pub quantity: u32,
+    /// ISO datetime string of the purchase (optional)
+    pub purchase_date: Option<String>,
+    /// ISO datetime string of the original purchase (optional)
+    pub original_purchase_date: Option<String>,
+    /// ISO datetime string when the subscription period ends, for
+    /// auto-renewable subscriptions (optional)
+    pub expiration_date: Option<String>,
+    /// ISO datetime string when Apple refunded the transaction (optional)
+    pub cancellation_date: Option<String>,
+}
+
+/// Outcome of [`validate_app_receipt`] and [`validate_receipt_data`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReceiptValidation {
+    /// The receipt belongs to this app and version.
+    Valid(AppReceipt),
+    /// The app has no receipt, e.g. on first launch or when it was copied
+    /// from another Mac.
+    Missing,
+    /// The receipt is malformed, unsigned or issued for another app.
+    Invalid {
+        /// Why the receipt was rejected
+        reason: String,
+    },
+}
+
+/// Validates the Mac App Store receipt of the running app, which Mac App
+/// Store apps are expected to do at launch.
+///
+/// The receipt must be issued for the app's identifier and version, as
+/// configured in `tauri.conf.json`. With the `receipt-verification` feature,
+/// its signature must also chain up to the Apple Root CA.
+///
+/// Call [`exit_for_receipt_refresh`] unless the receipt is valid.
+///
+/// # Example
+///
+/// ```
+/// use tauri_plugin_iap::{exit_for_receipt_refresh, validate_app_receipt, ReceiptValidation};
+///
+/// let builder = tauri::Builder::<tauri::Wry>::default().setup(|app| {
+///     if !matches!(validate_app_receipt(app.handle()), ReceiptValidation::Valid(_)) {
+///         exit_for_receipt_refresh();
+///     }
+///     Ok(())
+/// });
+/// # let _ = builder;
+/// ```
+#[cfg(target_os = "macos")]
+pub fn validate_app_receipt<R: Runtime>(app: &AppHandle<R>) -> ReceiptValidation {
+    let Some(path) = receipt_path() else {
+        return ReceiptValidation::Missing;
+    };
+    let der = match std::fs::read(&path) {
+        Ok(der) => der,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return ReceiptValidation::Missing,
+        Err(e) => {
+            return ReceiptValidation::Invalid {
+                reason: format!("Failed to read {}: {}", path.display(), e),
+            }
+        }
+    };
+
+    #[cfg(feature = "receipt-verification")]
+    if let Err(e) = crate::mac_app_store::verify_receipt_signature(&path) {
+        return ReceiptValidation::Invalid {
+            reason: e.to_string(),
+        };
+    }
+
+    validate_receipt_data(
+        &der,
+        &app.config().identifier,
+        &app.package_info().version.to_string(),
+    )
+}
+
+/// Validates receipt data against a bundle identifier and version, without
+/// checking its signature.
+pub fn validate_receipt_data(der: &[u8], bundle_id: &str, app_version: &str) -> ReceiptValidation {
+    let receipt = match parse_receipt(der) {
+        Ok(receipt) => receipt,
+        Err(reason) => {
+            return ReceiptValidation::Invalid {
+                reason: format!("Malformed receipt: {reason}"),
+            }
+        }
+    };
+    if receipt.bundle_id != bundle_id {
+        return ReceiptValidation::Invalid {
+            reason: format!(
+                "Receipt was issued for {}, not {}",
+                receipt.bundle_id, bundle_id
+            ),
+        };
+    }
+    if receipt.app_version != app_version {
+        return ReceiptValidation::Invalid {
+            reason: format!(
+                "Receipt was issued for version {}, not {}",
+                receipt.app_version, app_version
+            ),
+        };
+    }
+    ReceiptValidation::Valid(receipt)
+}
+
+/// Exits with code 173, which makes macOS ask the user to sign in to the Mac
+/// App Store, fetch a new receipt and relaunch the app.
+///
+/// Outside of the Mac App Store, e.g. in development builds, the app just
+/// exits.
+#[cfg(target_os = "macos")]
+pub fn exit_for_receipt_refresh() -> ! {
+    log::warn!("App receipt is missing or invalid, exiting to refresh it");
+    std::process::exit(RECEIPT_REFRESH_EXIT_CODE)
+}
+
+/// `Contents/_MASReceipt/receipt` in the app bundle, where the Mac App Store
+/// installs the receipt.
+#[cfg(target_os = "macos")]
+fn receipt_path() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    // The executable is in `Contents/MacOS`.
+    let contents = exe.parent()?.parent()?;
+    Some(contents.join("_MASReceipt").join("receipt"))
+}
+
+/// Reads the fields of an App Store receipt, e.g. the base64 decoded result
+/// of `getAppReceipt`, without checking its signature.
+///
+/// The `receipt-parse` feature makes this available on every platform, e.g.
+/// to read receipts on a desktop build server.
+///
+/// # Errors
+///
+/// Returns `Error::InvalidPurchaseToken` if the receipt is malformed.
+#[cfg(feature = "receipt-parse")]
+pub fn parse_app_receipt(der: &[u8]) -> crate::Result<AppReceipt> {
+    parse_receipt(der).map_err(|reason| {
+        crate::Error::InvalidPurchaseToken(format!("Malformed receipt: {reason}"))
+    })
+}
+
+/// Reads the receipt attributes out of the PKCS #7 container.
+fn parse_receipt(der: &[u8]) -> Result<AppReceipt, String> {
+    let mut content_info = Reader::new(Reader::new(der).expect(SEQUENCE)?);
+    if content_info.expect(OBJECT_IDENTIFIER)? != SIGNED_DATA_OID {
+        return Err("not a PKCS #7 signed data container".into());
+    }
+    let mut signed_data =
+        Reader::new(Reader::new(content_info.expect(EXPLICIT_0)?).expect(SEQUENCE)?);
+    signed_data.expect(INTEGER)?;
+    signed_data.expect(SET)?;
+    let mut content = Reader::new(signed_data.expect(SEQUENCE)?);
+    content.expect(OBJECT_IDENTIFIER)?;
+    let (tag, payload) = Reader::new(content.expect(EXPLICIT_0)?).next()?;
+    let payload = octets(tag, payload, 0)?;
+
+    let mut bundle_id = None;
+    let mut app_version = None;
+    let mut original_app_version = None;
+    let mut creation_date = None;
+    let mut in_app_purchases = Vec::new();
+    for_each_attribute(&payload, |attribute_type, value| {
+        match attribute_type {
+            BUNDLE_ID => bundle_id = Some(string(value)?),
+            APP_VERSION => app_version = Some(string(value)?),
+            ORIGINAL_APP_VERSION => original_app_version = Some(string(value)?),
+            CREATION_DATE => creation_date = date(value)?,
+            IN_APP_PURCHASE => in_app_purchases.push(in_app_purchase(value)?),
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    Ok(AppReceipt {
+        bundle_id: bundle_id.ok_or("no bundle identifier")?,
+        app_version: app_version.ok_or("no app version")?,
+        original_app_version,
+        creation_date,
+        in_app_purchases,
+    })
+}
+
+/// Reads an in-app purchase receipt, a set of attributes of its own.
+fn in_app_purchase(value: &[u8]) -> Result<InAppPurchaseReceipt, String> {
+    let mut product_id = None;
+    let mut transaction_id = None;
+    let mut original_transaction_id = None;
+    let mut quantity = 1;
+    let mut purchase_date = None;
+    let mut original_purchase_date = None;
+    let mut expiration_date = None;
+    let mut cancellation_date = None;
+    for_each_attribute(value, |attribute_type, value| {
+        match attribute_type {
+            PRODUCT_ID => product_id = Some(string(value)?),
+            TRANSACTION_ID => transaction_id = Some(string(value)?),
+            ORIGINAL_TRANSACTION_ID => original_transaction_id = Some(string(value)?),
+            QUANTITY => {
+                quantity = u32::try_from(integer(Reader::new(value).expect(INTEGER)?)?)
+                    .map_err(|_| "quantity out of range")?;
+            }
+            PURCHASE_DATE => purchase_date = date(value)?,
+            ORIGINAL_PURCHASE_DATE => original_purchase_date = date(value)?,
+            SUBSCRIPTION_EXPIRATION_DATE => expiration_date = date(value)?,
+            CANCELLATION_DATE => cancellation_date = date(value)?,
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    let transaction_id: String = transaction_id.ok_or("in-app purchase without transaction")?;
+    Ok(InAppPurchaseReceipt {
+        product_id: product_id.ok_or("in-app purchase without product")?,
+        original_transaction_id: original_transaction_id.unwrap_or_else(|| transaction_id.clone()),
+        transaction_id,
+        quantity,
+        purchase_date,
+        original_purchase_date,
+        expiration_date,
+        cancellation_date,
+    })
+}
+
+/// Calls `f` with the type and value of each attribute of a DER encoded set
+/// of `SEQUENCE { type INTEGER, version INTEGER, value OCTET STRING }`.
+fn for_each_attribute(
+    set: &[u8],
+    mut f: impl FnMut(u64, &[u8]) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut attributes = Reader::new(Reader::new(set).expect(SET)?);
+    while !attributes.is_empty() {
+        let mut attribute = Reader::new(attributes.expect(SEQUENCE)?);
+        let attribute_type = integer(attribute.expect(INTEGER)?)?;
+        attribute.expect(INTEGER)?;
+        f(attribute_type, attribute.expect(OCTET_STRING)?)?;
+    }
+    Ok(())
+}
+
+/// Reads consecutive BER elements, which is what receipts are encoded with.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Reads the next element, returning its tag and content.
+    fn next(&mut self) -> Result<(u8, &'a [u8]), String> {
+        let (tag, content, len) = element(self.data, 0)?;
+        self.data = &self.data[len..];
+        Ok((tag, content))
+    }
+
+    /// Reads the next element, failing unless it has the `expected` tag.
+    fn expect(&mut self, expected: u8) -> Result<&'a [u8], String> {
+        match self.next()? {
+            (tag, content) if tag == expected => Ok(content),
+            (tag, _) => Err(format!("expected tag {expected:#04x}, found {tag:#04x}")),
+        }
+    }
+}
+
+/// Splits the element at the start of `data` into its tag, its content and
+/// its total length, following indefinite lengths to the end-of-contents.
+fn element(data: &[u8], depth: usize) -> Result<(u8, &[u8], usize), String> {
+    if depth > MAX_DEPTH {
+        return Err("elements nested too deeply".into());
+    }
+    let [tag, first, rest @ ..] = data else {
+        return Err("truncated element".into());
+    };
+    if tag & 0x1F == 0x1F {
+        return Err("unsupported tag number".into());
+    }
+
+    if *first == 0x80 {
+        if tag & 0x20 == 0 {
+            return Err("indefinite length on a primitive element".into());
+        }
+        let mut len = 0;
+        while !rest[len..].starts_with(&[0, 0]) {
+            len += element(&rest[len..], depth + 1)?.2;
+        }
+        return Ok((*tag, &rest[..len], 2 + len + 2));
+    }
+
+    let (len, header) = if first & 0x80 == 0 {
+        (usize::from(*first), 2)
+    } else {
+        let count = usize::from(first & 0x7F);
+        if count > 4 || rest.len() < count {
+            return Err("invalid length".into());
+        }
+        let len = rest[..count]
+            .iter()
+            .fold(0, |len, byte| len << 8 | usize::from(*byte));
+        (len, 2 + count)
+    };
+    let content = header
+        .checked_add(len)
+        .and_then(|end| data.get(header..end))
+        .ok_or("truncated element")?;
+    Ok((*tag, content, header + len))
+}
+
+/// The bytes of an octet string, joining the chunks of a constructed one.
+fn octets(tag: u8, content: &[u8], depth: usize) -> Result<Cow<'_, [u8]>, String> {
+    if depth > MAX_DEPTH {
+        return Err("elements nested too deeply".into());
+    }
+    match tag {
+        OCTET_STRING => Ok(Cow::Borrowed(content)),
+        CONSTRUCTED_OCTET_STRING => {
+            let mut chunks = Reader::new(content);
+            let mut joined = Vec::new();
+            while !chunks.is_empty() {
+                let (tag, chunk) = chunks.next()?;
+                joined.extend_from_slice(&octets(tag, chunk, depth + 1)?);
+            }
+            Ok(Cow::Owned(joined))
+        }
+        tag => Err(format!("expected an octet string, found tag {tag:#04x}")),
+    }
+}
+
+fn integer(content: &[u8]) -> Result<u64, String> {
+    if content.is_empty() || content.len() > 8 || content[0] & 0x80 != 0 {
+        return Err("unsupported integer".into());
+    }
+    Ok(content
+        .iter()
+        .fold(0, |value, byte| value << 8 | u64::from(*byte)))
+}
+
+/// Decodes an attribute value holding an RFC 3339 date as an IA5 string,
+/// left empty by the App Store when there is none.
+fn date(value: &[u8]) -> Result<Option<String>, String> {
+    string(value).map(|date| Some(date).filter(|date| !date.is_empty()))
+}
+
+/// Decodes an attribute value holding a UTF-8 or IA5 string.
+fn string(value: &[u8]) -> Result<String, String> {
+    match Reader::new(value).next()? {
+        (UTF8_STRING | IA5_STRING, content) => String::from_utf8(content.to_vec())
+            .map_err(|_| "attribute is not valid UTF-8".to_string()),
+        (tag, _) => Err(format!("expected a string, found tag {tag:#04x}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The attributes of a sandbox receipt signed with a test certificate by
+    /// `openssl cms -sign -stream`, which encodes the container with
+    /// indefinite lengths and a chunked octet string like the App Store does.
+    const SANDBOX_RECEIPT: &[u8] = include_bytes!("../tests/fixtures/receipt/sandbox_receipt.der");
+
+    /// DER element with a short or long form length.
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut der = vec![tag];
+        match content.len() {
+            len @ 0..=0x7F => der.push(len as u8),
+            len => {
+                let bytes = (len as u32).to_be_bytes();
+                der.push(0x84);
+                der.extend_from_slice(&bytes);
+            }
+        }
+        der.extend_from_slice(content);
+        der
+    }
+
+    fn attribute(attribute_type: u16, value: &[u8]) -> Vec<u8> {
+        let mut content = tlv(INTEGER, &attribute_type.to_be_bytes());
+        content.extend(tlv(INTEGER, &[1]));
+        content.extend(tlv(OCTET_STRING, value));
+        tlv(SEQUENCE, &content)
+    }
+
+    /// A receipt holding the `attributes`, in a container without a
+    /// signature.
+    fn receipt(attributes: &[Vec<u8>]) -> Vec<u8> {
+        let payload = tlv(SET, &attributes.concat());
+        let content = [
+            tlv(
+                OBJECT_IDENTIFIER,
+                &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x01],
+            ),
+            tlv(EXPLICIT_0, &tlv(OCTET_STRING, &payload)),
+        ]
+        .concat();
+        let signed_data = [tlv(INTEGER, &[1]), tlv(SET, &[]), tlv(SEQUENCE, &content)].concat();
+        tlv(
+            SEQUENCE,
+            &[
+                tlv(OBJECT_IDENTIFIER, SIGNED_DATA_OID),
+                tlv(EXPLICIT_0, &tlv(SEQUENCE, &signed_data)),
+            ]
+            .concat(),
+        )
+    }
+
+    fn app_attributes() -> Vec<Vec<u8>> {
+        vec![
+            attribute(BUNDLE_ID as u16, &tlv(UTF8_STRING, b"com.example.iapdemo")),
+            attribute(APP_VERSION as u16, &tlv(UTF8_STRING, b"42")),
+        ]
+    }
+
+    fn rejection(der: &[u8]) -> String {
+        match parse_receipt(der) {
+            Err(reason) => reason,
+            Ok(receipt) => panic!("expected a malformed receipt, got {receipt:?}"),
+        }
+    }
+
+    #[test]
+    fn reads_a_sandbox_receipt() {
+        let receipt = parse_receipt(SANDBOX_RECEIPT).unwrap();
+
+        assert_eq!(receipt.bundle_id, "com.example.iapdemo");
+        assert_eq!(receipt.app_version, "42");
+        assert_eq!(receipt.original_app_version.as_deref(), Some("1.0"));
+        assert_eq!(
+            receipt.creation_date.as_deref(),
+            Some("2024-01-15T10:00:00Z")
+        );
+        assert_eq!(
+            receipt.in_app_purchases,
+            [
+                InAppPurchaseReceipt {
+                    product_id: "premium".into(),
+                    transaction_id: "2000000512345678".into(),
+                    original_transaction_id: "2000000512345678".into(),
+                    quantity: 1,
+                    purchase_date: Some("2024-01-10T09:30:00Z".into()),
+                    original_purchase_date: Some("2024-01-10T09:30:00Z".into()),
+                    expiration_date: None,
+                    cancellation_date: None,
+                },
+                InAppPurchaseReceipt {
+                    product_id: "coins_100".into(),
+                    transaction_id: "2000000512340000".into(),
+                    original_transaction_id: "2000000512340000".into(),
+                    quantity: 3,
+                    purchase_date: Some("2024-01-14T18:00:00Z".into()),
+                    original_purchase_date: Some("2024-01-14T18:00:00Z".into()),
+                    expiration_date: None,
+                    cancellation_date: Some("2024-01-14T19:00:00Z".into()),
+                },
+                InAppPurchaseReceipt {
+                    product_id: "pro_monthly".into(),
+                    transaction_id: "2000000512349999".into(),
+                    original_transaction_id: "2000000500000001".into(),
+                    quantity: 1,
+                    purchase_date: Some("2024-01-12T08:00:00Z".into()),
+                    original_purchase_date: Some("2023-12-12T08:00:00Z".into()),
+                    expiration_date: Some("2024-01-12T08:05:00Z".into()),
+                    cancellation_date: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_the_bundle_and_version() {
+        assert!(matches!(
+            validate_receipt_data(SANDBOX_RECEIPT, "com.example.iapdemo", "42"),
+            ReceiptValidation::Valid(_)
+        ));
+        for (bundle_id, app_version, reason) in [
+            (
+                "com.example.other",
+                "42",
+                "Receipt was issued for com.example.iapdemo, not com.example.other",
+            ),
+            (
+                "com.example.iapdemo",
+                "43",
+                "Receipt was issued for version 42, not 43",
+            ),
+        ] {
+            assert_eq!(
+                validate_receipt_data(SANDBOX_RECEIPT, bundle_id, app_version),
+                ReceiptValidation::Invalid {
+                    reason: reason.into()
+                }
+            );
+        }
+        assert!(matches!(
+            validate_receipt_data(b"", "com.example.iapdemo", "42"),
+            ReceiptValidation::Invalid { reason } if reason.starts_with("Malformed receipt")
+        ));
+    }
+
+    #[test]
+    fn reads_definite_length_receipts() {
+        let mut attributes = app_attributes();
+        attributes.push(attribute(
+            IN_APP_PURCHASE as u16,
+            &tlv(
+                SET,
+                &[
+                    attribute(PRODUCT_ID as u16, &tlv(UTF8_STRING, b"premium")),
+                    attribute(TRANSACTION_ID as u16, &tlv(UTF8_STRING, b"1")),
+                    // Empty dates are left out.
+                    attribute(SUBSCRIPTION_EXPIRATION_DATE as u16, &tlv(IA5_STRING, b"")),
+                ]
+                .concat(),
+            ),
+        ));
+
+        let receipt = parse_receipt(&receipt(&attributes)).unwrap();
+        assert_eq!(receipt.bundle_id, "com.example.iapdemo");
+        assert_eq!(receipt.original_app_version, None);
+        let purchase = &receipt.in_app_purchases[0];
+        assert_eq!(purchase.original_transaction_id, "1");
+        assert_eq!(purchase.quantity, 1);
+        assert_eq!(purchase.expiration_date, None);
+    }
+
+    #[test]
+    fn rejects_truncated_receipts() {
+        for len in 0..SANDBOX_RECEIPT.len() {
+            assert!(parse_receipt(&SANDBOX_RECEIPT[..len]).is_err(), "{len}");
+        }
+    }
+
+    #[test]
+    fn survives_corrupted_bytes() {
+        // Either an error or a receipt, never a panic.
+        for i in 0..SANDBOX_RECEIPT.len() {
+            for flip in [0x01, 0x80, 0xFF] {
+                let mut der = SANDBOX_RECEIPT.to_vec();
+                der[i] ^= flip;
+                let _ = parse_receipt(&der);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_containers() {
+        assert_eq!(rejection(b""), "truncated element");
+        assert_eq!(rejection(&[SEQUENCE]), "truncated element");
+        assert_eq!(rejection(&[INTEGER, 1, 0]), "expected tag 0x30, found 0x02");
+        // Another PKCS #7 content type.
+        let mut der = receipt(&app_attributes());
+        let oid = der
+            .windows(SIGNED_DATA_OID.len())
+            .position(|window| window == SIGNED_DATA_OID)
+            .unwrap();
+        der[oid + SIGNED_DATA_OID.len() - 1] = 0x01;
+        assert_eq!(rejection(&der), "not a PKCS #7 signed data container");
+        // Lengths past the end, over 4 bytes, and on a long form tag.
+        assert_eq!(
+            rejection(&[SEQUENCE, 0x84, 0xFF, 0xFF, 0xFF, 0xFF]),
+            "truncated element"
+        );
+        assert_eq!(
+            rejection(&[SEQUENCE, 0x89, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+            "invalid length"
+        );
+        assert_eq!(rejection(&[SEQUENCE, 0x82, 0x01]), "invalid length");
+        assert_eq!(rejection(&[0x1F, 0x01, 0x00]), "unsupported tag number");
+        assert_eq!(
+            rejection(&[OCTET_STRING, 0x80, 0, 0]),
+            "indefinite length on a primitive element"
+        );
+        // An indefinite length without its end-of-contents.
+        assert_eq!(
+            rejection(&[SEQUENCE, 0x80, INTEGER, 1, 0]),
+            "truncated element"
+        );
+    }
+
+    #[test]
+    fn rejects_deeply_nested_elements() {
+        let mut der = Vec::new();
+        for _ in 0..10_000 {
+            der.extend_from_slice(&[SEQUENCE, 0x80]);
+        }
+        assert_eq!(rejection(&der), "elements nested too deeply");
+
+        // Octet string chunks nested within each other.
+        let mut chunk = tlv(OCTET_STRING, &tlv(SET, &[]));
+        for _ in 0..10_000 {
+            chunk = tlv(CONSTRUCTED_OCTET_STRING, &chunk);
+        }
+        let content = [tlv(OBJECT_IDENTIFIER, &[0x2A]), tlv(EXPLICIT_0, &chunk)].concat();
+        let signed_data = [tlv(INTEGER, &[1]), tlv(SET, &[]), tlv(SEQUENCE, &content)].concat();
+        let der = tlv(
+            SEQUENCE,
+            &[
+                tlv(OBJECT_IDENTIFIER, SIGNED_DATA_OID),
+                tlv(EXPLICIT_0, &tlv(SEQUENCE, &signed_data)),
+            ]
+            .concat(),
+        );
+        assert_eq!(rejection(&der), "elements nested too deeply");
+    }
+
+    #[test]
+    fn rejects_malformed_attributes() {
+        let bundle_id = || attribute(BUNDLE_ID as u16, &tlv(UTF8_STRING, b"com.example.iapdemo"));
+        let app_version = || attribute(APP_VERSION as u16, &tlv(UTF8_STRING, b"42"));
+        let purchase = |attributes: &[Vec<u8>]| {
+            receipt(&[
+                bundle_id(),
+                app_version(),
+                attribute(IN_APP_PURCHASE as u16, &tlv(SET, &attributes.concat())),
+            ])
+        };
+        let product = || attribute(PRODUCT_ID as u16, &tlv(UTF8_STRING, b"premium"));
+        let transaction = || attribute(TRANSACTION_ID as u16, &tlv(UTF8_STRING, b"1"));
+
+        assert_eq!(
+            rejection(&receipt(&[app_version()])),
+            "no bundle identifier"
+        );
+        assert_eq!(rejection(&receipt(&[bundle_id()])), "no app version");
+        assert_eq!(
+            rejection(&receipt(&[
+                attribute(BUNDLE_ID as u16, &tlv(UTF8_STRING, &[0xFF, 0xFE])),
+                app_version(),
+            ])),
+            "attribute is not valid UTF-8"
+        );
+        assert_eq!(
+            rejection(&receipt(&[
+                attribute(BUNDLE_ID as u16, &tlv(INTEGER, &[1])),
+                app_version(),
+            ])),
+            "expected a string, found tag 0x02"
+        );
+        assert_eq!(
+            rejection(&purchase(&[transaction()])),
+            "in-app purchase without product"
+        );
+        assert_eq!(
+            rejection(&purchase(&[product()])),
+            "in-app purchase without transaction"
+        );
+        for (quantity, reason) in [
+            (&[0x01, 0x00, 0x00, 0x00, 0x00][..], "quantity out of range"),
+            (&[0xFF], "unsupported integer"),
+            (&[], "unsupported integer"),
+        ] {
+            assert_eq!(
+                rejection(&purchase(&[
+                    product(),
+                    transaction(),
+                    attribute(QUANTITY as u16, &tlv(INTEGER, quantity)),
+                ])),
+                reason
+            );
+        }
+        // Attribute types too large for a u64.
+        let mut content = tlv(INTEGER, &[0x01; 9]);
+        content.extend(tlv(INTEGER, &[1]));
+        content.extend(tlv(OCTET_STRING, &[]));
+        assert_eq!(
+            rejection(&receipt(&[tlv(SEQUENCE, &content)])),
+            "unsupported integer"
+        );
+    }
+
+    #[cfg(feature = "receipt-parse")]
+    #[test]
+    fn reports_malformed_receipts_as_invalid_tokens() {
+        assert_eq!(
+            parse_app_receipt(SANDBOX_RECEIPT).unwrap().bundle_id,
+            "com.example.iapdemo"
+        );
+        let error = parse_app_receipt(&SANDBOX_RECEIPT[..100]).unwrap_err();
+        assert_eq!(error.code(), crate::IapErrorCode::InvalidPurchaseToken);
+        assert!(error.to_string().contains("Malformed receipt"));
+    }
+
+    /// Test binaries aren't in a bundle, so they have no receipt.
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn reports_a_missing_receipt() {
+        let app = tauri::test::mock_app();
+
+        assert!(!receipt_path().unwrap().exists());
+        assert_eq!(validate_app_receipt(app.handle()), ReceiptValidation::Missing);
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashSet;
+use std::sync::{Mutex, PoisonError};
+
+use crate::models::PurchaseDetails;
+use crate::Error;
+
+/// Transaction ID and purchase token pairs the store has reported to the plugin.
+///
+/// Used to reject completing purchases the store never reported, e.g. details
+/// fabricated by a script running in the webview.
+#[derive(Default)]
+pub(crate) struct PurchaseRegistry {
+    known: Mutex<HashSet<(Option<String>, String)>>,
+}
+
+impl PurchaseRegistry {
+    /// Remembers the identifiers of purchases reported by the store.
+    pub(crate) fn record<'a>(&self, purchases: impl IntoIterator<Item = &'a PurchaseDetails>) {
+        let mut known = self.known.lock().unwrap_or_else(PoisonError::into_inner);
+        known.extend(purchases.into_iter().map(PurchaseDetails::identifiers));
+    }
+
+    /// Returns true if the store reported a purchase with the same transaction
+    /// ID and token, so a known ID can't be paired with a fabricated token.
+    pub(crate) fn contains(&self, purchase: &PurchaseDetails) -> bool {
+        self.known
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(&purchase.identifiers())
+    }
+
+    /// Checks that `purchase` may be completed: with `reject_unknown`, only
+    /// if the store reported it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::UnknownPurchase` if the store never reported it.
+    pub(crate) fn check(
+        &self,
+        purchase: &PurchaseDetails,
+        reject_unknown: bool,
+    ) -> crate::Result<()> {
+        if reject_unknown && !self.contains(purchase) {
+            return Err(Error::UnknownPurchase(
+                purchase
+                    .purchase_id
+                    .clone()
+                    .unwrap_or_else(|| purchase.product_id.clone()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::IapErrorCode;
+
+    fn purchase(id: Option<&str>, token: &str) -> PurchaseDetails {
+        serde_json::from_value(json!({
+            "purchaseId": id,
+            "productId": "premium",
+            "verificationData": {
+                "localVerificationData": "{}",
+                "serverVerificationData": token,
+                "source": "google",
+            },
+            "status": "purchased",
+            "pendingCompletePurchase": true,
+        }))
+        .unwrap()
+    }
+
+    fn rejected(registry: &PurchaseRegistry, purchase: &PurchaseDetails) -> String {
+        match registry.check(purchase, true) {
+            Err(Error::UnknownPurchase(id)) => id,
+            other => panic!("expected an unknown purchase, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn accepts_reported_purchases() {
+        let registry = PurchaseRegistry::default();
+        let reported = [
+            purchase(Some("GPA.1"), "token-1"),
+            purchase(None, "receipt"),
+        ];
+        registry.record(&reported);
+
+        for purchase in &reported {
+            registry.check(purchase, true).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_purchases() {
+        let registry = PurchaseRegistry::default();
+        registry.record([&purchase(Some("GPA.1"), "token-1")]);
+
+        assert_eq!(
+            rejected(&registry, &purchase(Some("GPA.2"), "token-2")),
+            "GPA.2"
+        );
+        // A reported ID with a forged token, and the reverse.
+        assert_eq!(
+            rejected(&registry, &purchase(Some("GPA.1"), "forged")),
+            "GPA.1"
+        );
+        assert_eq!(
+            rejected(&registry, &purchase(Some("GPA.2"), "token-1")),
+            "GPA.2"
+        );
+        // Without a transaction ID, the product names it.
+        assert_eq!(rejected(&registry, &purchase(None, "token-1")), "premium");
+        assert_eq!(
+            registry
+                .check(&purchase(None, "forged"), true)
+                .unwrap_err()
+                .code(),
+            IapErrorCode::UnknownPurchase
+        );
+    }
+
+    #[test]
+    fn accepts_unknown_purchases_when_opted_out() {
+        let registry = PurchaseRegistry::default();
+
+        registry
+            .check(&purchase(Some("GPA.2"), "token-2"), false)
+            .unwrap();
+        registry.check(&purchase(None, "forged"), false).unwrap();
+    }
+}
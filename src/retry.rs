@@ -0,0 +1,534 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{Arc, Condvar, Mutex, PoisonError},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Result;
+
+/// Controls how transient failures (see [`crate::Error::is_retryable`]) are retried.
+///
+/// Delays grow exponentially from `base_delay` and are capped at `max_delay`.
+/// Each delay is jittered so that many clients recovering from the same
+/// outage don't hit the store in lockstep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one. `1` disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry.
+    pub base_delay: Duration,
+    /// Upper bound for any single delay.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the jittered delay to wait before retry number `attempt` (starting at 1).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff_with_jitter(attempt, jitter())
+    }
+
+    /// Returns the delay before retry number `attempt` for a given jitter factor in `[0, 1)`.
+    ///
+    /// Half of the exponential delay is always waited, the other half is
+    /// scaled by `jitter`.
+    pub fn backoff_with_jitter(&self, attempt: u32, jitter: f64) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        delay / 2 + (delay / 2).mul_f64(jitter.clamp(0.0, 1.0))
+    }
+}
+
+/// Controls how the connection to the store is retried, both when
+/// `initialize` connects and when the connection drops afterwards.
+///
+/// Delays grow exponentially from `base_delay_ms` and are capped at
+/// `max_delay_ms`, like [`RetryPolicy`] delays.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct ConnectionRetryPolicy {
+    /// Total number of connection attempts, including the first one, or
+    /// `None` to retry until connected.
+    pub max_attempts: Option<u32>,
+    /// Delay before the first retry in milliseconds.
+    pub base_delay_ms: u64,
+    /// Upper bound for any single delay in milliseconds.
+    pub max_delay_ms: u64,
+    /// Whether delays are jittered, so that many clients recovering from the
+    /// same outage don't reconnect in lockstep.
+    pub jitter: bool,
+}
+
+impl Default for ConnectionRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: Some(3),
+            base_delay_ms: 1000,
+            max_delay_ms: 30_000,
+            jitter: true,
+        }
+    }
+}
+
+impl ConnectionRetryPolicy {
+    /// A policy that retries until connected, e.g. for kiosk apps.
+    pub fn forever() -> Self {
+        Self {
+            max_attempts: None,
+            ..Self::default()
+        }
+    }
+
+    /// Whether connection attempt `attempt` (starting at 1) may be retried.
+    pub fn allows_retry(&self, attempt: u32) -> bool {
+        self.max_attempts.map_or(true, |max| attempt < max)
+    }
+
+    /// Returns the delay to wait before retry number `attempt` (starting at 1).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff_with_jitter(attempt, jitter())
+    }
+
+    /// Returns the delay before retry number `attempt` for a given jitter
+    /// factor in `[0, 1)`, which is ignored unless `jitter` is set.
+    pub fn backoff_with_jitter(&self, attempt: u32, jitter: f64) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = Duration::from_millis(self.base_delay_ms)
+            .saturating_mul(factor)
+            .min(Duration::from_millis(self.max_delay_ms));
+        if !self.jitter {
+            return delay;
+        }
+        delay / 2 + (delay / 2).mul_f64(jitter.clamp(0.0, 1.0))
+    }
+}
+
+/// Returns a pseudo-random value in `[0, 1)` without pulling in an RNG crate.
+fn jitter() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Signals that pending retries should stop waiting and give up.
+///
+/// The plugin cancels its own token when it is dropped, so retry loops never
+/// outlive the app.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<(Mutex<bool>, Condvar)>);
+
+impl CancellationToken {
+    /// Creates a token that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the token, waking up anything waiting on it.
+    pub fn cancel(&self) {
+        let (cancelled, condvar) = &*self.0;
+        *cancelled.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        condvar.notify_all();
+    }
+
+    /// Returns true once [`CancellationToken::cancel`] has been called.
+    pub fn is_cancelled(&self) -> bool {
+        *self.0 .0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Blocks for `timeout` or until the token is cancelled, returning whether it was cancelled.
+    fn wait(&self, timeout: Duration) -> bool {
+        let (cancelled, condvar) = &*self.0;
+        let guard = cancelled.lock().unwrap_or_else(PoisonError::into_inner);
+        let (guard, _) = condvar
+            .wait_timeout_while(guard, timeout, |cancelled| !*cancelled)
+            .unwrap_or_else(PoisonError::into_inner);
+        *guard
+    }
+}
+
+/// Runs `op`, retrying retryable errors according to `policy`.
+///
+/// Returns the first success, the first non-retryable error, or the last
+/// error once the attempts are exhausted or `cancel` is cancelled.
+pub fn retry<T>(
+    policy: &RetryPolicy,
+    cancel: &CancellationToken,
+    op: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    retry_with(
+        op,
+        |attempt| (attempt < policy.max_attempts).then(|| policy.backoff(attempt)),
+        |delay| cancel.wait(delay),
+    )
+}
+
+/// Runs the connection attempt `connect`, retrying retryable errors according
+/// to `policy`.
+///
+/// Returns like [`retry`], which with `max_attempts` unset only happens on
+/// success, on a non-retryable error or once `cancel` is cancelled.
+pub fn retry_connection<T>(
+    policy: &ConnectionRetryPolicy,
+    cancel: &CancellationToken,
+    connect: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    retry_with(
+        connect,
+        |attempt| {
+            policy
+                .allows_retry(attempt)
+                .then(|| policy.backoff(attempt))
+        },
+        |delay| cancel.wait(delay),
+    )
+}
+
+/// Runs `op`, waiting `next_delay(attempt)` before retrying a retryable error
+/// until it returns `None`.
+///
+/// `wait` blocks for a delay and returns whether the retries were cancelled
+/// meanwhile, so the tests can run the loop without sleeping.
+fn retry_with<T>(
+    mut op: impl FnMut() -> Result<T>,
+    next_delay: impl Fn(u32) -> Option<Duration>,
+    mut wait: impl FnMut(Duration) -> bool,
+) -> Result<T> {
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(error) if error.is_retryable() => {
+                let Some(delay) = next_delay(attempt) else {
+                    return Err(error);
+                };
+                log::debug!("Attempt {attempt} failed with {error}, retrying in {delay:?}");
+                #[cfg(feature = "tracing")]
+                crate::instrument::retrying(attempt, delay, &error);
+                if wait(delay) {
+                    return Err(error);
+                }
+                attempt = attempt.saturating_add(1);
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::Error;
+
+    /// Runs `op` like [`retry`], recording the delays instead of sleeping.
+    /// The retries are cancelled while waiting the delay number `cancel_at`.
+    fn run<T>(
+        policy: &RetryPolicy,
+        cancel_at: Option<usize>,
+        op: impl FnMut() -> Result<T>,
+    ) -> (Result<T>, Vec<Duration>) {
+        let waited = RefCell::new(Vec::new());
+        let result = retry_with(
+            op,
+            |attempt| (attempt < policy.max_attempts).then(|| policy.backoff(attempt)),
+            |delay| {
+                let mut waited = waited.borrow_mut();
+                waited.push(delay);
+                cancel_at == Some(waited.len())
+            },
+        );
+        (result, waited.into_inner())
+    }
+
+    /// Connects with `connect` like [`retry_connection`], recording the
+    /// delays instead of sleeping.
+    fn run_connection<T>(
+        policy: &ConnectionRetryPolicy,
+        connect: impl FnMut() -> Result<T>,
+    ) -> (Result<T>, Vec<Duration>) {
+        let mut waited = Vec::new();
+        let result = retry_with(
+            connect,
+            |attempt| {
+                policy
+                    .allows_retry(attempt)
+                    .then(|| policy.backoff(attempt))
+            },
+            |delay| {
+                waited.push(delay);
+                false
+            },
+        );
+        (result, waited)
+    }
+
+    /// An operation failing with `errors` in turn, then succeeding with the
+    /// number of attempts.
+    fn failing(errors: Vec<Error>) -> impl FnMut() -> Result<usize> {
+        let mut errors = errors.into_iter();
+        let mut attempts = 0;
+        move || {
+            attempts += 1;
+            errors.next().map_or(Ok(attempts), Err)
+        }
+    }
+
+    fn network() -> Error {
+        Error::NetworkError("offline".into())
+    }
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+        }
+    }
+
+    #[test]
+    fn classifies_transient_errors_as_retryable() {
+        for error in [
+            network(),
+            Error::RateLimited { retry_after: None },
+            Error::ServiceDisconnected,
+            Error::ServiceTimeout,
+            Error::ServiceTimeout.with_native_code(-3),
+        ] {
+            assert!(error.is_retryable(), "{error:?}");
+        }
+        for error in [
+            Error::UserCancelled,
+            Error::ItemAlreadyOwned,
+            Error::DeveloperError("bad product".into()),
+            Error::NotInitialized,
+            Error::UserCancelled.with_native_code(1),
+        ] {
+            assert!(!error.is_retryable(), "{error:?}");
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_max_delay() {
+        let policy = policy(10);
+        let delays: Vec<_> = (1..=4)
+            .map(|attempt| policy.backoff_with_jitter(attempt, 0.0))
+            .collect();
+        // Half of the delay is always waited.
+        assert_eq!(delays, [50, 100, 150, 150].map(Duration::from_millis));
+        assert_eq!(
+            policy.backoff_with_jitter(2, 0.5),
+            Duration::from_millis(150)
+        );
+        assert_eq!(
+            policy.backoff_with_jitter(3, 1.0),
+            Duration::from_millis(300)
+        );
+        // Out of range jitter is clamped, and huge attempts saturate.
+        assert_eq!(
+            policy.backoff_with_jitter(1, -1.0),
+            Duration::from_millis(50)
+        );
+        assert_eq!(
+            policy.backoff_with_jitter(1, 7.0),
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            policy.backoff_with_jitter(u32::MAX, 1.0),
+            Duration::from_millis(300)
+        );
+        assert_eq!(
+            policy.backoff_with_jitter(0, 1.0),
+            Duration::from_millis(100)
+        );
+    }
+
+    #[test]
+    fn jittered_backoff_stays_within_bounds() {
+        let policy = policy(10);
+        for attempt in 1..=5 {
+            let full = policy.backoff_with_jitter(attempt, 1.0);
+            for _ in 0..100 {
+                let delay = policy.backoff(attempt);
+                assert!(delay >= full / 2 && delay <= full, "{delay:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn connection_backoff_is_exact_without_jitter() {
+        let policy = ConnectionRetryPolicy {
+            max_attempts: Some(5),
+            base_delay_ms: 1000,
+            max_delay_ms: 3000,
+            jitter: false,
+        };
+        let delays: Vec<_> = (1..=4).map(|attempt| policy.backoff(attempt)).collect();
+        assert_eq!(delays, [1, 2, 3, 3].map(Duration::from_secs));
+        assert!(policy.allows_retry(4));
+        assert!(!policy.allows_retry(5));
+        assert!(ConnectionRetryPolicy::forever().allows_retry(u32::MAX));
+    }
+
+    #[test]
+    fn connection_retries_follow_the_backoff_schedule() {
+        let policy = ConnectionRetryPolicy {
+            max_attempts: Some(4),
+            base_delay_ms: 1000,
+            max_delay_ms: 3000,
+            jitter: false,
+        };
+        let (result, waited) =
+            run_connection(&policy, failing((0..5).map(|_| network()).collect()));
+        assert!(matches!(result, Err(Error::NetworkError(_))));
+        assert_eq!(waited, [1, 2, 3].map(Duration::from_secs));
+
+        // A permanent failure isn't retried, however many attempts are left.
+        let (result, waited) = run_connection(
+            &policy,
+            failing(vec![
+                network(),
+                Error::FeatureNotSupported("billing".into()),
+            ]),
+        );
+        assert!(matches!(result, Err(Error::FeatureNotSupported(_))));
+        assert_eq!(waited.len(), 1);
+    }
+
+    #[test]
+    fn retries_the_connection_forever_without_max_attempts() {
+        let policy = ConnectionRetryPolicy {
+            jitter: false,
+            ..ConnectionRetryPolicy::forever()
+        };
+        let (result, waited) =
+            run_connection(&policy, failing((0..50).map(|_| network()).collect()));
+        assert_eq!(result.unwrap(), 51);
+        assert_eq!(waited.len(), 50);
+        assert_eq!(waited[0], Duration::from_secs(1));
+        assert!(waited[5..]
+            .iter()
+            .all(|delay| *delay == Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn jittered_connection_backoff_stays_within_bounds() {
+        let policy = ConnectionRetryPolicy::forever();
+        let (result, waited) =
+            run_connection(&policy, failing((0..8).map(|_| network()).collect()));
+        assert_eq!(result.unwrap(), 9);
+        for (attempt, delay) in (1..).zip(waited) {
+            let full = policy.backoff_with_jitter(attempt, 1.0);
+            assert!(delay >= full / 2 && delay <= full, "{attempt}: {delay:?}");
+        }
+    }
+
+    #[test]
+    fn retries_transient_errors_until_they_succeed() {
+        let (result, waited) = run(
+            &policy(3),
+            None,
+            failing(vec![network(), Error::ServiceTimeout]),
+        );
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(waited.len(), 2);
+        assert!(waited[0] >= Duration::from_millis(50) && waited[0] <= Duration::from_millis(100));
+        assert!(waited[1] >= Duration::from_millis(100) && waited[1] <= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn gives_up_with_the_last_error_after_max_attempts() {
+        let (result, waited) = run(
+            &policy(3),
+            None,
+            failing(vec![
+                network(),
+                network(),
+                Error::ServiceDisconnected,
+                network(),
+            ]),
+        );
+        assert!(matches!(result, Err(Error::ServiceDisconnected)));
+        assert_eq!(waited.len(), 2);
+
+        let (result, waited) = run(&RetryPolicy::none(), None, failing(vec![network()]));
+        assert!(matches!(result, Err(Error::NetworkError(_))));
+        assert!(waited.is_empty());
+    }
+
+    #[test]
+    fn does_not_retry_permanent_errors() {
+        let (result, waited) = run(
+            &policy(3),
+            None,
+            failing(vec![network(), Error::ItemAlreadyOwned]),
+        );
+        assert!(matches!(result, Err(Error::ItemAlreadyOwned)));
+        assert_eq!(waited.len(), 1);
+    }
+
+    #[test]
+    fn stops_retrying_once_cancelled() {
+        let (result, waited) = run(
+            &policy(10),
+            Some(2),
+            failing(vec![network(), network(), network()]),
+        );
+        assert!(matches!(result, Err(Error::NetworkError(_))));
+        assert_eq!(waited.len(), 2);
+    }
+
+    #[test]
+    fn cancelling_the_token_wakes_up_a_pending_retry() {
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            base_delay: Duration::from_secs(60),
+            max_delay: Duration::from_secs(60),
+        };
+        let cancel = CancellationToken::new();
+        let started = Instant::now();
+        let canceller = {
+            let cancel = cancel.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                cancel.cancel();
+            })
+        };
+
+        let result = retry(&policy, &cancel, failing(vec![network(), network()]));
+        canceller.join().unwrap();
+        assert!(matches!(result, Err(Error::NetworkError(_))));
+        assert!(cancel.is_cancelled());
+        assert!(started.elapsed() < Duration::from_secs(30));
+
+        // Already cancelled: gives up without waiting.
+        let started = Instant::now();
+        let result = retry_connection(
+            &ConnectionRetryPolicy::forever(),
+            &cancel,
+            failing(vec![network()]),
+        );
+        assert!(matches!(result, Err(Error::NetworkError(_))));
+        assert!(started.elapsed() < Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tauri::ipc::{CommandScope, ScopeObjectMatch};
+
+use crate::{Error, Result};
+
+/// Entry of the `allow` and `deny` scope lists of the purchase commands,
+/// matching product IDs exactly or with `*` and `?` wildcards.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub(crate) struct ProductScope {
+    product_id: String,
+}
+
+impl ScopeObjectMatch for ProductScope {
+    type Input = str;
+
+    fn matches(&self, product_id: &str) -> bool {
+        glob_match(&self.product_id, product_id)
+    }
+}
+
+/// Fails with `Error::ScopeViolation` unless the scope allows buying
+/// `product_id`. Denied products always fail, and with no allowed products
+/// every other product passes.
+pub(crate) fn ensure_allowed(scope: &CommandScope<ProductScope>, product_id: &str) -> Result<()> {
+    if permits(scope.allows(), scope.denies(), product_id) {
+        Ok(())
+    } else {
+        Err(Error::ScopeViolation {
+            product_id: product_id.into(),
+        })
+    }
+}
+
+/// Whether `product_id` matches none of the `deny` entries, and one of the
+/// `allow` entries unless there are none, like `CommandScope::matches`.
+fn permits(allow: &[Arc<ProductScope>], deny: &[Arc<ProductScope>], product_id: &str) -> bool {
+    !deny.iter().any(|entry| entry.matches(product_id))
+        && (allow.is_empty() || allow.iter().any(|entry| entry.matches(product_id)))
+}
+
+/// Matches `text` against `pattern`, where `*` matches any run of characters
+/// and `?` a single one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and of the text it was matched against, to
+    // let it match one more character when the rest fails.
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(patterns: &[&str]) -> Vec<Arc<ProductScope>> {
+        patterns
+            .iter()
+            .map(|pattern| {
+                Arc::new(ProductScope {
+                    product_id: pattern.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_exact_ids_and_wildcards() {
+        assert!(glob_match("premium", "premium"));
+        assert!(!glob_match("premium", "premium_plus"));
+        assert!(!glob_match("premium", "Premium"));
+
+        assert!(glob_match("coins_*", "coins_100"));
+        assert!(glob_match("coins_*", "coins_"));
+        assert!(!glob_match("coins_*", "coin_100"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*_monthly", "pro_monthly"));
+        assert!(glob_match("pro_*_v?", "pro_yearly_v2"));
+        assert!(!glob_match("pro_*_v?", "pro_yearly_v10"));
+        assert!(glob_match("**", "anything"));
+        // A star matching again after a partial match failed.
+        assert!(glob_match("*ab", "aab"));
+        assert!(glob_match("a*b*c", "axbxxbc"));
+        assert!(!glob_match("a*b*c", "axbxxb"));
+        assert!(glob_match("?", "é"));
+        assert!(!glob_match("?", ""));
+        assert!(!glob_match("", "premium"));
+    }
+
+    #[test]
+    fn allows_only_the_allowed_products() {
+        let allow = entries(&["premium", "coins_*"]);
+
+        assert!(permits(&allow, &[], "premium"));
+        assert!(permits(&allow, &[], "coins_500"));
+        assert!(!permits(&allow, &[], "pro_monthly"));
+        // Without allowed products, everything not denied passes.
+        assert!(permits(&[], &[], "pro_monthly"));
+    }
+
+    #[test]
+    fn denied_products_win_over_allowed_ones() {
+        let allow = entries(&["*"]);
+        let deny = entries(&["coins_9?9", "premium"]);
+
+        assert!(!permits(&allow, &deny, "premium"));
+        assert!(!permits(&allow, &deny, "coins_999"));
+        assert!(permits(&allow, &deny, "coins_100"));
+        assert!(!permits(&[], &deny, "premium"));
+        assert!(permits(&[], &deny, "pro_monthly"));
+    }
+}
@@ -0,0 +1,111 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Milliseconds since the Unix epoch of the current time.
+pub(crate) fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
+
+/// Formats seconds since the Unix epoch as an ISO 8601 UTC datetime.
+pub(crate) fn iso8601(secs: i64) -> String {
+    let (days, time) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+
+    // Civil date from days since the epoch, see
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        time / 3_600,
+        time % 3_600 / 60,
+        time % 60
+    )
+}
+
+/// Seconds since the Unix epoch of a UTC datetime.
+pub(crate) fn unix_time(
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+) -> i64 {
+    // Days since the epoch from a civil date, see
+    // https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    days * 86_400 + hour * 3_600 + minute * 60 + second
+}
+
+/// Seconds since the Unix epoch of an ISO 8601 datetime like the stores
+/// report, e.g. `2024-01-31T12:00:00Z`, with optional fractional seconds, in
+/// UTC or at an offset from it like `2024-01-31T14:00:00+02:00`.
+pub(crate) fn parse_iso8601(date: &str) -> Option<i64> {
+    let (date, offset) = match date.strip_suffix('Z') {
+        Some(date) => (date, 0),
+        None => {
+            let split = date.len().checked_sub(6)?;
+            let (date, offset) = (date.get(..split)?, date.get(split..)?);
+            let sign = match offset.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            let offset = hours.parse::<i64>().ok()? * 3_600 + minutes.parse::<i64>().ok()? * 60;
+            (date, sign * offset)
+        }
+    };
+    let date = date.split_once('.').map_or(date, |(date, _)| date);
+    let (day, time) = date.split_once('T')?;
+    let fields = day
+        .split('-')
+        .chain(time.split(':'))
+        .map(|field| field.parse().ok())
+        .collect::<Option<Vec<i64>>>()?;
+    let [year, month, day, hour, minute, second] = fields[..] else {
+        return None;
+    };
+    Some(unix_time(year, month, day, hour, minute, second) - offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_utc_datetimes() {
+        let dates = [
+            (0, "1970-01-01T00:00:00Z"),
+            (1_706_702_400, "2024-01-31T12:00:00Z"),
+            (1_709_164_799, "2024-02-28T23:59:59Z"),
+            (1_709_251_199, "2024-02-29T23:59:59Z"),
+            (951_868_800, "2000-03-01T00:00:00Z"),
+            (-1, "1969-12-31T23:59:59Z"),
+            (253_402_300_799, "9999-12-31T23:59:59Z"),
+        ];
+        for (secs, date) in dates {
+            assert_eq!(iso8601(secs), date, "{secs}");
+        }
+    }
+
+    #[test]
+    fn now_is_after_the_epoch() {
+        assert!(now_ms() > 1_700_000_000_000);
+    }
+}
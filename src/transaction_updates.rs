@@ -0,0 +1,36 @@
+use std::sync::{Mutex, PoisonError};
+
+/// Tracks whether the store's listener for transaction updates, e.g.
+/// renewals or approved Ask to Buy purchases, is running.
+///
+/// The listener is started at most once per connection: `initialize` may be
+/// called again, and a second listener would report every update twice.
+#[derive(Default)]
+pub(crate) struct TransactionUpdates {
+    running: Mutex<bool>,
+}
+
+impl TransactionUpdates {
+    /// Calls `start` unless the listener is already running. `start` returns
+    /// whether the store has such a listener.
+    pub(crate) fn start(&self, start: impl FnOnce() -> crate::Result<bool>) -> crate::Result<()> {
+        // Held while starting, so concurrent callers don't both start one.
+        let mut running = self.running.lock().unwrap_or_else(PoisonError::into_inner);
+        if !*running {
+            *running = start()?;
+        }
+        Ok(())
+    }
+
+    /// Calls `stop` if the listener is running.
+    pub(crate) fn stop(&self, stop: impl FnOnce()) {
+        let running = std::mem::take(&mut *self.running.lock().unwrap_or_else(PoisonError::into_inner));
+        if running {
+            stop();
+        }
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        *self.running.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,291 @@
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+
+use crate::models::{PurchaseDetails, PurchaseStatus};
+use crate::persist::write_atomically;
+
+/// File in the app data directory listing the purchases awaiting completion.
+const UNFINISHED_PURCHASES_FILE: &str = "iap-unfinished-purchases.json";
+
+/// What is kept of a purchase awaiting completion: enough to find it among
+/// the owned purchases, without its token or receipt.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+    product_id: String,
+    purchase_id: Option<String>,
+    transaction_date: Option<String>,
+}
+
+impl Record {
+    fn of(purchase: &PurchaseDetails) -> Self {
+        Self {
+            product_id: purchase.product_id.clone(),
+            purchase_id: purchase.purchase_id.clone(),
+            transaction_date: purchase.transaction_date.clone(),
+        }
+    }
+
+    fn matches(&self, purchase: &PurchaseDetails) -> bool {
+        self.product_id == purchase.product_id && self.purchase_id == purchase.purchase_id
+    }
+}
+
+/// Purchases reported as awaiting completion, kept across restarts so a
+/// purchase isn't stranded when the app is killed before completing it.
+pub(crate) struct UnfinishedPurchases {
+    path: Option<PathBuf>,
+    records: Mutex<Vec<Record>>,
+}
+
+impl UnfinishedPurchases {
+    /// Loads the records kept in the app data directory.
+    pub(crate) fn in_app_data_dir<R: Runtime>(app: &AppHandle<R>) -> Self {
+        let path = app
+            .path()
+            .app_data_dir()
+            .map(|dir| dir.join(UNFINISHED_PURCHASES_FILE))
+            .inspect_err(|e| log::warn!("Unfinished purchases won't persist: {}", e))
+            .ok();
+        Self::load(path)
+    }
+
+    /// Loads the records from `path`, or keeps them in memory without one.
+    pub(crate) fn load(path: Option<PathBuf>) -> Self {
+        let records = match &path {
+            Some(path) => match std::fs::read(path) {
+                Ok(json) => serde_json::from_slice(&json).unwrap_or_else(|e| {
+                    log::warn!("Ignoring unfinished purchases at {}: {}", path.display(), e);
+                    Vec::new()
+                }),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => {
+                    log::warn!("Failed to read unfinished purchases at {}: {}", path.display(), e);
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
+        Self {
+            path,
+            records: Mutex::new(records),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Records the reported purchases awaiting completion and forgets the
+    /// ones reported as completed.
+    pub(crate) fn update(&self, purchases: &[PurchaseDetails]) {
+        let mut records = self.lock();
+        let mut changed = false;
+        for purchase in purchases {
+            let known = records.iter().position(|record| record.matches(purchase));
+            match (awaits_completion(purchase), known) {
+                (true, None) => records.push(Record::of(purchase)),
+                (false, Some(index)) => {
+                    records.remove(index);
+                }
+                _ => continue,
+            }
+            changed = true;
+        }
+        if changed {
+            self.save(&records);
+        }
+    }
+
+    /// Forgets a purchase once it was completed.
+    pub(crate) fn remove(&self, purchase: &PurchaseDetails) {
+        let mut records = self.lock();
+        let count = records.len();
+        records.retain(|record| !record.matches(purchase));
+        if records.len() != count {
+            self.save(&records);
+        }
+    }
+
+    /// Forgets the records the store no longer reports as awaiting
+    /// completion, returning the owned purchases that still are.
+    pub(crate) fn reconcile(&self, owned: &[PurchaseDetails]) -> Vec<PurchaseDetails> {
+        let mut records = self.lock();
+        let unfinished: Vec<PurchaseDetails> = owned
+            .iter()
+            .filter(|purchase| awaits_completion(purchase))
+            .filter(|purchase| records.iter().any(|record| record.matches(purchase)))
+            .cloned()
+            .collect();
+        let count = records.len();
+        records.retain(|record| unfinished.iter().any(|purchase| record.matches(purchase)));
+        if records.len() != count {
+            self.save(&records);
+        }
+        unfinished
+    }
+
+    /// Writes the records to the file, or removes it once there are none.
+    /// Failures only log a warning, as the records are still kept in memory.
+    fn save(&self, records: &[Record]) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = if records.is_empty() {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            }
+        } else {
+            serde_json::to_vec_pretty(records)
+                .map_err(std::io::Error::other)
+                .and_then(|json| write_atomically(path, &json))
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to save unfinished purchases to {}: {}", path.display(), e);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Record>> {
+        self.records.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn awaits_completion(purchase: &PurchaseDetails) -> bool {
+    purchase.pending_complete_purchase
+        && matches!(
+            purchase.status,
+            PurchaseStatus::Purchased | PurchaseStatus::Restored
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn purchase(id: &str, pending: bool) -> PurchaseDetails {
+        serde_json::from_value(json!({
+            "purchaseId": id,
+            "productId": "premium",
+            "verificationData": {
+                "localVerificationData": "receipt",
+                "serverVerificationData": "token",
+                "source": "google",
+            },
+            "transactionDateMs": 1_700_000_000_000u64,
+            "status": "purchased",
+            "pendingCompletePurchase": pending,
+        }))
+        .unwrap()
+    }
+
+    /// An empty directory of its own for each test.
+    fn dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tauri-plugin-iap-unfinished-{test}"));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn files(dir: &Path) -> Vec<String> {
+        let mut files: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn writes_through_a_temporary_file() {
+        let dir = dir("writes");
+        let path = dir.join(UNFINISHED_PURCHASES_FILE);
+        let unfinished = UnfinishedPurchases::load(Some(path.clone()));
+
+        unfinished.update(&[purchase("GPA.1", true), purchase("GPA.2", true)]);
+
+        // Only the renamed file is left, complete, without tokens or receipts.
+        assert_eq!(files(&dir), [UNFINISHED_PURCHASES_FILE]);
+        let json = std::fs::read_to_string(&path).unwrap();
+        assert!(!json.contains("token") && !json.contains("receipt"));
+        let records: Vec<Record> = serde_json::from_str(&json).unwrap();
+        assert_eq!(records.len(), 2);
+    }
+
+    #[test]
+    fn a_crash_while_writing_keeps_the_previous_records() {
+        let dir = dir("crash");
+        let path = dir.join(UNFINISHED_PURCHASES_FILE);
+        UnfinishedPurchases::load(Some(path.clone())).update(&[purchase("GPA.1", true)]);
+        // Killed halfway through writing the next records.
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, br#"[{"productId":"premium","purcha"#).unwrap();
+
+        let relaunched = UnfinishedPurchases::load(Some(path.clone()));
+        let owned = [purchase("GPA.1", true)];
+        assert_eq!(relaunched.reconcile(&owned).len(), 1);
+
+        // The next write replaces the leftover.
+        relaunched.update(&[purchase("GPA.2", true)]);
+        assert_eq!(files(&dir), [UNFINISHED_PURCHASES_FILE]);
+        let records: Vec<Record> = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(records.len(), 2);
+    }
+
+    #[test]
+    fn ignores_a_corrupted_file() {
+        let dir = dir("corrupted");
+        let path = dir.join(UNFINISHED_PURCHASES_FILE);
+        std::fs::write(&path, b"[{\"productId\":").unwrap();
+
+        let unfinished = UnfinishedPurchases::load(Some(path.clone()));
+
+        assert!(unfinished.is_empty());
+        unfinished.update(&[purchase("GPA.1", true)]);
+        assert!(!UnfinishedPurchases::load(Some(path)).is_empty());
+    }
+
+    #[test]
+    fn removes_the_file_once_everything_completed() {
+        let dir = dir("completed");
+        let path = dir.join(UNFINISHED_PURCHASES_FILE);
+        let unfinished = UnfinishedPurchases::load(Some(path.clone()));
+        unfinished.update(&[purchase("GPA.1", true), purchase("GPA.2", true)]);
+
+        unfinished.remove(&purchase("GPA.1", true));
+        assert!(path.exists());
+        // Reported as completed, e.g. by another device.
+        unfinished.update(&[purchase("GPA.2", false)]);
+
+        assert!(unfinished.is_empty());
+        assert!(files(&dir).is_empty());
+    }
+
+    #[test]
+    fn reconciles_with_the_owned_purchases() {
+        let unfinished = UnfinishedPurchases::load(None);
+        unfinished.update(&[
+            purchase("GPA.1", true),
+            purchase("GPA.2", true),
+            purchase("GPA.3", true),
+        ]);
+
+        // GPA.2 was completed meanwhile and GPA.3 refunded.
+        let owned = [purchase("GPA.1", true), purchase("GPA.2", false)];
+        let still: Vec<_> = unfinished
+            .reconcile(&owned)
+            .into_iter()
+            .map(|p| p.purchase_id.unwrap())
+            .collect();
+
+        assert_eq!(still, ["GPA.1"]);
+        assert_eq!(unfinished.lock().len(), 1);
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use crate::models::{ProductDetails, PurchaseDetails};
+use crate::{Error, Result};
+
+/// Picks the active subscription a purchase of `product_id` replaces, among
+/// the `owned` purchases.
+///
+/// `products` holds the details the store found for the new product and the
+/// owned ones. Owned purchases without a subscription state are told apart
+/// by their product's billing period. When the store reports the
+/// subscription group of the new product, only subscriptions of that group
+/// can be replaced; Google Play has no groups, so any subscription can.
+///
+/// # Errors
+///
+/// - `Error::PurchaseError` if the new product isn't a subscription.
+/// - `Error::ItemAlreadyOwned` if the user already subscribes to it.
+/// - `Error::NoActiveSubscription` if nothing can be replaced.
+/// - `Error::AmbiguousSubscription` if several subscriptions can.
+pub(crate) fn subscription_to_replace(
+    product_id: &str,
+    owned: Vec<PurchaseDetails>,
+    products: &[ProductDetails],
+) -> Result<PurchaseDetails> {
+    let products: HashMap<&str, &ProductDetails> = products
+        .iter()
+        .map(|product| (product.id.as_str(), product))
+        .collect();
+    let new_product = products.get(product_id);
+    if new_product.is_some_and(|product| !product.is_subscription()) {
+        return Err(Error::PurchaseError(format!("{product_id} is not a subscription")));
+    }
+    let group = new_product.and_then(|product| product.subscription_group_id.as_deref());
+
+    let mut candidates: Vec<PurchaseDetails> = Vec::new();
+    for purchase in owned {
+        let product = products.get(purchase.product_id.as_str());
+        let active = match purchase.subscription_state {
+            Some(state) => state.is_entitled(),
+            None => product.is_some_and(|product| product.is_subscription()),
+        };
+        if !active {
+            continue;
+        }
+        if purchase.product_id == product_id {
+            return Err(Error::ItemAlreadyOwned);
+        }
+        let same_group = group.map_or(true, |group| {
+            product.and_then(|product| product.subscription_group_id.as_deref()) == Some(group)
+        });
+        if same_group && !candidates.iter().any(|known| known.product_id == purchase.product_id) {
+            candidates.push(purchase);
+        }
+    }
+
+    match candidates.len() {
+        0 => Err(Error::NoActiveSubscription(product_id.to_string())),
+        1 => Ok(candidates.remove(0)),
+        _ => Err(Error::AmbiguousSubscription {
+            candidates: candidates
+                .into_iter()
+                .map(|purchase| purchase.product_id)
+                .collect(),
+        }),
+    }
+}
+
+/// The product IDs to query before picking the subscription to replace: the
+/// new product, then the owned ones.
+pub(crate) fn products_to_query(product_id: &str, owned: &[PurchaseDetails]) -> Vec<String> {
+    let mut product_ids = vec![product_id.to_string()];
+    for purchase in owned {
+        if !product_ids.contains(&purchase.product_id) {
+            product_ids.push(purchase.product_id.clone());
+        }
+    }
+    product_ids
+}
@@ -0,0 +1,70 @@
+//! Wire format of `AppLicense` and how each store's answer maps to it: the
+//! Microsoft Store license, the App Store app transaction and the license
+//! declared for Android.
+
+use serde_json::json;
+use tauri_plugin_iap::{AppLicense, Config};
+
+#[test]
+fn microsoft_store_licenses_round_trip() {
+    let wire = json!({
+        "isActive": true,
+        "isTrial": true,
+        "trialExpirationDate": "2026-11-01T00:00:00Z",
+        "skuStoreId": "9NBLGGH4R315/0010",
+        "addOnLicenses": [{
+            "productId": "9NBLGGH4TNMP",
+            "skuStoreId": "9NBLGGH4TNMP/0010",
+            "inAppOfferToken": "premium",
+            "isActive": true,
+            "expirationDate": null
+        }]
+    });
+
+    let license: AppLicense = serde_json::from_value(wire.clone()).unwrap();
+    assert!(license.is_trial);
+    assert_eq!(license.original_version, None);
+    assert_eq!(license.add_on_licenses[0].in_app_offer_token, "premium");
+    assert_eq!(serde_json::to_value(&license).unwrap(), wire);
+}
+
+#[test]
+fn app_transactions_map_to_licenses() {
+    let license =
+        AppLicense::from_app_transaction(true, "1.2".into(), "2024-03-01T09:30:00Z".into());
+    assert_eq!(
+        serde_json::to_value(&license).unwrap(),
+        json!({
+            "isActive": true,
+            "isTrial": false,
+            "trialExpirationDate": null,
+            "originalVersion": "1.2",
+            "originalPurchaseDate": "2024-03-01T09:30:00Z",
+            "skuStoreId": "",
+            "addOnLicenses": []
+        })
+    );
+}
+
+#[test]
+fn unverified_app_transactions_grant_no_access() {
+    let license =
+        AppLicense::from_app_transaction(false, "1.2".into(), "2024-03-01T09:30:00Z".into());
+    assert!(!license.is_active);
+    assert_eq!(license.original_version.as_deref(), Some("1.2"));
+}
+
+#[test]
+fn android_licenses_are_declared_in_the_config() {
+    let config: Config = serde_json::from_value(json!({
+        "androidAppLicense": { "isActive": true, "isTrial": false }
+    }))
+    .unwrap();
+    let license = config.android_app_license.unwrap();
+    assert!(license.is_active && !license.is_trial);
+    assert_eq!(license.sku_store_id, "");
+    assert!(license.add_on_licenses.is_empty());
+
+    let config: Config = serde_json::from_value(json!({})).unwrap();
+    assert!(config.android_app_license.is_none());
+}
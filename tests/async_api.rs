@@ -0,0 +1,145 @@
+//! The async methods of `Iap` run their store calls on worker threads, so
+//! independent operations awaited at once reach the store concurrently
+//! instead of queueing behind each other.
+
+mod common;
+
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use tauri::async_runtime::{block_on, spawn};
+use tauri::test::{mock_builder, mock_context, noop_assets, MockRuntime};
+use tauri::App;
+use tauri_plugin_iap::{
+    DesktopIapBackend, Error, IapExt, ProductDetailsResponse, PurchaseDetails, PurchaseEventSink,
+    PurchaseParam, Result,
+};
+
+use common::{param, product};
+
+/// Store whose calls only return once `expected` of them are in progress at
+/// the same time, failing if that doesn't happen within a few seconds.
+struct RendezvousStore {
+    expected: usize,
+    in_progress: Mutex<usize>,
+    arrived: Condvar,
+}
+
+impl RendezvousStore {
+    fn new(expected: usize) -> Self {
+        Self {
+            expected,
+            in_progress: Mutex::new(0),
+            arrived: Condvar::new(),
+        }
+    }
+
+    fn meet(&self, operation: &str) -> Result<()> {
+        let mut in_progress = self.in_progress.lock().unwrap();
+        *in_progress += 1;
+        self.arrived.notify_all();
+        let (in_progress, timeout) = self
+            .arrived
+            .wait_timeout_while(in_progress, Duration::from_secs(5), |in_progress| {
+                *in_progress < self.expected
+            })
+            .unwrap();
+        if timeout.timed_out() {
+            return Err(Error::InternalError(format!(
+                "{operation} ran alone, {} of {} calls were in progress",
+                *in_progress, self.expected
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl DesktopIapBackend for RendezvousStore {
+    fn initialize(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn is_available(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn query_product_details(&self, product_ids: &[String]) -> Result<ProductDetailsResponse> {
+        self.meet("query_product_details")?;
+        Ok(ProductDetailsResponse {
+            product_details: product_ids.iter().map(|id| product(id)).collect(),
+            ..Default::default()
+        })
+    }
+
+    fn buy_non_consumable(
+        &self,
+        purchase_param: &PurchaseParam,
+        _: &PurchaseEventSink,
+    ) -> Result<bool> {
+        self.meet(&format!(
+            "buy_non_consumable({})",
+            purchase_param.product_details.id
+        ))?;
+        Ok(true)
+    }
+
+    fn buy_consumable(&self, _: &PurchaseParam, _: bool, _: &PurchaseEventSink) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn complete_purchase(&self, _: &PurchaseDetails) -> Result<()> {
+        Ok(())
+    }
+
+    fn restore_purchases(&self, _: Option<&str>, _: &PurchaseEventSink) -> Result<()> {
+        self.meet("restore_purchases")
+    }
+
+    fn country_code(&self) -> Result<String> {
+        Ok("US".into())
+    }
+}
+
+fn app(store: RendezvousStore) -> App<MockRuntime> {
+    let app = mock_builder()
+        .plugin(
+            tauri_plugin_iap::Builder::new()
+                .desktop_backend(store)
+                .build(),
+        )
+        .build(mock_context(noop_assets()))
+        .expect("failed to build the app");
+    block_on(app.iap().initialize()).unwrap();
+    app
+}
+
+#[test]
+fn a_product_query_and_a_restore_overlap() {
+    let app = app(RendezvousStore::new(2));
+    let (iap, other) = (app.iap().clone(), app.iap().clone());
+
+    let query = spawn(async move { iap.query_product_details(vec!["premium".into()]).await });
+    let restore = spawn(async move { other.restore_purchases(None).await });
+
+    let (query, restore) = block_on(async { (query.await.unwrap(), restore.await.unwrap()) });
+    assert_eq!(query.unwrap().product_details[0].id, "premium");
+    restore.unwrap();
+}
+
+#[test]
+fn purchases_of_different_products_overlap() {
+    let app = app(RendezvousStore::new(2));
+    let purchases: Vec<_> = ["premium", "no_ads"]
+        .into_iter()
+        .map(|id| {
+            let iap = app.iap().clone();
+            spawn(async move { iap.buy_non_consumable(param(id)).await })
+        })
+        .collect();
+
+    block_on(async {
+        for purchase in purchases {
+            assert!(purchase.await.unwrap().unwrap());
+        }
+    });
+}
@@ -0,0 +1,92 @@
+//! The owned purchases reported by `initialize` with `autoRestoreOnInit`,
+//! against the mock store.
+
+#![cfg(feature = "mock")]
+
+mod common;
+
+use std::time::Duration;
+
+use serde_json::json;
+use tauri::async_runtime::block_on;
+use tauri_plugin_iap::{IapErrorCode, IapExt, MockOperation, PurchaseStatus};
+
+use common::{fresh, launch, purchase_updates, wait_for};
+
+#[test]
+fn reports_the_owned_purchases_after_initialize() {
+    fresh("auto-restore-reports");
+    let app = launch("auto-restore-reports", json!({ "autoRestoreOnInit": true }));
+    let iap = app.handle().iap();
+    iap.mock_store().unwrap().grant("premium");
+    let updates = purchase_updates(&app);
+
+    // Nothing is queried before the app initializes.
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(updates.lock().unwrap().is_empty());
+    assert!(!iap.is_owned("premium"));
+
+    block_on(iap.initialize()).unwrap();
+
+    let updates = wait_for(&updates, 1);
+    assert_eq!(updates.len(), 1);
+    let products: Vec<_> = updates[0].iter().map(|p| p.product_id.as_str()).collect();
+    assert_eq!(products, ["premium"]);
+    assert!(matches!(updates[0][0].status, PurchaseStatus::Restored));
+    assert!(iap.is_owned("premium"));
+    // Restored silently: the app's restore counter doesn't move.
+    assert_eq!(iap.metrics().restores, 0);
+}
+
+#[test]
+fn reports_once_per_app_run() {
+    fresh("auto-restore-once");
+    let app = launch("auto-restore-once", json!({ "autoRestoreOnInit": true }));
+    let iap = app.handle().iap();
+    iap.mock_store().unwrap().grant("premium");
+    let updates = purchase_updates(&app);
+
+    block_on(iap.initialize()).unwrap();
+    wait_for(&updates, 1);
+    block_on(iap.initialize()).unwrap();
+    iap.end_connection();
+    block_on(iap.initialize()).unwrap();
+
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(updates.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn failures_do_not_fail_initialize() {
+    fresh("auto-restore-fails");
+    let app = launch("auto-restore-fails", json!({ "autoRestoreOnInit": true }));
+    let iap = app.handle().iap();
+    let mock = iap.mock_store().unwrap();
+    mock.grant("premium");
+    mock.fail_next(MockOperation::RestorePurchases, IapErrorCode::NetworkError);
+    let updates = purchase_updates(&app);
+
+    block_on(iap.initialize()).unwrap();
+
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(updates.lock().unwrap().is_empty());
+    assert!(!iap.is_owned("premium"));
+    // The app can still restore by itself.
+    block_on(iap.restore_purchases(None)).unwrap();
+    assert!(iap.is_owned("premium"));
+}
+
+#[test]
+fn reports_nothing_when_disabled() {
+    fresh("auto-restore-disabled");
+    let app = launch("auto-restore-disabled", json!({}));
+    let iap = app.handle().iap();
+    iap.mock_store().unwrap().grant("premium");
+    let updates = purchase_updates(&app);
+
+    block_on(iap.initialize()).unwrap();
+
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(updates.lock().unwrap().is_empty());
+    assert!(!iap.is_owned("premium"));
+}
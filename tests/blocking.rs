@@ -0,0 +1,96 @@
+//! The blocking wrappers return what the async methods they wrap return,
+//! from synchronous code and from inside an async task.
+
+#![cfg(all(feature = "blocking", feature = "mock"))]
+
+mod common;
+
+use serde_json::to_value;
+use tauri::async_runtime::{block_on, spawn};
+use tauri_plugin_iap::{Result, StoreFeature};
+
+use common::{mock_iap, param};
+
+/// What a session returns, in a comparable form.
+fn outcome<T: serde::Serialize>(result: Result<T>) -> serde_json::Value {
+    match result {
+        Ok(value) => to_value(value).unwrap(),
+        Err(e) => to_value(e.code()).unwrap(),
+    }
+}
+
+#[test]
+fn queries_return_what_the_async_call_returns() {
+    let (_app, iap) = mock_iap(&["premium", "coins"]);
+    let ids = || vec!["premium".to_string(), "missing".to_string()];
+
+    assert_eq!(
+        outcome(iap.blocking().query_product_details(ids())),
+        outcome(block_on(iap.query_product_details(ids())))
+    );
+    assert_eq!(
+        outcome(
+            iap.blocking()
+                .is_feature_supported(StoreFeature::Subscriptions)
+        ),
+        outcome(block_on(
+            iap.is_feature_supported(StoreFeature::Subscriptions)
+        ))
+    );
+    assert_eq!(
+        outcome(iap.blocking().country_code()),
+        outcome(block_on(iap.country_code()))
+    );
+}
+
+#[test]
+fn a_session_returns_what_the_async_calls_return() {
+    let (_app, blocking) = mock_iap(&["premium", "coins"]);
+    let (_other, awaited) = mock_iap(&["premium", "coins"]);
+    let blocking = blocking.blocking();
+
+    assert_eq!(
+        outcome(blocking.initialize()),
+        outcome(block_on(awaited.initialize()))
+    );
+    assert_eq!(
+        outcome(blocking.buy_non_consumable(param("premium"))),
+        outcome(block_on(awaited.buy_non_consumable(param("premium"))))
+    );
+    // Already owned.
+    assert_eq!(
+        outcome(blocking.buy_non_consumable(param("premium"))),
+        outcome(block_on(awaited.buy_non_consumable(param("premium"))))
+    );
+    assert_eq!(
+        outcome(blocking.buy_consumable(param("coins"), true)),
+        outcome(block_on(awaited.buy_consumable(param("coins"), true)))
+    );
+    assert_eq!(
+        outcome(blocking.restore_purchases(None)),
+        outcome(block_on(awaited.restore_purchases(None)))
+    );
+    assert_eq!(
+        outcome(blocking.subscription_status("premium")),
+        outcome(block_on(awaited.subscription_status("premium")))
+    );
+    assert_eq!(
+        outcome(blocking.subscription_time_remaining("missing")),
+        outcome(block_on(awaited.subscription_time_remaining("missing")))
+    );
+}
+
+#[test]
+fn can_be_called_from_an_async_task() {
+    let (_app, iap) = mock_iap(&["premium", "coins"]);
+    let expected = outcome(block_on(iap.query_product_details(vec!["premium".into()])));
+
+    let task = {
+        let iap = iap.clone();
+        spawn(async move { outcome(iap.blocking().query_product_details(vec!["premium".into()])) })
+    };
+    let nested = block_on(async { iap.blocking().country_code() });
+
+    assert_eq!(block_on(task).unwrap(), expected);
+    assert_eq!(outcome(nested), outcome(block_on(iap.country_code())));
+}
@@ -0,0 +1,144 @@
+//! `buy_upgrade` against the mock store: finding the subscription to replace
+//! among the owned ones, then switching it to the new subscription.
+
+#![cfg(feature = "mock")]
+
+use serde_json::json;
+use tauri::async_runtime::block_on;
+use tauri::test::{mock_app, MockRuntime};
+use tauri::App;
+use tauri_plugin_iap::{Error, IAPError, Iap, MockOperation, ProductDetails, ReplacementMode};
+
+fn product(id: &str, subscription_group_id: Option<&str>) -> ProductDetails {
+    ProductDetails {
+        id: id.into(),
+        title: id.into(),
+        description: String::new(),
+        price: "$4.99".into(),
+        raw_price: 4.99,
+        currency_code: "USD".into(),
+        currency_symbol: "$".into(),
+        subscription_period: Some("P1M".into()),
+        free_trial_period: None,
+        subscription_group_id: subscription_group_id.map(Into::into),
+    }
+}
+
+fn lifetime() -> ProductDetails {
+    ProductDetails {
+        subscription_period: None,
+        ..product("lifetime", None)
+    }
+}
+
+/// A mock store selling `catalog`, where the user owns `owned`. The app is
+/// kept alive with the plugin API.
+fn iap(catalog: Vec<ProductDetails>, owned: &[&str]) -> (App<MockRuntime>, Iap<MockRuntime>) {
+    let app = mock_app();
+    let iap = Iap::mock(app.handle(), catalog);
+    block_on(iap.initialize()).unwrap();
+    for product_id in owned {
+        iap.mock_store().unwrap().grant(product_id);
+    }
+    (app, iap)
+}
+
+#[test]
+fn upgrade_replaces_the_subscription_of_the_same_group() {
+    let (_app, iap) = iap(
+        vec![
+            product("premium_monthly", Some("premium")),
+            product("premium_yearly", Some("premium")),
+            product("storage_monthly", Some("storage")),
+        ],
+        &["premium_monthly", "storage_monthly"],
+    );
+
+    let purchase = block_on(iap.buy_upgrade(
+        "premium_yearly".into(),
+        ReplacementMode::ChargeProratedPrice,
+    ))
+    .unwrap()
+    .expect("mock purchases are never pending");
+
+    assert_eq!(purchase.product_id, "premium_yearly");
+    let mock = iap.mock_store().unwrap();
+    let owned: Vec<String> = mock.purchases().into_iter().map(|p| p.product_id).collect();
+    assert_eq!(owned, ["storage_monthly", "premium_yearly"]);
+    let call = mock
+        .calls()
+        .into_iter()
+        .find(|call| call.operation == MockOperation::BuyUpgrade)
+        .unwrap();
+    assert_eq!(call.payload["oldProductId"], "premium_monthly");
+    assert_eq!(call.payload["replacementMode"], "chargeProratedPrice");
+}
+
+#[test]
+fn upgrade_without_an_active_subscription_fails() {
+    let (_app, iap) = iap(
+        vec![lifetime(), product("premium_yearly", None)],
+        &["lifetime"],
+    );
+
+    let error =
+        block_on(iap.buy_upgrade("premium_yearly".into(), ReplacementMode::WithTimeProration))
+            .unwrap_err();
+
+    assert!(
+        matches!(&error, Error::NoActiveSubscription(id) if id == "premium_yearly"),
+        "{error:?}"
+    );
+    assert!(iap
+        .mock_store()
+        .unwrap()
+        .calls()
+        .iter()
+        .all(|call| call.operation != MockOperation::BuyUpgrade));
+}
+
+#[test]
+fn upgrade_with_several_candidates_lists_them() {
+    // Google Play has no subscription groups, so any subscription could be
+    // the one to replace.
+    let (_app, iap) = iap(
+        vec![
+            product("premium_monthly", None),
+            product("storage_monthly", None),
+            product("premium_yearly", None),
+        ],
+        &["premium_monthly", "storage_monthly"],
+    );
+
+    let error =
+        block_on(iap.buy_upgrade("premium_yearly".into(), ReplacementMode::WithTimeProration))
+            .unwrap_err();
+
+    assert!(
+        matches!(
+            &error,
+            Error::AmbiguousSubscription { candidates }
+                if candidates == &["premium_monthly", "storage_monthly"]
+        ),
+        "{error:?}"
+    );
+    assert_eq!(
+        IAPError::from(&error).details,
+        Some(json!({ "candidates": ["premium_monthly", "storage_monthly"] }))
+    );
+    assert_eq!(iap.mock_store().unwrap().purchases().len(), 2);
+}
+
+#[test]
+fn upgrade_to_the_current_subscription_fails() {
+    let (_app, iap) = iap(
+        vec![product("premium_monthly", Some("premium"))],
+        &["premium_monthly"],
+    );
+
+    let error =
+        block_on(iap.buy_upgrade("premium_monthly".into(), ReplacementMode::WithTimeProration))
+            .unwrap_err();
+
+    assert!(matches!(error, Error::ItemAlreadyOwned), "{error:?}");
+}
@@ -0,0 +1,1848 @@
+//! Drives the plugin commands through the IPC layer of the tauri mock runtime,
+//! with a recording store backend, to check payload deserialization, argument
+//! defaults, permission wiring and error serialization.
+
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tauri::async_runtime::block_on;
+use tauri::ipc::{CallbackFn, InvokeBody, RuntimeAuthority};
+use tauri::test::{
+    get_ipc_response, mock_builder, mock_context, noop_assets, MockRuntime, INVOKE_KEY,
+};
+use tauri::utils::acl::{manifest::Manifest, resolved::Resolved, ExecutionContext};
+use tauri::utils::platform::Target;
+use tauri::webview::InvokeRequest;
+use tauri::{App, Listener, Manager, WebviewWindow, WebviewWindowBuilder};
+use tauri_plugin_iap::{
+    DesktopIapBackend, Diagnostics, Error, IapErrorCode, IapExt, ProductDetails,
+    ProductDetailsResponse, PurchaseDetails, PurchaseEventSink, PurchaseParam, PurchaseStatus,
+    PurchaseVerificationData, Result, SubscriptionState, PURCHASE_UPDATE_EVENT,
+};
+
+/// Store answering with fixed products and recording the arguments it was
+/// called with.
+#[derive(Default)]
+struct RecordingStore {
+    calls: Mutex<Vec<Value>>,
+    /// Events of the last restore, kept to report late updates like a store
+    /// listener would.
+    updates: Mutex<Option<PurchaseEventSink>>,
+    /// Number of transaction update listeners running.
+    listeners: Mutex<usize>,
+    /// How long completing a purchase takes.
+    completion_delay: Mutex<Duration>,
+    /// How long the manage subscriptions page stays open.
+    sheet_delay: Mutex<Duration>,
+    /// How long restoring purchases takes.
+    restore_delay: Mutex<Duration>,
+}
+
+impl RecordingStore {
+    fn record(&self, call: Value) {
+        self.calls.lock().unwrap().push(call);
+    }
+
+    fn calls(&self) -> Vec<Value> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn purchase(product_id: &str, status: PurchaseStatus) -> PurchaseDetails {
+        PurchaseDetails {
+            purchase_id: Some(format!("order-{product_id}")),
+            product_id: product_id.into(),
+            verification_data: PurchaseVerificationData {
+                local_verification_data: format!("token-{product_id}"),
+                server_verification_data: format!("token-{product_id}"),
+                source: "test".into(),
+                signature: None,
+            },
+            transaction_date: None,
+            transaction_date_ms: None,
+            expiration_date: None,
+            subscription_state: None,
+            grace_period_expiration_date: None,
+            status,
+            error: None,
+            pending_complete_purchase: true,
+            verified: false,
+        }
+    }
+}
+
+impl DesktopIapBackend for RecordingStore {
+    fn initialize(&self) -> Result<()> {
+        self.record(json!({ "op": "initialize" }));
+        Ok(())
+    }
+
+    fn is_available(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn query_product_details(&self, product_ids: &[String]) -> Result<ProductDetailsResponse> {
+        self.record(json!({ "op": "query", "productIds": product_ids }));
+        let (found, not_found): (Vec<_>, Vec<_>) =
+            product_ids.iter().partition(|id| *id == "premium");
+        Ok(ProductDetailsResponse {
+            product_details: found.into_iter().map(|_| premium()).collect(),
+            not_found_ids: not_found.into_iter().cloned().collect(),
+            error: None,
+        })
+    }
+
+    fn buy_non_consumable(
+        &self,
+        purchase_param: &PurchaseParam,
+        events: &PurchaseEventSink,
+    ) -> Result<bool> {
+        let product_id = &purchase_param.product_details.id;
+        self.record(json!({
+            "op": "buy_non_consumable",
+            "productId": product_id,
+            "applicationUserName": purchase_param.application_user_name,
+        }));
+        events.emit(&[Self::purchase(product_id, PurchaseStatus::Purchased)]);
+        Ok(true)
+    }
+
+    fn buy_consumable(
+        &self,
+        purchase_param: &PurchaseParam,
+        auto_consume: bool,
+        events: &PurchaseEventSink,
+    ) -> Result<bool> {
+        let product_id = &purchase_param.product_details.id;
+        self.record(json!({
+            "op": "buy_consumable",
+            "productId": product_id,
+            "autoConsume": auto_consume,
+        }));
+        events.emit(&[Self::purchase(product_id, PurchaseStatus::Purchased)]);
+        Ok(true)
+    }
+
+    fn complete_purchase(&self, purchase: &PurchaseDetails) -> Result<()> {
+        self.record(json!({ "op": "complete_purchase", "purchaseId": purchase.purchase_id }));
+        thread::sleep(*self.completion_delay.lock().unwrap());
+        if purchase.purchase_id.as_deref() == Some("order-premium") {
+            Ok(())
+        } else {
+            Err(Error::UnknownPurchase(purchase.product_id.clone()))
+        }
+    }
+
+    fn restore_purchases(
+        &self,
+        application_user_name: Option<&str>,
+        events: &PurchaseEventSink,
+    ) -> Result<()> {
+        self.record(json!({
+            "op": "restore_purchases",
+            "applicationUserName": application_user_name,
+        }));
+        thread::sleep(*self.restore_delay.lock().unwrap());
+        events.emit(&[Self::purchase("premium", PurchaseStatus::Restored)]);
+        *self.updates.lock().unwrap() = Some(events.clone());
+        Ok(())
+    }
+
+    fn country_code(&self) -> Result<String> {
+        Ok("US".into())
+    }
+
+    fn open_manage_subscriptions(&self) -> Result<()> {
+        thread::sleep(*self.sheet_delay.lock().unwrap());
+        self.record(json!({ "op": "open_manage_subscriptions" }));
+        Ok(())
+    }
+
+    fn describe(&self, diagnostics: &mut Diagnostics) {
+        diagnostics.store = Some("Recording store".into());
+    }
+
+    fn start_transaction_updates(&self, _events: &PurchaseEventSink) -> Result<bool> {
+        *self.listeners.lock().unwrap() += 1;
+        Ok(true)
+    }
+
+    fn stop_transaction_updates(&self) {
+        *self.listeners.lock().unwrap() -= 1;
+    }
+}
+
+fn premium() -> ProductDetails {
+    ProductDetails {
+        id: "premium".into(),
+        title: "Premium".into(),
+        description: "Unlocks all features".into(),
+        price: "$4.99".into(),
+        raw_price: 4.99,
+        currency_code: "USD".into(),
+        currency_symbol: "$".into(),
+        subscription_period: None,
+        free_trial_period: None,
+        subscription_group_id: None,
+    }
+}
+
+/// The `productDetails` of a purchase param, as the JS bindings send it.
+fn premium_json() -> Value {
+    json!({
+        "id": "premium",
+        "title": "Premium",
+        "description": "Unlocks all features",
+        "price": "$4.99",
+        "rawPrice": 4.99,
+        "currencyCode": "USD",
+        "currencySymbol": "$"
+    })
+}
+
+/// Commands listed in build.rs, the source of the autogenerated permissions.
+fn commands() -> Vec<String> {
+    let build = include_str!("../build.rs");
+    let list = build
+        .split_once("const COMMANDS: &[&str] = &[")
+        .and_then(|(_, rest)| rest.split_once("];"))
+        .expect("COMMANDS is missing from build.rs")
+        .0;
+    list.split(',')
+        .map(|command| command.trim().trim_matches('"').to_string())
+        .filter(|command| !command.is_empty())
+        .collect()
+}
+
+/// The `allow-*` permissions listed in a permission file.
+fn allowed(permissions: &str) -> Vec<String> {
+    permissions
+        .split('"')
+        .filter(|token| token.starts_with("allow-"))
+        .map(|token| token.trim_start_matches("allow-").replace('-', "_"))
+        .collect()
+}
+
+fn app(store: Arc<RecordingStore>) -> (App<MockRuntime>, WebviewWindow<MockRuntime>) {
+    app_with_config(store, json!({}))
+}
+
+/// An identifier of its own for each test, whose app data directory is
+/// emptied first: the plugin keeps unfinished purchases there, which would
+/// otherwise be reported again by the other tests and the next runs.
+fn identifier() -> String {
+    let test = thread::current().name().unwrap_or("main").replace("::", ".");
+    let identifier = format!("dev.tauri-plugin-iap.command-tests.{test}");
+    let mut context = mock_context(noop_assets());
+    context.config_mut().identifier = identifier.clone();
+    let app = mock_builder().build(context).expect("failed to build the app");
+    if let Ok(dir) = app.path().app_data_dir() {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+    identifier
+}
+
+/// An app with `config` as its `plugins.iap` configuration.
+fn app_with_config(
+    store: Arc<RecordingStore>,
+    config: Value,
+) -> (App<MockRuntime>, WebviewWindow<MockRuntime>) {
+    app_with_builder(tauri_plugin_iap::Builder::new().desktop_backend(store), config)
+}
+
+/// An app with the plugin built by `builder`, and `config` as its
+/// `plugins.iap` configuration.
+fn app_with_builder(
+    builder: tauri_plugin_iap::Builder,
+    config: Value,
+) -> (App<MockRuntime>, WebviewWindow<MockRuntime>) {
+    let mut context = mock_context(noop_assets());
+    context.config_mut().identifier = identifier();
+    context.config_mut().plugins.0.insert("iap".into(), config);
+    for command in commands()
+        .iter()
+        .chain(["open_manage_subscriptions".into()].iter())
+    {
+        context
+            .runtime_authority_mut()
+            .__allow_command(format!("plugin:iap|{command}"), ExecutionContext::Local);
+    }
+    let app = mock_builder()
+        .plugin(builder.build())
+        .build(context)
+        .expect("failed to build the app");
+    let webview = WebviewWindowBuilder::new(&app, "main", Default::default())
+        .build()
+        .expect("failed to build the window");
+    (app, webview)
+}
+
+/// The ACL manifest of the plugin, put together from its permission files
+/// like the app build does: an `allow-*` permission per command, the default
+/// permissions and the sets.
+fn manifest() -> Manifest {
+    let permission = |command: &str| format!("allow-{}", command.replace('_', "-"));
+    let permissions: Vec<_> = commands()
+        .iter()
+        .map(String::as_str)
+        .chain(["open_manage_subscriptions"])
+        .map(|command| {
+            json!({ "identifier": permission(command), "commands": { "allow": [command] } })
+        })
+        .collect();
+    let sets: Vec<_> = [
+        include_str!("../permissions/sets.toml"),
+        include_str!("../permissions/subscription-management.toml"),
+    ]
+    .iter()
+    .flat_map(|file| file.split("[[set]]").skip(1))
+    .map(|set| {
+        let identifier = set.split('"').nth(1).unwrap();
+        let allowed: Vec<_> = allowed(set).iter().map(|command| permission(command)).collect();
+        json!({ "identifier": identifier, "description": "", "permissions": allowed })
+    })
+    .collect();
+    let default: Vec<_> = allowed(include_str!("../permissions/default.toml"))
+        .iter()
+        .map(|command| permission(command))
+        .collect();
+    let file = json!({
+        "default": { "permissions": default },
+        "set": sets,
+        "permission": permissions,
+    });
+    Manifest::new(vec![serde_json::from_value(file).unwrap()], None)
+}
+
+/// An app whose `main` window is granted `permissions` by a capability,
+/// instead of every command.
+fn app_with_capability(
+    store: Arc<RecordingStore>,
+    permissions: &[&str],
+) -> (App<MockRuntime>, WebviewWindow<MockRuntime>) {
+    let acl = [("iap".to_string(), manifest())].into();
+    let capability = json!({
+        "identifier": "preview",
+        "windows": ["main"],
+        "permissions": permissions,
+    });
+    let capabilities = [("preview".into(), serde_json::from_value(capability).unwrap())].into();
+    let resolved = Resolved::resolve(&acl, capabilities, Target::current()).unwrap();
+    let mut context = mock_context(noop_assets());
+    context.config_mut().identifier = identifier();
+    *context.runtime_authority_mut() = RuntimeAuthority::new(acl, resolved);
+    let builder = tauri_plugin_iap::Builder::new().desktop_backend(store);
+    let app = mock_builder()
+        .plugin(builder.build())
+        .build(context)
+        .expect("failed to build the app");
+    let webview = WebviewWindowBuilder::new(&app, "main", Default::default())
+        .build()
+        .expect("failed to build the window");
+    (app, webview)
+}
+
+/// Like [`app_with_config`], with the store connected by `initialize`,
+/// whose call isn't kept.
+fn initialized_app(
+    store: Arc<RecordingStore>,
+    config: Value,
+) -> (App<MockRuntime>, WebviewWindow<MockRuntime>) {
+    let (app, webview) = app_with_config(store.clone(), config);
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    store.calls.lock().unwrap().clear();
+    (app, webview)
+}
+
+fn invoke(
+    webview: &WebviewWindow<MockRuntime>,
+    command: &str,
+    args: Value,
+) -> std::result::Result<Value, Value> {
+    get_ipc_response(
+        webview,
+        InvokeRequest {
+            cmd: format!("plugin:iap|{command}"),
+            callback: CallbackFn(0),
+            error: CallbackFn(1),
+            url: if cfg!(windows) {
+                "http://tauri.localhost"
+            } else {
+                "tauri://localhost"
+            }
+            .parse()
+            .unwrap(),
+            body: InvokeBody::Json(args),
+            headers: Default::default(),
+            invoke_key: INVOKE_KEY.to_string(),
+        },
+    )
+    .map(|body| body.deserialize::<Value>().unwrap())
+}
+
+#[test]
+fn initializes_and_reports_the_connection_state() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = app(store.clone());
+
+    assert_eq!(
+        invoke(&webview, "connection_state", json!({})),
+        Ok(json!("uninitialized"))
+    );
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    assert_eq!(
+        invoke(&webview, "connection_state", json!({})),
+        Ok(json!("ready"))
+    );
+    assert_eq!(invoke(&webview, "is_available", json!({})), Ok(json!(true)));
+    assert_eq!(store.calls(), vec![json!({ "op": "initialize" })]);
+}
+
+#[test]
+fn store_calls_fail_before_initialize() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = app(store.clone());
+    let purchase = serde_json::to_value(RecordingStore::purchase(
+        "premium",
+        PurchaseStatus::Purchased,
+    ))
+    .unwrap();
+    let buy = json!({ "purchaseParam": { "productDetails": premium_json() } });
+
+    for (command, args) in [
+        ("query_product_details", json!({ "productIds": ["premium"] })),
+        ("buy_non_consumable", buy.clone()),
+        ("buy_consumable", buy),
+        (
+            "buy_upgrade",
+            json!({ "newProductId": "premium", "replacementMode": "withTimeProration" }),
+        ),
+        ("complete_purchase", json!({ "purchase": purchase })),
+        ("finalize_after", json!({ "purchase": purchase })),
+        ("restore_purchases", json!({})),
+        ("current_entitlements", json!({})),
+        ("country_code", json!({})),
+        ("is_feature_supported", json!({ "feature": "subscriptions" })),
+        ("get_app_receipt", json!({})),
+        ("app_license", json!({})),
+        ("open_manage_subscriptions", json!({})),
+        ("open_store_product_page", json!({})),
+    ] {
+        let error = invoke(&webview, command, args).unwrap_err();
+        assert_eq!(error["code"], json!("notInitialized"), "{command}: {error}");
+    }
+    // Nothing reached the store, and the app can still check it's there and
+    // where it was installed from.
+    assert_eq!(store.calls(), Vec::<Value>::new());
+    assert_eq!(invoke(&webview, "is_available", json!({})), Ok(json!(true)));
+    assert!(invoke(&webview, "install_source", json!({})).is_ok());
+    assert_eq!(
+        invoke(&webview, "connection_state", json!({})),
+        Ok(json!("uninitialized"))
+    );
+}
+
+#[test]
+fn concurrent_initializations_connect_once() {
+    let store = Arc::new(RecordingStore::default());
+    let (app, webview) = app(store.clone());
+
+    let barrier = Arc::new(Barrier::new(8));
+    let initializations: Vec<_> = (0..8)
+        .map(|_| {
+            let (handle, barrier) = (app.handle().clone(), barrier.clone());
+            thread::spawn(move || {
+                barrier.wait();
+                block_on(handle.iap().initialize())
+            })
+        })
+        .collect();
+    for initialization in initializations {
+        assert!(initialization.join().unwrap().is_ok());
+    }
+    // Initializing again once ready is a no-op.
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+
+    assert_eq!(store.calls(), vec![json!({ "op": "initialize" })]);
+    assert_eq!(
+        invoke(&webview, "connection_state", json!({})),
+        Ok(json!("ready"))
+    );
+}
+
+#[test]
+fn reinitializes_after_end_connection() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = app(store.clone());
+
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    assert_eq!(invoke(&webview, "end_connection", json!({})), Ok(Value::Null));
+    assert_eq!(
+        invoke(&webview, "connection_state", json!({})),
+        Ok(json!("closed"))
+    );
+    let error = invoke(&webview, "restore_purchases", json!({})).unwrap_err();
+    assert_eq!(error["code"], json!("notInitialized"));
+
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    assert_eq!(
+        invoke(&webview, "connection_state", json!({})),
+        Ok(json!("ready"))
+    );
+    assert_eq!(invoke(&webview, "restore_purchases", json!({})), Ok(Value::Null));
+    assert_eq!(
+        store.calls(),
+        vec![
+            json!({ "op": "initialize" }),
+            json!({ "op": "initialize" }),
+            json!({ "op": "restore_purchases", "applicationUserName": null }),
+        ]
+    );
+}
+
+#[test]
+fn starts_a_single_transaction_listener() {
+    let store = Arc::new(RecordingStore::default());
+    let (app, webview) = app(store.clone());
+    let listening = |webview: &WebviewWindow<MockRuntime>| {
+        invoke(webview, "diagnostics", json!({})).unwrap()["transactionListener"].clone()
+    };
+
+    assert_eq!(listening(&webview), json!(false));
+    let handle = app.handle();
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| block_on(handle.iap().initialize()).unwrap());
+        }
+    });
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    // A second listener would report every update twice.
+    assert_eq!(*store.listeners.lock().unwrap(), 1);
+    assert_eq!(listening(&webview), json!(true));
+
+    assert_eq!(invoke(&webview, "end_connection", json!({})), Ok(Value::Null));
+    assert_eq!(*store.listeners.lock().unwrap(), 0);
+    assert_eq!(listening(&webview), json!(false));
+
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    assert_eq!(*store.listeners.lock().unwrap(), 1);
+}
+
+#[test]
+fn drops_purchase_updates_after_end_connection() {
+    let store = Arc::new(RecordingStore::default());
+    let (app, webview) = app(store.clone());
+    let updates = Arc::new(Mutex::new(0));
+    {
+        let updates = updates.clone();
+        app.listen(PURCHASE_UPDATE_EVENT, move |_| *updates.lock().unwrap() += 1);
+    }
+    let late_update = || {
+        let events = store.updates.lock().unwrap().clone().unwrap();
+        events.emit(&[RecordingStore::purchase("premium", PurchaseStatus::Purchased)]);
+    };
+
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    assert_eq!(invoke(&webview, "restore_purchases", json!({})), Ok(Value::Null));
+    assert_eq!(*updates.lock().unwrap(), 1);
+
+    app.iap().end_connection();
+    late_update();
+    assert_eq!(*updates.lock().unwrap(), 1);
+
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    late_update();
+    assert_eq!(*updates.lock().unwrap(), 2);
+}
+
+#[test]
+fn windows_share_one_connection() {
+    let store = Arc::new(RecordingStore::default());
+    let (app, main) =
+        app_with_config(store.clone(), json!({ "eventTarget": "settings" }));
+    let settings = WebviewWindowBuilder::new(&app, "settings", Default::default())
+        .build()
+        .expect("failed to build the window");
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    for window in [&main, &settings] {
+        let (updates, label) = (updates.clone(), window.label().to_string());
+        window.listen(PURCHASE_UPDATE_EVENT, move |_| {
+            updates.lock().unwrap().push(label.clone())
+        });
+    }
+
+    assert_eq!(invoke(&main, "initialize", json!({})), Ok(Value::Null));
+    assert_eq!(invoke(&settings, "initialize", json!({})), Ok(Value::Null));
+    assert_eq!(invoke(&main, "initialize", json!({})), Ok(Value::Null));
+    assert_eq!(store.calls(), vec![json!({ "op": "initialize" })]);
+    assert_eq!(*store.listeners.lock().unwrap(), 1);
+
+    let buy = json!({ "purchaseParam": { "productDetails": premium_json() } });
+    assert_eq!(
+        invoke(&settings, "buy_non_consumable", buy),
+        Ok(json!(true))
+    );
+    assert_eq!(*updates.lock().unwrap(), ["settings"]);
+}
+
+#[test]
+fn purchases_only_from_allowed_windows() {
+    let store = Arc::new(RecordingStore::default());
+    let (app, main) = initialized_app(store.clone(), json!({ "allowedWindows": ["main"] }));
+    let ads = WebviewWindowBuilder::new(&app, "ads", Default::default())
+        .build()
+        .expect("failed to build the window");
+    let buy = json!({ "purchaseParam": { "productDetails": premium_json() } });
+    let purchase = serde_json::to_value(RecordingStore::purchase(
+        "premium",
+        PurchaseStatus::Purchased,
+    ))
+    .unwrap();
+
+    for (command, args) in [
+        ("buy_non_consumable", buy.clone()),
+        ("buy_consumable", buy.clone()),
+        (
+            "buy_upgrade",
+            json!({ "newProductId": "premium", "replacementMode": "withTimeProration" }),
+        ),
+        ("complete_purchase", json!({ "purchase": purchase })),
+        ("finalize_after", json!({ "purchase": purchase })),
+    ] {
+        let error = invoke(&ads, command, args).unwrap_err();
+        assert_eq!(error["code"], json!("windowNotAllowed"), "{command}: {error}");
+        assert!(error["message"].as_str().unwrap().contains("ads"), "{error}");
+    }
+    assert_eq!(store.calls(), Vec::<Value>::new());
+
+    // Other commands aren't gated, and the main window can buy.
+    let query = json!({ "productIds": ["premium"] });
+    assert!(invoke(&ads, "query_product_details", query).is_ok());
+    assert_eq!(invoke(&main, "buy_non_consumable", buy), Ok(json!(true)));
+}
+
+#[test]
+fn purchases_from_every_window_without_allowed_windows() {
+    let store = Arc::new(RecordingStore::default());
+    let (app, _main) = initialized_app(store.clone(), json!({}));
+    let ads = WebviewWindowBuilder::new(&app, "ads", Default::default())
+        .build()
+        .expect("failed to build the window");
+
+    let buy = json!({ "purchaseParam": { "productDetails": premium_json() } });
+    assert_eq!(invoke(&ads, "buy_non_consumable", buy), Ok(json!(true)));
+
+    // An empty list blocks every window.
+    let store = Arc::new(RecordingStore::default());
+    let (_app, main) = initialized_app(store, json!({ "allowedWindows": [] }));
+    let buy = json!({ "purchaseParam": { "productDetails": premium_json() } });
+    let error = invoke(&main, "buy_non_consumable", buy).unwrap_err();
+    assert_eq!(error["code"], json!("windowNotAllowed"));
+}
+
+#[test]
+fn owns_subscriptions_in_grace_period_but_not_on_hold() {
+    let subscription = |product_id: &str, state| PurchaseDetails {
+        subscription_state: Some(state),
+        ..RecordingStore::purchase(product_id, PurchaseStatus::Restored)
+    };
+    for owned_on_hold in [false, true] {
+        let store = Arc::new(RecordingStore::default());
+        let (_app, webview) =
+            initialized_app(store.clone(), json!({ "ownedOnHold": owned_on_hold }));
+        let is_owned = |product_id: &str| {
+            invoke(&webview, "is_owned", json!({ "productId": product_id })).unwrap()
+        };
+        assert_eq!(invoke(&webview, "restore_purchases", json!({})), Ok(Value::Null));
+        let events = store.updates.lock().unwrap().clone().unwrap();
+
+        events.emit(&[
+            subscription("pro_monthly", SubscriptionState::GracePeriod),
+            subscription("pro_yearly", SubscriptionState::OnHold),
+        ]);
+        assert_eq!(is_owned("pro_monthly"), json!(true));
+        assert_eq!(is_owned("pro_yearly"), json!(owned_on_hold));
+
+        // Fixing the payment method ends the hold, and a failed grace period
+        // starts one.
+        events.emit(&[
+            subscription("pro_yearly", SubscriptionState::Active),
+            subscription("pro_monthly", SubscriptionState::OnHold),
+        ]);
+        assert_eq!(is_owned("pro_yearly"), json!(true));
+        assert_eq!(is_owned("pro_monthly"), json!(owned_on_hold));
+
+        events.emit(&[subscription("pro_yearly", SubscriptionState::Expired)]);
+        assert_eq!(is_owned("pro_yearly"), json!(false));
+    }
+}
+
+#[test]
+fn price_consent_is_never_pending_on_desktop() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = app_with_config(store.clone(), json!({ "deferPriceConsent": true }));
+
+    assert_eq!(invoke(&webview, "show_price_consent_if_needed", json!({})), Ok(json!(false)));
+    let diagnostics = invoke(&webview, "diagnostics", json!({})).unwrap();
+    assert_eq!(diagnostics["config"]["deferPriceConsent"], json!(true));
+    assert!(store.calls().is_empty());
+}
+
+#[test]
+fn store_ui_times_out_after_the_sheet_timeout() {
+    let store = Arc::new(RecordingStore::default());
+    *store.sheet_delay.lock().unwrap() = Duration::from_millis(500);
+    let (_app, webview) =
+        initialized_app(store.clone(), json!({ "timeouts": { "sheetMs": 50 } }));
+
+    let error = invoke(&webview, "open_manage_subscriptions", json!({})).unwrap_err();
+    assert_eq!(error["code"], json!("timedOut"));
+    assert_eq!(error["details"], json!({ "operation": "sheet" }));
+}
+
+#[test]
+fn slow_store_calls_do_not_block_other_commands() {
+    let store = Arc::new(RecordingStore::default());
+    *store.sheet_delay.lock().unwrap() = Duration::from_millis(500);
+    let (_app, webview) = initialized_app(store.clone(), json!({}));
+
+    let sheet = {
+        let webview = webview.clone();
+        thread::spawn(move || invoke(&webview, "open_manage_subscriptions", json!({})))
+    };
+    thread::sleep(Duration::from_millis(50));
+    let started = std::time::Instant::now();
+    let query = json!({ "productIds": ["premium"] });
+    assert!(invoke(&webview, "query_product_details", query).is_ok());
+    assert!(started.elapsed() < Duration::from_millis(300));
+
+    assert_eq!(sheet.join().unwrap(), Ok(Value::Null));
+    let ops: Vec<_> = store.calls().iter().map(|call| call["op"].clone()).collect();
+    assert_eq!(ops, [json!("query"), json!("open_manage_subscriptions")]);
+}
+
+#[test]
+fn completions_time_out_without_blocking_the_next_one() {
+    let store = Arc::new(RecordingStore::default());
+    *store.completion_delay.lock().unwrap() = Duration::from_millis(300);
+    let (_app, webview) =
+        initialized_app(store.clone(), json!({ "timeouts": { "finalizeMs": 50 } }));
+    let purchase = RecordingStore::purchase("premium", PurchaseStatus::Purchased);
+    let args = json!({ "purchase": purchase });
+
+    let error = invoke(&webview, "complete_purchase", args.clone()).unwrap_err();
+    assert_eq!(error["code"], json!("timedOut"));
+    assert_eq!(error["details"], json!({ "operation": "finalize" }));
+
+    // The store call that timed out is left to finish, then forgotten.
+    thread::sleep(Duration::from_millis(400));
+    *store.completion_delay.lock().unwrap() = Duration::ZERO;
+    assert_eq!(invoke(&webview, "complete_purchase", args), Ok(Value::Null));
+    assert_eq!(store.calls().len(), 2);
+}
+
+#[test]
+fn finalizes_purchases_once_the_webview_verified_them() {
+    let store = Arc::new(RecordingStore::default());
+    let (app, webview) = initialized_app(store.clone(), json!({}));
+    let purchase = RecordingStore::purchase("premium", PurchaseStatus::Purchased);
+    let unfinished = app
+        .path()
+        .app_data_dir()
+        .unwrap()
+        .join("iap-unfinished-purchases.json");
+
+    let rejected = json!({
+        "purchase": purchase,
+        "verificationError": { "code": "invalidPurchaseToken", "message": "Rejected (403)" },
+    });
+    let error = invoke(&webview, "finalize_after", rejected).unwrap_err();
+    assert_eq!(error["code"], json!("invalidPurchaseToken"), "{error}");
+    assert!(error["message"].as_str().unwrap().ends_with("Rejected (403)"), "{error}");
+    // Kept to be reported again, and left unfinished at the store.
+    let kept: Value = serde_json::from_slice(&std::fs::read(&unfinished).unwrap()).unwrap();
+    assert_eq!(kept[0]["purchaseId"], json!("order-premium"));
+    assert_eq!(store.calls(), Vec::<Value>::new());
+
+    let verified = json!({ "purchase": purchase });
+    assert_eq!(invoke(&webview, "finalize_after", verified), Ok(Value::Null));
+    assert_eq!(
+        store.calls(),
+        vec![json!({ "op": "complete_purchase", "purchaseId": "order-premium" })]
+    );
+    assert!(!unfinished.exists());
+}
+
+#[test]
+fn rapid_store_ui_calls_do_not_deadlock() {
+    let store = Arc::new(RecordingStore::default());
+    *store.sheet_delay.lock().unwrap() = Duration::from_millis(5);
+    let (app, main) = app(store.clone());
+    let other = WebviewWindowBuilder::new(&app, "other", Default::default())
+        .build()
+        .expect("failed to build the window");
+    assert_eq!(invoke(&main, "initialize", json!({})), Ok(Value::Null));
+
+    let barrier = Arc::new(Barrier::new(8));
+    let callers: Vec<_> = (0..8)
+        .map(|i| {
+            let webview = if i % 2 == 0 { main.clone() } else { other.clone() };
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                for _ in 0..10 {
+                    assert_eq!(
+                        invoke(&webview, "open_manage_subscriptions", json!({})),
+                        Ok(Value::Null)
+                    );
+                    assert_eq!(
+                        invoke(&webview, "show_price_consent_if_needed", json!({})),
+                        Ok(json!(false))
+                    );
+                    let error =
+                        invoke(&webview, "open_store_product_page", json!({})).unwrap_err();
+                    assert_eq!(error["code"], json!("featureNotSupported"));
+                }
+            })
+        })
+        .collect();
+    for caller in callers {
+        caller.join().unwrap();
+    }
+
+    let opened = store
+        .calls()
+        .iter()
+        .filter(|call| call["op"] == "open_manage_subscriptions")
+        .count();
+    assert_eq!(opened, 80);
+}
+
+#[test]
+fn reads_current_entitlements_without_events() {
+    let store = Arc::new(RecordingStore::default());
+    let (app, webview) = initialized_app(store.clone(), json!({}));
+    let updates = Arc::new(Mutex::new(0));
+    {
+        let updates = updates.clone();
+        app.listen(PURCHASE_UPDATE_EVENT, move |_| *updates.lock().unwrap() += 1);
+    }
+
+    let entitlements = invoke(&webview, "current_entitlements", json!({})).unwrap();
+    assert_eq!(entitlements[0]["productId"], json!("premium"));
+    assert_eq!(entitlements[0]["status"], json!("restored"));
+    assert_eq!(
+        entitlements[0]["verificationData"]["serverVerificationData"],
+        json!("token-premium")
+    );
+    assert_eq!(*updates.lock().unwrap(), 0);
+}
+
+#[test]
+fn redacts_verification_data_sent_to_the_webview() {
+    let store = Arc::new(RecordingStore::default());
+    let (app, webview) = initialized_app(
+        store.clone(),
+        json!({ "redactVerificationData": true, "restoreCooldownMs": 0 }),
+    );
+    let events = Arc::new(Mutex::new(Vec::new()));
+    {
+        let events = events.clone();
+        app.listen(PURCHASE_UPDATE_EVENT, move |event| {
+            let payload: Value = serde_json::from_str(event.payload()).unwrap();
+            events.lock().unwrap().push(payload);
+        });
+    }
+    let redacted = |purchase: &Value| {
+        purchase.get("verificationData").is_none()
+            && purchase["verificationAvailable"] == json!(true)
+    };
+
+    let param = json!({ "purchaseParam": { "productDetails": premium_json() } });
+    assert!(invoke(&webview, "buy_non_consumable", param).is_ok());
+    assert!(invoke(&webview, "restore_purchases", json!({})).is_ok());
+    let query = json!({ "productIds": ["premium"] });
+    let products = invoke(&webview, "query_product_details", query).unwrap();
+    let entitlements = invoke(&webview, "current_entitlements", json!({})).unwrap();
+
+    let events = events.lock().unwrap().clone();
+    assert!(events.iter().all(|event| redacted(&event[0])), "{events:?}");
+    assert!(!products.to_string().contains("token-premium"));
+    assert!(redacted(&entitlements[0]), "{entitlements}");
+
+    // Rust gets the full data, and the webview can complete redacted purchases.
+    let purchases = block_on(app.iap().current_entitlements()).unwrap();
+    assert_eq!(
+        purchases[0].verification_data.server_verification_data,
+        "token-premium"
+    );
+    let purchase = json!({ "purchase": events[0][0] });
+    assert_eq!(invoke(&webview, "complete_purchase", purchase), Ok(Value::Null));
+}
+
+#[test]
+fn tags_the_purchases_of_correlated_buys() {
+    let store = Arc::new(RecordingStore::default());
+    let (app, webview) = initialized_app(store, json!({ "restoreCooldownMs": 0 }));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    {
+        let events = events.clone();
+        app.listen(PURCHASE_UPDATE_EVENT, move |event| {
+            let payload: Value = serde_json::from_str(event.payload()).unwrap();
+            events.lock().unwrap().push(payload[0].clone());
+        });
+    }
+    let mut coins = premium_json();
+    coins["id"] = "coins".into();
+    let buy = |command: &str, product: &Value, correlation_id: Option<&str>| {
+        let args = json!({
+            "purchaseParam": { "productDetails": product },
+            "correlationId": correlation_id,
+        });
+        assert_eq!(invoke(&webview, command, args), Ok(json!(true)));
+    };
+
+    assert!(invoke(&webview, "restore_purchases", json!({})).is_ok());
+    buy("buy_consumable", &coins, Some("flow-1"));
+    // The store reports the premium purchase it restored again.
+    buy("buy_non_consumable", &premium_json(), Some("flow-2"));
+    buy("buy_consumable", &coins, None);
+
+    let events = events.lock().unwrap().clone();
+    let tagged: Vec<_> = events
+        .iter()
+        .map(|purchase| (purchase["productId"].clone(), purchase.get("correlationId").cloned()))
+        .collect();
+    assert_eq!(
+        tagged,
+        [
+            (json!("premium"), None),
+            (json!("coins"), Some(json!("flow-1"))),
+            (json!("premium"), None),
+            (json!("coins"), None),
+        ]
+    );
+}
+
+#[test]
+fn queries_products_with_camel_case_payloads() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = initialized_app(store.clone(), json!({}));
+
+    let response = invoke(
+        &webview,
+        "query_product_details",
+        json!({ "productIds": ["premium", "missing"] }),
+    )
+    .unwrap();
+    assert_eq!(response["productDetails"][0], premium_json());
+    assert_eq!(response["notFoundIds"], json!(["missing"]));
+
+    // `noRetry` is optional, and the cached product isn't queried again.
+    let response = invoke(
+        &webview,
+        "query_product_details",
+        json!({ "productIds": ["missing"], "noRetry": true }),
+    )
+    .unwrap();
+    assert_eq!(response["notFoundIds"], json!(["missing"]));
+    assert_eq!(
+        store.calls(),
+        vec![
+            json!({ "op": "query", "productIds": ["premium", "missing"] }),
+            json!({ "op": "query", "productIds": ["missing"] }),
+        ]
+    );
+}
+
+/// The commands the guest-js bindings send, with their arguments and results,
+/// which the bindings' tests in `guest-js/tests/` check against.
+#[test]
+fn accepts_the_guest_js_payloads() {
+    let payloads: Vec<Value> =
+        serde_json::from_str(include_str!("../guest-js/tests/payloads.json")).unwrap();
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = app(store);
+
+    for payload in payloads {
+        let command = payload["command"].as_str().unwrap();
+        assert_eq!(
+            invoke(&webview, command, payload["args"].clone()),
+            Ok(payload["result"].clone()),
+            "{}",
+            payload["binding"]
+        );
+    }
+}
+
+#[test]
+fn defaults_optional_arguments() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = initialized_app(store.clone(), json!({}));
+
+    let param = json!({ "purchaseParam": { "productDetails": premium_json() } });
+    assert_eq!(
+        invoke(&webview, "buy_consumable", param.clone()),
+        Ok(json!(true))
+    );
+    let mut with_auto_consume = param.clone();
+    with_auto_consume["autoConsume"] = json!(true);
+    assert_eq!(
+        invoke(&webview, "buy_consumable", with_auto_consume),
+        Ok(json!(true))
+    );
+
+    assert_eq!(
+        invoke(
+            &webview,
+            "set_application_user_name",
+            json!({ "applicationUserName": "hashed-user" })
+        ),
+        Ok(Value::Null)
+    );
+    assert_eq!(
+        invoke(&webview, "buy_non_consumable", param),
+        Ok(json!(true))
+    );
+    assert_eq!(
+        invoke(&webview, "restore_purchases", json!({})),
+        Ok(Value::Null)
+    );
+    assert_eq!(invoke(&webview, "country_code", json!({})), Ok(json!("US")));
+    assert_eq!(
+        invoke(&webview, "country_code", json!({ "refresh": true })),
+        Ok(json!("US"))
+    );
+
+    assert_eq!(
+        store.calls(),
+        vec![
+            json!({ "op": "buy_consumable", "productId": "premium", "autoConsume": false }),
+            json!({ "op": "buy_consumable", "productId": "premium", "autoConsume": true }),
+            json!({ "op": "buy_non_consumable", "productId": "premium", "applicationUserName": "hashed-user" }),
+            json!({ "op": "restore_purchases", "applicationUserName": "hashed-user" }),
+        ]
+    );
+    assert_eq!(
+        invoke(&webview, "is_owned", json!({ "productId": "premium" })),
+        Ok(json!(true))
+    );
+    assert_eq!(
+        invoke(&webview, "owned_products", json!({})),
+        Ok(json!([{
+            "productId": "premium",
+            "purchaseId": "order-premium",
+            "expirationDate": null,
+            "source": "store"
+        }]))
+    );
+}
+
+#[test]
+fn injects_the_application_user_name_until_cleared() {
+    let store = Arc::new(RecordingStore::default());
+    let builder = tauri_plugin_iap::Builder::new()
+        .desktop_backend(store.clone())
+        .application_user_name("alice");
+    let (_app, webview) = app_with_builder(builder, json!({ "restoreCooldownMs": 0 }));
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    let buy = |name: Value| {
+        let param = json!({
+            "purchaseParam": { "productDetails": premium_json(), "applicationUserName": name }
+        });
+        invoke(&webview, "buy_non_consumable", param)
+    };
+    let restore = |name: Value| {
+        invoke(&webview, "restore_purchases", json!({ "applicationUserName": name }))
+    };
+
+    // Explicit names win over the default one.
+    assert!(buy(Value::Null).is_ok());
+    assert!(buy(json!("bob")).is_ok());
+    assert!(restore(Value::Null).is_ok());
+    assert!(restore(json!("bob")).is_ok());
+    let cleared = json!({ "applicationUserName": null });
+    assert!(invoke(&webview, "set_application_user_name", cleared).is_ok());
+    assert!(buy(Value::Null).is_ok());
+    assert!(restore(Value::Null).is_ok());
+
+    let names: Vec<_> = store.calls()[1..]
+        .iter()
+        .map(|call| call["applicationUserName"].clone())
+        .collect();
+    assert_eq!(
+        names,
+        [json!("alice"), json!("bob"), json!("alice"), json!("bob"), Value::Null, Value::Null]
+    );
+}
+
+#[test]
+fn completes_known_purchases_only() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = initialized_app(store, json!({}));
+
+    let mut purchase = serde_json::to_value(RecordingStore::purchase(
+        "premium",
+        PurchaseStatus::Purchased,
+    ))
+    .unwrap();
+    assert_eq!(
+        invoke(
+            &webview,
+            "complete_purchase",
+            json!({ "purchase": purchase })
+        ),
+        Ok(Value::Null)
+    );
+
+    purchase["purchaseId"] = json!("order-forged");
+    let error = invoke(
+        &webview,
+        "complete_purchase",
+        json!({ "purchase": purchase }),
+    )
+    .unwrap_err();
+    assert_eq!(error["code"], json!("unknownPurchase"));
+}
+
+#[test]
+fn completes_unknown_purchases_when_opted_out() {
+    let store = Arc::new(RecordingStore::default());
+    let mut context = mock_context(noop_assets());
+    context.config_mut().identifier = identifier();
+    let app = mock_builder()
+        .plugin(
+            tauri_plugin_iap::Builder::new()
+                .desktop_backend(store.clone())
+                .reject_unknown_purchases(false)
+                .build(),
+        )
+        .build(context)
+        .expect("failed to build the app");
+    assert!(!app.handle().iap().rejects_unknown_purchases());
+    block_on(app.handle().iap().initialize()).unwrap();
+
+    let forged = PurchaseDetails {
+        purchase_id: Some("order-forged".into()),
+        ..RecordingStore::purchase("premium", PurchaseStatus::Purchased)
+    };
+    block_on(app.handle().iap().complete_purchase(forged)).unwrap();
+    // The store is still asked, in case it knows the purchase after all.
+    assert_eq!(
+        store.calls(),
+        vec![
+            json!({ "op": "initialize" }),
+            json!({ "op": "complete_purchase", "purchaseId": "order-forged" }),
+        ]
+    );
+}
+
+#[test]
+fn builder_settings_override_the_config_file() {
+    let store = Arc::new(RecordingStore::default());
+    let builder = tauri_plugin_iap::Builder::new()
+        .desktop_backend(store.clone())
+        .products(["premium"])
+        .auto_finish(true)
+        .event_target("main")
+        .purchase_timeout(Duration::from_secs(120))
+        .application_user_name("alice");
+    let (app, _webview) = app_with_builder(
+        builder,
+        json!({
+            "products": ["coins"],
+            "autoFinishTransactions": false,
+            "eventTarget": "settings",
+            "timeouts": { "queryMs": 5000, "purchaseMs": 1000 },
+            "restoreCooldownMs": 0
+        }),
+    );
+
+    let iap = app.handle().iap();
+    let config = iap.config();
+    assert_eq!(config.products, ["premium"]);
+    assert!(config.auto_finish_transactions);
+    assert_eq!(config.event_target.as_deref(), Some("main"));
+    assert_eq!(config.timeouts.purchase(), Some(Duration::from_secs(120)));
+    // Settings the builder leaves alone keep the values of the file.
+    assert_eq!(config.timeouts.query(), Some(Duration::from_secs(5)));
+    assert_eq!(config.restore_cooldown(), Duration::ZERO);
+    assert_eq!(iap.application_user_name().as_deref(), Some("alice"));
+
+    // The configured products are the ones queried by default.
+    block_on(iap.initialize()).unwrap();
+    block_on(iap.query_product_details(Vec::new())).unwrap();
+    assert_eq!(
+        store.calls()[1],
+        json!({ "op": "query", "productIds": ["premium"] })
+    );
+}
+
+#[test]
+fn concurrent_restores_share_one_store_call() {
+    let store = Arc::new(RecordingStore::default());
+    *store.restore_delay.lock().unwrap() = Duration::from_millis(200);
+    let (app, webview) = initialized_app(store.clone(), json!({ "restoreCooldownMs": 1000 }));
+    let updates = Arc::new(Mutex::new(0));
+    {
+        let updates = updates.clone();
+        app.listen(PURCHASE_UPDATE_EVENT, move |_| *updates.lock().unwrap() += 1);
+    }
+
+    // Three taps on "Restore purchases".
+    let barrier = Arc::new(Barrier::new(3));
+    let restores: Vec<_> = (0..3)
+        .map(|_| {
+            let (webview, barrier) = (webview.clone(), barrier.clone());
+            thread::spawn(move || {
+                barrier.wait();
+                invoke(&webview, "restore_purchases", json!({}))
+            })
+        })
+        .collect();
+    for restore in restores {
+        assert_eq!(restore.join().unwrap(), Ok(Value::Null));
+    }
+    let restores = || {
+        let calls = store.calls();
+        calls.iter().filter(|call| call["op"] == "restore_purchases").count()
+    };
+    assert_eq!(restores(), 1);
+    assert_eq!(*updates.lock().unwrap(), 1);
+
+    // Tapping again right after gets the result of the one that completed,
+    // unless restoring the purchases of another user.
+    assert_eq!(invoke(&webview, "restore_purchases", json!({})), Ok(Value::Null));
+    assert_eq!(restores(), 1);
+    let alice = json!({ "applicationUserName": "alice" });
+    assert_eq!(invoke(&webview, "restore_purchases", alice), Ok(Value::Null));
+    assert_eq!(restores(), 2);
+}
+
+#[test]
+fn concurrent_completions_share_one_store_call() {
+    let store = Arc::new(RecordingStore::default());
+    *store.completion_delay.lock().unwrap() = Duration::from_millis(200);
+    let (app, _webview) = initialized_app(store.clone(), json!({}));
+
+    // The purchase event handler and a restore handler completing the same
+    // purchase at once.
+    let barrier = Arc::new(Barrier::new(2));
+    let completions: Vec<_> = (0..2)
+        .map(|_| {
+            let (handle, barrier) = (app.handle().clone(), barrier.clone());
+            thread::spawn(move || {
+                barrier.wait();
+                block_on(
+                    handle
+                        .iap()
+                        .complete_purchase(RecordingStore::purchase("premium", PurchaseStatus::Purchased)),
+                )
+            })
+        })
+        .collect();
+    for completion in completions {
+        assert!(completion.join().unwrap().is_ok());
+    }
+    assert_eq!(
+        store.calls(),
+        vec![json!({ "op": "complete_purchase", "purchaseId": "order-premium" })]
+    );
+
+    // Nothing is kept once the call finished, failures included.
+    *store.completion_delay.lock().unwrap() = Duration::ZERO;
+    let forged = PurchaseDetails {
+        purchase_id: Some("order-forged".into()),
+        ..RecordingStore::purchase("premium", PurchaseStatus::Purchased)
+    };
+    let handle = app.handle();
+    for _ in 0..2 {
+        let error = block_on(handle.iap().complete_purchase(forged.clone())).unwrap_err();
+        assert_eq!(error.code(), IapErrorCode::UnknownPurchase);
+    }
+    assert!(block_on(
+        handle
+            .iap()
+            .complete_purchase(RecordingStore::purchase("premium", PurchaseStatus::Purchased))
+    )
+    .is_ok());
+    assert_eq!(store.calls().len(), 4);
+}
+
+#[test]
+fn app_license_is_not_supported_without_a_licensing_store() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = initialized_app(store.clone(), json!({}));
+
+    let error = invoke(&webview, "app_license", json!({})).unwrap_err();
+    assert_eq!(error["code"], json!("featureNotSupported"));
+    assert!(store.calls().is_empty());
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn mock_controls_are_not_supported_by_other_backends() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = app(store.clone());
+
+    let error = invoke(&webview, "mock_clear_purchases", json!({})).unwrap_err();
+    assert_eq!(error["code"], json!("featureNotSupported"));
+    assert!(store.calls().is_empty());
+}
+
+/// An app served by the headless mock store selling `premium`, without
+/// retries, with `config` as its `plugins.iap` configuration.
+#[cfg(feature = "mock")]
+fn mock_store_app(config: Value) -> (App<MockRuntime>, WebviewWindow<MockRuntime>) {
+    let builder = tauri_plugin_iap::Builder::new()
+        .mock_catalog(vec![premium()])
+        .mock_headless(true)
+        .retry_policy(tauri_plugin_iap::RetryPolicy::none());
+    app_with_builder(builder, config)
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn injected_mock_failures_fail_commands_like_a_store() {
+    let (_app, webview) = mock_store_app(json!({}));
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    let buy = || {
+        let param = json!({ "purchaseParam": { "productDetails": premium_json() } });
+        invoke(&webview, "buy_non_consumable", param)
+    };
+    let query = || {
+        invoke(&webview, "query_product_details", json!({ "productIds": ["premium"] }))
+    };
+    let set_failure =
+        |failure: Value| invoke(&webview, "mock_set_failure", json!({ "failure": failure }));
+
+    // Only the next purchase of the product fails, as the store reports it.
+    let failure = json!({
+        "operation": "buy_non_consumable",
+        "code": "itemAlreadyOwned",
+        "productId": "premium",
+    });
+    assert_eq!(set_failure(failure), Ok(Value::Null));
+    assert_eq!(buy(), Err(serde_json::to_value(Error::ItemAlreadyOwned).unwrap()));
+    assert_eq!(buy(), Ok(json!(true)));
+
+    // Persistent failures last until cleared.
+    let failure = json!({
+        "operation": "query_product_details",
+        "code": "networkError",
+        "persistent": true,
+    });
+    assert_eq!(set_failure(failure), Ok(Value::Null));
+    let error = Error::NetworkError("Injected networkError failure".into());
+    for _ in 0..2 {
+        assert_eq!(query(), Err(serde_json::to_value(&error).unwrap()));
+    }
+    assert_eq!(set_failure(Value::Null), Ok(Value::Null));
+    assert!(query().is_ok());
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn simulated_mock_latency_delays_commands_within_their_timeout() {
+    let (_app, webview) = mock_store_app(json!({ "timeouts": { "queryMs": 300 } }));
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    let set_latency = |ms: u64| {
+        let latency = json!({ "minMs": ms, "maxMs": ms });
+        invoke(&webview, "mock_set_latency", json!({ "latency": latency }))
+    };
+    let query = |product_id: &str| {
+        invoke(&webview, "query_product_details", json!({ "productIds": [product_id] }))
+    };
+
+    assert_eq!(set_latency(200), Ok(Value::Null));
+    let started = std::time::Instant::now();
+    let param = json!({ "purchaseParam": { "productDetails": premium_json() } });
+    assert_eq!(invoke(&webview, "buy_non_consumable", param), Ok(json!(true)));
+    assert!(started.elapsed() >= Duration::from_millis(200));
+
+    // The delays don't block the runtime, so they overlap.
+    let started = std::time::Instant::now();
+    thread::scope(|scope| {
+        let queries = ["premium", "coins"].map(|id| scope.spawn(move || query(id)));
+        for query in queries {
+            assert!(query.join().unwrap().is_ok());
+        }
+    });
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(200) && elapsed < Duration::from_millis(400));
+
+    // The latency counts towards the timeout, and the next call runs normally.
+    assert_eq!(set_latency(1_000), Ok(Value::Null));
+    let started = std::time::Instant::now();
+    assert_eq!(query("premium").unwrap_err()["code"], json!("timedOut"));
+    assert!(started.elapsed() < Duration::from_millis(1_000));
+    let cleared = invoke(&webview, "mock_set_latency", json!({ "latency": null }));
+    assert_eq!(cleared, Ok(Value::Null));
+    assert!(query("premium").is_ok());
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn timed_out_purchases_do_not_block_the_next_one() {
+    let (app, webview) = mock_store_app(json!({ "timeouts": { "purchaseMs": 100 } }));
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    let buy = || {
+        let param = json!({ "purchaseParam": { "productDetails": premium_json() } });
+        invoke(&webview, "buy_non_consumable", param)
+    };
+    let latency = json!({ "minMs": 500, "maxMs": 500 });
+    assert!(invoke(&webview, "mock_set_latency", json!({ "latency": latency })).is_ok());
+
+    let error = buy().unwrap_err();
+    assert_eq!(error["code"], json!("timedOut"));
+    assert_eq!(error["details"], json!({ "operation": "purchase" }));
+    assert!(app.iap().mock_store().unwrap().purchases().is_empty());
+
+    assert!(invoke(&webview, "mock_set_latency", json!({ "latency": null })).is_ok());
+    assert_eq!(buy(), Ok(json!(true)));
+    assert!(app.iap().is_owned("premium"));
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn injected_mock_failures_reach_the_error_event() {
+    let (app, webview) = mock_store_app(json!({
+        "products": ["premium"],
+        "preloadProducts": true,
+    }));
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    {
+        let errors = errors.clone();
+        app.listen(tauri_plugin_iap::ERROR_EVENT, move |event| {
+            let error: Value = serde_json::from_str(event.payload()).unwrap();
+            errors.lock().unwrap().push(error);
+        });
+    }
+    let mut stream = app.iap().purchase_stream();
+    let failure = json!({ "operation": "query_product_details", "code": "networkError" });
+    let set = invoke(&webview, "mock_set_failure", json!({ "failure": failure }));
+    assert_eq!(set, Ok(Value::Null));
+
+    // The failure is injected into the products preloaded in the background.
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    for _ in 0..500 {
+        if !errors.lock().unwrap().is_empty() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let errors = errors.lock().unwrap().clone();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0]["code"], json!("networkError"));
+    match stream.try_recv() {
+        Ok(tauri_plugin_iap::PurchaseUpdate::Error(error)) => {
+            assert_eq!(error.code, "networkError")
+        }
+        other => panic!("expected an error, got {other:?}"),
+    }
+}
+
+#[test]
+fn imports_entitlements_in_debug_builds_only() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = app(store);
+    let export = invoke(&webview, "export_entitlements", json!({})).unwrap();
+    assert!(export.is_string());
+
+    let imported = invoke(&webview, "import_entitlements", json!({ "json": export }));
+    if cfg!(debug_assertions) {
+        assert_eq!(imported, Ok(Value::Null));
+    } else {
+        assert_eq!(imported.unwrap_err()["code"], json!("featureNotSupported"));
+    }
+}
+
+#[test]
+fn reports_failed_commands_to_the_error_hook() {
+    let store = Arc::new(RecordingStore::default());
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let builder = {
+        let reported = reported.clone();
+        tauri_plugin_iap::Builder::new()
+            .desktop_backend(store)
+            .on_command_error(move |command, error, elapsed| {
+                assert!(elapsed < Duration::from_secs(5));
+                reported.lock().unwrap().push((command.to_string(), error.code()));
+            })
+    };
+    let (_app, webview) = app_with_builder(builder, json!({}));
+
+    // Rejected by the initialization guard.
+    let error = invoke(&webview, "query_product_details", json!({ "productIds": ["premium"] }))
+        .unwrap_err();
+    assert_eq!(error["code"], json!("notInitialized"));
+
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    let query = json!({ "productIds": ["premium"] });
+    assert!(invoke(&webview, "query_product_details", query).is_ok());
+    // Failed by the store, with the result left as it was.
+    let error = invoke(
+        &webview,
+        "subscription_status",
+        json!({ "productId": "premium" }),
+    )
+    .unwrap_err();
+    assert_eq!(error["code"], json!("featureNotSupported"));
+
+    assert_eq!(
+        *reported.lock().unwrap(),
+        [
+            ("query_product_details".to_string(), IapErrorCode::NotInitialized),
+            ("subscription_status".to_string(), IapErrorCode::FeatureNotSupported),
+        ]
+    );
+}
+
+#[test]
+fn alternative_billing_is_not_supported_on_desktop() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = initialized_app(
+        store.clone(),
+        json!({ "alternativeBillingOnly": true }),
+    );
+
+    for command in [
+        "is_alternative_billing_available",
+        "show_alternative_billing_dialog",
+        "create_alternative_billing_token",
+    ] {
+        let error = invoke(&webview, command, json!({})).unwrap_err();
+        assert_eq!(
+            error,
+            json!({
+                "code": "featureNotSupported",
+                "message": "Feature not supported: \
+                    Alternative billing only is a Google Play program"
+            }),
+            "{command}"
+        );
+    }
+    assert!(store.calls().is_empty());
+}
+
+#[test]
+fn external_purchase_links_are_not_supported_on_desktop() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = initialized_app(store.clone(), json!({}));
+
+    for (command, args) in [
+        ("external_purchase_available", json!({})),
+        (
+            "open_external_purchase_link",
+            json!({ "url": "https://example.com/buy" }),
+        ),
+        ("external_purchase_token", json!({})),
+    ] {
+        let error = invoke(&webview, command, args).unwrap_err();
+        assert_eq!(
+            error,
+            json!({
+                "code": "featureNotSupported",
+                "message": "Feature not supported: \
+                    External purchase links are an App Store program"
+            }),
+            "{command}"
+        );
+    }
+    assert!(store.calls().is_empty());
+}
+
+#[test]
+fn app_receipts_are_not_issued_on_desktop() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = initialized_app(store.clone(), json!({}));
+
+    for args in [json!({}), json!({ "refresh": false }), json!({ "refresh": true })] {
+        let error = invoke(&webview, "get_app_receipt", args.clone()).unwrap_err();
+        assert_eq!(
+            error,
+            json!({
+                "code": "featureNotSupported",
+                "message": "Feature not supported: \
+                    App receipts are only issued by the App Store"
+            }),
+            "{args}"
+        );
+    }
+    assert!(store.calls().is_empty());
+}
+
+#[test]
+fn panicking_error_hooks_do_not_change_the_error() {
+    let store = Arc::new(RecordingStore::default());
+    let builder = tauri_plugin_iap::Builder::new()
+        .desktop_backend(store)
+        .on_command_error(|_, _, _| panic!("reporting failed"));
+    let (_app, webview) = app_with_builder(builder, json!({}));
+
+    let error = invoke(&webview, "restore_purchases", json!({})).unwrap_err();
+    assert_eq!(error["code"], json!("notInitialized"));
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+}
+
+#[test]
+fn serializes_errors() {
+    let store = Arc::new(RecordingStore::default());
+    let (app, webview) = initialized_app(store, json!({}));
+
+    let error = invoke(
+        &webview,
+        "subscription_status",
+        json!({ "productId": "premium" }),
+    )
+    .unwrap_err();
+    assert_eq!(error["code"], json!("featureNotSupported"));
+    assert!(error["message"].as_str().unwrap().contains("Subscriptions"));
+
+    // Unknown enum values are typed errors, not deserialization failures.
+    assert_eq!(
+        invoke(
+            &webview,
+            "is_feature_supported",
+            json!({ "feature": "subscriptionsUpdate" }),
+        ),
+        Ok(json!(false))
+    );
+    let error = invoke(
+        &webview,
+        "is_feature_supported",
+        json!({ "feature": "holograms" }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        error,
+        json!({
+            "code": "featureNotSupported",
+            "message": "Feature not supported: Unknown store feature: holograms"
+        })
+    );
+
+    let error = invoke(&webview, "open_store_product_page", json!({})).unwrap_err();
+    assert_eq!(error["code"], json!("featureNotSupported"));
+
+    app.iap().end_connection();
+    let error = invoke(
+        &webview,
+        "query_product_details",
+        json!({ "productIds": ["premium"] }),
+    )
+    .unwrap_err();
+    assert_eq!(
+        error,
+        json!({
+            "code": "notInitialized",
+            "message": "In-app purchases have not been initialized, call initialize() first"
+        })
+    );
+}
+
+#[test]
+fn reports_diagnostics() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = app(store);
+
+    // Bug reports are parsed by support tooling, so the shape is fixed.
+    assert_eq!(
+        invoke(&webview, "diagnostics", json!({})),
+        Ok(json!({
+            "pluginVersion": env!("CARGO_PKG_VERSION"),
+            "platform": std::env::consts::OS,
+            "store": "Recording store",
+            "billingLibraryVersion": null,
+            "storeKit2": null,
+            "connectionState": "uninitialized",
+            "transactionListener": false,
+            "connectionRetry": {
+                "maxAttempts": 3,
+                "baseDelayMs": 1000,
+                "maxDelayMs": 30000,
+                "jitter": true
+            },
+            "config": {
+                "products": 0,
+                "autoFinishTransactions": false,
+                "eventTarget": null,
+                "restrictedWindows": false,
+                "timeouts": {
+                    "queryMs": 30000,
+                    "purchaseMs": null,
+                    "finalizeMs": 30000,
+                    "restoreMs": 60000,
+                    "sheetMs": null
+                },
+                "pendingPurchases": { "enabled": true, "prepaidPlans": false },
+                "restoreCooldownMs": 3000,
+                "autoRestoreOnInit": false,
+                "preloadProducts": false,
+                "skipPreloadOnStorefrontChange": false,
+                "persistProducts": false,
+                "googlePlayPublicKey": false,
+                "rejectUnverifiedPurchases": false,
+                "redactVerificationData": false,
+                "alternativeBillingOnly": false,
+                "appStoreId": null,
+                "requireStoreInstall": false,
+                "transactionListenerOnLoad": false,
+                "ownedOnHold": false,
+                "deferPriceConsent": false,
+                "skipProductIdValidation": false,
+                "androidAppLicense": false,
+                "mockProducts": 0
+            }
+        }))
+    );
+}
+
+#[test]
+fn rejects_missing_and_mistyped_arguments() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = app(store.clone());
+
+    // Deserialization errors are reported by tauri as strings naming the argument.
+    let error = invoke(&webview, "query_product_details", json!({})).unwrap_err();
+    assert!(error.as_str().unwrap().contains("productIds"), "{error}");
+    let error = invoke(
+        &webview,
+        "query_product_details",
+        json!({ "product_ids": ["premium"] }),
+    )
+    .unwrap_err();
+    assert!(error.as_str().unwrap().contains("productIds"), "{error}");
+    let error = invoke(
+        &webview,
+        "buy_consumable",
+        json!({ "purchaseParam": { "productDetails": { "id": "premium" } } }),
+    )
+    .unwrap_err();
+    assert!(error.as_str().unwrap().contains("purchaseParam"), "{error}");
+    assert!(store.calls().is_empty());
+}
+
+#[test]
+fn rejects_unknown_config_keys_at_startup() {
+    let mut context = mock_context(noop_assets());
+    context.config_mut().identifier = identifier();
+    context
+        .config_mut()
+        .plugins
+        .0
+        .insert("iap".into(), json!({ "autoFinishTransaction": true }));
+    let Err(error) = mock_builder()
+        .plugin(tauri_plugin_iap::init())
+        .build(context)
+    else {
+        panic!("the misspelled key was accepted");
+    };
+    let error = error.to_string();
+
+    assert!(error.contains("iap"), "{error}");
+    assert!(error.contains("unknown field `autoFinishTransaction`"), "{error}");
+}
+
+#[test]
+fn read_only_windows_cannot_start_purchases() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = app_with_capability(store.clone(), &["iap:read-only"]);
+
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    let query = json!({ "productIds": ["premium"] });
+    assert!(invoke(&webview, "query_product_details", query).is_ok());
+    assert_eq!(invoke(&webview, "is_available", json!({})), Ok(json!(true)));
+
+    let param = json!({ "purchaseParam": { "productDetails": premium_json() } });
+    let purchase = RecordingStore::purchase("premium", PurchaseStatus::Purchased);
+    for (command, args) in [
+        ("buy_non_consumable", param.clone()),
+        ("buy_consumable", param),
+        ("complete_purchase", json!({ "purchase": purchase })),
+        ("finalize_after", json!({ "purchase": purchase })),
+        ("restore_purchases", json!({})),
+    ] {
+        let error = invoke(&webview, command, args).unwrap_err();
+        assert!(
+            error.as_str().unwrap().contains("not allowed"),
+            "{command}: {error}"
+        );
+    }
+    // Denied before reaching the plugin.
+    let ops: Vec<_> = store.calls().iter().map(|call| call["op"].clone()).collect();
+    assert_eq!(ops, [json!("initialize"), json!("query")]);
+}
+
+#[test]
+fn subscription_management_needs_its_own_permission() {
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = app_with_capability(store.clone(), &["iap:default"]);
+
+    let error = invoke(&webview, "open_manage_subscriptions", json!({})).unwrap_err();
+    assert!(error.as_str().unwrap().contains("not allowed"), "{error}");
+    assert!(store.calls().is_empty());
+
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = app_with_capability(
+        store.clone(),
+        &["iap:default", "iap:subscription-management"],
+    );
+    assert_eq!(invoke(&webview, "initialize", json!({})), Ok(Value::Null));
+    assert_eq!(
+        invoke(&webview, "open_manage_subscriptions", json!({})),
+        Ok(Value::Null)
+    );
+    assert_eq!(
+        store.calls(),
+        [json!({ "op": "initialize" }), json!({ "op": "open_manage_subscriptions" })]
+    );
+}
+
+#[test]
+fn every_command_is_registered_and_permitted() {
+    let commands = commands();
+    for command in &commands {
+        let file = format!(
+            "{}/permissions/autogenerated/commands/{command}.toml",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        assert!(
+            std::path::Path::new(&file).exists(),
+            "{command} has no permission"
+        );
+    }
+
+    let default = allowed(include_str!("../permissions/default.toml"));
+    for command in commands
+        .iter()
+        .filter(|command| !command.starts_with("mock_"))
+    {
+        assert!(
+            default.contains(command),
+            "{command} is missing from the default permissions"
+        );
+    }
+    let sets = allowed(include_str!("../permissions/sets.toml"));
+    let subscriptions = allowed(include_str!("../permissions/subscription-management.toml"));
+    for command in default.iter().chain(&sets).chain(&subscriptions) {
+        assert!(
+            commands.contains(command) || command == "open_manage_subscriptions",
+            "allow-{command} doesn't name a command"
+        );
+    }
+
+    let store = Arc::new(RecordingStore::default());
+    let (_app, webview) = app(store);
+    for command in default.iter().chain(&subscriptions) {
+        if let Err(error) = invoke(&webview, command, json!({})) {
+            assert!(
+                !error.to_string().contains("not found"),
+                "{command} isn't handled by the plugin: {error}"
+            );
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Fixtures shared by the integration tests, each of which uses a part.
+
+#![allow(dead_code)]
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "mock")]
+use serde_json::Value;
+use tauri::test::MockRuntime;
+#[cfg(feature = "mock")]
+use tauri::test::{mock_app, mock_builder, mock_context, noop_assets};
+#[cfg(feature = "mock")]
+use tauri::{async_runtime::block_on, Manager};
+use tauri::{App, Listener};
+#[cfg(feature = "mock")]
+use tauri_plugin_iap::Iap;
+use tauri_plugin_iap::{ProductDetails, PurchaseDetails, PurchaseParam, PURCHASE_UPDATE_EVENT};
+
+pub fn product(id: &str) -> ProductDetails {
+    ProductDetails {
+        id: id.into(),
+        title: id.into(),
+        description: String::new(),
+        price: "$1.99".into(),
+        raw_price: 1.99,
+        currency_code: "USD".into(),
+        currency_symbol: "$".into(),
+        subscription_period: None,
+        free_trial_period: None,
+        subscription_group_id: None,
+    }
+}
+
+pub fn param(id: &str) -> PurchaseParam {
+    PurchaseParam {
+        product_details: product(id),
+        application_user_name: None,
+    }
+}
+
+/// An initialized plugin of a bare app, served by an in-memory mock store
+/// selling `product_ids`.
+#[cfg(feature = "mock")]
+pub fn mock_iap(product_ids: &[&str]) -> (App<MockRuntime>, Iap<MockRuntime>) {
+    let app = mock_app();
+    let catalog = product_ids.iter().map(|id| product(id)).collect();
+    let iap = Iap::mock(app.handle(), catalog);
+    block_on(iap.initialize()).unwrap();
+    (app, iap)
+}
+
+/// Launches an app selling `premium` and `coins` through the headless mock
+/// store, with `config` as its `plugins.iap` configuration.
+///
+/// Launches with the same `name` share the app data directory, and with it
+/// the mock purchases and the unfinished purchases, like relaunches of an app
+/// do. See [`fresh`] to start over.
+#[cfg(feature = "mock")]
+pub fn launch(name: &str, config: Value) -> App<MockRuntime> {
+    try_launch(name, config).expect("failed to build the app")
+}
+
+/// Like [`launch`], returning the error of an app whose plugin failed to
+/// set up.
+#[cfg(feature = "mock")]
+pub fn try_launch(name: &str, config: Value) -> tauri::Result<App<MockRuntime>> {
+    let mut context = mock_context(noop_assets());
+    context.config_mut().identifier = format!("dev.tauri-plugin-iap.tests.{name}");
+    context.config_mut().plugins.0.insert("iap".into(), config);
+    mock_builder()
+        .plugin(
+            tauri_plugin_iap::Builder::new()
+                .mock_catalog(vec![product("premium"), product("coins")])
+                .mock_headless(true)
+                .build(),
+        )
+        .build(context)
+}
+
+/// Empties the app data directory of the launches named `name`, left by the
+/// previous runs.
+#[cfg(feature = "mock")]
+pub fn fresh(name: &str) {
+    let app = launch(name, Value::Null);
+    if let Ok(dir) = app.path().app_data_dir() {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
+
+/// The purchase update events the app emits from now on.
+pub fn purchase_updates(app: &App<MockRuntime>) -> Arc<Mutex<Vec<Vec<PurchaseDetails>>>> {
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let recorded = updates.clone();
+    app.listen(PURCHASE_UPDATE_EVENT, move |event| {
+        let purchases = serde_json::from_str(event.payload()).unwrap();
+        recorded.lock().unwrap().push(purchases);
+    });
+    updates
+}
+
+/// Waits up to 5 seconds for `count` updates, reported from a background
+/// thread, and returns them.
+pub fn wait_for<T: Clone>(updates: &Mutex<Vec<T>>, count: usize) -> Vec<T> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let received = updates.lock().unwrap().clone();
+        if received.len() >= count || Instant::now() > deadline {
+            return received;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
@@ -0,0 +1,120 @@
+//! The country code derived from the system locale on desktop, with an
+//! injected locale so the results don't depend on the host.
+
+use tauri::async_runtime::block_on;
+use tauri::test::{mock_builder, mock_context, noop_assets, MockRuntime};
+use tauri::App;
+use tauri_plugin_iap::{Error, IapExt, Result, UNKNOWN_COUNTRY_CODE};
+
+/// An app whose system locale is `locale`, served by the mock store if
+/// enabled and else by no store.
+fn app(locale: Option<&'static str>) -> App<MockRuntime> {
+    let builder =
+        tauri_plugin_iap::Builder::new().locale_provider(move || locale.map(String::from));
+    #[cfg(feature = "mock")]
+    let builder = builder.mock_headless(true);
+    mock_builder()
+        .plugin(builder.build())
+        .build(mock_context(noop_assets()))
+        .expect("failed to build the app")
+}
+
+/// The country code of an app whose system locale is `locale`.
+fn country_code(locale: Option<&'static str>) -> Result<String> {
+    let app = app(locale);
+    let iap = app.iap();
+    // Only the mock store has to be connected first.
+    #[cfg(feature = "mock")]
+    block_on(iap.initialize()).unwrap();
+    block_on(iap.country_code())
+}
+
+#[test]
+fn reads_the_region_of_the_locale() {
+    for (locale, region) in [
+        ("en-US", "US"),
+        ("de_DE.UTF-8", "DE"),
+        ("sr-Latn-RS", "RS"),
+        ("pt_br", "BR"),
+        ("ca_ES@valencia", "ES"),
+        ("fr", UNKNOWN_COUNTRY_CODE),
+        ("C.UTF-8", UNKNOWN_COUNTRY_CODE),
+    ] {
+        assert_eq!(country_code(Some(locale)).unwrap(), region, "{locale}");
+    }
+}
+
+#[test]
+fn fails_without_a_locale() {
+    for locale in [None, Some("")] {
+        assert!(matches!(country_code(locale), Err(Error::InternalError(_))));
+    }
+}
+
+#[cfg(not(feature = "mock"))]
+#[test]
+fn needs_no_connection_without_a_store() {
+    let app = app(Some("de-DE"));
+    let iap = app.iap();
+
+    assert!(block_on(iap.initialize()).is_err());
+    assert_eq!(block_on(iap.country_code()).unwrap(), "DE");
+    assert_eq!(block_on(iap.refresh_country_code()).unwrap(), "DE");
+    // Store calls still need the connection.
+    assert!(
+        block_on(iap.is_feature_supported(tauri_plugin_iap::StoreFeature::Subscriptions)).is_err()
+    );
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn the_mock_store_overrides_the_locale() {
+    let app = app(Some("de-DE"));
+    let iap = app.iap();
+    block_on(iap.initialize()).unwrap();
+    let mock = iap.mock_store().unwrap();
+
+    assert_eq!(block_on(iap.country_code()).unwrap(), "DE");
+    mock.set_country_code(Some("JP".into()));
+    assert_eq!(block_on(iap.country_code()).unwrap(), "JP");
+    mock.set_country_code(None);
+    assert_eq!(block_on(iap.country_code()).unwrap(), "DE");
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn caches_the_country_code_until_invalidated() {
+    use tauri_plugin_iap::MockOperation;
+
+    let app = app(Some("de-DE"));
+    let iap = app.iap();
+    block_on(iap.initialize()).unwrap();
+    let mock = iap.mock_store().unwrap();
+    let fetched = || {
+        let calls = mock.calls();
+        let fetches = calls
+            .iter()
+            .filter(|call| call.operation == MockOperation::CountryCode)
+            .count();
+        mock.clear_calls();
+        fetches
+    };
+
+    assert_eq!(block_on(iap.country_code()).unwrap(), "DE");
+    assert_eq!(block_on(iap.country_code()).unwrap(), "DE");
+    assert_eq!(fetched(), 1);
+
+    assert_eq!(block_on(iap.refresh_country_code()).unwrap(), "DE");
+    assert_eq!(block_on(iap.country_code()).unwrap(), "DE");
+    assert_eq!(fetched(), 1);
+
+    // A storefront change, and connecting again, drop the cached value.
+    mock.set_country_code(Some("JP".into()));
+    assert_eq!(block_on(iap.country_code()).unwrap(), "JP");
+    assert_eq!(fetched(), 1);
+    iap.end_connection();
+    block_on(iap.initialize()).unwrap();
+    assert_eq!(block_on(iap.country_code()).unwrap(), "JP");
+    assert_eq!(block_on(iap.country_code()).unwrap(), "JP");
+    assert_eq!(fetched(), 1);
+}
@@ -0,0 +1,108 @@
+//! Owned products kept by the entitlement cache and used while the store is
+//! unreachable, against the mock store.
+
+#![cfg(all(feature = "mock", feature = "entitlement-cache"))]
+
+mod common;
+
+use serde_json::{json, Value};
+use tauri::async_runtime::block_on;
+use tauri::test::{mock_builder, mock_context, noop_assets, MockRuntime};
+use tauri::{App, Manager};
+use tauri_plugin_iap::{EntitlementSource, IapExt};
+
+use common::{fresh, param, product, purchase_updates, wait_for};
+
+const ENTITLEMENT_CACHE_FILE: &str = "iap-entitlements.json";
+
+/// Launches an app like [`common::launch`], caching its entitlements with
+/// `secret`.
+fn launch(name: &str, secret: Option<&str>) -> App<MockRuntime> {
+    let mut context = mock_context(noop_assets());
+    context.config_mut().identifier = format!("dev.tauri-plugin-iap.tests.{name}");
+    let mut builder = tauri_plugin_iap::Builder::new()
+        .mock_catalog(vec![product("premium"), product("coins")])
+        .mock_headless(true);
+    if let Some(secret) = secret {
+        builder = builder.entitlement_cache_secret(secret);
+    }
+    mock_builder()
+        .plugin(builder.build())
+        .build(context)
+        .expect("failed to build the app")
+}
+
+fn cache_file(app: &App<MockRuntime>) -> std::path::PathBuf {
+    app.path()
+        .app_data_dir()
+        .unwrap()
+        .join(ENTITLEMENT_CACHE_FILE)
+}
+
+/// Buys and completes `premium` in a first run of the app named `name`.
+fn buy_premium(name: &str, secret: Option<&str>) {
+    let app = launch(name, secret);
+    let iap = app.handle().iap();
+    let updates = purchase_updates(&app);
+    block_on(iap.initialize()).unwrap();
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    let bought = wait_for(&updates, 1)[0][0].clone();
+    block_on(iap.complete_purchase(bought)).unwrap();
+    assert!(iap.is_owned("premium"));
+}
+
+#[test]
+fn serves_cached_products_while_offline() {
+    fresh("entitlement-cache-offline");
+    buy_premium("entitlement-cache-offline", Some("secret"));
+
+    // Relaunched without a connection to the store.
+    let app = launch("entitlement-cache-offline", Some("secret"));
+    let iap = app.handle().iap();
+    assert!(iap.is_owned("premium"));
+    assert!(!iap.is_owned("coins"));
+    let owned = iap.owned_products();
+    assert_eq!(owned.len(), 1);
+    assert_eq!(owned[0].product_id, "premium");
+    assert_eq!(owned[0].source, EntitlementSource::Cache);
+
+    // Once connected, only what the store reports counts.
+    block_on(iap.initialize()).unwrap();
+    assert!(iap
+        .owned_products()
+        .iter()
+        .all(|product| product.source == EntitlementSource::Store));
+}
+
+#[test]
+fn ignores_a_tampered_cache() {
+    fresh("entitlement-cache-tampered");
+    buy_premium("entitlement-cache-tampered", Some("secret"));
+    let app = launch("entitlement-cache-tampered", Some("secret"));
+    let path = cache_file(&app);
+    drop(app);
+
+    // Another product granted by editing the file.
+    let mut file: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    file["entitlements"]
+        .as_array_mut()
+        .unwrap()
+        .push(json!({ "productId": "coins", "purchaseId": null, "expirationDate": null }));
+    std::fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+
+    let app = launch("entitlement-cache-tampered", Some("secret"));
+    let iap = app.handle().iap();
+    assert!(!iap.is_owned("coins"));
+    assert!(!iap.is_owned("premium"));
+    assert!(iap.owned_products().is_empty());
+}
+
+#[test]
+fn caches_nothing_without_a_secret() {
+    fresh("entitlement-cache-disabled");
+    buy_premium("entitlement-cache-disabled", None);
+
+    let app = launch("entitlement-cache-disabled", Some("secret"));
+    assert!(!cache_file(&app).exists());
+    assert!(!app.handle().iap().is_owned("premium"));
+}
@@ -0,0 +1,148 @@
+//! Exporting the owned products for support tickets and importing crafted
+//! ones, against the mock store.
+
+#![cfg(feature = "mock")]
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use tauri::async_runtime::block_on;
+use tauri::test::mock_app;
+use tauri::Listener;
+use tauri_plugin_iap::{
+    EntitlementSource, EntitlementsExport, Iap, IapErrorCode, PURCHASE_UPDATE_EVENT,
+};
+
+use common::{param, product};
+
+#[test]
+fn exports_product_ids_states_and_dates_only() {
+    let app = mock_app();
+    let iap = Iap::mock(app.handle(), vec![product("premium"), product("no_ads")]);
+    block_on(iap.initialize()).unwrap();
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    block_on(iap.buy_non_consumable(param("no_ads"))).unwrap();
+
+    let export = iap.export_entitlements();
+    let json = export.to_json();
+
+    assert_eq!(export.schema_version, EntitlementsExport::SCHEMA_VERSION);
+    assert_eq!(export.plugin_version, env!("CARGO_PKG_VERSION"));
+    let products: Vec<_> = export
+        .entitlements
+        .iter()
+        .map(|entitlement| entitlement.product_id.as_str())
+        .collect();
+    assert_eq!(products, ["no_ads", "premium"]);
+    assert!(export
+        .entitlements
+        .iter()
+        .all(|entitlement| entitlement.source == EntitlementSource::Store
+            && entitlement.transaction_date.is_some()));
+    assert!(!json.contains("mock-token"), "{json}");
+    assert!(!json.contains("mock-order"), "{json}");
+    assert!(!json.contains("verificationData"), "{json}");
+}
+
+#[test]
+fn imports_round_trip() {
+    let app = mock_app();
+    let iap = Iap::mock(app.handle(), vec![product("premium"), product("no_ads")]);
+    block_on(iap.initialize()).unwrap();
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    let exported = iap.export_entitlements().to_json();
+    iap.mock_clear_purchases().unwrap();
+    block_on(iap.buy_non_consumable(param("no_ads"))).unwrap();
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    {
+        let updates = updates.clone();
+        app.listen(PURCHASE_UPDATE_EVENT, move |event| {
+            let purchases: Value = serde_json::from_str(event.payload()).unwrap();
+            updates.lock().unwrap().push(purchases);
+        });
+    }
+
+    iap.import_entitlements(EntitlementsExport::parse(&exported).unwrap())
+        .unwrap();
+
+    assert!(iap.is_owned("premium"));
+    assert!(!iap.is_owned("no_ads"));
+    let reimported = iap.export_entitlements();
+    let original = EntitlementsExport::parse(&exported).unwrap();
+    assert_eq!(
+        serde_json::to_value(&reimported.entitlements).unwrap(),
+        serde_json::to_value(&original.entitlements).unwrap()
+    );
+    let updates = updates.lock().unwrap().clone();
+    assert_eq!(updates.len(), 1);
+    let reported: Vec<_> = updates[0]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|purchase| (purchase["productId"].clone(), purchase["status"].clone()))
+        .collect();
+    assert_eq!(
+        reported,
+        [
+            (json!("no_ads"), json!("canceled")),
+            (json!("premium"), json!("purchased")),
+        ]
+    );
+}
+
+#[test]
+fn imports_crafted_states() {
+    let app = mock_app();
+    let iap = Iap::mock(app.handle(), vec![product("pro_monthly")]);
+    block_on(iap.initialize()).unwrap();
+    let crafted = json!({
+        "schemaVersion": EntitlementsExport::SCHEMA_VERSION,
+        "pluginVersion": "qa",
+        "exportedAt": "2025-01-01T00:00:00Z",
+        "entitlements": [
+            {
+                "productId": "pro_monthly",
+                "status": "restored",
+                "subscriptionState": "gracePeriod",
+                "transactionDate": "2025-01-01T00:00:00Z",
+                "expirationDate": "2025-01-31T00:00:00Z",
+                "gracePeriodExpirationDate": "2099-01-01T00:00:00Z",
+                "source": "store"
+            },
+            {
+                "productId": "lapsed",
+                "status": "restored",
+                "subscriptionState": "expired",
+                "source": "cache"
+            }
+        ]
+    });
+
+    iap.import_entitlements(EntitlementsExport::parse(&crafted.to_string()).unwrap())
+        .unwrap();
+
+    assert!(iap.is_owned("pro_monthly"));
+    assert!(!iap.is_owned("lapsed"));
+}
+
+#[test]
+fn rejects_other_schema_versions() {
+    let app = mock_app();
+    let iap = Iap::mock(app.handle(), Vec::new());
+    block_on(iap.initialize()).unwrap();
+    let mut newer = serde_json::to_value(iap.export_entitlements()).unwrap();
+    newer["schemaVersion"] = json!(EntitlementsExport::SCHEMA_VERSION + 1);
+    newer["entitlements"] = json!({ "unknown": "layout" });
+
+    let error = EntitlementsExport::parse(&newer.to_string()).unwrap_err();
+    assert_eq!(error.code(), IapErrorCode::DeveloperError);
+    let error = EntitlementsExport::parse("not json").unwrap_err();
+    assert_eq!(error.code(), IapErrorCode::DeveloperError);
+
+    let mut export = iap.export_entitlements();
+    export.schema_version += 1;
+    let error = iap.import_entitlements(export).unwrap_err();
+    assert_eq!(error.code(), IapErrorCode::DeveloperError);
+}
@@ -0,0 +1,191 @@
+//! Keeps `guest-js/error-codes.json`, which the JS build checks `IapErrorCode`
+//! against, in sync with the Rust error codes.
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use serde::Deserialize;
+use tauri_plugin_iap::{Error, IapErrorCode};
+
+#[derive(Deserialize)]
+struct Manifest {
+    codes: Vec<String>,
+    retryable: Vec<String>,
+}
+
+fn manifest() -> Manifest {
+    let json = include_str!("../guest-js/error-codes.json");
+    serde_json::from_str(json).expect("guest-js/error-codes.json is invalid")
+}
+
+/// One error of every variant. The match fails to compile when a variant is
+/// added, so it has to be added here too.
+fn every_error() -> Vec<Error> {
+    let errors = vec![
+        Error::Io(std::io::Error::other("io")),
+        Error::PlatformNotSupported,
+        Error::NotInitialized,
+        Error::BillingClientInitError("init".into()),
+        Error::StoreUnavailable("store".into()),
+        Error::ProductQueryError("query".into()),
+        Error::PurchaseError("purchase".into()),
+        Error::ConsumptionError("consume".into()),
+        Error::RestoreError("restore".into()),
+        Error::InvalidPurchaseToken("token".into()),
+        Error::NetworkError("network".into()),
+        Error::RateLimited {
+            retry_after: Some(Duration::from_secs(1)),
+        },
+        Error::UserCancelled,
+        Error::ItemAlreadyOwned,
+        Error::ItemNotOwned("premium".into()),
+        Error::UnknownPurchase("premium".into()),
+        Error::NoActiveSubscription("premium_yearly".into()),
+        Error::AmbiguousSubscription {
+            candidates: vec!["premium_monthly".into(), "pro_monthly".into()],
+        },
+        Error::ServiceDisconnected,
+        Error::ServiceTimeout,
+        Error::TimedOut {
+            operation: "purchase".into(),
+        },
+        Error::ScopeViolation {
+            product_id: "premium".into(),
+        },
+        Error::WindowNotAllowed {
+            window: "main".into(),
+        },
+        Error::ReceiptUnavailable,
+        Error::FeatureNotSupported("feature".into()),
+        Error::DeveloperError("developer".into()),
+        Error::InvalidConfig("config".into()),
+        Error::InternalError("internal".into()),
+        Error::NetworkError("network".into()).with_native_code(2),
+    ];
+    for error in &errors {
+        match error {
+            Error::Io(_)
+            | Error::PlatformNotSupported
+            | Error::NotInitialized
+            | Error::BillingClientInitError(_)
+            | Error::StoreUnavailable(_)
+            | Error::ProductQueryError(_)
+            | Error::PurchaseError(_)
+            | Error::ConsumptionError(_)
+            | Error::RestoreError(_)
+            | Error::InvalidPurchaseToken(_)
+            | Error::NetworkError(_)
+            | Error::RateLimited { .. }
+            | Error::UserCancelled
+            | Error::ItemAlreadyOwned
+            | Error::ItemNotOwned(_)
+            | Error::UnknownPurchase(_)
+            | Error::NoActiveSubscription(_)
+            | Error::AmbiguousSubscription { .. }
+            | Error::ServiceDisconnected
+            | Error::ServiceTimeout
+            | Error::TimedOut { .. }
+            | Error::ScopeViolation { .. }
+            | Error::WindowNotAllowed { .. }
+            | Error::ReceiptUnavailable
+            | Error::FeatureNotSupported(_)
+            | Error::DeveloperError(_)
+            | Error::InvalidConfig(_)
+            | Error::InternalError(_)
+            | Error::Native { .. } => {}
+        }
+    }
+    errors
+}
+
+#[test]
+fn manifest_lists_every_code() {
+    let manifest = manifest();
+    let codes: Vec<&str> = IapErrorCode::ALL.iter().map(IapErrorCode::as_str).collect();
+    assert_eq!(manifest.codes, codes);
+}
+
+#[test]
+fn errors_serialize_to_manifest_codes() {
+    let manifest = manifest();
+    for error in every_error() {
+        let payload = serde_json::to_value(&error).unwrap();
+        let code = payload["code"].as_str().unwrap();
+        assert!(
+            manifest.codes.iter().any(|known| known == code),
+            "{error:?} serialized to {code}, missing from the manifest"
+        );
+        assert_eq!(code, error.code().as_str());
+        assert_eq!(
+            manifest.retryable.iter().any(|known| known == code),
+            error.is_retryable(),
+            "retryable codes of the manifest disagree with {error:?}"
+        );
+    }
+}
+
+#[test]
+fn manifest_codes_are_unique() {
+    let manifest = manifest();
+    let codes: BTreeSet<&String> = manifest.codes.iter().collect();
+    assert_eq!(codes.len(), manifest.codes.len());
+    assert!(manifest
+        .retryable
+        .iter()
+        .all(|code| manifest.codes.contains(code)));
+}
+
+/// A payment sheet the user dismissed on the mock store, as every store
+/// reports it: `Error::UserCancelled` from Rust, `userCancelled` for the
+/// webview, mapped back the same from the rejection body whatever its
+/// message.
+#[cfg(feature = "mock")]
+#[test]
+fn a_cancelled_mock_purchase_is_user_cancelled() {
+    use tauri::async_runtime::block_on;
+    use tauri_plugin_iap::{IAPError, Iap, MockOperation, ProductDetails, PurchaseParam};
+
+    let app = tauri::test::mock_app();
+    let premium = ProductDetails {
+        id: "premium".into(),
+        title: "Premium".into(),
+        description: String::new(),
+        price: "$1.99".into(),
+        raw_price: 1.99,
+        currency_code: "USD".into(),
+        currency_symbol: "$".into(),
+        subscription_period: None,
+        free_trial_period: None,
+        subscription_group_id: None,
+    };
+    let iap = Iap::mock(app.handle(), vec![premium.clone()]);
+    block_on(iap.initialize()).unwrap();
+    let param = PurchaseParam {
+        product_details: premium,
+        application_user_name: None,
+    };
+    let mock = iap.mock_store().unwrap();
+
+    for operation in [
+        MockOperation::BuyNonConsumable,
+        MockOperation::BuyConsumable,
+    ] {
+        mock.fail_next(operation, IapErrorCode::UserCancelled);
+        let error = match operation {
+            MockOperation::BuyNonConsumable => block_on(iap.buy_non_consumable(param.clone())),
+            _ => block_on(iap.buy_consumable(param.clone(), true)),
+        }
+        .unwrap_err();
+        assert!(
+            matches!(error, Error::UserCancelled),
+            "{operation:?}: {error:?}"
+        );
+
+        let mut body = serde_json::to_value(&error).unwrap();
+        assert_eq!(body["code"], "userCancelled");
+        body["message"] = "Achat annulé".into();
+        let error: IAPError = serde_json::from_value(body).unwrap();
+        assert!(matches!(Error::from(error), Error::UserCancelled));
+    }
+    assert!(mock.purchases().is_empty());
+}
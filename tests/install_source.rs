@@ -0,0 +1,95 @@
+//! Maps what the platforms report about the installation, as the Kotlin and
+//! Swift `installSource` handlers send it, to an `InstallSource`.
+
+use tauri_plugin_iap::InstallSource;
+
+#[test]
+fn android_installers() {
+    let cases = [
+        (Some("com.android.vending"), InstallSource::PlayStore),
+        (
+            Some("com.google.android.feedback"),
+            InstallSource::PlayStore,
+        ),
+        (Some("com.amazon.venezia"), InstallSource::AmazonStore),
+        // Package installer after opening a shared APK, `adb install`, or
+        // no installer recorded at all.
+        (
+            Some("com.google.android.packageinstaller"),
+            InstallSource::Sideload,
+        ),
+        (
+            Some("com.android.packageinstaller"),
+            InstallSource::Sideload,
+        ),
+        (Some("com.android.shell"), InstallSource::Sideload),
+        (None, InstallSource::Sideload),
+        (
+            Some("com.sec.android.app.samsungapps"),
+            InstallSource::Unknown,
+        ),
+    ];
+    for (installer, expected) in cases {
+        assert_eq!(
+            InstallSource::from_android_installer(installer, false),
+            expected,
+            "{installer:?}"
+        );
+    }
+}
+
+#[test]
+fn debuggable_android_builds_are_dev_builds() {
+    for installer in [Some("com.android.vending"), Some("com.android.shell"), None] {
+        assert_eq!(
+            InstallSource::from_android_installer(installer, true),
+            InstallSource::DevBuild
+        );
+    }
+}
+
+#[test]
+fn app_store_environments() {
+    let cases = [
+        (Some("production"), false, InstallSource::AppStore),
+        (Some("production"), true, InstallSource::MacAppStore),
+        (Some("sandbox"), false, InstallSource::TestFlight),
+        (Some("sandbox"), true, InstallSource::TestFlight),
+        (Some("xcode"), false, InstallSource::DevBuild),
+        (None, false, InstallSource::Unknown),
+        (Some("enterprise"), false, InstallSource::Unknown),
+    ];
+    for (environment, macos, expected) in cases {
+        assert_eq!(
+            InstallSource::from_app_store_environment(environment, false, macos),
+            expected,
+            "{environment:?} on macOS: {macos}"
+        );
+    }
+}
+
+#[test]
+fn debuggable_app_store_builds_are_dev_builds() {
+    for environment in [Some("production"), Some("sandbox"), None] {
+        assert_eq!(
+            InstallSource::from_app_store_environment(environment, true, false),
+            InstallSource::DevBuild
+        );
+    }
+}
+
+#[test]
+fn serializes_like_the_bindings() {
+    assert_eq!(
+        serde_json::to_value(InstallSource::PlayStore).unwrap(),
+        "playStore"
+    );
+    assert_eq!(
+        serde_json::to_value(InstallSource::MacAppStore).unwrap(),
+        "macAppStore"
+    );
+    assert_eq!(
+        serde_json::to_value(InstallSource::DevBuild).unwrap(),
+        "devBuild"
+    );
+}
@@ -0,0 +1,92 @@
+//! Purchase funnel counts over a scripted session against the mock store.
+
+#![cfg(feature = "mock")]
+
+mod common;
+
+use std::collections::BTreeMap;
+
+use tauri::async_runtime::block_on;
+use tauri_plugin_iap::{IapErrorCode, Metrics, MockOperation};
+
+use common::{mock_iap, param};
+
+#[test]
+fn counts_start_at_zero() {
+    let (_app, iap) = mock_iap(&["premium", "coins"]);
+
+    assert_eq!(iap.metrics(), Metrics::default());
+    assert_eq!(iap.metrics().average_flow_duration_ms, None);
+}
+
+#[test]
+fn counts_the_funnel_of_a_session() {
+    let (_app, iap) = mock_iap(&["premium", "coins"]);
+    let mock = iap.mock_store().unwrap();
+
+    block_on(iap.query_product_details(vec!["premium".into(), "coins".into()])).unwrap();
+    mock.fail_next(MockOperation::BuyNonConsumable, IapErrorCode::UserCancelled);
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap_err();
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    // Already owned.
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap_err();
+    mock.fail_next(MockOperation::BuyConsumable, IapErrorCode::NetworkError);
+    block_on(iap.buy_consumable(param("coins"), true)).unwrap_err();
+    block_on(iap.buy_consumable(param("coins"), true)).unwrap();
+    block_on(iap.restore_purchases(None)).unwrap();
+
+    let metrics = iap.metrics();
+    assert_eq!(metrics.product_queries, 1);
+    assert_eq!(metrics.purchases_started, 5);
+    assert_eq!(metrics.purchased, 2);
+    assert_eq!(metrics.cancelled, 1);
+    assert_eq!(metrics.errored, 2);
+    assert_eq!(
+        metrics.errors_by_code,
+        BTreeMap::from([
+            ("itemAlreadyOwned".to_string(), 1),
+            ("networkError".to_string(), 1),
+        ])
+    );
+    assert_eq!(metrics.restores, 1);
+    assert!(metrics.average_flow_duration_ms.is_some());
+}
+
+#[test]
+fn calls_rejected_before_initialize_are_not_counted() {
+    let (_app, iap) = mock_iap(&["premium", "coins"]);
+    iap.end_connection();
+
+    block_on(iap.query_product_details(vec!["premium".into()])).unwrap_err();
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap_err();
+    block_on(iap.buy_consumable(param("coins"), true)).unwrap_err();
+    block_on(iap.restore_purchases(None)).unwrap_err();
+
+    assert_eq!(iap.metrics(), Metrics::default());
+}
+
+#[test]
+fn reset_zeroes_every_count() {
+    let (_app, iap) = mock_iap(&["premium", "coins"]);
+    block_on(iap.query_product_details(vec!["premium".into()])).unwrap();
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap_err();
+
+    iap.reset_metrics();
+
+    assert_eq!(iap.metrics(), Metrics::default());
+    block_on(iap.restore_purchases(None)).unwrap();
+    assert_eq!(iap.metrics().restores, 1);
+}
+
+#[test]
+fn serializes_to_camel_case() {
+    let (_app, iap) = mock_iap(&["premium", "coins"]);
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+
+    let json = serde_json::to_value(iap.metrics()).unwrap();
+
+    assert_eq!(json["purchasesStarted"], 1);
+    assert_eq!(json["errorsByCode"], serde_json::json!({}));
+    assert!(json["averageFlowDurationMs"].is_u64());
+}
@@ -0,0 +1,73 @@
+//! Wiping the mock store's purchases so the purchase path can be tested
+//! again.
+
+#![cfg(feature = "mock")]
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use tauri::async_runtime::block_on;
+use tauri::test::mock_app;
+use tauri::Listener;
+use tauri_plugin_iap::{Iap, PURCHASE_UPDATE_EVENT};
+
+use common::{param, product};
+
+#[test]
+fn clearing_purchases_lets_products_be_bought_again() {
+    let app = mock_app();
+    let iap = Iap::mock(app.handle(), vec![product("premium"), product("no_ads")]);
+    block_on(iap.initialize()).unwrap();
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    block_on(iap.buy_non_consumable(param("no_ads"))).unwrap();
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    {
+        let updates = updates.clone();
+        app.listen(PURCHASE_UPDATE_EVENT, move |event| {
+            let purchases: Value = serde_json::from_str(event.payload()).unwrap();
+            updates.lock().unwrap().push(purchases);
+        });
+    }
+
+    iap.mock_clear_purchases().unwrap();
+
+    let mock = iap.mock_store().unwrap();
+    assert!(mock.purchases().is_empty());
+    assert!(iap.owned_products().is_empty());
+    assert!(!iap.is_owned("premium"));
+    let updates = updates.lock().unwrap().clone();
+    assert_eq!(updates.len(), 1);
+    let revoked = updates[0].as_array().unwrap();
+    assert_eq!(revoked.len(), 2);
+    for purchase in revoked {
+        assert_eq!(purchase["status"], "canceled");
+        assert_eq!(purchase["pendingCompletePurchase"], false);
+    }
+
+    // Order IDs start over.
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    assert_eq!(
+        mock.purchases()[0].purchase_id.as_deref(),
+        Some("mock-order-1")
+    );
+}
+
+#[test]
+fn clearing_without_purchases_emits_nothing() {
+    let app = mock_app();
+    let iap = Iap::mock(app.handle(), vec![product("premium")]);
+    block_on(iap.initialize()).unwrap();
+    let updates = Arc::new(Mutex::new(0));
+    {
+        let updates = updates.clone();
+        app.listen(PURCHASE_UPDATE_EVENT, move |_| {
+            *updates.lock().unwrap() += 1
+        });
+    }
+
+    iap.mock_clear_purchases().unwrap();
+
+    assert_eq!(*updates.lock().unwrap(), 0);
+}
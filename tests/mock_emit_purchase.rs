@@ -0,0 +1,117 @@
+//! Purchase updates injected from the webview into the mock store's plugin,
+//! as if the store reported them.
+
+#![cfg(all(feature = "mock", debug_assertions))]
+
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use tauri::ipc::{CallbackFn, InvokeBody};
+use tauri::test::{get_ipc_response, mock_builder, mock_context, noop_assets, INVOKE_KEY};
+use tauri::utils::acl::ExecutionContext;
+use tauri::webview::InvokeRequest;
+use tauri::{Listener, WebviewWindowBuilder};
+use tauri_plugin_iap::{IapExt, PURCHASE_UPDATE_EVENT};
+
+#[test]
+fn injected_purchases_reach_rust_listeners_and_the_webview() {
+    let storage = std::env::temp_dir().join(format!(
+        "iap-mock-emit-purchase-{}.json",
+        std::process::id()
+    ));
+    let mut context = mock_context(noop_assets());
+    context.runtime_authority_mut().__allow_command(
+        "plugin:iap|mock_emit_purchase".into(),
+        ExecutionContext::Local,
+    );
+    let app = mock_builder()
+        .plugin(
+            tauri_plugin_iap::Builder::new()
+                .mock_headless(true)
+                .mock_storage_path(&storage)
+                .build(),
+        )
+        .build(context)
+        .expect("failed to build the app");
+    let webview = WebviewWindowBuilder::new(&app, "main", Default::default())
+        .build()
+        .expect("failed to build the window");
+    let received = Arc::new(Mutex::new(Vec::new()));
+    {
+        let received = received.clone();
+        app.listen(PURCHASE_UPDATE_EVENT, move |event| {
+            let purchases: Value = serde_json::from_str(event.payload()).unwrap();
+            received.lock().unwrap().push(("rust", purchases));
+        });
+    }
+    {
+        let received = received.clone();
+        webview.listen(PURCHASE_UPDATE_EVENT, move |event| {
+            let purchases: Value = serde_json::from_str(event.payload()).unwrap();
+            received.lock().unwrap().push(("webview", purchases));
+        });
+    }
+    // A renewal of a family shared subscription.
+    let renewal = json!({
+        "purchaseId": "renewal-2",
+        "productId": "pro_monthly",
+        "verificationData": {
+            "localVerificationData": "jws",
+            "serverVerificationData": "jws",
+            "source": "apple"
+        },
+        "transactionDateMs": 1_700_000_000_000_i64,
+        "expirationDate": "2099-01-01T00:00:00Z",
+        "subscriptionState": "active",
+        "status": "purchased",
+        "pendingCompletePurchase": false
+    });
+
+    let response = get_ipc_response(
+        &webview,
+        InvokeRequest {
+            cmd: "plugin:iap|mock_emit_purchase".into(),
+            callback: CallbackFn(0),
+            error: CallbackFn(1),
+            url: if cfg!(windows) {
+                "http://tauri.localhost"
+            } else {
+                "tauri://localhost"
+            }
+            .parse()
+            .unwrap(),
+            body: InvokeBody::Json(json!({ "details": renewal })),
+            headers: Default::default(),
+            invoke_key: INVOKE_KEY.to_string(),
+        },
+    );
+
+    assert!(response.is_ok(), "{:?}", response.err());
+    let received = received.lock().unwrap().clone();
+    let sources: Vec<_> = received.iter().map(|(source, _)| *source).collect();
+    assert!(sources.contains(&"rust"), "{sources:?}");
+    assert!(sources.contains(&"webview"), "{sources:?}");
+    for (_, purchases) in &received {
+        assert_eq!(purchases[0]["purchaseId"], "renewal-2");
+        assert_eq!(purchases[0]["transactionDate"], "2023-11-14T22:13:20Z");
+    }
+    assert!(app.iap().is_owned("pro_monthly"));
+
+    // Revoked, e.g. the family organizer stopped sharing.
+    app.iap()
+        .push_purchase_updates(&[serde_json::from_value(json!({
+            "purchaseId": "renewal-2",
+            "productId": "pro_monthly",
+            "verificationData": {
+                "localVerificationData": "jws",
+                "serverVerificationData": "jws",
+                "source": "apple"
+            },
+            "subscriptionState": "expired",
+            "status": "canceled",
+            "pendingCompletePurchase": false
+        }))
+        .unwrap()]);
+    assert!(!app.iap().is_owned("pro_monthly"));
+    let _ = std::fs::remove_file(storage);
+}
@@ -0,0 +1,56 @@
+//! The mock store configured through environment variables, as an end to end
+//! test harness does. The variables are shared by the whole process, so a
+//! single test sets them.
+
+#![cfg(feature = "mock")]
+
+mod common;
+
+use serde_json::json;
+use tauri::async_runtime::block_on;
+use tauri_plugin_iap::{IapExt, MOCK_CATALOG_ENV, MOCK_ENV, MOCK_OWNED_ENV};
+
+use common::{fresh, launch, product, try_launch};
+
+#[test]
+fn configures_the_mock_store_from_the_environment() {
+    fresh("mock-env");
+    let dir = std::env::temp_dir().join("tauri-plugin-iap-mock-env");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let catalog = dir.join("products.json");
+    let products = json!([product("gems"), product("pro")]);
+    std::fs::write(&catalog, serde_json::to_vec(&products).unwrap()).unwrap();
+    std::env::set_var(MOCK_CATALOG_ENV, &catalog);
+    std::env::set_var(MOCK_OWNED_ENV, " pro,,");
+
+    // The catalog replaces the one of the builder.
+    let app = launch("mock-env", json!({}));
+    let iap = app.handle().iap();
+    block_on(iap.initialize()).unwrap();
+    let response =
+        block_on(iap.query_product_details(vec!["gems".into(), "premium".into()])).unwrap();
+    assert_eq!(response.product_details.len(), 1);
+    assert_eq!(response.product_details[0].id, "gems");
+    assert_eq!(response.not_found_ids, ["premium"]);
+    let owned = iap.mock_store().unwrap().purchases();
+    assert_eq!(owned.len(), 1);
+    assert_eq!(owned[0].product_id, "pro");
+    drop(app);
+
+    std::fs::write(&catalog, br#"[{"id": "gems""#).unwrap();
+    let error = try_launch("mock-env", json!({})).unwrap_err().to_string();
+    assert!(error.contains("Invalid mock catalog"), "{error}");
+    assert!(error.contains(&catalog.display().to_string()), "{error}");
+
+    std::fs::remove_file(&catalog).unwrap();
+    let error = try_launch("mock-env", json!({})).unwrap_err().to_string();
+    assert!(error.contains("Failed to read the mock catalog"), "{error}");
+    std::env::remove_var(MOCK_CATALOG_ENV);
+    std::env::remove_var(MOCK_OWNED_ENV);
+
+    std::env::set_var(MOCK_ENV, "yes");
+    let error = try_launch("mock-env", json!({})).unwrap_err().to_string();
+    assert!(error.contains(MOCK_ENV), "{error}");
+    std::env::remove_var(MOCK_ENV);
+}
@@ -0,0 +1,297 @@
+//! The purchase flow against the in-memory mock store: its catalog, the
+//! purchases it makes up and the updates it reports like a real store.
+
+#![cfg(feature = "mock")]
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use tauri::async_runtime::block_on;
+use tauri::Listener;
+use tauri_plugin_iap::{
+    Error, IapExt, MockOperation, PurchaseStatus, MOCK_SOURCE, RESTORE_COMPLETED_EVENT,
+};
+
+use common::{fresh, launch, mock_iap, param, purchase_updates, wait_for};
+
+#[test]
+fn queries_the_catalog() {
+    let (_app, iap) = mock_iap(&["premium", "coins"]);
+
+    let response = block_on(iap.query_product_details(vec![
+        "coins".into(),
+        "missing".into(),
+        "premium".into(),
+    ]))
+    .unwrap();
+
+    let found: Vec<_> = response
+        .product_details
+        .iter()
+        .map(|p| p.id.as_str())
+        .collect();
+    assert_eq!(found, ["coins", "premium"]);
+    assert_eq!(response.not_found_ids, ["missing"]);
+    assert!(response.error.is_none());
+}
+
+#[test]
+fn reports_purchases_with_generated_tokens() {
+    let (app, iap) = mock_iap(&["premium", "coins"]);
+    let updates = purchase_updates(&app);
+
+    assert!(block_on(iap.buy_non_consumable(param("premium"))).unwrap());
+    assert!(block_on(iap.buy_consumable(param("coins"), false)).unwrap());
+
+    let updates = wait_for(&updates, 2);
+    assert_eq!(updates.len(), 2);
+    for (n, (update, product_id)) in updates.iter().zip(["premium", "coins"]).enumerate() {
+        assert_eq!(update.len(), 1);
+        let purchase = &update[0];
+        assert_eq!(purchase.product_id, product_id);
+        assert!(matches!(purchase.status, PurchaseStatus::Purchased));
+        assert!(purchase.pending_complete_purchase);
+        assert_eq!(purchase.purchase_id, Some(format!("mock-order-{}", n + 1)));
+        let token = format!("mock-token-{}", n + 1);
+        assert_eq!(purchase.verification_data.server_verification_data, token);
+        assert_eq!(purchase.verification_data.source, MOCK_SOURCE);
+        assert!(purchase.transaction_date_ms.is_some());
+    }
+    assert!(iap.is_owned("premium"));
+}
+
+#[test]
+fn completing_a_purchase_finishes_it() {
+    let (app, iap) = mock_iap(&["premium", "coins"]);
+    let updates = purchase_updates(&app);
+    let mock = iap.mock_store().unwrap();
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    block_on(iap.buy_consumable(param("coins"), false)).unwrap();
+    let bought = wait_for(&updates, 2);
+
+    block_on(iap.complete_purchase(bought[0][0].clone())).unwrap();
+    block_on(iap.complete_purchase(bought[1][0].clone())).unwrap();
+
+    // The non-consumable stays owned, the consumable is consumed.
+    let purchases = mock.purchases();
+    assert_eq!(purchases.len(), 1);
+    assert_eq!(purchases[0].product_id, "premium");
+    assert!(!purchases[0].pending_complete_purchase);
+    block_on(iap.buy_consumable(param("coins"), false)).unwrap();
+}
+
+#[test]
+fn auto_consumed_purchases_can_be_bought_again() {
+    let (_app, iap) = mock_iap(&["coins"]);
+
+    block_on(iap.buy_consumable(param("coins"), true)).unwrap();
+    block_on(iap.buy_consumable(param("coins"), true)).unwrap();
+
+    assert!(iap.mock_store().unwrap().purchases().is_empty());
+}
+
+#[test]
+fn fails_like_a_store() {
+    let (_app, iap) = mock_iap(&["premium"]);
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+
+    assert!(matches!(
+        block_on(iap.buy_non_consumable(param("premium"))),
+        Err(Error::ItemAlreadyOwned)
+    ));
+    assert!(matches!(
+        block_on(iap.buy_non_consumable(param("missing"))),
+        Err(Error::PurchaseError(_))
+    ));
+    let mut forged = iap.mock_store().unwrap().purchases()[0].clone();
+    forged.verification_data.server_verification_data = "forged".into();
+    assert!(matches!(
+        block_on(iap.complete_purchase(forged)),
+        Err(Error::UnknownPurchase(_))
+    ));
+}
+
+#[test]
+fn restores_earlier_purchases() {
+    let (app, iap) = mock_iap(&["premium", "coins"]);
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    block_on(iap.buy_consumable(param("coins"), false)).unwrap();
+    let updates = purchase_updates(&app);
+
+    block_on(iap.restore_purchases(None)).unwrap();
+
+    // Consumables are never restored.
+    let updates = wait_for(&updates, 1);
+    assert_eq!(updates.len(), 1);
+    let restored: Vec<_> = updates[0].iter().map(|p| p.product_id.as_str()).collect();
+    assert_eq!(restored, ["premium"]);
+    assert!(matches!(updates[0][0].status, PurchaseStatus::Restored));
+    let entitlements = block_on(iap.current_entitlements()).unwrap();
+    assert_eq!(entitlements.len(), 1);
+    assert_eq!(entitlements[0].product_id, "premium");
+}
+
+#[test]
+fn restores_the_purchases_of_an_earlier_run() {
+    fresh("mock-restore");
+    {
+        let app = launch("mock-restore", json!({}));
+        let iap = app.handle().iap();
+        let updates = purchase_updates(&app);
+        block_on(iap.initialize()).unwrap();
+        let mut premium = param("premium");
+        premium.application_user_name = Some("alice".into());
+        block_on(iap.buy_non_consumable(premium)).unwrap();
+        block_on(iap.buy_non_consumable(param("coins"))).unwrap();
+        for update in wait_for(&updates, 2) {
+            block_on(iap.complete_purchase(update[0].clone())).unwrap();
+        }
+    }
+
+    // Nothing is left in memory, only in the storage file.
+    let app = launch("mock-restore", json!({}));
+    let iap = app.handle().iap();
+    let updates = purchase_updates(&app);
+    let completed = Arc::new(Mutex::new(Vec::new()));
+    {
+        let completed = completed.clone();
+        app.listen(RESTORE_COMPLETED_EVENT, move |event| {
+            let payload: Value = serde_json::from_str(event.payload()).unwrap();
+            completed.lock().unwrap().push(payload);
+        });
+    }
+    block_on(iap.initialize()).unwrap();
+    assert!(!iap.is_owned("premium"));
+
+    block_on(iap.restore_purchases(None)).unwrap();
+    let restored = wait_for(&updates, 1);
+    let mut products: Vec<_> = restored[0].iter().map(|p| p.product_id.as_str()).collect();
+    products.sort();
+    assert_eq!(products, ["coins", "premium"]);
+    assert!(restored[0]
+        .iter()
+        .all(|purchase| matches!(purchase.status, PurchaseStatus::Restored)));
+    assert_eq!(wait_for(&completed, 1), [json!({ "restoredCount": 2 })]);
+    assert!(iap.is_owned("premium") && iap.is_owned("coins"));
+
+    // Only the purchases made with the application user name.
+    block_on(iap.restore_purchases(Some("alice".into()))).unwrap();
+    let restored = wait_for(&updates, 2);
+    let products: Vec<_> = restored[1].iter().map(|p| p.product_id.as_str()).collect();
+    assert_eq!(products, ["premium"]);
+    assert_eq!(wait_for(&completed, 2)[1], json!({ "restoredCount": 1 }));
+}
+
+#[test]
+fn reports_pending_purchases_again_once_paid() {
+    let (app, iap) = mock_iap(&["premium", "coins"]);
+    let updates = purchase_updates(&app);
+    let mock = iap.mock_store().unwrap();
+    mock.set_pending_purchases(true);
+
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    block_on(iap.buy_non_consumable(param("coins"))).unwrap();
+    let pending = wait_for(&updates, 2);
+    assert!(pending
+        .iter()
+        .all(|update| matches!(update[0].status, PurchaseStatus::Pending)));
+    assert!(!iap.is_owned("premium"));
+
+    mock.approve_pending("premium").unwrap();
+    mock.decline_pending("coins").unwrap();
+    let updates = wait_for(&updates, 4);
+    assert_eq!(updates[2][0].product_id, "premium");
+    assert!(matches!(updates[2][0].status, PurchaseStatus::Purchased));
+    assert!(updates[2][0].pending_complete_purchase);
+    assert_eq!(updates[2][0].purchase_id, pending[0][0].purchase_id);
+    assert_eq!(updates[3][0].product_id, "coins");
+    assert!(matches!(updates[3][0].status, PurchaseStatus::Canceled));
+    assert!(iap.is_owned("premium") && !iap.is_owned("coins"));
+    assert!(matches!(
+        mock.approve_pending("coins"),
+        Err(Error::ItemNotOwned(_))
+    ));
+}
+
+#[test]
+fn reports_pending_purchases_only_once_paid_when_disabled() {
+    fresh("mock-pending-disabled");
+    let app = launch(
+        "mock-pending-disabled",
+        json!({ "pendingPurchases": { "enabled": false } }),
+    );
+    let iap = app.handle().iap();
+    let updates = purchase_updates(&app);
+    block_on(iap.initialize()).unwrap();
+    let mock = iap.mock_store().unwrap();
+    mock.set_pending_purchases(true);
+
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    mock.approve_pending("premium").unwrap();
+
+    let updates = wait_for(&updates, 1);
+    assert_eq!(updates.len(), 1);
+    assert!(matches!(updates[0][0].status, PurchaseStatus::Purchased));
+}
+
+#[test]
+fn records_the_calls_made() {
+    let (_app, iap) = mock_iap(&["premium"]);
+    let mock = iap.mock_store().unwrap();
+    mock.clear_calls();
+
+    block_on(iap.query_product_details(vec!["premium".into()])).unwrap();
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    let bought = mock.purchases().pop().unwrap();
+    block_on(iap.complete_purchase(bought.clone())).unwrap();
+    block_on(iap.restore_purchases(Some("user".into()))).unwrap();
+
+    let calls = mock.calls();
+    let operations: Vec<_> = calls.iter().map(|call| call.operation).collect();
+    assert_eq!(
+        operations,
+        [
+            MockOperation::QueryProductDetails,
+            MockOperation::BuyNonConsumable,
+            MockOperation::CompletePurchase,
+            MockOperation::RestorePurchases,
+        ]
+    );
+    assert_eq!(calls[0].payload, json!({ "productIds": ["premium"] }));
+    assert_eq!(
+        calls[1].payload["purchaseParam"]["productDetails"]["id"],
+        "premium"
+    );
+    assert_eq!(
+        calls[2].payload["purchase"]["purchaseId"],
+        json!(bought.purchase_id)
+    );
+    assert_eq!(calls[3].payload, json!({ "applicationUserName": "user" }));
+
+    mock.clear_calls();
+    assert!(mock.calls().is_empty());
+}
+
+#[test]
+fn pushed_purchase_updates_are_handled_like_the_stores() {
+    let (app, iap) = mock_iap(&["premium"]);
+    let updates = purchase_updates(&app);
+    let mut stream = iap.purchase_stream();
+    iap.mock_store().unwrap().set_pending_purchases(true);
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    let mut approved = wait_for(&updates, 1)[0][0].clone();
+    assert!(!iap.is_owned("premium"));
+
+    // E.g. a purchase approved outside of the app.
+    approved.status = PurchaseStatus::Purchased;
+    iap.push_purchase_updates(&[approved.clone()]);
+
+    let updates = wait_for(&updates, 2);
+    assert_eq!(updates[1][0].purchase_id, approved.purchase_id);
+    assert!(matches!(updates[1][0].status, PurchaseStatus::Purchased));
+    assert!(iap.is_owned("premium"));
+    let streamed: Vec<_> = std::iter::from_fn(|| stream.try_recv().ok()).collect();
+    assert_eq!(streamed.len(), 2);
+}
@@ -0,0 +1,461 @@
+//! Payloads the Kotlin and Swift sides send to the Rust core, kept in
+//! `tests/fixtures/{android,ios,amazon}/`, the last from the Amazon Appstore
+//! plugin of `amazon-appstore` builds. Every fixture must deserialize into the
+//! `models.rs` types and serialize back without losing a known field, so a
+//! model change that breaks the wire format fails here.
+//!
+//! The fixture name picks the type:
+//!
+//! - `query_product_details*`: a `ProductDetailsResponse`
+//! - `products*`: a list of `ProductDetails`
+//! - `purchase_update*`, `owned_purchases*`, `current_entitlements*`:
+//!   `{ "purchases": [PurchaseDetails] }`
+//! - `bridge_purchases*`: a list of `PurchaseDetails`, as reported to the
+//!   Rust core through `IapBridge.onPurchaseUpdate`
+//! - `subscription_status*`: a `SubscriptionStatus`
+//! - `alternative_billing_reporting_details*`: an
+//!   `AlternativeBillingReportingDetails`
+//! - `external_purchase_link*`: an `ExternalPurchaseLinkResult`
+//! - `external_purchase_token*`: an `ExternalPurchaseToken`
+//! - `error*`: an `IAPError`, the JSON body of a rejected command
+
+use std::path::{Path, PathBuf};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tauri_plugin_iap::{
+    AlternativeBillingReportingDetails, Error, ExternalPurchaseLinkResult, ExternalPurchaseToken,
+    IAPError, IapErrorCode, ProductDetails, ProductDetailsResponse, PurchaseDetails,
+    PurchaseStatus, SubscriptionState, SubscriptionStatus, VerificationFormat, VerificationStore,
+};
+
+/// Fields the native sides send that the models don't read. Anything else
+/// missing after the round trip is lost data.
+const NATIVE_ONLY_FIELDS: &[&str] = &[
+    // Android query response
+    "success",
+    "errorMessage",
+    // Android purchase updates
+    "purchaseToken",
+    "purchaseTime",
+    "purchaseState",
+    "isAcknowledged",
+    "verificationAvailable",
+];
+
+#[derive(Deserialize, Serialize)]
+struct Purchases {
+    purchases: Vec<PurchaseDetails>,
+}
+
+fn fixtures() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut fixtures = Vec::new();
+    for platform in ["android", "ios", "amazon"] {
+        for entry in std::fs::read_dir(root.join(platform)).unwrap() {
+            let path = entry.unwrap().path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                fixtures.push(path);
+            }
+        }
+    }
+    fixtures.sort();
+    fixtures
+}
+
+/// Deserializes the fixture as `T` and serializes it back.
+fn round_trip<T: DeserializeOwned + Serialize>(path: &Path, payload: &Value) -> Value {
+    let model: T = serde_json::from_value(payload.clone())
+        .unwrap_or_else(|e| panic!("{} doesn't match the models: {e}", path.display()));
+    serde_json::to_value(model).unwrap()
+}
+
+/// Checks that every field of `native` survived in `model`, other than the
+/// native only ones and absent optional fields.
+fn assert_preserved(path: &Path, at: &str, native: &Value, model: &Value) {
+    match (native, model) {
+        (Value::Object(native), Value::Object(model)) => {
+            for (key, value) in native {
+                match model.get(key) {
+                    Some(kept) => assert_preserved(path, &format!("{at}.{key}"), value, kept),
+                    None => assert!(
+                        value.is_null() || NATIVE_ONLY_FIELDS.contains(&key.as_str()),
+                        "{}: {at}.{key} is lost by the models",
+                        path.display()
+                    ),
+                }
+            }
+        }
+        (Value::Array(native), Value::Array(model)) => {
+            assert_eq!(native.len(), model.len(), "{}: {at}", path.display());
+            for (index, (value, kept)) in native.iter().zip(model).enumerate() {
+                assert_preserved(path, &format!("{at}[{index}]"), value, kept);
+            }
+        }
+        // `4` and `4.0` are the same price.
+        (Value::Number(native), Value::Number(model)) => {
+            assert_eq!(native.as_f64(), model.as_f64(), "{}: {at}", path.display());
+        }
+        _ => assert_eq!(native, model, "{}: {at}", path.display()),
+    }
+}
+
+#[test]
+fn fixtures_survive_a_round_trip() {
+    let fixtures = fixtures();
+    assert!(!fixtures.is_empty(), "no fixtures in tests/fixtures");
+    for path in fixtures {
+        let json = std::fs::read_to_string(&path).unwrap();
+        let payload: Value = serde_json::from_str(&json)
+            .unwrap_or_else(|e| panic!("{} is invalid JSON: {e}", path.display()));
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let model = if name.starts_with("query_product_details") {
+            round_trip::<ProductDetailsResponse>(&path, &payload)
+        } else if name.starts_with("products") {
+            round_trip::<Vec<ProductDetails>>(&path, &payload)
+        } else if name.starts_with("bridge_purchases") {
+            round_trip::<Vec<PurchaseDetails>>(&path, &payload)
+        } else if ["purchase_update", "owned_purchases", "current_entitlements"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+        {
+            round_trip::<Purchases>(&path, &payload)
+        } else if name.starts_with("subscription_status") {
+            round_trip::<SubscriptionStatus>(&path, &payload)
+        } else if name.starts_with("alternative_billing_reporting_details") {
+            round_trip::<AlternativeBillingReportingDetails>(&path, &payload)
+        } else if name.starts_with("external_purchase_link") {
+            round_trip::<ExternalPurchaseLinkResult>(&path, &payload)
+        } else if name.starts_with("external_purchase_token") {
+            round_trip::<ExternalPurchaseToken>(&path, &payload)
+        } else if name.starts_with("error") {
+            round_trip::<IAPError>(&path, &payload)
+        } else {
+            panic!("{} doesn't name a known payload", path.display());
+        };
+        assert_preserved(&path, "$", &payload, &model);
+    }
+}
+
+#[test]
+fn current_entitlements_hold_a_subscription_and_a_non_consumable() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    for platform in ["android", "ios"] {
+        let path = root.join(platform).join("current_entitlements.json");
+        let entitlements: Purchases =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let products: Vec<_> = entitlements
+            .purchases
+            .iter()
+            .map(|purchase| purchase.product_id.as_str())
+            .collect();
+        assert_eq!(products, ["pro_monthly", "premium"], "{}", path.display());
+        for purchase in &entitlements.purchases {
+            assert!(
+                matches!(purchase.status, PurchaseStatus::Restored),
+                "{}",
+                path.display()
+            );
+            assert!(
+                purchase.verification_payload().is_ok(),
+                "{}: {} has no verification data",
+                path.display(),
+                purchase.product_id
+            );
+        }
+    }
+
+    let ios: Purchases = serde_json::from_str(
+        &std::fs::read_to_string(root.join("ios/current_entitlements.json")).unwrap(),
+    )
+    .unwrap();
+    assert!(ios.purchases.iter().all(|purchase| purchase.verified));
+    assert_eq!(
+        ios.purchases[0].expiration_date.as_deref(),
+        Some("2025-11-08T08:53:20Z")
+    );
+    assert_eq!(ios.purchases[1].expiration_date, None);
+}
+
+#[test]
+fn subscription_status_fixtures_report_their_state() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let cases = [
+        (
+            "android/subscription_status_active.json",
+            SubscriptionState::Active,
+        ),
+        (
+            "ios/subscription_status_active.json",
+            SubscriptionState::Active,
+        ),
+        (
+            "ios/subscription_status_grace_period.json",
+            SubscriptionState::GracePeriod,
+        ),
+        (
+            "ios/subscription_status_on_hold.json",
+            SubscriptionState::OnHold,
+        ),
+        (
+            "ios/subscription_status_expired.json",
+            SubscriptionState::Expired,
+        ),
+    ];
+    for (fixture, state) in cases {
+        let status: SubscriptionStatus =
+            serde_json::from_str(&std::fs::read_to_string(root.join(fixture)).unwrap()).unwrap();
+        assert_eq!(status.state, state, "{fixture}");
+        assert_eq!(status.is_active, state.is_entitled(), "{fixture}");
+        assert_eq!(
+            status.grace_period_expiration_date.is_some(),
+            state == SubscriptionState::GracePeriod,
+            "{fixture}"
+        );
+    }
+}
+
+#[test]
+fn grace_period_entitlements_outlive_their_expiration() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/ios/current_entitlements_grace_period.json");
+    let entitlements: Purchases =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let subscription = &entitlements.purchases[0];
+    assert_eq!(
+        subscription.subscription_state,
+        Some(SubscriptionState::GracePeriod)
+    );
+    assert!(
+        subscription.grace_period_expiration_date.as_deref()
+            > subscription.expiration_date.as_deref()
+    );
+}
+
+#[test]
+fn external_purchase_fixtures_report_the_notice_sheet_outcome() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/ios");
+    for (fixture, continued) in [
+        ("external_purchase_link_continued.json", true),
+        ("external_purchase_link_cancelled.json", false),
+    ] {
+        let result: ExternalPurchaseLinkResult =
+            serde_json::from_str(&std::fs::read_to_string(root.join(fixture)).unwrap()).unwrap();
+        assert_eq!(result.continued, continued, "{fixture}");
+    }
+    // A payload without the outcome is rejected rather than read as cancelled.
+    assert!(serde_json::from_value::<ExternalPurchaseLinkResult>(serde_json::json!({})).is_err());
+
+    let token: ExternalPurchaseToken = serde_json::from_str(
+        &std::fs::read_to_string(root.join("external_purchase_token.json")).unwrap(),
+    )
+    .unwrap();
+    assert!(token.token.starts_with("eyJ"));
+}
+
+#[test]
+fn billing_retry_is_on_hold() {
+    let state: SubscriptionState = serde_json::from_value(Value::from("billingRetry")).unwrap();
+    assert_eq!(state, SubscriptionState::OnHold);
+    assert!(!state.is_entitled());
+}
+
+#[test]
+fn error_fixtures_use_known_codes() {
+    for path in fixtures() {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        if !name.starts_with("error") {
+            continue;
+        }
+        let error: IAPError =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_ne!(
+            error.error_code().as_str(),
+            "unknown",
+            "{} has an unknown code {}",
+            path.display(),
+            error.code
+        );
+    }
+}
+
+#[test]
+fn amazon_purchases_export_receipts_with_the_user() {
+    let path =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/amazon/owned_purchases.json");
+    let owned: Purchases = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    for purchase in &owned.purchases {
+        let payload = purchase.verification_payload().unwrap();
+        assert_eq!(payload.store, VerificationStore::Amazon);
+        assert_eq!(payload.format, VerificationFormat::AmazonReceipt);
+        assert_eq!(Some(&payload.data), purchase.purchase_id.as_ref());
+        assert_eq!(
+            payload.user_id.as_deref(),
+            Some("amzn1.account.AGV3ZXhhbXBsZXVzZXI")
+        );
+        assert_eq!(payload.package_name, None);
+    }
+    let json = serde_json::to_value(owned.purchases[0].verification_payload().unwrap()).unwrap();
+    assert_eq!(json["store"], "amazon");
+    assert_eq!(json["format"], "amazon_receipt");
+}
+
+#[test]
+fn bridge_purchases_keep_their_verification_data() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let cases = [
+        ("android/bridge_purchases.json", VerificationStore::Google),
+        ("amazon/bridge_purchases.json", VerificationStore::Amazon),
+    ];
+    for (fixture, store) in cases {
+        // The Rust core verifies and records them, then redacts the event.
+        let purchases: Vec<PurchaseDetails> =
+            serde_json::from_str(&std::fs::read_to_string(root.join(fixture)).unwrap()).unwrap();
+        assert!(!purchases.is_empty(), "{fixture}");
+        for purchase in &purchases {
+            let payload = purchase
+                .verification_payload()
+                .unwrap_or_else(|e| panic!("{fixture}: {} is redacted: {e}", purchase.product_id));
+            assert_eq!(payload.store, store, "{fixture}");
+            assert_eq!(
+                payload.data, purchase.verification_data.server_verification_data,
+                "{fixture}"
+            );
+        }
+    }
+}
+
+#[test]
+fn amazon_bridge_purchases_report_cancelled_subscriptions() {
+    let path =
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/amazon/bridge_purchases.json");
+    let purchases: Vec<PurchaseDetails> =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let (entitlement, subscription) = (&purchases[0], &purchases[1]);
+    assert!(matches!(entitlement.status, PurchaseStatus::Purchased));
+    assert!(entitlement.pending_complete_purchase);
+    assert_eq!(entitlement.subscription_state, None);
+    // A cancelled receipt has nothing left to fulfill.
+    assert!(matches!(subscription.status, PurchaseStatus::Canceled));
+    assert!(!subscription.pending_complete_purchase);
+    assert_eq!(
+        subscription.subscription_state,
+        Some(SubscriptionState::Expired)
+    );
+    assert_eq!(
+        subscription.expiration_date.as_deref(),
+        Some("2023-12-14T22:23:20Z")
+    );
+    assert_eq!(
+        subscription
+            .verification_payload()
+            .unwrap()
+            .user_id
+            .as_deref(),
+        Some("amzn1.account.AGV3ZXhhbXBsZXVzZXI")
+    );
+}
+
+#[test]
+fn amazon_request_statuses_map_to_errors() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/amazon");
+    let cases = [
+        (
+            "error_already_purchased.json",
+            IapErrorCode::ItemAlreadyOwned,
+        ),
+        ("error_invalid_sku.json", IapErrorCode::DeveloperError),
+        ("error_failed.json", IapErrorCode::UserCancelled),
+        (
+            "error_not_supported.json",
+            IapErrorCode::FeatureNotSupported,
+        ),
+    ];
+    for (fixture, code) in cases {
+        let error: IAPError =
+            serde_json::from_str(&std::fs::read_to_string(root.join(fixture)).unwrap()).unwrap();
+        let status = error
+            .details
+            .as_ref()
+            .map(|details| &details["requestStatus"]);
+        assert!(status.is_some_and(Value::is_string), "{fixture}");
+        assert_eq!(Error::from(error).code(), code, "{fixture}");
+    }
+}
+
+#[test]
+fn purchase_fixtures_agree_on_the_transaction_time() {
+    for path in fixtures() {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        if !["purchase_update", "owned_purchases", "current_entitlements"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+        {
+            continue;
+        }
+        let payload: Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let purchases: Purchases = serde_json::from_value(payload.clone()).unwrap();
+        for (purchase, native) in purchases
+            .purchases
+            .iter()
+            .zip(payload["purchases"].as_array().unwrap())
+        {
+            let ms = purchase
+                .transaction_date_ms
+                .unwrap_or_else(|| panic!("{}: no transactionDateMs", path.display()));
+            assert_eq!(
+                Some(ms),
+                native["transactionDateMs"].as_i64(),
+                "{}",
+                path.display()
+            );
+            // Payloads with only the ISO date, as older versions kept them,
+            // read back the same time to the second.
+            let iso_only: PurchaseDetails = serde_json::from_value(serde_json::json!({
+                "productId": purchase.product_id,
+                "verificationData": purchase.verification_data,
+                "transactionDate": purchase.transaction_date,
+                "status": "purchased",
+                "pendingCompletePurchase": false,
+            }))
+            .unwrap();
+            assert_eq!(
+                iso_only.transaction_date_ms,
+                Some(ms - ms.rem_euclid(1000)),
+                "{}",
+                path.display()
+            );
+        }
+    }
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let ios: Purchases = serde_json::from_str(
+        &std::fs::read_to_string(root.join("ios/owned_purchases.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        ios.purchases[0].transaction_date_ms,
+        Some(1_700_000_000_250)
+    );
+    assert_eq!(
+        ios.purchases[0].transaction_date.as_deref(),
+        Some("2023-11-14T22:13:20Z")
+    );
+    // Google reports the same `purchaseTime` in the signed purchase.
+    let android: Purchases = serde_json::from_str(
+        &std::fs::read_to_string(root.join("android/owned_purchases.json")).unwrap(),
+    )
+    .unwrap();
+    for purchase in &android.purchases {
+        let signed: Value =
+            serde_json::from_str(&purchase.verification_data.local_verification_data).unwrap();
+        assert_eq!(
+            signed["purchaseTime"].as_i64(),
+            purchase.transaction_date_ms
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! The configured products queried after `initialize` with `preloadProducts`,
+//! against the mock store.
+
+#![cfg(feature = "mock")]
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use tauri::async_runtime::block_on;
+use tauri::{App, Listener};
+use tauri_plugin_iap::{
+    IapErrorCode, IapExt, MockCall, MockOperation, ERROR_EVENT, PRODUCTS_LOADED_EVENT,
+};
+
+use common::{fresh, launch, wait_for};
+
+/// The payloads of the `event` emitted by `app`, as they arrive.
+fn events(app: &App<tauri::test::MockRuntime>, event: &str) -> Arc<Mutex<Vec<Value>>> {
+    let payloads = Arc::new(Mutex::new(Vec::new()));
+    let received = payloads.clone();
+    app.listen(event, move |event| {
+        let payload = serde_json::from_str(event.payload()).unwrap();
+        received.lock().unwrap().push(payload);
+    });
+    payloads
+}
+
+fn queried(calls: &[MockCall]) -> Vec<Value> {
+    calls
+        .iter()
+        .filter(|call| call.operation == MockOperation::QueryProductDetails)
+        .map(|call| call.payload["productIds"].clone())
+        .collect()
+}
+
+#[test]
+fn answers_queries_from_the_preloaded_products() {
+    fresh("preload-cache");
+    let app = launch(
+        "preload-cache",
+        json!({ "products": ["premium", "coins"], "preloadProducts": true }),
+    );
+    let iap = app.handle().iap();
+    let loaded = events(&app, PRODUCTS_LOADED_EVENT);
+
+    block_on(iap.initialize()).unwrap();
+
+    let loaded = wait_for(&loaded, 1);
+    let ids: Vec<_> = loaded[0]["productDetails"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|product| product["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, ["premium", "coins"]);
+
+    // Only the products that weren't preloaded reach the store.
+    let mock = iap.mock_store().unwrap();
+    mock.clear_calls();
+    let response = block_on(iap.query_product_details(vec!["coins".into()])).unwrap();
+    assert_eq!(response.product_details[0].id, "coins");
+    assert!(queried(&mock.calls()).is_empty());
+    let response =
+        block_on(iap.query_product_details(vec!["premium".into(), "missing".into()])).unwrap();
+    assert_eq!(response.product_details[0].id, "premium");
+    assert_eq!(response.not_found_ids, ["missing"]);
+    assert_eq!(queried(&mock.calls()), [json!(["missing"])]);
+}
+
+#[test]
+fn retries_a_failed_preload() {
+    fresh("preload-retry");
+    let app = launch(
+        "preload-retry",
+        json!({ "products": ["premium"], "preloadProducts": true }),
+    );
+    let iap = app.handle().iap();
+    let mock = iap.mock_store().unwrap();
+    mock.fail_next(
+        MockOperation::QueryProductDetails,
+        IapErrorCode::NetworkError,
+    );
+    let loaded = events(&app, PRODUCTS_LOADED_EVENT);
+    let errors = events(&app, ERROR_EVENT);
+
+    block_on(iap.initialize()).unwrap();
+
+    assert_eq!(wait_for(&loaded, 1).len(), 1);
+    assert_eq!(queried(&mock.calls()).len(), 2);
+    assert!(errors.lock().unwrap().is_empty());
+}
+
+#[test]
+fn reports_a_failed_preload_without_failing_initialize() {
+    fresh("preload-fails");
+    let app = launch(
+        "preload-fails",
+        json!({ "products": ["premium"], "preloadProducts": true }),
+    );
+    let iap = app.handle().iap();
+    let mock = iap.mock_store().unwrap();
+    mock.fail_next(
+        MockOperation::QueryProductDetails,
+        IapErrorCode::DeveloperError,
+    );
+    let loaded = events(&app, PRODUCTS_LOADED_EVENT);
+    let errors = events(&app, ERROR_EVENT);
+
+    block_on(iap.initialize()).unwrap();
+
+    let errors = wait_for(&errors, 1);
+    assert_eq!(errors[0]["code"], json!("developerError"));
+    assert!(loaded.lock().unwrap().is_empty());
+    // Nothing was cached, so the next query reaches the store.
+    mock.clear_calls();
+    block_on(iap.query_product_details(vec!["premium".into()])).unwrap();
+    assert_eq!(queried(&mock.calls()).len(), 1);
+}
@@ -0,0 +1,68 @@
+//! Versioned cache files of product details: files of the current schema
+//! read as written, older ones are migrated and unknown ones discarded.
+
+mod common;
+
+use serde_json::json;
+use tauri_plugin_iap::{CachedProducts, ProductDetailsResponse};
+
+use common::product;
+
+#[test]
+fn current_files_read_back_as_written() {
+    let cached = CachedProducts::new(ProductDetailsResponse {
+        product_details: vec![product("premium")],
+        ..Default::default()
+    });
+
+    let read = CachedProducts::from_json(&cached.to_json().unwrap()).unwrap();
+
+    assert_eq!(read.schema_version, CachedProducts::SCHEMA_VERSION);
+    assert_eq!(read.plugin_version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(read.cached_at_ms, cached.cached_at_ms);
+    assert!(read.cached_at_ms > 0);
+    assert_eq!(read.response.product_details[0].id, "premium");
+}
+
+#[test]
+fn version_1_files_migrate_to_version_2() {
+    // Version 1 didn't record when the products were cached.
+    let v1 = json!({
+        "schemaVersion": 1,
+        "pluginVersion": "0.5.0",
+        "response": {
+            "productDetails": [product("premium")],
+            "notFoundIds": [],
+            "error": null
+        }
+    });
+
+    let read = CachedProducts::from_json(&serde_json::to_vec(&v1).unwrap()).unwrap();
+
+    assert_eq!(read.schema_version, 2);
+    assert_eq!(read.plugin_version, "0.5.0");
+    assert_eq!(read.cached_at_ms, 0);
+    assert_eq!(read.response.product_details[0].id, "premium");
+}
+
+#[test]
+fn unknown_files_are_discarded() {
+    let newer = json!({
+        "schemaVersion": CachedProducts::SCHEMA_VERSION + 1,
+        "pluginVersion": "99.0.0",
+        "products": {}
+    });
+    let unversioned = json!({ "response": { "productDetails": [], "notFoundIds": [] } });
+    let broken = json!({
+        "schemaVersion": CachedProducts::SCHEMA_VERSION,
+        "pluginVersion": env!("CARGO_PKG_VERSION"),
+        "cachedAtMs": 0,
+        "response": { "productDetails": [{ "id": "premium" }], "notFoundIds": [] }
+    });
+
+    for file in [newer, unversioned, broken, json!({ "schemaVersion": 0 })] {
+        let json = serde_json::to_vec(&file).unwrap();
+        assert!(CachedProducts::from_json(&json).is_none(), "{file}");
+    }
+    assert!(CachedProducts::from_json(b"not json").is_none());
+}
@@ -0,0 +1,97 @@
+//! Product ID rules of Google Play and the App Store, checked before IDs
+//! reach the store.
+
+use tauri_plugin_iap::{Error, ProductIdRules};
+
+fn violation(rules: ProductIdRules, id: &str) -> Option<String> {
+    match rules.validate(&[id]) {
+        Ok(()) => None,
+        Err(Error::ProductQueryError(message)) => Some(message),
+        Err(e) => panic!("unexpected error for {id:?}: {e}"),
+    }
+}
+
+#[test]
+fn both_stores_accept_their_formats() {
+    for id in ["premium", "coins_100", "com.example.pro", "1year.sub"] {
+        assert_eq!(violation(ProductIdRules::GooglePlay, id), None, "{id}");
+        assert_eq!(violation(ProductIdRules::AppStore, id), None, "{id}");
+    }
+    for id in ["Premium", "com.example.ProMonthly", "_legacy"] {
+        assert_eq!(violation(ProductIdRules::AppStore, id), None, "{id}");
+    }
+}
+
+#[test]
+fn surrounding_whitespace_is_rejected() {
+    for rules in [ProductIdRules::GooglePlay, ProductIdRules::AppStore] {
+        for id in ["premium ", " premium", "premium\n"] {
+            let message = violation(rules, id).unwrap();
+            assert!(
+                message.contains("leading or trailing whitespace"),
+                "{message}"
+            );
+        }
+        assert!(violation(rules, "").unwrap().contains("empty"));
+    }
+}
+
+#[test]
+fn google_play_ids_are_lowercase() {
+    let message = violation(ProductIdRules::GooglePlay, "Premium").unwrap();
+    assert!(message.contains("\"Premium\""), "{message}");
+    assert!(message.contains("start with a lowercase letter or digit"));
+
+    for id in ["proMonthly", "pro-monthly", "pro monthly", "pro€"] {
+        let message = violation(ProductIdRules::GooglePlay, id).unwrap();
+        assert!(
+            message.contains("only contain lowercase letters, digits, underscores and dots"),
+            "{message}"
+        );
+    }
+}
+
+#[test]
+fn google_play_ids_start_with_a_letter_or_digit() {
+    for id in ["_premium", ".premium"] {
+        let message = violation(ProductIdRules::GooglePlay, id).unwrap();
+        assert!(message.contains("start with a lowercase letter or digit"));
+    }
+}
+
+#[test]
+fn google_play_ids_are_at_most_139_characters() {
+    assert_eq!(
+        violation(ProductIdRules::GooglePlay, &"a".repeat(139)),
+        None
+    );
+    let message = violation(ProductIdRules::GooglePlay, &"a".repeat(140)).unwrap();
+    assert!(message.contains("at most 139 characters"));
+    // The App Store has no such limit.
+    assert_eq!(violation(ProductIdRules::AppStore, &"a".repeat(140)), None);
+}
+
+#[test]
+fn app_store_ids_have_no_dashes_or_spaces() {
+    for id in ["pro-monthly", "pro monthly", "pro/monthly", "prö"] {
+        let message = violation(ProductIdRules::AppStore, id).unwrap();
+        assert!(
+            message.contains(
+                "App Store product IDs only contain letters, digits, underscores and dots"
+            ),
+            "{message}"
+        );
+    }
+}
+
+#[test]
+fn every_invalid_id_is_named() {
+    let error = ProductIdRules::GooglePlay
+        .validate(&["premium", "Gold", "coins "])
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Product details query failed: Invalid product IDs: \"Gold\" (Google Play product IDs start with \
+         a lowercase letter or digit), \"coins \" (leading or trailing whitespace)"
+    );
+}
@@ -0,0 +1,108 @@
+//! Purchase streams pulled from Rust next to the webview events, and the
+//! lag policy of a stream whose receiver falls behind.
+
+#![cfg(feature = "mock")]
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tauri::async_runtime::block_on;
+use tauri::test::MockRuntime;
+use tauri::Listener;
+use tauri_plugin_iap::{
+    Iap, PurchaseDetails, PurchaseUpdate, PURCHASE_STREAM_BUFFER, PURCHASE_UPDATE_EVENT,
+};
+use tokio::sync::mpsc::error::TryRecvError;
+use tokio::sync::mpsc::Receiver;
+
+use common::{mock_iap, param};
+
+/// The product IDs of the next update, which must be purchases.
+fn next_purchases(stream: &mut Receiver<PurchaseUpdate>) -> Vec<String> {
+    match stream.try_recv() {
+        Ok(PurchaseUpdate::Purchases(purchases)) => {
+            purchases.into_iter().map(|p| p.product_id).collect()
+        }
+        other => panic!("expected purchases, got {other:?}"),
+    }
+}
+
+/// A purchase of `premium` to report again and again.
+fn bought(iap: &Iap<MockRuntime>) -> PurchaseDetails {
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    iap.mock_store().unwrap().purchases()[0].clone()
+}
+
+#[test]
+fn every_stream_and_the_webview_get_a_purchase() {
+    let (app, iap) = mock_iap(&["premium", "no_ads"]);
+    let events = Arc::new(AtomicUsize::new(0));
+    {
+        let events = events.clone();
+        app.listen(PURCHASE_UPDATE_EVENT, move |_| {
+            events.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+    let (mut first, mut second) = (iap.purchase_stream(), iap.purchase_stream());
+
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+
+    assert_eq!(next_purchases(&mut first), ["premium"]);
+    assert_eq!(next_purchases(&mut second), ["premium"]);
+    assert_eq!(events.load(Ordering::SeqCst), 1);
+    assert!(matches!(first.try_recv(), Err(TryRecvError::Empty)));
+}
+
+#[test]
+fn streams_only_get_updates_reported_after_they_open() {
+    let (_app, iap) = mock_iap(&["premium", "no_ads"]);
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    let mut stream = iap.purchase_stream();
+
+    block_on(iap.buy_non_consumable(param("no_ads"))).unwrap();
+
+    assert_eq!(next_purchases(&mut stream), ["no_ads"]);
+}
+
+#[test]
+fn restores_end_with_their_completion() {
+    let (_app, iap) = mock_iap(&["premium", "no_ads"]);
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    let mut stream = iap.purchase_stream();
+
+    block_on(iap.restore_purchases(None)).unwrap();
+
+    assert_eq!(next_purchases(&mut stream), ["premium"]);
+    match stream.try_recv() {
+        Ok(PurchaseUpdate::RestoreCompleted(completed)) => {
+            assert_eq!(completed.restored_count, 1)
+        }
+        other => panic!("expected the restore completion, got {other:?}"),
+    }
+}
+
+#[test]
+fn a_slow_stream_lags_without_holding_up_the_others() {
+    let (_app, iap) = mock_iap(&["premium", "no_ads"]);
+    let purchase = bought(&iap);
+    let (mut slow, mut fast) = (iap.purchase_stream(), iap.purchase_stream());
+
+    // Reporting returns right away even though `slow` is never read.
+    for _ in 0..PURCHASE_STREAM_BUFFER + 3 {
+        iap.push_purchase_updates(std::slice::from_ref(&purchase));
+        assert_eq!(next_purchases(&mut fast), ["premium"]);
+    }
+
+    for _ in 0..PURCHASE_STREAM_BUFFER {
+        assert_eq!(next_purchases(&mut slow), ["premium"]);
+    }
+    assert!(matches!(slow.try_recv(), Err(TryRecvError::Empty)));
+
+    // Once read, the stream learns what it missed before the next update.
+    iap.push_purchase_updates(std::slice::from_ref(&purchase));
+    assert!(matches!(slow.try_recv(), Ok(PurchaseUpdate::Lagged(3))));
+    assert_eq!(next_purchases(&mut slow), ["premium"]);
+    assert_eq!(next_purchases(&mut fast), ["premium"]);
+}
@@ -0,0 +1,83 @@
+//! Product queries retrying transient failures, against the mock store.
+
+#![cfg(feature = "mock")]
+
+mod common;
+
+use serde_json::json;
+use tauri::async_runtime::block_on;
+use tauri_plugin_iap::{IapErrorCode, IapExt, MockOperation, RetryPolicy};
+
+use common::{fresh, launch};
+
+fn queries(app: &tauri::App<tauri::test::MockRuntime>) -> usize {
+    let mock = app.handle().iap().mock_store().unwrap();
+    mock.calls()
+        .iter()
+        .filter(|call| call.operation == MockOperation::QueryProductDetails)
+        .count()
+}
+
+#[test]
+fn retries_a_query_that_failed_then_succeeds() {
+    fresh("query-retry");
+    let app = launch("query-retry", json!({}));
+    let iap = app.handle().iap();
+    block_on(iap.initialize()).unwrap();
+    let mock = iap.mock_store().unwrap();
+    mock.fail_next(
+        MockOperation::QueryProductDetails,
+        IapErrorCode::NetworkError,
+    );
+
+    let response =
+        block_on(iap.query_product_details(vec!["premium".into(), "coins".into()])).unwrap();
+
+    let found: Vec<_> = response
+        .product_details
+        .iter()
+        .map(|p| p.id.as_str())
+        .collect();
+    assert_eq!(found, ["premium", "coins"]);
+    assert!(response.error.is_none());
+    assert_eq!(queries(&app), 2);
+}
+
+#[test]
+fn does_not_retry_without_a_retry_policy() {
+    fresh("query-no-retry");
+    let app = launch("query-no-retry", json!({}));
+    let iap = app.handle().iap();
+    block_on(iap.initialize()).unwrap();
+    let mock = iap.mock_store().unwrap();
+    mock.fail_next(
+        MockOperation::QueryProductDetails,
+        IapErrorCode::ServiceDisconnected,
+    );
+
+    let error = block_on(
+        iap.query_product_details_with_policy(vec!["premium".into()], &RetryPolicy::none()),
+    )
+    .unwrap_err();
+
+    assert_eq!(error.code(), IapErrorCode::ServiceDisconnected);
+    assert_eq!(queries(&app), 1);
+}
+
+#[test]
+fn does_not_retry_permanent_failures() {
+    fresh("query-permanent-failure");
+    let app = launch("query-permanent-failure", json!({}));
+    let iap = app.handle().iap();
+    block_on(iap.initialize()).unwrap();
+    let mock = iap.mock_store().unwrap();
+    mock.fail_next(
+        MockOperation::QueryProductDetails,
+        IapErrorCode::DeveloperError,
+    );
+
+    let error = block_on(iap.query_product_details(vec!["premium".into()])).unwrap_err();
+
+    assert_eq!(error.code(), IapErrorCode::DeveloperError);
+    assert_eq!(queries(&app), 1);
+}
@@ -0,0 +1,73 @@
+//! Wire names of `ReplacementMode`, as the bindings send them, and the Google
+//! Play constants the Kotlin side maps them to.
+
+use std::str::FromStr;
+
+use tauri_plugin_iap::{Error, ReplacementMode};
+
+/// Every mode with its name and `SubscriptionUpdateParams.ReplacementMode`
+/// constant.
+const MODES: &[(ReplacementMode, &str, i32)] = &[
+    (ReplacementMode::WithTimeProration, "withTimeProration", 1),
+    (
+        ReplacementMode::ChargeProratedPrice,
+        "chargeProratedPrice",
+        2,
+    ),
+    (ReplacementMode::WithoutProration, "withoutProration", 3),
+    (ReplacementMode::ChargeFullPrice, "chargeFullPrice", 5),
+    (ReplacementMode::Deferred, "deferred", 6),
+];
+
+#[test]
+fn covers_every_mode() {
+    let listed: Vec<_> = MODES.iter().map(|(mode, _, _)| *mode).collect();
+    assert_eq!(listed, ReplacementMode::ALL);
+}
+
+#[test]
+fn serializes_as_camel_case_names() {
+    for (mode, name, _) in MODES {
+        assert_eq!(serde_json::to_value(mode).unwrap(), *name);
+        assert_eq!(
+            serde_json::from_value::<ReplacementMode>((*name).into()).unwrap(),
+            *mode
+        );
+        assert_eq!(mode.as_str(), *name);
+        assert_eq!(mode.to_string(), *name);
+        assert_eq!(ReplacementMode::from_str(name).unwrap(), *mode);
+    }
+}
+
+#[test]
+fn maps_to_google_play_constants() {
+    for (mode, _, constant) in MODES {
+        assert_eq!(i32::from(*mode), *constant);
+        assert_eq!(ReplacementMode::try_from(*constant).unwrap(), *mode);
+    }
+}
+
+#[test]
+fn rejects_unknown_constants() {
+    // `UNKNOWN_REPLACEMENT_MODE` and the retired `DEFERRED` of the old
+    // proration modes.
+    for constant in [0, 4, 7, -1] {
+        assert!(matches!(
+            ReplacementMode::try_from(constant),
+            Err(Error::DeveloperError(_))
+        ));
+    }
+}
+
+#[test]
+fn rejects_unknown_names_listing_the_valid_ones() {
+    for name in ["", "deferredd", "WithTimeProration", "with_time_proration"] {
+        let Err(Error::DeveloperError(message)) = ReplacementMode::from_str(name) else {
+            panic!("{name:?} was accepted");
+        };
+        for (_, valid, _) in MODES {
+            assert!(message.contains(valid), "{message} doesn't list {valid}");
+        }
+    }
+    assert!(serde_json::from_value::<ReplacementMode>("immediate".into()).is_err());
+}
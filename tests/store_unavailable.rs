@@ -0,0 +1,73 @@
+//! Devices without a store: `initialize` fails with `StoreUnavailable` right
+//! away, and `is_available` reports `false` instead of failing.
+
+#![cfg(feature = "mock")]
+
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+use tauri::async_runtime::block_on;
+use tauri::test::mock_app;
+use tauri::Listener;
+use tauri_plugin_iap::{Error, Iap, IapErrorCode, MockOperation, CONNECTION_STATE_EVENT};
+
+#[test]
+fn missing_store_fails_initialize_without_retrying() {
+    let app = mock_app();
+    let iap = Iap::mock(app.handle(), Vec::new());
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    {
+        let changes = changes.clone();
+        app.listen(CONNECTION_STATE_EVENT, move |event| {
+            let change: Value = serde_json::from_str(event.payload()).unwrap();
+            changes.lock().unwrap().push(change);
+        });
+    }
+    let mock = iap.mock_store().unwrap();
+    mock.fail_next(MockOperation::Initialize, IapErrorCode::StoreUnavailable);
+
+    let error = block_on(iap.initialize()).unwrap_err();
+
+    assert!(matches!(error, Error::StoreUnavailable(_)), "{error:?}");
+    assert!(!error.is_retryable());
+    let attempts = mock
+        .calls()
+        .iter()
+        .filter(|call| call.operation == MockOperation::Initialize)
+        .count();
+    assert_eq!(attempts, 1);
+    let last = changes.lock().unwrap().last().cloned().unwrap();
+    assert_eq!(last["state"], "uninitialized");
+    assert_eq!(last["storePresent"], false);
+    assert!(!block_on(iap.is_available()).unwrap());
+
+    block_on(iap.initialize()).unwrap();
+
+    let last = changes.lock().unwrap().last().cloned().unwrap();
+    assert_eq!(last["state"], "ready");
+    assert_eq!(last["storePresent"], true);
+    assert!(block_on(iap.is_available()).unwrap());
+}
+
+#[test]
+fn unavailable_store_is_not_available() {
+    let app = mock_app();
+    let iap = Iap::mock(app.handle(), Vec::new());
+    let mock = iap.mock_store().unwrap();
+    mock.fail_next(MockOperation::IsAvailable, IapErrorCode::StoreUnavailable);
+
+    assert!(!block_on(iap.is_available()).unwrap());
+    assert!(block_on(iap.is_available()).unwrap());
+}
+
+#[test]
+fn other_availability_failures_are_reported() {
+    let app = mock_app();
+    let iap = Iap::mock(app.handle(), Vec::new());
+    let mock = iap.mock_store().unwrap();
+    mock.fail_next(MockOperation::IsAvailable, IapErrorCode::NetworkError);
+
+    let error = block_on(iap.is_available()).unwrap_err();
+
+    assert!(matches!(error, Error::NetworkError(_)), "{error:?}");
+}
@@ -0,0 +1,33 @@
+//! Storefront changes reported by the mock store: the cached country code is
+//! dropped and the change is emitted to the app.
+
+#![cfg(feature = "mock")]
+
+use std::sync::{Arc, Mutex};
+
+use tauri::async_runtime::block_on;
+use tauri::test::mock_app;
+use tauri::Listener;
+use tauri_plugin_iap::{Iap, STOREFRONT_CHANGED_EVENT};
+
+#[test]
+fn storefront_change_refreshes_the_country_code() {
+    let app = mock_app();
+    let iap = Iap::mock(app.handle(), Vec::new());
+    let changes = Arc::new(Mutex::new(0));
+    {
+        let changes = changes.clone();
+        app.listen(STOREFRONT_CHANGED_EVENT, move |_| {
+            *changes.lock().unwrap() += 1
+        });
+    }
+    block_on(iap.initialize()).unwrap();
+    let mock = iap.mock_store().unwrap();
+    mock.set_country_code(Some("US".into()));
+    assert_eq!(block_on(iap.country_code()).unwrap(), "US");
+
+    mock.set_country_code(Some("DE".into()));
+
+    assert_eq!(block_on(iap.country_code()).unwrap(), "DE");
+    assert_eq!(*changes.lock().unwrap(), 2);
+}
@@ -0,0 +1,243 @@
+//! Time left on a subscription, computed from its status at a given time:
+//! boundaries around the end of the period and of the grace period, and a
+//! subscription renewing while the app runs.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tauri::async_runtime::block_on;
+use tauri::test::{mock_builder, mock_context, noop_assets};
+use tauri_plugin_iap::{
+    DesktopIapBackend, Error, IapExt, ProductDetailsResponse, PurchaseDetails, PurchaseEventSink,
+    PurchaseParam, PurchaseStatus, PurchaseVerificationData, Result, SubscriptionState,
+    SubscriptionStatus,
+};
+
+/// 2024-01-31T12:00:00Z
+const END: u64 = 1_706_702_400;
+
+fn at(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn status(state: SubscriptionState, expiration_date: Option<&str>) -> SubscriptionStatus {
+    SubscriptionStatus {
+        product_id: "pro_monthly".into(),
+        is_active: state.is_entitled(),
+        state,
+        grace_period_expiration_date: None,
+        expiration_date: expiration_date.map(Into::into),
+        will_auto_renew: Some(true),
+    }
+}
+
+#[test]
+fn counts_down_to_the_end_of_the_period() {
+    let active = status(SubscriptionState::Active, Some("2024-01-31T12:00:00Z"));
+
+    assert_eq!(
+        active.time_remaining(at(END - 86_400)),
+        Some(Duration::from_secs(86_400))
+    );
+    assert_eq!(
+        active.time_remaining(at(END - 1)),
+        Some(Duration::from_secs(1))
+    );
+    assert_eq!(active.time_remaining(at(END)), Some(Duration::ZERO));
+    assert_eq!(active.time_remaining(at(END + 1)), Some(Duration::ZERO));
+}
+
+#[test]
+fn keeps_sub_second_precision_before_the_end() {
+    let active = status(SubscriptionState::Active, Some("2024-01-31T12:00:00Z"));
+
+    let now = at(END) - Duration::from_millis(250);
+
+    assert_eq!(active.time_remaining(now), Some(Duration::from_millis(250)));
+}
+
+#[test]
+fn reads_ends_reported_at_an_offset_from_utc() {
+    let active = status(SubscriptionState::Active, Some("2024-01-31T14:00:00+02:00"));
+
+    assert_eq!(
+        active.time_remaining(at(END - 60)),
+        Some(Duration::from_secs(60))
+    );
+    assert_eq!(active.time_remaining(at(END)), Some(Duration::ZERO));
+}
+
+#[test]
+fn counts_down_to_the_end_of_the_grace_period_while_in_one() {
+    let mut grace = status(SubscriptionState::GracePeriod, Some("2024-01-31T12:00:00Z"));
+    grace.grace_period_expiration_date = Some("2024-02-07T12:00:00Z".into());
+
+    // The period ended, but access lasts until the grace period does.
+    assert_eq!(
+        grace.time_remaining(at(END)),
+        Some(Duration::from_secs(7 * 86_400))
+    );
+    assert_eq!(
+        grace.time_remaining(at(END + 7 * 86_400)),
+        Some(Duration::ZERO)
+    );
+}
+
+#[test]
+fn grace_periods_without_an_end_report_none() {
+    let grace = status(SubscriptionState::GracePeriod, Some("2024-01-31T12:00:00Z"));
+
+    assert_eq!(grace.time_remaining(at(END - 60)), None);
+}
+
+#[test]
+fn lapsed_subscriptions_have_no_time_left() {
+    for state in [SubscriptionState::OnHold, SubscriptionState::Expired] {
+        let lapsed = status(state, Some("2024-01-31T12:00:00Z"));
+
+        assert_eq!(
+            lapsed.time_remaining(at(END - 86_400)),
+            Some(Duration::ZERO)
+        );
+    }
+}
+
+#[test]
+fn subscriptions_without_an_end_report_none() {
+    assert_eq!(
+        status(SubscriptionState::Active, None).time_remaining(at(END)),
+        None
+    );
+    assert_eq!(
+        status(SubscriptionState::Active, Some("soon")).time_remaining(at(END)),
+        None
+    );
+}
+
+/// Store selling a monthly subscription whose status the test changes, and
+/// a lifetime unlock restored as owned.
+#[derive(Default)]
+struct SubscriptionStore {
+    status: Mutex<Option<SubscriptionStatus>>,
+}
+
+impl DesktopIapBackend for SubscriptionStore {
+    fn initialize(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn is_available(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn query_product_details(&self, product_ids: &[String]) -> Result<ProductDetailsResponse> {
+        Ok(ProductDetailsResponse {
+            not_found_ids: product_ids.to_vec(),
+            ..Default::default()
+        })
+    }
+
+    fn buy_non_consumable(&self, _: &PurchaseParam, _: &PurchaseEventSink) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn buy_consumable(&self, _: &PurchaseParam, _: bool, _: &PurchaseEventSink) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn complete_purchase(&self, _: &PurchaseDetails) -> Result<()> {
+        Ok(())
+    }
+
+    fn restore_purchases(&self, _: Option<&str>, events: &PurchaseEventSink) -> Result<()> {
+        events.emit(&[PurchaseDetails {
+            purchase_id: Some("order-lifetime".into()),
+            product_id: "lifetime".into(),
+            verification_data: PurchaseVerificationData {
+                local_verification_data: "token-lifetime".into(),
+                server_verification_data: "token-lifetime".into(),
+                source: "test".into(),
+                signature: None,
+            },
+            transaction_date: None,
+            transaction_date_ms: None,
+            expiration_date: None,
+            subscription_state: None,
+            grace_period_expiration_date: None,
+            status: PurchaseStatus::Restored,
+            error: None,
+            pending_complete_purchase: false,
+            verified: false,
+        }]);
+        Ok(())
+    }
+
+    fn country_code(&self) -> Result<String> {
+        Ok("US".into())
+    }
+
+    fn subscription_status(&self, product_id: &str) -> Result<SubscriptionStatus> {
+        self.status
+            .lock()
+            .unwrap()
+            .clone()
+            .filter(|status| status.product_id == product_id)
+            .ok_or_else(|| Error::ItemNotOwned(product_id.into()))
+    }
+}
+
+fn app(store: Arc<SubscriptionStore>) -> tauri::App<tauri::test::MockRuntime> {
+    mock_builder()
+        .plugin(
+            tauri_plugin_iap::Builder::new()
+                .desktop_backend(store)
+                .build(),
+        )
+        .build(mock_context(noop_assets()))
+        .expect("failed to build the app")
+}
+
+#[test]
+fn renewals_during_the_session_extend_the_time_left() {
+    let store = Arc::new(SubscriptionStore::default());
+    let app = app(store.clone());
+    let iap = app.iap();
+    block_on(iap.initialize()).unwrap();
+    *store.status.lock().unwrap() = Some(status(
+        SubscriptionState::Active,
+        Some("2024-01-31T12:00:00Z"),
+    ));
+    assert_eq!(
+        block_on(iap.subscription_time_remaining_at("pro_monthly", at(END - 60))).unwrap(),
+        Some(Duration::from_secs(60))
+    );
+
+    // The store renews the subscription for another period.
+    *store.status.lock().unwrap() = Some(status(
+        SubscriptionState::Active,
+        Some("2024-03-02T12:00:00Z"),
+    ));
+
+    assert_eq!(
+        block_on(iap.subscription_time_remaining_at("pro_monthly", at(END))).unwrap(),
+        Some(Duration::from_secs(31 * 86_400))
+    );
+}
+
+#[test]
+fn lifetime_purchases_have_no_end() {
+    let store = Arc::new(SubscriptionStore::default());
+    let app = app(store);
+    let iap = app.iap();
+    block_on(iap.initialize()).unwrap();
+    block_on(iap.restore_purchases(None)).unwrap();
+
+    assert_eq!(
+        block_on(iap.subscription_time_remaining_at("lifetime", at(END))).unwrap(),
+        None
+    );
+    assert!(matches!(
+        block_on(iap.subscription_time_remaining_at("pro_monthly", at(END))),
+        Err(Error::ItemNotOwned(_))
+    ));
+}
@@ -0,0 +1,211 @@
+//! Purchases left unfinished when the app is killed before completing them,
+//! or when their verification failed, reported again by `initialize` on the
+//! next launch, against the mock store.
+
+#![cfg(feature = "mock")]
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+use tauri::async_runtime::{block_on, spawn};
+use tauri::Manager;
+use tauri_plugin_iap::{DesktopIapBackend, Error, IapErrorCode, IapExt, MockOperation};
+
+use common::{fresh, launch, param, purchase_updates, wait_for};
+
+const UNFINISHED_PURCHASES_FILE: &str = "iap-unfinished-purchases.json";
+
+/// The records kept in the app data directory of `app`, if any.
+fn kept(app: &tauri::App<tauri::test::MockRuntime>) -> Option<serde_json::Value> {
+    let path = app
+        .path()
+        .app_data_dir()
+        .unwrap()
+        .join(UNFINISHED_PURCHASES_FILE);
+    let json = std::fs::read(path).ok()?;
+    Some(serde_json::from_slice(&json).unwrap())
+}
+
+#[test]
+fn reports_a_purchase_stranded_by_a_crash_on_the_next_launch() {
+    fresh("unfinished-crash");
+    let bought = {
+        let app = launch("unfinished-crash", json!({}));
+        let iap = app.handle().iap();
+        let updates = purchase_updates(&app);
+        block_on(iap.initialize()).unwrap();
+        assert!(block_on(iap.buy_non_consumable(param("premium"))).unwrap());
+        let bought = wait_for(&updates, 1)[0][0].clone();
+        assert!(bought.pending_complete_purchase);
+
+        // Kept without the token or the receipt.
+        let records = kept(&app).expect("no unfinished purchases kept");
+        assert_eq!(
+            records,
+            json!([{
+                "productId": "premium",
+                "purchaseId": bought.purchase_id,
+                "transactionDate": bought.transaction_date,
+            }])
+        );
+        bought
+        // Killed before `complete_purchase`.
+    };
+
+    let app = launch("unfinished-crash", json!({}));
+    let iap = app.handle().iap();
+    let updates = purchase_updates(&app);
+    block_on(iap.initialize()).unwrap();
+
+    let updates = wait_for(&updates, 1);
+    assert_eq!(updates.len(), 1);
+    let stranded = &updates[0];
+    assert_eq!(stranded.len(), 1);
+    assert_eq!(stranded[0].purchase_id, bought.purchase_id);
+    assert!(stranded[0].pending_complete_purchase);
+
+    block_on(iap.complete_purchase(stranded[0].clone())).unwrap();
+    assert_eq!(kept(&app), None);
+    drop(app);
+
+    // Completed, so the next launch has nothing to report.
+    let app = launch("unfinished-crash", json!({}));
+    let updates = purchase_updates(&app);
+    block_on(app.handle().iap().initialize()).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(updates.lock().unwrap().is_empty());
+}
+
+#[test]
+fn forgets_purchases_completed_before_the_relaunch() {
+    fresh("unfinished-completed");
+    {
+        let app = launch("unfinished-completed", json!({}));
+        let iap = app.handle().iap();
+        let updates = purchase_updates(&app);
+        block_on(iap.initialize()).unwrap();
+        block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+        let bought = wait_for(&updates, 1)[0][0].clone();
+        assert!(kept(&app).is_some());
+        // Completed by the store, e.g. from another device, but the app was
+        // killed before it learned about it.
+        iap.mock_store()
+            .unwrap()
+            .complete_purchase(&bought)
+            .unwrap();
+    }
+
+    let app = launch("unfinished-completed", json!({}));
+    let updates = purchase_updates(&app);
+    block_on(app.handle().iap().initialize()).unwrap();
+
+    // Checked against the store in the background.
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while kept(&app).is_some() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(kept(&app), None);
+    assert!(updates.lock().unwrap().is_empty());
+}
+
+#[test]
+fn completes_purchases_once_verified() {
+    fresh("finalize-verified");
+    let app = launch("finalize-verified", json!({}));
+    let iap = app.handle().iap();
+    let updates = purchase_updates(&app);
+    block_on(iap.initialize()).unwrap();
+    block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+    let bought = wait_for(&updates, 1)[0][0].clone();
+    let mock = iap.mock_store().unwrap();
+    // Completing fails once, and is retried.
+    mock.fail_next(MockOperation::CompletePurchase, IapErrorCode::NetworkError);
+
+    let verified = block_on(iap.finalize_after(bought.clone(), |purchase| async move {
+        assert_eq!(purchase.purchase_id, bought.purchase_id);
+        Ok(())
+    }));
+
+    assert!(verified.is_ok());
+    let completions = mock
+        .calls()
+        .iter()
+        .filter(|call| call.operation == MockOperation::CompletePurchase)
+        .count();
+    assert_eq!(completions, 2);
+    assert!(!mock.purchases()[0].pending_complete_purchase);
+    assert_eq!(kept(&app), None);
+}
+
+#[test]
+fn leaves_purchases_failing_verification_unfinished() {
+    fresh("finalize-rejected");
+    let bought = {
+        let app = launch("finalize-rejected", json!({}));
+        let iap = app.handle().iap();
+        let updates = purchase_updates(&app);
+        block_on(iap.initialize()).unwrap();
+        block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+        let bought = wait_for(&updates, 1)[0][0].clone();
+
+        let rejected = block_on(iap.finalize_after(bought.clone(), |_| async {
+            Err(Error::InvalidPurchaseToken("rejected by the server".into()))
+        }));
+
+        assert!(matches!(rejected, Err(Error::InvalidPurchaseToken(_))));
+        assert!(iap.mock_store().unwrap().purchases()[0].pending_complete_purchase);
+        assert!(kept(&app).is_some());
+        bought
+    };
+
+    // Reported again on the next launch, to verify once the server agrees.
+    let app = launch("finalize-rejected", json!({}));
+    let iap = app.handle().iap();
+    let updates = purchase_updates(&app);
+    block_on(iap.initialize()).unwrap();
+    let stranded = wait_for(&updates, 1)[0][0].clone();
+    assert_eq!(stranded.purchase_id, bought.purchase_id);
+    block_on(iap.finalize_after(stranded, |_| async { Ok(()) })).unwrap();
+    assert_eq!(kept(&app), None);
+}
+
+#[test]
+fn reports_purchases_verified_before_a_crash_again() {
+    fresh("finalize-crash");
+    let bought = {
+        let app = launch("finalize-crash", json!({}));
+        let iap = app.handle().iap();
+        let updates = purchase_updates(&app);
+        block_on(iap.initialize()).unwrap();
+        block_on(iap.buy_non_consumable(param("premium"))).unwrap();
+        let bought = wait_for(&updates, 1)[0][0].clone();
+
+        // The server accepted the purchase, and the app is killed before
+        // hearing back.
+        let handle = app.handle().clone();
+        let purchase = bought.clone();
+        let _finalizing = spawn(async move {
+            handle
+                .iap()
+                .finalize_after(purchase, |_| std::future::pending())
+                .await
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        bought
+    };
+
+    let app = launch("finalize-crash", json!({}));
+    let iap = app.handle().iap();
+    let updates = purchase_updates(&app);
+    block_on(iap.initialize()).unwrap();
+
+    // Verifying again is up to the server, which already knows the purchase.
+    let stranded = wait_for(&updates, 1)[0][0].clone();
+    assert_eq!(stranded.purchase_id, bought.purchase_id);
+    assert!(stranded.pending_complete_purchase);
+    block_on(iap.finalize_after(stranded, |_| async { Ok(()) })).unwrap();
+    assert_eq!(kept(&app), None);
+    assert!(!iap.mock_store().unwrap().purchases()[0].pending_complete_purchase);
+}
@@ -0,0 +1,108 @@
+//! Snapshots of the verification payload exported for each store, kept in
+//! `tests/fixtures/verification_payload/`. App servers parse this format, so
+//! any change to the JSON, field order included, fails here and needs a new
+//! `schema` version unless it only adds a field.
+
+mod common;
+
+use std::path::Path;
+
+use serde::Deserialize;
+use tauri_plugin_iap::{Error, PurchaseDetails, VerificationPayload, VERIFICATION_PAYLOAD_SCHEMA};
+
+#[derive(Deserialize)]
+struct Purchases {
+    purchases: Vec<PurchaseDetails>,
+}
+
+fn fixture(path: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(path);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display()))
+}
+
+/// The first purchase of a native owned purchases fixture.
+fn owned(platform: &str) -> PurchaseDetails {
+    let owned: Purchases =
+        serde_json::from_str(&fixture(&format!("{platform}/owned_purchases.json"))).unwrap();
+    owned.purchases.into_iter().next().unwrap()
+}
+
+/// An App Store purchase with a legacy receipt instead of a JWS.
+fn apple_receipt() -> PurchaseDetails {
+    let mut purchase = owned("ios");
+    purchase.verification_data.server_verification_data =
+        "MIIUVQYJKoZIhvcNAQcCoIIURjCCFEICAQExCzAJBgUrDgMCGgUAMIIDwwYJKoZIhvcNAQcB".into();
+    purchase
+}
+
+#[test]
+fn exports_the_snapshotted_format() {
+    let cases = [
+        ("google", owned("android")),
+        ("apple_jws", owned("ios")),
+        ("apple_receipt", apple_receipt()),
+        ("amazon", owned("amazon")),
+    ];
+    for (snapshot, purchase) in cases {
+        let payload = purchase.verification_payload().unwrap();
+        let json = serde_json::to_string_pretty(&payload).unwrap() + "\n";
+
+        let expected = fixture(&format!("verification_payload/{snapshot}.json"));
+        assert_eq!(json, expected, "{snapshot}");
+        assert_eq!(payload.schema, VERIFICATION_PAYLOAD_SCHEMA);
+        // Servers written against the format read it back unchanged.
+        let parsed: VerificationPayload = serde_json::from_str(&expected).unwrap();
+        assert_eq!(parsed, payload, "{snapshot}");
+    }
+}
+
+#[test]
+fn reads_payloads_with_fields_added_later() {
+    let mut json: serde_json::Value =
+        serde_json::from_str(&fixture("verification_payload/google.json")).unwrap();
+    json["obfuscatedAccountId"] = "account".into();
+
+    let payload: VerificationPayload = serde_json::from_value(json).unwrap();
+
+    assert_eq!(payload, owned("android").verification_payload().unwrap());
+}
+
+#[test]
+fn has_no_payload_without_verification_data() {
+    let mut redacted = owned("android");
+    redacted.verification_data = Default::default();
+    assert!(matches!(
+        redacted.verification_payload(),
+        Err(Error::InvalidPurchaseToken(_))
+    ));
+
+    let mut other_store = owned("android");
+    other_store.verification_data.source = "microsoft".into();
+    let error = other_store.verification_payload().unwrap_err();
+    assert!(matches!(error, Error::FeatureNotSupported(_)));
+    assert!(error.to_string().contains("microsoft"), "{error}");
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn exports_owned_purchases_by_their_id() {
+    use tauri::async_runtime::block_on;
+
+    let (app, iap) = common::mock_iap(&["premium"]);
+    let updates = common::purchase_updates(&app);
+    block_on(iap.buy_non_consumable(common::param("premium"))).unwrap();
+    let bought = common::wait_for(&updates, 1)[0][0].clone();
+
+    // The mock store's purchases have nothing for a server to verify.
+    let purchase_id = bought.purchase_id.unwrap();
+    assert!(matches!(
+        iap.export_verification_payload(&purchase_id),
+        Err(Error::FeatureNotSupported(_))
+    ));
+    assert!(matches!(
+        iap.export_verification_payload("missing"),
+        Err(Error::UnknownPurchase(_))
+    ));
+}